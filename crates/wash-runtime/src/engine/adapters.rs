@@ -0,0 +1,507 @@
+//! Adapters for components built against a slightly different revision of an interface
+//! than the one the host links.
+//!
+//! Point releases of stable packages (`wasi:http@0.2.1` vs `wasi:http@0.2.6`) aren't handled
+//! here: they're resolved by wasmtime's semver-aware linker, which serves any `0.2.x` import
+//! from the newest `0.2` revision the host links. Prerelease revisions such as
+//! `wasi:keyvalue@0.2.0-draft2` are never considered compatible by semver, even when the
+//! functions a component uses didn't change between revisions. For the revisions listed in
+//! [`VERSION_ADAPTERS`], imports are renamed to the revision the host provides before the
+//! component is compiled.
+//!
+//! An import is only renamed if every function the component imports from it is one of the
+//! adapter's [`VersionAdapter::shared`] functions, which have the same signature and
+//! semantics in both revisions. Imports using anything else are left alone, so the component
+//! fails to link with a missing import rather than calling a function that changed. Renamed
+//! imports are renamed in nested components and the arguments they're instantiated with too,
+//! keeping composed components consistent. Wasmtime still type-checks every imported
+//! function at link time.
+//!
+//! With the `wasip1` feature, classic `wasm32-wasip1` core modules are also turned into
+//! components at load time using the `wasi_snapshot_preview1` adapter.
+
+use std::collections::HashMap;
+
+use anyhow::Context;
+use tracing::debug;
+use wasm_encoder::reencode::{self, Reencode, ReencodeComponent, component_utils};
+use wasmparser::component_types::ComponentEntityType;
+use wasmparser::{ComponentInstance, ComponentTypeDeclaration, Parser, Payload};
+
+use crate::wit::WitInterface;
+
+/// A prerelease revision of a package that can be served by another revision.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionAdapter {
+    /// The package, as `namespace:package`
+    pub package: &'static str,
+    /// The revision components may be built against
+    pub from: &'static str,
+    /// The revision the host links
+    pub to: &'static str,
+    /// The functions of each interface that have the same signature and semantics in both
+    /// revisions, as `(interface, functions)`, with functions named as the component model
+    /// does, e.g. `[method]bucket.get`
+    pub shared: &'static [(&'static str, &'static [&'static str])],
+}
+
+/// The revisions adapted by default.
+///
+/// `wasi:keyvalue@0.2.0-draft2` changed `store.list-keys` cursors to strings, `batch.get-many`
+/// results to `(key, option<value>)` pairs and `atomics.increment` to signed deltas, and
+/// added compare-and-swap to `atomics`; everything else is unchanged from `0.2.0-draft`.
+pub const VERSION_ADAPTERS: &[VersionAdapter] = &[VersionAdapter {
+    package: "wasi:keyvalue",
+    from: "0.2.0-draft2",
+    to: "0.2.0-draft",
+    shared: &[
+        (
+            "store",
+            &[
+                "open",
+                "[method]bucket.get",
+                "[method]bucket.set",
+                "[method]bucket.delete",
+                "[method]bucket.exists",
+            ],
+        ),
+        ("batch", &["set-many", "delete-many"]),
+    ],
+}];
+
+/// Name of the core module that preview1 modules import WASI functions from
+#[cfg(feature = "wasip1")]
+const PREVIEW1_ADAPTER_NAME: &str = "wasi_snapshot_preview1";
+
+/// Returns the adapter for a fully qualified import, e.g. `wasi:keyvalue/store@0.2.0-draft2`,
+/// along with the import's interface and adapted name, `wasi:keyvalue/store@0.2.0-draft`.
+fn adapter_for(name: &str) -> Option<(&'static VersionAdapter, &str, String)> {
+    let (path, version) = name.rsplit_once('@')?;
+    let (package, interface) = path.split_once('/')?;
+    VERSION_ADAPTERS
+        .iter()
+        .find(|adapter| adapter.package == package && adapter.from == version)
+        .map(|adapter| (adapter, interface, format!("{path}@{}", adapter.to)))
+}
+
+/// Rewrites the version of a requested [`WitInterface`] to the revision the host links,
+/// if it's covered by [`VERSION_ADAPTERS`]. Returns `true` if the interface was changed.
+pub fn adapt_interface(interface: &mut WitInterface) -> bool {
+    let Some(version) = &interface.version else {
+        return false;
+    };
+    let package = format!("{}:{}", interface.namespace, interface.package);
+    let Some(adapter) = VERSION_ADAPTERS
+        .iter()
+        .find(|adapter| adapter.package == package && version.to_string() == adapter.from)
+    else {
+        return false;
+    };
+    match semver::Version::parse(adapter.to) {
+        Ok(to) => {
+            interface.version = Some(to);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Returns `true` if the binary is a core WebAssembly module rather than a component.
+pub(crate) fn is_core_module(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\0asm") && bytes.get(4..8) == Some(&[0x01, 0x00, 0x00, 0x00][..])
+}
+
+/// Picks the built-in `wasi_snapshot_preview1` adapter for a core module: the command
+/// adapter for a module exporting `_start`, which then runs as `wasi:cli/run`, the
+/// reactor adapter for others.
+///
+/// # Errors
+/// Returns an error if the module's export section is malformed.
+#[cfg(feature = "wasip1")]
+pub(crate) fn builtin_preview1_adapter(module: &[u8]) -> anyhow::Result<&'static [u8]> {
+    use wasi_preview1_component_adapter_provider::{
+        WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER, WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+    };
+
+    for payload in wasmparser::Parser::new(0).parse_all(module) {
+        if let wasmparser::Payload::ExportSection(exports) = payload? {
+            for export in exports {
+                if export?.name == "_start" {
+                    return Ok(WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER);
+                }
+            }
+        }
+    }
+    Ok(WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER)
+}
+
+/// Wraps a `wasm32-wasip1` core module into a component, using `adapter` (a build of
+/// `wasi_snapshot_preview1.reactor.wasm` or `.command.wasm` matching the host's wasmtime
+/// version) to implement the preview1 imports with WASI 0.2.
+///
+/// # Errors
+/// Returns an error if the module or adapter are invalid, or the module imports
+/// something other than preview1 functions.
+#[cfg(feature = "wasip1")]
+pub(crate) fn componentize_preview1_module(
+    module: &[u8],
+    adapter: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    wit_component::ComponentEncoder::default()
+        .validate(true)
+        .module(module)
+        .context("failed to parse core module")?
+        .adapter(PREVIEW1_ADAPTER_NAME, adapter)
+        .context("failed to load preview1 adapter")?
+        .encode()
+        .context("failed to adapt preview1 module into a component")
+}
+
+/// Renames the imports of a component binary according to [`VERSION_ADAPTERS`].
+///
+/// # Returns
+/// The adapted binary, or `None` if the binary isn't a component or none of its imports
+/// could be adapted.
+///
+/// # Errors
+/// Returns an error if the component is malformed.
+pub(crate) fn adapt_component_imports(bytes: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+    if !Parser::is_component(bytes) {
+        return Ok(None);
+    }
+
+    // Find the top-level imports an adapter applies to before paying for validation
+    let mut candidates = Vec::new();
+    let mut depth = 0usize;
+    for payload in Parser::new(0).parse_all(bytes) {
+        match payload.context("failed to parse component")? {
+            Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => depth += 1,
+            Payload::End(_) => depth = depth.saturating_sub(1),
+            Payload::ComponentImportSection(section) if depth == 0 => {
+                for import in section {
+                    let name = import.context("failed to parse component import")?.name.0;
+                    if let Some(candidate) = adapter_for(name) {
+                        candidates.push((name, candidate));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    if candidates.is_empty() {
+        return Ok(None);
+    }
+
+    let types = wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+        .validate_all(bytes)
+        .context("failed to validate component")?;
+    let mut renames = HashMap::new();
+    for (name, (adapter, interface, adapted)) in candidates {
+        let Some(ComponentEntityType::Instance(id)) = types.component_entity_type_of_import(name)
+        else {
+            continue;
+        };
+        let shared = adapter
+            .shared
+            .iter()
+            .find(|(shared, _)| *shared == interface)
+            .map_or(&[][..], |(_, functions)| *functions);
+        let changed: Vec<&str> = types[id]
+            .exports
+            .iter()
+            .filter(|(function, ty)| {
+                matches!(ty, ComponentEntityType::Func(_)) && !shared.contains(&function.as_str())
+            })
+            .map(|(function, _)| function.as_str())
+            .collect();
+        if changed.is_empty() {
+            debug!(from = name, to = %adapted, "adapting component import");
+            renames.insert(name.to_string(), adapted);
+        } else {
+            debug!(
+                import = name,
+                ?changed,
+                "not adapting component import using functions that changed"
+            );
+        }
+    }
+    if renames.is_empty() {
+        return Ok(None);
+    }
+
+    let mut component = wasm_encoder::Component::new();
+    ImportRenamer { renames: &renames }
+        .parse_component(&mut component, Parser::new(0), bytes)
+        .context("failed to encode adapted component")?;
+    Ok(Some(component.finish()))
+}
+
+/// Re-encodes a component with imports renamed, along with the matching imports of nested
+/// components and component types, and the arguments nested components are instantiated
+/// with.
+struct ImportRenamer<'a> {
+    renames: &'a HashMap<String, String>,
+}
+
+impl ImportRenamer<'_> {
+    fn rename<'b>(&'b self, name: &'b str) -> &'b str {
+        self.renames.get(name).map_or(name, String::as_str)
+    }
+}
+
+impl Reencode for ImportRenamer<'_> {
+    type Error = std::convert::Infallible;
+}
+
+impl ReencodeComponent for ImportRenamer<'_> {
+    fn parse_component_import_section(
+        &mut self,
+        imports: &mut wasm_encoder::ComponentImportSection,
+        section: wasmparser::ComponentImportSectionReader<'_>,
+    ) -> Result<(), reencode::Error<Self::Error>> {
+        for import in section {
+            let import = import?;
+            let ty = self.component_type_ref(import.ty)?;
+            imports.import(self.rename(import.name.0), ty);
+        }
+        Ok(())
+    }
+
+    fn parse_component_type_declaration(
+        &mut self,
+        component: &mut wasm_encoder::ComponentType,
+        decl: ComponentTypeDeclaration<'_>,
+    ) -> Result<(), reencode::Error<Self::Error>> {
+        match decl {
+            ComponentTypeDeclaration::Import(import) => {
+                let ty = self.component_type_ref(import.ty)?;
+                component.import(self.rename(import.name.0), ty);
+                Ok(())
+            }
+            decl => component_utils::parse_component_type_declaration(self, component, decl),
+        }
+    }
+
+    fn parse_component_instance(
+        &mut self,
+        instances: &mut wasm_encoder::ComponentInstanceSection,
+        instance: ComponentInstance<'_>,
+    ) -> Result<(), reencode::Error<Self::Error>> {
+        match instance {
+            ComponentInstance::Instantiate {
+                component_index,
+                args,
+            } => {
+                let component_index = self.component_index(component_index);
+                let args: Vec<_> = args
+                    .iter()
+                    .map(|arg| {
+                        let index = self.component_external_index(arg.kind, arg.index);
+                        (arg.name, arg.kind, index)
+                    })
+                    .collect();
+                instances.instantiate(
+                    component_index,
+                    args.into_iter()
+                        .map(|(name, kind, index)| (self.rename(name), kind.into(), index)),
+                );
+                Ok(())
+            }
+            instance => component_utils::parse_component_instance(self, instances, instance),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A component built with `wit-component` against the upstream `wasi:keyvalue@0.2.0-draft2`
+    /// WIT, using `store.open`, `bucket.get` and `bucket.set`.
+    const KEYVALUE_DRAFT2_WASM: &[u8] = include_bytes!("../../tests/fixtures/keyvalue_draft2.wasm");
+
+    /// Returns the names of the imports of a component and its nested components, with the
+    /// depth they're nested at.
+    fn import_names(bytes: &[u8]) -> Vec<(usize, String)> {
+        let mut names = Vec::new();
+        let mut depth = 0usize;
+        for payload in Parser::new(0).parse_all(bytes) {
+            match payload.unwrap() {
+                Payload::ModuleSection { .. } | Payload::ComponentSection { .. } => depth += 1,
+                Payload::End(_) => depth = depth.saturating_sub(1),
+                Payload::ComponentImportSection(section) => {
+                    for import in section {
+                        names.push((depth, import.unwrap().name.0.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        names
+    }
+
+    #[cfg(feature = "wasip1")]
+    #[test]
+    fn test_builtin_preview1_adapter() -> anyhow::Result<()> {
+        // A module exporting its memory, and a function of type `func()` as `export`
+        let module = |export: &str| {
+            let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+            bytes.extend([0x01, 0x04, 0x01, 0x60, 0x00, 0x00]);
+            bytes.extend([0x03, 0x02, 0x01, 0x00]);
+            bytes.extend([0x05, 0x03, 0x01, 0x00, 0x01]);
+            bytes.extend([0x07, export.len() as u8 + 13, 0x02]);
+            bytes.extend([0x06]);
+            bytes.extend(b"memory");
+            bytes.extend([0x02, 0x00, export.len() as u8]);
+            bytes.extend(export.as_bytes());
+            bytes.extend([0x00, 0x00]);
+            bytes.extend([0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b]);
+            bytes
+        };
+        let engine = wasmtime::Engine::default();
+        let exports = |module: &[u8]| -> anyhow::Result<Vec<String>> {
+            let adapter = builtin_preview1_adapter(module)?;
+            let bytes = componentize_preview1_module(module, adapter)?;
+            let component = wasmtime::component::Component::new(&engine, bytes)?;
+            Ok(component
+                .component_type()
+                .exports(&engine)
+                .map(|(name, _)| name.to_string())
+                .collect())
+        };
+
+        let command = exports(&module("_start"))?;
+        assert!(
+            command
+                .iter()
+                .any(|name| name.starts_with("wasi:cli/run@0.2")),
+            "{command:?}"
+        );
+        let reactor = exports(&module("run"))?;
+        assert!(
+            reactor.iter().all(|name| !name.starts_with("wasi:cli/run")),
+            "{reactor:?}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_adapt_component_imports() -> anyhow::Result<()> {
+        let adapted = adapt_component_imports(KEYVALUE_DRAFT2_WASM)?
+            .expect("keyvalue import should be adapted");
+
+        assert_eq!(
+            import_names(&adapted),
+            vec![(0, "wasi:keyvalue/store@0.2.0-draft".to_string())]
+        );
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&adapted)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_adapt_component_imports_changed_functions() -> anyhow::Result<()> {
+        // `list-keys` takes a string cursor in draft2 and a u64 in draft
+        let bytes = wat::parse_str(
+            r#"(component
+                (import "wasi:keyvalue/store@0.2.0-draft2" (instance
+                    (export "bucket" (type $bucket (sub resource)))
+                    (type $key-response (record
+                        (field "keys" (list string))
+                        (field "cursor" (option string))))
+                    (export "key-response" (type $kr (eq $key-response)))
+                    (export "[method]bucket.get" (func
+                        (param "self" (borrow $bucket))
+                        (param "key" string)
+                        (result (result (option (list u8)) (error string)))))
+                    (export "[method]bucket.list-keys" (func
+                        (param "self" (borrow $bucket))
+                        (param "cursor" (option string))
+                        (result (result $kr (error string)))))
+                ))
+            )"#,
+        )?;
+        assert_eq!(adapt_component_imports(&bytes)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_adapt_nested_component_imports() -> anyhow::Result<()> {
+        let bytes = wat::parse_str(
+            r#"(component
+                (import "wasi:keyvalue/store@0.2.0-draft2" (instance $kv
+                    (export "bucket" (type (sub resource)))
+                ))
+                (import "composed" (component $composed
+                    (import "wasi:keyvalue/store@0.2.0-draft2" (instance
+                        (export "bucket" (type (sub resource)))
+                    ))
+                ))
+                (component $inner
+                    (import "wasi:keyvalue/store@0.2.0-draft2" (instance
+                        (export "bucket" (type (sub resource)))
+                    ))
+                )
+                (instance (instantiate $inner
+                    (with "wasi:keyvalue/store@0.2.0-draft2" (instance $kv))))
+                (instance (instantiate $composed
+                    (with "wasi:keyvalue/store@0.2.0-draft2" (instance $kv))))
+            )"#,
+        )?;
+        let adapted = adapt_component_imports(&bytes)?.expect("imports should be adapted");
+
+        assert_eq!(
+            import_names(&adapted),
+            vec![
+                (0, "wasi:keyvalue/store@0.2.0-draft".to_string()),
+                (0, "composed".to_string()),
+                (1, "wasi:keyvalue/store@0.2.0-draft".to_string()),
+            ]
+        );
+        // Instantiating the nested and imported components with the renamed instance
+        // only validates if their imports were renamed too
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&adapted)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_adapt_component_imports_unchanged() -> anyhow::Result<()> {
+        let bytes =
+            wat::parse_str(r#"(component (import "wasi:keyvalue/store@0.2.0-draft" (instance)))"#)?;
+        assert_eq!(adapt_component_imports(&bytes)?, None);
+
+        // Core modules are left alone
+        let module = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        assert_eq!(adapt_component_imports(&module)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_adapt_component_imports_malformed() {
+        let bytes = &KEYVALUE_DRAFT2_WASM[..KEYVALUE_DRAFT2_WASM.len() - 2];
+        assert!(adapt_component_imports(bytes).is_err());
+    }
+
+    #[test]
+    fn test_is_core_module() {
+        assert!(is_core_module(&[
+            0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00
+        ]));
+        assert!(!is_core_module(KEYVALUE_DRAFT2_WASM));
+        assert!(!is_core_module(b"\0asm"));
+    }
+
+    #[test]
+    fn test_adapt_interface() {
+        let mut interface = WitInterface::from("wasi:keyvalue/store,atomics@0.2.0-draft2");
+        assert!(adapt_interface(&mut interface));
+        assert_eq!(
+            interface.to_string().split_once('@').unwrap().1,
+            "0.2.0-draft"
+        );
+
+        let mut interface = WitInterface::from("wasi:blobstore/container@0.2.0-draft2");
+        assert!(!adapt_interface(&mut interface));
+    }
+}
@@ -0,0 +1,327 @@
+//! Egress rules parsed from a component's [`LocalResources::allowed_hosts`].
+//!
+//! Each entry has the form `[scheme://]host[:port]`, where `host` is one of:
+//!
+//! - `*`, matching every host
+//! - an exact host name, e.g. `api.example.com`
+//! - a wildcard, e.g. `*.internal.corp`, matching any subdomain but not the domain itself
+//! - an IP address, e.g. `10.1.2.3` or `[::1]`
+//! - a CIDR range, e.g. `10.0.0.0/8` or `[fd00::/8]:443`
+//!
+//! Leaving out the scheme or port allows any. Outgoing HTTP requests are checked against
+//! every rule. Socket connections only carry an address, so they are checked against the
+//! rules without a scheme whose host is `*`, an IP address or a CIDR range. `localhost`
+//! and loopback addresses are always allowed.
+//!
+//! An empty list allows every outgoing HTTP request and leaves sockets denied, which is
+//! the behavior of components without `allowed_hosts`.
+//!
+//! [`LocalResources::allowed_hosts`]: crate::types::LocalResources::allowed_hosts
+
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::{Context, bail, ensure};
+
+/// The host part of an [`AllowedHosts`] rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HostPattern {
+    Any,
+    Exact(String),
+    /// Matches hosts ending in the suffix, which includes the leading `.`
+    Wildcard(String),
+    Ip(IpAddr),
+    Cidr(IpAddr, u8),
+}
+
+impl HostPattern {
+    fn parse(host: &str) -> anyhow::Result<Self> {
+        if host == "*" {
+            return Ok(Self::Any);
+        }
+        if let Some(suffix) = host.strip_prefix("*.") {
+            ensure!(
+                is_host_name(suffix),
+                "invalid wildcard host '{host}', expected e.g. '*.example.com'"
+            );
+            return Ok(Self::Wildcard(format!(".{suffix}")));
+        }
+        if let Some((ip, prefix)) = host.split_once('/') {
+            let ip: IpAddr = ip
+                .parse()
+                .with_context(|| format!("invalid CIDR range '{host}'"))?;
+            let prefix: u8 = prefix
+                .parse()
+                .with_context(|| format!("invalid CIDR prefix length in '{host}'"))?;
+            let max = if ip.is_ipv4() { 32 } else { 128 };
+            ensure!(
+                prefix <= max,
+                "CIDR prefix length in '{host}' is larger than {max}"
+            );
+            return Ok(Self::Cidr(ip, prefix));
+        }
+        if let Ok(ip) = host.parse() {
+            return Ok(Self::Ip(ip));
+        }
+        ensure!(is_host_name(host), "invalid host '{host}'");
+        Ok(Self::Exact(host.to_string()))
+    }
+
+    fn matches_host(&self, host: &str) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Exact(exact) => exact == host,
+            Self::Wildcard(suffix) => host.len() > suffix.len() && host.ends_with(suffix.as_str()),
+            Self::Ip(_) | Self::Cidr(..) => host.parse().is_ok_and(|ip| self.matches_ip(ip)),
+        }
+    }
+
+    fn matches_ip(&self, ip: IpAddr) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Ip(allowed) => *allowed == ip,
+            Self::Cidr(IpAddr::V4(net), prefix) => match ip {
+                IpAddr::V4(ip) => {
+                    let mask = u32::MAX.checked_shl(32 - u32::from(*prefix)).unwrap_or(0);
+                    u32::from(*net) & mask == u32::from(ip) & mask
+                }
+                IpAddr::V6(_) => false,
+            },
+            Self::Cidr(IpAddr::V6(net), prefix) => match ip {
+                IpAddr::V6(ip) => {
+                    let mask = u128::MAX.checked_shl(128 - u32::from(*prefix)).unwrap_or(0);
+                    u128::from(*net) & mask == u128::from(ip) & mask
+                }
+                IpAddr::V4(_) => false,
+            },
+            Self::Exact(_) | Self::Wildcard(_) => false,
+        }
+    }
+}
+
+/// A single `[scheme://]host[:port]` entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct HostRule {
+    scheme: Option<String>,
+    host: HostPattern,
+    port: Option<u16>,
+}
+
+impl HostRule {
+    fn parse(entry: &str) -> anyhow::Result<Self> {
+        let entry = entry.trim().to_ascii_lowercase();
+        let (scheme, rest) = match entry.split_once("://") {
+            Some((scheme, rest)) => {
+                ensure!(
+                    !scheme.is_empty()
+                        && scheme
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')),
+                    "invalid scheme '{scheme}'"
+                );
+                (Some(scheme.to_string()), rest)
+            }
+            None => (None, entry.as_str()),
+        };
+
+        let (host, port) = if let Some(bracketed) = rest.strip_prefix('[') {
+            let Some((host, after)) = bracketed.split_once(']') else {
+                bail!("missing ']' in '{rest}'");
+            };
+            match after.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None if after.is_empty() => (host, None),
+                None => bail!("unexpected '{after}' after ']' in '{rest}'"),
+            }
+        } else if rest.matches(':').count() == 1 {
+            let (host, port) = rest.split_once(':').unwrap_or((rest, ""));
+            (host, Some(port))
+        } else {
+            (rest, None)
+        };
+        let port = port
+            .map(|port| {
+                port.parse::<u16>()
+                    .with_context(|| format!("invalid port '{port}'"))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            scheme,
+            host: HostPattern::parse(host)?,
+            port,
+        })
+    }
+}
+
+/// The hosts a component may reach over outgoing HTTP and sockets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllowedHosts {
+    rules: Vec<HostRule>,
+}
+
+impl AllowedHosts {
+    /// Parses `allowed_hosts` entries, see the [module docs](self) for the syntax.
+    ///
+    /// # Errors
+    /// Returns an error naming the first entry that can't be parsed.
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> anyhow::Result<Self> {
+        let rules = entries
+            .iter()
+            .map(|entry| {
+                HostRule::parse(entry.as_ref())
+                    .with_context(|| format!("invalid allowed host '{}'", entry.as_ref()))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Returns whether no entries were given.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns whether an outgoing HTTP request may be sent. Every request is allowed
+    /// when no entries were given.
+    ///
+    /// # Arguments
+    /// * `scheme` - The request scheme, e.g. `https`
+    /// * `host` - The request host, with IPv6 addresses optionally in brackets
+    /// * `port` - The request port, defaulting to the scheme's well-known port
+    pub fn allows_request(&self, scheme: &str, host: &str, port: Option<u16>) -> bool {
+        if self.rules.is_empty() {
+            return true;
+        }
+        let scheme = scheme.to_ascii_lowercase();
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_ascii_lowercase();
+        if host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()) {
+            return true;
+        }
+        let port = port.or(match scheme.as_str() {
+            "http" => Some(80),
+            "https" => Some(443),
+            _ => None,
+        });
+        self.rules.iter().any(|rule| {
+            rule.scheme.as_ref().is_none_or(|s| *s == scheme)
+                && rule.port.is_none_or(|p| Some(p) == port)
+                && rule.host.matches_host(&host)
+        })
+    }
+
+    /// Returns whether a socket may connect or send datagrams to `addr`. No socket is
+    /// allowed when no entries were given.
+    pub fn allows_socket(&self, addr: SocketAddr) -> bool {
+        if !self.rules.is_empty() && addr.ip().is_loopback() {
+            return true;
+        }
+        self.rules.iter().any(|rule| {
+            rule.scheme.is_none()
+                && rule.port.is_none_or(|p| p == addr.port())
+                && rule.host.matches_ip(addr.ip())
+        })
+    }
+}
+
+/// Returns whether `host` is a plausible DNS host name.
+fn is_host_name(host: &str) -> bool {
+    !host.is_empty()
+        && host.split('.').all(|label| {
+            !label.is_empty()
+                && label
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_allows_requests_only() -> anyhow::Result<()> {
+        let allowed = AllowedHosts::parse::<&str>(&[])?;
+        assert!(allowed.allows_request("https", "example.com", None));
+        assert!(!allowed.allows_socket("10.0.0.1:80".parse()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_patterns() -> anyhow::Result<()> {
+        let allowed = AllowedHosts::parse(&["api.example.com", "*.internal.corp"])?;
+        assert!(allowed.allows_request("https", "api.example.com", None));
+        assert!(allowed.allows_request("http", "API.example.com", Some(8080)));
+        assert!(!allowed.allows_request("https", "example.com", None));
+        assert!(allowed.allows_request("https", "db.internal.corp", None));
+        assert!(allowed.allows_request("https", "a.b.internal.corp", None));
+        assert!(!allowed.allows_request("https", "internal.corp", None));
+        assert!(!allowed.allows_request("https", "evilinternal.corp", None));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ports_and_schemes() -> anyhow::Result<()> {
+        let allowed = AllowedHosts::parse(&["https://api.example.com", "cache.local:6379"])?;
+        assert!(allowed.allows_request("https", "api.example.com", None));
+        assert!(!allowed.allows_request("http", "api.example.com", None));
+        assert!(allowed.allows_request("http", "cache.local", Some(6379)));
+        assert!(!allowed.allows_request("http", "cache.local", None));
+
+        let allowed = AllowedHosts::parse(&["https://*:443"])?;
+        assert!(allowed.allows_request("https", "anything.example", None));
+        assert!(!allowed.allows_request("https", "anything.example", Some(8443)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_ips_and_cidrs() -> anyhow::Result<()> {
+        let allowed = AllowedHosts::parse(&["10.0.0.0/8", "[fd00::/8]:443", "192.168.1.10"])?;
+        assert!(allowed.allows_request("http", "10.20.30.40", None));
+        assert!(!allowed.allows_request("http", "11.0.0.1", None));
+        assert!(allowed.allows_request("https", "[fd00::1]", None));
+        assert!(!allowed.allows_request("http", "[fd00::1]", None));
+
+        assert!(allowed.allows_socket("10.1.2.3:5432".parse()?));
+        assert!(allowed.allows_socket("192.168.1.10:22".parse()?));
+        assert!(!allowed.allows_socket("192.168.1.11:22".parse()?));
+        assert!(allowed.allows_socket("[fd00::1]:443".parse()?));
+        assert!(!allowed.allows_socket("[fd00::1]:80".parse()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_loopback_always_allowed() -> anyhow::Result<()> {
+        let allowed = AllowedHosts::parse(&["https://api.example.com"])?;
+        assert!(allowed.allows_request("http", "localhost", Some(8080)));
+        assert!(allowed.allows_request("http", "[::1]", None));
+        assert!(allowed.allows_socket("127.0.0.1:6379".parse()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_socket_rules_ignore_names_and_schemes() -> anyhow::Result<()> {
+        let allowed = AllowedHosts::parse(&["example.com", "https://10.0.0.1"])?;
+        assert!(!allowed.allows_socket("10.0.0.1:443".parse()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        for entry in [
+            "",
+            "*.",
+            "exa mple.com",
+            "10.0.0.0/33",
+            "example.com:http",
+            "[::1",
+            "://x",
+        ] {
+            assert!(
+                AllowedHosts::parse(&[entry]).is_err(),
+                "'{entry}' should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Memory arenas, sizing the pooling allocator's pre-reserved instance slots from the
+//! workloads a host runs rather than from wasmtime's defaults.
+//!
+//! With the pooling allocator, wasmtime reserves the address space of every instance
+//! slot when the engine is created and resets a slot's memories in place when an instance
+//! is dropped, so instantiating doesn't map and unmap memory. Its default slots are sized
+//! for any instance, reserving far more address space than hosts running many workloads
+//! with small pools need, and falling back to mapping each instance when a machine can't
+//! reserve that much. A [`MemoryArena`] reserves a slot for each of a component's
+//! `pool_size` instances, at least one, with memories as large as the largest component
+//! `memory_limit_mb`: `pool_size * memory_limit_mb` over the host's components.
+//!
+//! The arena is a hard limit: instantiating fails once every slot is in use, and memories
+//! can't grow past the slot size, even those of components without a memory limit. An
+//! arena must therefore cover every invocation the host runs at once, e.g. by building it
+//! with [`MemoryArena::new`] and some headroom. A component instance can hold several core
+//! instances, such as those of WASI adapters, each with its own memory and table, so every
+//! slot has room for [`CORE_INSTANCES_PER_SLOT`] of them.
+
+use wasmtime::PoolingAllocationConfig;
+
+use crate::types::Workload;
+
+/// The core instances, memories and tables each slot has room for.
+pub const CORE_INSTANCES_PER_SLOT: u32 = 4;
+
+/// The instance slots an engine's pooling allocator reserves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryArena {
+    slots: u32,
+    slot_memory_bytes: Option<usize>,
+}
+
+impl MemoryArena {
+    /// Creates an arena of `slots` component instances, with memories of up to
+    /// `slot_memory_bytes` each, or as large as wasmtime allows by default if `None`.
+    pub fn new(slots: u32, slot_memory_bytes: Option<usize>) -> Self {
+        Self {
+            slots: slots.max(1),
+            slot_memory_bytes,
+        }
+    }
+
+    /// Sizes an arena for the components and services of `workloads`: a slot for each
+    /// instance in their pools, with memories as large as the largest memory limit.
+    ///
+    /// # Returns
+    /// The arena, with slots as large as wasmtime allows by default if any component or
+    /// service doesn't set a memory limit.
+    pub fn for_workloads<'a>(workloads: impl IntoIterator<Item = &'a Workload>) -> Self {
+        let mut slots: u32 = 0;
+        let mut slot_memory_bytes = Some(0);
+        for workload in workloads {
+            let pools = workload
+                .components
+                .iter()
+                .map(|component| (component.pool_size, &component.local_resources))
+                .chain(
+                    workload
+                        .service
+                        .iter()
+                        .map(|service| (1, &service.local_resources)),
+                );
+            for (pool_size, resources) in pools {
+                slots = slots.saturating_add(u32::try_from(pool_size).unwrap_or(0).max(1));
+                slot_memory_bytes = match usize::try_from(resources.memory_limit_mb) {
+                    Ok(limit_mb @ 1..) => {
+                        slot_memory_bytes.map(|bytes: usize| bytes.max(limit_mb * 1024 * 1024))
+                    }
+                    _ => None,
+                };
+            }
+        }
+        Self::new(slots, slot_memory_bytes.filter(|bytes| *bytes > 0))
+    }
+
+    /// The component instances the arena has slots for.
+    pub fn slots(&self) -> u32 {
+        self.slots
+    }
+
+    /// The most bytes each memory in the arena can grow to, if the arena limits them.
+    pub fn slot_memory_bytes(&self) -> Option<usize> {
+        self.slot_memory_bytes
+    }
+
+    /// Limits a pooling allocator to the arena's slots.
+    pub(crate) fn configure(&self, pooling: &mut PoolingAllocationConfig) {
+        let core_slots = self.slots.saturating_mul(CORE_INSTANCES_PER_SLOT);
+        pooling
+            .total_component_instances(self.slots)
+            .total_core_instances(core_slots)
+            .total_memories(core_slots)
+            .total_tables(core_slots)
+            .total_stacks(self.slots);
+        if let Some(bytes) = self.slot_memory_bytes {
+            pooling.max_memory_size(bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Component, LocalResources};
+
+    fn component(pool_size: i32, memory_limit_mb: i32) -> Component {
+        Component {
+            pool_size,
+            local_resources: LocalResources {
+                memory_limit_mb,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn workload(components: Vec<Component>) -> Workload {
+        Workload {
+            namespace: "default".to_string(),
+            name: "arena".to_string(),
+            annotations: Default::default(),
+            secret_config_keys: Default::default(),
+            service: None,
+            components,
+            host_interfaces: vec![],
+            volumes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_for_workloads() {
+        let workloads = [
+            workload(vec![component(4, 64), component(0, 16)]),
+            workload(vec![component(2, 128)]),
+        ];
+        let arena = MemoryArena::for_workloads(&workloads);
+        assert_eq!(arena.slots(), 7);
+        assert_eq!(arena.slot_memory_bytes(), Some(128 * 1024 * 1024));
+
+        let unlimited = workload(vec![component(1, -1)]);
+        let arena = MemoryArena::for_workloads(workloads.iter().chain([&unlimited]));
+        assert_eq!(arena.slots(), 8);
+        assert_eq!(arena.slot_memory_bytes(), None);
+
+        assert_eq!(MemoryArena::for_workloads([]).slots(), 1);
+    }
+}
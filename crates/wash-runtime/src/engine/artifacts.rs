@@ -0,0 +1,94 @@
+//! Compiled artifacts, kept so components aren't compiled again each time they're loaded,
+//! and shared between clustered hosts, see [`crate::host::artifacts`].
+//!
+//! An engine keeps the last [`MAX_CACHED`] components it compiled or loaded, by artifact
+//! key: the SHA-256 of the component's bytes and a hash of the engine settings its
+//! compilation depends on, see [`crate::engine::Engine::artifact_key`]. Starting a
+//! workload whose components are cached, e.g. when it's restarted or scaled out, skips
+//! compiling them. Clones of an engine share its cache.
+
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+
+use wasmtime::component::Component;
+
+/// The most compiled components an engine keeps.
+pub const MAX_CACHED: usize = 32;
+
+/// Compiled components by artifact key, least recently used first.
+#[derive(Default)]
+pub(crate) struct ArtifactCache {
+    components: Mutex<VecDeque<(String, Component)>>,
+}
+
+impl ArtifactCache {
+    /// The component cached under `key`, which becomes the most recently used.
+    pub(crate) fn get(&self, key: &str) -> Option<Component> {
+        let mut components = self
+            .components
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let index = components.iter().position(|(cached, _)| cached == key)?;
+        let entry = components.remove(index)?;
+        let component = entry.1.clone();
+        components.push_back(entry);
+        Some(component)
+    }
+
+    /// Caches a component, dropping the least recently used one when the cache is full.
+    pub(crate) fn insert(&self, key: String, component: Component) {
+        let mut components = self
+            .components
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        components.retain(|(cached, _)| *cached != key);
+        if components.len() >= MAX_CACHED {
+            components.pop_front();
+        }
+        components.push_back((key, component));
+    }
+}
+
+impl std::fmt::Debug for ArtifactCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components = self
+            .components
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        f.debug_list()
+            .entries(components.iter().map(|(key, _)| key))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+
+    const EMPTY_COMPONENT: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+
+    #[test]
+    fn test_share_artifact() -> anyhow::Result<()> {
+        let compiling = Engine::builder().with_pooling_allocator(false).build()?;
+        let loading = Engine::builder().with_pooling_allocator(false).build()?;
+        let key = compiling.artifact_key(&EMPTY_COMPONENT);
+        assert_eq!(key, loading.artifact_key(&EMPTY_COMPONENT));
+        assert!(compiling.serialize_artifact(&key)?.is_none());
+
+        compiling.compile_component(&EMPTY_COMPONENT)?;
+        let artifact = compiling
+            .serialize_artifact(&key)?
+            .expect("compiled component is cached");
+        assert!(!loading.has_artifact(&key));
+        // SAFETY: the artifact was just serialized by an engine with the same settings
+        unsafe { loading.load_artifact(&key, &artifact)? };
+        assert!(loading.has_artifact(&key));
+
+        let unadapted = Engine::builder()
+            .with_pooling_allocator(false)
+            .with_version_adapters(false)
+            .build()?;
+        assert_ne!(key, unadapted.artifact_key(&EMPTY_COMPONENT));
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+//! Claims embedded in a component by `wash claims sign` in earlier versions of wasmCloud:
+//! a JWT in the component's `jwt` custom section, signed with the Ed25519 nkey of the
+//! account that issued it.
+//!
+//! The claims name the component and its version, and carry the hash of the component
+//! without the `jwt` section. They're only reported as [verified](ComponentClaims::verified)
+//! when the signature is valid for the issuer's key and the hash matches the component
+//! they're embedded in.
+
+use aws_lc_rs::{digest, signature};
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::Deserialize;
+
+use crate::engine::inspect::read_leb128;
+use crate::types::ComponentClaims;
+
+/// The custom section holding the claims JWT.
+const JWT_SECTION: &str = "jwt";
+/// The length of a decoded nkey: a prefix byte, the 32 byte key and a CRC-16.
+const NKEY_LENGTH: usize = 35;
+
+#[derive(Deserialize)]
+struct Header {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: String,
+    sub: String,
+    iat: Option<u64>,
+    exp: Option<u64>,
+    wascap: Option<Metadata>,
+}
+
+#[derive(Default, Deserialize)]
+struct Metadata {
+    name: Option<String>,
+    hash: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    rev: Option<i32>,
+    ver: Option<String>,
+}
+
+/// Reads the claims embedded in a component, if it has any that can be decoded.
+pub(crate) fn embedded_claims(bytes: &[u8]) -> Option<ComponentClaims> {
+    let (token, stripped) = split_jwt_section(bytes)?;
+    let token = std::str::from_utf8(token).ok()?;
+    let mut segments = token.split('.');
+    let (header, payload, sig) = (segments.next()?, segments.next()?, segments.next()?);
+    if segments.next().is_some() {
+        return None;
+    }
+    let header: Header = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+    let claims: Claims = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    let metadata = claims.wascap.unwrap_or_default();
+
+    let signature_valid = header.alg.eq_ignore_ascii_case("ed25519")
+        && URL_SAFE_NO_PAD
+            .decode(sig)
+            .ok()
+            .zip(nkey_public_key(&claims.iss))
+            .is_some_and(|(sig, key)| {
+                signature::UnparsedPublicKey::new(&signature::ED25519, key)
+                    .verify(token_prefix(token).as_bytes(), &sig)
+                    .is_ok()
+            });
+    let hash_matches = metadata
+        .hash
+        .as_deref()
+        .is_some_and(|hash| hash.eq_ignore_ascii_case(&sha256_hex(&stripped)));
+
+    Some(ComponentClaims {
+        issuer: claims.iss,
+        subject: claims.sub,
+        name: metadata.name,
+        version: metadata.ver,
+        revision: metadata.rev,
+        tags: metadata.tags,
+        issued_at: claims.iat,
+        expires_at: claims.exp,
+        verified: signature_valid && hash_matches,
+    })
+}
+
+/// Returns the `header.payload` part of a JWT that its signature covers.
+fn token_prefix(token: &str) -> &str {
+    token.rsplit_once('.').map_or(token, |(prefix, _)| prefix)
+}
+
+/// Finds the top-level `jwt` custom section of a binary.
+///
+/// # Returns
+/// The section's contents, and the binary without the section, which the claims' hash
+/// covers.
+fn split_jwt_section(bytes: &[u8]) -> Option<(&[u8], Vec<u8>)> {
+    // Skip the preamble: 4 bytes of magic followed by a 4 byte version and layer
+    let mut pos = 8;
+    while let Some(&section_id) = bytes.get(pos) {
+        let start = pos;
+        let (size, read) = read_leb128(bytes.get(pos + 1..)?)?;
+        let contents = pos + 1 + read;
+        let end = contents.checked_add(size)?;
+        let section = bytes.get(contents..end)?;
+        pos = end;
+
+        if section_id != 0 {
+            continue;
+        }
+        let Some((name_len, read)) = read_leb128(section) else {
+            continue;
+        };
+        if section.get(read..read + name_len) == Some(JWT_SECTION.as_bytes()) {
+            let mut stripped = bytes[..start].to_vec();
+            stripped.extend_from_slice(&bytes[end..]);
+            return Some((&section[read + name_len..], stripped));
+        }
+    }
+    None
+}
+
+/// Decodes the Ed25519 public key of an nkey, e.g. an account key starting with `A`,
+/// checking its CRC.
+fn nkey_public_key(nkey: &str) -> Option<Vec<u8>> {
+    let decoded = base32_decode(nkey)?;
+    if decoded.len() != NKEY_LENGTH {
+        return None;
+    }
+    let (data, crc) = decoded.split_at(NKEY_LENGTH - 2);
+    (crc16(data) == u16::from_le_bytes([crc[0], crc[1]])).then(|| data[1..].to_vec())
+}
+
+/// Decodes unpadded RFC 4648 base32, as nkeys are encoded.
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() * 5 / 8);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for c in encoded.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'2'..=b'7' => c - b'2' + 26,
+            _ => return None,
+        };
+        buffer = (buffer << 5) | u32::from(value);
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// The CRC-16/XMODEM checksum nkeys end with.
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, &byte| {
+        (0..8).fold(crc ^ (u16::from(byte) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    digest::digest(&digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_lc_rs::signature::KeyPair as _;
+
+    use super::*;
+
+    fn base32_encode(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+        let (mut encoded, mut buffer, mut bits) = (String::new(), 0u32, 0);
+        for &byte in bytes {
+            buffer = (buffer << 8) | u32::from(byte);
+            bits += 8;
+            while bits >= 5 {
+                bits -= 5;
+                encoded.push(ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+            }
+        }
+        if bits > 0 {
+            encoded.push(ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+        }
+        encoded
+    }
+
+    /// Encodes an Ed25519 public key as an account nkey.
+    fn account_nkey(key: &[u8]) -> String {
+        let mut data = vec![0];
+        data.extend_from_slice(key);
+        let crc = crc16(&data);
+        data.extend_from_slice(&crc.to_le_bytes());
+        base32_encode(&data)
+    }
+
+    fn jwt_section(token: &str) -> Vec<u8> {
+        let mut payload = vec![JWT_SECTION.len() as u8];
+        payload.extend_from_slice(JWT_SECTION.as_bytes());
+        payload.extend_from_slice(token.as_bytes());
+        let mut section = vec![0];
+        let mut size = payload.len();
+        // LEB128, as tokens are longer than a single byte can say
+        loop {
+            let byte = (size & 0x7f) as u8;
+            size >>= 7;
+            if size == 0 {
+                section.push(byte);
+                break;
+            }
+            section.push(byte | 0x80);
+        }
+        section.extend(payload);
+        section
+    }
+
+    /// A component with claims for its own hash, signed by `signer`.
+    fn signed_component(signer: &signature::Ed25519KeyPair, issuer: &str) -> Vec<u8> {
+        let component = vec![0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+        let header = URL_SAFE_NO_PAD.encode(r#"{"typ":"jwt","alg":"Ed25519"}"#);
+        let claims = URL_SAFE_NO_PAD.encode(
+            serde_json::json!({
+                "iss": issuer,
+                "sub": "MBCFOPM6JW2APJLXJD3Z5O4CN7CPYJ2B4FTKLJUR5YR5MITIU7HD3WD5",
+                "iat": 1_700_000_000,
+                "wascap": {
+                    "name": "http-hello-world",
+                    "hash": sha256_hex(&component),
+                    "tags": ["wasmcloud.com/experimental"],
+                    "rev": 2,
+                    "ver": "0.1.0",
+                },
+            })
+            .to_string(),
+        );
+        let signature =
+            URL_SAFE_NO_PAD.encode(signer.sign(format!("{header}.{claims}").as_bytes()));
+        let mut signed = component;
+        signed.extend(jwt_section(&format!("{header}.{claims}.{signature}")));
+        signed
+    }
+
+    #[test]
+    fn test_embedded_claims() -> anyhow::Result<()> {
+        let signer = signature::Ed25519KeyPair::generate()?;
+        let issuer = account_nkey(signer.public_key().as_ref());
+        assert!(issuer.starts_with('A'));
+        let component = signed_component(&signer, &issuer);
+
+        let claims = embedded_claims(&component).expect("the component has claims");
+        assert_eq!(claims.issuer, issuer);
+        assert_eq!(claims.name.as_deref(), Some("http-hello-world"));
+        assert_eq!(claims.version.as_deref(), Some("0.1.0"));
+        assert_eq!(claims.revision, Some(2));
+        assert!(claims.verified);
+
+        // Claims signed by another key than their issuer's aren't verified
+        let other = signature::Ed25519KeyPair::generate()?;
+        let forged = signed_component(&other, &issuer);
+        assert!(!embedded_claims(&forged).is_some_and(|claims| claims.verified));
+
+        // Nor are claims for another component
+        let mut modified = component.clone();
+        modified.extend([0, 2, 1, b'x']);
+        assert!(!embedded_claims(&modified).is_some_and(|claims| claims.verified));
+
+        assert!(embedded_claims(&component[..8]).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nkey_public_key() {
+        let key = [7u8; 32];
+        let nkey = account_nkey(&key);
+        assert_eq!(nkey_public_key(&nkey), Some(key.to_vec()));
+
+        // A changed character fails the CRC
+        let mut corrupted = nkey.into_bytes();
+        corrupted[10] = if corrupted[10] == b'A' { b'B' } else { b'A' };
+        let corrupted = String::from_utf8(corrupted).expect("nkeys are ASCII");
+        assert_eq!(nkey_public_key(&corrupted), None);
+        assert_eq!(nkey_public_key("not-an-nkey"), None);
+    }
+}
@@ -0,0 +1,452 @@
+//! Compatibility of a replacement component with a running one, to check an update before
+//! it's applied.
+//!
+//! [`diff`] compares the instances two components import and export, and the signatures
+//! of their functions. The changes that can break a workload are
+//! [breaking](WitChange::is_breaking): exports removed or moved to an incompatible
+//! version, which callers of the component rely on, imports added or moved to an
+//! incompatible version, which the host or the workload may not provide, and functions
+//! whose signature changed. Signatures are compared by structure, so renaming a type
+//! isn't a change, and resources are only told apart by being owned or borrowed.
+//!
+//! An instance is compared with the replacement's instance of the same name, or else with
+//! its only other version of the same interface, so a component importing or exporting
+//! several versions of an interface has each compared with its own counterpart.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use wasmtime::component::types::{ComponentFunc, ComponentInstance, ComponentItem, Type};
+
+use crate::types::WitChange;
+
+/// The signatures of an instance's functions by function name.
+type Functions = BTreeMap<String, String>;
+
+/// The instances a component imports and exports, with the signatures of their functions.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ComponentInterfaces {
+    /// Fully qualified names of the imported instances, e.g. `wasi:keyvalue/store@0.2.0-draft`
+    imports: BTreeMap<String, Functions>,
+    /// Fully qualified names of the exported instances
+    exports: BTreeMap<String, Functions>,
+}
+
+impl ComponentInterfaces {
+    /// Reads the instances a compiled component imports and exports.
+    pub fn of(component: &wasmtime::component::Component) -> Self {
+        let engine = component.engine();
+        let ty = component.component_type();
+        Self {
+            imports: instances(engine, ty.imports(engine)),
+            exports: instances(engine, ty.exports(engine)),
+        }
+    }
+}
+
+/// Reads the signatures of the functions of the instances among a component's imports or
+/// exports.
+fn instances<'a>(
+    engine: &wasmtime::Engine,
+    items: impl Iterator<Item = (&'a str, ComponentItem)>,
+) -> BTreeMap<String, Functions> {
+    items
+        .filter_map(|(name, item)| match item {
+            ComponentItem::ComponentInstance(instance) => {
+                Some((name.to_string(), functions(engine, &instance)))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reads the signatures of an instance's functions.
+fn functions(engine: &wasmtime::Engine, instance: &ComponentInstance) -> Functions {
+    instance
+        .exports(engine)
+        .filter_map(|(name, item)| match item {
+            ComponentItem::ComponentFunc(func) => Some((name.to_string(), signature(&func))),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Lists the changes between the interfaces of a running component and its replacement.
+///
+/// # Returns
+/// The changes to exports followed by those to imports, each sorted by instance name.
+/// Empty if both components have the same interfaces.
+pub fn diff(running: &ComponentInterfaces, replacement: &ComponentInterfaces) -> Vec<WitChange> {
+    let mut changes = Vec::new();
+
+    let exports = pair(&running.exports, &replacement.exports);
+    for (interface, new) in &exports {
+        let Some(new) = new else {
+            changes.push(WitChange::ExportRemoved {
+                interface: interface.to_string(),
+            });
+            continue;
+        };
+        if new != interface {
+            changes.push(WitChange::ExportVersionChanged {
+                interface: interface.to_string(),
+                replacement: new.to_string(),
+            });
+        }
+        let functions = &replacement.exports[*new];
+        for (function, signature) in &running.exports[*interface] {
+            match functions.get(function) {
+                None => changes.push(WitChange::FunctionRemoved {
+                    interface: interface.to_string(),
+                    function: function.clone(),
+                }),
+                Some(new) if new != signature => changes.push(WitChange::FunctionChanged {
+                    interface: interface.to_string(),
+                    function: function.clone(),
+                    signature: signature.clone(),
+                    replacement: new.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+    }
+    changes.extend(unpaired(&exports, &replacement.exports).map(|interface| {
+        WitChange::ExportAdded {
+            interface: interface.to_string(),
+        }
+    }));
+
+    let imports = pair(&running.imports, &replacement.imports);
+    for (interface, new) in &imports {
+        let Some(new) = new else {
+            changes.push(WitChange::ImportRemoved {
+                interface: interface.to_string(),
+            });
+            continue;
+        };
+        if new != interface {
+            changes.push(WitChange::ImportVersionChanged {
+                interface: interface.to_string(),
+                replacement: new.to_string(),
+            });
+        }
+        let functions = &running.imports[*interface];
+        for (function, new) in &replacement.imports[*new] {
+            match functions.get(function) {
+                None => changes.push(WitChange::ImportFunctionAdded {
+                    interface: interface.to_string(),
+                    function: function.clone(),
+                }),
+                Some(signature) if signature != new => changes.push(WitChange::FunctionChanged {
+                    interface: interface.to_string(),
+                    function: function.clone(),
+                    signature: signature.clone(),
+                    replacement: new.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+    }
+    changes.extend(unpaired(&imports, &replacement.imports).map(|interface| {
+        WitChange::ImportAdded {
+            interface: interface.to_string(),
+        }
+    }));
+    changes
+}
+
+/// Pairs each running instance with its counterpart in the replacement: the instance of
+/// the same name, or else the only other version of the same interface, if the running
+/// component has no other version of it either.
+///
+/// # Returns
+/// The running instances by name, each with its counterpart or `None` if it has none.
+fn pair<'a>(
+    running: &'a BTreeMap<String, Functions>,
+    replacement: &'a BTreeMap<String, Functions>,
+) -> BTreeMap<&'a str, Option<&'a str>> {
+    let unmatched = |instances: &'a BTreeMap<String, Functions>,
+                     others: &BTreeMap<String, Functions>| {
+        let mut by_path: BTreeMap<&'a str, Vec<&'a str>> = BTreeMap::new();
+        for name in instances.keys().filter(|name| !others.contains_key(*name)) {
+            by_path.entry(unversioned(name)).or_default().push(name);
+        }
+        by_path
+    };
+    let replacement_versions = unmatched(replacement, running);
+    let running_versions = unmatched(running, replacement);
+    running
+        .keys()
+        .map(|name| {
+            let counterpart = if replacement.contains_key(name) {
+                Some(name.as_str())
+            } else {
+                let path = unversioned(name);
+                match (running_versions.get(path), replacement_versions.get(path)) {
+                    (Some(old), Some(new)) if old.len() == 1 && new.len() == 1 => Some(new[0]),
+                    _ => None,
+                }
+            };
+            (name.as_str(), counterpart)
+        })
+        .collect()
+}
+
+/// Returns the replacement's instances without a counterpart in the running component.
+fn unpaired<'a>(
+    pairs: &BTreeMap<&str, Option<&str>>,
+    replacement: &'a BTreeMap<String, Functions>,
+) -> impl Iterator<Item = &'a String> {
+    let paired: BTreeSet<&str> = pairs.values().flatten().copied().collect();
+    replacement
+        .keys()
+        .filter(move |name| !paired.contains(name.as_str()))
+}
+
+/// Strips the version from an instance name, e.g. `wasi:http/types@0.2.0` to `wasi:http/types`.
+fn unversioned(name: &str) -> &str {
+    name.split_once('@').map_or(name, |(path, _)| path)
+}
+
+/// Renders a function's signature like `func(key: string) -> option<list<u8>>`.
+fn signature(func: &ComponentFunc) -> String {
+    let params = join(
+        func.params()
+            .map(|(name, ty)| format!("{name}: {}", type_name(&ty))),
+    );
+    let results: Vec<String> = func.results().map(|ty| type_name(&ty)).collect();
+    match results.as_slice() {
+        [] => format!("func({params})"),
+        [result] => format!("func({params}) -> {result}"),
+        _ => format!("func({params}) -> ({})", results.join(", ")),
+    }
+}
+
+/// Renders a type by its structure, e.g. `record { id: u32, tags: list<string> }`, as
+/// the names of a component's types aren't part of its type.
+fn type_name(ty: &Type) -> String {
+    match ty {
+        Type::Bool => "bool".to_string(),
+        Type::S8 => "s8".to_string(),
+        Type::U8 => "u8".to_string(),
+        Type::S16 => "s16".to_string(),
+        Type::U16 => "u16".to_string(),
+        Type::S32 => "s32".to_string(),
+        Type::U32 => "u32".to_string(),
+        Type::S64 => "s64".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::Float32 => "f32".to_string(),
+        Type::Float64 => "f64".to_string(),
+        Type::Char => "char".to_string(),
+        Type::String => "string".to_string(),
+        Type::List(list) => format!("list<{}>", type_name(&list.ty())),
+        Type::Record(record) => format!(
+            "record {{ {} }}",
+            join(
+                record
+                    .fields()
+                    .map(|field| format!("{}: {}", field.name, type_name(&field.ty)))
+            )
+        ),
+        Type::Tuple(tuple) => format!("tuple<{}>", join(tuple.types().map(|ty| type_name(&ty)))),
+        Type::Variant(variant) => format!(
+            "variant {{ {} }}",
+            join(variant.cases().map(|case| match case.ty {
+                Some(ty) => format!("{}({})", case.name, type_name(&ty)),
+                None => case.name.to_string(),
+            }))
+        ),
+        Type::Enum(enum_) => format!("enum {{ {} }}", join(enum_.names().map(str::to_string))),
+        Type::Option(option) => format!("option<{}>", type_name(&option.ty())),
+        Type::Result(result) => match (result.ok(), result.err()) {
+            (None, None) => "result".to_string(),
+            (Some(ok), None) => format!("result<{}>", type_name(&ok)),
+            (None, Some(err)) => format!("result<_, {}>", type_name(&err)),
+            (Some(ok), Some(err)) => format!("result<{}, {}>", type_name(&ok), type_name(&err)),
+        },
+        Type::Flags(flags) => format!("flags {{ {} }}", join(flags.names().map(str::to_string))),
+        Type::Own(_) => "own<resource>".to_string(),
+        Type::Borrow(_) => "borrow<resource>".to_string(),
+        Type::Future(future) => match future.ty() {
+            Some(ty) => format!("future<{}>", type_name(&ty)),
+            None => "future".to_string(),
+        },
+        Type::Stream(stream) => match stream.ty() {
+            Some(ty) => format!("stream<{}>", type_name(&ty)),
+            None => "stream".to_string(),
+        },
+        Type::ErrorContext => "error-context".to_string(),
+    }
+}
+
+fn join(items: impl Iterator<Item = String>) -> String {
+    items.collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Instances<'a> = &'a [(&'a str, &'a [(&'a str, &'a str)])];
+
+    fn instances(instances: Instances) -> BTreeMap<String, Functions> {
+        instances
+            .iter()
+            .map(|(name, functions)| {
+                (
+                    name.to_string(),
+                    functions
+                        .iter()
+                        .map(|(function, signature)| (function.to_string(), signature.to_string()))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn interfaces(imports: Instances, exports: Instances) -> ComponentInterfaces {
+        ComponentInterfaces {
+            imports: instances(imports),
+            exports: instances(exports),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let get = ("get", "func(key: string) -> option<list<u8>>");
+        let running = interfaces(
+            &[
+                ("wasi:keyvalue/store@0.2.0-draft", &[get]),
+                ("wasi:logging/logging", &[("log", "func(message: string)")]),
+            ],
+            &[
+                (
+                    "acme:inventory/query@0.1.0",
+                    &[
+                        ("get", "func(id: u32) -> string"),
+                        ("list", "func() -> list<u32>"),
+                    ],
+                ),
+                ("wasi:http/incoming-handler@0.2.0", &[]),
+            ],
+        );
+        assert!(diff(&running, &running).is_empty());
+
+        let replacement = interfaces(
+            &[
+                (
+                    "wasi:keyvalue/store@0.2.0-draft2",
+                    &[get, ("delete", "func(key: string)")],
+                ),
+                ("wasi:blobstore/blobstore", &[]),
+            ],
+            &[
+                (
+                    "acme:inventory/query@0.1.1",
+                    &[("get", "func(id: u64) -> string")],
+                ),
+                ("acme:inventory/admin@0.1.0", &[]),
+            ],
+        );
+        let changes = diff(&running, &replacement);
+        assert_eq!(
+            changes,
+            [
+                WitChange::ExportVersionChanged {
+                    interface: "acme:inventory/query@0.1.0".to_string(),
+                    replacement: "acme:inventory/query@0.1.1".to_string(),
+                },
+                WitChange::FunctionChanged {
+                    interface: "acme:inventory/query@0.1.0".to_string(),
+                    function: "get".to_string(),
+                    signature: "func(id: u32) -> string".to_string(),
+                    replacement: "func(id: u64) -> string".to_string(),
+                },
+                WitChange::FunctionRemoved {
+                    interface: "acme:inventory/query@0.1.0".to_string(),
+                    function: "list".to_string(),
+                },
+                WitChange::ExportRemoved {
+                    interface: "wasi:http/incoming-handler@0.2.0".to_string(),
+                },
+                WitChange::ExportAdded {
+                    interface: "acme:inventory/admin@0.1.0".to_string(),
+                },
+                WitChange::ImportVersionChanged {
+                    interface: "wasi:keyvalue/store@0.2.0-draft".to_string(),
+                    replacement: "wasi:keyvalue/store@0.2.0-draft2".to_string(),
+                },
+                WitChange::ImportFunctionAdded {
+                    interface: "wasi:keyvalue/store@0.2.0-draft".to_string(),
+                    function: "delete".to_string(),
+                },
+                WitChange::ImportRemoved {
+                    interface: "wasi:logging/logging".to_string(),
+                },
+                WitChange::ImportAdded {
+                    interface: "wasi:blobstore/blobstore".to_string(),
+                },
+            ]
+        );
+        let breaking: Vec<bool> = changes.iter().map(WitChange::is_breaking).collect();
+        // A compatible version bump, additions and removed imports don't break anything
+        assert_eq!(
+            breaking,
+            [false, true, true, true, false, true, true, false, true]
+        );
+    }
+
+    #[test]
+    fn test_diff_several_versions() {
+        let running = interfaces(
+            &[],
+            &[
+                ("wasi:http/incoming-handler@0.2.0", &[]),
+                ("wasi:http/incoming-handler@0.3.0", &[]),
+            ],
+        );
+        // Each version is compared with its own counterpart
+        let replacement = interfaces(
+            &[],
+            &[
+                ("wasi:http/incoming-handler@0.2.0", &[]),
+                ("wasi:http/incoming-handler@0.3.1", &[]),
+            ],
+        );
+        assert_eq!(
+            diff(&running, &replacement),
+            [WitChange::ExportVersionChanged {
+                interface: "wasi:http/incoming-handler@0.3.0".to_string(),
+                replacement: "wasi:http/incoming-handler@0.3.1".to_string(),
+            }]
+        );
+
+        // Dropping one of the versions removes it, rather than moving it to the other
+        let replacement = interfaces(&[], &[("wasi:http/incoming-handler@0.3.0", &[])]);
+        assert_eq!(
+            diff(&running, &replacement),
+            [WitChange::ExportRemoved {
+                interface: "wasi:http/incoming-handler@0.2.0".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_signatures() -> anyhow::Result<()> {
+        let engine = wasmtime::Engine::default();
+        let component = wasmtime::component::Component::new(
+            &engine,
+            include_bytes!("../../tests/fixtures/http_counter.wasm"),
+        )?;
+        let interfaces = ComponentInterfaces::of(&component);
+        let (_, handler) = interfaces
+            .exports
+            .iter()
+            .find(|(name, _)| name.starts_with("wasi:http/incoming-handler@"))
+            .expect("the component exports an HTTP handler");
+        assert_eq!(
+            handler["handle"],
+            "func(request: own<resource>, response-out: own<resource>)"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+//! Cron schedules parsed from a [`CronJob::schedule`].
+//!
+//! A schedule has five space-separated fields, matched against UTC time:
+//!
+//! | field        | values                      |
+//! |--------------|-----------------------------|
+//! | minute       | `0-59`                      |
+//! | hour         | `0-23`                      |
+//! | day of month | `1-31`                      |
+//! | month        | `1-12` or `jan`-`dec`       |
+//! | day of week  | `0-7` or `sun`-`sat`, where both `0` and `7` are Sunday |
+//!
+//! Each field is `*`, a value, a range like `1-5`, or a list of those separated by commas,
+//! and each item can have a step like `*/15` or `0-30/10`. When both the day of month and
+//! the day of week are restricted, a day matching either runs the job. The macros
+//! `@yearly` (or `@annually`), `@monthly`, `@weekly`, `@daily` (or `@midnight`) and
+//! `@hourly` stand for the equivalent schedules.
+//!
+//! [`CronJob::schedule`]: crate::types::CronJob::schedule
+
+use std::str::FromStr;
+
+use anyhow::{Context, bail, ensure};
+use chrono::{DateTime, Datelike, Days, NaiveTime, TimeDelta, Timelike, Utc};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// How far ahead a schedule is searched, long enough to find e.g. February 29th.
+const SEARCH_DAYS: u64 = 8 * 366;
+
+/// A parsed cron schedule, see the [module docs](self) for the format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    /// Bit `n` is set when minute `n` matches
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    /// Bit 0 is Sunday
+    days_of_week: u64,
+    /// Whether the day of month field is `*`
+    any_day_of_month: bool,
+    /// Whether the day of week field is `*`
+    any_day_of_week: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(schedule: &str) -> anyhow::Result<Self> {
+        let expanded = match schedule.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            other if other.starts_with('@') => bail!("unknown cron macro '{other}'"),
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            bail!(
+                "invalid cron schedule '{schedule}', expected 5 fields but got {}",
+                fields.len()
+            );
+        };
+
+        let mut days_of_week = parse_field("day of week", day_of_week, 0, 7, &WEEKDAYS)?;
+        // 7 is another name for Sunday
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            minutes: parse_field("minute", minute, 0, 59, &[])?,
+            hours: parse_field("hour", hour, 0, 23, &[])?,
+            days_of_month: parse_field("day of month", day_of_month, 1, 31, &[])?,
+            months: parse_field("month", month, 1, 12, &MONTHS)?,
+            days_of_week,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    /// Returns the first time the schedule matches after `after`, if it matches at all
+    /// within the next few years.
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after
+            .with_second(0)?
+            .with_nanosecond(0)?
+            .checked_add_signed(TimeDelta::minutes(1))?;
+        let mut day = start.date_naive();
+        let mut from = (start.hour(), start.minute());
+        for _ in 0..SEARCH_DAYS {
+            if self.matches_day(day)
+                && let Some((hour, minute)) = self.first_time_from(from)
+            {
+                let time = NaiveTime::from_hms_opt(hour, minute, 0)?;
+                return Some(day.and_time(time).and_utc());
+            }
+            day = day.checked_add_days(Days::new(1))?;
+            from = (0, 0);
+        }
+        None
+    }
+
+    /// Returns the most recent time the schedule matched after `after` and up to `now`.
+    pub fn last_between(&self, after: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut last = None;
+        let mut time = after;
+        while let Some(next) = self.next_after(time)
+            && next <= now
+        {
+            last = Some(next);
+            time = next;
+        }
+        last
+    }
+
+    fn matches_day(&self, day: chrono::NaiveDate) -> bool {
+        if self.months & (1 << day.month()) == 0 {
+            return false;
+        }
+        let day_of_month = self.days_of_month & (1 << day.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << day.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+
+    /// Returns the first matching hour and minute of a day at or after `(hour, minute)`.
+    fn first_time_from(&self, (from_hour, from_minute): (u32, u32)) -> Option<(u32, u32)> {
+        (from_hour..24)
+            .filter(|hour| self.hours & (1 << hour) != 0)
+            .find_map(|hour| {
+                let first_minute = if hour == from_hour { from_minute } else { 0 };
+                (first_minute..60)
+                    .find(|minute| self.minutes & (1 << minute) != 0)
+                    .map(|minute| (hour, minute))
+            })
+    }
+}
+
+/// Parses a field into a bit set of the values it matches.
+fn parse_field(name: &str, field: &str, min: u32, max: u32, names: &[&str]) -> anyhow::Result<u64> {
+    let value = |value: &str| -> anyhow::Result<u32> {
+        let lower = value.to_ascii_lowercase();
+        let value = match names.iter().position(|name| *name == lower) {
+            Some(index) => index as u32 + min,
+            None => value
+                .parse()
+                .with_context(|| format!("invalid {name} '{value}'"))?,
+        };
+        ensure!(
+            (min..=max).contains(&value),
+            "{name} {value} is out of range {min}-{max}"
+        );
+        Ok(value)
+    };
+
+    let mut bits = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .with_context(|| format!("invalid step '{step}' in {name} field"))?;
+                ensure!(step > 0, "step in {name} field must be positive");
+                (range, Some(step))
+            }
+            None => (item, None),
+        };
+        let (start, end) = match (range, range.split_once('-')) {
+            ("*", _) => (min, max),
+            (_, Some((start, end))) => (value(start)?, value(end)?),
+            // A single value with a step runs from the value to the end of the range
+            (_, None) if step.is_some() => (value(range)?, max),
+            (_, None) => (value(range)?, value(range)?),
+        };
+        ensure!(start <= end, "invalid {name} range '{range}'");
+        for value in (start..=end).step_by(step.unwrap_or(1) as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    #[test]
+    fn test_next_after() -> anyhow::Result<()> {
+        let schedule: CronSchedule = "*/15 9-17 * * mon-fri".parse()?;
+        // Saturday afternoon runs next on Monday morning
+        assert_eq!(
+            schedule.next_after(time("2026-10-17T13:05:00Z")),
+            Some(time("2026-10-19T09:00:00Z"))
+        );
+        assert_eq!(
+            schedule.next_after(time("2026-10-19T09:00:00Z")),
+            Some(time("2026-10-19T09:15:00Z"))
+        );
+
+        let leap_day: CronSchedule = "0 12 29 2 *".parse()?;
+        assert_eq!(
+            leap_day.next_after(time("2026-10-16T00:00:00Z")),
+            Some(time("2028-02-29T12:00:00Z"))
+        );
+
+        // Either the 1st of the month or a Sunday
+        let either: CronSchedule = "0 0 1 * 7".parse()?;
+        assert_eq!(
+            either.next_after(time("2026-10-16T00:00:00Z")),
+            Some(time("2026-10-18T00:00:00Z"))
+        );
+        assert_eq!(
+            either.next_after(time("2026-10-26T00:00:00Z")),
+            Some(time("2026-11-01T00:00:00Z"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_last_between() -> anyhow::Result<()> {
+        let schedule: CronSchedule = "@hourly".parse()?;
+        assert_eq!(
+            schedule.last_between(time("2026-10-16T08:30:00Z"), time("2026-10-16T11:59:00Z")),
+            Some(time("2026-10-16T11:00:00Z"))
+        );
+        assert_eq!(
+            schedule.last_between(time("2026-10-16T08:30:00Z"), time("2026-10-16T08:59:00Z")),
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        for schedule in [
+            "* * * *",
+            "60 * * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * foo *",
+            "@often",
+        ] {
+            assert!(
+                schedule.parse::<CronSchedule>().is_err(),
+                "'{schedule}' should be rejected"
+            );
+        }
+    }
+}
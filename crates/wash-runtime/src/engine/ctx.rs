@@ -4,12 +4,33 @@
 //! for wasmtime when executing WebAssembly components. It integrates WASI
 //! interfaces, HTTP capabilities, and plugin access into a unified context.
 
-use std::{any::Any, collections::HashMap, sync::Arc};
+use std::{
+    any::Any,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use http_body_util::BodyExt as _;
+use wasmtime::CallHook;
 use wasmtime::component::ResourceTable;
 use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
 use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
 
+use crate::engine::allowed_hosts::AllowedHosts;
+use crate::engine::mounts::VolumeQuotas;
+use crate::engine::scratch::ScratchDirs;
+use crate::host::baggage::Baggage;
+use crate::host::bandwidth::{ThrottledBody, WorkloadBandwidth};
+use crate::host::cgroups::WorkloadCgroup;
+use crate::host::egress::{EgressLog, HttpEgress};
+use crate::host::proxy::EgressProxy;
+use crate::host::request_id::RequestId;
+use crate::host::saturation;
+use crate::host::slow_invocations::InvocationTimer;
+use crate::host::trace_context::TraceContext;
+use crate::host::usage::{StoreUsage, WorkloadUsage};
 use crate::plugin::HostPlugin;
 
 /// The context for a component store and linker, providing access to implementations of:
@@ -34,12 +55,181 @@ pub struct Ctx {
     plugins: HashMap<&'static str, Arc<dyn Any + Send + Sync>>,
     /// The HTTP handler for outgoing HTTP requests.
     http_handler: Option<Arc<dyn crate::host::http::HostHandler>>,
+    /// The hosts this component may send outgoing HTTP requests to.
+    allowed_hosts: Arc<AllowedHosts>,
+    /// The proxy for outgoing HTTP requests, overriding the HTTP handler's.
+    egress_proxy: Option<Arc<EgressProxy>>,
+    /// The log outgoing HTTP requests are recorded in.
+    egress_log: Arc<EgressLog>,
+    /// The resource usage of the workload this store runs for.
+    usage: Arc<WorkloadUsage>,
+    /// The bandwidth the transfers of the workload this store runs for share.
+    bandwidth: Arc<WorkloadBandwidth>,
+    /// The guest memory and table elements this store has allocated, released from the
+    /// workload's usage when it's dropped.
+    store_usage: Arc<StoreUsage>,
+    /// When the store last started executing guest code, while it's doing so.
+    guest_since: Option<Instant>,
+    /// The time the store has spent executing guest code.
+    cpu_time: Duration,
+    /// The fuel the store has consumed, if the engine meters fuel.
+    fuel_used: Option<u64>,
+    /// The cgroup threads are moved into while executing the workload's guest code.
+    cgroup: Option<Arc<WorkloadCgroup>>,
+    /// The ID of the incoming HTTP request this store handles, if it handles one.
+    request_id: Option<RequestId>,
+    /// The trace context of the incoming HTTP request this store handles, if it has one.
+    trace_context: Option<TraceContext>,
+    /// The baggage of the request or message this store handles, if it has any.
+    baggage: Option<Baggage>,
+    /// When the invocation this store handles started acquiring its instance, until the
+    /// instance is ready.
+    acquiring_since: Option<Instant>,
+    /// Measures the invocation this store handles, if its component logs slow invocations.
+    invocation_timer: Option<InvocationTimer>,
+    /// The directories of the workload's scratch volumes, removed with the store.
+    #[allow(dead_code)]
+    scratch: Option<ScratchDirs>,
+    /// The quotas of the directories the store mounts, if any has one.
+    volume_quotas: Option<VolumeQuotas>,
 }
 
 impl Ctx {
-    /// Get a plugin by its string ID and downcast to the expected type
+    /// Get a plugin by its string ID and downcast to the expected type. Each lookup counts
+    /// as an operation of the plugin in the workload's usage.
     pub fn get_plugin<T: HostPlugin + 'static>(&self, plugin_id: &str) -> Option<Arc<T>> {
-        self.plugins.get(plugin_id)?.clone().downcast().ok()
+        let plugin = self.plugins.get(plugin_id)?.clone().downcast().ok()?;
+        self.usage.record_plugin_op(plugin_id);
+        if let Some(timer) = &self.invocation_timer {
+            timer.record_plugin_call(plugin_id);
+        }
+        Some(plugin)
+    }
+
+    /// Sets the ID of the incoming HTTP request the store handles, which is passed on to
+    /// the outgoing HTTP requests it sends.
+    pub(crate) fn set_request_id(&mut self, request_id: RequestId) {
+        self.request_id = Some(request_id);
+    }
+
+    /// Sets the trace context of the incoming HTTP request the store handles, which is
+    /// passed on to the outgoing HTTP requests it sends.
+    pub(crate) fn set_trace_context(&mut self, trace_context: TraceContext) {
+        self.trace_context = Some(trace_context);
+    }
+
+    /// Sets the baggage of the request or message the store handles, which is passed on
+    /// to the outgoing HTTP requests and messages it sends.
+    pub(crate) fn set_baggage(&mut self, baggage: Baggage) {
+        self.baggage = Some(baggage);
+    }
+
+    /// The baggage of the request or message the store handles.
+    pub(crate) fn baggage(&self) -> Option<&Baggage> {
+        self.baggage.as_ref()
+    }
+
+    /// The bandwidth the workload's transfers share, which plugins moving data for the
+    /// component throttle them by, see [`crate::host::bandwidth`].
+    pub fn bandwidth(&self) -> &Arc<WorkloadBandwidth> {
+        &self.bandwidth
+    }
+
+    /// Records another invocation handled by a store that runs calls concurrently, see
+    /// [`crate::engine::instances::InstanceReuse::Concurrent`].
+    #[cfg(feature = "wasip3")]
+    pub(crate) fn record_invocation(&self) {
+        self.usage.record_invocation();
+    }
+
+    /// Records the component being instantiated in the store, ending the time its
+    /// invocation spent acquiring an instance, see [`crate::host::saturation`].
+    pub(crate) fn instance_ready(&mut self) {
+        if let Some(since) = self.acquiring_since.take() {
+            saturation::record_acquisition(&self.workload_id, since.elapsed());
+        }
+    }
+
+    /// Prepares a store left idle by an earlier invocation to handle another, clearing
+    /// what it knew of the earlier one, see [`crate::engine::instances`].
+    pub(crate) fn reuse(&mut self) {
+        let now = Instant::now();
+        self.usage.record_invocation();
+        self.request_id = None;
+        self.trace_context = None;
+        self.baggage = None;
+        self.acquiring_since = Some(now);
+        if let Some(timer) = &mut self.invocation_timer {
+            timer.restart(now);
+        }
+    }
+
+    /// Describes what the store was invoked for in its slow invocation log, see
+    /// [`crate::host::slow_invocations`].
+    pub(crate) fn describe_invocation(&mut self, describe: impl FnOnce() -> String) {
+        if let Some(timer) = &mut self.invocation_timer {
+            timer.set_invocation(describe());
+        }
+    }
+
+    pub(crate) fn invocation_timer(&self) -> Option<&InvocationTimer> {
+        self.invocation_timer.as_ref()
+    }
+
+    /// Records the error a call into the store failed with, shown with the store's state
+    /// by [`crate::host::HostApi::workload_instances`].
+    pub(crate) fn record_error(&self, error: &anyhow::Error) {
+        self.store_usage
+            .record_error(crate::redact::redact(&format!("{error:#}")).into_owned());
+    }
+
+    /// The time the store has spent executing guest code.
+    pub(crate) fn cpu_time(&self) -> Duration {
+        self.cpu_time
+    }
+
+    /// The fuel the store has consumed, if the engine meters fuel.
+    pub(crate) fn fuel_used(&self) -> Option<u64> {
+        self.fuel_used
+    }
+
+    /// Measures the time the store spends executing guest code, and the fuel it consumes
+    /// from the `fuel` left when it stops, see [`wasmtime::Store::call_hook`].
+    ///
+    /// # Errors
+    /// Returns an error, trapping the guest, if it returns from a host call with a mounted
+    /// directory over its quota, see [`crate::engine::mounts`].
+    pub(crate) fn track_call(&mut self, hook: CallHook, fuel: Option<u64>) -> anyhow::Result<()> {
+        match hook {
+            CallHook::CallingWasm | CallHook::ReturningFromHost => {
+                if matches!(hook, CallHook::CallingWasm) {
+                    self.store_usage.record_call();
+                } else if let Some(quotas) = &mut self.volume_quotas {
+                    quotas.check()?;
+                }
+                self.store_usage.set_busy(true);
+                if let Some(cgroup) = &self.cgroup {
+                    cgroup.enter();
+                }
+                self.guest_since = Some(Instant::now());
+            }
+            CallHook::ReturningFromWasm | CallHook::CallingHost => {
+                self.store_usage.set_busy(false);
+                if let Some(since) = self.guest_since.take() {
+                    let elapsed = since.elapsed();
+                    self.usage.record_cpu_time(elapsed);
+                    self.cpu_time += elapsed;
+                }
+                // Stores are given all the fuel there is when the engine meters it
+                if let Some(remaining) = fuel {
+                    self.fuel_used = Some(u64::MAX - remaining);
+                }
+                if let Some(cgroup) = &self.cgroup {
+                    cgroup.leave();
+                }
+            }
+        }
+        Ok(())
     }
 
     /// Create a new [`CtxBuilder`] to construct a [`Ctx`]
@@ -51,6 +241,38 @@ impl Ctx {
     }
 }
 
+impl wasmtime::ResourceLimiter for Ctx {
+    fn memory_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let grown = desired.saturating_sub(current) as u64;
+        self.usage.memory_grown(&self.store_usage, grown);
+        Ok(true)
+    }
+
+    fn table_growing(
+        &mut self,
+        current: usize,
+        desired: usize,
+        _maximum: Option<usize>,
+    ) -> anyhow::Result<bool> {
+        let grown = desired.saturating_sub(current) as u64;
+        self.usage.table_grown(&self.store_usage, grown);
+        Ok(true)
+    }
+}
+
+impl Drop for Ctx {
+    fn drop(&mut self) {
+        self.usage.store_dropped(&self.id);
+        self.usage.record_consumption(self.cpu_time, self.fuel_used);
+        saturation::instance_dropped(&self.workload_id);
+    }
+}
+
 impl std::fmt::Debug for Ctx {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Ctx")
@@ -89,11 +311,58 @@ impl WasiHttpView for Ctx {
 
     fn send_request(
         &mut self,
-        request: hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
+        mut request: hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
         config: wasmtime_wasi_http::types::OutgoingRequestConfig,
     ) -> wasmtime_wasi_http::HttpResult<wasmtime_wasi_http::types::HostFutureIncomingResponse> {
+        let uri = request.uri();
+        if !self.allowed_hosts.allows_request(
+            uri.scheme_str().unwrap_or("http"),
+            uri.host().unwrap_or_default(),
+            uri.port_u16(),
+        ) {
+            HttpEgress::blocked(
+                &self.egress_log,
+                &self.workload_id,
+                &self.component_id,
+                &request,
+            );
+            return Err(ErrorCode::HttpRequestDenied.into());
+        }
+
+        if let Some(RequestId { header, id }) = &self.request_id
+            && !request.headers().contains_key(header)
+        {
+            request.headers_mut().insert(header.clone(), id.clone());
+        }
+        if let Some(trace_context) = &self.trace_context {
+            trace_context.inject(request.headers_mut());
+        }
+        if let Some(baggage) = &self.baggage {
+            baggage.inject(request.headers_mut());
+        }
+
+        // The HTTP handler picks up the workload's proxy from the request
+        if let Some(proxy) = &self.egress_proxy {
+            request.extensions_mut().insert(proxy.clone());
+        }
+
         match &self.http_handler {
-            Some(handler) => handler.outgoing_request(&self.workload_id, request, config),
+            Some(handler) => {
+                let egress = HttpEgress::start(
+                    self.egress_log.clone(),
+                    self.workload_id.clone(),
+                    self.component_id.clone(),
+                    &mut request,
+                );
+                // Throttled after metering, so the log records when bytes were sent
+                let bandwidth = self.bandwidth.rate().map(|_| self.bandwidth.clone());
+                if let Some(bandwidth) = &bandwidth {
+                    let body = std::mem::take(request.body_mut());
+                    *request.body_mut() = ThrottledBody::new(body, bandwidth.clone()).boxed();
+                }
+                let response = handler.outgoing_request(&self.workload_id, request, config)?;
+                Ok(egress.finish(response, bandwidth))
+            }
             None => Err(wasmtime_wasi_http::HttpError::trap(anyhow::anyhow!(
                 "http client not available"
             ))),
@@ -109,6 +378,17 @@ pub struct CtxBuilder {
     ctx: Option<WasiCtx>,
     plugins: HashMap<&'static str, Arc<dyn HostPlugin + Send + Sync>>,
     http_handler: Option<Arc<dyn crate::host::http::HostHandler>>,
+    allowed_hosts: Arc<AllowedHosts>,
+    egress_proxy: Option<Arc<EgressProxy>>,
+    egress_log: Arc<EgressLog>,
+    usage: Arc<WorkloadUsage>,
+    bandwidth: Arc<WorkloadBandwidth>,
+    cgroup: Option<Arc<WorkloadCgroup>>,
+    acquiring_since: Option<Instant>,
+    slow_invocation_threshold: Option<Duration>,
+    memory_limit_bytes: Option<u64>,
+    scratch: Option<ScratchDirs>,
+    volume_quotas: Option<VolumeQuotas>,
 }
 
 impl CtxBuilder {
@@ -120,6 +400,17 @@ impl CtxBuilder {
             ctx: None,
             http_handler: None,
             plugins: HashMap::new(),
+            allowed_hosts: Arc::default(),
+            egress_proxy: None,
+            egress_log: Arc::default(),
+            usage: Arc::default(),
+            bandwidth: Arc::default(),
+            cgroup: None,
+            acquiring_since: None,
+            slow_invocation_threshold: None,
+            memory_limit_bytes: None,
+            scratch: None,
+            volume_quotas: None,
         }
     }
 
@@ -136,6 +427,77 @@ impl CtxBuilder {
         self
     }
 
+    /// Restricts outgoing HTTP requests to the given hosts. By default all are allowed.
+    pub fn with_allowed_hosts(mut self, allowed_hosts: Arc<AllowedHosts>) -> Self {
+        self.allowed_hosts = allowed_hosts;
+        self
+    }
+
+    /// Sends outgoing HTTP requests through the given proxy instead of the HTTP handler's.
+    pub fn with_egress_proxy(mut self, proxy: Arc<EgressProxy>) -> Self {
+        self.egress_proxy = Some(proxy);
+        self
+    }
+
+    /// Records outgoing HTTP requests in the given log instead of a private one.
+    pub fn with_egress_log(mut self, egress_log: Arc<EgressLog>) -> Self {
+        self.egress_log = egress_log;
+        self
+    }
+
+    /// Reports the store's resource usage to the given workload usage instead of a
+    /// private one.
+    pub fn with_usage(mut self, usage: Arc<WorkloadUsage>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Throttles the store's transfers by the given workload bandwidth instead of leaving
+    /// them unlimited.
+    pub fn with_bandwidth(mut self, bandwidth: Arc<WorkloadBandwidth>) -> Self {
+        self.bandwidth = bandwidth;
+        self
+    }
+
+    /// Executes the store's guest code in the given workload cgroup.
+    pub fn with_cgroup(mut self, cgroup: Arc<WorkloadCgroup>) -> Self {
+        self.cgroup = Some(cgroup);
+        self
+    }
+
+    /// Measures the time until [`Ctx::instance_ready`] from `since`, when the invocation
+    /// the store is created for started acquiring its instance.
+    pub fn with_acquiring_since(mut self, since: Instant) -> Self {
+        self.acquiring_since = Some(since);
+        self
+    }
+
+    /// Logs the store's invocation if it takes longer than `threshold`, see
+    /// [`crate::host::slow_invocations`].
+    pub fn with_slow_invocation_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_invocation_threshold = Some(threshold);
+        self
+    }
+
+    /// Reports `bytes` as the memory limit of the store's component in its usage.
+    pub fn with_memory_limit(mut self, bytes: u64) -> Self {
+        self.memory_limit_bytes = Some(bytes);
+        self
+    }
+
+    /// Keeps the scratch directories preopened in the store's WASI context until the
+    /// store is dropped.
+    pub(crate) fn with_scratch(mut self, scratch: ScratchDirs) -> Self {
+        self.scratch = Some(scratch);
+        self
+    }
+
+    /// Enforces the quotas of the directories preopened in the store's WASI context.
+    pub(crate) fn with_volume_quotas(mut self, quotas: VolumeQuotas) -> Self {
+        self.volume_quotas = Some(quotas);
+        self
+    }
+
     pub fn with_plugins(
         mut self,
         plugins: HashMap<&'static str, Arc<dyn HostPlugin + Send + Sync>>,
@@ -150,6 +512,12 @@ impl CtxBuilder {
             .into_iter()
             .map(|(k, v)| (k, v as Arc<dyn Any + Send + Sync>))
             .collect();
+        // Each invocation runs in a new store, unless its instance is reused
+        self.usage.record_invocation();
+        saturation::instance_created(&self.workload_id);
+        let store_usage =
+            self.usage
+                .store_created(&self.id, &self.component_id, self.memory_limit_bytes);
 
         Ctx {
             id: self.id,
@@ -165,6 +533,25 @@ impl CtxBuilder {
             table: ResourceTable::new(),
             plugins,
             http_handler: self.http_handler,
+            allowed_hosts: self.allowed_hosts,
+            egress_proxy: self.egress_proxy,
+            egress_log: self.egress_log,
+            usage: self.usage,
+            bandwidth: self.bandwidth,
+            store_usage,
+            guest_since: None,
+            cpu_time: Duration::ZERO,
+            fuel_used: None,
+            cgroup: self.cgroup,
+            request_id: None,
+            trace_context: None,
+            baggage: None,
+            acquiring_since: self.acquiring_since,
+            invocation_timer: self.slow_invocation_threshold.map(|threshold| {
+                InvocationTimer::new(threshold, self.acquiring_since.unwrap_or_else(Instant::now))
+            }),
+            scratch: self.scratch,
+            volume_quotas: self.volume_quotas,
         }
     }
 }
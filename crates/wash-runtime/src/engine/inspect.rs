@@ -0,0 +1,378 @@
+//! Helpers for inspecting a compiled [`Component`] without instantiating it.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use aws_lc_rs::digest;
+use tracing::debug;
+use wasmtime::component::{Component, types::ComponentItem};
+
+use crate::types::{ComponentInventory, ComponentSource};
+use crate::wit::{WitInterface, WitWorld};
+
+/// Custom sections written by `wasm-tools metadata add` / `wasm-metadata` that hold
+/// plain UTF-8 values.
+const METADATA_SECTIONS: &[&str] = &[
+    "authors",
+    "description",
+    "licenses",
+    "source",
+    "homepage",
+    "revision",
+    "version",
+];
+
+/// The custom section listing the languages and tools that produced a binary.
+const PRODUCERS_SECTION: &str = "producers";
+/// The custom section older `wasm-tools metadata add` writes JSON registry metadata to.
+const REGISTRY_METADATA_SECTION: &str = "registry-metadata";
+/// How deeply nested components and modules are searched for `producers` sections.
+const MAX_NESTING: usize = 8;
+
+/// Size of a WebAssembly page in bytes.
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
+
+/// Builds the [`WitWorld`] of a component from its instance imports and exports,
+/// merging interfaces when `namespace:package@version` matches.
+pub fn component_world(component: &Component) -> WitWorld {
+    let ty = component.component_type();
+    let merge_into = |map: &mut HashMap<String, WitInterface>, name: &str, item: ComponentItem| {
+        if let ComponentItem::ComponentInstance(_) = item {
+            let interface = WitInterface::from(name);
+            map.entry(interface.instance())
+                .and_modify(|existing| {
+                    existing.merge(&interface);
+                })
+                .or_insert(interface);
+        } else {
+            debug!(name, "item is not a component instance, skipping");
+        }
+    };
+
+    let mut imports = HashMap::new();
+    for (import_name, import_item) in ty.imports(component.engine()) {
+        merge_into(&mut imports, import_name, import_item);
+    }
+    let mut exports = HashMap::new();
+    for (export_name, export_item) in ty.exports(component.engine()) {
+        merge_into(&mut exports, export_name, export_item);
+    }
+
+    WitWorld {
+        imports: imports.into_values().collect::<HashSet<_>>(),
+        exports: exports.into_values().collect::<HashSet<_>>(),
+    }
+}
+
+/// Returns the sorted, fully qualified names of the instances a component imports
+/// and exports, as a tuple of (imports, exports).
+pub(crate) fn instance_names(component: &Component) -> (Vec<String>, Vec<String>) {
+    let ty = component.component_type();
+    (
+        sorted_instance_names(ty.imports(component.engine())),
+        sorted_instance_names(ty.exports(component.engine())),
+    )
+}
+
+fn sorted_instance_names<'a>(items: impl Iterator<Item = (&'a str, ComponentItem)>) -> Vec<String> {
+    let mut names: Vec<String> = items
+        .filter(|(_, item)| matches!(item, ComponentItem::ComponentInstance(_)))
+        .map(|(name, _)| name.to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+/// Estimates the memory needed to instantiate a component once: the initial size of
+/// each linear memory plus the compiled code image.
+pub(crate) fn estimated_memory_bytes(component: &Component) -> u64 {
+    let image = component.image_range();
+    let image_bytes = (image.end as usize).saturating_sub(image.start as usize) as u64;
+
+    let memory_bytes = component
+        .resources_required()
+        .map(|resources| {
+            u64::from(resources.num_memories)
+                .saturating_mul(resources.max_initial_memory_size.unwrap_or_default())
+                .saturating_mul(WASM_PAGE_SIZE)
+        })
+        .unwrap_or_default();
+
+    image_bytes.saturating_add(memory_bytes)
+}
+
+/// Returns the initial size of a component's largest linear memory, which its instances
+/// need however little they use.
+pub(crate) fn min_memory_bytes(component: &Component) -> u64 {
+    component
+        .resources_required()
+        .and_then(|resources| resources.max_initial_memory_size)
+        .unwrap_or_default()
+        .saturating_mul(WASM_PAGE_SIZE)
+}
+
+/// Reads the metadata embedded in a component binary's top-level custom sections,
+/// e.g. `version` or `authors`. Malformed binaries yield whatever was read before
+/// the first malformed section.
+pub(crate) fn embedded_metadata(bytes: &[u8]) -> HashMap<String, String> {
+    let mut metadata = HashMap::new();
+    visit_custom_sections(bytes, 0, &mut |depth, name, value| {
+        if depth == 0
+            && METADATA_SECTIONS.contains(&name)
+            && let Ok(value) = std::str::from_utf8(value)
+        {
+            metadata.insert(name.to_string(), value.to_string());
+        }
+    });
+    metadata
+}
+
+/// Reads the `producers` sections of a component and the components and modules nested
+/// in it, merging the `(name, version)` pairs listed under each field.
+pub(crate) fn producers(bytes: &[u8]) -> BTreeMap<String, Vec<(String, String)>> {
+    let mut producers: BTreeMap<String, BTreeSet<(String, String)>> = BTreeMap::new();
+    visit_custom_sections(bytes, 0, &mut |_, name, section| {
+        if name == PRODUCERS_SECTION {
+            for (field, values) in parse_producers(section).unwrap_or_default() {
+                producers.entry(field).or_default().extend(values);
+            }
+        }
+    });
+    producers
+        .into_iter()
+        .map(|(field, values)| (field, values.into_iter().collect()))
+        .collect()
+}
+
+/// Reads the JSON `registry-metadata` section of a component, if it has a valid one.
+pub(crate) fn registry_metadata(bytes: &[u8]) -> Option<serde_json::Value> {
+    let mut metadata = None;
+    visit_custom_sections(bytes, 0, &mut |depth, name, section| {
+        if depth == 0 && name == REGISTRY_METADATA_SECTION {
+            metadata = serde_json::from_slice(section).ok();
+        }
+    });
+    metadata
+}
+
+/// Records what a component is and where it came from, for the host's inventory.
+pub(crate) fn component_inventory(
+    bytes: &[u8],
+    source: Option<ComponentSource>,
+) -> ComponentInventory {
+    let sha256 = digest::digest(&digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    ComponentInventory {
+        sha256,
+        source,
+        metadata: embedded_metadata(bytes),
+        producers: producers(bytes),
+        registry_metadata: registry_metadata(bytes),
+        claims: crate::engine::claims::embedded_claims(bytes),
+    }
+}
+
+/// Calls `visit` with the nesting depth, name and contents of each custom section of a
+/// component or module, descending into the components and modules a component nests.
+/// Malformed binaries are visited up to the first malformed section.
+fn visit_custom_sections(bytes: &[u8], depth: usize, visit: &mut impl FnMut(usize, &str, &[u8])) {
+    // Components have layer 1 in the preamble, core modules layer 0
+    let is_component = bytes.get(6..8) == Some(&[0x01, 0x00]);
+    // Skip the preamble: 4 bytes of magic followed by a 4 byte version and layer
+    let mut pos = 8;
+    while let Some(&section_id) = bytes.get(pos) {
+        pos += 1;
+        let Some((size, read)) = bytes.get(pos..).and_then(read_leb128) else {
+            break;
+        };
+        pos += read;
+        let Some(section) = pos.checked_add(size).and_then(|end| bytes.get(pos..end)) else {
+            break;
+        };
+        pos += size;
+
+        match section_id {
+            // Custom sections have ID 0 and start with their name
+            0 => {
+                if let Some((name, value)) = read_name(section) {
+                    visit(depth, name, value);
+                }
+            }
+            // Nested core modules and components
+            1 | 4 if is_component && depth < MAX_NESTING => {
+                visit_custom_sections(section, depth + 1, visit);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The fields of a `producers` section, each with its `(name, version)` pairs.
+type ProducerFields = Vec<(String, Vec<(String, String)>)>;
+
+/// Parses a `producers` section into its fields and their `(name, version)` pairs.
+fn parse_producers(section: &[u8]) -> Option<ProducerFields> {
+    let (field_count, read) = read_leb128(section)?;
+    let mut rest = &section[read..];
+    let mut fields = Vec::new();
+    for _ in 0..field_count {
+        let (field, after) = read_name(rest)?;
+        let (value_count, read) = read_leb128(after)?;
+        rest = &after[read..];
+        let mut values = Vec::new();
+        for _ in 0..value_count {
+            let (name, after) = read_name(rest)?;
+            let (version, after) = read_name(after)?;
+            values.push((name.to_string(), version.to_string()));
+            rest = after;
+        }
+        fields.push((field.to_string(), values));
+    }
+    Some(fields)
+}
+
+/// Reads a length-prefixed UTF-8 name, returning it and the bytes after it.
+fn read_name(bytes: &[u8]) -> Option<(&str, &[u8])> {
+    let (len, read) = read_leb128(bytes)?;
+    let name = bytes.get(read..read.checked_add(len)?)?;
+    Some((std::str::from_utf8(name).ok()?, &bytes[read + len..]))
+}
+
+/// Reads an unsigned LEB128 `u32`, returning the value and the number of bytes read.
+pub(crate) fn read_leb128(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut value: u32 = 0;
+    for (i, byte) in bytes.iter().take(5).enumerate() {
+        value |= u32::from(byte & 0x7f) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value as usize, i + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom_section(name: &str, value: &str) -> Vec<u8> {
+        let mut payload = vec![name.len() as u8];
+        payload.extend_from_slice(name.as_bytes());
+        payload.extend_from_slice(value.as_bytes());
+
+        let mut section = vec![0, payload.len() as u8];
+        section.extend(payload);
+        section
+    }
+
+    #[test]
+    fn test_embedded_metadata() {
+        // Component preamble
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+        bytes.extend(custom_section("version", "1.2.3"));
+        bytes.extend(custom_section("unrelated", "ignored"));
+        bytes.extend(custom_section("authors", "wasmCloud"));
+
+        let metadata = embedded_metadata(&bytes);
+        assert_eq!(metadata.len(), 2);
+        assert_eq!(metadata.get("version").map(String::as_str), Some("1.2.3"));
+        assert_eq!(
+            metadata.get("authors").map(String::as_str),
+            Some("wasmCloud")
+        );
+    }
+
+    #[test]
+    fn test_embedded_metadata_truncated() {
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+        bytes.extend(custom_section("version", "1.2.3"));
+        // A section claiming more bytes than remain
+        bytes.extend([0, 0x7f, 0x01]);
+
+        let metadata = embedded_metadata(&bytes);
+        assert_eq!(metadata.get("version").map(String::as_str), Some("1.2.3"));
+    }
+
+    /// A length-prefixed name, as written in `producers` sections.
+    fn name(value: &str) -> Vec<u8> {
+        let mut bytes = vec![value.len() as u8];
+        bytes.extend_from_slice(value.as_bytes());
+        bytes
+    }
+
+    fn producers_section(fields: &[(&str, &[(&str, &str)])]) -> Vec<u8> {
+        let mut payload = name("producers");
+        payload.push(fields.len() as u8);
+        for (field, values) in fields {
+            payload.extend(name(field));
+            payload.push(values.len() as u8);
+            for (value, version) in *values {
+                payload.extend(name(value));
+                payload.extend(name(version));
+            }
+        }
+        let mut section = vec![0, payload.len() as u8];
+        section.extend(payload);
+        section
+    }
+
+    #[test]
+    fn test_producers() {
+        // A core module nested in a component, each with its own producers
+        let mut module = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        module.extend(producers_section(&[
+            ("language", &[("Rust", "")]),
+            ("processed-by", &[("rustc", "1.90.0")]),
+        ]));
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+        bytes.extend([1, module.len() as u8]);
+        bytes.extend(module);
+        bytes.extend(producers_section(&[(
+            "processed-by",
+            &[("wit-component", "0.240.0")],
+        )]));
+
+        let producers = producers(&bytes);
+        assert_eq!(
+            producers.get("language"),
+            Some(&vec![("Rust".to_string(), String::new())])
+        );
+        assert_eq!(
+            producers.get("processed-by"),
+            Some(&vec![
+                ("rustc".to_string(), "1.90.0".to_string()),
+                ("wit-component".to_string(), "0.240.0".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_component_inventory() {
+        let mut bytes = vec![0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+        bytes.extend(custom_section("version", "1.2.3"));
+        bytes.extend(custom_section(
+            "registry-metadata",
+            r#"{"authors":["wasmCloud"]}"#,
+        ));
+
+        let inventory = component_inventory(&bytes, None);
+        assert_eq!(inventory.sha256.len(), 64);
+        assert_eq!(
+            inventory.metadata.get("version").map(String::as_str),
+            Some("1.2.3")
+        );
+        assert_eq!(
+            inventory.registry_metadata,
+            Some(serde_json::json!({ "authors": ["wasmCloud"] }))
+        );
+        assert!(inventory.producers.is_empty());
+    }
+
+    #[test]
+    fn test_read_leb128() {
+        assert_eq!(read_leb128(&[0x05]), Some((5, 1)));
+        assert_eq!(read_leb128(&[0xe5, 0x8e, 0x26]), Some((624_485, 3)));
+        assert_eq!(read_leb128(&[0x80]), None);
+    }
+}
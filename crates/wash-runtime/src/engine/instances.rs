@@ -0,0 +1,441 @@
+//! Instance reuse, letting components trade the isolation of their invocations for not
+//! instantiating a component for each of them.
+//!
+//! By default every invocation is handled by a fresh instance in a store of its own,
+//! dropped once the invocation returns, so no guest state outlives it. A component picks
+//! its strategy under [`INSTANCE_REUSE_CONFIG`] in its [`LocalResources::config`]:
+//!
+//! | value        | strategy                                                          |
+//! |--------------|-------------------------------------------------------------------|
+//! | `fresh`      | a new instance for each invocation, the default                   |
+//! | `reuse`      | HTTP requests are handled by instances earlier requests left idle |
+//! | `concurrent` | calls share one instance, which runs them concurrently            |
+//!
+//! A reused instance keeps its guest state, e.g. globals, caches and the files in its
+//! scratch volumes, so a request sees what earlier requests left behind: only reuse
+//! instances of components that keep nothing of one request another mustn't see. An
+//! instance is left idle once the component's handler returns, unless it trapped or the
+//! component already has its `pool_size` of idle instances, at least one. Idle instances
+//! are dropped with the workload, or once the component is switched back to `fresh`.
+//!
+//! Only HTTP requests reuse instances; messages and calls through
+//! [`crate::engine::workload::ResolvedWorkload::call_export`] get fresh ones. A
+//! reused instance keeps the resources it was created with, so updating the component's
+//! [`LocalResources`] drops its idle instances, and those handling a request when it's
+//! updated aren't left idle afterwards. The guest time and fuel in a reused instance's
+//! slow invocation logs are the instance's totals.
+//!
+//! `concurrent` needs the `wasip3` feature and is meant for WASI 0.3 components whose
+//! exports are lifted with the component model's async ABI: calls through `call_export`
+//! are sent to one long-lived instance of the component, which runs them concurrently
+//! instead of taking an instance per in-flight call, so calls that spend their time
+//! waiting, e.g. on streams, share the instance's memory. The instance is created by the
+//! first call and lives as long as the workload, unless it traps, which fails the calls
+//! in flight and lets the next call create another, or the component's
+//! [`LocalResources`] are updated. Calls to exports lifted synchronously are still run
+//! one at a time by the instance. HTTP requests and messages get instances as before.
+//!
+//! Resetting an instance to a snapshot between invocations isn't offered, as wasmtime
+//! can't snapshot instances. A fresh instance starts from the component's initial memory
+//! image, mapped copy-on-write when the engine uses the pooling allocator, which is what
+//! such a reset would restore, see
+//! [`crate::engine::EngineBuilder::with_memory_keep_resident`].
+
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+#[cfg(feature = "wasip3")]
+use anyhow::Context as _;
+use anyhow::bail;
+#[cfg(feature = "wasip3")]
+use futures::StreamExt as _;
+#[cfg(feature = "wasip3")]
+use tracing::warn;
+use wasmtime::Store;
+use wasmtime::component::Instance;
+#[cfg(feature = "wasip3")]
+use wasmtime::component::{Accessor, Val};
+
+use crate::engine::ctx::Ctx;
+use crate::types::LocalResources;
+
+/// The key in a component's [`LocalResources::config`] setting whether its instances are
+/// reused, `fresh` or `reuse`.
+pub const INSTANCE_REUSE_CONFIG: &str = "instance-reuse";
+
+/// Whether a component's instances handle more than one invocation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InstanceReuse {
+    /// A new instance for each invocation.
+    #[default]
+    Fresh,
+    /// Idle instances handle later HTTP requests.
+    Reuse,
+    /// Calls share one instance, which runs them concurrently.
+    #[cfg(feature = "wasip3")]
+    Concurrent,
+}
+
+/// Reads a component's instance reuse strategy.
+///
+/// # Returns
+/// The strategy, [`InstanceReuse::Fresh`] if the component doesn't set one.
+///
+/// # Errors
+/// Returns an error if the strategy isn't `fresh`, `reuse` or, with the `wasip3` feature,
+/// `concurrent`.
+pub(crate) fn strategy(resources: &LocalResources) -> anyhow::Result<InstanceReuse> {
+    match resources
+        .config
+        .get(INSTANCE_REUSE_CONFIG)
+        .map(|s| s.trim())
+    {
+        None | Some("fresh") => Ok(InstanceReuse::Fresh),
+        Some("reuse") => Ok(InstanceReuse::Reuse),
+        #[cfg(feature = "wasip3")]
+        Some("concurrent") => Ok(InstanceReuse::Concurrent),
+        #[cfg(not(feature = "wasip3"))]
+        Some("concurrent") => {
+            bail!("{INSTANCE_REUSE_CONFIG} 'concurrent' needs a host built with the wasip3 feature")
+        }
+        Some(other) => bail!(
+            "invalid {INSTANCE_REUSE_CONFIG} '{other}', expected 'fresh', 'reuse' or \
+             'concurrent'; instances can't be reset from a snapshot, fresh ones already start \
+             from the component's initial state"
+        ),
+    }
+}
+
+/// The instances of a workload's components left idle by earlier invocations.
+#[derive(Default)]
+pub(crate) struct IdleInstances {
+    /// Idle instances by component ID
+    idle: Mutex<HashMap<String, IdlePool>>,
+}
+
+/// The idle instances of a component.
+#[derive(Default)]
+struct IdlePool {
+    /// Bumped each time the pool is cleared, so instances taken before aren't put back
+    generation: u64,
+    /// Stores and their instances, the most recently used last
+    instances: Vec<(Store<Ctx>, Instance)>,
+}
+
+impl IdleInstances {
+    /// Returns the generation of a component's pool, to pass to [`Self::put`] for an
+    /// instance taken or created after reading it.
+    pub(crate) fn generation(&self, component_id: &str) -> u64 {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(component_id)
+            .map_or(0, |pool| pool.generation)
+    }
+
+    /// Takes the most recently used idle instance of a component.
+    pub(crate) fn take(&self, component_id: &str) -> Option<(Store<Ctx>, Instance)> {
+        self.idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(component_id)?
+            .instances
+            .pop()
+    }
+
+    /// Leaves an instance idle, unless the component already has `max_idle` idle ones or
+    /// its pool was cleared since `generation` was read.
+    pub(crate) fn put(
+        &self,
+        component_id: &str,
+        generation: u64,
+        store: Store<Ctx>,
+        instance: Instance,
+        max_idle: usize,
+    ) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let pool = idle.entry(component_id.to_string()).or_default();
+        if pool.generation == generation && pool.instances.len() < max_idle {
+            pool.instances.push((store, instance));
+        }
+    }
+
+    /// Drops the idle instances of a component, and keeps those in use from being left
+    /// idle once they return.
+    pub(crate) fn clear(&self, component_id: &str) {
+        let removed = {
+            let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+            let pool = idle.entry(component_id.to_string()).or_default();
+            pool.generation += 1;
+            std::mem::take(&mut pool.instances)
+        };
+        // Stores are dropped outside the lock
+        drop(removed);
+    }
+}
+
+/// The instances of a workload's components that run calls concurrently, see
+/// [`InstanceReuse::Concurrent`].
+#[cfg(feature = "wasip3")]
+#[derive(Default)]
+pub(crate) struct SharedInstances {
+    /// Shared instances by component ID, locked while one is created
+    shared: tokio::sync::Mutex<HashMap<String, SharedInstance>>,
+}
+
+#[cfg(feature = "wasip3")]
+impl SharedInstances {
+    /// Returns the shared instance of a component, creating it with `start` unless the
+    /// component has one that is still running.
+    ///
+    /// # Errors
+    /// Returns the error `start` failed with.
+    pub(crate) async fn get_or_start(
+        &self,
+        component_id: &str,
+        start: impl Future<Output = anyhow::Result<SharedInstance>>,
+    ) -> anyhow::Result<SharedInstance> {
+        let mut shared = self.shared.lock().await;
+        if let Some(instance) = shared.get(component_id)
+            && !instance.calls.is_closed()
+        {
+            return Ok(instance.clone());
+        }
+        let instance = start.await?;
+        shared.insert(component_id.to_string(), instance.clone());
+        Ok(instance)
+    }
+
+    /// Drops the shared instance of a component. Calls already sent to it still finish.
+    pub(crate) async fn clear(&self, component_id: &str) {
+        self.shared.lock().await.remove(component_id);
+    }
+}
+
+#[cfg(feature = "wasip3")]
+impl std::fmt::Debug for SharedInstances {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedInstances").finish_non_exhaustive()
+    }
+}
+
+/// An instance that runs the calls sent to it concurrently, on a task owning its store.
+///
+/// The task finishes the calls in flight and drops the store once every handle to the
+/// instance is dropped, or as soon as a call fails, e.g. because it trapped.
+#[cfg(feature = "wasip3")]
+#[derive(Clone)]
+pub(crate) struct SharedInstance {
+    calls: tokio::sync::mpsc::UnboundedSender<SharedCall>,
+}
+
+/// A call sent to a [`SharedInstance`].
+#[cfg(feature = "wasip3")]
+struct SharedCall {
+    /// The exported interface the function is in
+    export: String,
+    function: String,
+    params: Vec<Val>,
+    results: Vec<Val>,
+    /// Where the results are sent once the call returns
+    reply: SharedReply,
+}
+
+#[cfg(feature = "wasip3")]
+type SharedReply = tokio::sync::oneshot::Sender<anyhow::Result<Vec<Val>>>;
+
+#[cfg(feature = "wasip3")]
+impl SharedInstance {
+    /// Creates a shared instance from an instance and its store.
+    ///
+    /// # Returns
+    /// The instance, and the task running its calls, which the caller spawns.
+    pub(crate) fn new(
+        mut store: Store<Ctx>,
+        instance: Instance,
+    ) -> (Self, impl Future<Output = ()> + Send + 'static) {
+        let (calls, mut pending) = tokio::sync::mpsc::unbounded_channel::<SharedCall>();
+        let run = async move {
+            let ran = instance
+                .run_concurrent(&mut store, async |accessor| {
+                    let mut in_flight = futures::stream::FuturesUnordered::new();
+                    loop {
+                        tokio::select! {
+                            call = pending.recv() => match call {
+                                Some(call) => in_flight.push(call.run(accessor, instance)),
+                                None => break,
+                            },
+                            Some((reply, results, failed)) = in_flight.next() => {
+                                // A failed call may leave the instance unusable, so it's
+                                // closed to calls before the caller learns of the failure
+                                if failed {
+                                    pending.close();
+                                }
+                                let _ = reply.send(results);
+                                if failed {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                    pending.close();
+                    while let Some((reply, results, _)) = in_flight.next().await {
+                        let _ = reply.send(results);
+                    }
+                })
+                .await;
+            if let Err(e) = ran {
+                warn!(err = ?e, "shared instance stopped");
+                store.data().record_error(&e);
+            }
+        };
+        (Self { calls }, run)
+    }
+
+    /// Calls `function` in the exported interface `export`, concurrently with the other
+    /// calls into the instance.
+    ///
+    /// # Returns
+    /// The results of the call, as many as in `results`.
+    ///
+    /// # Errors
+    /// Returns an error if the function isn't exported, the call fails, or the instance
+    /// stopped before the call returned.
+    pub(crate) async fn call(
+        &self,
+        export: &str,
+        function: &str,
+        params: Vec<Val>,
+        results: Vec<Val>,
+    ) -> anyhow::Result<Vec<Val>> {
+        let (reply, returned) = tokio::sync::oneshot::channel();
+        self.calls
+            .send(SharedCall {
+                export: export.to_string(),
+                function: function.to_string(),
+                params,
+                results,
+                reply,
+            })
+            .ok()
+            .context("the shared instance has stopped")?;
+        returned
+            .await
+            .context("the shared instance stopped before the call returned")?
+    }
+}
+
+#[cfg(feature = "wasip3")]
+impl SharedCall {
+    /// Runs the call in the instance's event loop.
+    ///
+    /// # Returns
+    /// Where to send the call's results, the results, and whether the call failed once it
+    /// was made.
+    async fn run(
+        self,
+        accessor: &Accessor<Ctx>,
+        instance: Instance,
+    ) -> (SharedReply, anyhow::Result<Vec<Val>>, bool) {
+        let Self {
+            export,
+            function,
+            params,
+            mut results,
+            reply,
+        } = self;
+        let func = accessor.with(|mut store| {
+            let store = &mut store;
+            instance
+                .get_export_index(&mut *store, None, &export)
+                .and_then(|idx| instance.get_export_index(&mut *store, Some(&idx), &function))
+                .and_then(|idx| instance.get_func(&mut *store, idx))
+        });
+        let Some(func) = func else {
+            let e = anyhow::anyhow!("function '{function}' not found in '{export}'");
+            return (reply, Err(e), false);
+        };
+        accessor.with(|mut store| store.get().record_invocation());
+        match func.call_concurrent(accessor, &params, &mut results).await {
+            Ok(_) => (reply, Ok(results), false),
+            Err(e) => {
+                accessor.with(|mut store| store.get().record_error(&e));
+                (reply, Err(e), true)
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for IdleInstances {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_map()
+            .entries(idle.iter().map(|(id, pool)| (id, pool.instances.len())))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strategy() -> anyhow::Result<()> {
+        let mut resources = LocalResources::default();
+        assert_eq!(strategy(&resources)?, InstanceReuse::Fresh);
+
+        resources
+            .config
+            .insert(INSTANCE_REUSE_CONFIG.to_string(), "reuse".to_string());
+        assert_eq!(strategy(&resources)?, InstanceReuse::Reuse);
+
+        resources
+            .config
+            .insert(INSTANCE_REUSE_CONFIG.to_string(), "snapshot".to_string());
+        assert!(strategy(&resources).is_err());
+
+        resources
+            .config
+            .insert(INSTANCE_REUSE_CONFIG.to_string(), "concurrent".to_string());
+        #[cfg(feature = "wasip3")]
+        assert_eq!(strategy(&resources)?, InstanceReuse::Concurrent);
+        #[cfg(not(feature = "wasip3"))]
+        assert!(strategy(&resources).is_err());
+        Ok(())
+    }
+
+    fn empty_instance(engine: &wasmtime::Engine) -> anyhow::Result<(Store<Ctx>, Instance)> {
+        let component = wasmtime::component::Component::new(engine, b"\0asm\x0d\x00\x01\x00")?;
+        let linker = wasmtime::component::Linker::<Ctx>::new(engine);
+        let mut store = Store::new(engine, Ctx::builder("w", "c").build());
+        let instance = linker.instantiate(&mut store, &component)?;
+        Ok((store, instance))
+    }
+
+    #[test]
+    fn test_idle_instances() -> anyhow::Result<()> {
+        let engine = wasmtime::Engine::default();
+        let idle = IdleInstances::default();
+        assert!(idle.take("c").is_none());
+
+        let generation = idle.generation("c");
+        let (store, instance) = empty_instance(&engine)?;
+        idle.put("c", generation, store, instance, 1);
+        let (store, instance) = empty_instance(&engine)?;
+        idle.put("c", generation, store, instance, 1);
+        let (store, instance) = idle.take("c").expect("an idle instance");
+        assert!(idle.take("c").is_none(), "the pool holds at most max_idle");
+
+        // An instance in use when the pool is cleared isn't left idle afterwards
+        idle.clear("c");
+        idle.put("c", generation, store, instance, 1);
+        assert!(idle.take("c").is_none());
+
+        let generation = idle.generation("c");
+        let (store, instance) = empty_instance(&engine)?;
+        idle.put("c", generation, store, instance, 1);
+        idle.clear("c");
+        assert!(idle.take("c").is_none());
+        Ok(())
+    }
+}
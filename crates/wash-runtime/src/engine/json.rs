@@ -0,0 +1,374 @@
+//! Converts component model values to and from JSON, for callers outside the host that
+//! invoke workload exports, such as the [gRPC ingress](crate::host::grpc).
+//!
+//! The mapping follows the shape of the WIT type:
+//!
+//! | WIT type                  | JSON                                           |
+//! |---------------------------|------------------------------------------------|
+//! | `bool`                    | boolean                                        |
+//! | integers, floats          | number                                         |
+//! | `char`, `string`          | string                                         |
+//! | `list<T>`, `tuple<..>`    | array                                          |
+//! | `record`                  | object keyed by field name                     |
+//! | `enum`                    | string naming the case                         |
+//! | `variant`                 | case name, or `{"<case>": payload}`            |
+//! | `option<T>`               | `null` or the value                            |
+//! | `result<T, E>`            | `{"ok": value}` or `{"err": value}`            |
+//! | `flags`                   | array of the names of the set flags            |
+//!
+//! Resources can't cross the host boundary and are rejected.
+
+use anyhow::{Context as _, bail};
+use serde_json::{Map, Value};
+use wasmtime::component::{Type, Val};
+
+/// Converts a JSON value into a [`Val`] of the given type.
+///
+/// # Errors
+/// Returns an error if the JSON doesn't have the shape of the type, a number is out of
+/// range, or the type contains a resource.
+pub fn val_from_json(ty: &Type, value: &Value) -> anyhow::Result<Val> {
+    Ok(match ty {
+        Type::Bool => Val::Bool(value.as_bool().context("expected a boolean")?),
+        Type::S8 => Val::S8(int(value)?),
+        Type::U8 => Val::U8(uint(value)?),
+        Type::S16 => Val::S16(int(value)?),
+        Type::U16 => Val::U16(uint(value)?),
+        Type::S32 => Val::S32(int(value)?),
+        Type::U32 => Val::U32(uint(value)?),
+        Type::S64 => Val::S64(int(value)?),
+        Type::U64 => Val::U64(uint(value)?),
+        Type::Float32 => Val::Float32(value.as_f64().context("expected a number")? as f32),
+        Type::Float64 => Val::Float64(value.as_f64().context("expected a number")?),
+        Type::Char => {
+            let s = value
+                .as_str()
+                .context("expected a single character string")?;
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Val::Char(c),
+                _ => bail!("expected a single character, got '{s}'"),
+            }
+        }
+        Type::String => Val::String(value.as_str().context("expected a string")?.to_string()),
+        Type::List(list) => {
+            let element = list.ty();
+            Val::List(
+                array(value)?
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| {
+                        val_from_json(&element, v).with_context(|| format!("at index {i}"))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        Type::Tuple(tuple) => {
+            let values = array(value)?;
+            if values.len() != tuple.types().len() {
+                bail!(
+                    "expected a tuple of {} elements, got {}",
+                    tuple.types().len(),
+                    values.len()
+                );
+            }
+            Val::Tuple(
+                tuple
+                    .types()
+                    .zip(values)
+                    .enumerate()
+                    .map(|(i, (ty, v))| {
+                        val_from_json(&ty, v).with_context(|| format!("at index {i}"))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        Type::Record(record) => {
+            let object = value.as_object().context("expected an object")?;
+            if let Some(unknown) = object
+                .keys()
+                .find(|key| !record.fields().any(|field| field.name == key.as_str()))
+            {
+                bail!("unknown field '{unknown}'");
+            }
+            Val::Record(
+                record
+                    .fields()
+                    .map(|field| {
+                        let v = object.get(field.name).unwrap_or(&Value::Null);
+                        let val = val_from_json(&field.ty, v)
+                            .with_context(|| format!("in field '{}'", field.name))?;
+                        Ok((field.name.to_string(), val))
+                    })
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        Type::Variant(variant) => {
+            let (name, payload) = case(value)?;
+            let Some(case) = variant.cases().find(|c| c.name == name) else {
+                bail!("unknown variant case '{name}'");
+            };
+            let payload = match (case.ty, payload) {
+                (Some(ty), Some(v)) => Some(Box::new(
+                    val_from_json(&ty, v).with_context(|| format!("in case '{name}'"))?,
+                )),
+                (Some(_), None) => bail!("case '{name}' requires a payload"),
+                (None, None | Some(Value::Null)) => None,
+                (None, Some(_)) => bail!("case '{name}' has no payload"),
+            };
+            Val::Variant(name.to_string(), payload)
+        }
+        Type::Enum(enum_) => {
+            let name = value.as_str().context("expected an enum case name")?;
+            if !enum_.names().any(|n| n == name) {
+                bail!("unknown enum case '{name}'");
+            }
+            Val::Enum(name.to_string())
+        }
+        Type::Option(option) => match value {
+            Value::Null => Val::Option(None),
+            v => Val::Option(Some(Box::new(val_from_json(&option.ty(), v)?))),
+        },
+        Type::Result(result) => {
+            let (name, payload) = case(value)?;
+            let lift = |ty: Option<Type>| -> anyhow::Result<Option<Box<Val>>> {
+                match (ty, payload) {
+                    (Some(ty), Some(v)) => Ok(Some(Box::new(
+                        val_from_json(&ty, v).with_context(|| format!("in '{name}'"))?,
+                    ))),
+                    (Some(_), None) => bail!("'{name}' requires a payload"),
+                    (None, _) => Ok(None),
+                }
+            };
+            match name {
+                "ok" => Val::Result(Ok(lift(result.ok())?)),
+                "err" => Val::Result(Err(lift(result.err())?)),
+                _ => bail!("expected 'ok' or 'err', got '{name}'"),
+            }
+        }
+        Type::Flags(flags) => Val::Flags(
+            array(value)?
+                .iter()
+                .map(|v| {
+                    let name = v.as_str().context("expected a flag name")?;
+                    if !flags.names().any(|n| n == name) {
+                        bail!("unknown flag '{name}'");
+                    }
+                    Ok(name.to_string())
+                })
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        other => bail!(
+            "values of type {} can't be passed as JSON",
+            type_name(other)
+        ),
+    })
+}
+
+/// Converts a [`Val`] into JSON.
+///
+/// # Errors
+/// Returns an error if the value is or contains a resource, or is a non-finite float.
+pub fn val_to_json(val: &Val) -> anyhow::Result<Value> {
+    Ok(match val {
+        Val::Bool(v) => Value::Bool(*v),
+        Val::S8(v) => Value::from(*v),
+        Val::U8(v) => Value::from(*v),
+        Val::S16(v) => Value::from(*v),
+        Val::U16(v) => Value::from(*v),
+        Val::S32(v) => Value::from(*v),
+        Val::U32(v) => Value::from(*v),
+        Val::S64(v) => Value::from(*v),
+        Val::U64(v) => Value::from(*v),
+        Val::Float32(v) => float(f64::from(*v))?,
+        Val::Float64(v) => float(*v)?,
+        Val::Char(v) => Value::String(v.to_string()),
+        Val::String(v) => Value::String(v.clone()),
+        Val::List(vs) | Val::Tuple(vs) => {
+            Value::Array(vs.iter().map(val_to_json).collect::<anyhow::Result<_>>()?)
+        }
+        Val::Record(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, v)| Ok((name.clone(), val_to_json(v)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Val::Variant(name, None) | Val::Enum(name) => Value::String(name.clone()),
+        Val::Variant(name, Some(payload)) => {
+            Value::Object(Map::from_iter([(name.clone(), val_to_json(payload)?)]))
+        }
+        Val::Option(None) => Value::Null,
+        Val::Option(Some(v)) => val_to_json(v)?,
+        Val::Result(result) => {
+            let (name, payload) = match result {
+                Ok(payload) => ("ok", payload),
+                Err(payload) => ("err", payload),
+            };
+            let payload = match payload {
+                Some(v) => val_to_json(v)?,
+                None => Value::Null,
+            };
+            Value::Object(Map::from_iter([(name.to_string(), payload)]))
+        }
+        Val::Flags(names) => Value::Array(names.iter().cloned().map(Value::String).collect()),
+        _ => bail!("resources can't be returned as JSON"),
+    })
+}
+
+/// Renders a type in WIT-like syntax, e.g. `list<option<u32>>`. Named types are
+/// rendered structurally since component types don't carry their WIT names.
+pub fn type_name(ty: &Type) -> String {
+    let join = |items: Vec<String>| items.join(", ");
+    match ty {
+        Type::Bool => "bool".to_string(),
+        Type::S8 => "s8".to_string(),
+        Type::U8 => "u8".to_string(),
+        Type::S16 => "s16".to_string(),
+        Type::U16 => "u16".to_string(),
+        Type::S32 => "s32".to_string(),
+        Type::U32 => "u32".to_string(),
+        Type::S64 => "s64".to_string(),
+        Type::U64 => "u64".to_string(),
+        Type::Float32 => "f32".to_string(),
+        Type::Float64 => "f64".to_string(),
+        Type::Char => "char".to_string(),
+        Type::String => "string".to_string(),
+        Type::List(list) => format!("list<{}>", type_name(&list.ty())),
+        Type::Tuple(tuple) => format!(
+            "tuple<{}>",
+            join(tuple.types().map(|ty| type_name(&ty)).collect())
+        ),
+        Type::Record(record) => format!(
+            "record {{ {} }}",
+            join(
+                record
+                    .fields()
+                    .map(|field| format!("{}: {}", field.name, type_name(&field.ty)))
+                    .collect()
+            )
+        ),
+        Type::Variant(variant) => format!(
+            "variant {{ {} }}",
+            join(
+                variant
+                    .cases()
+                    .map(|case| match &case.ty {
+                        Some(ty) => format!("{}({})", case.name, type_name(ty)),
+                        None => case.name.to_string(),
+                    })
+                    .collect()
+            )
+        ),
+        Type::Enum(enum_) => format!(
+            "enum {{ {} }}",
+            join(enum_.names().map(str::to_string).collect())
+        ),
+        Type::Option(option) => format!("option<{}>", type_name(&option.ty())),
+        Type::Result(result) => match (result.ok(), result.err()) {
+            (Some(ok), Some(err)) => format!("result<{}, {}>", type_name(&ok), type_name(&err)),
+            (Some(ok), None) => format!("result<{}>", type_name(&ok)),
+            (None, Some(err)) => format!("result<_, {}>", type_name(&err)),
+            (None, None) => "result".to_string(),
+        },
+        Type::Flags(flags) => format!(
+            "flags {{ {} }}",
+            join(flags.names().map(str::to_string).collect())
+        ),
+        Type::Own(_) => "own<resource>".to_string(),
+        Type::Borrow(_) => "borrow<resource>".to_string(),
+        #[allow(unreachable_patterns)]
+        _ => "unknown".to_string(),
+    }
+}
+
+fn int<T: TryFrom<i64>>(value: &Value) -> anyhow::Result<T> {
+    let v = value.as_i64().context("expected an integer")?;
+    T::try_from(v).map_err(|_| anyhow::anyhow!("integer {v} is out of range"))
+}
+
+fn uint<T: TryFrom<u64>>(value: &Value) -> anyhow::Result<T> {
+    let v = value.as_u64().context("expected a non-negative integer")?;
+    T::try_from(v).map_err(|_| anyhow::anyhow!("integer {v} is out of range"))
+}
+
+fn float(v: f64) -> anyhow::Result<Value> {
+    serde_json::Number::from_f64(v)
+        .map(Value::Number)
+        .with_context(|| format!("{v} can't be represented in JSON"))
+}
+
+fn array(value: &Value) -> anyhow::Result<&Vec<Value>> {
+    value.as_array().context("expected an array")
+}
+
+/// Splits a case-shaped value (`"name"` or `{"name": payload}`) into its parts.
+fn case(value: &Value) -> anyhow::Result<(&str, Option<&Value>)> {
+    match value {
+        Value::String(name) => Ok((name, None)),
+        Value::Object(object) if object.len() == 1 => {
+            let (name, payload) = object.iter().next().context("expected a case object")?;
+            Ok((name, Some(payload)))
+        }
+        _ => bail!("expected a case name or an object with a single case key"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_val_to_json() {
+        let val = Val::Record(vec![
+            ("id".to_string(), Val::U64(7)),
+            ("name".to_string(), Val::String("widget".to_string())),
+            ("tags".to_string(), Val::Flags(vec!["new".to_string()])),
+            ("stock".to_string(), Val::Option(None)),
+            (
+                "status".to_string(),
+                Val::Result(Err(Some(Box::new(Val::Enum("missing".to_string()))))),
+            ),
+            (
+                "kind".to_string(),
+                Val::Variant("bundle".to_string(), Some(Box::new(Val::U8(3)))),
+            ),
+        ]);
+        assert_eq!(
+            val_to_json(&val).expect("value should convert"),
+            json!({
+                "id": 7,
+                "name": "widget",
+                "tags": ["new"],
+                "stock": null,
+                "status": {"err": "missing"},
+                "kind": {"bundle": 3},
+            })
+        );
+    }
+
+    #[test]
+    fn test_primitive_from_json() {
+        assert!(matches!(
+            val_from_json(&Type::U8, &json!(255)),
+            Ok(Val::U8(255))
+        ));
+        assert!(val_from_json(&Type::U8, &json!(256)).is_err());
+        assert!(val_from_json(&Type::U32, &json!(-1)).is_err());
+        assert!(matches!(
+            val_from_json(&Type::S16, &json!(-3)),
+            Ok(Val::S16(-3))
+        ));
+        assert!(matches!(
+            val_from_json(&Type::Char, &json!("x")),
+            Ok(Val::Char('x'))
+        ));
+        assert!(val_from_json(&Type::Char, &json!("xy")).is_err());
+        assert!(val_from_json(&Type::String, &json!(1)).is_err());
+    }
+
+    #[test]
+    fn test_non_finite_float() {
+        assert!(val_to_json(&Val::Float64(f64::NAN)).is_err());
+    }
+}
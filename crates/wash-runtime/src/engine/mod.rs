@@ -27,6 +27,7 @@
 //!     name: "my-workload".to_string(),
 //!     // ... other fields
 //! #   annotations: std::collections::HashMap::new(),
+//! #   secret_config_keys: std::collections::HashSet::new(),
 //! #   service: None,
 //! #   components: vec![],
 //! #   host_interfaces: vec![],
@@ -43,13 +44,32 @@ use anyhow::{Context, bail};
 use wasmtime::PoolingAllocationConfig;
 use wasmtime::component::{Component, Linker};
 
+use crate::engine::allowed_hosts::AllowedHosts;
+use crate::engine::arena::MemoryArena;
+use crate::engine::artifacts::ArtifactCache;
 use crate::engine::ctx::Ctx;
 use crate::engine::workload::{UnresolvedWorkload, WorkloadComponent, WorkloadService};
-use crate::types::{EmptyDirVolume, HostPathVolume, VolumeType, Workload};
+use crate::host::error::HostError;
+use crate::types::{EmptyDirVolume, HostPathVolume, ScratchVolume, VolumeType, Workload};
+use std::hash::{Hash as _, Hasher as _};
 use std::path::PathBuf;
-
+use std::sync::Arc;
+
+pub mod adapters;
+pub mod allowed_hosts;
+pub mod arena;
+pub mod artifacts;
+pub mod claims;
+pub mod compat;
+pub mod cron;
 pub mod ctx;
+pub mod inspect;
+pub mod instances;
+pub mod json;
+pub mod mounts;
+pub mod scratch;
 mod value;
+pub mod wave;
 pub mod workload;
 
 /// The core WebAssembly engine for executing components and workloads.
@@ -61,6 +81,13 @@ pub mod workload;
 pub struct Engine {
     // wasmtime engine
     pub(crate) inner: wasmtime::Engine,
+    // whether to apply the built-in interface version adapters when compiling components
+    version_adapters: bool,
+    // adapter used to turn preview1 core modules into components, instead of the built-in ones
+    #[cfg(feature = "wasip1")]
+    preview1_adapter: Option<bytes::Bytes>,
+    // compiled components by artifact key, shared by clones
+    artifacts: Arc<ArtifactCache>,
 }
 
 impl Engine {
@@ -82,6 +109,124 @@ impl Engine {
         &self.inner
     }
 
+    /// Compiles a component from its binary, renaming imports of adjacent interface
+    /// revisions to the ones the host links (see [`adapters`]) unless disabled. Core
+    /// `wasm32-wasip1` modules are adapted into components first when the `wasip1`
+    /// feature is enabled.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes are not a valid component, or are a core module that
+    /// can't be adapted.
+    ///
+    /// Compiled components are cached, see [`artifacts`], so compiling the same bytes again
+    /// returns the cached component.
+    // TODO: pre-initialize components at load time, running their initialization function
+    // once and compiling the resulting state in, as `wizer` does at build time. wasmtime
+    // can't snapshot a component instance's state, so for now components must be
+    // pre-initialized when they're built (see `EngineBuilder::with_memory_keep_resident`).
+    pub fn compile_component(&self, bytes: &[u8]) -> anyhow::Result<Component> {
+        let key = self.artifact_key(bytes);
+        if let Some(component) = self.artifacts.get(&key) {
+            return Ok(component);
+        }
+
+        let module_component;
+        let bytes = if adapters::is_core_module(bytes) {
+            module_component = self.componentize_module(bytes)?;
+            module_component.as_slice()
+        } else {
+            bytes
+        };
+
+        let adapted = if self.version_adapters {
+            adapters::adapt_component_imports(bytes).context("failed to adapt component imports")?
+        } else {
+            None
+        };
+        let component = Component::new(&self.inner, adapted.as_deref().unwrap_or(bytes))
+            .context("failed to create component from bytes")?;
+        self.artifacts.insert(key, component.clone());
+        Ok(component)
+    }
+
+    /// The key the compiled artifact of a component is cached and shared under: the
+    /// SHA-256 of its bytes and a hash of the engine settings compiling it depends on, so
+    /// engines only share artifacts they'd compile the same way.
+    pub fn artifact_key(&self, bytes: &[u8]) -> String {
+        let mut settings = std::hash::DefaultHasher::new();
+        self.inner
+            .precompile_compatibility_hash()
+            .hash(&mut settings);
+        self.version_adapters.hash(&mut settings);
+        #[cfg(feature = "wasip1")]
+        self.preview1_adapter.hash(&mut settings);
+        let digest = aws_lc_rs::digest::digest(&aws_lc_rs::digest::SHA256, bytes);
+        let sha256: String = digest
+            .as_ref()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("{sha256}-{:016x}", settings.finish())
+    }
+
+    /// Whether the engine has the compiled artifact of a component cached.
+    pub fn has_artifact(&self, key: &str) -> bool {
+        self.artifacts.get(key).is_some()
+    }
+
+    /// Serializes the cached compiled artifact of a component, e.g. to share it with
+    /// other hosts.
+    ///
+    /// # Returns
+    /// The artifact, or `None` if the engine doesn't have it cached.
+    ///
+    /// # Errors
+    /// Returns an error if the artifact can't be serialized.
+    pub fn serialize_artifact(&self, key: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        self.artifacts
+            .get(key)
+            .map(|component| {
+                component
+                    .serialize()
+                    .context("failed to serialize compiled component")
+            })
+            .transpose()
+    }
+
+    /// Caches a compiled artifact serialized by [`Self::serialize_artifact`], so the
+    /// component isn't compiled when it's loaded.
+    ///
+    /// # Safety
+    /// The artifact is native code that's run without being verified, see
+    /// [`Component::deserialize`]: it must come from a trusted engine, and `key` must be
+    /// the key of the component it was compiled from.
+    ///
+    /// # Errors
+    /// Returns an error if the artifact is corrupt or was compiled by an engine with
+    /// incompatible settings.
+    pub unsafe fn load_artifact(&self, key: &str, artifact: &[u8]) -> anyhow::Result<()> {
+        // SAFETY: the caller vouches for the artifact
+        let component = unsafe { Component::deserialize(&self.inner, artifact) }
+            .context("failed to load compiled component")?;
+        self.artifacts.insert(key.to_string(), component);
+        Ok(())
+    }
+
+    #[cfg(feature = "wasip1")]
+    fn componentize_module(&self, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let adapter = match &self.preview1_adapter {
+            Some(adapter) => adapter.as_ref(),
+            None => adapters::builtin_preview1_adapter(bytes)?,
+        };
+        tracing::debug!("adapting preview1 module into a component");
+        adapters::componentize_preview1_module(bytes, adapter)
+    }
+
+    #[cfg(not(feature = "wasip1"))]
+    fn componentize_module(&self, _bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        bail!("core wasm modules are only supported with the `wasip1` feature enabled")
+    }
+
     /// Initializes a workload by validating and preparing all its components.
     ///
     /// This function takes a workload definition and prepares it for execution by:
@@ -113,15 +258,39 @@ impl Engine {
             components,
             service,
             volumes,
-            host_interfaces,
+            mut host_interfaces,
+            annotations,
             ..
         } = workload;
 
-        // Process and validate volumes - create a lookup map from volume name to validated host path
+        if self.version_adapters {
+            for interface in &mut host_interfaces {
+                if adapters::adapt_interface(interface) {
+                    tracing::debug!(%interface, "adapted requested interface version");
+                }
+            }
+        }
+
+        // Process and validate volumes - create a lookup map from volume name to validated host path,
+        // or to `None` for scratch volumes, which get a directory per instance
         let mut validated_volumes = std::collections::HashMap::new();
+        let mut scratch_volumes = std::collections::HashMap::new();
 
         for v in volumes {
             let host_path = match v.volume_type {
+                VolumeType::Scratch(scratch) => {
+                    scratch_volumes.insert(v.name.clone(), scratch);
+                    validated_volumes.insert(v.name, None);
+                    continue;
+                }
+                VolumeType::Blobstore(blobstore) => {
+                    // The host downloads containers into host path volumes before starting
+                    anyhow::bail!(
+                        "blobstore volume '{}' of container '{}' wasn't downloaded by the host",
+                        v.name,
+                        blobstore.container
+                    );
+                }
                 VolumeType::HostPath(HostPathVolume { local_path }) => {
                     let path = PathBuf::from(&local_path);
                     if !path.is_dir() {
@@ -141,7 +310,32 @@ impl Engine {
             };
 
             // Store the validated volume for later lookup
-            validated_volumes.insert(v.name.clone(), host_path);
+            validated_volumes.insert(v.name.clone(), Some(host_path));
+        }
+
+        // Every instance mounts the workload's scratch volumes, as components share a WasiCtx,
+        // each path once with the strictest permissions and quota it's mounted with
+        let mut scratch_mounts: Vec<(crate::types::VolumeMount, ScratchVolume)> = Vec::new();
+        for mount in service
+            .iter()
+            .map(|service| &service.local_resources)
+            .chain(
+                components
+                    .iter()
+                    .map(|component| &component.local_resources),
+            )
+            .flat_map(|resources| &resources.volume_mounts)
+        {
+            let Some(scratch) = scratch_volumes.get(&mount.name) else {
+                continue;
+            };
+            match scratch_mounts
+                .iter_mut()
+                .find(|(existing, _)| existing.mount_path == mount.mount_path)
+            {
+                Some((existing, _)) => mounts::merge(existing, mount),
+                None => scratch_mounts.push((mount.clone(), scratch.clone())),
+            }
         }
 
         // Iniitalize service
@@ -188,7 +382,9 @@ impl Engine {
             service,
             workload_components,
             host_interfaces,
-        ))
+        )
+        .with_annotations(annotations)
+        .with_scratch_volumes(scratch_mounts))
     }
 
     fn initialize_service(
@@ -197,18 +393,17 @@ impl Engine {
         workload_name: impl AsRef<str>,
         workload_namespace: impl AsRef<str>,
         service: crate::types::Service,
-        validated_volumes: &std::collections::HashMap<String, PathBuf>,
+        validated_volumes: &std::collections::HashMap<String, Option<PathBuf>>,
     ) -> anyhow::Result<WorkloadService> {
         // Create a wasmtime component from the bytes
-        let wasmtime_component = Component::new(&self.inner, service.bytes)
-            .context("failed to create component from bytes")?;
+        let wasmtime_component = self
+            .compile_component(&service.bytes)
+            .context(HostError::CompilationFailed)?;
 
         // Create a linker for this component
         let mut linker: Linker<Ctx> = Linker::new(&self.inner);
 
-        // Add WASI@0.2 interfaces to the linker
-        wasmtime_wasi::p2::add_to_linker_async(&mut linker)
-            .context("failed to add WASI to linker")?;
+        add_wasi_to_linker(&mut linker)?;
 
         // Add HTTP interfaces to the linker if feature is enabled and component uses them
         if uses_wasi_http(&wasmtime_component) {
@@ -216,21 +411,33 @@ impl Engine {
                 .context("failed to add wasi:http/types to linker")?;
         }
 
+        AllowedHosts::parse(&service.local_resources.allowed_hosts)
+            .context("invalid allowed_hosts for service")?;
+        if let Some(cron_job) = service.command.as_ref().and_then(|c| c.cron_job.as_ref()) {
+            cron_job
+                .schedule
+                .parse::<cron::CronSchedule>()
+                .context("invalid cron job schedule for service")?;
+        }
+
         // Build volume mounts for this component by looking up validated volumes
         let mut component_volume_mounts = Vec::new();
         for vm in &service.local_resources.volume_mounts {
-            if let Some(host_path) = validated_volumes.get(&vm.name) {
-                component_volume_mounts.push((host_path.clone(), vm.clone()));
-            } else {
-                tracing::warn!(
+            match validated_volumes.get(&vm.name) {
+                Some(Some(host_path)) => {
+                    component_volume_mounts.push((host_path.clone(), vm.clone()));
+                }
+                // Scratch volumes are mounted for each instance
+                Some(None) => {}
+                None => tracing::warn!(
                     volume = %vm.name,
                     "component references volume that was not found in workload volumes",
-                );
+                ),
             }
         }
 
         // Create the WorkloadService with volume mounts
-        Ok(WorkloadService::new(
+        let workload_service = WorkloadService::new(
             workload_id.as_ref(),
             workload_name.as_ref(),
             workload_namespace.as_ref(),
@@ -239,7 +446,15 @@ impl Engine {
             component_volume_mounts,
             service.local_resources,
             service.max_restarts,
-        ))
+        );
+        let workload_service = match service.name {
+            Some(name) => workload_service.with_published_exports(name, service.exports),
+            None => workload_service,
+        };
+        Ok(match service.command {
+            Some(command) => workload_service.with_command(command),
+            None => workload_service,
+        })
     }
 
     /// Initialize a component that is a part of a workload, add wasi@0.2 interfaces (and
@@ -250,18 +465,17 @@ impl Engine {
         workload_name: impl AsRef<str>,
         workload_namespace: impl AsRef<str>,
         component: crate::types::Component,
-        validated_volumes: &std::collections::HashMap<String, PathBuf>,
+        validated_volumes: &std::collections::HashMap<String, Option<PathBuf>>,
     ) -> anyhow::Result<WorkloadComponent> {
         // Create a wasmtime component from the bytes
-        let wasmtime_component = Component::new(&self.inner, component.bytes)
-            .context("failed to create component from bytes")?;
+        let wasmtime_component = self
+            .compile_component(&component.bytes)
+            .context(HostError::CompilationFailed)?;
 
         // Create a linker for this component
         let mut linker: Linker<Ctx> = Linker::new(&self.inner);
 
-        // Add WASI@0.2 interfaces to the linker
-        wasmtime_wasi::p2::add_to_linker_async(&mut linker)
-            .context("failed to add WASI to linker")?;
+        add_wasi_to_linker(&mut linker)?;
 
         // Add HTTP interfaces to the linker
         if uses_wasi_http(&wasmtime_component) {
@@ -269,20 +483,29 @@ impl Engine {
                 .context("failed to add wasi:http/types to linker")?;
         }
 
+        AllowedHosts::parse(&component.local_resources.allowed_hosts)
+            .context("invalid allowed_hosts for component")?;
+
         // Build volume mounts for this component by looking up validated volumes
         let mut component_volume_mounts = Vec::new();
         for vm in &component.local_resources.volume_mounts {
-            if let Some(host_path) = validated_volumes.get(&vm.name) {
-                component_volume_mounts.push((host_path.clone(), vm.clone()));
-            } else {
-                tracing::warn!(
+            match validated_volumes.get(&vm.name) {
+                Some(Some(host_path)) => {
+                    component_volume_mounts.push((host_path.clone(), vm.clone()));
+                }
+                // Scratch volumes are mounted for each instance
+                Some(None) => {}
+                None => tracing::warn!(
                     volume = %vm.name,
                     "component references volume that was not found in workload volumes",
-                );
+                ),
             }
         }
 
-        // Create the WorkloadComponent with volume mounts
+        // Create the WorkloadComponent with volume mounts. The pool size caps the idle
+        // instances of components that reuse them, see `instances`.
+        // TODO: implement instance limits
+        let pool_size = usize::try_from(component.pool_size).unwrap_or_default();
         Ok(WorkloadComponent::new(
             workload_id.as_ref(),
             workload_name.as_ref(),
@@ -291,10 +514,8 @@ impl Engine {
             linker,
             component_volume_mounts,
             component.local_resources,
-            // TODO: implement pooling and instance limits
-            // component.pool_size,
-            // component.max_invocations,
-        ))
+        )
+        .with_pool_size(pool_size))
     }
 }
 
@@ -307,6 +528,11 @@ impl Engine {
 pub struct EngineBuilder {
     config: wasmtime::Config,
     use_pooling_allocator: Option<bool>,
+    memory_keep_resident: Option<usize>,
+    memory_arena: Option<MemoryArena>,
+    disable_version_adapters: bool,
+    #[cfg(feature = "wasip1")]
+    preview1_adapter: Option<bytes::Bytes>,
 }
 
 impl EngineBuilder {
@@ -324,6 +550,64 @@ impl EngineBuilder {
         self
     }
 
+    /// Keeps up to `bytes` of each linear memory and table resident when the pooling
+    /// allocator reuses an instance slot, resetting them to the component's image in place
+    /// rather than discarding the pages and faulting them back in from the image.
+    ///
+    /// Instances are created copy-on-write from the memory image compiled from the
+    /// component's data segments; wasmtime can't create instances from the memory of a
+    /// running one. Components with heavy initialization should be pre-initialized when
+    /// they're built, e.g. with `wizer`, so the initialized memory becomes their image.
+    /// Without the pooling allocator this has no effect.
+    ///
+    /// # Arguments
+    /// * `bytes` - How much of each memory and table to keep resident
+    ///
+    /// # Returns
+    /// The builder instance for method chaining.
+    pub fn with_memory_keep_resident(mut self, bytes: usize) -> Self {
+        self.memory_keep_resident = Some(bytes);
+        self
+    }
+
+    /// Reserves the pooling allocator's instance slots from `arena` rather than wasmtime's
+    /// defaults, see [`arena`]. Enables the pooling allocator unless it was disabled with
+    /// [`Self::with_pooling_allocator`].
+    ///
+    /// # Arguments
+    /// * `arena` - The slots to reserve, e.g. [`MemoryArena::for_workloads`]
+    ///
+    /// # Returns
+    /// The builder instance for method chaining.
+    pub fn with_memory_arena(mut self, arena: MemoryArena) -> Self {
+        self.memory_arena = Some(arena);
+        self
+    }
+
+    /// Enables or disables the built-in adapters that let components built against an
+    /// adjacent prerelease revision of an interface (e.g. `wasi:keyvalue@0.2.0-draft2`)
+    /// link against the revision the host provides. Enabled by default.
+    pub fn with_version_adapters(mut self, enable: bool) -> Self {
+        self.disable_version_adapters = !enable;
+        self
+    }
+
+    /// Overrides the `wasi_snapshot_preview1` adapter used to run classic `wasm32-wasip1`
+    /// modules. By default the adapters published with the host's wasmtime are used: the
+    /// command adapter for modules exporting `_start`, the reactor adapter for others.
+    /// The adapter must be built for the same WASI 0.2 release as the host's wasmtime.
+    ///
+    /// # Arguments
+    /// * `adapter` - The adapter core module bytes
+    ///
+    /// # Returns
+    /// The builder instance for method chaining.
+    #[cfg(feature = "wasip1")]
+    pub fn with_preview1_adapter(mut self, adapter: impl Into<bytes::Bytes>) -> Self {
+        self.preview1_adapter = Some(adapter.into());
+        self
+    }
+
     /// Sets a custom wasmtime configuration for the engine.
     ///
     /// This allows full control over the wasmtime engine configuration,
@@ -355,20 +639,50 @@ impl EngineBuilder {
     pub fn build(mut self) -> anyhow::Result<Engine> {
         // Async support must be enabled
         self.config.async_support(true);
+        // WASI 0.3 components use the component model's async ABI
+        #[cfg(feature = "wasip3")]
+        self.config.wasm_component_model_async(true);
         // The pooling allocator can be more efficient for workloads with many short-lived instances
-        if let Ok(true) = use_pooling_allocator_by_default(self.use_pooling_allocator) {
+        let use_pooling_allocator = self
+            .use_pooling_allocator
+            .or(self.memory_arena.map(|_| true));
+        if let Ok(true) = use_pooling_allocator_by_default(use_pooling_allocator) {
             tracing::debug!("using pooling allocator by default");
+            let mut pooling = PoolingAllocationConfig::default();
+            if let Some(bytes) = self.memory_keep_resident {
+                pooling.linear_memory_keep_resident(bytes);
+                pooling.table_keep_resident(bytes);
+            }
+            if let Some(arena) = &self.memory_arena {
+                arena.configure(&mut pooling);
+            }
             self.config
-                .allocation_strategy(wasmtime::InstanceAllocationStrategy::Pooling(
-                    PoolingAllocationConfig::default(),
-                ));
+                .allocation_strategy(wasmtime::InstanceAllocationStrategy::Pooling(pooling));
         }
 
         let inner = wasmtime::Engine::new(&self.config)?;
-        Ok(Engine { inner })
+        Ok(Engine {
+            inner,
+            version_adapters: !self.disable_version_adapters,
+            #[cfg(feature = "wasip1")]
+            preview1_adapter: self.preview1_adapter,
+            artifacts: Arc::default(),
+        })
     }
 }
 
+/// Adds the WASI@0.2 interfaces to a linker, plus the WASI@0.3 interfaces when the
+/// `wasip3` feature is enabled.
+///
+/// A WASI 0.3 component can run concurrent calls in one instance when it sets the
+/// `concurrent` strategy, see [`crate::engine::instances`].
+fn add_wasi_to_linker(linker: &mut Linker<Ctx>) -> anyhow::Result<()> {
+    wasmtime_wasi::p2::add_to_linker_async(linker).context("failed to add WASI to linker")?;
+    #[cfg(feature = "wasip3")]
+    wasmtime_wasi::p3::add_to_linker(linker).context("failed to add WASI@0.3 to linker")?;
+    Ok(())
+}
+
 /// Helper function to determine if a component uses wasi:http interfaces
 pub fn uses_wasi_http(component: &Component) -> bool {
     imports_wasi_http(component) || exports_wasi_http(component)
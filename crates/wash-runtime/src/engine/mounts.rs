@@ -0,0 +1,167 @@
+//! Enforcement of volume mounts' read-only flags and byte quotas, so a runaway component
+//! can't fill the host's disk through a volume.
+//!
+//! A read-only [`VolumeMount`] is preopened without write permissions, so
+//! `wasi:filesystem` rejects creating, writing, renaming and removing files under it.
+//! Components of a workload share a WASI context, so when several mount a directory at
+//! the same path the mount is read-only if any of them is, with the smallest quota of
+//! them, see [`merge`].
+//!
+//! A writable mount's `quota_bytes`, and a [`crate::types::ScratchVolume`]'s
+//! `size_limit_bytes`, limit the bytes the files in the mounted directory may hold, files
+//! there before the workload started included. Quotas are checked as instances return
+//! from host calls, at most every [`CHECK_INTERVAL`], by adding up the sizes of the files,
+//! so directories holding many files are costly to check. An instance finding a directory
+//! over its quota traps: a single write can overshoot the quota before the instance is
+//! stopped, but it can't keep filling the disk. Files in volumes other than scratch
+//! volumes outlive the instance, so later instances writing to a full volume trap too,
+//! until files are removed.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::bail;
+
+use crate::types::VolumeMount;
+
+/// How often the size of directories with quotas is checked.
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Combines `other` into `mount` when both mount a directory at the same path, keeping
+/// the stricter permissions and the smaller quota.
+pub(crate) fn merge(mount: &mut VolumeMount, other: &VolumeMount) {
+    mount.read_only |= other.read_only;
+    mount.quota_bytes = match (mount.quota_bytes, other.quota_bytes) {
+        (Some(quota), Some(other)) => Some(quota.min(other)),
+        (quota, other) => quota.or(other),
+    };
+}
+
+/// A directory with a quota.
+#[derive(Debug)]
+struct Quota {
+    dir: PathBuf,
+    mount_path: String,
+    limit_bytes: u64,
+}
+
+/// The quotas of the directories an instance mounts.
+#[derive(Debug)]
+pub(crate) struct VolumeQuotas {
+    quotas: Vec<Quota>,
+    last_checked: Instant,
+}
+
+impl Default for VolumeQuotas {
+    fn default() -> Self {
+        Self {
+            quotas: Vec::new(),
+            last_checked: Instant::now(),
+        }
+    }
+}
+
+impl VolumeQuotas {
+    /// Limits the bytes `dir`, mounted at `mount_path`, may hold.
+    pub(crate) fn add(&mut self, dir: impl Into<PathBuf>, mount_path: &str, limit_bytes: u64) {
+        self.quotas.push(Quota {
+            dir: dir.into(),
+            mount_path: mount_path.to_string(),
+            limit_bytes,
+        });
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.quotas.is_empty()
+    }
+
+    /// Checks that no directory holds more than its quota, unless they were checked
+    /// within the last [`CHECK_INTERVAL`].
+    ///
+    /// # Errors
+    /// Returns an error naming the first directory over its quota.
+    pub(crate) fn check(&mut self) -> anyhow::Result<()> {
+        if self.last_checked.elapsed() < CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.last_checked = Instant::now();
+        for quota in &self.quotas {
+            // A directory that can't be measured is left to the next check
+            if let Ok(size) = dir_size(&quota.dir)
+                && size > quota.limit_bytes
+            {
+                bail!(
+                    "volume mounted at '{}' holds {size} bytes, over its quota of {}",
+                    quota.mount_path,
+                    quota.limit_bytes
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bytes stored in the files under `path`, without following symlinks.
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(read_only: bool, quota_bytes: Option<u64>) -> VolumeMount {
+        VolumeMount {
+            name: "data".to_string(),
+            mount_path: "/data".to_string(),
+            read_only,
+            quota_bytes,
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut merged = mount(false, None);
+        merge(&mut merged, &mount(false, Some(2048)));
+        assert_eq!(merged, mount(false, Some(2048)));
+        merge(&mut merged, &mount(true, Some(1024)));
+        assert_eq!(merged, mount(true, Some(1024)));
+        merge(&mut merged, &mount(false, None));
+        assert_eq!(merged, mount(true, Some(1024)));
+    }
+
+    #[test]
+    fn test_quotas() -> anyhow::Result<()> {
+        let data = tempfile::tempdir()?;
+        let cache = tempfile::tempdir()?;
+        let mut quotas = VolumeQuotas::default();
+        quotas.add(data.path(), "/data", 1024);
+        quotas.add(cache.path(), "/cache", 8192);
+        fs::create_dir(data.path().join("nested"))?;
+        fs::write(data.path().join("nested/a"), [0; 1000])?;
+        fs::write(cache.path().join("b"), [0; 4096])?;
+        assert_eq!(dir_size(data.path())?, 1000);
+
+        quotas.last_checked -= CHECK_INTERVAL;
+        quotas.check()?;
+
+        fs::write(data.path().join("c"), [0; 100])?;
+        // Checks are skipped within the interval
+        quotas.check()?;
+        quotas.last_checked -= CHECK_INTERVAL;
+        assert!(quotas.check().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Scratch volumes, giving components space for temporary files that doesn't outlive them
+//! or pile up on the host.
+//!
+//! Every instance gets a new, empty directory for each [`ScratchVolume`] its workload's
+//! components mount, preopened at the mount's `mount_path`. The directories are created
+//! in the host's temporary directory, so pointing `TMPDIR` at a `tmpfs` keeps them in
+//! memory, and are removed with everything in them when the instance's store is dropped.
+//!
+//! A volume's `size_limit_bytes` is enforced like the quota of its mount, see
+//! [`crate::engine::mounts`]; a mount with both gets the smaller of them.
+
+use anyhow::Context as _;
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+
+use crate::engine::mounts::VolumeQuotas;
+use crate::types::{ScratchVolume, VolumeMount};
+
+/// The scratch directories of one instance, removed when dropped.
+#[derive(Debug)]
+pub(crate) struct ScratchDirs {
+    #[allow(dead_code)]
+    dirs: Vec<tempfile::TempDir>,
+}
+
+impl ScratchDirs {
+    /// Creates a directory for each scratch volume mount, preopens it in `wasi` and adds
+    /// the size limits of writable ones to `quotas`.
+    ///
+    /// # Errors
+    /// Returns an error if a directory can't be created or preopened.
+    pub(crate) fn create(
+        mounts: &[(VolumeMount, ScratchVolume)],
+        wasi: &mut WasiCtxBuilder,
+        quotas: &mut VolumeQuotas,
+    ) -> anyhow::Result<Self> {
+        let mut dirs = Vec::with_capacity(mounts.len());
+        for (mount, volume) in mounts {
+            let dir = tempfile::Builder::new()
+                .prefix("wash-scratch-")
+                .tempdir()
+                .with_context(|| format!("failed to create scratch volume '{}'", mount.name))?;
+            let (dir_perms, file_perms) = match mount.read_only {
+                true => (DirPerms::READ, FilePerms::READ),
+                false => (DirPerms::all(), FilePerms::all()),
+            };
+            wasi.preopened_dir(dir.path(), &mount.mount_path, dir_perms, file_perms)?;
+            let limit = match (volume.size_limit_bytes, mount.quota_bytes) {
+                (Some(limit), Some(quota)) => Some(limit.min(quota)),
+                (limit, quota) => limit.or(quota),
+            };
+            if let Some(limit) = limit
+                && !mount.read_only
+            {
+                quotas.add(dir.path(), &mount.mount_path, limit);
+            }
+            dirs.push(dir);
+        }
+        Ok(Self { dirs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mount(name: &str, size_limit_bytes: Option<u64>) -> (VolumeMount, ScratchVolume) {
+        (
+            VolumeMount {
+                name: name.to_string(),
+                mount_path: format!("/{name}"),
+                read_only: false,
+                quota_bytes: None,
+            },
+            ScratchVolume { size_limit_bytes },
+        )
+    }
+
+    #[test]
+    fn test_scratch_dirs() -> anyhow::Result<()> {
+        let mut quotas = VolumeQuotas::default();
+        let scratch = ScratchDirs::create(
+            &[mount("tmp", Some(1024)), mount("cache", None)],
+            &mut WasiCtxBuilder::new(),
+            &mut quotas,
+        )?;
+        let tmp = scratch.dirs[0].path().to_path_buf();
+        let cache = scratch.dirs[1].path().to_path_buf();
+        assert!(tmp.is_dir());
+        assert!(cache.is_dir());
+        assert!(!quotas.is_empty());
+
+        drop(scratch);
+        assert!(!tmp.exists());
+        assert!(!cache.exists());
+        Ok(())
+    }
+}
@@ -0,0 +1,612 @@
+//! Converts component model values to and from WAVE, the WebAssembly Value Encoding, so
+//! CLI users and scripts can pass typed arguments to workload exports as text.
+//!
+//! WAVE follows the shape of the WIT type:
+//!
+//! | WIT type                  | WAVE                                           |
+//! |---------------------------|------------------------------------------------|
+//! | `bool`                    | `true`, `false`                                |
+//! | integers                  | `42`, `-7`                                     |
+//! | floats                    | `3.5`, `-1e10`, `nan`, `inf`, `-inf`           |
+//! | `char`                    | `'x'`, `'\u{1f600}'`                           |
+//! | `string`                  | `"hello\n"`                                    |
+//! | `list<T>`                 | `[1, 2, 3]`                                    |
+//! | `tuple<..>`               | `(1, "a")`                                     |
+//! | `record`                  | `{id: 7, name: "widget"}`, or `{:}` if empty   |
+//! | `enum`                    | `red`                                          |
+//! | `variant`                 | `circle(2.5)` or `none-case`                   |
+//! | `option<T>`               | `some(1)` or `none`                            |
+//! | `result<T, E>`            | `ok(1)`, `err("boom")`, or `ok`/`err`          |
+//! | `flags`                   | `{read, write}`                                |
+//!
+//! Record fields of option type may be left out when they're `none`. Labels that are
+//! also keywords, such as a case named `ok`, are written with a `%` prefix (`%ok`).
+//! Values may contain whitespace and `//` line comments. Multiline strings and resources
+//! aren't supported.
+
+use std::fmt::Write as _;
+
+use anyhow::{Context as _, bail, ensure};
+use wasmtime::component::{Type, Val};
+
+use crate::engine::json::type_name;
+
+/// Labels that have to be written with a `%` prefix.
+const KEYWORDS: [&str; 8] = ["true", "false", "some", "none", "ok", "err", "inf", "nan"];
+
+/// Parses a WAVE value of the given type.
+///
+/// # Errors
+/// Returns an error if the text isn't a value of the type, a number is out of range, or
+/// the type contains a resource.
+pub fn val_from_wave(ty: &Type, text: &str) -> anyhow::Result<Val> {
+    let mut parser = Parser { text, pos: 0 };
+    let val = parser.value(ty)?;
+    parser.skip_whitespace();
+    ensure!(
+        parser.rest().is_empty(),
+        "unexpected '{}' after value at offset {}",
+        parser.rest(),
+        parser.pos
+    );
+    Ok(val)
+}
+
+/// Renders a [`Val`] as WAVE.
+///
+/// # Errors
+/// Returns an error if the value is or contains a resource.
+pub fn val_to_wave(val: &Val) -> anyhow::Result<String> {
+    let mut out = String::new();
+    write_val(&mut out, val)?;
+    Ok(out)
+}
+
+fn write_val(out: &mut String, val: &Val) -> anyhow::Result<()> {
+    match val {
+        Val::Bool(v) => write!(out, "{v}")?,
+        Val::S8(v) => write!(out, "{v}")?,
+        Val::U8(v) => write!(out, "{v}")?,
+        Val::S16(v) => write!(out, "{v}")?,
+        Val::U16(v) => write!(out, "{v}")?,
+        Val::S32(v) => write!(out, "{v}")?,
+        Val::U32(v) => write!(out, "{v}")?,
+        Val::S64(v) => write!(out, "{v}")?,
+        Val::U64(v) => write!(out, "{v}")?,
+        Val::Float32(v) => write_float(out, f64::from(*v)),
+        Val::Float64(v) => write_float(out, *v),
+        Val::Char(v) => {
+            out.push('\'');
+            write_escaped(out, *v, '\'');
+            out.push('\'');
+        }
+        Val::String(v) => {
+            out.push('"');
+            for c in v.chars() {
+                write_escaped(out, c, '"');
+            }
+            out.push('"');
+        }
+        Val::List(vs) => write_seq(out, '[', vs, ']')?,
+        Val::Tuple(vs) => write_seq(out, '(', vs, ')')?,
+        Val::Record(fields) if fields.is_empty() => out.push_str("{:}"),
+        Val::Record(fields) => {
+            out.push('{');
+            for (i, (name, v)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_label(out, name);
+                out.push_str(": ");
+                write_val(out, v)?;
+            }
+            out.push('}');
+        }
+        Val::Enum(name) | Val::Variant(name, None) => write_label(out, name),
+        Val::Variant(name, Some(payload)) => {
+            write_label(out, name);
+            write_payload(out, payload)?;
+        }
+        Val::Option(None) => out.push_str("none"),
+        Val::Option(Some(v)) => {
+            out.push_str("some");
+            write_payload(out, v)?;
+        }
+        Val::Result(result) => {
+            let (name, payload) = match result {
+                Ok(payload) => ("ok", payload),
+                Err(payload) => ("err", payload),
+            };
+            out.push_str(name);
+            if let Some(payload) = payload {
+                write_payload(out, payload)?;
+            }
+        }
+        Val::Flags(names) => {
+            out.push('{');
+            for (i, name) in names.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_label(out, name);
+            }
+            out.push('}');
+        }
+        _ => bail!("resources can't be written as WAVE"),
+    }
+    Ok(())
+}
+
+fn write_seq(out: &mut String, open: char, vals: &[Val], close: char) -> anyhow::Result<()> {
+    out.push(open);
+    for (i, v) in vals.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_val(out, v)?;
+    }
+    out.push(close);
+    Ok(())
+}
+
+fn write_payload(out: &mut String, payload: &Val) -> anyhow::Result<()> {
+    out.push('(');
+    write_val(out, payload)?;
+    out.push(')');
+    Ok(())
+}
+
+fn write_label(out: &mut String, label: &str) {
+    if KEYWORDS.contains(&label) {
+        out.push('%');
+    }
+    out.push_str(label);
+}
+
+fn write_float(out: &mut String, v: f64) {
+    if v.is_nan() {
+        out.push_str("nan");
+    } else if v.is_infinite() {
+        out.push_str(if v > 0.0 { "inf" } else { "-inf" });
+    } else {
+        let _ = write!(out, "{v:?}");
+    }
+}
+
+fn write_escaped(out: &mut String, c: char, quote: char) {
+    match c {
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if c == quote => {
+            out.push('\\');
+            out.push(c);
+        }
+        c if c.is_control() => {
+            let _ = write!(out, "\\u{{{:x}}}", c as u32);
+        }
+        c => out.push(c),
+    }
+}
+
+/// A type-directed parser over WAVE text.
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Consumes `c` if it's the next character after whitespace.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        ensure!(
+            self.eat(c),
+            "expected '{c}' at offset {}, found {}",
+            self.pos,
+            self.found()
+        );
+        Ok(())
+    }
+
+    /// Describes the next token for error messages.
+    fn found(&self) -> String {
+        match self.rest().split_whitespace().next() {
+            Some(token) => format!("'{}'", token.chars().take(16).collect::<String>()),
+            None => "end of input".to_string(),
+        }
+    }
+
+    /// Reads a run of characters that can make up a label or number.
+    fn word(&mut self) -> &'a str {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.' | '_')))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        &rest[..len]
+    }
+
+    /// Reads a label, removing the `%` prefix that escapes keywords.
+    fn label(&mut self) -> anyhow::Result<&'a str> {
+        let escaped = self.eat('%');
+        let start = self.pos;
+        let label = self.word();
+        ensure!(
+            !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+            "expected a label at offset {start}, found {}",
+            if label.is_empty() {
+                self.found()
+            } else {
+                format!("'{label}'")
+            }
+        );
+        ensure!(
+            escaped || !KEYWORDS.contains(&label),
+            "'{label}' is a keyword, write it as '%{label}'"
+        );
+        Ok(label)
+    }
+
+    /// Reads a keyword such as `some` or `ok`.
+    fn keyword(&mut self) -> anyhow::Result<&'a str> {
+        self.skip_whitespace();
+        if self.peek() == Some('%') {
+            bail!("expected a keyword at offset {}", self.pos);
+        }
+        Ok(self.word())
+    }
+
+    /// Parses comma-separated items up to `close`, allowing a trailing comma.
+    fn seq(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.expect(open)?;
+        loop {
+            if self.eat(close) {
+                return Ok(());
+            }
+            item(self)?;
+            if !self.eat(',') {
+                return self.expect(close);
+            }
+        }
+    }
+
+    /// Parses an optional parenthesized payload.
+    fn payload(&mut self, ty: Option<Type>, name: &str) -> anyhow::Result<Option<Box<Val>>> {
+        match ty {
+            Some(ty) => {
+                self.expect('(')?;
+                let val = self
+                    .value(&ty)
+                    .with_context(|| format!("in payload of '{name}'"))?;
+                self.expect(')')?;
+                Ok(Some(Box::new(val)))
+            }
+            None => {
+                ensure!(!self.eat('('), "'{name}' has no payload");
+                Ok(None)
+            }
+        }
+    }
+
+    fn int<T: TryFrom<i128>>(&mut self) -> anyhow::Result<T> {
+        let word = self.word();
+        let v: i128 = word
+            .parse()
+            .with_context(|| format!("expected an integer, found '{word}'"))?;
+        T::try_from(v).map_err(|_| anyhow::anyhow!("integer {v} is out of range"))
+    }
+
+    fn float(&mut self) -> anyhow::Result<f64> {
+        let word = self.word();
+        match word {
+            "nan" => Ok(f64::NAN),
+            "inf" => Ok(f64::INFINITY),
+            "-inf" => Ok(f64::NEG_INFINITY),
+            // Rust also accepts spellings like "NaN" and "infinity", which WAVE doesn't
+            _ if word
+                .chars()
+                .any(|c| c.is_ascii_alphabetic() && c != 'e' && c != 'E') =>
+            {
+                bail!("expected a number, found '{word}'")
+            }
+            _ => word
+                .parse()
+                .with_context(|| format!("expected a number, found '{word}'")),
+        }
+    }
+
+    /// Reads a quoted string or character literal, unescaping it.
+    fn quoted(&mut self, quote: char) -> anyhow::Result<String> {
+        self.expect(quote)?;
+        if quote == '"' && self.rest().starts_with("\"\"") {
+            bail!("multiline strings aren't supported");
+        }
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                c if c == quote => {
+                    self.pos += i + c.len_utf8();
+                    return Ok(value);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('\\') => '\\',
+                        Some('\'') => '\'',
+                        Some('"') => '"',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let hex: String = chars
+                                .by_ref()
+                                .map(|(_, c)| c)
+                                .take_while(|c| *c != '}')
+                                .collect();
+                            let hex = hex.strip_prefix('{').context("expected '\\u{...}'")?;
+                            u32::from_str_radix(hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .with_context(|| format!("invalid escape '\\u{{{hex}}}'"))?
+                        }
+                        other => bail!("invalid escape '\\{}'", other.unwrap_or(' ')),
+                    };
+                    value.push(escaped);
+                }
+                '\n' => bail!("unterminated literal"),
+                c => value.push(c),
+            }
+        }
+        bail!("unterminated literal")
+    }
+
+    fn value(&mut self, ty: &Type) -> anyhow::Result<Val> {
+        Ok(match ty {
+            Type::Bool => match self.keyword()? {
+                "true" => Val::Bool(true),
+                "false" => Val::Bool(false),
+                other => bail!("expected 'true' or 'false', found '{other}'"),
+            },
+            Type::S8 => Val::S8(self.int()?),
+            Type::U8 => Val::U8(self.int()?),
+            Type::S16 => Val::S16(self.int()?),
+            Type::U16 => Val::U16(self.int()?),
+            Type::S32 => Val::S32(self.int()?),
+            Type::U32 => Val::U32(self.int()?),
+            Type::S64 => Val::S64(self.int()?),
+            Type::U64 => Val::U64(self.int()?),
+            Type::Float32 => Val::Float32(self.float()? as f32),
+            Type::Float64 => Val::Float64(self.float()?),
+            Type::Char => {
+                let literal = self.quoted('\'')?;
+                let mut chars = literal.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Val::Char(c),
+                    _ => bail!("expected a single character, got '{literal}'"),
+                }
+            }
+            Type::String => Val::String(self.quoted('"')?),
+            Type::List(list) => {
+                let element = list.ty();
+                let mut values = Vec::new();
+                self.seq('[', ']', |p| {
+                    let val = p
+                        .value(&element)
+                        .with_context(|| format!("at index {}", values.len()))?;
+                    values.push(val);
+                    Ok(())
+                })?;
+                Val::List(values)
+            }
+            Type::Tuple(tuple) => {
+                let mut types = tuple.types();
+                let mut values = Vec::new();
+                self.seq('(', ')', |p| {
+                    let ty = types.next().with_context(|| {
+                        format!("expected a tuple of {} elements", tuple.types().len())
+                    })?;
+                    let val = p
+                        .value(&ty)
+                        .with_context(|| format!("at index {}", values.len()))?;
+                    values.push(val);
+                    Ok(())
+                })?;
+                ensure!(
+                    values.len() == tuple.types().len(),
+                    "expected a tuple of {} elements, got {}",
+                    tuple.types().len(),
+                    values.len()
+                );
+                Val::Tuple(values)
+            }
+            Type::Record(record) => {
+                let mut fields: Vec<(String, Val)> = Vec::new();
+                let is_empty = {
+                    let start = self.pos;
+                    let empty = self.eat('{') && self.eat(':') && self.eat('}');
+                    if !empty {
+                        self.pos = start;
+                    }
+                    empty
+                };
+                if !is_empty {
+                    self.seq('{', '}', |p| {
+                        let name = p.label()?;
+                        let Some(field) = record.fields().find(|f| f.name == name) else {
+                            bail!("unknown field '{name}'");
+                        };
+                        ensure!(
+                            !fields.iter().any(|(n, _)| n == name),
+                            "duplicate field '{name}'"
+                        );
+                        p.expect(':')?;
+                        let val = p
+                            .value(&field.ty)
+                            .with_context(|| format!("in field '{name}'"))?;
+                        fields.push((name.to_string(), val));
+                        Ok(())
+                    })?;
+                }
+                Val::Record(
+                    record
+                        .fields()
+                        .map(
+                            |field| match fields.iter().position(|(n, _)| n == field.name) {
+                                Some(i) => Ok(fields.swap_remove(i)),
+                                None if matches!(field.ty, Type::Option(_)) => {
+                                    Ok((field.name.to_string(), Val::Option(None)))
+                                }
+                                None => bail!("missing field '{}'", field.name),
+                            },
+                        )
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            Type::Variant(variant) => {
+                let name = self.label()?;
+                let Some(case) = variant.cases().find(|c| c.name == name) else {
+                    bail!("unknown variant case '{name}'");
+                };
+                Val::Variant(name.to_string(), self.payload(case.ty, name)?)
+            }
+            Type::Enum(enum_) => {
+                let name = self.label()?;
+                ensure!(
+                    enum_.names().any(|n| n == name),
+                    "unknown enum case '{name}'"
+                );
+                Val::Enum(name.to_string())
+            }
+            Type::Option(option) => match self.keyword()? {
+                "none" => Val::Option(None),
+                "some" => Val::Option(self.payload(Some(option.ty()), "some")?),
+                other => bail!("expected 'some(...)' or 'none', found '{other}'"),
+            },
+            Type::Result(result) => match self.keyword()? {
+                "ok" => Val::Result(Ok(self.payload(result.ok(), "ok")?)),
+                "err" => Val::Result(Err(self.payload(result.err(), "err")?)),
+                other => bail!("expected 'ok' or 'err', found '{other}'"),
+            },
+            Type::Flags(flags) => {
+                let mut names: Vec<String> = Vec::new();
+                self.seq('{', '}', |p| {
+                    let name = p.label()?;
+                    ensure!(flags.names().any(|n| n == name), "unknown flag '{name}'");
+                    if !names.iter().any(|n| n == name) {
+                        names.push(name.to_string());
+                    }
+                    Ok(())
+                })?;
+                Val::Flags(names)
+            }
+            other => bail!(
+                "values of type {} can't be written as WAVE",
+                type_name(other)
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_val_to_wave() -> anyhow::Result<()> {
+        let val = Val::Record(vec![
+            ("id".to_string(), Val::U64(7)),
+            ("name".to_string(), Val::String("say \"hi\"\n".to_string())),
+            (
+                "tags".to_string(),
+                Val::Flags(vec!["new".to_string(), "ok".to_string()]),
+            ),
+            ("stock".to_string(), Val::Option(None)),
+            (
+                "status".to_string(),
+                Val::Result(Err(Some(Box::new(Val::Enum("missing".to_string()))))),
+            ),
+            (
+                "dims".to_string(),
+                Val::Tuple(vec![Val::Float32(1.5), Val::Float64(f64::NEG_INFINITY)]),
+            ),
+            (
+                "kind".to_string(),
+                Val::Variant("bundle".to_string(), Some(Box::new(Val::Char('\'')))),
+            ),
+            (
+                "sizes".to_string(),
+                Val::List(vec![Val::S8(-1), Val::S8(2)]),
+            ),
+            ("extra".to_string(), Val::Record(vec![])),
+        ]);
+        assert_eq!(
+            val_to_wave(&val)?,
+            r#"{id: 7, name: "say \"hi\"\n", tags: {new, %ok}, stock: none, status: err(missing), dims: (1.5, -inf), kind: bundle('\''), sizes: [-1, 2], extra: {:}}"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_primitive_from_wave() -> anyhow::Result<()> {
+        assert_eq!(val_from_wave(&Type::U8, " 255 ")?, Val::U8(255));
+        assert!(val_from_wave(&Type::U8, "256").is_err());
+        assert!(val_from_wave(&Type::U32, "-1").is_err());
+        assert_eq!(val_from_wave(&Type::S16, "-3")?, Val::S16(-3));
+        assert_eq!(val_from_wave(&Type::Bool, "true")?, Val::Bool(true));
+        assert_eq!(
+            val_from_wave(&Type::Float64, "-1e3")?,
+            Val::Float64(-1000.0)
+        );
+        assert_eq!(
+            val_from_wave(&Type::Float32, "inf")?,
+            Val::Float32(f32::INFINITY)
+        );
+        assert!(val_from_wave(&Type::Float64, "NaN").is_err());
+        assert_eq!(val_from_wave(&Type::Char, r"'\u{1f600}'")?, Val::Char('😀'));
+        assert!(val_from_wave(&Type::Char, "'xy'").is_err());
+        assert_eq!(
+            val_from_wave(&Type::String, "\"tab\\there\" // a comment")?,
+            Val::String("tab\there".to_string())
+        );
+        assert!(val_from_wave(&Type::String, "\"unterminated").is_err());
+        assert!(val_from_wave(&Type::U8, "1 2").is_err());
+        Ok(())
+    }
+}
@@ -1,31 +1,75 @@
 //! This module is primarily concerned with converting an [`UnresolvedWorkload`] into a [`ResolvedWorkload`] by
 //! resolving all components and their dependencies.
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     ops::{Deref, DerefMut},
     path::PathBuf,
-    sync::Arc,
-    time::Duration,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::{Context as _, bail, ensure};
-use tokio::{sync::RwLock, task::JoinHandle, time::timeout};
+use chrono::{DateTime, Utc};
+use tokio::{
+    sync::{RwLock, watch},
+    task::JoinHandle,
+    time::timeout,
+};
+use tokio_util::task::AbortOnDropHandle;
 use tracing::{debug, info, trace, warn};
 use wasmtime::component::{
-    Component, Instance, InstancePre, Linker, ResourceAny, ResourceType, Val, types::ComponentItem,
+    Component, Instance, InstancePre, Linker, ResourceAny, ResourceType, Type, Val,
+    types::ComponentItem,
+};
+use wasmtime_wasi::{
+    DirPerms, FilePerms, I32Exit, WasiCtxBuilder,
+    p2::{
+        bindings::CommandPre,
+        pipe::{MemoryInputPipe, MemoryOutputPipe},
+    },
+    sockets::SocketAddrUse,
 };
-use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder, p2::bindings::CommandPre};
 
+#[cfg(feature = "wasip3")]
+use crate::engine::instances::{SharedInstance, SharedInstances};
 use crate::{
     engine::{
+        allowed_hosts::AllowedHosts,
+        cron::CronSchedule,
         ctx::Ctx,
+        instances::{self, IdleInstances, InstanceReuse},
+        json,
+        mounts::{self, VolumeQuotas},
+        scratch::ScratchDirs,
         value::{lift, lower},
+        wave,
+    },
+    host::{
+        bandwidth::{self, BANDWIDTH_LIMIT_CONFIG, WorkloadBandwidth},
+        cgroups::WorkloadCgroup,
+        egress::EgressLog,
+        error::HostError,
+        proxy::EgressProxy,
+        services::{ServiceRegistry, service_reference},
+        slow_invocations,
+        usage::WorkloadUsage,
+        wrpc::WrpcTransport,
     },
     plugin::HostPlugin,
-    types::{LocalResources, VolumeMount},
+    types::{
+        Command, CommandResult, ConcurrencyPolicy, CronJob, CronJobRun, CronJobStatus, Job,
+        JobState, JobStatus, LocalResources, LocalResourcesUpdate, ScratchVolume, VolumeMount,
+    },
     wit::{WitInterface, WitWorld},
 };
 
+/// The most bytes captured from each of a command's stdout and stderr. A command that
+/// writes more traps.
+pub const MAX_COMMAND_OUTPUT: usize = 4 * 1024 * 1024;
+
 /// Type alias for tracking bound plugins with their matched interfaces during binding.
 /// Tuple: (plugin, matched_interfaces, component_ids)
 type BoundPluginWithInterfaces = (
@@ -34,6 +78,274 @@ type BoundPluginWithInterfaces = (
     Vec<String>,
 );
 
+/// WASI packages linked into every component by [`wasmtime_wasi::p2::add_to_linker_async`].
+const HOST_WASI_PACKAGES: &[&str] = &["io", "clocks", "random", "cli", "filesystem", "sockets"];
+
+/// An interface a workload needs that nothing on the host provides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingInterface {
+    /// The ID of the component (or service) that needs the interface
+    pub component_id: String,
+    /// The fully qualified interface, e.g. `wasi:keyvalue/store@0.2.0-draft`
+    pub interface: String,
+    /// `true` if the interface was requested in the workload's host interfaces, `false`
+    /// if it's a component import that nothing was linked to
+    pub requested: bool,
+    /// Plugins that provide the interface at an incompatible version, formatted as
+    /// `plugin-id (namespace:package@version)`
+    pub offered: Vec<String>,
+}
+
+/// Error returned when a workload's interfaces can't all be satisfied by the host.
+///
+/// This is returned through [`anyhow::Error`] and can be recovered with
+/// [`anyhow::Error::downcast_ref`] to inspect exactly what is missing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnresolvedInterfacesError {
+    pub workload_id: String,
+    pub missing: Vec<MissingInterface>,
+    /// IDs of the plugins that were considered when binding the workload, sorted
+    pub considered_plugins: Vec<String>,
+}
+
+impl std::fmt::Display for UnresolvedInterfacesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "workload {} has unresolved interfaces:",
+            self.workload_id
+        )?;
+        for missing in &self.missing {
+            if missing.requested && !missing.offered.is_empty() {
+                write!(
+                    f,
+                    " component '{}' requested '{}', which no plugin provides at a compatible version (offered: {});",
+                    missing.component_id,
+                    missing.interface,
+                    missing.offered.join(", ")
+                )?;
+            } else if missing.requested {
+                write!(
+                    f,
+                    " component '{}' requested '{}', which no plugin provides;",
+                    missing.component_id, missing.interface
+                )?;
+            } else {
+                write!(
+                    f,
+                    " component '{}' imports '{}', which is not linked;",
+                    missing.component_id, missing.interface
+                )?;
+            }
+        }
+        write!(
+            f,
+            " considered plugins: [{}]",
+            self.considered_plugins.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnresolvedInterfacesError {}
+
+/// Checks the resources of a component the way starting or updating it does.
+///
+/// # Errors
+/// Returns an error naming the first resource setting that's invalid.
+pub(crate) fn check_resources(resources: &LocalResources) -> anyhow::Result<()> {
+    AllowedHosts::parse(&resources.allowed_hosts).context("invalid allowed_hosts")?;
+    slow_invocations::threshold(resources)?;
+    instances::strategy(resources)?;
+    bandwidth::limit(resources)?;
+    Ok(())
+}
+
+/// The host's environment variables, without those that aren't valid UTF-8.
+fn host_environment() -> impl Iterator<Item = (String, String)> {
+    std::env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+}
+
+/// Adds a finished run to the front of a cron job's history, dropping the oldest runs
+/// beyond its history limits.
+fn record_cron_job_run(status: &mut CronJobStatus, run: CronJobRun, cron_job: &CronJob) {
+    status.history.insert(0, run);
+    let (mut succeeded, mut failed) = (0, 0);
+    status.history.retain(|run| {
+        if run.status.state == JobState::Succeeded {
+            succeeded += 1;
+            succeeded <= cron_job.successful_jobs_history_limit
+        } else {
+            failed += 1;
+            failed <= cron_job.failed_jobs_history_limit
+        }
+    });
+}
+
+/// Drives the runs of a [`Job`], starting each one with `run`, applying changes to its
+/// [`JobStatus`] through `update`, and returns the result of its last run.
+///
+/// A failed run is replaced once its backoff has elapsed, and runs that finish in the
+/// meantime are still counted.
+async fn drive_job<F>(
+    workload_id: &str,
+    job: Job,
+    update: impl Fn(&dyn Fn(&mut JobStatus)),
+    run: impl Fn() -> F,
+) -> CommandResult
+where
+    F: Future<Output = CommandResult> + Send + 'static,
+{
+    update(&|status| *status = JobStatus::default());
+
+    let completions = job.completions.max(1);
+    let parallelism = job.parallelism.max(1);
+    let mut last_result = None;
+    let runs = async {
+        let mut active = tokio::task::JoinSet::new();
+        let (mut succeeded, mut failed) = (0, 0);
+        let mut backoff = job.backoff;
+        // When the runs replacing a failed one may start
+        let mut backing_off = None;
+        loop {
+            while backing_off.is_none()
+                && (active.len() as u32) < parallelism
+                && succeeded + (active.len() as u32) < completions
+            {
+                active.spawn(run());
+            }
+            let active_runs = active.len() as u32;
+            update(&|status| status.active = active_runs);
+
+            let run = tokio::select! {
+                Some(run) = active.join_next() => run,
+                () = tokio::time::sleep_until(backing_off.unwrap_or_else(tokio::time::Instant::now)),
+                    if backing_off.is_some() =>
+                {
+                    backing_off = None;
+                    continue;
+                }
+                else => return Ok(()),
+            };
+            let result = run.unwrap_or_else(|e| CommandResult {
+                exit_code: 1,
+                stderr: format!("error: {e}\n").into(),
+                ..Default::default()
+            });
+            let exit_code = result.exit_code;
+            last_result = Some(result);
+            if exit_code == 0 {
+                succeeded += 1;
+                update(&|status| status.succeeded = succeeded);
+                if succeeded >= completions {
+                    return Ok(());
+                }
+            } else {
+                failed += 1;
+                update(&|status| status.failed = failed);
+                if failed > job.backoff_limit {
+                    bail!("job has reached its backoff limit of {}", job.backoff_limit);
+                }
+                warn!(
+                    workload_id,
+                    exit_code, failed, "job run failed, replacing it after {backoff:?}"
+                );
+                backing_off = Some(tokio::time::Instant::now() + backoff);
+                backoff = (backoff * 2).min(Job::MAX_BACKOFF);
+            }
+        }
+    };
+    let outcome = match job.active_deadline {
+        Some(deadline) => timeout(deadline, runs)
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("job exceeded its deadline of {deadline:?}"))),
+        None => runs.await,
+    };
+
+    update(&|status| {
+        status.active = 0;
+        match &outcome {
+            Ok(()) => status.state = JobState::Succeeded,
+            Err(e) => {
+                status.state = JobState::Failed;
+                status.message = e.to_string();
+            }
+        }
+    });
+    info!(workload_id, succeeded = outcome.is_ok(), "job finished");
+    last_result.unwrap_or_default()
+}
+
+/// Expands a [`WitInterface`] into one fully qualified name per interface, e.g.
+/// `wasi:keyvalue/store,atomics@0.2.0` into `wasi:keyvalue/store@0.2.0` and
+/// `wasi:keyvalue/atomics@0.2.0`.
+fn qualified_interface_names(interface: &WitInterface) -> Vec<String> {
+    let version = match (&interface.version, &interface.version_req) {
+        (Some(v), _) => format!("@{v}"),
+        (None, Some(req)) => format!("@{req}"),
+        (None, None) => String::new(),
+    };
+    let mut names: Vec<String> = interface
+        .interfaces
+        .iter()
+        .map(|name| {
+            format!(
+                "{}:{}/{name}{version}",
+                interface.namespace, interface.package
+            )
+        })
+        .collect();
+    if names.is_empty() {
+        names.push(format!(
+            "{}:{}{version}",
+            interface.namespace, interface.package
+        ));
+    }
+    names.sort();
+    names
+}
+
+/// Lists the plugins that provide `interface` at a version incompatible with the one
+/// requested, so version mismatches can be told apart from interfaces nobody provides.
+fn incompatible_offers(
+    plugins: &HashMap<&'static str, Arc<dyn HostPlugin + 'static>>,
+    interface: &WitInterface,
+) -> Vec<String> {
+    let mut offers: Vec<String> = plugins
+        .iter()
+        .flat_map(|(id, plugin)| {
+            plugin
+                .world()
+                .imports
+                .into_iter()
+                .filter(|provided| {
+                    provided.namespace == interface.namespace
+                        && provided.package == interface.package
+                        && (interface.interfaces.is_empty()
+                            || !interface.interfaces.is_disjoint(&provided.interfaces))
+                        && !provided.version_compatible(interface)
+                })
+                .map(move |provided| format!("{id} ({})", provided.instance()))
+        })
+        .collect();
+    offers.sort();
+    offers.dedup();
+    offers
+}
+
+/// A function in an interface exported by a workload component.
+#[derive(Debug, Clone)]
+pub struct ExportedFunction {
+    /// The fully qualified exported interface, e.g. `acme:inventory/query@0.1.0`
+    pub interface: String,
+    /// The name of the function within the interface
+    pub name: String,
+    /// The names and types of the function parameters
+    pub params: Vec<(String, Type)>,
+    /// The types of the function results
+    pub results: Vec<Type>,
+}
+
 /// Metadata associated with components and services within a workload.
 #[derive(Clone)]
 pub struct WorkloadMetadata {
@@ -93,6 +405,11 @@ impl WorkloadMetadata {
         &self.local_resources
     }
 
+    /// Returns the directories the component mounts, with their mounts.
+    pub fn volume_mounts(&self) -> &[(PathBuf, VolumeMount)] {
+        &self.volume_mounts
+    }
+
     /// Returns a reference to the plugins associated with this component.
     pub fn plugins(&self) -> &Option<HashMap<&'static str, Arc<dyn HostPlugin + Send + Sync>>> {
         &self.plugins
@@ -117,6 +434,47 @@ impl WorkloadMetadata {
         self.plugins = Some(plugins);
     }
 
+    /// Returns the instance imports of this component that aren't provided by the host's
+    /// WASI implementation, by a plugin bound to this component, or by another component
+    /// in the workload.
+    ///
+    /// # Arguments
+    /// * `workload_exports` - Instance names exported by components in the same workload
+    fn unlinked_imports(&self, workload_exports: &HashSet<String>) -> Vec<String> {
+        let plugin_worlds: Vec<WitWorld> = self
+            .plugins
+            .iter()
+            .flat_map(|plugins| plugins.values())
+            .map(|plugin| plugin.world())
+            .collect();
+        let uses_wasi_http = self.uses_wasi_http();
+
+        let mut unlinked: Vec<String> = self
+            .component
+            .component_type()
+            .imports(self.component.engine())
+            .filter(|(_, item)| matches!(item, ComponentItem::ComponentInstance(_)))
+            .map(|(name, _)| name.to_string())
+            .filter(|name| {
+                if workload_exports.contains(name) {
+                    return false;
+                }
+                let interface = WitInterface::from(name.as_str());
+                if interface.namespace == "wasi"
+                    && (HOST_WASI_PACKAGES.contains(&interface.package.as_str())
+                        || (interface.package == "http" && uses_wasi_http))
+                {
+                    return false;
+                }
+                !plugin_worlds
+                    .iter()
+                    .any(|world| world.includes_bidirectional(&interface))
+            })
+            .collect();
+        unlinked.sort();
+        unlinked
+    }
+
     /// Extracts the [`ComponentItem::ComponentInstance`]s that the component exports.
     pub fn component_exports(&self) -> anyhow::Result<Vec<(String, ComponentItem)>> {
         Ok(self
@@ -133,6 +491,31 @@ impl WorkloadMetadata {
             .collect::<Vec<_>>())
     }
 
+    /// Lists the functions in every interface the component exports.
+    pub fn exported_functions(&self) -> Vec<ExportedFunction> {
+        let engine = self.component.engine();
+        let mut functions = Vec::new();
+        for (interface, item) in self.component.component_type().exports(engine) {
+            let ComponentItem::ComponentInstance(instance) = item else {
+                continue;
+            };
+            for (name, item) in instance.exports(engine) {
+                if let ComponentItem::ComponentFunc(func) = item {
+                    functions.push(ExportedFunction {
+                        interface: interface.to_string(),
+                        name: name.to_string(),
+                        params: func
+                            .params()
+                            .map(|(name, ty)| (name.to_string(), ty))
+                            .collect(),
+                        results: func.results().collect(),
+                    });
+                }
+            }
+        }
+        functions
+    }
+
     pub fn uses_wasi_http(&self) -> bool {
         crate::engine::uses_wasi_http(&self.component)
     }
@@ -147,59 +530,13 @@ impl WorkloadMetadata {
 
     /// Computes and returns the [`WitWorld`] of this component.
     pub fn world(&self) -> WitWorld {
-        let mut imports = HashMap::new();
-        let mut exports = HashMap::new();
-
-        // Iterate over imports, merging interfaces when namespace:package@version matches
-        for (import_name, import_item) in self
-            .component
-            .component_type()
-            .imports(self.component.engine())
-        {
-            if let ComponentItem::ComponentInstance(_) = import_item {
-                let interface = WitInterface::from(import_name);
-                let k = interface.instance();
-                imports
-                    .entry(k)
-                    .and_modify(|existing: &mut WitInterface| {
-                        existing.merge(&interface);
-                    })
-                    .or_insert(interface);
-            } else {
-                debug!(
-                    import_name,
-                    "imported item is not a component instance, skipping"
-                );
-            }
-        }
-
-        // Iterate over exports, merging interfaces when namespace:package@version matches
-        for (export_name, export_item) in self
-            .component
-            .component_type()
-            .exports(self.component.engine())
-        {
-            if let ComponentItem::ComponentInstance(_) = export_item {
-                let interface = WitInterface::from(export_name);
-                let k = interface.instance();
-                exports
-                    .entry(k)
-                    .and_modify(|existing: &mut WitInterface| {
-                        existing.merge(&interface);
-                    })
-                    .or_insert(interface);
-            } else {
-                debug!(
-                    export_name,
-                    "exported item is not a component instance, skipping"
-                );
-            }
-        }
+        crate::engine::inspect::component_world(&self.component)
+    }
 
-        WitWorld {
-            imports: imports.into_values().collect(),
-            exports: exports.into_values().collect(),
-        }
+    /// Reads the instances this component imports and exports, see
+    /// [`crate::engine::compat`].
+    pub fn interfaces(&self) -> crate::engine::compat::ComponentInterfaces {
+        crate::engine::compat::ComponentInterfaces::of(&self.component)
     }
 }
 
@@ -213,6 +550,18 @@ pub struct WorkloadService {
     max_restarts: u64,
     /// The [`JoinHandle`] for the running service
     handle: Option<Arc<JoinHandle<()>>>,
+    /// The name the service is published under, if any
+    name: Option<Arc<str>>,
+    /// The interfaces published to other workloads under `name`
+    exports: Vec<WitInterface>,
+    /// Set when the service runs to completion as a command
+    command: Option<Command>,
+    /// The result of the command once it has run to completion, shared across clones
+    command_result: Arc<OnceLock<CommandResult>>,
+    /// The progress of the command when it runs as a job, shared across clones
+    job_status: Arc<Mutex<JobStatus>>,
+    /// The progress of the command when it runs as a cron job, shared across clones
+    cron_job_status: Arc<Mutex<CronJobStatus>>,
 }
 
 impl WorkloadService {
@@ -243,9 +592,32 @@ impl WorkloadService {
             },
             handle: None,
             max_restarts,
+            name: None,
+            exports: Vec::new(),
+            command: None,
+            command_result: Arc::default(),
+            job_status: Arc::default(),
+            cron_job_status: Arc::default(),
         }
     }
 
+    /// Publishes the service to other workloads under `name`, exposing `exports`.
+    pub fn with_published_exports(
+        mut self,
+        name: impl Into<Arc<str>>,
+        exports: Vec<WitInterface>,
+    ) -> Self {
+        self.name = Some(name.into());
+        self.exports = exports;
+        self
+    }
+
+    /// Runs the service to completion as a command, see [`Command`].
+    pub fn with_command(mut self, command: Command) -> Self {
+        self.command = Some(command);
+        self
+    }
+
     /// Pre-instantiate the component to prepare for execution.
     pub fn pre_instantiate(&mut self) -> anyhow::Result<CommandPre<Ctx>> {
         let component = self.metadata.component.clone();
@@ -315,6 +687,17 @@ impl WorkloadComponent {
     pub fn metadata(&self) -> &WorkloadMetadata {
         &self.metadata
     }
+
+    /// Returns the number of warm instances to keep for this component.
+    pub fn pool_size(&self) -> usize {
+        self.pool_size
+    }
+
+    /// Sets the number of warm instances to keep for this component.
+    pub fn with_pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
 }
 
 impl std::fmt::Debug for WorkloadComponent {
@@ -341,6 +724,7 @@ impl std::fmt::Debug for WorkloadService {
             .field("workload_id", &self.metadata.workload_id.as_ref())
             .field("volume_mounts", &self.metadata.volume_mounts)
             .field("is_running", &self.is_running())
+            .field("is_command", &self.command.is_some())
             .finish()
     }
 }
@@ -391,10 +775,33 @@ pub struct ResolvedWorkload {
     components: Arc<RwLock<HashMap<Arc<str>, WorkloadComponent>>>,
     /// The HTTP handler for outgoing HTTP requests
     http_handler: Arc<dyn crate::host::http::HostHandler>,
+    /// The proxy this workload's outgoing HTTP requests are sent through, overriding the handler's
+    egress_proxy: Option<Arc<EgressProxy>>,
+    /// The log outbound connections are recorded in
+    egress_log: Arc<EgressLog>,
     /// An optional service component that runs once to completion or for the duration of the workload
     service: Option<WorkloadService>,
     /// The requested host [`WitInterface`]s to resolve this workload
     host_interfaces: Vec<WitInterface>,
+    /// Whether incoming requests to this workload are currently rejected, shared across clones
+    routing_paused: Arc<AtomicBool>,
+    /// Whether an operator paused the workload, shared across clones
+    paused: Arc<watch::Sender<bool>>,
+    /// The annotations the workload was started with, e.g. for label selectors
+    annotations: Arc<HashMap<String, String>>,
+    /// The resource usage the workload's stores report to
+    usage: Arc<WorkloadUsage>,
+    /// The cgroup the workload's guest code executes in, if it has a CPU limit or pinning
+    cgroup: Option<Arc<WorkloadCgroup>>,
+    /// The scratch volumes each instance mounts
+    scratch_volumes: Arc<Vec<(VolumeMount, ScratchVolume)>>,
+    /// Instances of components that reuse them, left idle by earlier invocations
+    idle_instances: Arc<IdleInstances>,
+    /// Instances of components that run calls concurrently
+    #[cfg(feature = "wasip3")]
+    shared_instances: Arc<SharedInstances>,
+    /// The bandwidth the workload's transfers share
+    bandwidth: Arc<WorkloadBandwidth>,
 }
 
 impl ResolvedWorkload {
@@ -406,6 +813,14 @@ impl ResolvedWorkload {
             .map(|s| (s.pre_instantiate(), s.max_restarts));
 
         if let Some((Ok(pre), mut max_restarts)) = service {
+            if self.service.as_ref().is_some_and(|s| s.command.is_some()) {
+                let handle = self.spawn(self.clone().run_command(pre, max_restarts));
+                if let Some(s) = self.service.as_mut() {
+                    s.handle = Some(Arc::new(handle));
+                }
+                return Ok(true);
+            }
+
             // This will always be present since we just checked above, but we need this structure
             // to only borrow the service metadata
             let mut store = if let Some(service) = self.service.as_ref() {
@@ -414,10 +829,11 @@ impl ResolvedWorkload {
                 bail!("service unexpectedly missing during execution");
             };
             let instance = pre.instantiate_async(&mut store).await?;
-            let handle = tokio::spawn(async move {
+            let handle = self.spawn(async move {
                 loop {
                     if let Err(e) = instance.wasi_cli_run().call_run(&mut store).await {
                         warn!(err = %e, retries = max_restarts, "service execution failed");
+                        store.data().record_error(&e);
                         if max_restarts == 0 {
                             info!("max restarts reached, service will not be restarted");
                             break;
@@ -440,6 +856,317 @@ impl ResolvedWorkload {
         }
     }
 
+    /// Runs a command service to completion, restarting it up to `max_restarts` times
+    /// while it fails, and records the result of its last run.
+    async fn run_command(self, pre: CommandPre<Ctx>, mut max_restarts: u64) {
+        let Some(service) = &self.service else {
+            return;
+        };
+        let Some(command) = &service.command else {
+            return;
+        };
+        if let Some(cron_job) = &command.cron_job {
+            let job = command.job.clone().unwrap_or_default();
+            return self.run_cron_job(pre, job, cron_job.clone()).await;
+        }
+        if let Some(job) = command.job.clone() {
+            let result = self
+                .run_job(pre, job, |f| {
+                    f(&mut service.job_status.lock().unwrap_or_else(|e| e.into_inner()))
+                })
+                .await;
+            let _ = service.command_result.set(result);
+            return;
+        }
+        loop {
+            let result = self.run_command_once(&pre).await;
+            if result.exit_code != 0 && max_restarts > 0 {
+                warn!(
+                    exit_code = result.exit_code,
+                    retries = max_restarts,
+                    "command failed, restarting"
+                );
+                max_restarts -= 1;
+                continue;
+            }
+            info!(
+                workload_id = self.id.as_ref(),
+                exit_code = result.exit_code,
+                "command ran to completion"
+            );
+            let _ = service.command_result.set(result);
+            break;
+        }
+    }
+
+    /// Runs a command service as a [`Job`], applying changes to its [`JobStatus`] through
+    /// `update`, and returns the result of its last run.
+    async fn run_job(
+        &self,
+        pre: CommandPre<Ctx>,
+        job: Job,
+        update: impl Fn(&dyn Fn(&mut JobStatus)),
+    ) -> CommandResult {
+        let run = || {
+            let workload = self.clone();
+            let pre = pre.clone();
+            async move { workload.run_command_once(&pre).await }
+        };
+        drive_job(&self.id, job, update, run).await
+    }
+
+    /// Starts jobs of a command service on the schedule of its [`CronJob`], keeping its
+    /// [`CronJobStatus`] up to date until the workload is stopped.
+    async fn run_cron_job(&self, pre: CommandPre<Ctx>, job: Job, cron_job: CronJob) {
+        let Some(service) = &self.service else {
+            return;
+        };
+        let schedule: CronSchedule = match cron_job.schedule.parse() {
+            Ok(schedule) => schedule,
+            Err(e) => {
+                warn!(workload_id = self.id.as_ref(), err = ?e, "invalid cron job schedule");
+                return;
+            }
+        };
+        let status = &service.cron_job_status;
+        let lock = || status.lock().unwrap_or_else(|e| e.into_inner());
+        lock().last_schedule_time = cron_job.last_schedule_time;
+
+        // Dropping the set when the workload stops aborts the active jobs
+        let mut jobs = tokio::task::JoinSet::new();
+        let mut after = Utc::now();
+        // The most recent job missed while no instance of the workload was running
+        let mut due = cron_job
+            .last_schedule_time
+            .and_then(|last| schedule.last_between(last, after));
+        loop {
+            if let Some(scheduled_at) = due.take() {
+                while jobs.try_join_next().is_some() {}
+                lock().last_schedule_time = Some(scheduled_at);
+
+                let late = (Utc::now() - scheduled_at).to_std().unwrap_or_default();
+                let active = !lock().active.is_empty();
+                if cron_job
+                    .starting_deadline
+                    .is_some_and(|deadline| late > deadline)
+                {
+                    info!(
+                        workload_id = self.id.as_ref(),
+                        %scheduled_at,
+                        "skipping job that is past its starting deadline"
+                    );
+                } else if self.is_paused() {
+                    info!(
+                        workload_id = self.id.as_ref(),
+                        %scheduled_at,
+                        "skipping job while the workload is paused"
+                    );
+                } else if active && cron_job.concurrency_policy == ConcurrencyPolicy::Forbid {
+                    info!(
+                        workload_id = self.id.as_ref(),
+                        %scheduled_at,
+                        "skipping job while an earlier one is active"
+                    );
+                } else {
+                    if active && cron_job.concurrency_policy == ConcurrencyPolicy::Replace {
+                        let mut current = lock();
+                        for mut run in std::mem::take(&mut current.active) {
+                            run.finished_at = Some(Utc::now());
+                            run.status.state = JobState::Failed;
+                            run.status.active = 0;
+                            run.status.message = "replaced by a newer job".to_string();
+                            record_cron_job_run(&mut current, run, &cron_job);
+                        }
+                        jobs.abort_all();
+                    }
+                    lock().active.push(CronJobRun {
+                        scheduled_at,
+                        finished_at: None,
+                        status: JobStatus::default(),
+                        result: None,
+                    });
+                    jobs.spawn(self.clone().run_scheduled_job(
+                        pre.clone(),
+                        job.clone(),
+                        cron_job.clone(),
+                        scheduled_at,
+                    ));
+                }
+            }
+
+            let Some(next) = schedule.next_after(after) else {
+                warn!(
+                    workload_id = self.id.as_ref(),
+                    "cron job schedule has no upcoming runs"
+                );
+                break;
+            };
+            tokio::time::sleep((next - Utc::now()).to_std().unwrap_or_default()).await;
+            after = next;
+            due = Some(next);
+        }
+        while jobs.join_next().await.is_some() {}
+    }
+
+    /// Runs a job started by a [`CronJob`] for `scheduled_at`, then moves it to the cron
+    /// job's history unless it was replaced in the meantime.
+    async fn run_scheduled_job(
+        self,
+        pre: CommandPre<Ctx>,
+        job: Job,
+        cron_job: CronJob,
+        scheduled_at: DateTime<Utc>,
+    ) {
+        let Some(service) = &self.service else {
+            return;
+        };
+        let lock = || {
+            service
+                .cron_job_status
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+        };
+        let result = self
+            .run_job(pre, job, |f| {
+                let mut status = lock();
+                if let Some(run) = status
+                    .active
+                    .iter_mut()
+                    .find(|run| run.scheduled_at == scheduled_at)
+                {
+                    f(&mut run.status);
+                }
+            })
+            .await;
+
+        let mut status = lock();
+        let Some(index) = status
+            .active
+            .iter()
+            .position(|run| run.scheduled_at == scheduled_at)
+        else {
+            return;
+        };
+        let mut run = status.active.remove(index);
+        run.finished_at = Some(Utc::now());
+        run.result = Some(result);
+        if run.status.state == JobState::Succeeded {
+            status.last_successful_time = run.finished_at;
+        }
+        record_cron_job_run(&mut status, run, &cron_job);
+    }
+
+    /// Runs a command service once with captured stdio and returns its result.
+    async fn run_command_once(&self, pre: &CommandPre<Ctx>) -> CommandResult {
+        let Some(service) = &self.service else {
+            return CommandResult::default();
+        };
+        let stdin = service
+            .command
+            .as_ref()
+            .map(|command| command.stdin.clone())
+            .unwrap_or_default();
+        let stdout = MemoryOutputPipe::new(MAX_COMMAND_OUTPUT);
+        let stderr = MemoryOutputPipe::new(MAX_COMMAND_OUTPUT);
+        let run = async {
+            let mut store = self
+                .new_store_with(&service.metadata, |wasi| {
+                    wasi.stdin(MemoryInputPipe::new(stdin))
+                        .stdout(stdout.clone())
+                        .stderr(stderr.clone());
+                })
+                .await?;
+            let instance = pre.instantiate_async(&mut store).await?;
+            anyhow::Ok(instance.wasi_cli_run().call_run(&mut store).await)
+        };
+        let (exit_code, error) = match run.await.and_then(|result| result) {
+            Ok(Ok(())) => (0, None),
+            Ok(Err(())) => (1, None),
+            Err(e) => match e.downcast_ref::<I32Exit>() {
+                Some(I32Exit(code)) => (*code, None),
+                None => (1, Some(e)),
+            },
+        };
+
+        let mut stderr = stderr.contents().to_vec();
+        if let Some(e) = error {
+            warn!(workload_id = self.id.as_ref(), err = ?e, "command trapped");
+            stderr.extend_from_slice(format!("error: {e:#}\n").as_bytes());
+        }
+        CommandResult {
+            exit_code,
+            stdout: stdout.contents(),
+            stderr: stderr.into(),
+        }
+    }
+
+    /// Returns the result of the workload's command, once it has run to completion.
+    pub fn command_result(&self) -> Option<&CommandResult> {
+        self.service.as_ref()?.command_result.get()
+    }
+
+    /// Returns the progress of the workload's [`Job`], if its command runs as one.
+    pub fn job_status(&self) -> Option<JobStatus> {
+        let service = self.service.as_ref()?;
+        let command = service.command.as_ref()?;
+        if command.job.is_none() || command.cron_job.is_some() {
+            return None;
+        }
+        Some(
+            service
+                .job_status
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+
+    /// Returns the progress of the workload's [`CronJob`], if its command has one.
+    pub fn cron_job_status(&self) -> Option<CronJobStatus> {
+        let service = self.service.as_ref()?;
+        service.command.as_ref()?.cron_job.as_ref()?;
+        Some(
+            service
+                .cron_job_status
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        )
+    }
+
+    /// Spawns a task executing the workload's guest code, on the threads in its cgroup if
+    /// it has one, so they don't have to move between cgroups, see
+    /// [`crate::host::cgroups`].
+    pub(crate) fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.cgroup {
+            Some(cgroup) => cgroup.spawn(future),
+            None => tokio::spawn(future),
+        }
+    }
+
+    /// Executes the workload's guest code in `future` like [`Self::spawn`] and waits for it,
+    /// aborting it if the returned future is dropped.
+    ///
+    /// # Errors
+    /// Returns an error if the task executing `future` panics or is aborted, e.g. because
+    /// the workload was stopped.
+    pub(crate) async fn execute<F>(&self, future: F) -> anyhow::Result<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.cgroup {
+            Some(cgroup) => AbortOnDropHandle::new(cgroup.spawn(future))
+                .await
+                .context("workload task failed"),
+            None => Ok(future.await),
+        }
+    }
+
     /// Aborts the running service [`JoinHandle`] if it exists.
     pub(crate) fn stop_service(&self) {
         if let Some(service) = &self.service
@@ -457,10 +1184,608 @@ impl ResolvedWorkload {
         self.components.clone()
     }
 
+    /// Returns the directories the workload's service and components mount, with their
+    /// mounts. Scratch volumes aren't included, as each instance mounts new directories.
+    pub async fn volume_mounts(&self) -> Vec<(PathBuf, VolumeMount)> {
+        let mut volume_mounts: Vec<_> = self
+            .service
+            .iter()
+            .flat_map(|service| service.volume_mounts().iter().cloned())
+            .collect();
+        for component in self.components.read().await.values() {
+            volume_mounts.extend(component.volume_mounts().iter().cloned());
+        }
+        volume_mounts
+    }
+
+    /// Sets the instance pool size of one of the workload's components, or of all of them,
+    /// without redeploying the workload.
+    ///
+    /// Only components that reuse their instances keep up to this many idle for HTTP
+    /// requests, see [`crate::engine::instances`]; for others each invocation gets an
+    /// instance of its own, so the size is only recorded. Shrinking never interrupts live
+    /// instances, which drain by finishing their invocation, and idle instances beyond the
+    /// new size aren't left idle again once they've handled a request.
+    ///
+    /// # Arguments
+    /// * `component_id` - The component to scale, or `None` for every component
+    /// * `pool_size` - The number of warm instances to keep
+    ///
+    /// # Returns
+    /// The IDs of the scaled components and their previous pool sizes.
+    ///
+    /// # Errors
+    /// Returns an error if the workload has no component with `component_id`, caused by
+    /// [`HostError::InvalidRequest`].
+    pub async fn scale(
+        &self,
+        component_id: Option<&str>,
+        pool_size: usize,
+    ) -> anyhow::Result<Vec<(String, usize)>> {
+        let mut components = self.components.write().await;
+        let mut previous: Vec<(String, usize)> = components
+            .iter_mut()
+            .filter_map(|(id, component)| {
+                if component_id.is_some_and(|wanted| wanted != &**id) {
+                    return None;
+                }
+                let previous = std::mem::replace(&mut component.pool_size, pool_size);
+                Some((id.to_string(), previous))
+            })
+            .collect();
+        if let Some(component_id) = component_id
+            && previous.is_empty()
+        {
+            return Err(
+                anyhow::Error::new(HostError::InvalidRequest).context(format!(
+                    "workload {} has no component '{component_id}'",
+                    self.id
+                )),
+            );
+        }
+        previous.sort();
+        Ok(previous)
+    }
+
+    /// Changes the memory limit, CPU limit, allowed hosts, config or environment of one of
+    /// the workload's components, or of all of them, without redeploying the workload.
+    ///
+    /// The changes apply to instances created afterwards. Live instances keep their old
+    /// resources until their invocation returns, and the idle instances of updated
+    /// components that [reuse](crate::engine::instances) them are dropped, as are those
+    /// handling a request at the time once it returns; the workload's service keeps running
+    /// with the resources it was started with. The plugins bound to updated components are
+    /// told with [`HostPlugin::on_component_update`], so config they serve, such as
+    /// `wasi:config/store`, changes too. A CPU limit is enforced by the workload's cgroup,
+    /// so on workloads started without one it's only recorded, and the cgroup's CPU and
+    /// NUMA pinning keep the lists the workload started with. A changed bandwidth limit
+    /// applies to the workload's transfers at once, see [`crate::host::bandwidth`].
+    ///
+    /// Values patched in aren't registered for [redaction](crate::redact), so secrets should
+    /// still be delivered by starting the workload with them.
+    ///
+    /// # Arguments
+    /// * `component_id` - The component to update, or `None` for every component
+    /// * `update` - The changes to make
+    ///
+    /// # Returns
+    /// The IDs of the updated components.
+    ///
+    /// # Errors
+    /// Returns an error if the workload has no component with `component_id` or the new
+    /// resources are invalid, caused by [`HostError::InvalidRequest`], or if the cgroup's
+    /// CPU limit can't be changed. Nothing is changed then.
+    pub async fn update_resources(
+        &self,
+        component_id: Option<&str>,
+        update: &LocalResourcesUpdate,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut components = self.components.write().await;
+        let resources: BTreeMap<String, LocalResources> = components
+            .iter()
+            .filter(|(id, _)| component_id.is_none_or(|wanted| wanted == &***id))
+            .map(|(id, component)| {
+                let mut resources = component.metadata.local_resources.clone();
+                update.apply(&mut resources);
+                (id.to_string(), resources)
+            })
+            .collect();
+        if let Some(component_id) = component_id
+            && resources.is_empty()
+        {
+            return Err(
+                anyhow::Error::new(HostError::InvalidRequest).context(format!(
+                    "workload {} has no component '{component_id}'",
+                    self.id
+                )),
+            );
+        }
+        for (id, new) in &resources {
+            check_resources(new)
+                .context(HostError::InvalidRequest)
+                .with_context(|| format!("invalid resources for component '{id}'"))?;
+        }
+
+        let bandwidth_updated = update.config.contains_key(BANDWIDTH_LIMIT_CONFIG);
+        if update.cpu_limit.is_some() || bandwidth_updated {
+            let workload_resources: Vec<&LocalResources> = components
+                .iter()
+                .map(|(id, component)| {
+                    resources
+                        .get(id.as_ref())
+                        .unwrap_or(&component.metadata.local_resources)
+                })
+                .chain(
+                    self.service
+                        .iter()
+                        .map(|service| &service.metadata.local_resources),
+                )
+                .collect();
+            if update.cpu_limit.is_some() {
+                match &self.cgroup {
+                    Some(cgroup) => cgroup.update_cpu_limit(workload_resources.iter().copied())?,
+                    None => debug!(
+                        workload_id = self.id.as_ref(),
+                        "workload has no cgroup, its CPU limit isn't enforced"
+                    ),
+                }
+            }
+            if bandwidth_updated {
+                self.bandwidth
+                    .set_rate(bandwidth::workload_limit(workload_resources)?);
+            }
+        }
+
+        let mut updated = Vec::with_capacity(resources.len());
+        for (id, new) in resources {
+            if let Some(component) = components.get_mut(id.as_str()) {
+                component.metadata.local_resources = new;
+                updated.push(component.clone());
+            }
+        }
+        drop(components);
+        // Reused instances were created with the old resources
+        for component in &updated {
+            self.idle_instances.clear(component.id());
+            #[cfg(feature = "wasip3")]
+            self.shared_instances.clear(component.id()).await;
+        }
+
+        for component in &updated {
+            for plugin in component.plugins().iter().flat_map(HashMap::values) {
+                if let Err(e) = plugin.on_component_update(component).await {
+                    warn!(
+                        workload_id = self.id.as_ref(),
+                        component_id = component.id(),
+                        plugin_id = plugin.id(),
+                        err = ?e,
+                        "plugin failed to apply updated component resources"
+                    );
+                }
+            }
+        }
+        Ok(updated
+            .iter()
+            .map(|component| component.id().to_string())
+            .collect())
+    }
+
     pub fn host_interfaces(&self) -> &Vec<WitInterface> {
         &self.host_interfaces
     }
 
+    /// Returns the name this workload's service is published under, if any.
+    pub fn service_name(&self) -> Option<&str> {
+        self.service.as_ref().and_then(|s| s.name.as_deref())
+    }
+
+    /// Returns the interfaces this workload publishes through its service.
+    pub fn service_exports(&self) -> &[WitInterface] {
+        self.service.as_ref().map_or(&[], |s| s.exports.as_slice())
+    }
+
+    /// Calls a function exported by one of this workload's components from outside the
+    /// workload, e.g. on behalf of another workload importing this workload's service.
+    /// Each call gets a fresh store and instance.
+    ///
+    /// # Arguments
+    /// * `interface` - The exported interface, e.g. `acme:inventory/query@0.1.0`
+    /// * `function` - The name of the function within the interface
+    /// * `params` - The function parameters
+    /// * `results` - Where to write the function results
+    ///
+    /// # Errors
+    /// Returns an error if no component exports the function or the call fails.
+    pub async fn call_export(
+        &self,
+        interface: &str,
+        function: &str,
+        params: &[Val],
+        results: &mut [Val],
+    ) -> anyhow::Result<()> {
+        let wanted = WitInterface::from(interface);
+        let (component_id, export_name) = self
+            .components
+            .read()
+            .await
+            .values()
+            .find_map(|c| {
+                c.component_exports()
+                    .ok()?
+                    .into_iter()
+                    .find(|(name, _)| {
+                        name == interface || WitInterface::from(name.as_str()).contains(&wanted)
+                    })
+                    .map(|(name, _)| (c.id().to_string(), name))
+            })
+            .with_context(|| {
+                format!("no component in workload {} exports '{interface}'", self.id)
+            })?;
+        if self.is_paused() {
+            return Err(anyhow::Error::new(HostError::WorkloadNotRunning {
+                workload_id: self.id.to_string(),
+            })
+            .context(format!("workload {} is paused", self.id)));
+        }
+
+        // The call may execute on the workload's threads, so it owns its arguments and results
+        let workload = self.clone();
+        let function = function.to_string();
+        let params = params.to_vec();
+        let mut call_results = results.to_vec();
+        let call_results = self
+            .execute(async move {
+                #[cfg(feature = "wasip3")]
+                if let Some(shared) = workload.shared_instance(&component_id).await? {
+                    return shared
+                        .call(&export_name, &function, params, call_results)
+                        .await
+                        .with_context(|| format!("failed to call '{export_name}#{function}'"));
+                }
+                let pre = workload.instantiate_pre(&component_id).await?;
+                let mut store = workload.new_store(&component_id).await?;
+                let instance = pre
+                    .instantiate_async(&mut store)
+                    .await
+                    .context("failed to instantiate component")?;
+                store.data_mut().instance_ready();
+                store
+                    .data_mut()
+                    .describe_invocation(|| format!("{export_name}#{function}"));
+                let func = instance
+                    .get_export_index(&mut store, None, &export_name)
+                    .and_then(|idx| instance.get_export_index(&mut store, Some(&idx), &function))
+                    .and_then(|idx| instance.get_func(&mut store, idx))
+                    .with_context(|| {
+                        format!("function '{function}' not found in '{export_name}'")
+                    })?;
+
+                if let Err(e) = func
+                    .call_async(&mut store, &params, &mut call_results)
+                    .await
+                {
+                    store.data().record_error(&e);
+                    return Err(e)
+                        .with_context(|| format!("failed to call '{export_name}#{function}'"));
+                }
+                func.post_return_async(&mut store)
+                    .await
+                    .context("failed to execute post-return")?;
+                slow_invocations::log_if_slow(&store);
+                anyhow::Ok(call_results)
+            })
+            .await??;
+        results.clone_from_slice(&call_results);
+        Ok(())
+    }
+
+    /// Lists the functions exported by this workload's components.
+    pub async fn exported_functions(&self) -> Vec<ExportedFunction> {
+        self.components
+            .read()
+            .await
+            .values()
+            .flat_map(|c| c.exported_functions())
+            .collect()
+    }
+
+    /// Calls an exported function like [`Self::call_export`], converting the arguments
+    /// and results with [`crate::engine::json`].
+    ///
+    /// # Errors
+    /// Returns an error if the function isn't exported, the arguments don't match its
+    /// parameter types, or the call fails.
+    pub async fn call_export_json(
+        &self,
+        interface: &str,
+        function: &str,
+        args: &[serde_json::Value],
+    ) -> anyhow::Result<Vec<serde_json::Value>> {
+        let export = self
+            .exported_function(interface, function, args.len())
+            .await?;
+        let params = export
+            .params
+            .iter()
+            .zip(args)
+            .map(|((name, ty), arg)| {
+                json::val_from_json(ty, arg)
+                    .context(HostError::InvalidRequest)
+                    .with_context(|| format!("invalid argument '{name}'"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut results = vec![Val::Bool(false); export.results.len()];
+        self.call_export(&export.interface, function, &params, &mut results)
+            .await?;
+        results.iter().map(json::val_to_json).collect()
+    }
+
+    /// Calls an exported function like [`Self::call_export_json`], with arguments and
+    /// results written in WAVE (see [`crate::engine::wave`]).
+    ///
+    /// # Errors
+    /// Returns an error if the function isn't exported, the arguments don't parse as its
+    /// parameter types, or the call fails.
+    pub async fn invoke_wave(
+        &self,
+        interface: &str,
+        function: &str,
+        args: &[String],
+    ) -> anyhow::Result<Vec<String>> {
+        let export = self
+            .exported_function(interface, function, args.len())
+            .await?;
+        let params = export
+            .params
+            .iter()
+            .zip(args)
+            .map(|((name, ty), arg)| {
+                wave::val_from_wave(ty, arg)
+                    .context(HostError::InvalidRequest)
+                    .with_context(|| format!("invalid argument '{name}'"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mut results = vec![Val::Bool(false); export.results.len()];
+        self.call_export(&export.interface, function, &params, &mut results)
+            .await?;
+        results.iter().map(wave::val_to_wave).collect()
+    }
+
+    /// Calls an exported function like [`Self::call_export`], returning as many results
+    /// as the function has.
+    ///
+    /// # Errors
+    /// Returns an error if the function isn't exported, the arguments don't match its
+    /// parameter types, or the call fails.
+    pub async fn invoke(
+        &self,
+        interface: &str,
+        function: &str,
+        params: &[Val],
+    ) -> anyhow::Result<Vec<Val>> {
+        let export = self
+            .exported_function(interface, function, params.len())
+            .await?;
+        let mut results = vec![Val::Bool(false); export.results.len()];
+        self.call_export(&export.interface, function, params, &mut results)
+            .await?;
+        Ok(results)
+    }
+
+    /// Returns the exported function with the given name in an interface, checking that it
+    /// takes `arity` arguments.
+    async fn exported_function(
+        &self,
+        interface: &str,
+        function: &str,
+        arity: usize,
+    ) -> anyhow::Result<ExportedFunction> {
+        let wanted = WitInterface::from(interface);
+        let Some(export) = self.exported_functions().await.into_iter().find(|f| {
+            f.name == function
+                && (f.interface == interface
+                    || WitInterface::from(f.interface.as_str()).contains(&wanted))
+        }) else {
+            bail!(
+                "no component in workload {} exports '{interface}#{function}'",
+                self.id
+            );
+        };
+        if arity != export.params.len() {
+            bail!(
+                "'{interface}#{function}' takes {} arguments, got {arity}",
+                export.params.len(),
+            );
+        }
+        Ok(export)
+    }
+
+    /// Links imports that reference a published service (see [`crate::host::services`])
+    /// to functions that forward each call to an instance of that service. With a
+    /// [`WrpcTransport`], services with no instances on this host are called on other
+    /// hosts.
+    async fn link_service_references(
+        &mut self,
+        registry: &Arc<ServiceRegistry>,
+        wrpc: Option<&Arc<WrpcTransport>>,
+    ) -> anyhow::Result<()> {
+        let mut references = Vec::new();
+        for interface in &self.host_interfaces {
+            let Some(service) = service_reference(interface) else {
+                continue;
+            };
+            let Some(exports) = registry.exports(&self.namespace, service).await else {
+                if wrpc.is_some() {
+                    // The remote host checks what the service publishes on each call
+                    debug!(
+                        service,
+                        interface = %interface,
+                        "service has no local instances, linking it to remote hosts"
+                    );
+                    references.push((interface.clone(), Arc::<str>::from(service)));
+                    continue;
+                }
+                bail!(
+                    "service '{service}' referenced by '{interface}' has no running instances in namespace '{}'",
+                    self.namespace
+                );
+            };
+            if !exports.iter().any(|e| e.contains(interface)) {
+                bail!(
+                    "service '{service}' in namespace '{}' does not publish '{interface}'",
+                    self.namespace
+                );
+            }
+            references.push((interface.clone(), Arc::<str>::from(service)));
+        }
+        if references.is_empty() {
+            return Ok(());
+        }
+
+        let mut components = self.components.write().await;
+        let metadata = components
+            .values_mut()
+            .map(|c| &mut c.metadata)
+            .chain(self.service.as_mut().map(|s| &mut s.metadata));
+        for metadata in metadata {
+            let component = metadata.component.clone();
+            let ty = component.component_type();
+            for (import_name, import_item) in ty.imports(component.engine()) {
+                let ComponentItem::ComponentInstance(instance_ty) = import_item else {
+                    continue;
+                };
+                let import = WitInterface::from(import_name);
+                let Some((_, service)) = references.iter().find(|(r, _)| r.contains(&import))
+                else {
+                    continue;
+                };
+                trace!(
+                    name = import_name,
+                    service = %service,
+                    "linking import to service"
+                );
+
+                let mut linker_instance = metadata
+                    .linker
+                    .instance(import_name)
+                    .with_context(|| format!("failed to link '{import_name}' to service"))?;
+                for (func_name, item) in instance_ty.exports(component.engine()) {
+                    let ComponentItem::ComponentFunc(func_ty) = item else {
+                        trace!(
+                            name = import_name,
+                            item = func_name,
+                            "skipping non-function service import"
+                        );
+                        continue;
+                    };
+                    let registry = registry.clone();
+                    let wrpc = wrpc.cloned();
+                    let namespace = self.namespace.clone();
+                    let service = service.clone();
+                    let import_name: Arc<str> = import_name.into();
+                    let func_name: Arc<str> = func_name.into();
+                    let param_types: Arc<[Type]> = func_ty.params().map(|(_, ty)| ty).collect();
+                    let result_types: Arc<[Type]> = func_ty.results().collect();
+                    let context = format!("failed to define service import {import_name}");
+                    linker_instance
+                        .func_new_async(&func_name.clone(), move |_store, params, results| {
+                            let registry = registry.clone();
+                            let wrpc = wrpc.clone();
+                            let namespace = namespace.clone();
+                            let service = service.clone();
+                            let import_name = import_name.clone();
+                            let func_name = func_name.clone();
+                            let param_types = param_types.clone();
+                            let result_types = result_types.clone();
+                            Box::new(async move {
+                                if let Some(target) = registry.pick(&namespace, &service).await {
+                                    return target
+                                        .call_export(&import_name, &func_name, params, results)
+                                        .await;
+                                }
+                                let Some(wrpc) = wrpc else {
+                                    bail!("service '{service}' has no running instances");
+                                };
+                                let values = wrpc
+                                    .invoke(
+                                        &namespace,
+                                        &service,
+                                        &import_name,
+                                        &func_name,
+                                        params,
+                                        &param_types,
+                                        &result_types,
+                                    )
+                                    .await?;
+                                for (result, value) in results.iter_mut().zip(values) {
+                                    *result = value;
+                                }
+                                Ok(())
+                            })
+                        })
+                        .context(context)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensures every component's imports are satisfied by its linker, so that a missing
+    /// implementation fails the workload start instead of the first invocation.
+    ///
+    /// # Arguments
+    /// * `considered_plugins` - IDs of the plugins that were considered when binding
+    ///
+    /// # Errors
+    /// Returns an [`UnresolvedInterfacesError`] naming each import that nothing on
+    /// the host provides.
+    async fn ensure_imports_linked(&self, considered_plugins: Vec<String>) -> anyhow::Result<()> {
+        let components = self.components.read().await;
+        let mut workload_exports = HashSet::new();
+        for component in components.values() {
+            workload_exports.extend(
+                component
+                    .component_exports()?
+                    .into_iter()
+                    .map(|(name, _)| name),
+            );
+        }
+
+        let mut missing = Vec::new();
+        let all_metadata = components
+            .values()
+            .map(|c| &c.metadata)
+            .chain(self.service.as_ref().map(|s| &s.metadata));
+        for metadata in all_metadata {
+            let Err(e) = metadata.linker.instantiate_pre(&metadata.component) else {
+                continue;
+            };
+            let unlinked = metadata.unlinked_imports(&workload_exports);
+            if unlinked.is_empty() {
+                // Not something we can attribute to a missing interface
+                return Err(e).with_context(|| {
+                    format!("failed to pre-instantiate component '{}'", metadata.id())
+                });
+            }
+            missing.extend(unlinked.into_iter().map(|interface| MissingInterface {
+                component_id: metadata.id().to_string(),
+                interface,
+                requested: false,
+                offered: Vec::new(),
+            }));
+        }
+
+        if !missing.is_empty() {
+            bail!(UnresolvedInterfacesError {
+                workload_id: self.id.to_string(),
+                missing,
+                considered_plugins,
+            });
+        }
+        Ok(())
+    }
+
     async fn link_components(&mut self) -> anyhow::Result<()> {
         // A map from component ID to its exported interfaces
         let mut interface_map: HashMap<String, Arc<str>> = HashMap::new();
@@ -570,8 +1895,7 @@ impl ResolvedWorkload {
                     let mut all_components = self.components.write().await;
                     let (plugin_component, instance_idx) = {
                         let Some(exporter_component) = interface_map.get(import_name) else {
-                            // TODO: error because unsatisfied import, if there's no available
-                            // export then it's an unresolvable workload
+                            // Imports that nothing provides are reported by `ensure_imports_linked`
                             trace!(
                                 name = import_name,
                                 "import not found in component exports, skipping"
@@ -837,12 +2161,180 @@ impl ResolvedWorkload {
         &self.namespace
     }
 
+    /// Gets the annotations the workload was started with
+    pub fn annotations(&self) -> &HashMap<String, String> {
+        &self.annotations
+    }
+
     /// Returns the number of components in this workload.
     /// Does not include the service component if one is defined.
     pub async fn component_count(&self) -> usize {
         self.components.read().await.len()
     }
 
+    /// Returns the IDs of all plugins bound to this workload's components and service.
+    pub async fn plugin_ids(&self) -> HashSet<&'static str> {
+        let mut ids: HashSet<&'static str> = self
+            .components
+            .read()
+            .await
+            .values()
+            .filter_map(|component| component.plugins().as_ref())
+            .flat_map(|plugins| plugins.keys().copied())
+            .collect();
+        if let Some(plugins) = self.service.as_ref().and_then(|s| s.plugins().as_ref()) {
+            ids.extend(plugins.keys().copied());
+        }
+        ids
+    }
+
+    /// Pauses or resumes routing of incoming requests to this workload. While paused,
+    /// the HTTP server rejects requests for the workload with `503 Service Unavailable`.
+    ///
+    /// # Returns
+    /// Whether routing was paused before.
+    pub fn set_routing_paused(&self, paused: bool) -> bool {
+        self.routing_paused.swap(paused, Ordering::Relaxed)
+    }
+
+    /// Returns whether routing of incoming requests to this workload is paused, either
+    /// with [`Self::set_routing_paused`] or because the workload is [paused](Self::pause).
+    pub fn is_routing_paused(&self) -> bool {
+        self.routing_paused.load(Ordering::Relaxed) || self.is_paused()
+    }
+
+    /// Pauses the workload, e.g. to suspend a misbehaving tenant during an incident. While
+    /// paused, incoming HTTP requests are rejected as with [`Self::set_routing_paused`],
+    /// `wasmcloud:messaging` messages and queue invokers wait for the workload to resume,
+    /// scheduled jobs are skipped and [`Self::call_export`] fails. Invocations already
+    /// running and the workload's service aren't interrupted, and its components, plugin
+    /// bindings and state are kept, so it resumes warm.
+    ///
+    /// # Returns
+    /// Whether the workload was paused before.
+    pub fn pause(&self) -> bool {
+        self.paused.send_replace(true)
+    }
+
+    /// Resumes a workload paused with [`Self::pause`], letting waiting messages through.
+    ///
+    /// # Returns
+    /// Whether the workload was paused before.
+    pub fn resume(&self) -> bool {
+        self.paused.send_replace(false)
+    }
+
+    /// Returns whether the workload is paused with [`Self::pause`].
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Waits until the workload isn't paused, returning at once if it isn't.
+    pub async fn resumed(&self) {
+        let mut paused = self.paused.subscribe();
+        // The sender lives as long as the workload, so this only fails once it's gone
+        let _ = paused.wait_for(|paused| !paused).await;
+    }
+
+    /// Returns the generation of a component's idle instances, to pass to
+    /// [`Self::keep_idle_instance`] for an instance taken or created after reading it, so
+    /// it isn't left idle if the component is updated in the meantime.
+    pub(crate) fn idle_generation(&self, component_id: &str) -> u64 {
+        self.idle_instances.generation(component_id)
+    }
+
+    /// Takes an idle instance of a component to handle an invocation, if the component
+    /// reuses its instances and has one, see [`crate::engine::instances`].
+    pub(crate) async fn take_idle_instance(
+        &self,
+        component_id: &str,
+    ) -> Option<(wasmtime::Store<Ctx>, wasmtime::component::Instance)> {
+        let reuse = self
+            .components
+            .read()
+            .await
+            .get(component_id)
+            .is_some_and(|component| {
+                matches!(
+                    instances::strategy(&component.metadata.local_resources),
+                    Ok(InstanceReuse::Reuse)
+                )
+            });
+        if !reuse {
+            self.idle_instances.clear(component_id);
+            return None;
+        }
+        let (mut store, instance) = self.idle_instances.take(component_id)?;
+        store.data_mut().reuse();
+        Some((store, instance))
+    }
+
+    /// Returns the instance a component shares between calls, creating it for the first
+    /// call, if the component runs calls concurrently, see [`crate::engine::instances`].
+    ///
+    /// # Errors
+    /// Returns an error if the component can't be instantiated.
+    #[cfg(feature = "wasip3")]
+    async fn shared_instance(&self, component_id: &str) -> anyhow::Result<Option<SharedInstance>> {
+        let concurrent = self
+            .components
+            .read()
+            .await
+            .get(component_id)
+            .is_some_and(|component| {
+                matches!(
+                    instances::strategy(&component.metadata.local_resources),
+                    Ok(InstanceReuse::Concurrent)
+                )
+            });
+        if !concurrent {
+            self.shared_instances.clear(component_id).await;
+            return Ok(None);
+        }
+        let start = async {
+            let pre = self.instantiate_pre(component_id).await?;
+            let mut store = self.new_store(component_id).await?;
+            let instance = pre
+                .instantiate_async(&mut store)
+                .await
+                .context("failed to instantiate component")?;
+            store.data_mut().instance_ready();
+            let (shared, run) = SharedInstance::new(store, instance);
+            self.spawn(run);
+            Ok(shared)
+        };
+        self.shared_instances
+            .get_or_start(component_id, start)
+            .await
+            .map(Some)
+    }
+
+    /// Leaves an instance idle for a later invocation once it has handled one, if its
+    /// component reuses instances, doesn't have its pool size of idle ones and wasn't
+    /// updated since `generation` was read with [`Self::idle_generation`]. Otherwise the
+    /// instance is dropped.
+    pub(crate) async fn keep_idle_instance(
+        &self,
+        component_id: &str,
+        generation: u64,
+        store: wasmtime::Store<Ctx>,
+        instance: wasmtime::component::Instance,
+    ) {
+        let max_idle = match self.components.read().await.get(component_id) {
+            Some(component)
+                if matches!(
+                    instances::strategy(&component.metadata.local_resources),
+                    Ok(InstanceReuse::Reuse)
+                ) =>
+            {
+                component.pool_size().max(1)
+            }
+            _ => return,
+        };
+        self.idle_instances
+            .put(component_id, generation, store, instance, max_idle);
+    }
+
     /// Helper to create a new wasmtime Store for a given component in the workload.
     pub async fn new_store(&self, component_id: &str) -> anyhow::Result<wasmtime::Store<Ctx>> {
         let components = self.components.read().await;
@@ -857,29 +2349,79 @@ impl ResolvedWorkload {
         &self,
         metadata: &WorkloadMetadata,
     ) -> anyhow::Result<wasmtime::Store<Ctx>> {
+        self.new_store_with(metadata, |_| {}).await
+    }
+
+    /// Creates a new wasmtime Store from the given workload metadata, letting `configure`
+    /// adjust the WASI context, e.g. to capture stdio.
+    async fn new_store_with(
+        &self,
+        metadata: &WorkloadMetadata,
+        configure: impl FnOnce(&mut WasiCtxBuilder),
+    ) -> anyhow::Result<wasmtime::Store<Ctx>> {
+        let started = Instant::now();
         let components = self.components.read().await;
 
         // TODO: Consider stderr/stdout buffering + logging
         let mut wasi_ctx_builder = WasiCtxBuilder::new();
         wasi_ctx_builder
             .envs(
-                metadata
+                &metadata
                     .local_resources
-                    .environment
-                    .iter()
-                    .map(|kv| (kv.0.as_str(), kv.1.as_str()))
-                    .collect::<Vec<_>>()
-                    .as_slice(),
+                    .guest_environment(host_environment()),
             )
+            .args(&metadata.local_resources.arguments)
             .inherit_stdout()
             .inherit_stderr();
 
-        // Mount all possible volume mounts in the workload since components share a WasiCtx
-        for (host_path, mount) in &components
+        // Sockets keep wasmtime's deny-all default unless the component allows hosts
+        let allowed_hosts = Arc::new(
+            AllowedHosts::parse(&metadata.local_resources.allowed_hosts)
+                .context("invalid allowed_hosts")?,
+        );
+        if !allowed_hosts.is_empty() {
+            let rules = allowed_hosts.clone();
+            let egress_log = self.egress_log.clone();
+            let workload_id = metadata.workload_id().to_string();
+            let component_id = metadata.id().to_string();
+            wasi_ctx_builder.socket_addr_check(move |addr, addr_use| {
+                let allowed = match addr_use {
+                    SocketAddrUse::TcpBind | SocketAddrUse::UdpBind => true,
+                    // Only blocked datagrams are recorded, allowed ones would flood the log
+                    SocketAddrUse::UdpOutgoingDatagram => {
+                        let allowed = rules.allows_socket(addr);
+                        if !allowed {
+                            egress_log.record_socket(&workload_id, &component_id, addr, false);
+                        }
+                        allowed
+                    }
+                    _ => {
+                        let allowed = rules.allows_socket(addr);
+                        egress_log.record_socket(&workload_id, &component_id, addr, allowed);
+                        allowed
+                    }
+                };
+                Box::pin(async move { allowed })
+            });
+        }
+
+        // Mount all possible volume mounts in the workload since components share a WasiCtx,
+        // each path once with the strictest permissions and quota it's mounted with
+        let mut volume_mounts: Vec<(PathBuf, VolumeMount)> = Vec::new();
+        for (host_path, mount) in components
             .iter()
-            .flat_map(|(_id, workload_component)| workload_component.metadata.volume_mounts.clone())
-            .collect::<Vec<_>>()
+            .flat_map(|(_id, workload_component)| &workload_component.metadata.volume_mounts)
         {
+            match volume_mounts
+                .iter_mut()
+                .find(|(_, existing)| existing.mount_path == mount.mount_path)
+            {
+                Some((_, existing)) => mounts::merge(existing, mount),
+                None => volume_mounts.push((host_path.clone(), mount.clone())),
+            }
+        }
+        let mut quotas = VolumeQuotas::default();
+        for (host_path, mount) in &volume_mounts {
             let dir = tokio::fs::canonicalize(host_path).await?;
             debug!(host_path = %dir.display(), container_path = %mount.mount_path, "preopening volume mount");
             let (dir_perms, file_perms) = match mount.read_only {
@@ -887,17 +2429,72 @@ impl ResolvedWorkload {
                 false => (DirPerms::all(), FilePerms::all()),
             };
             wasi_ctx_builder.preopened_dir(&dir, &mount.mount_path, dir_perms, file_perms)?;
+            if let Some(quota) = mount.quota_bytes
+                && !mount.read_only
+            {
+                quotas.add(dir, &mount.mount_path, quota);
+            }
         }
+        let scratch = if self.scratch_volumes.is_empty() {
+            None
+        } else {
+            Some(ScratchDirs::create(
+                &self.scratch_volumes,
+                &mut wasi_ctx_builder,
+                &mut quotas,
+            )?)
+        };
+        configure(&mut wasi_ctx_builder);
 
         let mut ctx_builder = Ctx::builder(metadata.workload_id(), metadata.id())
             .with_http_handler(self.http_handler.clone())
+            .with_allowed_hosts(allowed_hosts)
+            .with_egress_log(self.egress_log.clone())
+            .with_usage(self.usage.clone())
+            .with_bandwidth(self.bandwidth.clone())
+            .with_acquiring_since(started)
             .with_wasi_ctx(wasi_ctx_builder.build());
 
+        if let Some(proxy) = &self.egress_proxy {
+            ctx_builder = ctx_builder.with_egress_proxy(proxy.clone());
+        }
+
+        if let Some(cgroup) = &self.cgroup {
+            ctx_builder = ctx_builder.with_cgroup(cgroup.clone());
+        }
+
         if let Some(plugins) = &metadata.plugins {
             ctx_builder = ctx_builder.with_plugins(plugins.clone());
         }
 
-        let store = wasmtime::Store::new(metadata.engine(), ctx_builder.build());
+        if let Some(scratch) = scratch {
+            ctx_builder = ctx_builder.with_scratch(scratch);
+        }
+
+        if !quotas.is_empty() {
+            ctx_builder = ctx_builder.with_volume_quotas(quotas);
+        }
+
+        if let Ok(limit_mb @ 1..) = u64::try_from(metadata.local_resources.memory_limit_mb) {
+            ctx_builder = ctx_builder.with_memory_limit(limit_mb * 1024 * 1024);
+        }
+
+        if let Some(threshold) = slow_invocations::threshold(&metadata.local_resources)? {
+            ctx_builder = ctx_builder.with_slow_invocation_threshold(threshold);
+        }
+        instances::strategy(&metadata.local_resources)?;
+
+        let mut store = wasmtime::Store::new(metadata.engine(), ctx_builder.build());
+        // Nothing budgets fuel yet, so when the engine meters it stores get all there is
+        // rather than trapping at once, and the fuel they use is reported
+        if store.get_fuel().is_ok() {
+            store.set_fuel(u64::MAX)?;
+        }
+        store.limiter(|ctx| ctx);
+        store.call_hook(|mut ctx, hook| {
+            let fuel = ctx.get_fuel().ok();
+            ctx.data_mut().track_call(hook, fuel)
+        });
 
         Ok(store)
     }
@@ -1009,6 +2606,20 @@ pub struct UnresolvedWorkload {
     service: Option<WorkloadService>,
     /// All [`WorkloadComponent`]s in the workload
     components: HashMap<Arc<str>, WorkloadComponent>,
+    /// Registry used to resolve host interfaces that reference published services
+    services: Option<Arc<ServiceRegistry>>,
+    /// Transport to services with no instances on this host
+    wrpc: Option<Arc<WrpcTransport>>,
+    /// Log that outbound connections of the workload are recorded in
+    egress_log: Option<Arc<EgressLog>>,
+    /// The annotations the workload was started with
+    annotations: Arc<HashMap<String, String>>,
+    /// The resource usage the workload's stores report to
+    usage: Arc<WorkloadUsage>,
+    /// The cgroup the workload's guest code executes in
+    cgroup: Option<Arc<WorkloadCgroup>>,
+    /// The scratch volumes each instance mounts
+    scratch_volumes: Arc<Vec<(VolumeMount, ScratchVolume)>>,
 }
 
 impl UnresolvedWorkload {
@@ -1046,9 +2657,64 @@ impl UnresolvedWorkload {
                 })
                 .collect(),
             host_interfaces,
+            services: None,
+            wrpc: None,
+            egress_log: None,
+            annotations: Arc::default(),
+            usage: Arc::default(),
+            cgroup: None,
+            scratch_volumes: Arc::default(),
         }
     }
 
+    /// Sets the registry used to resolve host interfaces that reference a service
+    /// published by another workload. Without one, such references fail to resolve.
+    pub fn with_service_registry(mut self, services: Arc<ServiceRegistry>) -> Self {
+        self.services = Some(services);
+        self
+    }
+
+    /// Sets the transport used to call services referenced by host interfaces that have
+    /// no instances on this host. Without one, such references fail to resolve.
+    pub fn with_wrpc_transport(mut self, wrpc: Arc<WrpcTransport>) -> Self {
+        self.wrpc = Some(wrpc);
+        self
+    }
+
+    /// Sets the log that outbound connections of the workload are recorded in. Without
+    /// one, they are only logged.
+    pub fn with_egress_log(mut self, egress_log: Arc<EgressLog>) -> Self {
+        self.egress_log = Some(egress_log);
+        self
+    }
+
+    /// Sets the annotations the workload was started with.
+    pub fn with_annotations(mut self, annotations: HashMap<String, String>) -> Self {
+        self.annotations = Arc::new(annotations);
+        self
+    }
+
+    /// Sets the scratch volumes each instance of the workload gets new directories for,
+    /// see [`crate::engine::scratch`].
+    pub fn with_scratch_volumes(mut self, mounts: Vec<(VolumeMount, ScratchVolume)>) -> Self {
+        self.scratch_volumes = Arc::new(mounts);
+        self
+    }
+
+    /// Sets the usage the workload's memory, CPU time, invocations and plugin operations
+    /// are reported to. Without one, they're counted but not reported.
+    pub fn with_usage(mut self, usage: Arc<WorkloadUsage>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    /// Sets the cgroup the workload's guest code executes in, so the kernel enforces its
+    /// CPU limit and pinning, see [`crate::host::cgroups`].
+    pub fn with_cgroup(mut self, cgroup: Arc<WorkloadCgroup>) -> Self {
+        self.cgroup = Some(cgroup);
+        self
+    }
+
     /// Bind this workload to the host plugins based on the requested
     /// interfaces. Returns a list of plugins and the component IDs they were bound to.
     pub async fn bind_plugins(
@@ -1068,12 +2734,32 @@ impl UnresolvedWorkload {
             self.host_interfaces
                 .iter()
                 .filter(|wit_interface| !http_iface.contains(wit_interface))
+                // Service references are linked to other workloads, not plugins
+                .filter(|wit_interface| service_reference(wit_interface).is_none())
                 .cloned()
                 .collect::<Vec<_>>()
         };
 
         trace!(host_interfaces = ?host_interfaces, "determining missing guest interfaces");
 
+        // Validate interface configuration up front so typos fail before anything is bound
+        for (plugin_id, p) in plugins.iter() {
+            let Some(schema) = p.config_schema() else {
+                continue;
+            };
+            let plugin_world = p.world();
+            for wit_interface in host_interfaces
+                .iter()
+                .filter(|wit_interface| plugin_world.includes_bidirectional(wit_interface))
+            {
+                schema.validate(&wit_interface.config).with_context(|| {
+                    format!(
+                        "invalid config for interface '{wit_interface}' provided by plugin '{plugin_id}'"
+                    )
+                })?;
+            }
+        }
+
         if let Some(service) = self.service.as_ref() {
             let world = service.world();
             trace!(?world, "comparing service world to host interfaces");
@@ -1170,6 +2856,12 @@ impl UnresolvedWorkload {
                             );
                         }
                     }
+                    // A plugin that's down couldn't bind any workload, rather than this one
+                    if !p.health().await.is_healthy() {
+                        bail!(e.context(HostError::PluginUnavailable {
+                            plugin: plugin_id.to_string(),
+                        }));
+                    }
                     bail!(e)
                 }
 
@@ -1250,6 +2942,7 @@ impl UnresolvedWorkload {
         }
 
         // Check if all required interfaces were matched
+        let mut missing = Vec::new();
         for (component_id, unmatched) in unmatched_interfaces.iter() {
             if !unmatched.is_empty() {
                 tracing::error!(
@@ -1257,12 +2950,36 @@ impl UnresolvedWorkload {
                     interfaces = ?unmatched,
                     "no plugins found for requested interfaces"
                 );
-                bail!(
-                    "workload component {component_id} requested interfaces that are not available on this host: {unmatched:?}",
-                )
+                for interface in unmatched {
+                    let offered = incompatible_offers(plugins, interface);
+                    missing.extend(
+                        qualified_interface_names(interface)
+                            .into_iter()
+                            .map(|name| MissingInterface {
+                                component_id: component_id.to_string(),
+                                interface: name,
+                                requested: true,
+                                offered: offered.clone(),
+                            }),
+                    );
+                }
             }
         }
 
+        if !missing.is_empty() {
+            missing.sort_by(|a, b| {
+                (&a.component_id, &a.interface).cmp(&(&b.component_id, &b.interface))
+            });
+            let mut considered_plugins: Vec<String> =
+                plugins.keys().map(|id| id.to_string()).collect();
+            considered_plugins.sort();
+            bail!(UnresolvedInterfacesError {
+                workload_id: self.id.to_string(),
+                missing,
+                considered_plugins,
+            });
+        }
+
         Ok(bound_plugins)
     }
 
@@ -1294,6 +3011,26 @@ impl UnresolvedWorkload {
         plugins: Option<&HashMap<&'static str, Arc<dyn HostPlugin + 'static>>>,
         http_handler: Arc<dyn crate::host::http::HostHandler>,
     ) -> anyhow::Result<ResolvedWorkload> {
+        let mut considered_plugins: Vec<String> = plugins
+            .into_iter()
+            .flat_map(|plugins| plugins.keys().map(|id| id.to_string()))
+            .collect();
+        considered_plugins.sort();
+
+        let egress_proxy = EgressProxy::from_host_interfaces(&self.host_interfaces)
+            .context("invalid egress proxy for workload")?
+            .map(Arc::new);
+        let bandwidth = bandwidth::workload_limit(
+            self.components
+                .values()
+                .map(|component| &component.metadata.local_resources)
+                .chain(
+                    self.service
+                        .iter()
+                        .map(|service| &service.metadata.local_resources),
+                ),
+        )?;
+
         // Bind to plugins
         let bound_plugins = if let Some(plugins) = plugins {
             trace!("binding plugins to workload");
@@ -1319,6 +3056,10 @@ impl UnresolvedWorkload {
             }
         };
 
+        let services = self.services.take();
+        let wrpc = self.wrpc.take();
+        let egress_log = self.egress_log.take().unwrap_or_default();
+
         // Resolve the workload
         let mut resolved_workload = ResolvedWorkload {
             id: self.id.clone(),
@@ -1328,6 +3069,18 @@ impl UnresolvedWorkload {
             service: self.service,
             host_interfaces: self.host_interfaces,
             http_handler: http_handler.clone(),
+            egress_proxy,
+            egress_log,
+            routing_paused: Arc::default(),
+            paused: Arc::new(watch::Sender::new(false)),
+            annotations: self.annotations,
+            usage: self.usage,
+            cgroup: self.cgroup,
+            scratch_volumes: self.scratch_volumes,
+            idle_instances: Arc::default(),
+            #[cfg(feature = "wasip3")]
+            shared_instances: Arc::default(),
+            bandwidth: Arc::new(WorkloadBandwidth::new(bandwidth)),
         };
 
         // Link components before plugin resolution
@@ -1341,6 +3094,31 @@ impl UnresolvedWorkload {
             bail!(e);
         }
 
+        if let Some(services) = services.as_ref()
+            && let Err(e) = resolved_workload
+                .link_service_references(services, wrpc.as_ref())
+                .await
+        {
+            warn!(
+                error = %e,
+                "failed to link service references, unbinding all plugins"
+            );
+            let _ = resolved_workload.unbind_all_plugins().await;
+            bail!(e);
+        }
+
+        if let Err(e) = resolved_workload
+            .ensure_imports_linked(considered_plugins)
+            .await
+        {
+            warn!(
+                error = %e,
+                "workload has unlinked imports, unbinding all plugins"
+            );
+            let _ = resolved_workload.unbind_all_plugins().await;
+            bail!(e);
+        }
+
         // Notify plugins of the resolved workload
         for (plugin, component_ids) in bound_plugins.iter() {
             trace!(
@@ -1572,6 +3350,7 @@ mod tests {
             package: "blobstore".to_string(),
             interfaces: ["container".to_string()].into_iter().collect(),
             version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+            version_req: None,
             config: std::collections::HashMap::new(),
         };
 
@@ -1766,6 +3545,89 @@ mod tests {
         // In practice, this would fail if a component imports blobstore but no plugin provides it
     }
 
+    /// Tests that a requested interface no plugin provides produces an
+    /// [`UnresolvedInterfacesError`] naming the interface and the plugins considered.
+    #[tokio::test]
+    async fn test_missing_interface_error_details() {
+        let blobstore_interface = WitInterface::from("wasi:blobstore/container@0.2.0-draft");
+
+        let plugin = Arc::new(MockPlugin::new(
+            "keyvalue-plugin",
+            vec![WitInterface::from("wasi:keyvalue/store@0.2.0-draft")],
+            vec![],
+        ));
+        let mut plugins = HashMap::new();
+        plugins.insert(plugin.id(), plugin.clone() as Arc<dyn HostPlugin>);
+
+        let mut workload = UnresolvedWorkload::new(
+            "test-workload-id".to_string(),
+            "test-workload".to_string(),
+            "test-namespace".to_string(),
+            None,
+            vec![create_test_component("component1")],
+            vec![blobstore_interface],
+        );
+
+        let Err(err) = workload.bind_plugins(&plugins).await else {
+            panic!("binding should fail without a blobstore plugin");
+        };
+        let details = err
+            .downcast_ref::<UnresolvedInterfacesError>()
+            .expect("error should be an UnresolvedInterfacesError");
+
+        assert_eq!(details.workload_id, "test-workload-id");
+        assert_eq!(details.considered_plugins, vec![ID.to_string()]);
+        assert_eq!(details.missing.len(), 1);
+        assert_eq!(
+            details.missing[0].interface,
+            "wasi:blobstore/container@0.2.0-draft"
+        );
+        assert!(details.missing[0].requested);
+        assert!(err.to_string().contains(
+            "requested 'wasi:blobstore/container@0.2.0-draft', which no plugin provides"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_interface_reports_incompatible_versions() {
+        let plugin = Arc::new(MockPlugin::new(
+            "keyvalue-plugin",
+            vec![WitInterface::from("wasi:keyvalue/store@0.2.0")],
+            vec![],
+        ));
+        let mut plugins = HashMap::new();
+        plugins.insert(plugin.id(), plugin.clone() as Arc<dyn HostPlugin>);
+
+        let mut workload = UnresolvedWorkload::new(
+            "test-workload-id".to_string(),
+            "test-workload".to_string(),
+            "test-namespace".to_string(),
+            None,
+            vec![create_test_component("component1")],
+            // The component imports this version, which a plugin offering 0.2.0 can't
+            // provide as prereleases only match themselves
+            vec![WitInterface::from("wasi:keyvalue/store@0.2.0-draft")],
+        );
+
+        let Err(err) = workload.bind_plugins(&plugins).await else {
+            panic!("binding should fail without a compatible keyvalue plugin");
+        };
+        let details = err
+            .downcast_ref::<UnresolvedInterfacesError>()
+            .expect("error should be an UnresolvedInterfacesError");
+
+        assert_eq!(details.missing.len(), 1);
+        assert_eq!(
+            details.missing[0].interface,
+            "wasi:keyvalue/store@0.2.0-draft"
+        );
+        assert_eq!(
+            details.missing[0].offered,
+            vec![format!("{ID} (wasi:keyvalue@0.2.0)")]
+        );
+        assert!(err.to_string().contains("at a compatible version"));
+    }
+
     /// Tests that plugin callbacks are invoked in the correct order:
     /// `on_workload_bind` first, then `on_component_bind` for each component.
     #[tokio::test]
@@ -1838,4 +3700,675 @@ mod tests {
         // Show the difference between includes and includes_bidirectional
         assert!(!world.includes(&interface3));
     }
+
+    #[test]
+    fn test_guest_environment() {
+        let resources = LocalResources {
+            environment: HashMap::from([("AWS_REGION".to_string(), "eu-west-1".to_string())]),
+            inherited_environment: vec!["HOME".to_string(), "AWS_*".to_string()],
+            ..Default::default()
+        };
+        let host_env = [
+            ("HOME", "/home/wash"),
+            ("HOMEBREW_PREFIX", "/opt/homebrew"),
+            ("AWS_REGION", "us-east-1"),
+            ("AWS_PROFILE", "dev"),
+            ("PATH", "/usr/bin"),
+        ]
+        .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        assert_eq!(
+            resources.guest_environment(host_env.clone()),
+            [
+                ("AWS_PROFILE", "dev"),
+                ("AWS_REGION", "eu-west-1"),
+                ("HOME", "/home/wash"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+        assert!(
+            LocalResources::default()
+                .guest_environment(host_env)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_cron_job_history_limits() {
+        let cron_job = CronJob {
+            schedule: "@hourly".to_string(),
+            successful_jobs_history_limit: 2,
+            failed_jobs_history_limit: 1,
+            ..Default::default()
+        };
+        let mut status = CronJobStatus::default();
+        let start = Utc::now();
+        for (hour, state) in [
+            JobState::Succeeded,
+            JobState::Failed,
+            JobState::Succeeded,
+            JobState::Failed,
+            JobState::Succeeded,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let run = CronJobRun {
+                scheduled_at: start + chrono::TimeDelta::hours(hour as i64),
+                finished_at: None,
+                status: JobStatus {
+                    state,
+                    ..Default::default()
+                },
+                result: None,
+            };
+            record_cron_job_run(&mut status, run, &cron_job);
+        }
+
+        let kept: Vec<_> = status
+            .history
+            .iter()
+            .map(|run| (run.scheduled_at - start).num_hours())
+            .collect();
+        assert_eq!(kept, [4, 3, 2]);
+    }
+
+    /// Drives `job` with runs that each take the next of `runs`, a delay and an exit code,
+    /// and returns its final status, the result of its last run and the most runs that
+    /// were ever active at once.
+    async fn drive_test_job(
+        job: Job,
+        runs: impl IntoIterator<Item = (u64, i32)>,
+    ) -> (JobStatus, CommandResult, usize) {
+        let runs = Arc::new(Mutex::new(runs.into_iter().collect::<Vec<_>>()));
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        let status = Mutex::new(JobStatus::default());
+        let result = drive_job(
+            "job",
+            job,
+            |f| f(&mut status.lock().unwrap()),
+            || {
+                let (delay, exit_code) = runs.lock().unwrap().remove(0);
+                let (active, max_active) = (active.clone(), max_active.clone());
+                async move {
+                    let now_active = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_active.fetch_max(now_active, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(delay)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    CommandResult {
+                        exit_code,
+                        ..Default::default()
+                    }
+                }
+            },
+        )
+        .await;
+        let status = status.into_inner().unwrap();
+        (status, result, max_active.load(Ordering::SeqCst))
+    }
+
+    /// Tests that failed runs are replaced after a doubling backoff until a run succeeds.
+    #[tokio::test]
+    async fn test_job_retries_failed_runs() {
+        let job = Job {
+            backoff_limit: 2,
+            backoff: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let (status, result, _) = drive_test_job(job, [(0, 1), (0, 2), (0, 0)]).await;
+        // Backing off for 50ms and then 100ms
+        assert!(started.elapsed() >= Duration::from_millis(150));
+        assert_eq!(status.state, JobState::Succeeded);
+        assert_eq!((status.succeeded, status.failed, status.active), (1, 2, 0));
+        assert!(status.message.is_empty());
+        assert_eq!(result.exit_code, 0);
+    }
+
+    /// Tests that a job fails once more runs failed than its backoff limit allows.
+    #[tokio::test]
+    async fn test_job_backoff_limit() {
+        let job = Job {
+            backoff_limit: 1,
+            backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let (status, result, _) = drive_test_job(job, [(0, 1), (0, 3), (0, 0)]).await;
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!((status.succeeded, status.failed, status.active), (0, 2, 0));
+        assert!(status.message.contains("backoff limit of 1"));
+        assert_eq!(result.exit_code, 3);
+    }
+
+    /// Tests that a job fails and stops its runs once it outlives its active deadline.
+    #[tokio::test]
+    async fn test_job_active_deadline() {
+        let job = Job {
+            parallelism: 2,
+            completions: 2,
+            active_deadline: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let started = Instant::now();
+        let (status, _, _) = drive_test_job(job, [(0, 0), (10_000, 0)]).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!((status.succeeded, status.failed, status.active), (1, 0, 0));
+        assert!(status.message.contains("deadline"));
+    }
+
+    /// Tests that a job runs up to `parallelism` runs at once until `completions` succeed.
+    #[tokio::test]
+    async fn test_job_parallelism_and_completions() {
+        let job = Job {
+            parallelism: 2,
+            completions: 5,
+            ..Default::default()
+        };
+        let (status, _, max_active) = drive_test_job(job, [(20, 0); 5]).await;
+        assert_eq!(max_active, 2);
+        assert_eq!(status.state, JobState::Succeeded);
+        assert_eq!((status.succeeded, status.failed, status.active), (5, 0, 0));
+    }
+
+    /// Tests that runs finishing while a job backs off are counted, so the job fails at its
+    /// backoff limit instead of when it outlives its deadline.
+    #[tokio::test]
+    async fn test_job_counts_runs_while_backing_off() {
+        let job = Job {
+            parallelism: 2,
+            completions: 2,
+            backoff_limit: 1,
+            backoff: Duration::from_secs(10),
+            active_deadline: Some(Duration::from_secs(1)),
+        };
+        let started = Instant::now();
+        let (status, result, _) = drive_test_job(job, [(0, 1), (50, 2)]).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(status.state, JobState::Failed);
+        assert_eq!((status.succeeded, status.failed, status.active), (0, 2, 0));
+        assert!(status.message.contains("backoff limit of 1"));
+        assert_eq!(result.exit_code, 2);
+    }
+
+    /// Tests that updating a component's resources drops the instances it reuses.
+    #[tokio::test]
+    async fn test_update_resources_clears_idle_instances() -> anyhow::Result<()> {
+        let engine = wasmtime::Engine::default();
+        // An empty component, which imports nothing
+        let component = Component::new(&engine, b"\0asm\x0d\x00\x01\x00")?;
+        let mut local_resources = LocalResources::default();
+        local_resources.config.insert(
+            instances::INSTANCE_REUSE_CONFIG.to_string(),
+            "reuse".to_string(),
+        );
+        let component = WorkloadComponent::new(
+            "workload".to_string(),
+            "reuse".to_string(),
+            "default".to_string(),
+            component,
+            Linker::new(&engine),
+            Vec::new(),
+            local_resources,
+        );
+        let component_id = component.id().to_string();
+        let workload = UnresolvedWorkload::new(
+            "workload".to_string(),
+            "reuse".to_string(),
+            "default".to_string(),
+            None,
+            vec![component],
+            vec![],
+        )
+        .resolve(None, Arc::new(crate::host::http::NullServer::default()))
+        .await?;
+
+        let keep_instance = async |generation| -> anyhow::Result<()> {
+            let mut store = workload.new_store(&component_id).await?;
+            let pre = workload.instantiate_pre(&component_id).await?;
+            let instance = pre.instantiate(&mut store)?;
+            workload
+                .keep_idle_instance(&component_id, generation, store, instance)
+                .await;
+            Ok(())
+        };
+
+        keep_instance(workload.idle_generation(&component_id)).await?;
+        assert!(workload.take_idle_instance(&component_id).await.is_some());
+        keep_instance(workload.idle_generation(&component_id)).await?;
+
+        // An instance handling a request during the update isn't left idle after it
+        let in_flight = workload.idle_generation(&component_id);
+        let update = LocalResourcesUpdate {
+            memory_limit_mb: Some(64),
+            ..Default::default()
+        };
+        workload.update_resources(None, &update).await?;
+        assert!(workload.take_idle_instance(&component_id).await.is_none());
+        keep_instance(in_flight).await?;
+        assert!(workload.take_idle_instance(&component_id).await.is_none());
+
+        keep_instance(workload.idle_generation(&component_id)).await?;
+        assert!(workload.take_idle_instance(&component_id).await.is_some());
+        Ok(())
+    }
+
+    /// A command component that imports nothing and exits successfully.
+    fn command_component(engine: &wasmtime::Engine) -> anyhow::Result<Component> {
+        let bytes = wat::parse_str(
+            r#"(component
+                (core module $m (func (export "run") (result i32) i32.const 0))
+                (core instance $i (instantiate $m))
+                (func $run (result (result)) (canon lift (core func $i "run")))
+                (instance $run (export "run" (func $run)))
+                (export "wasi:cli/run@0.2.0" (instance $run))
+            )"#,
+        )?;
+        Component::new(engine, bytes)
+    }
+
+    /// Resolves a workload whose service is a [command](command_component), started by a
+    /// cron job every minute that last ran 90 seconds ago, so a missed job is due at once.
+    async fn cron_workload() -> anyhow::Result<ResolvedWorkload> {
+        let engine = crate::engine::Engine::builder()
+            .with_pooling_allocator(false)
+            .build()?;
+        let service = WorkloadService::new(
+            "workload",
+            "cron",
+            "default",
+            command_component(engine.inner())?,
+            Linker::new(engine.inner()),
+            Vec::new(),
+            LocalResources::default(),
+            0,
+        )
+        .with_command(Command {
+            stdin: Default::default(),
+            job: None,
+            cron_job: Some(CronJob {
+                schedule: "* * * * *".to_string(),
+                last_schedule_time: Some(Utc::now() - chrono::TimeDelta::seconds(90)),
+                ..Default::default()
+            }),
+        });
+        UnresolvedWorkload::new(
+            "workload".to_string(),
+            "cron".to_string(),
+            "default".to_string(),
+            Some(service),
+            vec![],
+            vec![],
+        )
+        .resolve(None, Arc::new(crate::host::http::NullServer::default()))
+        .await
+    }
+
+    /// Waits until the status of a workload's cron job matches `done`.
+    async fn cron_job_status(
+        workload: &ResolvedWorkload,
+        done: impl Fn(&CronJobStatus) -> bool,
+    ) -> CronJobStatus {
+        tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            loop {
+                let status = workload
+                    .cron_job_status()
+                    .expect("the workload has a cron job");
+                if done(&status) {
+                    return status;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the cron job status should be updated")
+    }
+
+    /// Tests that a paused workload skips the jobs its cron job schedules.
+    #[tokio::test]
+    async fn test_paused_cron_job_skips_runs() -> anyhow::Result<()> {
+        let missed = Utc::now() - chrono::TimeDelta::seconds(60);
+
+        let mut running = cron_workload().await?;
+        assert!(running.execute_service().await?);
+        let status = cron_job_status(&running, |status| !status.history.is_empty()).await;
+        assert!(status.last_schedule_time > Some(missed));
+        assert_eq!(status.history[0].status.state, JobState::Succeeded);
+
+        let mut paused = cron_workload().await?;
+        assert!(!paused.pause());
+        assert!(paused.execute_service().await?);
+        cron_job_status(&paused, |status| status.last_schedule_time > Some(missed)).await;
+        // Give a job that wasn't skipped time to start
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let status = paused
+            .cron_job_status()
+            .expect("the workload has a cron job");
+        assert!(status.active.is_empty());
+        assert!(status.history.is_empty());
+        Ok(())
+    }
+
+    /// Tests that pausing stops invocations until the workload is resumed, and that
+    /// resuming routing after a health check doesn't lift the pause.
+    #[tokio::test]
+    async fn test_pause_and_resume() -> anyhow::Result<()> {
+        let engine = crate::engine::Engine::builder()
+            .with_pooling_allocator(false)
+            .build()?;
+        let component = WorkloadComponent::new(
+            "workload".to_string(),
+            "command".to_string(),
+            "default".to_string(),
+            command_component(engine.inner())?,
+            Linker::new(engine.inner()),
+            Vec::new(),
+            LocalResources::default(),
+        );
+        let workload = UnresolvedWorkload::new(
+            "workload".to_string(),
+            "command".to_string(),
+            "default".to_string(),
+            None,
+            vec![component],
+            vec![],
+        )
+        .resolve(None, Arc::new(crate::host::http::NullServer::default()))
+        .await?;
+        let run = async || {
+            let mut results = [Val::Bool(false)];
+            workload
+                .call_export("wasi:cli/run", "run", &[], &mut results)
+                .await
+        };
+        run().await?;
+
+        assert!(!workload.pause());
+        assert!(workload.pause());
+        assert!(workload.is_paused());
+        assert!(workload.is_routing_paused());
+        let err = run()
+            .await
+            .expect_err("a paused workload shouldn't be invoked");
+        assert!(matches!(
+            HostError::of(&err),
+            HostError::WorkloadNotRunning { .. }
+        ));
+
+        // Routing resumed by a health check stays paused for the operator
+        workload.set_routing_paused(false);
+        assert!(workload.is_routing_paused());
+
+        assert!(workload.resume());
+        assert!(!workload.resume());
+        assert!(!workload.is_routing_paused());
+        tokio::time::timeout(std::time::Duration::from_secs(1), workload.resumed()).await?;
+        run().await?;
+
+        // A health check pausing routing isn't lifted by resuming the workload
+        workload.set_routing_paused(true);
+        assert!(!workload.resume());
+        assert!(workload.is_routing_paused());
+        Ok(())
+    }
+
+    /// A WASI 0.3 component exporting `test:concurrent/sleeper#sleep`, an async-lifted
+    /// function that waits for the given nanoseconds with `wasi:clocks` and returns how
+    /// many calls the instance had started when it was called, and `#trap`, which traps.
+    #[cfg(feature = "wasip3")]
+    const SLEEPER_WAT: &str = r#"
+    (component
+      (import "wasi:clocks/monotonic-clock@0.3.0-rc-2025-09-16" (instance $clock
+        (export "[async]wait-for" (func (param "how-long" u64)))
+      ))
+      (alias export $clock "[async]wait-for" (func $wait-for))
+
+      (core module $libc (memory (export "memory") 1))
+      (core instance $libc (instantiate $libc))
+      (core func $wait-for (canon lower (func $wait-for) async (memory $libc "memory")))
+      (core func $task-return (canon task.return (result u32)))
+      (core func $waitable-set-new (canon waitable-set.new))
+      (core func $waitable-set-drop (canon waitable-set.drop))
+      (core func $waitable-join (canon waitable.join))
+      (core func $subtask-drop (canon subtask.drop))
+      (core func $context-get (canon context.get i32 0))
+      (core func $context-set (canon context.set i32 0))
+
+      (core module $m
+        (import "" "wait-for" (func $wait-for (param i64) (result i32)))
+        (import "" "task.return" (func $task-return (param i32)))
+        (import "" "waitable-set.new" (func $waitable-set-new (result i32)))
+        (import "" "waitable-set.drop" (func $waitable-set-drop (param i32)))
+        (import "" "waitable.join" (func $waitable-join (param i32 i32)))
+        (import "" "subtask.drop" (func $subtask-drop (param i32)))
+        (import "" "context.get" (func $context-get (result i32)))
+        (import "" "context.set" (func $context-set (param i32)))
+        (global $calls (mut i32) (i32.const 0))
+
+        ;; Starts waiting, keeping the call count and a waitable set holding the wait in
+        ;; the task's context
+        (func (export "sleep") (param $ns i64) (result i32)
+          (local $status i32)
+          (local $set i32)
+          (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+          (local.set $status (call $wait-for (local.get $ns)))
+          (if (i32.eq (i32.and (local.get $status) (i32.const 0xf)) (i32.const 2))
+            (then
+              (call $task-return (global.get $calls))
+              (return (i32.const 0))))
+          (local.set $set (call $waitable-set-new))
+          (call $context-set
+            (i32.or (i32.shl (global.get $calls) (i32.const 16)) (local.get $set)))
+          (call $waitable-join (i32.shr_u (local.get $status) (i32.const 4)) (local.get $set))
+          (i32.or (i32.const 2) (i32.shl (local.get $set) (i32.const 4))))
+
+        ;; Returns the call count once the wait has returned
+        (func (export "callback") (param $event i32) (param $waitable i32) (param $code i32)
+          (result i32)
+          (local $set i32)
+          (local.set $set (i32.and (call $context-get) (i32.const 0xffff)))
+          (if (i32.and
+                (i32.eq (local.get $event) (i32.const 1))
+                (i32.eq (local.get $code) (i32.const 2)))
+            (then
+              (call $subtask-drop (local.get $waitable))
+              (call $waitable-set-drop (local.get $set))
+              (call $task-return (i32.shr_u (call $context-get) (i32.const 16)))
+              (return (i32.const 0))))
+          (i32.or (i32.const 2) (i32.shl (local.get $set) (i32.const 4))))
+
+        (func (export "trap") unreachable)
+      )
+      (core instance $i (instantiate $m
+        (with "" (instance
+          (export "wait-for" (func $wait-for))
+          (export "task.return" (func $task-return))
+          (export "waitable-set.new" (func $waitable-set-new))
+          (export "waitable-set.drop" (func $waitable-set-drop))
+          (export "waitable.join" (func $waitable-join))
+          (export "subtask.drop" (func $subtask-drop))
+          (export "context.get" (func $context-get))
+          (export "context.set" (func $context-set))
+        ))
+      ))
+
+      (func $sleep (param "ns" u64) (result u32)
+        (canon lift (core func $i "sleep") async (callback (func $i "callback"))))
+      (func $trap (canon lift (core func $i "trap")))
+      (instance $sleeper (export "sleep" (func $sleep)) (export "trap" (func $trap)))
+      (export "test:concurrent/sleeper" (instance $sleeper))
+    )
+    "#;
+
+    /// Tests that a WASI 0.3 component with the `concurrent` strategy runs concurrent
+    /// calls in one instance, and gets a fresh instance for each call by default.
+    #[cfg(feature = "wasip3")]
+    #[tokio::test]
+    async fn test_concurrent_calls_share_an_instance() -> anyhow::Result<()> {
+        let engine = crate::engine::Engine::builder()
+            .with_pooling_allocator(false)
+            .build()?;
+        let sleeper = async |reuse: &str| -> anyhow::Result<ResolvedWorkload> {
+            let mut linker = Linker::new(engine.inner());
+            wasmtime_wasi::p3::add_to_linker(&mut linker)?;
+            let mut resources = LocalResources::default();
+            resources.config.insert(
+                instances::INSTANCE_REUSE_CONFIG.to_string(),
+                reuse.to_string(),
+            );
+            let component = WorkloadComponent::new(
+                "workload".to_string(),
+                "sleeper".to_string(),
+                "default".to_string(),
+                Component::new(engine.inner(), wat::parse_str(SLEEPER_WAT)?)?,
+                linker,
+                Vec::new(),
+                resources,
+            );
+            UnresolvedWorkload::new(
+                "workload".to_string(),
+                "sleeper".to_string(),
+                "default".to_string(),
+                None,
+                vec![component],
+                vec![],
+            )
+            .resolve(None, Arc::new(crate::host::http::NullServer::default()))
+            .await
+        };
+        let sleep_calls = async |workload: &ResolvedWorkload| -> anyhow::Result<Vec<Val>> {
+            let wait = Val::U64(Duration::from_millis(200).as_nanos() as u64);
+            let calls = (0..4).map(|_| async {
+                let mut results = [Val::U32(0)];
+                workload
+                    .call_export(
+                        "test:concurrent/sleeper",
+                        "sleep",
+                        std::slice::from_ref(&wait),
+                        &mut results,
+                    )
+                    .await
+                    .map(|()| results[0].clone())
+            });
+            futures::future::try_join_all(calls).await
+        };
+
+        let concurrent = sleeper("concurrent").await?;
+        let started = Instant::now();
+        let mut counts = sleep_calls(&concurrent).await?;
+        assert!(started.elapsed() < Duration::from_millis(600));
+        counts.sort_by_key(|count| match count {
+            Val::U32(count) => *count,
+            _ => 0,
+        });
+        assert_eq!(counts, (1..=4).map(Val::U32).collect::<Vec<_>>());
+        // Later calls go to the same instance
+        assert_eq!(sleep_calls(&concurrent).await?.len(), 4);
+        let mut results = [Val::U32(0)];
+        concurrent
+            .call_export(
+                "test:concurrent/sleeper",
+                "sleep",
+                &[Val::U64(0)],
+                &mut results,
+            )
+            .await?;
+        assert_eq!(results[0], Val::U32(9));
+
+        // A failed call stops the instance, later calls get a new one
+        concurrent
+            .call_export("test:concurrent/sleeper", "trap", &[], &mut [])
+            .await
+            .expect_err("the call traps");
+        concurrent
+            .call_export(
+                "test:concurrent/sleeper",
+                "sleep",
+                &[Val::U64(0)],
+                &mut results,
+            )
+            .await?;
+        assert_eq!(results[0], Val::U32(1));
+
+        let fresh = sleeper("fresh").await?;
+        assert_eq!(sleep_calls(&fresh).await?, vec![Val::U32(1); 4]);
+        Ok(())
+    }
+
+    /// Tests that scaling and updating the resources of an unknown component fail
+    /// without changing anything.
+    #[tokio::test]
+    async fn test_scale_and_update_unknown_component() -> anyhow::Result<()> {
+        let engine = wasmtime::Engine::default();
+        let component = WorkloadComponent::new(
+            "workload".to_string(),
+            "api".to_string(),
+            "default".to_string(),
+            Component::new(&engine, b"\0asm\x0d\x00\x01\x00")?,
+            Linker::new(&engine),
+            Vec::new(),
+            LocalResources::default(),
+        );
+        let component_id = component.id().to_string();
+        let workload = UnresolvedWorkload::new(
+            "workload".to_string(),
+            "api".to_string(),
+            "default".to_string(),
+            None,
+            vec![component],
+            vec![],
+        )
+        .resolve(None, Arc::new(crate::host::http::NullServer::default()))
+        .await?;
+
+        let err = workload
+            .scale(Some("missing"), 4)
+            .await
+            .expect_err("scaling an unknown component should fail");
+        assert_eq!(HostError::of(&err), HostError::InvalidRequest);
+        assert_eq!(
+            workload.scale(None, 2).await?,
+            vec![(component_id.clone(), 0)]
+        );
+
+        let update = LocalResourcesUpdate {
+            config: HashMap::from([("flag".to_string(), Some("on".to_string()))]),
+            ..Default::default()
+        };
+        let err = workload
+            .update_resources(Some("missing"), &update)
+            .await
+            .expect_err("updating an unknown component should fail");
+        assert_eq!(HostError::of(&err), HostError::InvalidRequest);
+
+        let invalid = LocalResourcesUpdate {
+            allowed_hosts: Some(vec!["10.0.0.0/33".to_string()]),
+            ..update.clone()
+        };
+        let err = workload
+            .update_resources(None, &invalid)
+            .await
+            .expect_err("invalid resources should be rejected");
+        assert_eq!(HostError::of(&err), HostError::InvalidRequest);
+        let config = |workload: &ResolvedWorkload| {
+            let components = workload.components();
+            let components = components.try_read().expect("components aren't locked");
+            components[component_id.as_str()]
+                .local_resources()
+                .config
+                .clone()
+        };
+        assert!(config(&workload).is_empty());
+
+        assert_eq!(
+            workload.update_resources(None, &update).await?,
+            vec![component_id.clone()]
+        );
+        assert_eq!(
+            config(&workload).get("flag").map(String::as_str),
+            Some("on")
+        );
+        Ok(())
+    }
 }
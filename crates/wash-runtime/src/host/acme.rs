@@ -0,0 +1,586 @@
+//! Automatic certificates from an ACME certificate authority like Let's Encrypt, so HTTPS
+//! virtual hosts don't need certificates provisioned and renewed by hand.
+//!
+//! An [`Acme`] orders a certificate for each of its domains, which are the virtual hosts
+//! to serve over HTTPS, and renews them [`RENEW_AFTER`] they were issued. Certificates
+//! and the ACME account are kept in its directory, e.g. under the host's data dir, so
+//! restarted hosts reuse them rather than ordering new ones. Ordering failures are logged
+//! and retried after [`RETRY_INTERVAL`].
+//!
+//! Servers built with [`crate::host::http::HttpServer::with_acme`] serve HTTPS with the
+//! certificates of the domains clients name in the TLS handshake, and their own
+//! certificate, if created with one, to other clients. The certificate authority checks
+//! that the host controls a domain with one of two challenges, see [`AcmeChallenge`]:
+//!
+//! - TLS-ALPN-01 is answered by the HTTPS server itself, which has to listen on port 443
+//! - HTTP-01 is answered by any server built with [`crate::host::http::HttpServer::with_acme`]
+//!   or [`crate::host::http::HttpServer::with_acme_challenges`], e.g. a plain HTTP server
+//!   on port 80, before its requests are routed to workloads
+//!
+//! Wildcard domains need the DNS-01 challenge, which isn't supported.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context as _, bail, ensure};
+use bytes::Bytes;
+use http_body_util::{BodyExt as _, Full};
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus,
+};
+use rcgen::{CertificateParams, CustomExtension, DistinguishedName, KeyPair};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tracing::{debug, info, warn};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use crate::host::http::{load_cert_chain, load_private_key};
+
+/// The directory of Let's Encrypt's certificate authority.
+pub const LETS_ENCRYPT_DIRECTORY: &str = "https://acme-v02.api.letsencrypt.org/directory";
+
+/// The directory of Let's Encrypt's staging certificate authority, for testing without
+/// running into its rate limits. Its certificates aren't trusted by clients.
+pub const LETS_ENCRYPT_STAGING_DIRECTORY: &str =
+    "https://acme-staging-v02.api.letsencrypt.org/directory";
+
+/// How long after a certificate was issued it's renewed, a month before the 90 days
+/// Let's Encrypt certificates are valid for run out.
+pub const RENEW_AFTER: Duration = Duration::from_secs(60 * 24 * 60 * 60);
+
+/// How long to wait before ordering a certificate again after an order failed.
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often certificates are checked for renewal.
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// How often a pending order is polled, and how many times before giving up.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const POLL_ATTEMPTS: u32 = 30;
+
+/// The ALPN protocol of TLS-ALPN-01 challenge handshakes.
+pub(crate) const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// The path HTTP-01 challenges are requested under, followed by their token.
+const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+
+/// The file the ACME account's credentials are kept in.
+const ACCOUNT_FILE: &str = "account.json";
+
+/// How the certificate authority checks the host controls a domain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AcmeChallenge {
+    /// A TLS handshake with the HTTPS server on port 443
+    #[default]
+    TlsAlpn01,
+    /// A plain HTTP request on port 80
+    Http01,
+}
+
+/// Orders and renews certificates for a set of domains, see the [module docs](self).
+#[derive(Debug)]
+pub struct Acme {
+    domains: Vec<String>,
+    /// Where the account and certificates are kept
+    dir: PathBuf,
+    directory_url: String,
+    contact: Vec<String>,
+    challenge: AcmeChallenge,
+    /// Certificates by domain
+    certificates: RwLock<HashMap<String, Arc<CertifiedKey>>>,
+    /// The certificates answering pending TLS-ALPN-01 challenges, by domain
+    challenge_certificates: Mutex<HashMap<String, Arc<CertifiedKey>>>,
+    /// The key authorizations answering pending HTTP-01 challenges, by token
+    key_authorizations: Mutex<HashMap<String, String>>,
+}
+
+impl Acme {
+    /// Creates certificates for `domains` from Let's Encrypt, answering TLS-ALPN-01
+    /// challenges.
+    ///
+    /// # Arguments
+    /// * `domains` - The virtual hosts to order certificates for
+    /// * `dir` - The directory the account and certificates are kept in, created if missing
+    pub fn new(domains: impl IntoIterator<Item = impl Into<String>>, dir: PathBuf) -> Self {
+        Self {
+            domains: domains
+                .into_iter()
+                .map(|domain| {
+                    domain
+                        .into()
+                        .trim()
+                        .trim_end_matches('.')
+                        .to_ascii_lowercase()
+                })
+                .collect(),
+            dir,
+            directory_url: LETS_ENCRYPT_DIRECTORY.to_string(),
+            contact: Vec::new(),
+            challenge: AcmeChallenge::default(),
+            certificates: RwLock::default(),
+            challenge_certificates: Mutex::default(),
+            key_authorizations: Mutex::default(),
+        }
+    }
+
+    /// Orders certificates from the certificate authority at `directory_url` rather than
+    /// from Let's Encrypt.
+    pub fn with_directory_url(mut self, directory_url: impl Into<String>) -> Self {
+        self.directory_url = directory_url.into();
+        self
+    }
+
+    /// Registers the account with contact addresses, e.g. `mailto:ops@example.com`, which
+    /// the certificate authority may notify about expiring certificates.
+    pub fn with_contact(mut self, contact: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.contact = contact.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Answers `challenge` rather than TLS-ALPN-01.
+    pub fn with_challenge(mut self, challenge: AcmeChallenge) -> Self {
+        self.challenge = challenge;
+        self
+    }
+
+    /// The domains certificates are ordered for.
+    pub fn domains(&self) -> &[String] {
+        &self.domains
+    }
+
+    /// Orders certificates for domains without one, and renews them, until the task is
+    /// aborted. Certificates kept from earlier runs are loaded first.
+    pub async fn run(self: Arc<Self>) {
+        loop {
+            let mut failed = false;
+            for domain in &self.domains {
+                if let Err(e) = self.ensure_certificate(domain).await {
+                    warn!(domain, err = ?e, "failed to provision ACME certificate");
+                    failed = true;
+                }
+            }
+            tokio::time::sleep(if failed {
+                RETRY_INTERVAL
+            } else {
+                CHECK_INTERVAL
+            })
+            .await;
+        }
+    }
+
+    /// Loads the kept certificate for `domain`, ordering a new one if there's none or it's
+    /// due for renewal.
+    async fn ensure_certificate(&self, domain: &str) -> anyhow::Result<()> {
+        let (cert_path, key_path) = self.certificate_paths(domain);
+        let issued = tokio::fs::metadata(&cert_path)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let due = issued.is_none_or(|issued| {
+            SystemTime::now()
+                .duration_since(issued)
+                .is_ok_and(|age| age >= RENEW_AFTER)
+        });
+        if !self.has_certificate(domain) && issued.is_some() {
+            match self.load_certificate(&cert_path, &key_path).await {
+                Ok(certificate) => self.insert_certificate(domain, certificate),
+                Err(e) => warn!(domain, err = ?e, "failed to load kept ACME certificate"),
+            }
+        }
+        if due || !self.has_certificate(domain) {
+            self.order(domain).await?;
+        }
+        Ok(())
+    }
+
+    /// Orders a certificate for `domain` and keeps it.
+    async fn order(&self, domain: &str) -> anyhow::Result<()> {
+        info!(
+            domain,
+            directory = self.directory_url,
+            "ordering ACME certificate"
+        );
+        let account = self.account().await?;
+        let mut order = account
+            .new_order(&NewOrder {
+                identifiers: &[Identifier::Dns(domain.to_string())],
+            })
+            .await
+            .context("failed to create order")?;
+        let result = async {
+            self.answer_challenges(&mut order).await?;
+            self.finalize(&mut order, domain).await
+        }
+        .await;
+        // Challenges are only answered while their order is pending
+        self.challenge_certificates
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(domain);
+        self.key_authorizations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+        result
+    }
+
+    /// Sets up the answers to an order's challenges and tells the certificate authority
+    /// they're ready.
+    async fn answer_challenges(&self, order: &mut Order) -> anyhow::Result<()> {
+        let challenge_type = match self.challenge {
+            AcmeChallenge::TlsAlpn01 => ChallengeType::TlsAlpn01,
+            AcmeChallenge::Http01 => ChallengeType::Http01,
+        };
+        let authorizations = order
+            .authorizations()
+            .await
+            .context("failed to get authorizations")?;
+        let mut ready = Vec::new();
+        for authorization in &authorizations {
+            match authorization.status {
+                AuthorizationStatus::Pending => {}
+                AuthorizationStatus::Valid => continue,
+                status => bail!("authorization is {status:?}"),
+            }
+            let Identifier::Dns(domain) = &authorization.identifier;
+            let challenge = authorization
+                .challenges
+                .iter()
+                .find(|challenge| challenge.r#type == challenge_type)
+                .with_context(|| format!("no {challenge_type:?} challenge offered"))?;
+            let key_authorization = order.key_authorization(challenge);
+            match self.challenge {
+                AcmeChallenge::TlsAlpn01 => {
+                    let certificate = challenge_certificate(domain, key_authorization.digest())?;
+                    self.challenge_certificates
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .insert(domain.to_ascii_lowercase(), certificate);
+                }
+                AcmeChallenge::Http01 => {
+                    self.key_authorizations
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .insert(
+                            challenge.token.clone(),
+                            key_authorization.as_str().to_string(),
+                        );
+                }
+            }
+            ready.push(challenge.url.clone());
+        }
+        for url in &ready {
+            order
+                .set_challenge_ready(url)
+                .await
+                .context("failed to mark challenge ready")?;
+        }
+        Ok(())
+    }
+
+    /// Waits for the certificate authority to validate an order, then finalizes it with a
+    /// new key and keeps the certificate.
+    async fn finalize(&self, order: &mut Order, domain: &str) -> anyhow::Result<()> {
+        let mut attempts = 0;
+        loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let state = order.refresh().await.context("failed to refresh order")?;
+            match state.status {
+                OrderStatus::Ready => break,
+                OrderStatus::Invalid => bail!("order is invalid: {:?}", state.error),
+                _ => {}
+            }
+            attempts += 1;
+            ensure!(attempts < POLL_ATTEMPTS, "order wasn't validated in time");
+        }
+
+        let key_pair = KeyPair::generate().context("failed to generate key")?;
+        let mut params = CertificateParams::new(vec![domain.to_string()])?;
+        params.distinguished_name = DistinguishedName::new();
+        let csr = params.serialize_request(&key_pair)?;
+        order
+            .finalize(csr.der())
+            .await
+            .context("failed to finalize order")?;
+        let mut attempts = 0;
+        let cert_chain = loop {
+            if let Some(cert_chain) = order
+                .certificate()
+                .await
+                .context("failed to download certificate")?
+            {
+                break cert_chain;
+            }
+            attempts += 1;
+            ensure!(
+                attempts < POLL_ATTEMPTS,
+                "certificate wasn't issued in time"
+            );
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+
+        let (cert_path, key_path) = self.certificate_paths(domain);
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        // The certificate is written last, as its modification time is when it was issued
+        write_private(&key_path, key_pair.serialize_pem().as_bytes()).await?;
+        tokio::fs::write(&cert_path, cert_chain.as_bytes())
+            .await
+            .with_context(|| format!("failed to write {}", cert_path.display()))?;
+        let certificate = self.load_certificate(&cert_path, &key_path).await?;
+        self.insert_certificate(domain, certificate);
+        info!(domain, "provisioned ACME certificate");
+        Ok(())
+    }
+
+    /// Loads the account from the directory, or registers a new one and keeps it.
+    async fn account(&self) -> anyhow::Result<Account> {
+        let path = self.dir.join(ACCOUNT_FILE);
+        match tokio::fs::read(&path).await {
+            Ok(credentials) => {
+                let credentials: AccountCredentials = serde_json::from_slice(&credentials)
+                    .with_context(|| format!("invalid ACME account in {}", path.display()))?;
+                return Account::from_credentials(credentials)
+                    .await
+                    .context("failed to load ACME account");
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read {}", path.display()));
+            }
+        }
+        let contact = self.contact.iter().map(String::as_str).collect::<Vec<_>>();
+        let (account, credentials) = Account::create(
+            &NewAccount {
+                contact: &contact,
+                terms_of_service_agreed: true,
+                only_return_existing: false,
+            },
+            &self.directory_url,
+            None,
+        )
+        .await
+        .context("failed to register ACME account")?;
+        debug!(directory = self.directory_url, "registered ACME account");
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        write_private(&path, &serde_json::to_vec(&credentials)?).await?;
+        Ok(account)
+    }
+
+    fn certificate_paths(&self, domain: &str) -> (PathBuf, PathBuf) {
+        (
+            self.dir.join(format!("{domain}.crt")),
+            self.dir.join(format!("{domain}.key")),
+        )
+    }
+
+    async fn load_certificate(
+        &self,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> anyhow::Result<Arc<CertifiedKey>> {
+        let cert_chain = load_cert_chain(cert_path).await?;
+        let key = load_private_key(key_path).await?;
+        certified_key(cert_chain, key)
+    }
+
+    fn has_certificate(&self, domain: &str) -> bool {
+        self.certificates
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(domain)
+    }
+
+    fn insert_certificate(&self, domain: &str, certificate: Arc<CertifiedKey>) {
+        self.certificates
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(domain.to_string(), certificate);
+    }
+
+    /// The answer to an HTTP-01 challenge requested at `path`, if one is pending.
+    pub(crate) fn key_authorization(&self, path: &str) -> Option<String> {
+        let token = path.strip_prefix(CHALLENGE_PATH)?;
+        self.key_authorizations
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(token)
+            .cloned()
+    }
+}
+
+/// Resolves the certificates of an [`Acme`] for a server, and of its pending TLS-ALPN-01
+/// challenges.
+#[derive(Debug)]
+pub(crate) struct AcmeResolver {
+    pub(crate) acme: Arc<Acme>,
+    /// Resolves the certificate for clients naming no domain with a certificate
+    pub(crate) fallback: Option<Arc<dyn ResolvesServerCert>>,
+}
+
+impl ResolvesServerCert for AcmeResolver {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let server_name = client_hello.server_name().map(str::to_ascii_lowercase);
+        let challenge = client_hello
+            .alpn()
+            .is_some_and(|mut protocols| protocols.any(|protocol| protocol == ACME_TLS_ALPN));
+        if let Some(server_name) = &server_name {
+            let certificate = if challenge {
+                self.acme
+                    .challenge_certificates
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get(server_name)
+                    .cloned()
+            } else {
+                self.acme
+                    .certificates
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get(server_name)
+                    .cloned()
+            };
+            if certificate.is_some() {
+                return certificate;
+            }
+        }
+        if challenge {
+            return None;
+        }
+        self.fallback
+            .as_ref()
+            .and_then(|fallback| fallback.resolve(client_hello))
+    }
+}
+
+/// A self-signed certificate for `domain` answering a TLS-ALPN-01 challenge with the
+/// digest of its key authorization.
+fn challenge_certificate(
+    domain: &str,
+    digest: impl AsRef<[u8]>,
+) -> anyhow::Result<Arc<CertifiedKey>> {
+    let key_pair = KeyPair::generate().context("failed to generate key")?;
+    let mut params = CertificateParams::new(vec![domain.to_string()])?;
+    params.distinguished_name = DistinguishedName::new();
+    params.custom_extensions = vec![CustomExtension::new_acme_identifier(digest.as_ref())];
+    let certificate = params.self_signed(&key_pair)?;
+    certified_key(
+        vec![certificate.der().clone()],
+        PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der())),
+    )
+}
+
+fn certified_key(
+    cert_chain: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+) -> anyhow::Result<Arc<CertifiedKey>> {
+    let signing_key = rustls::crypto::aws_lc_rs::sign::any_supported_type(&key)
+        .context("unsupported private key")?;
+    Ok(Arc::new(CertifiedKey::new(cert_chain, signing_key)))
+}
+
+/// Writes a file only its owner can read, for keys and account credentials.
+async fn write_private(path: &Path, contents: &[u8]) -> anyhow::Result<()> {
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options
+        .open(path)
+        .await
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    tokio::io::AsyncWriteExt::write_all(&mut file, contents)
+        .await
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Wraps the HTTP server's service, answering the HTTP-01 challenges `acme` has pending
+/// before `service` handles requests.
+pub(crate) fn service<S>(
+    service: S,
+    acme: Option<Arc<Acme>>,
+) -> impl hyper::service::Service<
+    hyper::Request<hyper::body::Incoming>,
+    Response = hyper::Response<HyperOutgoingBody>,
+    Error = hyper::Error,
+    Future = impl Future<Output = Result<hyper::Response<HyperOutgoingBody>, hyper::Error>>
+             + Send
+             + 'static,
+> + Clone
++ Send
++ 'static
+where
+    S: hyper::service::Service<
+            hyper::Request<hyper::body::Incoming>,
+            Response = hyper::Response<HyperOutgoingBody>,
+            Error = hyper::Error,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+        let key_authorization = acme
+            .as_ref()
+            .and_then(|acme| acme.key_authorization(req.uri().path()));
+        let response = match key_authorization {
+            Some(key_authorization) => Err(key_authorization),
+            None => Ok(service.call(req)),
+        };
+        async move {
+            match response {
+                Ok(response) => response.await,
+                Err(key_authorization) => Ok(challenge_response(key_authorization)),
+            }
+        }
+    })
+}
+
+fn challenge_response(key_authorization: String) -> hyper::Response<HyperOutgoingBody> {
+    let body = Full::new(Bytes::from(key_authorization))
+        .map_err(|never: std::convert::Infallible| match never {})
+        .boxed();
+    hyper::Response::builder()
+        .header(hyper::header::CONTENT_TYPE, "application/octet-stream")
+        .body(body)
+        .expect("failed to build ACME challenge response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_authorization() {
+        let acme = Acme::new(["Example.com."], PathBuf::from("acme"));
+        assert_eq!(acme.domains(), ["example.com"]);
+        acme.key_authorizations
+            .lock()
+            .unwrap()
+            .insert("token".to_string(), "token.thumbprint".to_string());
+        assert_eq!(
+            acme.key_authorization("/.well-known/acme-challenge/token")
+                .as_deref(),
+            Some("token.thumbprint")
+        );
+        assert_eq!(
+            acme.key_authorization("/.well-known/acme-challenge/other"),
+            None
+        );
+        assert_eq!(acme.key_authorization("/token"), None);
+    }
+
+    #[test]
+    fn test_challenge_certificate() -> anyhow::Result<()> {
+        let certificate = challenge_certificate("example.com", [7u8; 32])?;
+        assert_eq!(certificate.cert.len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+//! Host-managed API keys, a simple way for service-to-service callers to authenticate to
+//! a virtual host on the [HTTP server](crate::host::http).
+//!
+//! Keys are created, rotated and revoked through [`HostApi`](crate::host::HostApi). Each
+//! key is accepted on the virtual hosts it was created for, or on every host with `*`.
+//! The host keeps only a SHA-256 hash of each key's secret, in memory or in a JSON file
+//! that survives restarts, so a key is shown once, when it's created or rotated.
+//!
+//! A workload requires a key for its virtual host by setting `api-key` to `required` in
+//! its `wasi:http/incoming-handler` config. Callers send the key in the `X-Api-Key`
+//! header, and requests without a valid key are answered with `401 Unauthorized`. The
+//! workload gets the key's ID in `X-Api-Key-Id` instead of the key itself.
+//!
+//! Keys also authenticate calls to the [gRPC ingress](crate::host::grpc), where they're
+//! created for a service's full name instead of a virtual host.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{PoisonError, RwLock};
+
+use anyhow::{Context as _, anyhow, bail, ensure};
+use aws_lc_rs::digest;
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hyper::StatusCode;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use crate::engine::workload::ResolvedWorkload;
+use crate::wit::WitInterface;
+
+/// The prefix of every key, making leaked keys easy to spot.
+const KEY_PREFIX: &str = "wck_";
+/// The header callers send their key in.
+const KEY_HEADER: &str = "x-api-key";
+/// The header the workload gets the key's ID in.
+const KEY_ID_HEADER: &str = "x-api-key-id";
+
+/// A key as kept by the host.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct StoredKey {
+    name: String,
+    hosts: Vec<String>,
+    /// The hex SHA-256 hash of the key's secret
+    hash: String,
+}
+
+impl StoredKey {
+    fn accepts(&self, host: Option<&str>) -> bool {
+        self.hosts
+            .iter()
+            .any(|accepted| accepted == "*" || Some(accepted.as_str()) == host)
+    }
+}
+
+/// The API keys of a host, by ID.
+#[derive(Debug, Default)]
+pub struct ApiKeyStore {
+    /// The file keys are kept in, if they outlive the host
+    path: Option<PathBuf>,
+    keys: RwLock<HashMap<String, StoredKey>>,
+}
+
+impl ApiKeyStore {
+    /// Creates a store that keeps keys in memory, for the life of the host.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Opens a store that keeps keys in a JSON file, creating it on the first change.
+    ///
+    /// # Errors
+    /// Returns an error if the file exists but can't be read.
+    pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let keys = match std::fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("invalid API key file '{}'", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read API key file '{}'", path.display()));
+            }
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            keys: RwLock::new(keys),
+        })
+    }
+
+    /// Creates a key accepted on `hosts`.
+    ///
+    /// # Returns
+    /// The key's ID and the key itself.
+    ///
+    /// # Errors
+    /// Returns an error if no hosts are given or the store can't be saved.
+    pub fn create(&self, name: &str, hosts: Vec<String>) -> anyhow::Result<(String, String)> {
+        ensure!(!hosts.is_empty(), "an API key needs a virtual host, or '*'");
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        let (key, hash) = generate_key(&id)?;
+        self.update(|keys| {
+            keys.insert(
+                id.clone(),
+                StoredKey {
+                    name: name.to_string(),
+                    hosts,
+                    hash,
+                },
+            );
+            Ok(())
+        })?;
+        debug!(id, name, "created API key");
+        Ok((id, key))
+    }
+
+    /// Replaces the secret of a key. The old key stops being accepted right away.
+    ///
+    /// # Returns
+    /// The new key.
+    ///
+    /// # Errors
+    /// Returns an error if there's no key with the ID or the store can't be saved.
+    pub fn rotate(&self, id: &str) -> anyhow::Result<String> {
+        let (key, hash) = generate_key(id)?;
+        self.update(|keys| {
+            keys.get_mut(id)
+                .with_context(|| format!("API key '{id}' not found"))?
+                .hash = hash;
+            Ok(())
+        })?;
+        debug!(id, "rotated API key");
+        Ok(key)
+    }
+
+    /// Revokes a key.
+    ///
+    /// # Errors
+    /// Returns an error if there's no key with the ID or the store can't be saved.
+    pub fn revoke(&self, id: &str) -> anyhow::Result<()> {
+        self.update(|keys| {
+            keys.remove(id)
+                .with_context(|| format!("API key '{id}' not found"))?;
+            Ok(())
+        })?;
+        debug!(id, "revoked API key");
+        Ok(())
+    }
+
+    /// Checks a key for a virtual host.
+    ///
+    /// # Returns
+    /// The key's ID, or `None` if the key isn't valid or isn't accepted on the host.
+    pub fn verify(&self, key: &str, host: Option<&str>) -> Option<String> {
+        let (id, secret) = key.strip_prefix(KEY_PREFIX)?.split_once('_')?;
+        let keys = self.keys.read().unwrap_or_else(PoisonError::into_inner);
+        let stored = keys.get(id)?;
+        let hash = hash_secret(secret);
+        (aws_lc_rs::constant_time::verify_slices_are_equal(hash.as_bytes(), stored.hash.as_bytes())
+            .is_ok()
+            && stored.accepts(host))
+        .then(|| id.to_string())
+    }
+
+    /// Applies a change to the keys, saving them if the store has a file. The change is
+    /// dropped if it can't be saved.
+    fn update(
+        &self,
+        change: impl FnOnce(&mut HashMap<String, StoredKey>) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let mut keys = self.keys.write().unwrap_or_else(PoisonError::into_inner);
+        let mut updated = keys.clone();
+        change(&mut updated)?;
+        if let Some(path) = &self.path {
+            save(path, &updated)?;
+        }
+        *keys = updated;
+        Ok(())
+    }
+}
+
+/// Writes the keys through a temporary file, so a crash can't leave the file half written.
+fn save(path: &Path, keys: &HashMap<String, StoredKey>) -> anyhow::Result<()> {
+    let temp = path.with_extension("tmp");
+    std::fs::write(&temp, serde_json::to_vec_pretty(keys)?)
+        .with_context(|| format!("failed to write API key file '{}'", temp.display()))?;
+    std::fs::rename(&temp, path)
+        .with_context(|| format!("failed to replace API key file '{}'", path.display()))
+}
+
+/// Returns a new key with the ID, and the hash of its secret.
+fn generate_key(id: &str) -> anyhow::Result<(String, String)> {
+    let mut secret = [0u8; 32];
+    aws_lc_rs::rand::fill(&mut secret).map_err(|_| anyhow!("failed to generate API key"))?;
+    let secret = URL_SAFE_NO_PAD.encode(secret);
+    let hash = hash_secret(&secret);
+    Ok((format!("{KEY_PREFIX}{id}_{secret}"), hash))
+}
+
+fn hash_secret(secret: &str) -> String {
+    digest::digest(&digest::SHA256, secret.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Whether a workload requires API keys on its virtual host.
+///
+/// # Errors
+/// Returns an error if `api-key` is set to anything but `required`.
+pub(crate) fn required(workload: &ResolvedWorkload) -> anyhow::Result<bool> {
+    let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+    let setting = workload
+        .host_interfaces()
+        .iter()
+        .find(|iface| iface.contains(&incoming_handler_interface))
+        .and_then(|iface| iface.config.get("api-key"));
+    match setting.map(String::as_str) {
+        None => Ok(false),
+        Some("required") => Ok(true),
+        Some(other) => bail!("invalid api-key '{other}', expected 'required'"),
+    }
+}
+
+/// Checks the key a request carries in its `X-Api-Key` header, or gRPC metadata, for a
+/// virtual host or service.
+///
+/// # Returns
+/// The key's ID, or `None` if there's no valid key accepted for `host`.
+pub(crate) fn verify_header(
+    store: Option<&ApiKeyStore>,
+    host: Option<&str>,
+    headers: &HeaderMap,
+) -> Option<String> {
+    let key = headers.get(KEY_HEADER)?.to_str().ok()?;
+    store?.verify(key, host)
+}
+
+/// Removes the key ID header from a request, which only [`authenticate`] may set.
+pub(crate) fn strip_key_id(headers: &mut HeaderMap) {
+    headers.remove(KEY_ID_HEADER);
+}
+
+/// Checks the API key of a request to a virtual host, replacing it with its ID.
+///
+/// # Returns
+/// The response to answer the request with if its key isn't valid.
+pub(crate) fn authenticate(
+    store: Option<&ApiKeyStore>,
+    host: Option<&str>,
+    headers: &mut HeaderMap,
+) -> Option<hyper::Response<HyperOutgoingBody>> {
+    strip_key_id(headers);
+    let id = verify_header(store, host, headers);
+    headers.remove(KEY_HEADER);
+    match id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        Some(id) => {
+            headers.insert(HeaderName::from_static(KEY_ID_HEADER), id);
+            None
+        }
+        None => {
+            debug!(host, "rejecting request without a valid API key");
+            Some(
+                hyper::Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(HyperOutgoingBody::default())
+                    .expect("failed to build 401 response"),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_lifecycle() -> anyhow::Result<()> {
+        let store = ApiKeyStore::in_memory();
+        let (id, key) = store.create("billing", vec!["api.localhost".to_string()])?;
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(store.verify(&key, Some("api.localhost")), Some(id.clone()));
+        assert_eq!(store.verify(&key, Some("other.localhost")), None);
+        assert_eq!(
+            store.verify(&format!("{key}x"), Some("api.localhost")),
+            None
+        );
+
+        let rotated = store.rotate(&id)?;
+        assert_eq!(store.verify(&key, Some("api.localhost")), None);
+        assert_eq!(
+            store.verify(&rotated, Some("api.localhost")),
+            Some(id.clone())
+        );
+
+        store.revoke(&id)?;
+        assert_eq!(store.verify(&rotated, Some("api.localhost")), None);
+        assert!(store.revoke(&id).is_err());
+        assert!(store.create("nowhere", Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_store() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("api-keys.json");
+        let store = ApiKeyStore::open(&path)?;
+        let (id, key) = store.create("deploys", vec!["*".to_string()])?;
+
+        // Only the hash of the secret is written
+        let contents = std::fs::read_to_string(&path)?;
+        let (_, secret) = key
+            .strip_prefix(KEY_PREFIX)
+            .and_then(|key| key.split_once('_'))
+            .context("expected an ID and secret")?;
+        assert!(!contents.contains(secret));
+
+        let reopened = ApiKeyStore::open(&path)?;
+        assert_eq!(reopened.verify(&key, None), Some(id));
+        Ok(())
+    }
+
+    #[test]
+    fn test_authenticate() -> anyhow::Result<()> {
+        let store = ApiKeyStore::in_memory();
+        let (id, key) = store.create("billing", vec!["api.localhost".to_string()])?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(KEY_HEADER, HeaderValue::from_str(&key)?);
+        headers.insert(KEY_ID_HEADER, HeaderValue::from_static("spoofed"));
+        assert!(authenticate(Some(&store), Some("api.localhost"), &mut headers).is_none());
+        assert!(!headers.contains_key(KEY_HEADER));
+        assert_eq!(headers[KEY_ID_HEADER], id.as_str());
+
+        let mut headers = HeaderMap::new();
+        let rejected = authenticate(Some(&store), Some("api.localhost"), &mut headers);
+        assert_eq!(
+            rejected.map(|response| response.status()),
+            Some(StatusCode::UNAUTHORIZED)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+//! Sharing of compiled components between clustered hosts, so a component is compiled by
+//! the first host that starts it rather than by every host it's scheduled on.
+//!
+//! Hosts built with [`crate::host::HostBuilder::with_artifact_store`] look up the compiled
+//! artifacts of a workload's components and service in the store before starting it, by
+//! their artifact keys, see [`crate::engine::Engine::artifact_key`]. Those found are
+//! loaded rather than compiled; those the host compiles are put into the store once the
+//! workload started. Keys include the engine's settings, so hosts only share artifacts
+//! when they run the same wasmtime with the same settings. Looking artifacts up or
+//! putting them never fails a workload, and an artifact that can't be loaded is compiled
+//! as usual.
+//!
+//! Artifacts are native code that's run without being verified: anyone able to write to
+//! the store can run code on every host using it, so only use a store no one but the
+//! cluster's hosts can write to.
+
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use tokio::io::AsyncReadExt as _;
+use tracing::{debug, warn};
+
+use crate::engine::Engine;
+use crate::types::Workload;
+
+/// The NATS object store [`NatsArtifactStore`] keeps artifacts in by default.
+pub const DEFAULT_BUCKET: &str = "wash-artifacts";
+
+/// Where clustered hosts share compiled artifacts.
+#[async_trait::async_trait]
+pub trait ArtifactStore: Send + Sync + 'static {
+    /// Reads an artifact.
+    ///
+    /// # Returns
+    /// The artifact, or `None` if the store doesn't have it.
+    ///
+    /// # Errors
+    /// Returns an error if the store can't be read.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>>;
+
+    /// Writes an artifact, replacing any artifact with the same key.
+    ///
+    /// # Errors
+    /// Returns an error if the artifact can't be written.
+    async fn put(&self, key: &str, artifact: Bytes) -> anyhow::Result<()>;
+}
+
+/// Keeps artifacts in a NATS object store, created when it doesn't exist.
+#[derive(Clone)]
+pub struct NatsArtifactStore {
+    client: Arc<async_nats::Client>,
+    bucket: String,
+}
+
+impl NatsArtifactStore {
+    pub fn new(client: Arc<async_nats::Client>) -> Self {
+        Self {
+            client,
+            bucket: DEFAULT_BUCKET.to_string(),
+        }
+    }
+
+    /// Keeps artifacts in `bucket` rather than [`DEFAULT_BUCKET`].
+    pub fn with_bucket(mut self, bucket: impl Into<String>) -> Self {
+        self.bucket = bucket.into();
+        self
+    }
+
+    async fn store(&self) -> anyhow::Result<async_nats::jetstream::object_store::ObjectStore> {
+        let jetstream = async_nats::jetstream::new((*self.client).clone());
+        if let Ok(store) = jetstream.get_object_store(&self.bucket).await {
+            return Ok(store);
+        }
+        jetstream
+            .create_object_store(async_nats::jetstream::object_store::Config {
+                bucket: self.bucket.clone(),
+                description: Some("Compiled components shared by wash hosts".to_string()),
+                ..Default::default()
+            })
+            .await
+            .with_context(|| format!("failed to create object store {}", self.bucket))
+    }
+}
+
+#[async_trait::async_trait]
+impl ArtifactStore for NatsArtifactStore {
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        use async_nats::jetstream::object_store::GetErrorKind;
+
+        let mut object = match self.store().await?.get(key).await {
+            Ok(object) => object,
+            Err(e) if e.kind() == GetErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("failed to get artifact {key}")),
+        };
+        let mut artifact = Vec::with_capacity(object.info().size);
+        object
+            .read_to_end(&mut artifact)
+            .await
+            .with_context(|| format!("failed to read artifact {key}"))?;
+        Ok(Some(artifact.into()))
+    }
+
+    async fn put(&self, key: &str, artifact: Bytes) -> anyhow::Result<()> {
+        self.store()
+            .await?
+            .put(key, &mut &artifact[..])
+            .await
+            .with_context(|| format!("failed to put artifact {key}"))?;
+        Ok(())
+    }
+}
+
+/// Loads the artifacts of the components a host starts from a store, and puts those it
+/// compiles into it.
+pub struct SharedArtifacts {
+    store: Arc<dyn ArtifactStore>,
+}
+
+impl SharedArtifacts {
+    pub fn new(store: Arc<dyn ArtifactStore>) -> Self {
+        Self { store }
+    }
+
+    /// Loads the artifacts of a workload's components and service that `engine` doesn't
+    /// have cached from the store.
+    ///
+    /// # Returns
+    /// The keys of the components `engine` will compile, to [`Self::publish`] once it has.
+    pub async fn fetch(&self, engine: &Engine, workload: &Workload) -> Vec<String> {
+        let bytes = workload
+            .components
+            .iter()
+            .map(|component| &component.bytes)
+            .chain(workload.service.iter().map(|service| &service.bytes));
+        let mut compiled = Vec::new();
+        for bytes in bytes {
+            let key = engine.artifact_key(bytes);
+            if engine.has_artifact(&key) || compiled.contains(&key) {
+                continue;
+            }
+            match self.store.get(&key).await {
+                Ok(Some(artifact)) => {
+                    // SAFETY: the store is trusted to hold artifacts serialized by the
+                    // cluster's hosts, see the module docs
+                    match unsafe { engine.load_artifact(&key, &artifact) } {
+                        Ok(()) => {
+                            debug!(key, "loaded compiled component from artifact store");
+                            continue;
+                        }
+                        Err(e) => warn!(key, err = ?e, "failed to load shared artifact"),
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!(key, err = ?e, "failed to look up shared artifact"),
+            }
+            compiled.push(key);
+        }
+        compiled
+    }
+
+    /// Puts the artifacts `engine` compiled into the store, in the background.
+    pub fn publish(&self, engine: &Engine, keys: Vec<String>) {
+        if keys.is_empty() {
+            return;
+        }
+        let store = self.store.clone();
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            for key in keys {
+                let artifact = match engine.serialize_artifact(&key) {
+                    Ok(Some(artifact)) => artifact,
+                    // Dropped from the engine's cache meanwhile
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!(key, err = ?e, "failed to serialize artifact");
+                        continue;
+                    }
+                };
+                match store.put(&key, artifact.into()).await {
+                    Ok(()) => debug!(key, "put compiled component into artifact store"),
+                    Err(e) => warn!(key, err = ?e, "failed to share artifact"),
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::types::Component;
+
+    const EMPTY_COMPONENT: [u8; 8] = [0x00, 0x61, 0x73, 0x6d, 0x0d, 0x00, 0x01, 0x00];
+
+    #[derive(Default)]
+    struct MemoryArtifactStore {
+        artifacts: Mutex<HashMap<String, Bytes>>,
+    }
+
+    #[async_trait::async_trait]
+    impl ArtifactStore for MemoryArtifactStore {
+        async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+            Ok(self.artifacts.lock().unwrap().get(key).cloned())
+        }
+
+        async fn put(&self, key: &str, artifact: Bytes) -> anyhow::Result<()> {
+            self.artifacts
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), artifact);
+            Ok(())
+        }
+    }
+
+    fn workload() -> Workload {
+        Workload {
+            namespace: "default".to_string(),
+            name: "artifacts".to_string(),
+            annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
+            service: None,
+            components: vec![Component {
+                bytes: Bytes::from_static(&EMPTY_COMPONENT),
+                ..Default::default()
+            }],
+            host_interfaces: vec![],
+            volumes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_share_between_engines() -> anyhow::Result<()> {
+        let store = Arc::new(MemoryArtifactStore::default());
+        let shared = SharedArtifacts::new(store.clone());
+        let first = Engine::builder().with_pooling_allocator(false).build()?;
+        let second = Engine::builder().with_pooling_allocator(false).build()?;
+        let key = first.artifact_key(&EMPTY_COMPONENT);
+
+        assert_eq!(
+            shared.fetch(&first, &workload()).await,
+            std::slice::from_ref(&key)
+        );
+        first.compile_component(&EMPTY_COMPONENT)?;
+        shared.publish(&first, vec![key.clone()]);
+        while store.get(&key).await?.is_none() {
+            tokio::task::yield_now().await;
+        }
+
+        assert!(shared.fetch(&second, &workload()).await.is_empty());
+        assert!(second.has_artifact(&key));
+        Ok(())
+    }
+}
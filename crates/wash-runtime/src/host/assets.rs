@@ -0,0 +1,474 @@
+//! Static files served by the [HTTP server](crate::host::http) next to workloads, so
+//! single-page apps and their assets don't round-trip through a component for every
+//! file.
+//!
+//! A [`StaticMount`] serves an [`AssetSource`] under a path prefix of a virtual host:
+//! a `GET` or `HEAD` request for `/assets/app.js` on a mount at `/assets` reads `app.js`
+//! from the source. Paths ending in `/` serve the mount's index file. Files the source
+//! doesn't have fall through to the workload serving the virtual host, unless the mount
+//! has a fallback file, which single-page apps use to serve `index.html` for client-side
+//! routes. Other methods always go to the workload.
+//!
+//! Responses carry a `Content-Type` guessed from the file extension and, when the
+//! source provides one, an `ETag` that `If-None-Match` is checked against.
+//!
+//! Sources read whole files into memory, so they suit typical web assets rather than
+//! large downloads. [`DirectoryAssets`] reads a local directory and
+//! [`ObjectStoreAssets`] a NATS object store, such as a `wasi:blobstore` container.
+//! Encrypted containers can't be served.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Context as _, ensure};
+use bytes::Bytes;
+use http_body_util::{BodyExt as _, Full};
+use hyper::{Method, StatusCode, header};
+use tokio::io::AsyncReadExt as _;
+use tracing::{debug, error};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+/// The index file served for paths ending in `/`, unless a mount sets another.
+pub const DEFAULT_INDEX: &str = "index.html";
+
+/// A file read from an [`AssetSource`].
+#[derive(Debug, Clone)]
+pub struct Asset {
+    pub body: Bytes,
+    /// An opaque validator of the file's contents, quoted in the `ETag` header
+    pub etag: Option<String>,
+}
+
+/// A store of static files, such as a directory.
+#[async_trait::async_trait]
+pub trait AssetSource: Send + Sync + 'static {
+    /// Reads a file.
+    ///
+    /// # Arguments
+    /// * `path` - The file's path relative to the source, with `/` separators and no
+    ///   empty, `.` or `..` segments
+    ///
+    /// # Returns
+    /// The file, or `None` if the source doesn't have it.
+    ///
+    /// # Errors
+    /// Returns an error if the source can't be read.
+    async fn get(&self, path: &str) -> anyhow::Result<Option<Asset>>;
+}
+
+/// Serves the files in a local directory.
+#[derive(Debug, Clone)]
+pub struct DirectoryAssets {
+    root: PathBuf,
+}
+
+impl DirectoryAssets {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl AssetSource for DirectoryAssets {
+    async fn get(&self, path: &str) -> anyhow::Result<Option<Asset>> {
+        let file = self.root.join(path);
+        let metadata = match tokio::fs::metadata(&file).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to stat {}", file.display()));
+            }
+        };
+        let body = tokio::fs::read(&file)
+            .await
+            .with_context(|| format!("failed to read {}", file.display()))?;
+        let etag = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|modified| format!("{:x}-{:x}", modified.as_millis(), body.len()));
+        Ok(Some(Asset {
+            body: body.into(),
+            etag,
+        }))
+    }
+}
+
+/// Serves the objects in a NATS object store, named by their paths.
+#[derive(Clone)]
+pub struct ObjectStoreAssets {
+    client: Arc<async_nats::Client>,
+    bucket: String,
+}
+
+impl ObjectStoreAssets {
+    pub fn new(client: Arc<async_nats::Client>, bucket: impl Into<String>) -> Self {
+        Self {
+            client,
+            bucket: bucket.into(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AssetSource for ObjectStoreAssets {
+    async fn get(&self, path: &str) -> anyhow::Result<Option<Asset>> {
+        use async_nats::jetstream::object_store::GetErrorKind;
+
+        // The bucket is looked up per request, so it may be created after the mount
+        let store = async_nats::jetstream::new((*self.client).clone())
+            .get_object_store(&self.bucket)
+            .await
+            .with_context(|| format!("failed to open object store {}", self.bucket))?;
+        let mut object = match store.get(path).await {
+            Ok(object) => object,
+            Err(e) if e.kind() == GetErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to get object {path}"));
+            }
+        };
+        let etag = object.info().digest.clone();
+        let mut body = Vec::with_capacity(object.info().size);
+        object
+            .read_to_end(&mut body)
+            .await
+            .with_context(|| format!("failed to read object {path}"))?;
+        Ok(Some(Asset {
+            body: body.into(),
+            etag,
+        }))
+    }
+}
+
+/// An [`AssetSource`] served under a path prefix of a virtual host.
+#[derive(Clone)]
+pub struct StaticMount {
+    host: String,
+    prefix: String,
+    source: Arc<dyn AssetSource>,
+    index: String,
+    fallback: Option<String>,
+}
+
+impl std::fmt::Debug for StaticMount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StaticMount")
+            .field("host", &self.host)
+            .field("prefix", &self.prefix)
+            .field("index", &self.index)
+            .field("fallback", &self.fallback)
+            .finish()
+    }
+}
+
+impl StaticMount {
+    /// Creates a mount serving `source` under `prefix` of the virtual host `host`.
+    ///
+    /// # Arguments
+    /// * `host` - The virtual host, matched against the `Host` header with or without its
+    ///   port, or `*` for every host
+    /// * `prefix` - The path the files are served under, such as `/` or `/assets`
+    /// * `source` - The files to serve
+    pub fn new(host: impl Into<String>, prefix: &str, source: Arc<dyn AssetSource>) -> Self {
+        Self {
+            host: host.into(),
+            prefix: prefix.trim_end_matches('/').to_string(),
+            source,
+            index: DEFAULT_INDEX.to_string(),
+            fallback: None,
+        }
+    }
+
+    /// Sets the file served for paths ending in `/`, [`DEFAULT_INDEX`] by default.
+    pub fn with_index(mut self, index: impl Into<String>) -> Self {
+        self.index = index.into();
+        self
+    }
+
+    /// Serves `path` in place of files the source doesn't have, instead of passing the
+    /// request on to the workload.
+    pub fn with_fallback(mut self, path: impl Into<String>) -> Self {
+        self.fallback = Some(path.into());
+        self
+    }
+
+    /// Returns the path of a requested file relative to the mount, or `None` if the
+    /// request isn't for this mount.
+    fn file_path(&self, host: Option<&str>, path: &str) -> Option<String> {
+        if self.host != "*" {
+            let host = host?;
+            let without_port = host.rsplit_once(':').map_or(host, |(host, _port)| host);
+            if self.host != host && self.host != without_port {
+                return None;
+            }
+        }
+        let rest = path.strip_prefix(&self.prefix)?;
+        if !(rest.is_empty() || rest.starts_with('/')) {
+            return None;
+        }
+        let rest = rest.trim_start_matches('/');
+        if rest.is_empty() || rest.ends_with('/') {
+            Some(format!("{rest}{}", self.index))
+        } else {
+            Some(rest.to_string())
+        }
+    }
+}
+
+/// Serves a request from the first mount it's for that has the requested file.
+///
+/// # Returns
+/// The response, or `None` if no mount serves the request and it should go to the
+/// workload.
+pub(crate) async fn serve<B>(
+    mounts: &[StaticMount],
+    req: &hyper::Request<B>,
+) -> Option<hyper::Response<HyperOutgoingBody>> {
+    if mounts.is_empty() || !matches!(*req.method(), Method::GET | Method::HEAD) {
+        return None;
+    }
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()));
+    for mount in mounts {
+        let Some(path) = mount.file_path(host, req.uri().path()) else {
+            continue;
+        };
+        let Ok(path) = sanitize(&path) else {
+            return Some(response(StatusCode::BAD_REQUEST, None));
+        };
+        let mut found = mount.source.get(&path).await;
+        let mut served = path;
+        if matches!(found, Ok(None))
+            && let Some(fallback) = &mount.fallback
+        {
+            served = fallback.trim_start_matches('/').to_string();
+            found = mount.source.get(&served).await;
+        }
+        match found {
+            Ok(Some(asset)) => {
+                debug!(path = served, prefix = mount.prefix, "serving static file");
+                return Some(asset_response(req, &served, asset));
+            }
+            Ok(None) => continue,
+            Err(e) => {
+                error!(err = ?e, path = served, "failed to read static file");
+                return Some(response(StatusCode::INTERNAL_SERVER_ERROR, None));
+            }
+        }
+    }
+    None
+}
+
+/// Decodes a request path relative to a mount, rejecting paths that could escape it.
+fn sanitize(path: &str) -> anyhow::Result<String> {
+    let mut decoded = Vec::with_capacity(path.len());
+    let mut bytes = path.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [
+                bytes.next().context("truncated escape")?,
+                bytes.next().context("truncated escape")?,
+            ];
+            let hex = std::str::from_utf8(&hex).context("invalid escape")?;
+            decoded.push(u8::from_str_radix(hex, 16).context("invalid escape")?);
+        } else {
+            decoded.push(byte);
+        }
+    }
+    let decoded = String::from_utf8(decoded).context("path is not valid UTF-8")?;
+    ensure!(
+        !decoded.contains(['\\', '\0']),
+        "path contains a forbidden character"
+    );
+    ensure!(
+        decoded
+            .split('/')
+            .all(|segment| !matches!(segment, "" | "." | "..")),
+        "path contains an empty, '.' or '..' segment"
+    );
+    Ok(decoded)
+}
+
+fn asset_response<B>(
+    req: &hyper::Request<B>,
+    path: &str,
+    asset: Asset,
+) -> hyper::Response<HyperOutgoingBody> {
+    let etag = asset.etag.map(|etag| format!("\"{etag}\""));
+    if let Some(etag) = &etag
+        && req
+            .headers()
+            .get(header::IF_NONE_MATCH)
+            .and_then(|h| h.to_str().ok())
+            .is_some_and(|tags| {
+                tags.split(',')
+                    .any(|tag| tag.trim() == etag || tag.trim() == "*")
+            })
+    {
+        let mut resp = response(StatusCode::NOT_MODIFIED, None);
+        if let Ok(value) = etag.parse() {
+            resp.headers_mut().insert(header::ETAG, value);
+        }
+        return resp;
+    }
+
+    let len = asset.body.len();
+    let body = (req.method() != Method::HEAD).then_some(asset.body);
+    let mut resp = response(StatusCode::OK, body);
+    let headers = resp.headers_mut();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(content_type(path)),
+    );
+    headers.insert(header::CONTENT_LENGTH, len.into());
+    if let Some(value) = etag.and_then(|etag| etag.parse().ok()) {
+        headers.insert(header::ETAG, value);
+    }
+    resp
+}
+
+fn response(status: StatusCode, body: Option<Bytes>) -> hyper::Response<HyperOutgoingBody> {
+    let body = match body {
+        Some(body) => Full::new(body)
+            .map_err(|never: std::convert::Infallible| match never {})
+            .boxed(),
+        None => HyperOutgoingBody::default(),
+    };
+    hyper::Response::builder()
+        .status(status)
+        .body(body)
+        .expect("failed to build static file response")
+}
+
+/// Guesses the content type of a file from its extension.
+fn content_type(path: &str) -> &'static str {
+    let extension = path
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "webmanifest" => "application/manifest+json",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_path() {
+        let source = Arc::new(DirectoryAssets::new("/srv/site"));
+        let mount = StaticMount::new("app.localhost", "/assets/", source.clone());
+        assert_eq!(
+            mount.file_path(Some("app.localhost:8080"), "/assets/js/app.js"),
+            Some("js/app.js".to_string())
+        );
+        assert_eq!(
+            mount.file_path(Some("app.localhost"), "/assets"),
+            Some("index.html".to_string())
+        );
+        assert_eq!(
+            mount.file_path(Some("app.localhost"), "/assets/docs/"),
+            Some("docs/index.html".to_string())
+        );
+        assert_eq!(mount.file_path(Some("app.localhost"), "/assetsfoo"), None);
+        assert_eq!(mount.file_path(Some("other.localhost"), "/assets/a"), None);
+        assert_eq!(mount.file_path(None, "/assets/a"), None);
+
+        let root = StaticMount::new("*", "/", source).with_index("home.html");
+        assert_eq!(root.file_path(None, "/"), Some("home.html".to_string()));
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(sanitize("js/app%20v2.js").unwrap(), "js/app v2.js");
+        assert!(sanitize("../secret").is_err());
+        assert!(sanitize("a/%2e%2e/b").is_err());
+        assert!(sanitize("a//b").is_err());
+        assert!(sanitize("a\\..\\b").is_err());
+        assert!(sanitize("a%2").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_serve_directory() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        tokio::fs::write(dir.path().join("index.html"), "<h1>hi</h1>").await?;
+        tokio::fs::write(dir.path().join("app.js"), "run()").await?;
+        let mounts = [StaticMount::new(
+            "app.localhost",
+            "/",
+            Arc::new(DirectoryAssets::new(dir.path())),
+        )
+        .with_fallback("index.html")];
+        let request = |path: &str| {
+            hyper::Request::get(path)
+                .header(header::HOST, "app.localhost")
+                .body(())
+                .unwrap()
+        };
+
+        let resp = serve(&mounts, &request("/app.js")).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/javascript; charset=utf-8"
+        );
+        let etag = resp.headers()[header::ETAG].clone();
+        let body = resp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, "run()");
+
+        // Client-side routes get the fallback
+        let resp = serve(&mounts, &request("/orders/42")).await.unwrap();
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+
+        let mut conditional = request("/app.js");
+        conditional
+            .headers_mut()
+            .insert(header::IF_NONE_MATCH, etag);
+        let resp = serve(&mounts, &conditional).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+
+        let post = hyper::Request::post("/app.js")
+            .header(header::HOST, "app.localhost")
+            .body(())?;
+        assert!(serve(&mounts, &post).await.is_none());
+        assert_eq!(
+            serve(&mounts, &request("/../etc/passwd"))
+                .await
+                .unwrap()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        Ok(())
+    }
+}
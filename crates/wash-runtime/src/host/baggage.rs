@@ -0,0 +1,208 @@
+//! [W3C Baggage] carried through components, so values like a tenant ID or feature flags
+//! set at the edge reach every service a request passes through.
+//!
+//! The HTTP server keeps the entries of an incoming request's `baggage` header whose keys
+//! are selected with [`crate::host::http::HttpServer::with_baggage`], and removes the
+//! rest before the component sees the header. The kept entries are then added to:
+//!
+//! - the outgoing HTTP requests the component sends, next to any entries it sets itself
+//! - the messages it publishes or sends as requests with `wasmcloud:messaging`
+//!
+//! Messages delivered to a component's `wasmcloud:messaging` handler carry their `baggage`
+//! header on to what the component sends in the same way. Without selected keys, the
+//! HTTP server passes `baggage` headers through untouched and doesn't propagate them.
+//!
+//! [W3C Baggage]: https://www.w3.org/TR/baggage/
+
+use std::fmt;
+
+use hyper::header::{HeaderMap, HeaderValue};
+
+/// The header baggage is carried in.
+pub const BAGGAGE_HEADER: &str = "baggage";
+/// The most entries kept, as the specification requires propagating at least this many.
+const MAX_MEMBERS: usize = 64;
+/// The longest baggage kept, in bytes.
+const MAX_BYTES: usize = 8192;
+
+/// The baggage entries the HTTP server propagates.
+#[derive(Clone, Debug, Default)]
+pub struct BaggageConfig {
+    /// The keys of the entries kept, where `*` keeps every entry
+    pub keys: Vec<String>,
+}
+
+impl BaggageConfig {
+    fn selects(&self, key: &str) -> bool {
+        self.keys
+            .iter()
+            .any(|selected| selected == "*" || selected == key)
+    }
+
+    /// Reduces a request's baggage to the selected entries.
+    ///
+    /// # Returns
+    /// The kept entries, or `None` if there are none or no keys are selected.
+    pub(crate) fn extract(&self, headers: &mut HeaderMap) -> Option<Baggage> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let mut baggage = Baggage::from_headers(headers);
+        baggage
+            .members
+            .retain(|member| self.selects(member_key(member)));
+        headers.remove(BAGGAGE_HEADER);
+        let value = baggage.to_header_value()?;
+        headers.insert(BAGGAGE_HEADER, value);
+        Some(baggage)
+    }
+}
+
+/// The entries of a `baggage` header, each kept as written, e.g. `tenant=acme;ttl=60`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Baggage {
+    members: Vec<String>,
+}
+
+impl Baggage {
+    /// Parses the values of `baggage` headers, skipping malformed entries and those past
+    /// the size limits.
+    pub fn parse<'a>(values: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut members: Vec<String> = Vec::new();
+        let mut bytes = 0;
+        for member in values.into_iter().flat_map(|value| value.split(',')) {
+            let member = member.trim();
+            if !is_valid(member) {
+                continue;
+            }
+            if members.len() == MAX_MEMBERS || bytes + member.len() > MAX_BYTES {
+                break;
+            }
+            bytes += member.len() + 1;
+            members.push(member.to_string());
+        }
+        Self { members }
+    }
+
+    fn from_headers(headers: &HeaderMap) -> Self {
+        Self::parse(
+            headers
+                .get_all(BAGGAGE_HEADER)
+                .into_iter()
+                .filter_map(|value| value.to_str().ok()),
+        )
+    }
+
+    /// Whether there are no entries.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    /// The value of the entry with `key`, as written.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.members
+            .iter()
+            .find(|member| member_key(member) == key)
+            .map(|member| member_value(member))
+    }
+
+    fn to_header_value(&self) -> Option<HeaderValue> {
+        if self.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&self.to_string()).ok()
+    }
+
+    /// Adds the entries to a request's `baggage`, keeping the request's own entries where
+    /// both have the same key.
+    pub(crate) fn inject(&self, headers: &mut HeaderMap) {
+        let mut merged = Baggage::from_headers(headers);
+        for member in &self.members {
+            if merged.get(member_key(member)).is_none() {
+                merged.members.push(member.clone());
+            }
+        }
+        if let Some(value) = merged.to_header_value() {
+            headers.insert(BAGGAGE_HEADER, value);
+        }
+    }
+}
+
+impl fmt::Display for Baggage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.members.join(","))
+    }
+}
+
+fn member_key(member: &str) -> &str {
+    member.split_once('=').map_or(member, |(key, _)| key).trim()
+}
+
+fn member_value(member: &str) -> &str {
+    let value = member.split_once('=').map_or("", |(_, value)| value);
+    value
+        .split_once(';')
+        .map_or(value, |(value, _)| value)
+        .trim()
+}
+
+/// Entries need a key that's a token and a value of visible characters.
+fn is_valid(member: &str) -> bool {
+    let Some((key, _)) = member.split_once('=') else {
+        return false;
+    };
+    let key = key.trim();
+    !key.is_empty()
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && !b"\"(),/:;<=>?@[\\]{}".contains(&b))
+        && member.bytes().all(|b| b == b' ' || b.is_ascii_graphic())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_selected_keys() {
+        let config = BaggageConfig {
+            keys: vec!["tenant".to_string(), "flags".to_string()],
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            BAGGAGE_HEADER,
+            HeaderValue::from_static("tenant=acme;ttl=60, user=bob, bad entry"),
+        );
+        headers.append(BAGGAGE_HEADER, HeaderValue::from_static("flags=beta"));
+        let baggage = config.extract(&mut headers).expect("entries are kept");
+        assert_eq!(baggage.get("tenant"), Some("acme"));
+        assert_eq!(baggage.get("user"), None);
+        assert_eq!(headers[BAGGAGE_HEADER], "tenant=acme;ttl=60,flags=beta");
+
+        // Without selected keys nothing is touched
+        let mut headers = HeaderMap::new();
+        headers.insert(BAGGAGE_HEADER, HeaderValue::from_static("user=bob"));
+        assert!(BaggageConfig::default().extract(&mut headers).is_none());
+        assert_eq!(headers[BAGGAGE_HEADER], "user=bob");
+
+        // Nor is the header left behind when nothing's kept
+        assert!(config.extract(&mut headers).is_none());
+        assert!(!headers.contains_key(BAGGAGE_HEADER));
+    }
+
+    #[test]
+    fn test_inject() {
+        let baggage = Baggage::parse(["tenant=acme,flags=beta"]);
+        let mut headers = HeaderMap::new();
+        headers.insert(BAGGAGE_HEADER, HeaderValue::from_static("flags=alpha"));
+        baggage.inject(&mut headers);
+        assert_eq!(headers[BAGGAGE_HEADER], "flags=alpha,tenant=acme");
+    }
+
+    #[test]
+    fn test_limits() {
+        let values: Vec<String> = (0..100).map(|i| format!("k{i}=v")).collect();
+        let baggage = Baggage::parse(values.iter().map(String::as_str));
+        assert_eq!(baggage.members.len(), MAX_MEMBERS);
+    }
+}
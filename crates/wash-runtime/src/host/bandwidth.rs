@@ -0,0 +1,302 @@
+//! Bandwidth limits, so a workload moving a lot of data, such as a backup, can't saturate
+//! the host's network and starve latency-sensitive workloads.
+//!
+//! A component sets its limit in bytes per second under [`BANDWIDTH_LIMIT_CONFIG`] in its
+//! [`LocalResources::config`]. If all of a workload's components and its service set one,
+//! the workload gets a [`WorkloadBandwidth`] of their sum, shared by:
+//!
+//! - the bodies of its outgoing HTTP requests and of their responses
+//! - the objects it reads from and writes to blobstores
+//!
+//! Transfers are throttled with a token bucket holding up to a second's worth of bytes, so
+//! short bursts pass at once and sustained transfers average the limit. Chunks are
+//! throttled after the fact: a chunk passes, and the next one waits until the workload is
+//! back under its limit. Blobstores holding objects in memory hand them over in one piece,
+//! so reading a large object from them waits as long as streaming it would have.
+
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use hyper::body::{Body, Frame, SizeHint};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Sleep;
+
+use crate::types::LocalResources;
+
+/// The key in a component's [`LocalResources::config`] setting how many bytes per second
+/// its workload may transfer, e.g. `10485760` for 10 MiB/s.
+pub const BANDWIDTH_LIMIT_CONFIG: &str = "bandwidth-limit";
+
+/// Reads a component's bandwidth limit.
+///
+/// # Returns
+/// The limit in bytes per second, or `None` if the component doesn't set one.
+///
+/// # Errors
+/// Returns an error if the limit isn't a positive number of bytes.
+pub(crate) fn limit(resources: &LocalResources) -> anyhow::Result<Option<u64>> {
+    resources
+        .config
+        .get(BANDWIDTH_LIMIT_CONFIG)
+        .map(|bytes| {
+            bytes
+                .trim()
+                .parse()
+                .ok()
+                .filter(|bytes| *bytes > 0)
+                .with_context(|| format!("invalid {BANDWIDTH_LIMIT_CONFIG} '{bytes}'"))
+        })
+        .transpose()
+}
+
+/// The sum of the bandwidth limits of a workload's components, if they all have one.
+///
+/// # Errors
+/// Returns an error if a limit is invalid.
+pub(crate) fn workload_limit<'a>(
+    resources: impl IntoIterator<Item = &'a LocalResources>,
+) -> anyhow::Result<Option<u64>> {
+    let mut sum = Some(0u64);
+    let mut any = false;
+    for resources in resources {
+        any = true;
+        sum = match limit(resources)? {
+            Some(bytes) => sum.map(|sum| sum.saturating_add(bytes)),
+            None => None,
+        };
+    }
+    Ok(sum.filter(|_| any))
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// Bytes per second, or `None` if the workload isn't limited
+    rate: Option<u64>,
+    /// The bytes that can pass at once, negative while transfers wait
+    available: f64,
+    refilled: Instant,
+}
+
+/// The bandwidth a workload's transfers share.
+#[derive(Debug)]
+pub struct WorkloadBandwidth {
+    bucket: Mutex<Bucket>,
+}
+
+impl Default for WorkloadBandwidth {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl WorkloadBandwidth {
+    /// Creates a limit of `rate` bytes per second, or no limit if `None`.
+    pub fn new(rate: Option<u64>) -> Self {
+        Self {
+            bucket: Mutex::new(Bucket {
+                rate,
+                available: rate.unwrap_or(0) as f64,
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// The limit in bytes per second, if the workload has one.
+    pub fn rate(&self) -> Option<u64> {
+        self.bucket
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .rate
+    }
+
+    /// Changes the limit, e.g. when the workload's components are updated.
+    pub fn set_rate(&self, rate: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+        if bucket.rate != rate {
+            *bucket = Bucket {
+                rate,
+                available: rate.unwrap_or(0) as f64,
+                refilled: Instant::now(),
+            };
+        }
+    }
+
+    /// Counts `bytes` against the limit.
+    ///
+    /// # Returns
+    /// How long to wait before transferring anything more.
+    pub fn reserve(&self, bytes: usize) -> Duration {
+        self.reserve_at(bytes, Instant::now())
+    }
+
+    fn reserve_at(&self, bytes: usize, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(rate) = bucket.rate else {
+            return Duration::ZERO;
+        };
+        let rate = rate as f64;
+        let elapsed = now.saturating_duration_since(bucket.refilled);
+        bucket.available = (bucket.available + elapsed.as_secs_f64() * rate).min(rate);
+        bucket.refilled = now;
+        bucket.available -= bytes as f64;
+        if bucket.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.available / rate)
+        }
+    }
+
+    /// Counts `bytes` against the limit, waiting until the workload is back under it.
+    pub async fn throttle(&self, bytes: usize) {
+        let wait = self.reserve(bytes);
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// A body whose data frames are throttled by a workload's bandwidth.
+pub(crate) struct ThrottledBody<B> {
+    inner: B,
+    bandwidth: Arc<WorkloadBandwidth>,
+    /// Waits until the workload is back under its limit before the next frame
+    wait: Option<Pin<Box<Sleep>>>,
+}
+
+impl<B> ThrottledBody<B> {
+    pub(crate) fn new(inner: B, bandwidth: Arc<WorkloadBandwidth>) -> Self {
+        Self {
+            inner,
+            bandwidth,
+            wait: None,
+        }
+    }
+}
+
+impl<B: Body<Data = Bytes> + Unpin> Body for ThrottledBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        if let Some(wait) = &mut self.wait {
+            ready!(wait.as_mut().poll(cx));
+            self.wait = None;
+        }
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(data) = frame
+            .as_ref()
+            .and_then(|frame| frame.as_ref().ok())
+            .and_then(Frame::data_ref)
+        {
+            self.wait = sleep_for(self.bandwidth.reserve(data.len()));
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// A reader throttled by a workload's bandwidth, e.g. of an object streamed to or from a
+/// blobstore.
+pub struct ThrottledReader<R> {
+    inner: R,
+    bandwidth: Arc<WorkloadBandwidth>,
+    /// Waits until the workload is back under its limit before the next read
+    wait: Option<Pin<Box<Sleep>>>,
+}
+
+impl<R> ThrottledReader<R> {
+    pub fn new(inner: R, bandwidth: Arc<WorkloadBandwidth>) -> Self {
+        Self {
+            inner,
+            bandwidth,
+            wait: None,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if let Some(wait) = &mut self.wait {
+            ready!(wait.as_mut().poll(cx));
+            self.wait = None;
+        }
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+        self.wait = sleep_for(self.bandwidth.reserve(buf.filled().len() - filled));
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn sleep_for(wait: Duration) -> Option<Pin<Box<Sleep>>> {
+    (!wait.is_zero()).then(|| Box::pin(tokio::time::sleep(wait)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(limit: Option<&str>) -> LocalResources {
+        let mut resources = LocalResources::default();
+        if let Some(limit) = limit {
+            resources
+                .config
+                .insert(BANDWIDTH_LIMIT_CONFIG.to_string(), limit.to_string());
+        }
+        resources
+    }
+
+    #[test]
+    fn test_workload_limit() -> anyhow::Result<()> {
+        let limited = [resources(Some("1000")), resources(Some("500"))];
+        assert_eq!(workload_limit(&limited)?, Some(1500));
+        let partly = [resources(Some("1000")), resources(None)];
+        assert_eq!(workload_limit(&partly)?, None);
+        assert_eq!(workload_limit([])?, None);
+        assert!(workload_limit([&resources(Some("0"))]).is_err());
+        assert!(workload_limit([&resources(Some("10MB"))]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserve() {
+        let bandwidth = WorkloadBandwidth::new(Some(1000));
+        let start = bandwidth
+            .bucket
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .refilled;
+
+        // A second's worth passes at once, then transfers wait for the bucket to refill
+        assert_eq!(bandwidth.reserve_at(1000, start), Duration::ZERO);
+        assert_eq!(bandwidth.reserve_at(500, start), Duration::from_millis(500));
+        let later = start + Duration::from_millis(500);
+        assert_eq!(bandwidth.reserve_at(0, later), Duration::ZERO);
+        assert_eq!(bandwidth.reserve_at(250, later), Duration::from_millis(250));
+
+        // Idle time refills at most a second's worth
+        let idle = later + Duration::from_secs(10);
+        assert_eq!(bandwidth.reserve_at(1000, idle), Duration::ZERO);
+
+        bandwidth.set_rate(None);
+        assert_eq!(bandwidth.reserve_at(usize::MAX, idle), Duration::ZERO);
+    }
+}
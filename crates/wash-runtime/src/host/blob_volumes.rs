@@ -0,0 +1,603 @@
+//! Blobstore volumes, letting components read and write the objects of a blobstore
+//! container as files, without using `wasi:blobstore`.
+//!
+//! A [`BlobstoreVolume`] names a container of the blobstore registered with
+//! [`crate::host::HostBuilder::with_volume_blobstore`]. When a workload mounting one
+//! starts, the container's objects are downloaded into a local directory, which is mounted
+//! like a host path. Objects named with `/` become nested directories; objects whose names
+//! can't be paths, e.g. with `..` segments, are skipped. WASI preopens real directories,
+//! so the container is copied when the workload starts rather than object by object as
+//! components read it, and it doesn't see objects written to the container afterwards.
+//!
+//! Volumes are mounted read-only, whatever their mounts say, unless they sync writes.
+//! Files of those written, added or removed by components are put into or deleted from
+//! the container every [`SYNC_INTERVAL`] and when the workload stops. Files are uploaded
+//! whole, so a file being written during a sync may be uploaded half-written and again
+//! at the next sync. Objects changed in the container meanwhile are overwritten.
+//!
+//! The directories are removed when the workload stops. Encrypted containers can't be
+//! mounted.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context as _, bail};
+use bytes::Bytes;
+use futures::StreamExt as _;
+use tokio::io::AsyncReadExt as _;
+use tokio::task::JoinHandle;
+use tokio_util::sync::{CancellationToken, DropGuard};
+use tracing::{debug, warn};
+
+use crate::types::{BlobstoreVolume, HostPathVolume, VolumeType, Workload};
+
+/// How often the files of volumes that sync writes are put into their containers.
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A blobstore the containers of [`BlobstoreVolume`]s are read from and written to.
+#[async_trait::async_trait]
+pub trait VolumeBlobstore: Send + Sync + 'static {
+    /// Lists the names of the objects in a container.
+    ///
+    /// # Errors
+    /// Returns an error if the container doesn't exist or can't be read.
+    async fn list(&self, container: &str) -> anyhow::Result<Vec<String>>;
+
+    /// Reads an object.
+    ///
+    /// # Errors
+    /// Returns an error if the object doesn't exist or can't be read.
+    async fn get(&self, container: &str, name: &str) -> anyhow::Result<Bytes>;
+
+    /// Writes an object, replacing any object of the same name.
+    ///
+    /// # Errors
+    /// Returns an error if the object can't be written.
+    async fn put(&self, container: &str, name: &str, body: Bytes) -> anyhow::Result<()>;
+
+    /// Deletes an object.
+    ///
+    /// # Errors
+    /// Returns an error if the object can't be deleted.
+    async fn delete(&self, container: &str, name: &str) -> anyhow::Result<()>;
+}
+
+/// Reads and writes the NATS object stores `wasi:blobstore` containers are kept in.
+#[derive(Clone)]
+pub struct NatsVolumeBlobstore {
+    client: Arc<async_nats::Client>,
+}
+
+impl NatsVolumeBlobstore {
+    pub fn new(client: Arc<async_nats::Client>) -> Self {
+        Self { client }
+    }
+
+    async fn store(
+        &self,
+        container: &str,
+    ) -> anyhow::Result<async_nats::jetstream::object_store::ObjectStore> {
+        async_nats::jetstream::new((*self.client).clone())
+            .get_object_store(container)
+            .await
+            .with_context(|| format!("failed to open object store {container}"))
+    }
+}
+
+#[async_trait::async_trait]
+impl VolumeBlobstore for NatsVolumeBlobstore {
+    async fn list(&self, container: &str) -> anyhow::Result<Vec<String>> {
+        let mut objects = self
+            .store(container)
+            .await?
+            .list()
+            .await
+            .with_context(|| format!("failed to list objects in {container}"))?;
+        let mut names = Vec::new();
+        while let Some(object) = objects.next().await {
+            let object =
+                object.with_context(|| format!("failed to list objects in {container}"))?;
+            if !object.deleted {
+                names.push(object.name);
+            }
+        }
+        Ok(names)
+    }
+
+    async fn get(&self, container: &str, name: &str) -> anyhow::Result<Bytes> {
+        let mut object = self
+            .store(container)
+            .await?
+            .get(name)
+            .await
+            .with_context(|| format!("failed to get object {name}"))?;
+        let mut body = Vec::with_capacity(object.info().size);
+        object
+            .read_to_end(&mut body)
+            .await
+            .with_context(|| format!("failed to read object {name}"))?;
+        Ok(body.into())
+    }
+
+    async fn put(&self, container: &str, name: &str, body: Bytes) -> anyhow::Result<()> {
+        self.store(container)
+            .await?
+            .put(name, &mut &body[..])
+            .await
+            .with_context(|| format!("failed to put object {name}"))?;
+        Ok(())
+    }
+
+    async fn delete(&self, container: &str, name: &str) -> anyhow::Result<()> {
+        self.store(container)
+            .await?
+            .delete(name)
+            .await
+            .with_context(|| format!("failed to delete object {name}"))
+    }
+}
+
+/// The length and modification time of a file, compared to find files written since the
+/// last sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileState {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+/// A container downloaded into a directory.
+struct MaterializedVolume {
+    name: String,
+    volume: BlobstoreVolume,
+    dir: tempfile::TempDir,
+    /// The files as of the last sync, keyed by object name
+    synced: HashMap<String, FileState>,
+}
+
+impl MaterializedVolume {
+    /// Downloads a container into a new directory.
+    async fn download(
+        store: &dyn VolumeBlobstore,
+        name: &str,
+        volume: &BlobstoreVolume,
+    ) -> anyhow::Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("wash-blobstore-")
+            .tempdir()
+            .with_context(|| format!("failed to create directory for volume '{name}'"))?;
+        for object in store.list(&volume.container).await? {
+            let Some(path) = object_path(&object) else {
+                warn!(
+                    volume = name,
+                    object, "skipping object whose name isn't a valid path"
+                );
+                continue;
+            };
+            let body = store.get(&volume.container, &object).await?;
+            let path = dir.path().join(path);
+            let written = async {
+                if let Some(parent) = path.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                tokio::fs::write(&path, body).await
+            };
+            if let Err(e) = written.await {
+                // e.g. both `a` and `a/b` exist, which a directory can't hold
+                warn!(volume = name, object, err = ?e, "skipping object that can't be written");
+            }
+        }
+        let synced = scan(dir.path()).with_context(|| format!("failed to scan volume '{name}'"))?;
+        debug!(
+            volume = name,
+            container = volume.container,
+            objects = synced.len(),
+            "downloaded blobstore volume"
+        );
+        Ok(Self {
+            name: name.to_string(),
+            volume: volume.clone(),
+            dir,
+            synced,
+        })
+    }
+
+    /// Puts the files written since the last sync into the container and deletes the
+    /// objects of removed files. Files that fail to sync are retried at the next sync.
+    async fn sync(&mut self, store: &dyn VolumeBlobstore) {
+        let files = match scan(self.dir.path()) {
+            Ok(files) => files,
+            Err(e) => {
+                warn!(volume = self.name, err = ?e, "failed to scan blobstore volume");
+                return;
+            }
+        };
+        let container = &self.volume.container;
+        for (object, state) in &files {
+            if self.synced.get(object) == Some(state) {
+                continue;
+            }
+            let path = self.dir.path().join(object);
+            let result = match tokio::fs::read(&path).await {
+                Ok(body) => store.put(container, object, body.into()).await,
+                Err(e) => Err(e.into()),
+            };
+            match result {
+                Ok(()) => {
+                    self.synced.insert(object.clone(), *state);
+                }
+                Err(e) => warn!(volume = self.name, object, err = ?e, "failed to sync file"),
+            }
+        }
+        let removed: Vec<_> = self
+            .synced
+            .keys()
+            .filter(|object| !files.contains_key(*object))
+            .cloned()
+            .collect();
+        for object in removed {
+            match store.delete(container, &object).await {
+                Ok(()) => {
+                    self.synced.remove(&object);
+                }
+                Err(e) => {
+                    warn!(volume = self.name, object, err = ?e, "failed to sync removed file")
+                }
+            }
+        }
+    }
+}
+
+/// The path of the file an object is downloaded to, relative to the volume's directory.
+fn object_path(name: &str) -> Option<PathBuf> {
+    let segments: Vec<_> = name.split('/').collect();
+    let valid = segments
+        .iter()
+        .all(|segment| !matches!(*segment, "" | "." | "..") && !segment.contains('\\'));
+    valid.then(|| segments.into_iter().collect())
+}
+
+/// The files under a volume's directory, keyed by object name, without following symlinks.
+fn scan(root: &Path) -> io::Result<HashMap<String, FileState>> {
+    fn walk(dir: &Path, prefix: &str, files: &mut HashMap<String, FileState>) -> io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let object = format!("{prefix}{name}");
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                walk(&entry.path(), &format!("{object}/"), files)?;
+            } else if file_type.is_file() {
+                let metadata = entry.metadata()?;
+                files.insert(
+                    object,
+                    FileState {
+                        len: metadata.len(),
+                        modified: metadata.modified().ok(),
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    let mut files = HashMap::new();
+    walk(root, "", &mut files)?;
+    Ok(files)
+}
+
+/// The downloaded volumes of a workload, synced by a task until it stops.
+struct WorkloadVolumes {
+    /// Cancels the task when dropped, e.g. if the workload fails to start
+    cancel: DropGuard,
+    /// Syncs the volumes until cancelled, then removes their directories
+    task: JoinHandle<()>,
+}
+
+/// The blobstore volumes of a host's running workloads.
+#[derive(Default)]
+pub struct BlobVolumes {
+    store: Option<Arc<dyn VolumeBlobstore>>,
+    /// Keyed by workload ID
+    running: Mutex<HashMap<String, WorkloadVolumes>>,
+}
+
+impl BlobVolumes {
+    pub fn new(store: Option<Arc<dyn VolumeBlobstore>>) -> Self {
+        Self {
+            store,
+            running: Mutex::default(),
+        }
+    }
+
+    /// Downloads the blobstore volumes of a workload and turns them into host path volumes
+    /// of the directories, which are kept until [`Self::release`] is called. Mounts of
+    /// volumes that don't sync writes are made read-only.
+    ///
+    /// # Errors
+    /// Returns an error if the workload has blobstore volumes but the host has no
+    /// blobstore, or a container can't be downloaded.
+    pub async fn materialize(
+        &self,
+        workload_id: &str,
+        workload: &mut Workload,
+    ) -> anyhow::Result<()> {
+        let mut volumes = Vec::new();
+        for volume in &workload.volumes {
+            let VolumeType::Blobstore(blobstore) = &volume.volume_type else {
+                continue;
+            };
+            let Some(store) = &self.store else {
+                bail!(
+                    "volume '{}' mounts a blobstore container, but the host has no blobstore for volumes",
+                    volume.name
+                );
+            };
+            volumes.push(
+                MaterializedVolume::download(store.as_ref(), &volume.name, blobstore)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to download container '{}' of volume '{}'",
+                            blobstore.container, volume.name
+                        )
+                    })?,
+            );
+        }
+        let Some(store) = self.store.clone() else {
+            return Ok(());
+        };
+        if volumes.is_empty() {
+            return Ok(());
+        }
+
+        for materialized in &volumes {
+            if let Some(volume) = workload
+                .volumes
+                .iter_mut()
+                .find(|volume| volume.name == materialized.name)
+            {
+                volume.volume_type = VolumeType::HostPath(HostPathVolume {
+                    local_path: materialized.dir.path().display().to_string(),
+                });
+            }
+            if materialized.volume.sync_writes {
+                continue;
+            }
+            for mount in workload
+                .components
+                .iter_mut()
+                .map(|component| &mut component.local_resources)
+                .chain(
+                    workload
+                        .service
+                        .iter_mut()
+                        .map(|service| &mut service.local_resources),
+                )
+                .flat_map(|resources| &mut resources.volume_mounts)
+                .filter(|mount| mount.name == materialized.name)
+            {
+                mount.read_only = true;
+            }
+        }
+
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn(sync(store, volumes, cancel.clone()));
+        self.running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                workload_id.to_string(),
+                WorkloadVolumes {
+                    cancel: cancel.drop_guard(),
+                    task,
+                },
+            );
+        Ok(())
+    }
+
+    /// Syncs the blobstore volumes of a stopped workload a last time and removes their
+    /// directories.
+    pub async fn release(&self, workload_id: &str) {
+        let volumes = self
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(workload_id);
+        if let Some(WorkloadVolumes { cancel, task }) = volumes {
+            drop(cancel);
+            if let Err(e) = task.await {
+                warn!(workload_id, err = ?e, "blobstore volume sync failed");
+            }
+        }
+    }
+}
+
+/// Syncs the volumes that sync writes every [`SYNC_INTERVAL`] until cancelled, and once
+/// more afterwards.
+async fn sync(
+    store: Arc<dyn VolumeBlobstore>,
+    mut volumes: Vec<MaterializedVolume>,
+    cancel: CancellationToken,
+) {
+    // Volumes that don't sync writes only need their directories kept until cancelled
+    if !volumes.iter().any(|volume| volume.volume.sync_writes) {
+        cancel.cancelled().await;
+        return;
+    }
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval.tick().await;
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = interval.tick() => {}
+        }
+        for volume in volumes
+            .iter_mut()
+            .filter(|volume| volume.volume.sync_writes)
+        {
+            volume.sync(store.as_ref()).await;
+        }
+    }
+    for volume in volumes
+        .iter_mut()
+        .filter(|volume| volume.volume.sync_writes)
+    {
+        volume.sync(store.as_ref()).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    use crate::types::{Volume, VolumeMount};
+
+    /// A blobstore holding containers in memory.
+    #[derive(Default)]
+    struct MemoryBlobstore(Mutex<BTreeMap<(String, String), Bytes>>);
+
+    impl MemoryBlobstore {
+        fn objects(&self) -> BTreeMap<(String, String), Bytes> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl VolumeBlobstore for MemoryBlobstore {
+        async fn list(&self, container: &str) -> anyhow::Result<Vec<String>> {
+            Ok(self
+                .objects()
+                .into_keys()
+                .filter(|(c, _)| c == container)
+                .map(|(_, name)| name)
+                .collect())
+        }
+
+        async fn get(&self, container: &str, name: &str) -> anyhow::Result<Bytes> {
+            self.objects()
+                .remove(&(container.to_string(), name.to_string()))
+                .context("no such object")
+        }
+
+        async fn put(&self, container: &str, name: &str, body: Bytes) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .insert((container.to_string(), name.to_string()), body);
+            Ok(())
+        }
+
+        async fn delete(&self, container: &str, name: &str) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .remove(&(container.to_string(), name.to_string()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_object_path() {
+        assert_eq!(object_path("a.txt"), Some(PathBuf::from("a.txt")));
+        assert_eq!(object_path("a/b/c.txt"), Some(PathBuf::from("a/b/c.txt")));
+        for name in ["", "/a", "a//b", "a/", "../a", "a/./b", "a\\b"] {
+            assert_eq!(object_path(name), None, "{name}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_download_and_sync() -> anyhow::Result<()> {
+        let store = MemoryBlobstore::default();
+        store.put("data", "a.txt", Bytes::from_static(b"a")).await?;
+        store
+            .put("data", "dir/b.txt", Bytes::from_static(b"b"))
+            .await?;
+        store
+            .put("data", "../escape", Bytes::from_static(b"x"))
+            .await?;
+        store
+            .put("other", "c.txt", Bytes::from_static(b"c"))
+            .await?;
+        let volume = BlobstoreVolume {
+            container: "data".to_string(),
+            sync_writes: true,
+        };
+
+        let mut materialized = MaterializedVolume::download(&store, "data", &volume).await?;
+        let dir = materialized.dir.path().to_path_buf();
+        assert_eq!(std::fs::read(dir.join("a.txt"))?, b"a");
+        assert_eq!(std::fs::read(dir.join("dir/b.txt"))?, b"b");
+        assert_eq!(materialized.synced.len(), 2);
+
+        std::fs::write(dir.join("a.txt"), b"changed")?;
+        std::fs::write(dir.join("dir/new.txt"), b"new")?;
+        std::fs::remove_file(dir.join("dir/b.txt"))?;
+        materialized.sync(&store).await;
+
+        let key = |name: &str| ("data".to_string(), name.to_string());
+        let objects = store.objects();
+        assert_eq!(objects[&key("a.txt")], Bytes::from_static(b"changed"));
+        assert_eq!(objects[&key("dir/new.txt")], Bytes::from_static(b"new"));
+        assert!(!objects.contains_key(&key("dir/b.txt")));
+        assert!(objects.contains_key(&key("../escape")));
+
+        drop(materialized);
+        assert!(!dir.exists());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_materialize() -> anyhow::Result<()> {
+        let mut component = crate::types::Component::default();
+        component.local_resources.volume_mounts.push(VolumeMount {
+            name: "data".to_string(),
+            mount_path: "/data".to_string(),
+            read_only: false,
+            quota_bytes: None,
+        });
+        let mut workload = Workload {
+            namespace: "default".to_string(),
+            name: "blobs".to_string(),
+            annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
+            service: None,
+            components: vec![component],
+            host_interfaces: Vec::new(),
+            volumes: vec![Volume {
+                name: "data".to_string(),
+                volume_type: VolumeType::Blobstore(BlobstoreVolume {
+                    container: "data".to_string(),
+                    sync_writes: false,
+                }),
+            }],
+        };
+
+        assert!(
+            BlobVolumes::default()
+                .materialize("w", &mut workload.clone())
+                .await
+                .is_err()
+        );
+
+        let volumes = BlobVolumes::new(Some(Arc::new(MemoryBlobstore::default())));
+        volumes.materialize("w", &mut workload).await?;
+        let VolumeType::HostPath(HostPathVolume { local_path }) = &workload.volumes[0].volume_type
+        else {
+            panic!("the volume is a host path");
+        };
+        let dir = PathBuf::from(local_path);
+        assert!(dir.is_dir());
+        assert!(workload.components[0].local_resources.volume_mounts[0].read_only);
+
+        volumes.release("w").await;
+        assert!(!dir.exists());
+        Ok(())
+    }
+}
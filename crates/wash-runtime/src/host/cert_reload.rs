@@ -0,0 +1,204 @@
+//! Reloading of the HTTP server's certificates when their files change, so certificates
+//! can be rotated without restarting the host or dropping connections.
+//!
+//! Certificates loaded from files, the one a server is created with by
+//! [`crate::host::http::HttpServer::new_with_tls`] and its
+//! [SNI certificates](crate::host::sni), are swapped in place: connections already
+//! established keep the certificate they were set up with, and handshakes after the swap
+//! get the new one. With [`crate::host::http::HttpServer::with_certificate_reload`], the
+//! directories of the files are watched while the server runs, and certificates are
+//! reloaded [`RELOAD_DELAY`] after their files last changed, so a certificate and its key
+//! written one after the other are loaded together. Directories rather than files are
+//! watched, so certificates replaced by renaming files or swapping symlinks, as with
+//! Kubernetes secrets, are noticed too.
+//!
+//! A certificate whose files can't be loaded, or whose key doesn't match, keeps being
+//! served until they're fixed. Certificates ordered from an ACME certificate authority,
+//! behind the `acme` feature, are swapped in place when renewed, without watching files.
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher as _};
+use rustls::ServerConfig;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::host::http::{load_cert_chain, load_private_key};
+
+/// How long after its files last changed a certificate is reloaded.
+pub const RELOAD_DELAY: Duration = Duration::from_millis(500);
+
+/// A certificate loaded from files, which can be swapped for their current contents.
+#[derive(Debug)]
+pub struct ReloadableCertificate {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl ReloadableCertificate {
+    /// Loads a certificate chain and its private key, as PEM.
+    ///
+    /// # Errors
+    /// Returns an error if the files can't be read, or the key can't be used for signing.
+    pub async fn load(cert_path: &Path, key_path: &Path) -> anyhow::Result<Self> {
+        Ok(Self {
+            current: RwLock::new(load_certified_key(cert_path, key_path).await?),
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+        })
+    }
+
+    /// The certificate currently served.
+    pub fn current(&self) -> Arc<CertifiedKey> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Loads the certificate's files again and serves their contents from now on.
+    ///
+    /// # Errors
+    /// Returns an error if the files can't be loaded, in which case the certificate
+    /// loaded before keeps being served.
+    pub async fn reload(&self) -> anyhow::Result<()> {
+        let certificate = load_certified_key(&self.cert_path, &self.key_path).await?;
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = certificate;
+        Ok(())
+    }
+
+    fn paths(&self) -> [&Path; 2] {
+        [&self.cert_path, &self.key_path]
+    }
+}
+
+impl ResolvesServerCert for ReloadableCertificate {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+async fn load_certified_key(
+    cert_path: &Path,
+    key_path: &Path,
+) -> anyhow::Result<Arc<CertifiedKey>> {
+    let cert_chain = load_cert_chain(cert_path).await?;
+    let key = load_private_key(key_path).await?;
+    let signing_key = ServerConfig::builder()
+        .crypto_provider()
+        .key_provider
+        .load_private_key(key)
+        .with_context(|| format!("unsupported private key: {}", key_path.display()))?;
+    let certificate = CertifiedKey::new(cert_chain, signing_key);
+    certificate
+        .keys_match()
+        .with_context(|| format!("{} doesn't match its key", cert_path.display()))?;
+    Ok(Arc::new(certificate))
+}
+
+/// Reloads certificates when their files change, until dropped.
+pub(crate) struct CertificateWatcher {
+    /// Stops sending changes when dropped, which ends the reloading task
+    _watcher: RecommendedWatcher,
+}
+
+impl CertificateWatcher {
+    /// Watches the directories of `certificates`' files.
+    ///
+    /// # Errors
+    /// Returns an error if a directory can't be watched.
+    pub(crate) fn new(certificates: Vec<Arc<ReloadableCertificate>>) -> anyhow::Result<Self> {
+        let dirs: HashSet<PathBuf> = certificates
+            .iter()
+            .flat_map(|certificate| certificate.paths())
+            .map(|path| match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+                _ => PathBuf::from("."),
+            })
+            .collect();
+
+        let (changes_tx, changes_rx) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) if !event.kind.is_access() => {
+                    let _ = changes_tx.send(());
+                }
+                Ok(_) => {}
+                Err(e) => warn!(err = ?e, "error watching certificate files"),
+            })
+            .context("failed to create certificate watcher")?;
+        for dir in &dirs {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .with_context(|| format!("failed to watch {}", dir.display()))?;
+        }
+        tokio::spawn(reload_on_change(certificates, changes_rx));
+        Ok(Self { _watcher: watcher })
+    }
+}
+
+/// Reloads every certificate once their files stopped changing for [`RELOAD_DELAY`].
+async fn reload_on_change(
+    certificates: Vec<Arc<ReloadableCertificate>>,
+    mut changes: mpsc::UnboundedReceiver<()>,
+) {
+    while changes.recv().await.is_some() {
+        // Wait for the rest of the rotation to be written
+        loop {
+            match tokio::time::timeout(RELOAD_DELAY, changes.recv()).await {
+                Ok(Some(())) => continue,
+                Ok(None) => return,
+                Err(_) => break,
+            }
+        }
+        for certificate in &certificates {
+            let previous = certificate.current();
+            match certificate.reload().await {
+                Ok(()) if previous.cert != certificate.current().cert => {
+                    info!(cert = %certificate.cert_path.display(), "reloaded TLS certificate");
+                }
+                Ok(()) => {}
+                Err(e) => warn!(
+                    cert = %certificate.cert_path.display(),
+                    err = ?e,
+                    "failed to reload TLS certificate, serving the previous one"
+                ),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_load_missing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert_path = dir.path().join("tls.crt");
+        let key_path = dir.path().join("tls.key");
+        assert!(
+            ReloadableCertificate::load(&cert_path, &key_path)
+                .await
+                .is_err()
+        );
+
+        // Files that aren't PEM hold no certificates
+        tokio::fs::write(&cert_path, "not a certificate")
+            .await
+            .unwrap();
+        tokio::fs::write(&key_path, "not a key").await.unwrap();
+        assert!(
+            ReloadableCertificate::load(&cert_path, &key_path)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,650 @@
+//! Kernel enforcement of workload CPU limits and execution layout with Linux cgroup v2.
+//!
+//! The host runs in a cgroup delegated to it, e.g. by a systemd unit with `Delegate=yes`,
+//! and each workload that needs one gets a threaded child cgroup:
+//!
+//! - If all its components have a [`cpu_limit`](LocalResources::cpu_limit), in CPUs, the
+//!   cgroup's `cpu.max` is set to their sum, as a second line of defense beyond the limits
+//!   the runtime applies itself.
+//! - If its components set [`CPUSET_CONFIG`] or [`NUMA_NODES_CONFIG`] in their
+//!   [`config`](LocalResources::config), the cgroup's `cpuset.cpus` or `cpuset.mems` is set
+//!   to the union of their lists, such as `0-7,16`. On big multi-socket hosts this keeps a
+//!   workload's execution on the cores of a NUMA node and its memory allocated from it.
+//!
+//! Each cgroup comes with a runtime whose threads are moved into it once, as they start,
+//! and the host executes the workload's invocations on them, so they don't move between
+//! cgroups on every call. It has a thread for each CPU the workload is limited or pinned
+//! to when it starts, as it can't execute on more at once. Guest code that still runs on
+//! another thread, such as a component calling into another workload, moves that thread
+//! into the workload's cgroup until it returns to the host.
+//!
+//! The memory controller can't be enabled on threaded cgroups, so the kernel can't limit
+//! the memory of one workload within the host process, and
+//! [`memory_limit_mb`](LocalResources::memory_limit_mb) isn't enforced here.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, bail, ensure};
+use tokio::runtime::Runtime;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use crate::types::LocalResources;
+
+/// Where the cgroup v2 hierarchy is mounted.
+const CGROUP_MOUNT: &str = "/sys/fs/cgroup";
+/// The period `cpu.max` quotas are given for, in microseconds.
+const CPU_PERIOD_MICROS: u64 = 100_000;
+/// Beyond the IDs of any CPU or NUMA node Linux supports.
+const MAX_LIST_ID: u32 = 1 << 16;
+/// How long a dropped cgroup waits for the threads of its runtime to exit before it's
+/// left behind.
+const REMOVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The key in a component's [`LocalResources::config`] listing the CPUs its workload
+/// executes on, e.g. `0-7,16`.
+pub const CPUSET_CONFIG: &str = "cpuset";
+/// The key in a component's [`LocalResources::config`] listing the NUMA nodes its
+/// workload's memory is allocated from, e.g. `1`.
+pub const NUMA_NODES_CONFIG: &str = "numa-nodes";
+
+thread_local! {
+    /// The cgroups of the current thread.
+    static THREAD: RefCell<ThreadCgroups> = const { RefCell::new(ThreadCgroups::new()) };
+}
+
+/// The cgroups of a thread, where `None` stands for the host's cgroup.
+struct ThreadCgroups {
+    /// The kernel's ID of the thread, once it's been read
+    id: Option<String>,
+    /// The workload cgroup whose runtime the thread belongs to
+    home: Option<PathBuf>,
+    /// The workload cgroup the thread was moved into
+    current: Option<PathBuf>,
+}
+
+impl ThreadCgroups {
+    const fn new() -> Self {
+        Self {
+            id: None,
+            home: None,
+            current: None,
+        }
+    }
+}
+
+/// Creates the cgroups of workloads under the host's cgroup.
+#[derive(Debug)]
+pub struct Cgroups {
+    root: PathBuf,
+    /// The controllers available to the cgroups of workloads
+    controllers: Vec<String>,
+}
+
+impl Cgroups {
+    /// Manages workload cgroups under the cgroup of the host process.
+    ///
+    /// # Errors
+    /// Returns an error if the host isn't running on Linux with cgroup v2, or neither the
+    /// `cpu` nor the `cpuset` controller is available to its cgroup.
+    pub fn for_current_process() -> anyhow::Result<Self> {
+        if !cfg!(target_os = "linux") {
+            bail!("cgroups are only supported on Linux");
+        }
+        let cgroup = fs::read_to_string("/proc/self/cgroup")
+            .context("failed to read the cgroup of the host process")?;
+        // Under cgroup v2 the only entry is that of the unified hierarchy, `0::/path`
+        let path = cgroup
+            .lines()
+            .find_map(|line| line.strip_prefix("0::"))
+            .context("the host process isn't in a cgroup v2 hierarchy")?;
+        Self::new(Path::new(CGROUP_MOUNT).join(path.trim_start_matches('/')))
+    }
+
+    /// Manages workload cgroups under `root`, which must contain the host process, and
+    /// enables the `cpu` and `cpuset` controllers available to it for its children.
+    ///
+    /// # Errors
+    /// Returns an error if neither the `cpu` nor the `cpuset` controller is available to
+    /// `root`, or they can't be enabled for its children.
+    pub fn new(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        let controllers: Vec<String> = fs::read_to_string(root.join("cgroup.controllers"))
+            .with_context(|| format!("failed to read the controllers of '{}'", root.display()))?
+            .split_whitespace()
+            .filter(|c| ["cpu", "cpuset"].contains(c))
+            .map(str::to_string)
+            .collect();
+        ensure!(
+            !controllers.is_empty(),
+            "neither the cpu nor the cpuset controller is available to cgroup '{}'",
+            root.display()
+        );
+        // Only threaded controllers can be enabled while `root` contains the host process,
+        // which both of these are
+        let enabled: Vec<String> = controllers.iter().map(|c| format!("+{c}")).collect();
+        write(&root, "cgroup.subtree_control", &enabled.join(" "))?;
+        Ok(Self { root, controllers })
+    }
+
+    /// Creates the cgroup of a workload from the resources of its components.
+    ///
+    /// # Returns
+    /// The workload's cgroup, or `None` if the workload has no CPU limit and isn't pinned
+    /// to CPUs or NUMA nodes.
+    ///
+    /// # Errors
+    /// Returns an error if the components' CPU or NUMA node lists are invalid, or the
+    /// cgroup can't be created or set up.
+    pub fn create<'a>(
+        &self,
+        workload_id: &str,
+        resources: impl IntoIterator<Item = &'a LocalResources>,
+    ) -> anyhow::Result<Option<Arc<WorkloadCgroup>>> {
+        let Some(layout) = self.layout(resources)? else {
+            return Ok(None);
+        };
+
+        // A workload's cgroup is never shared, not even with an earlier workload of the
+        // same ID whose cgroup is still being removed
+        let path = self.root.join(cgroup_name(workload_id));
+        fs::create_dir(&path)
+            .with_context(|| format!("failed to create cgroup '{}'", path.display()))?;
+        // Removes the cgroup again if it can't be set up
+        let mut cgroup = WorkloadCgroup {
+            path,
+            root: self.root.clone(),
+            runtime: None,
+        };
+
+        // Threads, rather than whole processes, can only be moved between threaded cgroups
+        write(&cgroup.path, "cgroup.type", "threaded")?;
+        if let Some(cpus) = layout.cpus {
+            write(
+                &cgroup.path,
+                "cpu.max",
+                &format!("{} {CPU_PERIOD_MICROS}", cpus * CPU_PERIOD_MICROS),
+            )?;
+        }
+        if !layout.cpuset.is_empty() {
+            write(&cgroup.path, "cpuset.cpus", &format_list(&layout.cpuset))?;
+        }
+        if !layout.mems.is_empty() {
+            write(&cgroup.path, "cpuset.mems", &format_list(&layout.mems))?;
+        }
+
+        let home = cgroup.path.clone();
+        cgroup.runtime = Some(
+            tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(layout.threads())
+                .thread_name("wash-workload")
+                .on_thread_start(move || pin_current_thread(&home))
+                .enable_all()
+                .build()
+                .context("failed to start the runtime of the workload cgroup")?,
+        );
+        debug!(workload_id, ?layout, cgroup = %cgroup.path.display(), "created workload cgroup");
+        Ok(Some(Arc::new(cgroup)))
+    }
+
+    /// Checks that the cgroup of a workload could be created from the resources of its
+    /// components, without creating it.
+    ///
+    /// # Errors
+    /// Returns an error if the components' CPU or NUMA node lists are invalid, or a
+    /// controller they need isn't available.
+    pub fn check<'a>(
+        &self,
+        resources: impl IntoIterator<Item = &'a LocalResources>,
+    ) -> anyhow::Result<()> {
+        self.layout(resources).map(|_| ())
+    }
+
+    /// The layout of a workload's cgroup, or `None` if the workload needs no cgroup.
+    ///
+    /// # Errors
+    /// Returns an error if the layout is invalid or needs a controller that isn't
+    /// available.
+    fn layout<'a>(
+        &self,
+        resources: impl IntoIterator<Item = &'a LocalResources>,
+    ) -> anyhow::Result<Option<Layout>> {
+        let layout = Layout::of(resources)?;
+        let pinned = !layout.cpuset.is_empty() || !layout.mems.is_empty();
+        if layout.cpus.is_none() && !pinned {
+            return Ok(None);
+        }
+        let mut controllers = Vec::new();
+        if layout.cpus.is_some() {
+            controllers.push("cpu");
+        }
+        if pinned {
+            controllers.push("cpuset");
+        }
+        for controller in &controllers {
+            ensure!(
+                self.controllers.iter().any(|c| c == controller),
+                "the {controller} controller isn't available to cgroup '{}'",
+                self.root.display()
+            );
+        }
+        Ok(Some(layout))
+    }
+}
+
+/// The cgroup of one workload, removed when the workload's last store is dropped.
+#[derive(Debug)]
+pub struct WorkloadCgroup {
+    path: PathBuf,
+    /// The host's cgroup, which threads return to
+    root: PathBuf,
+    /// The runtime whose threads stay in the cgroup, only `None` while it's set up or
+    /// dropped
+    runtime: Option<Runtime>,
+}
+
+impl WorkloadCgroup {
+    /// Spawns a task on the threads in the workload's cgroup.
+    pub(crate) fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.runtime {
+            Some(runtime) => runtime.spawn(future),
+            None => tokio::spawn(future),
+        }
+    }
+
+    /// Moves the current thread into the workload's cgroup as it starts executing guest
+    /// code, unless it's already there, like the threads of the cgroup's runtime.
+    pub(crate) fn enter(&self) {
+        move_current_thread(Some(&self.path), &self.root);
+    }
+
+    /// Moves the current thread back into the cgroup it belongs to as it returns to the
+    /// host, which is the host's cgroup unless it's a thread of a workload cgroup's
+    /// runtime.
+    pub(crate) fn leave(&self) {
+        move_current_thread(None, &self.root);
+    }
+
+    /// Sets the workload's CPU limit from the resources of its components, e.g. after
+    /// one of them changed its [`cpu_limit`](LocalResources::cpu_limit), lifting the limit
+    /// unless they all have one.
+    ///
+    /// # Errors
+    /// Returns an error if `cpu.max` can't be written, e.g. because the cgroup was created
+    /// without the `cpu` controller.
+    pub(crate) fn update_cpu_limit<'a>(
+        &self,
+        resources: impl IntoIterator<Item = &'a LocalResources>,
+    ) -> anyhow::Result<()> {
+        let quota = match cpu_limit(resources) {
+            Some(cpus) => (cpus * CPU_PERIOD_MICROS).to_string(),
+            None => "max".to_string(),
+        };
+        write(
+            &self.path,
+            "cpu.max",
+            &format!("{quota} {CPU_PERIOD_MICROS}"),
+        )?;
+        debug!(cgroup = %self.path.display(), quota, "updated workload CPU limit");
+        Ok(())
+    }
+}
+
+impl Drop for WorkloadCgroup {
+    fn drop(&mut self) {
+        // The cgroup can only be removed once the runtime's threads exited, which may be
+        // waited for neither on one of them nor in async code
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+        let path = std::mem::take(&mut self.path);
+        let removal = std::thread::Builder::new()
+            .name("wash-cgroup-rm".to_string())
+            .spawn({
+                let path = path.clone();
+                move || remove_cgroup(&path)
+            });
+        if let Err(e) = removal {
+            warn!(error = %e, cgroup = %path.display(), "failed to remove workload cgroup");
+        }
+    }
+}
+
+/// Removes a cgroup, waiting up to [`REMOVE_TIMEOUT`] for its threads to exit.
+fn remove_cgroup(path: &Path) {
+    let deadline = Instant::now() + REMOVE_TIMEOUT;
+    loop {
+        match fs::remove_dir(path) {
+            Err(e) if e.kind() == io::ErrorKind::ResourceBusy && Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                warn!(error = %e, cgroup = %path.display(), "failed to remove workload cgroup");
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+/// What the cgroup of a workload enforces, combined over its components.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Layout {
+    /// The CPU limit, in CPUs
+    cpus: Option<u64>,
+    /// The CPUs guest code executes on, or any if empty
+    cpuset: BTreeSet<u32>,
+    /// The NUMA nodes memory is allocated from, or any if empty
+    mems: BTreeSet<u32>,
+}
+
+impl Layout {
+    /// The number of threads that can execute the workload's guest code at once, one
+    /// for each CPU it's limited or pinned to, up to the CPUs of the host.
+    fn threads(&self) -> usize {
+        let host = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        let limit = self.cpus.map_or(usize::MAX, |cpus| {
+            usize::try_from(cpus).unwrap_or(usize::MAX)
+        });
+        let pinned = if self.cpuset.is_empty() {
+            usize::MAX
+        } else {
+            self.cpuset.len()
+        };
+        host.min(limit).min(pinned).max(1)
+    }
+
+    fn of<'a>(resources: impl IntoIterator<Item = &'a LocalResources>) -> anyhow::Result<Self> {
+        let resources: Vec<&LocalResources> = resources.into_iter().collect();
+        let mut layout = Self {
+            cpus: cpu_limit(resources.iter().copied()),
+            ..Default::default()
+        };
+        for resources in resources {
+            for (key, set) in [
+                (CPUSET_CONFIG, &mut layout.cpuset),
+                (NUMA_NODES_CONFIG, &mut layout.mems),
+            ] {
+                if let Some(list) = resources.config.get(key) {
+                    set.extend(
+                        parse_list(list).with_context(|| format!("invalid {key} '{list}'"))?,
+                    );
+                }
+            }
+        }
+        Ok(layout)
+    }
+}
+
+/// Parses a list of CPU or NUMA node IDs in the kernel's format, e.g. `0-3,8`.
+fn parse_list(list: &str) -> anyhow::Result<BTreeSet<u32>> {
+    let mut ids = BTreeSet::new();
+    for range in list
+        .split(',')
+        .map(str::trim)
+        .filter(|range| !range.is_empty())
+    {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: u32 = start.trim().parse().context("expected a number")?;
+        let end: u32 = end.trim().parse().context("expected a number")?;
+        ensure!(start <= end, "the range '{range}' is reversed");
+        ensure!(end < MAX_LIST_ID, "the ID {end} is out of range");
+        ids.extend(start..=end);
+    }
+    ensure!(!ids.is_empty(), "the list is empty");
+    Ok(ids)
+}
+
+fn format_list(ids: &BTreeSet<u32>) -> String {
+    ids.iter().map(u32::to_string).collect::<Vec<_>>().join(",")
+}
+
+/// The sum of the CPU limits of a workload's components, if they all have one.
+fn cpu_limit<'a>(resources: impl IntoIterator<Item = &'a LocalResources>) -> Option<u64> {
+    resources
+        .into_iter()
+        .map(|resources| {
+            u64::try_from(resources.cpu_limit)
+                .ok()
+                .filter(|&cpus| cpus > 0)
+        })
+        .sum::<Option<u64>>()
+        .filter(|&cpus| cpus > 0)
+}
+
+/// Names the cgroup of a workload after the hex encoding of its ID, which is safe in a
+/// path and distinct for distinct IDs.
+fn cgroup_name(workload_id: &str) -> String {
+    let id: String = workload_id
+        .bytes()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("workload-{id}")
+}
+
+fn write(cgroup: &Path, file: &str, contents: &str) -> anyhow::Result<()> {
+    let path = cgroup.join(file);
+    fs::write(&path, contents)
+        .with_context(|| format!("failed to write '{contents}' to '{}'", path.display()))
+}
+
+/// Makes the current thread a thread of the runtime of the workload cgroup `home`,
+/// moving it there for good.
+fn pin_current_thread(home: &Path) {
+    THREAD.with_borrow_mut(|thread| thread.home = Some(home.to_path_buf()));
+    move_current_thread(Some(home), home);
+}
+
+/// Moves the current thread into `target`, or back into the cgroup it belongs to, which
+/// is `root` unless it's a thread of a workload cgroup's runtime, unless it's already
+/// there. Failures are only logged, as the guest code runs either way.
+fn move_current_thread(target: Option<&Path>, root: &Path) {
+    THREAD.with_borrow_mut(|thread| {
+        let target = target.or(thread.home.as_deref());
+        if thread.current.as_deref() == target {
+            return;
+        }
+        let target = target.map(Path::to_path_buf);
+        let destination = target.as_deref().unwrap_or(root);
+        let id = match thread.id.take() {
+            Some(id) => Ok(id),
+            None => thread_id(),
+        };
+        match id.and_then(|id| {
+            fs::write(destination.join("cgroup.threads"), &id)?;
+            Ok(id)
+        }) {
+            Ok(id) => {
+                thread.id = Some(id);
+                thread.current = target;
+            }
+            Err(e) => debug!(
+                error = %e,
+                cgroup = %destination.display(),
+                "failed to move thread between cgroups"
+            ),
+        }
+    });
+}
+
+/// The kernel's ID of the current thread, from `/proc/thread-self`, which links to
+/// `<pid>/task/<tid>`.
+fn thread_id() -> io::Result<String> {
+    fs::read_link("/proc/thread-self")?
+        .file_name()
+        .and_then(|tid| tid.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| io::Error::other("unexpected link of /proc/thread-self"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpus(limits: &[i32]) -> Vec<LocalResources> {
+        limits
+            .iter()
+            .map(|&cpu_limit| LocalResources {
+                cpu_limit,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cpu_limit() {
+        assert_eq!(cpu_limit(&cpus(&[1, 2])), Some(3));
+        assert_eq!(cpu_limit(&cpus(&[1, -1])), None);
+        assert_eq!(cpu_limit(&cpus(&[0])), None);
+        assert_eq!(cpu_limit(&cpus(&[])), None);
+    }
+
+    #[test]
+    fn test_parse_list() -> anyhow::Result<()> {
+        assert_eq!(
+            format_list(&parse_list("0-3, 8,2")?),
+            "0,1,2,3,8".to_string()
+        );
+        for list in ["", "3-1", "a", "0-", "70000"] {
+            assert!(parse_list(list).is_err(), "expected '{list}' to be invalid");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_create() -> anyhow::Result<()> {
+        // A plain directory stands in for the host's cgroup
+        let root = tempfile::tempdir()?;
+        fs::write(
+            root.path().join("cgroup.controllers"),
+            "cpuset cpu io memory",
+        )?;
+        let cgroups = Cgroups::new(root.path())?;
+        assert_eq!(
+            fs::read_to_string(root.path().join("cgroup.subtree_control"))?,
+            "+cpuset +cpu"
+        );
+
+        assert!(cgroups.create("unlimited", &cpus(&[-1]))?.is_none());
+
+        let cgroup = cgroups
+            .create("../escape", &cpus(&[1, 1]))?
+            .expect("the workload has a CPU limit");
+        let path = root.path().join("workload-2e2e2f657363617065");
+        assert_eq!(fs::read_to_string(path.join("cgroup.type"))?, "threaded");
+        assert_eq!(fs::read_to_string(path.join("cpu.max"))?, "200000 100000");
+        cgroup.update_cpu_limit(&cpus(&[3]))?;
+        assert_eq!(fs::read_to_string(path.join("cpu.max"))?, "300000 100000");
+        cgroup.update_cpu_limit(&cpus(&[3, -1]))?;
+        assert_eq!(fs::read_to_string(path.join("cpu.max"))?, "max 100000");
+        drop(cgroup);
+
+        // Pinned components without a CPU limit only need the cpuset controller
+        let mut pinned = cpus(&[-1, -1]);
+        pinned[0]
+            .config
+            .insert(CPUSET_CONFIG.to_string(), "0-1".to_string());
+        pinned[1]
+            .config
+            .insert(CPUSET_CONFIG.to_string(), "4".to_string());
+        pinned[1]
+            .config
+            .insert(NUMA_NODES_CONFIG.to_string(), "0".to_string());
+        let cgroup = cgroups
+            .create("pinned", &pinned)?
+            .expect("the workload is pinned");
+        let path = root.path().join("workload-70696e6e6564");
+        assert_eq!(fs::read_to_string(path.join("cpuset.cpus"))?, "0,1,4");
+        assert_eq!(fs::read_to_string(path.join("cpuset.mems"))?, "0");
+        assert!(!path.join("cpu.max").exists());
+        drop(cgroup);
+
+        pinned[0]
+            .config
+            .insert(NUMA_NODES_CONFIG.to_string(), "one".to_string());
+        assert!(cgroups.create("invalid", &pinned).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_cgroups_are_not_shared() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        fs::write(root.path().join("cgroup.controllers"), "cpu")?;
+        let cgroups = Cgroups::new(root.path())?;
+
+        // IDs that only differ in characters that aren't safe in a path
+        assert_ne!(cgroup_name("a.b"), cgroup_name("a_b"));
+        let _a = cgroups.create("a.b", &cpus(&[1]))?;
+        let _b = cgroups.create("a_b", &cpus(&[1]))?;
+
+        // The files of a plain directory keep it from being removed
+        let _first = cgroups.create("same", &cpus(&[1]))?;
+        assert!(cgroups.create("same", &cpus(&[1])).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_threads() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        fs::write(root.path().join("cgroup.controllers"), "cpu")?;
+        let cgroup = Cgroups::new(root.path())?
+            .create("threads", &cpus(&[1]))?
+            .expect("the workload has a CPU limit");
+        let path = root.path().join(cgroup_name("threads"));
+
+        // The threads of the cgroup's runtime were moved into it as they started, and
+        // stay there as they return to the host
+        let (tid, current) = cgroup
+            .spawn({
+                let cgroup = cgroup.clone();
+                async move {
+                    cgroup.enter();
+                    cgroup.leave();
+                    (
+                        thread_id(),
+                        THREAD.with_borrow(|thread| thread.current.clone()),
+                    )
+                }
+            })
+            .await?;
+        assert_eq!(current, Some(path.clone()));
+        assert_eq!(fs::read_to_string(path.join("cgroup.threads"))?, tid?);
+        assert!(!root.path().join("cgroup.threads").exists());
+
+        // Other threads move in while they execute guest code
+        cgroup.enter();
+        assert_eq!(
+            fs::read_to_string(path.join("cgroup.threads"))?,
+            thread_id()?
+        );
+        cgroup.leave();
+        assert_eq!(
+            fs::read_to_string(root.path().join("cgroup.threads"))?,
+            thread_id()?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_controllers() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        fs::write(root.path().join("cgroup.controllers"), "memory pids")?;
+        assert!(Cgroups::new(root.path()).is_err());
+
+        // The cpuset controller alone can't enforce CPU limits
+        fs::write(root.path().join("cgroup.controllers"), "cpuset")?;
+        let cgroups = Cgroups::new(root.path())?;
+        assert!(cgroups.create("limited", &cpus(&[1])).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+//! Limits on the HTTP server's connections, so a host can protect itself from connection
+//! floods without a load balancer in front of it.
+//!
+//! - [`ConnectionLimits::max_connections`] caps the connections open at once. While the
+//!   server is at the cap it stops accepting, so further connections wait in the listen
+//!   backlog, sized by [`ConnectionLimits::accept_backlog`], and are refused by the kernel
+//!   once that's full.
+//! - [`ConnectionLimits::max_requests_per_connection`] and
+//!   [`ConnectionLimits::idle_timeout`] gracefully close a connection after that many
+//!   requests, or once no request has arrived on it for that long. Requests in flight
+//!   finish first.
+//!
+//! Limits are set with [`crate::host::http::HttpServer::with_connection_limits`].
+
+use std::future::Future;
+use std::pin::{Pin, pin};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use tokio::sync::Notify;
+
+/// The listen backlog of sockets bound by the standard library and Tokio on Linux.
+pub const DEFAULT_ACCEPT_BACKLOG: u32 = 1024;
+
+/// Limits on the HTTP server's connections. None are set by default.
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectionLimits {
+    /// The most connections open at once
+    pub max_connections: Option<usize>,
+    /// The most requests served on one connection before it's closed
+    pub max_requests_per_connection: Option<usize>,
+    /// How long a connection stays open without a new request arriving
+    pub idle_timeout: Option<Duration>,
+    /// The connections the kernel queues while the server isn't accepting
+    pub accept_backlog: u32,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_requests_per_connection: None,
+            idle_timeout: None,
+            accept_backlog: DEFAULT_ACCEPT_BACKLOG,
+        }
+    }
+}
+
+/// Tracks the requests of one connection against the limits.
+#[derive(Debug)]
+pub(crate) struct ConnectionTracker {
+    limits: ConnectionLimits,
+    requests: AtomicUsize,
+    /// Notified as each request arrives
+    activity: Notify,
+}
+
+impl ConnectionTracker {
+    pub(crate) fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            limits,
+            requests: AtomicUsize::new(0),
+            activity: Notify::new(),
+        }
+    }
+
+    /// Records a request arriving on the connection.
+    pub(crate) fn request_started(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.activity.notify_waiters();
+    }
+
+    /// Resolves once the connection has served its requests or has been idle too long.
+    async fn expired(&self) {
+        loop {
+            let activity = self.activity.notified();
+            if self
+                .limits
+                .max_requests_per_connection
+                .is_some_and(|max| self.requests.load(Ordering::Relaxed) >= max)
+            {
+                return;
+            }
+            match self.limits.idle_timeout {
+                Some(timeout) => {
+                    if tokio::time::timeout(timeout, activity).await.is_err() {
+                        return;
+                    }
+                }
+                None => activity.await,
+            }
+        }
+    }
+
+    /// Drives a connection to completion, shutting it down gracefully with `shutdown`
+    /// once it expires.
+    pub(crate) async fn serve<C: Future>(
+        &self,
+        connection: C,
+        shutdown: impl FnOnce(Pin<&mut C>),
+    ) -> C::Output {
+        let mut connection = pin!(connection);
+        tokio::select! {
+            output = connection.as_mut() => return output,
+            () = self.expired() => {}
+        }
+        shutdown(connection.as_mut());
+        connection.await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_max_requests_per_connection() {
+        let tracker = ConnectionTracker::new(ConnectionLimits {
+            max_requests_per_connection: Some(2),
+            ..Default::default()
+        });
+        tracker.request_started();
+        let expired = tokio::time::timeout(Duration::from_millis(50), tracker.expired());
+        assert!(expired.await.is_err(), "one request is below the limit");
+
+        tracker.request_started();
+        tracker.expired().await;
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let tracker = ConnectionTracker::new(ConnectionLimits {
+            idle_timeout: Some(Duration::from_millis(20)),
+            ..Default::default()
+        });
+        let started = std::time::Instant::now();
+        tracker.expired().await;
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+}
@@ -0,0 +1,283 @@
+//! Name resolution and connection racing for outgoing HTTP requests.
+//!
+//! Components' outgoing requests are sent over connections the host opens itself:
+//!
+//! - Host names are resolved through a [`Resolver`], which caches each answer for as long
+//!   as its TTL allows, so components calling the same hosts over and over don't wait on
+//!   DNS each time.
+//! - The resolved addresses are raced with happy eyeballs ([RFC 8305]) in [`connect`]:
+//!   IPv6 and IPv4 addresses are tried alternately, starting the next attempt when the
+//!   previous fails or hasn't connected within [`CONNECTION_ATTEMPT_DELAY`]. The first
+//!   connection wins, so an unreachable address or a broken address family only costs a
+//!   short delay rather than a full connect timeout.
+//!
+//! Resolvers use the system's DNS configuration unless a [`ResolverConfig`] sets other
+//! name servers, adds search domains, or pins host names to addresses, as split-horizon
+//! and air-gapped networks need. The same resolver can be shared by the HTTP server,
+//! [`crate::host::http::HttpServer::with_resolver`], and plugins with HTTP clients of
+//! their own. Name lookups made by components through `wasi:sockets` still use the
+//! system's resolver.
+//!
+//! [RFC 8305]: https://www.rfc-editor.org/rfc/rfc8305
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr as _;
+use std::time::Duration;
+
+use futures::StreamExt as _;
+use futures::stream::FuturesUnordered;
+use hickory_resolver::TokioResolver;
+use hickory_resolver::config::{NameServerConfig, NameServerConfigGroup, ResolverOpts};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::rr::Name;
+use hickory_resolver::proto::xfer::Protocol;
+use tokio::net::TcpStream;
+use tokio::sync::OnceCell;
+use tracing::debug;
+
+/// How long a connection attempt gets before the next address is tried alongside it, as
+/// recommended by RFC 8305.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// How a [`Resolver`] resolves names, where it differs from the system's configuration.
+#[derive(Clone, Debug, Default)]
+pub struct ResolverConfig {
+    /// Name servers queried instead of the system's, over UDP and TCP
+    pub name_servers: Vec<SocketAddr>,
+    /// Domains appended to names that aren't fully qualified, after the system's
+    pub search_domains: Vec<String>,
+    /// Addresses host names resolve to without querying DNS, like a hosts file
+    pub hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl ResolverConfig {
+    /// Parses a host override written as `name=ip[,ip...]`.
+    ///
+    /// # Errors
+    /// Returns an error if the override isn't in that form or an address is invalid.
+    pub fn parse_host(value: &str) -> anyhow::Result<(String, Vec<IpAddr>)> {
+        let (name, ips) = value
+            .split_once('=')
+            .filter(|(name, ips)| !name.is_empty() && !ips.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("expected a host as name=ip[,ip...], got '{value}'"))?;
+        let ips = ips
+            .split(',')
+            .map(|ip| {
+                ip.trim()
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("invalid address '{ip}' for host '{name}'"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok((normalize(name), ips))
+    }
+}
+
+/// A caching DNS resolver, set up on first use.
+#[derive(Default)]
+pub struct Resolver {
+    config: ResolverConfig,
+    inner: OnceCell<TokioResolver>,
+}
+
+impl Resolver {
+    /// Creates a resolver that resolves names as `config` sets out.
+    pub fn new(mut config: ResolverConfig) -> Self {
+        config.hosts = config
+            .hosts
+            .into_iter()
+            .map(|(name, ips)| (normalize(&name), ips))
+            .collect();
+        Self {
+            config,
+            inner: OnceCell::new(),
+        }
+    }
+
+    /// Resolves `host` to its addresses, answering from the cache while its records' TTLs
+    /// haven't expired. IP addresses resolve to themselves, and overridden host names to
+    /// their addresses.
+    ///
+    /// # Errors
+    /// Returns an error if the system's DNS configuration is needed but can't be read, or
+    /// the name doesn't resolve.
+    pub async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        if let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(vec![ip]);
+        }
+        if let Some(ips) = self.config.hosts.get(&normalize(host)) {
+            return Ok(ips.clone());
+        }
+        let resolver = self
+            .inner
+            .get_or_try_init(|| async { self.build() })
+            .await?;
+        let lookup = resolver.lookup_ip(host).await.map_err(io::Error::other)?;
+        Ok(lookup.iter().collect())
+    }
+
+    fn build(&self) -> io::Result<TokioResolver> {
+        let (mut config, options) = if self.config.name_servers.is_empty() {
+            hickory_resolver::system_conf::read_system_conf().map_err(io::Error::other)?
+        } else {
+            let name_servers: Vec<_> = self
+                .config
+                .name_servers
+                .iter()
+                .flat_map(|addr| {
+                    [Protocol::Udp, Protocol::Tcp]
+                        .map(|protocol| NameServerConfig::new(*addr, protocol))
+                })
+                .collect();
+            let config = hickory_resolver::config::ResolverConfig::from_parts(
+                None,
+                Vec::new(),
+                NameServerConfigGroup::from(name_servers),
+            );
+            (config, ResolverOpts::default())
+        };
+        for domain in &self.config.search_domains {
+            let domain = Name::from_str(domain).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid search domain '{domain}': {e}"),
+                )
+            })?;
+            config.add_search(domain);
+        }
+        Ok(
+            TokioResolver::builder_with_config(config, TokioConnectionProvider::default())
+                .with_options(options)
+                .build(),
+        )
+    }
+}
+
+impl std::fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resolver")
+            .field("config", &self.config)
+            .field("initialized", &self.inner.initialized())
+            .finish()
+    }
+}
+
+/// Resolves names for `reqwest` clients with a [`Resolver`], see
+/// [`reqwest::ClientBuilder::dns_resolver`].
+#[cfg(any(feature = "wasi-blobstore-gcs", feature = "sqs-invoker"))]
+pub(crate) struct ReqwestResolver(pub(crate) std::sync::Arc<Resolver>);
+
+#[cfg(any(feature = "wasi-blobstore-gcs", feature = "sqs-invoker"))]
+impl reqwest::dns::Resolve for ReqwestResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let resolver = self.0.clone();
+        Box::pin(async move {
+            let ips = resolver.lookup(name.as_str()).await?;
+            // reqwest replaces the port with the request's
+            let addrs: reqwest::dns::Addrs =
+                Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(addrs)
+        })
+    }
+}
+
+/// Host names compare case-insensitively, with or without the root's trailing dot.
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Connects to the first of `ips` to accept a connection on `port`, racing them with
+/// happy eyeballs.
+///
+/// # Errors
+/// Returns the last connection error if none of the addresses could be connected to.
+pub async fn connect(ips: &[IpAddr], port: u16) -> io::Result<TcpStream> {
+    let mut remaining: VecDeque<_> = interleave(ips)
+        .into_iter()
+        .map(|ip| SocketAddr::new(ip, port))
+        .collect();
+    let mut attempts = FuturesUnordered::new();
+    let mut last_err = None;
+    loop {
+        if attempts.is_empty() {
+            match remaining.pop_front() {
+                Some(addr) => attempts.push(TcpStream::connect(addr)),
+                None => {
+                    return Err(last_err.unwrap_or_else(|| {
+                        io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")
+                    }));
+                }
+            }
+        }
+        tokio::select! {
+            Some(result) = attempts.next() => match result {
+                Ok(stream) => return Ok(stream),
+                Err(e) => {
+                    debug!(err = ?e, "connection attempt failed, trying the next address");
+                    last_err = Some(e);
+                    if let Some(addr) = remaining.pop_front() {
+                        attempts.push(TcpStream::connect(addr));
+                    }
+                }
+            },
+            () = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if !remaining.is_empty() => {
+                if let Some(addr) = remaining.pop_front() {
+                    attempts.push(TcpStream::connect(addr));
+                }
+            }
+        }
+    }
+}
+
+/// Orders addresses for connection attempts, alternating address families starting with
+/// IPv6 and otherwise keeping the resolver's order.
+fn interleave(ips: &[IpAddr]) -> Vec<IpAddr> {
+    let (v6, v4): (Vec<_>, Vec<_>) = ips.iter().copied().partition(IpAddr::is_ipv6);
+    let mut v6 = v6.into_iter();
+    let mut v4 = v4.into_iter();
+    let mut ordered = Vec::with_capacity(ips.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interleave() {
+        let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "::1", "10.0.0.3", "::2"]
+            .iter()
+            .map(|ip| ip.parse().unwrap())
+            .collect();
+        let ordered: Vec<String> = interleave(&ips).iter().map(ToString::to_string).collect();
+        assert_eq!(ordered, ["::1", "10.0.0.1", "::2", "10.0.0.2", "10.0.0.3"]);
+    }
+
+    #[tokio::test]
+    async fn test_connect_skips_unreachable_addresses() -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let port = listener.local_addr()?.port();
+        // Nothing listens on 127.0.0.2's port, so the first attempt is refused
+        let ips = ["127.0.0.2".parse()?, "127.0.0.1".parse()?];
+        let stream = connect(&ips, port).await?;
+        assert_eq!(stream.peer_addr()?.port(), port);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lookup_ip_literals() -> anyhow::Result<()> {
+        let resolver = Resolver::default();
+        assert_eq!(
+            resolver.lookup("[::1]").await?,
+            vec!["::1".parse::<IpAddr>()?]
+        );
+        assert!(!resolver.inner.initialized(), "IP literals aren't resolved");
+        Ok(())
+    }
+}
@@ -0,0 +1,514 @@
+//! Auditing of outbound connections.
+//!
+//! Every outgoing HTTP request and socket connection made by a component is recorded in
+//! the host's [`EgressLog`], including those blocked by `allowed_hosts`. Each record is
+//! also logged under the [`EGRESS_LOG_TARGET`] tracing target, so audits can collect
+//! them with a target filter. The log keeps the most recent records of each workload and
+//! running totals per destination, which are queried with
+//! [`crate::host::HostApi::workload_egress`].
+
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, ready};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Frame, SizeHint};
+use tracing::{info, warn};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::{HostFutureIncomingResponse, IncomingResponse};
+
+use crate::host::bandwidth::{ThrottledBody, WorkloadBandwidth};
+use crate::types::{DestinationMetrics, EgressKind, EgressOutcome, EgressRecord};
+
+/// The tracing target egress records are logged under.
+pub const EGRESS_LOG_TARGET: &str = "wash_runtime::egress";
+
+/// The number of recent records kept per workload by default.
+pub const DEFAULT_RECENT_RECORDS: usize = 256;
+
+#[derive(Debug, Default)]
+struct WorkloadEgress {
+    recent: VecDeque<EgressRecord>,
+    destinations: HashMap<String, DestinationMetrics>,
+}
+
+/// Recent outbound connections and per-destination totals of every workload.
+#[derive(Debug)]
+pub struct EgressLog {
+    /// The number of recent records kept per workload
+    capacity: usize,
+    /// Records are added from synchronous contexts, e.g. when a response body is dropped
+    workloads: Mutex<HashMap<String, WorkloadEgress>>,
+}
+
+impl Default for EgressLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_RECENT_RECORDS)
+    }
+}
+
+impl EgressLog {
+    /// Creates a log that keeps the `capacity` most recent records of each workload.
+    /// Totals per destination are kept regardless.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            workloads: Mutex::default(),
+        }
+    }
+
+    /// Logs an outbound connection and adds it to the workload's records and totals.
+    pub fn record(&self, record: EgressRecord) {
+        log_record(&record);
+
+        let mut workloads = self
+            .workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let egress = workloads.entry(record.workload_id.clone()).or_default();
+        let metrics = egress
+            .destinations
+            .entry(record.destination.clone())
+            .or_default();
+        metrics.connections += 1;
+        metrics.bytes_sent += record.bytes_sent;
+        metrics.bytes_received += record.bytes_received;
+        match record.outcome {
+            EgressOutcome::Allowed => metrics.total_latency += record.latency,
+            EgressOutcome::BlockedByPolicy => metrics.blocked += 1,
+            EgressOutcome::Failed(_) => metrics.failed += 1,
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+        if egress.recent.len() >= self.capacity {
+            egress.recent.pop_front();
+        }
+        egress.recent.push_back(record);
+    }
+
+    /// Records a socket connection or datagram to `addr`.
+    pub fn record_socket(
+        &self,
+        workload_id: &str,
+        component_id: &str,
+        addr: SocketAddr,
+        allowed: bool,
+    ) {
+        self.record(EgressRecord {
+            timestamp: chrono::Utc::now(),
+            workload_id: workload_id.to_string(),
+            component_id: component_id.to_string(),
+            kind: EgressKind::Socket,
+            destination: addr.to_string(),
+            method: None,
+            status: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            latency: Duration::ZERO,
+            outcome: if allowed {
+                EgressOutcome::Allowed
+            } else {
+                EgressOutcome::BlockedByPolicy
+            },
+        });
+    }
+
+    /// Returns the recent records of a workload, newest first.
+    ///
+    /// # Arguments
+    /// * `workload_id` - The workload to return records for
+    /// * `limit` - The maximum number of records to return, or all retained records
+    pub fn recent(&self, workload_id: &str, limit: Option<usize>) -> Vec<EgressRecord> {
+        let workloads = self
+            .workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        workloads
+            .get(workload_id)
+            .map(|egress| {
+                egress
+                    .recent
+                    .iter()
+                    .rev()
+                    .take(limit.unwrap_or(usize::MAX))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the totals of a workload, keyed by destination.
+    pub fn destinations(&self, workload_id: &str) -> HashMap<String, DestinationMetrics> {
+        let workloads = self
+            .workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        workloads
+            .get(workload_id)
+            .map(|egress| egress.destinations.clone())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the records and totals of a workload, e.g. once it has stopped.
+    pub fn remove(&self, workload_id: &str) {
+        self.workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(workload_id);
+    }
+}
+
+fn log_record(record: &EgressRecord) {
+    let EgressRecord {
+        workload_id,
+        component_id,
+        kind,
+        destination,
+        method,
+        status,
+        bytes_sent,
+        bytes_received,
+        latency,
+        outcome,
+        ..
+    } = record;
+    match outcome {
+        EgressOutcome::Allowed => info!(
+            target: EGRESS_LOG_TARGET,
+            workload_id,
+            component_id,
+            ?kind,
+            destination,
+            method,
+            status,
+            bytes_sent,
+            bytes_received,
+            latency_ms = latency.as_millis() as u64,
+            "outbound connection"
+        ),
+        EgressOutcome::BlockedByPolicy => warn!(
+            target: EGRESS_LOG_TARGET,
+            workload_id,
+            component_id,
+            ?kind,
+            destination,
+            method,
+            "outbound connection denied by allowed_hosts"
+        ),
+        EgressOutcome::Failed(err) => warn!(
+            target: EGRESS_LOG_TARGET,
+            workload_id,
+            component_id,
+            ?kind,
+            destination,
+            method,
+            err,
+            latency_ms = latency.as_millis() as u64,
+            "outbound connection failed"
+        ),
+    }
+}
+
+/// Returns the `scheme://host:port` destination of an HTTP request.
+pub(crate) fn http_destination(uri: &hyper::Uri) -> String {
+    let scheme = uri.scheme_str().unwrap_or("http");
+    let port = uri.port_u16().unwrap_or(match scheme {
+        "https" => 443,
+        _ => 80,
+    });
+    format!(
+        "{scheme}://{host}:{port}",
+        host = uri.host().unwrap_or_default()
+    )
+}
+
+/// Meters a single outgoing HTTP request of a component, recording it once its
+/// response body has been dropped.
+pub(crate) struct HttpEgress {
+    log: Arc<EgressLog>,
+    workload_id: Arc<str>,
+    component_id: Arc<str>,
+    destination: String,
+    method: String,
+    started: Instant,
+    bytes_sent: Arc<AtomicU64>,
+}
+
+impl HttpEgress {
+    /// Starts metering `request`, counting the bytes of its body from here on.
+    pub(crate) fn start(
+        log: Arc<EgressLog>,
+        workload_id: Arc<str>,
+        component_id: Arc<str>,
+        request: &mut hyper::Request<HyperOutgoingBody>,
+    ) -> Self {
+        let bytes_sent = Arc::new(AtomicU64::new(0));
+        let body = std::mem::take(request.body_mut());
+        *request.body_mut() = MeteredBody::new(body, bytes_sent.clone(), None).boxed();
+        Self {
+            log,
+            workload_id,
+            component_id,
+            destination: http_destination(request.uri()),
+            method: request.method().to_string(),
+            started: Instant::now(),
+            bytes_sent,
+        }
+    }
+
+    /// Records a request that `allowed_hosts` didn't let through.
+    pub(crate) fn blocked(
+        log: &EgressLog,
+        workload_id: &str,
+        component_id: &str,
+        request: &hyper::Request<HyperOutgoingBody>,
+    ) {
+        log.record(EgressRecord {
+            timestamp: chrono::Utc::now(),
+            workload_id: workload_id.to_string(),
+            component_id: component_id.to_string(),
+            kind: EgressKind::Http,
+            destination: http_destination(request.uri()),
+            method: Some(request.method().to_string()),
+            status: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            latency: Duration::ZERO,
+            outcome: EgressOutcome::BlockedByPolicy,
+        });
+    }
+
+    /// Meters the response to the request, throttling its body by `bandwidth` if given.
+    pub(crate) fn finish(
+        self,
+        response: HostFutureIncomingResponse,
+        bandwidth: Option<Arc<WorkloadBandwidth>>,
+    ) -> HostFutureIncomingResponse {
+        match response {
+            HostFutureIncomingResponse::Pending(handle) => {
+                HostFutureIncomingResponse::pending(wasmtime_wasi::runtime::spawn(async move {
+                    let result = handle.await;
+                    self.observe(result, bandwidth)
+                }))
+            }
+            HostFutureIncomingResponse::Ready(result) => {
+                HostFutureIncomingResponse::ready(self.observe(result, bandwidth))
+            }
+            HostFutureIncomingResponse::Consumed => HostFutureIncomingResponse::Consumed,
+        }
+    }
+
+    fn observe(
+        self,
+        result: anyhow::Result<Result<IncomingResponse, ErrorCode>>,
+        bandwidth: Option<Arc<WorkloadBandwidth>>,
+    ) -> anyhow::Result<Result<IncomingResponse, ErrorCode>> {
+        let latency = self.started.elapsed();
+        let mut response = match result {
+            Ok(Ok(response)) => response,
+            Ok(Err(code)) => {
+                self.record(None, 0, latency, EgressOutcome::Failed(code.to_string()));
+                return Ok(Err(code));
+            }
+            Err(e) => {
+                self.record(None, 0, latency, EgressOutcome::Failed(e.to_string()));
+                return Err(e);
+            }
+        };
+
+        let status = response.resp.status().as_u16();
+        let body = std::mem::take(response.resp.body_mut());
+        let on_drop: OnDrop = Box::new(move |bytes_received| {
+            self.record(
+                Some(status),
+                bytes_received,
+                latency,
+                EgressOutcome::Allowed,
+            )
+        });
+        let body = MeteredBody::new(body, Arc::new(AtomicU64::new(0)), Some(on_drop));
+        *response.resp.body_mut() = match bandwidth {
+            Some(bandwidth) => ThrottledBody::new(body, bandwidth).boxed(),
+            None => body.boxed(),
+        };
+        Ok(Ok(response))
+    }
+
+    fn record(
+        &self,
+        status: Option<u16>,
+        bytes_received: u64,
+        latency: Duration,
+        outcome: EgressOutcome,
+    ) {
+        self.log.record(EgressRecord {
+            timestamp: chrono::Utc::now(),
+            workload_id: self.workload_id.to_string(),
+            component_id: self.component_id.to_string(),
+            kind: EgressKind::Http,
+            destination: self.destination.clone(),
+            method: Some(self.method.clone()),
+            status,
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received,
+            latency,
+            outcome,
+        });
+    }
+}
+
+type OnDrop = Box<dyn FnOnce(u64) + Send + Sync>;
+
+/// A body that counts the bytes of its data frames, calling `on_drop` with the count
+/// when dropped.
+struct MeteredBody<B> {
+    inner: B,
+    bytes: Arc<AtomicU64>,
+    on_drop: Option<OnDrop>,
+}
+
+impl<B> MeteredBody<B> {
+    fn new(inner: B, bytes: Arc<AtomicU64>, on_drop: Option<OnDrop>) -> Self {
+        Self {
+            inner,
+            bytes,
+            on_drop,
+        }
+    }
+}
+
+impl<B: Body<Data = Bytes> + Unpin> Body for MeteredBody<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(data) = frame
+            .as_ref()
+            .and_then(|frame| frame.as_ref().ok())
+            .and_then(Frame::data_ref)
+        {
+            self.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> Drop for MeteredBody<B> {
+    fn drop(&mut self) {
+        if let Some(on_drop) = self.on_drop.take() {
+            on_drop(self.bytes.load(Ordering::Relaxed));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(workload_id: &str, destination: &str, outcome: EgressOutcome) -> EgressRecord {
+        EgressRecord {
+            timestamp: chrono::Utc::now(),
+            workload_id: workload_id.to_string(),
+            component_id: "component".to_string(),
+            kind: EgressKind::Http,
+            destination: destination.to_string(),
+            method: Some("GET".to_string()),
+            status: Some(200),
+            bytes_sent: 10,
+            bytes_received: 100,
+            latency: Duration::from_millis(20),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn test_recent_records_are_bounded() {
+        let log = EgressLog::new(2);
+        for destination in ["https://a:443", "https://b:443", "https://c:443"] {
+            log.record(record("w1", destination, EgressOutcome::Allowed));
+        }
+
+        let recent = log.recent("w1", None);
+        let destinations: Vec<_> = recent.iter().map(|r| r.destination.as_str()).collect();
+        assert_eq!(destinations, ["https://c:443", "https://b:443"]);
+        assert_eq!(log.recent("w1", Some(1)).len(), 1);
+        assert!(log.recent("w2", None).is_empty());
+
+        // Totals outlive the records they were computed from
+        assert_eq!(log.destinations("w1").len(), 3);
+        log.remove("w1");
+        assert!(log.destinations("w1").is_empty());
+    }
+
+    #[test]
+    fn test_destination_metrics() {
+        let log = EgressLog::default();
+        log.record(record("w1", "https://a:443", EgressOutcome::Allowed));
+        log.record(record("w1", "https://a:443", EgressOutcome::Allowed));
+        log.record(record(
+            "w1",
+            "https://a:443",
+            EgressOutcome::Failed("timeout".to_string()),
+        ));
+        log.record_socket("w1", "component", "10.0.0.1:5432".parse().unwrap(), false);
+
+        let destinations = log.destinations("w1");
+        let metrics = &destinations["https://a:443"];
+        assert_eq!(metrics.connections, 3);
+        assert_eq!(metrics.failed, 1);
+        assert_eq!(metrics.bytes_received, 300);
+        assert_eq!(metrics.mean_latency(), Some(Duration::from_millis(20)));
+
+        let metrics = &destinations["10.0.0.1:5432"];
+        assert_eq!(metrics.blocked, 1);
+        assert_eq!(metrics.mean_latency(), None);
+    }
+
+    #[test]
+    fn test_http_destination() {
+        let uri: hyper::Uri = "https://api.example.com/v1".parse().unwrap();
+        assert_eq!(http_destination(&uri), "https://api.example.com:443");
+        let uri: hyper::Uri = "http://localhost:8080/".parse().unwrap();
+        assert_eq!(http_destination(&uri), "http://localhost:8080");
+    }
+
+    #[tokio::test]
+    async fn test_metered_body_counts_bytes() -> anyhow::Result<()> {
+        let counted = Arc::new(AtomicU64::new(0));
+        let dropped = Arc::new(AtomicU64::new(0));
+        let on_drop: OnDrop = Box::new({
+            let dropped = dropped.clone();
+            move |bytes| dropped.store(bytes, Ordering::Relaxed)
+        });
+        let body = MeteredBody::new(
+            http_body_util::Full::new(Bytes::from_static(b"hello world")),
+            counted.clone(),
+            Some(on_drop),
+        );
+        let bytes = body.collect().await?.to_bytes();
+        assert_eq!(bytes.len(), 11);
+        assert_eq!(counted.load(Ordering::Relaxed), 11);
+        assert_eq!(dropped.load(Ordering::Relaxed), 11);
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+//! Machine-readable causes of the errors [`HostApi`](crate::host::HostApi) returns, so
+//! callers can branch on why a request failed and report an accurate status.
+//!
+//! The API returns [`anyhow::Error`]s like the rest of the runtime, with a [`HostError`]
+//! attached where the cause is known, the way [`UnresolvedInterfacesError`] is.
+//! [`HostError::of`] recovers the cause of any error the API returns, and
+//! [`HostError::code`] gives the stable code to report for it, e.g. in an API response.
+//! Errors without a known cause are [`HostError::Internal`].
+
+use crate::engine::workload::UnresolvedInterfacesError;
+
+/// Why a [`HostApi`](crate::host::HostApi) request failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum HostError {
+    /// The request is malformed, e.g. its label selector can't be parsed
+    InvalidRequest,
+    /// No workload has the ID
+    WorkloadNotFound { workload_id: String },
+    /// The workload exists but isn't running, e.g. it's still starting
+    WorkloadNotRunning { workload_id: String },
+    /// A component's image can't be pulled, or its digest doesn't match
+    FetchFailed { image: String },
+    /// A component isn't valid WebAssembly, or the host's engine can't compile it
+    CompilationFailed,
+    /// No plugin provides an interface the workload needs, at a compatible version
+    InterfaceUnsatisfied { iface: String },
+    /// The workload ran out of a resource it's budgeted, such as fuel
+    QuotaExceeded { resource: String },
+    /// The host's policy forbids the request, e.g. mounting a host path outside the
+    /// directories the host allows
+    PolicyDenied,
+    /// A plugin the workload binds to isn't healthy
+    PluginUnavailable { plugin: String },
+    /// The cause isn't known
+    Internal,
+}
+
+impl HostError {
+    /// Returns the cause of an error returned by [`HostApi`](crate::host::HostApi).
+    pub fn of(error: &anyhow::Error) -> Self {
+        if let Some(cause) = error.downcast_ref::<HostError>() {
+            return cause.clone();
+        }
+        if let Some(unresolved) = error.downcast_ref::<UnresolvedInterfacesError>() {
+            return Self::InterfaceUnsatisfied {
+                iface: unresolved
+                    .missing
+                    .first()
+                    .map(|missing| missing.interface.clone())
+                    .unwrap_or_default(),
+            };
+        }
+        if let Some(wasmtime::Trap::OutOfFuel) = error.downcast_ref::<wasmtime::Trap>() {
+            return Self::QuotaExceeded {
+                resource: "fuel".to_string(),
+            };
+        }
+        Self::Internal
+    }
+
+    /// The stable, machine-readable code of the cause, e.g. `workload_not_found`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest => "invalid_request",
+            Self::WorkloadNotFound { .. } => "workload_not_found",
+            Self::WorkloadNotRunning { .. } => "workload_not_running",
+            Self::FetchFailed { .. } => "fetch_failed",
+            Self::CompilationFailed => "compilation_failed",
+            Self::InterfaceUnsatisfied { .. } => "interface_unsatisfied",
+            Self::QuotaExceeded { .. } => "quota_exceeded",
+            Self::PolicyDenied => "policy_denied",
+            Self::PluginUnavailable { .. } => "plugin_unavailable",
+            Self::Internal => "internal",
+        }
+    }
+}
+
+impl std::fmt::Display for HostError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidRequest => write!(f, "invalid request"),
+            Self::WorkloadNotFound { workload_id } => {
+                write!(f, "workload '{workload_id}' not found")
+            }
+            Self::WorkloadNotRunning { workload_id } => {
+                write!(f, "workload '{workload_id}' is not running")
+            }
+            Self::FetchFailed { image } => write!(f, "failed to pull image '{image}'"),
+            Self::CompilationFailed => write!(f, "failed to compile component"),
+            Self::InterfaceUnsatisfied { iface } => {
+                write!(f, "no plugin provides interface '{iface}'")
+            }
+            Self::QuotaExceeded { resource } => write!(f, "workload exceeded its {resource}"),
+            Self::PolicyDenied => write!(f, "denied by host policy"),
+            Self::PluginUnavailable { plugin } => write!(f, "plugin '{plugin}' is unavailable"),
+            Self::Internal => write!(f, "internal error"),
+        }
+    }
+}
+
+impl std::error::Error for HostError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context as _;
+
+    #[test]
+    fn test_of() {
+        let error = anyhow::anyhow!("component bytes are empty")
+            .context(HostError::CompilationFailed)
+            .context("failed to initialize workload");
+        assert_eq!(HostError::of(&error), HostError::CompilationFailed);
+        assert_eq!(HostError::of(&error).code(), "compilation_failed");
+
+        let error = anyhow::Error::new(UnresolvedInterfacesError {
+            workload_id: "w".to_string(),
+            missing: vec![crate::engine::workload::MissingInterface {
+                component_id: "c".to_string(),
+                interface: "wasi:keyvalue/store@0.2.0-draft".to_string(),
+                requested: true,
+                offered: Vec::new(),
+            }],
+            considered_plugins: Vec::new(),
+        });
+        assert_eq!(
+            HostError::of(&error),
+            HostError::InterfaceUnsatisfied {
+                iface: "wasi:keyvalue/store@0.2.0-draft".to_string()
+            }
+        );
+
+        let error = Err::<(), _>(wasmtime::Trap::OutOfFuel)
+            .context("call trapped")
+            .unwrap_err();
+        assert_eq!(HostError::of(&error).code(), "quota_exceeded");
+
+        assert_eq!(
+            HostError::of(&anyhow::anyhow!("disk full")),
+            HostError::Internal
+        );
+    }
+}
@@ -0,0 +1,179 @@
+//! Filter chains for the [HTTP server](crate::host::http): workloads that run in front of
+//! the workloads of a virtual host, for authentication, header rewriting or request checks.
+//!
+//! A filter is a workload serving `wasi:http/incoming-handler` whose interface config sets
+//! `filter` to the virtual hosts it runs for, comma separated, or `*` for every host. It
+//! has no `host` of its own, so requests only reach it through the chains it's part of.
+//! The filters of a virtual host run in order of their `filter-order` config, lowest
+//! first and defaulting to `0`, then by workload ID.
+//!
+//! A filter receives each request as though it were the workload. To pass a request on,
+//! possibly rewritten, the filter sends it to the same virtual host through
+//! `wasi:http/outgoing-handler`, which the host hands in-process to the next filter, or
+//! to the workload after the last one. The filter then returns the response it gets back,
+//! or one of its own, so it runs both before and after the rest of the chain. A filter
+//! that answers without passing the request on, e.g. with `401 Unauthorized`, ends it.
+//!
+//! Requests other workloads on the host send to the virtual host go through its filters
+//! too. Filters with `allowed_hosts` must allow the virtual hosts they filter.
+
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use wasmtime::component::InstancePre;
+
+use crate::engine::ctx::Ctx;
+use crate::engine::workload::ResolvedWorkload;
+use crate::host::http::WorkloadHandles;
+use crate::wit::WitInterface;
+
+/// The incoming handler config key listing the virtual hosts a filter runs for.
+const FILTER_KEY: &str = "filter";
+/// The incoming handler config key with a filter's position in its chains.
+const ORDER_KEY: &str = "filter-order";
+
+/// A workload serving HTTP, with the instance and component that handle its requests.
+type Handler = (ResolvedWorkload, InstancePre<Ctx>, String);
+
+/// Where a filter runs, from its `wasi:http/incoming-handler` config.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FilterConfig {
+    hosts: Vec<String>,
+    order: i64,
+}
+
+impl FilterConfig {
+    /// Reads the filter config of a workload.
+    ///
+    /// # Returns
+    /// The config, or `None` if the workload isn't a filter.
+    ///
+    /// # Errors
+    /// Returns an error if `filter-order` isn't an integer.
+    pub(crate) fn from_workload(workload: &ResolvedWorkload) -> anyhow::Result<Option<Self>> {
+        let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+        match workload
+            .host_interfaces()
+            .iter()
+            .find(|iface| iface.contains(&incoming_handler_interface))
+        {
+            Some(iface) => Self::from_config(&iface.config),
+            None => Ok(None),
+        }
+    }
+
+    fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let Some(hosts) = config.get(FILTER_KEY) else {
+            return Ok(None);
+        };
+        let order = match config.get(ORDER_KEY) {
+            Some(order) => order
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid {ORDER_KEY} '{order}'"))?,
+            None => 0,
+        };
+        Ok(Some(Self {
+            hosts: hosts
+                .split(',')
+                .map(str::trim)
+                .filter(|host| !host.is_empty())
+                .map(str::to_string)
+                .collect(),
+            order,
+        }))
+    }
+
+    fn applies_to(&self, host: Option<&str>) -> bool {
+        self.hosts
+            .iter()
+            .any(|filtered| filtered == "*" || Some(filtered.as_str()) == host)
+    }
+}
+
+/// Whether a workload is a filter in the chain of a virtual host.
+pub(crate) fn runs_for(workload: &ResolvedWorkload, host: Option<&str>) -> bool {
+    FilterConfig::from_workload(workload)
+        .ok()
+        .flatten()
+        .is_some_and(|config| config.applies_to(host))
+}
+
+/// Returns the handler a request for a workload goes to next in the filter chain of its
+/// virtual host.
+///
+/// # Arguments
+/// * `workload` - The workload serving the virtual host
+/// * `host` - The virtual host
+/// * `sender` - The workload passing the request on. A filter of the chain continues it,
+///   anything else starts it.
+///
+/// # Returns
+/// The next filter, or the workload once every filter has run.
+pub(crate) async fn next(
+    workload_handles: &WorkloadHandles,
+    workload: Handler,
+    host: Option<&str>,
+    sender: Option<&str>,
+) -> Handler {
+    let handles = workload_handles.read().await;
+    let mut chain: Vec<(i64, &str, &Handler)> = handles
+        .values()
+        .filter_map(|entry| {
+            let config = FilterConfig::from_workload(&entry.0).ok().flatten()?;
+            config
+                .applies_to(host)
+                .then(|| (config.order, entry.0.id(), entry))
+        })
+        .collect();
+    chain.sort_by_key(|(order, id, _)| (*order, *id));
+
+    let start = sender
+        .and_then(|sender| chain.iter().position(|(_, id, _)| *id == sender))
+        .map_or(0, |position| position + 1);
+    match chain.get(start) {
+        Some((_, _, filter)) => (*filter).clone(),
+        None => workload,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_config() -> anyhow::Result<()> {
+        let config = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+
+        assert_eq!(
+            FilterConfig::from_config(&config(&[("host", "app")]))?,
+            None
+        );
+
+        let filter = FilterConfig::from_config(&config(&[
+            ("filter", "app.localhost, api.localhost"),
+            ("filter-order", "-1"),
+        ]))?
+        .context("expected a filter")?;
+        assert_eq!(filter.order, -1);
+        assert!(filter.applies_to(Some("api.localhost")));
+        assert!(!filter.applies_to(Some("other.localhost")));
+        assert!(!filter.applies_to(None));
+
+        let filter =
+            FilterConfig::from_config(&config(&[("filter", "*")]))?.context("expected a filter")?;
+        assert_eq!(filter.order, 0);
+        assert!(filter.applies_to(None));
+
+        assert!(
+            FilterConfig::from_config(&config(&[("filter", "*"), ("filter-order", "first")]))
+                .is_err()
+        );
+        Ok(())
+    }
+}
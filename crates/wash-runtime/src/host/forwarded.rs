@@ -0,0 +1,295 @@
+//! How the HTTP server treats the `forwarded` and `x-forwarded-for` headers of incoming
+//! requests, so workloads can tell who their clients are without clients being able to
+//! claim to be someone else.
+//!
+//! Both headers list the addresses a request was forwarded for, client first, with each
+//! proxy appending the address it received the request from. Only proxies can vouch for
+//! those addresses, so [`ForwardedConfig::trusted_proxies`] names the ones whose headers
+//! are believed. A [`ForwardedPolicy`] then decides what workloads see:
+//!
+//! - [`ForwardedPolicy::Preserve`] passes the headers through as they arrive, which is the
+//!   default and leaves telling real addresses from spoofed ones to the workload.
+//! - [`ForwardedPolicy::Overwrite`] replaces them with the client's address alone. The
+//!   client is the last address in the list that isn't a trusted proxy, so a request from
+//!   a client that isn't a trusted proxy is attributed to the connection's address.
+//! - [`ForwardedPolicy::Append`] keeps the list from trusted proxies and appends the
+//!   connection's address, as a proxy does, and starts a new list for anyone else.
+//!
+//! `forwarded` is read in preference to `x-forwarded-for`, and both are written. Set with
+//! [`crate::host::http::HttpServer::with_forwarded`].
+
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use anyhow::{Context as _, bail, ensure};
+use hyper::header::{FORWARDED, HeaderMap, HeaderValue};
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// What the HTTP server does with the forwarding headers of incoming requests.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForwardedPolicy {
+    /// Pass the headers through unchanged
+    #[default]
+    Preserve,
+    /// Replace the headers with the client's address
+    Overwrite,
+    /// Append the connection's address to the headers of trusted proxies
+    Append,
+}
+
+impl FromStr for ForwardedPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(policy: &str) -> anyhow::Result<Self> {
+        match policy {
+            "preserve" => Ok(Self::Preserve),
+            "overwrite" => Ok(Self::Overwrite),
+            "append" => Ok(Self::Append),
+            _ => bail!(
+                "unknown forwarded header policy '{policy}', expected preserve, overwrite or append"
+            ),
+        }
+    }
+}
+
+/// A range of addresses, written as a CIDR range like `10.0.0.0/8` or a single address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IpRange {
+    net: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    /// Whether `ip` is in the range.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.net, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = anyhow::Error;
+
+    fn from_str(range: &str) -> anyhow::Result<Self> {
+        let (net, prefix) = match range.split_once('/') {
+            Some((net, prefix)) => (net, Some(prefix)),
+            None => (range, None),
+        };
+        let net: IpAddr = net
+            .parse()
+            .with_context(|| format!("invalid address range '{range}'"))?;
+        let max = if net.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .with_context(|| format!("invalid CIDR prefix length in '{range}'"))?,
+            None => max,
+        };
+        ensure!(
+            prefix <= max,
+            "CIDR prefix length in '{range}' is larger than {max}"
+        );
+        Ok(Self { net, prefix })
+    }
+}
+
+/// How the HTTP server treats forwarding headers, see the [module docs](self).
+#[derive(Clone, Debug, Default)]
+pub struct ForwardedConfig {
+    /// What workloads see in the headers
+    pub policy: ForwardedPolicy,
+    /// The proxies whose headers are believed
+    pub trusted_proxies: Vec<IpRange>,
+}
+
+impl ForwardedConfig {
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|range| range.contains(ip))
+    }
+
+    /// Rewrites the forwarding headers of a request received from `peer` by the policy.
+    pub(crate) fn apply(&self, headers: &mut HeaderMap, peer: SocketAddr) {
+        if self.policy == ForwardedPolicy::Preserve {
+            return;
+        }
+        let mut nodes = if self.is_trusted(peer.ip()) {
+            forwarded_nodes(headers)
+        } else {
+            Vec::new()
+        };
+        nodes.push(peer.to_string());
+        if self.policy == ForwardedPolicy::Overwrite {
+            // The client is the first hop, from the end, that isn't a trusted proxy. Hops
+            // that aren't addresses, like `unknown`, were reported by a trusted proxy, so
+            // they're taken as the client too.
+            let client = nodes
+                .iter()
+                .rposition(|node| node_ip(node).is_none_or(|ip| !self.is_trusted(ip)))
+                .unwrap_or(0);
+            nodes = vec![nodes.swap_remove(client)];
+        }
+        set_nodes(headers, &nodes);
+    }
+}
+
+/// The hops listed in a request's `forwarded` header, or its `x-forwarded-for` header
+/// if it has none, in the form of `forwarded`'s `for` parameter.
+fn forwarded_nodes(headers: &HeaderMap) -> Vec<String> {
+    let values = |name: &str| {
+        headers
+            .get_all(name)
+            .into_iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|element| !element.is_empty())
+    };
+    if headers.contains_key(FORWARDED) {
+        values(FORWARDED.as_str())
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(name, _)| name.eq_ignore_ascii_case("for"))
+                    .map_or("unknown", |(_, node)| node.trim_matches('"'))
+                    .to_string()
+            })
+            .collect()
+    } else {
+        values(X_FORWARDED_FOR).map(ToString::to_string).collect()
+    }
+}
+
+/// The address of a hop, written as an address with an optional port.
+fn node_ip(node: &str) -> Option<IpAddr> {
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|ip| ip.parse().ok())
+        })
+}
+
+/// Replaces the forwarding headers of a request with ones listing `nodes`.
+fn set_nodes(headers: &mut HeaderMap, nodes: &[String]) {
+    let forwarded = nodes
+        .iter()
+        .map(|node| format!("for=\"{node}\""))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let forwarded_for = nodes
+        .iter()
+        .map(|node| node_ip(node).map_or_else(|| node.clone(), |ip| ip.to_string()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    // Nodes are parsed from header values or addresses, so they're valid header values
+    if let (Ok(forwarded), Ok(forwarded_for)) = (
+        HeaderValue::from_str(&forwarded),
+        HeaderValue::from_str(&forwarded_for),
+    ) {
+        headers.insert(FORWARDED, forwarded);
+        headers.insert(X_FORWARDED_FOR, forwarded_for);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderName;
+
+    fn config(policy: ForwardedPolicy) -> ForwardedConfig {
+        ForwardedConfig {
+            policy,
+            trusted_proxies: vec!["10.0.0.0/8".parse().unwrap(), "fd00::1".parse().unwrap()],
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_static(value),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ip_range() -> anyhow::Result<()> {
+        let range: IpRange = "10.1.0.0/16".parse()?;
+        assert!(range.contains("10.1.200.3".parse()?));
+        assert!(range.contains("::ffff:10.1.0.1".parse()?));
+        assert!(!range.contains("10.2.0.1".parse()?));
+        assert!(!range.contains("::1".parse()?));
+        assert!(IpRange::from_str("::1")?.contains("::1".parse()?));
+        assert!(IpRange::from_str("10.0.0.0/33").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_overwrite() -> anyhow::Result<()> {
+        let config = config(ForwardedPolicy::Overwrite);
+
+        // A trusted proxy's hops are skipped back to the client
+        let mut trusted = headers(&[("x-forwarded-for", "203.0.113.7, 10.0.0.3")]);
+        config.apply(&mut trusted, "10.0.0.2:4000".parse()?);
+        assert_eq!(trusted["x-forwarded-for"], "203.0.113.7");
+        assert_eq!(trusted["forwarded"], "for=\"203.0.113.7\"");
+
+        // Anyone else can't claim another address
+        let mut spoofed = headers(&[("forwarded", "for=203.0.113.7")]);
+        config.apply(&mut spoofed, "198.51.100.1:4000".parse()?);
+        assert_eq!(spoofed["x-forwarded-for"], "198.51.100.1");
+        assert_eq!(spoofed["forwarded"], "for=\"198.51.100.1:4000\"");
+
+        // The client is the last untrusted hop, not the first
+        let mut chain = headers(&[(
+            "forwarded",
+            "for=192.0.2.1, for=\"[2001:db8::7]:443\";proto=https, for=\"[fd00::1]\"",
+        )]);
+        config.apply(&mut chain, "10.0.0.2:4000".parse()?);
+        assert_eq!(chain["x-forwarded-for"], "2001:db8::7");
+        assert_eq!(chain["forwarded"], "for=\"[2001:db8::7]:443\"");
+        Ok(())
+    }
+
+    #[test]
+    fn test_append_and_preserve() -> anyhow::Result<()> {
+        let config = config(ForwardedPolicy::Append);
+        let mut trusted = headers(&[("x-forwarded-for", "203.0.113.7")]);
+        config.apply(&mut trusted, "10.0.0.2:4000".parse()?);
+        assert_eq!(trusted["x-forwarded-for"], "203.0.113.7, 10.0.0.2");
+        assert_eq!(
+            trusted["forwarded"],
+            "for=\"203.0.113.7\", for=\"10.0.0.2:4000\""
+        );
+
+        let mut spoofed = headers(&[("x-forwarded-for", "203.0.113.7")]);
+        config.apply(&mut spoofed, "198.51.100.1:4000".parse()?);
+        assert_eq!(spoofed["x-forwarded-for"], "198.51.100.1");
+
+        let mut preserved = headers(&[("x-forwarded-for", "203.0.113.7")]);
+        ForwardedConfig::default().apply(&mut preserved, "198.51.100.1:4000".parse()?);
+        assert_eq!(preserved["x-forwarded-for"], "203.0.113.7");
+        assert!(!preserved.contains_key("forwarded"));
+        Ok(())
+    }
+}
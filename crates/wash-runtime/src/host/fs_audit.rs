@@ -0,0 +1,233 @@
+//! Auditing of the files workloads touch through their volumes, so operators can verify
+//! components only open, create and delete what they're supposed to.
+//!
+//! Hosts built with [`crate::host::HostBuilder::with_fs_audit`] watch the directories
+//! each running workload mounts and log what happens in them at INFO, with the workload,
+//! the operation, the path the guest sees and, for opened files, whether they were
+//! written:
+//!
+//! | operation | when                                                              |
+//! |-----------|-------------------------------------------------------------------|
+//! | `open`    | a file is closed, with `mode` `read` or `write` as it was opened  |
+//! | `create`  | a file or directory is created                                    |
+//! | `delete`  | a file or directory is removed                                    |
+//! | `rename`  | a file or directory is moved                                      |
+//!
+//! Only a fraction of the operations, the sample rate, is logged, spread evenly over
+//! them, as busy volumes would otherwise flood the logs.
+//!
+//! The directories are watched by the host's OS, e.g. with inotify on Linux, rather than
+//! by intercepting `wasi:filesystem` calls. Operations by anything else using the
+//! directories, such as another workload mounting the same host path, are logged as the
+//! workload's too, and files opened but never closed aren't logged until the instance
+//! is dropped. Scratch volumes aren't audited, as each instance gets new directories.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::{Context as _, ensure};
+use notify::event::{AccessKind, AccessMode, CreateKind, ModifyKind, RemoveKind};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use tracing::{info, warn};
+
+use crate::engine::workload::ResolvedWorkload;
+
+/// Decides which operations are logged, logging `rate` of them spread evenly.
+#[derive(Debug)]
+struct Sampler {
+    rate: f64,
+    credit: f64,
+}
+
+impl Sampler {
+    fn new(rate: f64) -> Self {
+        // Start with enough credit that the first operation is logged
+        Self { rate, credit: 1.0 }
+    }
+
+    fn sample(&mut self) -> bool {
+        if self.credit >= 1.0 {
+            self.credit -= 1.0;
+            self.credit += self.rate;
+            true
+        } else {
+            self.credit += self.rate;
+            false
+        }
+    }
+}
+
+/// The operation and mode an event is audited as, if it's audited.
+fn operation(kind: &EventKind) -> Option<(&'static str, &'static str)> {
+    match kind {
+        EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(("open", "write")),
+        EventKind::Access(AccessKind::Close(_)) => Some(("open", "read")),
+        EventKind::Create(CreateKind::Folder) => Some(("create", "directory")),
+        EventKind::Create(_) => Some(("create", "file")),
+        EventKind::Remove(RemoveKind::Folder) => Some(("delete", "directory")),
+        EventKind::Remove(_) => Some(("delete", "file")),
+        EventKind::Modify(ModifyKind::Name(_)) => Some(("rename", "")),
+        _ => None,
+    }
+}
+
+/// The path the guest sees a host path at, given the mounted directories and their
+/// mount paths.
+fn guest_path(mounts: &[(PathBuf, String)], path: &Path) -> Option<String> {
+    mounts.iter().find_map(|(dir, mount_path)| {
+        let relative = path.strip_prefix(dir).ok()?;
+        if relative.as_os_str().is_empty() {
+            return Some(mount_path.clone());
+        }
+        Some(
+            Path::new(mount_path)
+                .join(relative)
+                .to_string_lossy()
+                .into_owned(),
+        )
+    })
+}
+
+/// Watches the volumes of a host's running workloads.
+pub struct FsAudit {
+    sample_rate: f64,
+    /// Watchers by workload ID, which stop watching when dropped
+    running: Mutex<HashMap<String, RecommendedWatcher>>,
+}
+
+impl FsAudit {
+    /// Creates an audit logging `sample_rate` of the operations, from more than 0 up to 1
+    /// for every operation.
+    ///
+    /// # Errors
+    /// Returns an error if the sample rate is out of range.
+    pub fn new(sample_rate: f64) -> anyhow::Result<Self> {
+        ensure!(
+            sample_rate > 0.0 && sample_rate <= 1.0,
+            "filesystem audit sample rate must be more than 0 and at most 1, got {sample_rate}"
+        );
+        Ok(Self {
+            sample_rate,
+            running: Mutex::default(),
+        })
+    }
+
+    /// Starts watching the directories a workload mounts, until [`Self::stop`] is called.
+    ///
+    /// # Errors
+    /// Returns an error if a directory can't be watched.
+    pub async fn start(&self, workload: &ResolvedWorkload) -> anyhow::Result<()> {
+        let mut mounts: Vec<(PathBuf, String)> = Vec::new();
+        for (host_path, mount) in workload.volume_mounts().await {
+            let dir = tokio::fs::canonicalize(&host_path)
+                .await
+                .with_context(|| format!("failed to resolve {}", host_path.display()))?;
+            if !mounts.iter().any(|(existing, _)| *existing == dir) {
+                mounts.push((dir, mount.mount_path));
+            }
+        }
+        if mounts.is_empty() {
+            return Ok(());
+        }
+
+        let workload_id = workload.id().to_string();
+        let mut sampler = Sampler::new(self.sample_rate);
+        let watched = mounts.clone();
+        let mut watcher = notify::recommended_watcher({
+            let workload_id = workload_id.clone();
+            move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        warn!(workload_id, err = ?e, "error watching volumes for audit");
+                        return;
+                    }
+                };
+                let Some((operation, mode)) = operation(&event.kind) else {
+                    return;
+                };
+                for path in &event.paths {
+                    let Some(path) = guest_path(&watched, path) else {
+                        continue;
+                    };
+                    if sampler.sample() {
+                        info!(workload_id, operation, mode, path, "filesystem access");
+                    }
+                }
+            }
+        })
+        .context("failed to create volume watcher")?;
+        for (dir, _) in &mounts {
+            watcher
+                .watch(dir, RecursiveMode::Recursive)
+                .with_context(|| format!("failed to watch {}", dir.display()))?;
+        }
+        self.running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(workload_id, watcher);
+        Ok(())
+    }
+
+    /// Stops watching the directories a workload mounts.
+    pub fn stop(&self, workload_id: &str) {
+        self.running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(workload_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampler() {
+        let mut sampler = Sampler::new(0.25);
+        let sampled: Vec<_> = (0..8).map(|_| sampler.sample()).collect();
+        assert_eq!(
+            sampled,
+            [true, false, false, false, true, false, false, false]
+        );
+
+        let mut sampler = Sampler::new(1.0);
+        assert!((0..4).all(|_| sampler.sample()));
+        assert!(FsAudit::new(0.0).is_err());
+        assert!(FsAudit::new(1.5).is_err());
+    }
+
+    #[test]
+    fn test_guest_path() {
+        let mounts = [
+            (PathBuf::from("/srv/data"), "/data".to_string()),
+            (PathBuf::from("/tmp/cache"), "/cache".to_string()),
+        ];
+        assert_eq!(
+            guest_path(&mounts, Path::new("/srv/data/a/b.txt")).as_deref(),
+            Some("/data/a/b.txt")
+        );
+        assert_eq!(
+            guest_path(&mounts, Path::new("/tmp/cache")).as_deref(),
+            Some("/cache")
+        );
+        assert_eq!(guest_path(&mounts, Path::new("/srv/other")), None);
+    }
+
+    #[test]
+    fn test_operation() {
+        assert_eq!(
+            operation(&EventKind::Access(AccessKind::Close(AccessMode::Write))),
+            Some(("open", "write"))
+        );
+        assert_eq!(
+            operation(&EventKind::Remove(RemoveKind::Folder)),
+            Some(("delete", "directory"))
+        );
+        assert_eq!(
+            operation(&EventKind::Access(AccessKind::Open(AccessMode::Any))),
+            None
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! The subset of `google/protobuf/descriptor.proto` the ingress needs to describe
+//! services and the messages their methods take, decoded from the `FileDescriptorSet`
+//! a workload declares, e.g. the output of `protoc --descriptor_set_out`, or encoded
+//! from the services [generated](super::generate) for its published interfaces.
+
+use std::collections::HashMap;
+
+use anyhow::{Context as _, ensure};
+use prost::Message as _;
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct FileDescriptorSet {
+    #[prost(message, repeated, tag = "1")]
+    pub file: Vec<FileDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct FileDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub package: Option<String>,
+    #[prost(message, repeated, tag = "4")]
+    pub message_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "5")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, repeated, tag = "6")]
+    pub service: Vec<ServiceDescriptorProto>,
+    #[prost(string, optional, tag = "12")]
+    pub syntax: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct DescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    pub field: Vec<FieldDescriptorProto>,
+    #[prost(message, repeated, tag = "3")]
+    pub nested_type: Vec<DescriptorProto>,
+    #[prost(message, repeated, tag = "4")]
+    pub enum_type: Vec<EnumDescriptorProto>,
+    #[prost(message, optional, tag = "7")]
+    pub options: Option<MessageOptions>,
+    #[prost(message, repeated, tag = "8")]
+    pub oneof_decl: Vec<OneofDescriptorProto>,
+}
+
+impl DescriptorProto {
+    /// Whether this is the entry type protoc generates for a `map<K, V>` field.
+    pub fn is_map_entry(&self) -> bool {
+        self.options
+            .as_ref()
+            .and_then(|options| options.map_entry)
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct MessageOptions {
+    #[prost(bool, optional, tag = "7")]
+    pub map_entry: Option<bool>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct FieldDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(int32, optional, tag = "3")]
+    pub number: Option<i32>,
+    #[prost(int32, optional, tag = "4")]
+    pub label: Option<i32>,
+    #[prost(int32, optional, tag = "5")]
+    pub r#type: Option<i32>,
+    #[prost(string, optional, tag = "6")]
+    pub type_name: Option<String>,
+    #[prost(int32, optional, tag = "9")]
+    pub oneof_index: Option<i32>,
+    /// Whether the field is a proto3 `optional` field, which tracks whether it's set
+    #[prost(bool, optional, tag = "17")]
+    pub proto3_optional: Option<bool>,
+}
+
+impl FieldDescriptorProto {
+    pub fn is_repeated(&self) -> bool {
+        self.label == Some(LABEL_REPEATED)
+    }
+
+    pub fn field_type(&self) -> anyhow::Result<FieldType> {
+        FieldType::from_i32(self.r#type.unwrap_or_default())
+            .with_context(|| format!("field '{}' has an unsupported type", self.name()))
+    }
+
+    /// The fully qualified name of the field's message or enum type, without the
+    /// leading dot.
+    pub fn full_type_name(&self) -> &str {
+        self.type_name().trim_start_matches('.')
+    }
+}
+
+/// `FieldDescriptorProto.Label.LABEL_OPTIONAL`
+pub(super) const LABEL_OPTIONAL: i32 = 1;
+/// `FieldDescriptorProto.Label.LABEL_REPEATED`
+pub(super) const LABEL_REPEATED: i32 = 3;
+
+/// The field types of `FieldDescriptorProto.Type`, except groups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum FieldType {
+    Double = 1,
+    Float = 2,
+    Int64 = 3,
+    Uint64 = 4,
+    Int32 = 5,
+    Fixed64 = 6,
+    Fixed32 = 7,
+    Bool = 8,
+    String = 9,
+    Message = 11,
+    Bytes = 12,
+    Uint32 = 13,
+    Enum = 14,
+    Sfixed32 = 15,
+    Sfixed64 = 16,
+    Sint32 = 17,
+    Sint64 = 18,
+}
+
+impl FieldType {
+    fn from_i32(value: i32) -> Option<Self> {
+        Some(match value {
+            1 => Self::Double,
+            2 => Self::Float,
+            3 => Self::Int64,
+            4 => Self::Uint64,
+            5 => Self::Int32,
+            6 => Self::Fixed64,
+            7 => Self::Fixed32,
+            8 => Self::Bool,
+            9 => Self::String,
+            11 => Self::Message,
+            12 => Self::Bytes,
+            13 => Self::Uint32,
+            14 => Self::Enum,
+            15 => Self::Sfixed32,
+            16 => Self::Sfixed64,
+            17 => Self::Sint32,
+            18 => Self::Sint64,
+            _ => return None,
+        })
+    }
+
+    /// Whether repeated fields of the type are packed into one length-delimited record.
+    pub fn is_packable(self) -> bool {
+        !matches!(self, Self::String | Self::Bytes | Self::Message)
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct OneofDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct EnumDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    pub value: Vec<EnumValueDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct EnumValueDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(int32, optional, tag = "2")]
+    pub number: Option<i32>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct ServiceDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(message, repeated, tag = "2")]
+    pub method: Vec<MethodDescriptorProto>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub(super) struct MethodDescriptorProto {
+    #[prost(string, optional, tag = "1")]
+    pub name: Option<String>,
+    #[prost(string, optional, tag = "2")]
+    pub input_type: Option<String>,
+    #[prost(string, optional, tag = "3")]
+    pub output_type: Option<String>,
+    #[prost(bool, optional, tag = "5")]
+    pub client_streaming: Option<bool>,
+    #[prost(bool, optional, tag = "6")]
+    pub server_streaming: Option<bool>,
+}
+
+/// The services, messages and enums of a descriptor set by fully qualified name.
+#[derive(Debug, Default)]
+pub(super) struct Descriptors {
+    pub services: HashMap<String, ServiceDescriptorProto>,
+    pub messages: HashMap<String, DescriptorProto>,
+    pub enums: HashMap<String, EnumDescriptorProto>,
+}
+
+impl Descriptors {
+    /// Decodes an encoded `FileDescriptorSet`.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes aren't a descriptor set.
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Self> {
+        let set = FileDescriptorSet::decode(bytes).context("invalid FileDescriptorSet")?;
+        let mut descriptors = Self::default();
+        for file in set.file {
+            let package = file.package.unwrap_or_default();
+            for service in file.service {
+                let name = qualify(&package, service.name.as_deref().unwrap_or_default());
+                descriptors.services.insert(name, service);
+            }
+            for message in file.message_type {
+                descriptors.add_message(&package, message);
+            }
+            for enumeration in file.enum_type {
+                let name = qualify(&package, enumeration.name.as_deref().unwrap_or_default());
+                descriptors.enums.insert(name, enumeration);
+            }
+        }
+        Ok(descriptors)
+    }
+
+    fn add_message(&mut self, scope: &str, mut message: DescriptorProto) {
+        let name = qualify(scope, message.name.as_deref().unwrap_or_default());
+        for nested in std::mem::take(&mut message.nested_type) {
+            self.add_message(&name, nested);
+        }
+        for enumeration in std::mem::take(&mut message.enum_type) {
+            let enum_name = qualify(&name, enumeration.name.as_deref().unwrap_or_default());
+            self.enums.insert(enum_name, enumeration);
+        }
+        self.messages.insert(name, message);
+    }
+
+    pub fn message(&self, name: &str) -> anyhow::Result<&DescriptorProto> {
+        self.messages
+            .get(name.trim_start_matches('.'))
+            .with_context(|| format!("descriptor set has no message '{name}'"))
+    }
+
+    pub fn enumeration(&self, name: &str) -> anyhow::Result<&EnumDescriptorProto> {
+        self.enums
+            .get(name.trim_start_matches('.'))
+            .with_context(|| format!("descriptor set has no enum '{name}'"))
+    }
+
+    /// Checks that every message and enum a message refers to is in the set, so
+    /// requests can't fail on a missing type later.
+    pub fn check_message(&self, name: &str) -> anyhow::Result<()> {
+        let mut pending = vec![name.trim_start_matches('.').to_string()];
+        let mut seen = std::collections::HashSet::new();
+        while let Some(name) = pending.pop() {
+            if !seen.insert(name.clone()) {
+                continue;
+            }
+            for field in &self.message(&name)?.field {
+                match field.field_type()? {
+                    FieldType::Message => pending.push(field.full_type_name().to_string()),
+                    FieldType::Enum => {
+                        ensure!(
+                            !self.enumeration(field.full_type_name())?.value.is_empty(),
+                            "enum '{}' has no values",
+                            field.full_type_name()
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{scope}.{name}")
+    }
+}
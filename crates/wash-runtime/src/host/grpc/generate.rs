@@ -0,0 +1,462 @@
+//! Generates typed gRPC services for the interfaces workloads publish as named services
+//! (see [`crate::host::services`]), so clients outside the host can call them without a
+//! hand-written descriptor.
+//!
+//! Each published interface becomes a service in the package
+//! `<namespace>.<service>.<wit namespace>.<wit package>.<interface>`, named after the
+//! interface: `acme:inventory/query` published by the `stock` service of the `shop`
+//! namespace is `shop.stock.acme.inventory.query.Query`. Each function of the interface
+//! becomes a unary method, `get-stock` becoming `GetStock`, taking a `GetStockRequest`
+//! with a field per parameter. A function returning a record responds with the record as
+//! its `GetStockResponse`, and a function returning any other value with a
+//! `GetStockResponse` holding the value in its `result` field. Fields map from WIT types
+//! as follows, with messages and enums nested in the message of the field using them:
+//!
+//! | WIT type                   | protobuf field                                      |
+//! |----------------------------|-----------------------------------------------------|
+//! | `bool`                     | `bool`                                              |
+//! | `s8`, `s16`, `s32`, `s64`  | `int32`, or `int64` for `s64`                       |
+//! | `u8`, `u16`, `u32`, `u64`  | `uint32`, or `uint64` for `u64`                     |
+//! | `f32`, `f64`               | `float`, `double`                                   |
+//! | `char`, `string`           | `string`                                            |
+//! | `list<u8>`                 | `bytes`                                             |
+//! | `list<T>`                  | `repeated T`                                        |
+//! | `record`                   | a message named after the field                     |
+//! | `enum`                     | an enum named after the field, its values prefixed with the enum name |
+//! | `flags`                    | a `repeated` enum of the flags                      |
+//! | `option<T>`                | `optional T`, or the message field of a record      |
+//!
+//! Functions may return a `result`, which is unwrapped the way the [ingress](super)
+//! unwraps it. Functions taking or returning a type with no mapping, such as a variant
+//! or tuple, aren't served.
+//!
+//! Calls to generated services need an API key accepted for the service's full name
+//! (see [`crate::host::api_keys`]), and clients get the service's descriptor set from
+//! [`super::GrpcIngress::descriptor_set`].
+
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{Context as _, anyhow, bail};
+use prost::Message as _;
+use tracing::warn;
+use wasmtime::component::Type;
+
+use super::IngressSpec;
+use super::descriptor::{
+    DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+    FieldType, FileDescriptorProto, FileDescriptorSet, LABEL_OPTIONAL, LABEL_REPEATED,
+    MethodDescriptorProto, OneofDescriptorProto, ServiceDescriptorProto,
+};
+use super::wire;
+use crate::engine::json::type_name;
+use crate::engine::workload::{ExportedFunction, ResolvedWorkload};
+use crate::wit::WitInterface;
+
+/// Generates the services of the interfaces a workload publishes. Workloads without a
+/// named service publish none.
+pub(super) fn services(
+    workload: &ResolvedWorkload,
+    exports: &[ExportedFunction],
+) -> Vec<IngressSpec> {
+    let Some(service) = workload.service_name() else {
+        return Vec::new();
+    };
+    published_services(
+        workload.namespace(),
+        service,
+        workload.service_exports(),
+        exports,
+    )
+}
+
+fn published_services(
+    namespace: &str,
+    service: &str,
+    published: &[WitInterface],
+    exports: &[ExportedFunction],
+) -> Vec<IngressSpec> {
+    let mut interfaces: BTreeMap<&str, Vec<&ExportedFunction>> = BTreeMap::new();
+    for export in exports {
+        let interface = WitInterface::from(export.interface.as_str());
+        if published.iter().any(|p| p.contains(&interface)) {
+            interfaces
+                .entry(export.interface.as_str())
+                .or_default()
+                .push(export);
+        }
+    }
+    interfaces
+        .into_iter()
+        .filter_map(|(interface, functions)| {
+            let wit = WitInterface::from(interface);
+            let name = wit.interfaces.iter().next()?;
+            let package = [
+                namespace,
+                service,
+                &wit.namespace,
+                &wit.package,
+                name.as_str(),
+            ]
+            .map(package_segment)
+            .join(".");
+            interface_service(&package, &pascal_case(name), &functions)
+        })
+        .collect()
+}
+
+/// Generates the service of an interface, skipping functions that can't be mapped.
+/// Returns `None` if no function can be.
+fn interface_service(
+    package: &str,
+    name: &str,
+    functions: &[&ExportedFunction],
+) -> Option<IngressSpec> {
+    let mut file = FileDescriptorProto {
+        name: Some(format!("{}.proto", package.replace('.', "/"))),
+        package: Some(package.to_string()),
+        syntax: Some("proto3".to_string()),
+        ..Default::default()
+    };
+    let mut service = ServiceDescriptorProto {
+        name: Some(name.to_string()),
+        method: Vec::new(),
+    };
+    let mut methods = HashMap::new();
+    for export in functions {
+        match method(package, export) {
+            Ok((method, messages)) => {
+                methods.insert(
+                    method.name().to_string(),
+                    format!("{}#{}", export.interface, export.name),
+                );
+                service.method.push(method);
+                file.message_type.extend(messages);
+            }
+            Err(e) => warn!(
+                interface = export.interface,
+                function = export.name,
+                err = format!("{e:#}"),
+                "not serving function over gRPC"
+            ),
+        }
+    }
+    if service.method.is_empty() {
+        return None;
+    }
+    file.service.push(service);
+    Some(IngressSpec {
+        descriptor: FileDescriptorSet { file: vec![file] }.encode_to_vec(),
+        service: format!("{package}.{name}"),
+        interface: None,
+        methods,
+        api_key: true,
+    })
+}
+
+/// Generates the method of a function, with its request and response messages.
+fn method(
+    package: &str,
+    export: &ExportedFunction,
+) -> anyhow::Result<(MethodDescriptorProto, [DescriptorProto; 2])> {
+    let name = pascal_case(&export.name);
+    let request = format!("{name}Request");
+    let response = format!("{name}Response");
+    let result = match export.results.as_slice() {
+        [] => None,
+        [Type::Result(result)] => result.ok(),
+        [ty] => Some(ty.clone()),
+        _ => bail!("functions returning several values have no gRPC method"),
+    };
+
+    let request_message = message(package, &request, export.params.clone())?;
+    let response_message = match result {
+        None => message(package, &response, Vec::new())?,
+        Some(Type::Record(record)) => {
+            let fields = record
+                .fields()
+                .map(|field| (field.name.to_string(), field.ty))
+                .collect();
+            message(package, &response, fields)?
+        }
+        Some(ty) => message(package, &response, vec![("result".to_string(), ty)])?,
+    };
+    let method = MethodDescriptorProto {
+        name: Some(name),
+        input_type: Some(format!(".{package}.{request}")),
+        output_type: Some(format!(".{package}.{response}")),
+        ..Default::default()
+    };
+    Ok((method, [request_message, response_message]))
+}
+
+/// Generates a message with a field for each WIT value, in order.
+fn message(
+    scope: &str,
+    name: &str,
+    fields: Vec<(String, Type)>,
+) -> anyhow::Result<DescriptorProto> {
+    let full_name = format!("{scope}.{name}");
+    let mut message = DescriptorProto {
+        name: Some(name.to_string()),
+        ..Default::default()
+    };
+    for (number, (field_name, ty)) in (1..).zip(fields) {
+        let field = field(&mut message, &full_name, &field_name, number, &ty)
+            .with_context(|| format!("can't map '{field_name}' to a protobuf field"))?;
+        message.field.push(field);
+    }
+    Ok(message)
+}
+
+/// Generates the field of a WIT value, nesting the message or enum of its type in the
+/// field's message.
+fn field(
+    message: &mut DescriptorProto,
+    scope: &str,
+    name: &str,
+    number: i32,
+    wit: &Type,
+) -> anyhow::Result<FieldDescriptorProto> {
+    let (ty, optional) = match wit {
+        Type::Option(option) => (option.ty(), true),
+        ty => (ty.clone(), false),
+    };
+    let (element, repeated) = match &ty {
+        Type::List(list) if !matches!(list.ty(), Type::U8) => (list.ty(), true),
+        Type::Flags(_) => (ty.clone(), true),
+        _ => (ty.clone(), false),
+    };
+    let unsupported = || anyhow!("{} has no protobuf field type", type_name(wit));
+    if optional && repeated {
+        return Err(unsupported());
+    }
+
+    let nested = pascal_case(name);
+    let field_type = match &element {
+        Type::Bool => FieldType::Bool,
+        Type::S8 | Type::S16 | Type::S32 => FieldType::Int32,
+        Type::U8 | Type::U16 | Type::U32 => FieldType::Uint32,
+        Type::S64 => FieldType::Int64,
+        Type::U64 => FieldType::Uint64,
+        Type::Float32 => FieldType::Float,
+        Type::Float64 => FieldType::Double,
+        Type::Char | Type::String => FieldType::String,
+        Type::List(list) if matches!(list.ty(), Type::U8) => FieldType::Bytes,
+        Type::Record(record) => {
+            let fields = record
+                .fields()
+                .map(|field| (field.name.to_string(), field.ty))
+                .collect();
+            message
+                .nested_type
+                .push(self::message(scope, &nested, fields)?);
+            FieldType::Message
+        }
+        Type::Enum(enumeration) => {
+            message
+                .enum_type
+                .push(enum_descriptor(&nested, enumeration.names()));
+            FieldType::Enum
+        }
+        Type::Flags(flags) => {
+            message
+                .enum_type
+                .push(enum_descriptor(&nested, flags.names()));
+            FieldType::Enum
+        }
+        _ => return Err(unsupported()),
+    };
+
+    let mut field = FieldDescriptorProto {
+        name: Some(name.replace('-', "_")),
+        number: Some(number),
+        label: Some(if repeated {
+            LABEL_REPEATED
+        } else {
+            LABEL_OPTIONAL
+        }),
+        r#type: Some(field_type as i32),
+        type_name: matches!(field_type, FieldType::Message | FieldType::Enum)
+            .then(|| format!(".{scope}.{nested}")),
+        ..Default::default()
+    };
+    // Message fields are already unset when `none`; scalars need proto3 `optional`, which
+    // is declared with a synthetic oneof
+    if optional && field_type != FieldType::Message {
+        field.proto3_optional = Some(true);
+        field.oneof_index = Some(message.oneof_decl.len() as i32);
+        message.oneof_decl.push(OneofDescriptorProto {
+            name: Some(format!("_{}", field.name())),
+        });
+    }
+    Ok(field)
+}
+
+/// Generates an enum with a value for each case, numbered from zero.
+fn enum_descriptor<'a>(name: &str, cases: impl Iterator<Item = &'a str>) -> EnumDescriptorProto {
+    let prefix = wire::enum_prefix(name);
+    EnumDescriptorProto {
+        name: Some(name.to_string()),
+        value: (0..)
+            .zip(cases)
+            .map(|(number, case)| EnumValueDescriptorProto {
+                name: Some(format!(
+                    "{prefix}{}",
+                    case.to_ascii_uppercase().replace('-', "_")
+                )),
+                number: Some(number),
+            })
+            .collect(),
+    }
+}
+
+/// Returns a WIT name as a protobuf type or method name, e.g. `get-stock` as `GetStock`.
+fn pascal_case(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Returns a name as a segment of a protobuf package, e.g. `my-service` as `my_service`.
+fn package_segment(name: &str) -> String {
+    let segment: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if segment.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{segment}")
+    } else {
+        segment
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::descriptor::Descriptors;
+    use super::*;
+    use serde_json::json;
+
+    const QUERY: &str = r#"
+        (component
+          (import "acme:inventory/query@0.1.0" (instance $query
+            (type $color (enum "red" "dark-red"))
+            (export "color" (type $color' (eq $color)))
+            (type $item (record (field "sku" string) (field "count" u32) (field "color" $color')))
+            (export "item" (type $item' (eq $item)))
+            (type $tags (flags "new" "on-sale"))
+            (export "tags" (type $tags' (eq $tags)))
+            (type $choice (variant (case "a") (case "b")))
+            (export "choice" (type $choice' (eq $choice)))
+            (export "get-item" (func (param "sku" string) (result (result $item' (error string)))))
+            (export "count-items"
+              (func (param "skus" (list string)) (param "limit" (option u32)) (param "tags" $tags')
+                    (result u64)))
+            (export "pick" (func (param "choice" $choice')))
+          ))
+        )
+    "#;
+
+    /// Reads the functions of the interface `QUERY` imports, which have the types an
+    /// exporting component's functions would have.
+    fn exports() -> anyhow::Result<Vec<ExportedFunction>> {
+        use wasmtime::component::types::ComponentItem;
+
+        let engine = wasmtime::Engine::default();
+        let component = wasmtime::component::Component::new(&engine, wat::parse_str(QUERY)?)?;
+        let mut functions = Vec::new();
+        for (interface, item) in component.component_type().imports(&engine) {
+            let ComponentItem::ComponentInstance(instance) = item else {
+                continue;
+            };
+            for (name, item) in instance.exports(&engine) {
+                if let ComponentItem::ComponentFunc(func) = item {
+                    functions.push(ExportedFunction {
+                        interface: interface.to_string(),
+                        name: name.to_string(),
+                        params: func.params().map(|(n, ty)| (n.to_string(), ty)).collect(),
+                        results: func.results().collect(),
+                    });
+                }
+            }
+        }
+        Ok(functions)
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(pascal_case("get-stock"), "GetStock");
+        assert_eq!(pascal_case("sku2-id"), "Sku2Id");
+        assert_eq!(package_segment("my-service"), "my_service");
+        assert_eq!(package_segment("1st"), "_1st");
+    }
+
+    #[test]
+    fn test_published_services() -> anyhow::Result<()> {
+        let exports = exports()?;
+        let published = [WitInterface::from("acme:inventory/query")];
+        let [spec] =
+            <[IngressSpec; 1]>::try_from(published_services("shop", "stock", &published, &exports))
+                .map_err(|specs| anyhow!("expected one service, got {}", specs.len()))?;
+        assert_eq!(spec.service, "shop.stock.acme.inventory.query.Query");
+        assert!(spec.api_key);
+        assert_eq!(
+            spec.target("GetItem")?,
+            (
+                "acme:inventory/query@0.1.0".to_string(),
+                "get-item".to_string()
+            )
+        );
+        // Variants have no protobuf field type, so `pick` isn't served
+        assert!(!spec.methods.contains_key("Pick"));
+
+        let descriptors = Descriptors::decode(&spec.descriptor)?;
+        let service = &descriptors.services[&spec.service];
+        assert_eq!(service.method.len(), 2);
+        let package = "shop.stock.acme.inventory.query";
+        for message in ["GetItemRequest", "GetItemResponse", "CountItemsRequest"] {
+            descriptors.check_message(&format!("{package}.{message}"))?;
+        }
+
+        // The record result is the response message
+        let item = json!({ "sku": "sku-1", "count": 3, "color": "dark-red" });
+        let response = format!("{package}.GetItemResponse");
+        let encoded = wire::encode_message(&descriptors, &response, &item)?;
+        assert_eq!(
+            wire::decode_message(&descriptors, &response, &encoded)?,
+            item
+        );
+
+        // Optional scalars are null when unset, and flags are a list of names
+        let request = format!("{package}.CountItemsRequest");
+        let encoded = wire::encode_message(
+            &descriptors,
+            &request,
+            &json!({ "skus": ["a", "b"], "tags": ["on-sale"] }),
+        )?;
+        assert_eq!(
+            wire::decode_message(&descriptors, &request, &encoded)?,
+            json!({ "skus": ["a", "b"], "limit": null, "tags": ["on-sale"] })
+        );
+        let response = format!("{package}.CountItemsResponse");
+        let encoded = wire::encode_message(&descriptors, &response, &json!({ "result": 7 }))?;
+        assert_eq!(
+            wire::decode_message(&descriptors, &response, &encoded)?,
+            json!({ "result": 7 })
+        );
+
+        assert!(published_services("shop", "stock", &[], &exports).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,811 @@
+//! gRPC ingress that maps the methods of protobuf-described services to workload
+//! exports, so gRPC clients can call components without a JSON translation layer.
+//!
+//! A workload serves a gRPC service by adding a [`INGRESS_INTERFACE`] host interface
+//! with this config:
+//!
+//! | key              | value                                                          |
+//! |------------------|----------------------------------------------------------------|
+//! | `descriptor`     | base64 `FileDescriptorSet` describing the service, e.g. from `protoc --descriptor_set_out` |
+//! | `service`        | the fully qualified service name, e.g. `acme.inventory.Stock`  |
+//! | `interface`      | the exported interface methods are mapped into by default      |
+//! | `method.<Name>`  | the export a method is mapped to, as `function` in `interface` or `interface#function` |
+//! | `api-key`        | `required` to only accept calls with an API key                |
+//!
+//! Methods without a `method.<Name>` entry are mapped to the kebab-cased method name
+//! in `interface`, so `GetStock` calls `get-stock`. Every method of the service must map
+//! to an export; streaming methods aren't supported.
+//!
+//! The fields of a request message are passed as the parameters of the same name, with
+//! `_` in field names written as `-`. Messages map to records, maps to lists of key-value
+//! tuples, and enums to the WIT enum cases named after their values without the enum's
+//! prefix, so `COLOR_DARK_RED` of `Color` is `dark-red`. A function returning a record
+//! responds with it as the response message, and a function returning any other value
+//! responds with a message with a single field set to it. Functions may return a `result`: its `ok`
+//! value is the response, and its `err` value fails the call. Errors that are a string,
+//! enum case or variant case named after a gRPC status code, e.g. `not-found`, fail with
+//! that code; others fail with `UNKNOWN`.
+//!
+//! Failed calls report a status for their [`HostError`] cause: `RESOURCE_EXHAUSTED` when
+//! the workload ran out of fuel, `UNAVAILABLE` when it's paused or a plugin it needs is
+//! unhealthy, `INVALID_ARGUMENT` when the request doesn't convert to the export's
+//! parameters, and `INTERNAL` for traps and other errors.
+//!
+//! A workload can serve several services. Calls to a method are spread over the
+//! instances of the workloads serving it. Workloads publishing interfaces as a named
+//! service also serve the services [generated](generate) for them.
+//!
+//! Services requiring a key accept the keys of the host's [`ApiKeyStore`] created for the
+//! service's full name, or for `*`, sent in the `x-api-key` metadata. Calls without one
+//! fail with `UNAUTHENTICATED`.
+//!
+//! Hosts serve the ingress when built with
+//! [`crate::host::HostBuilder::with_grpc_ingress_addr`].
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+use anyhow::{Context as _, bail, ensure};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use bytes::{Buf as _, BufMut as _, Bytes};
+use futures::future::BoxFuture;
+use serde_json::Value;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tonic::Status;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tracing::{debug, warn};
+use wasmtime::component::Type;
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::engine::workload::{ExportedFunction, ResolvedWorkload};
+use crate::host::api_keys::ApiKeyStore;
+use crate::host::error::HostError;
+use crate::redact;
+use crate::wit::WitInterface;
+
+mod descriptor;
+pub mod generate;
+mod wire;
+
+use descriptor::{Descriptors, MethodDescriptorProto};
+
+/// The host interface a workload declares a gRPC service with.
+pub const INGRESS_INTERFACE: &str = "wasmcloud:grpc/ingress";
+/// Config key holding the base64 `FileDescriptorSet` describing the service.
+pub const DESCRIPTOR_KEY: &str = "descriptor";
+/// Config key naming the fully qualified service.
+pub const SERVICE_KEY: &str = "service";
+/// Config key naming the interface methods are mapped into by default.
+pub const INTERFACE_KEY: &str = "interface";
+/// Prefix of the config keys mapping a method to an export.
+pub const METHOD_KEY_PREFIX: &str = "method.";
+/// Config key set to `required` to only accept calls with an API key.
+pub const API_KEY_KEY: &str = "api-key";
+
+/// A gRPC service declared by a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IngressSpec {
+    /// The encoded `FileDescriptorSet`
+    pub descriptor: Vec<u8>,
+    pub service: String,
+    pub interface: Option<String>,
+    /// Exports of methods mapped explicitly, keyed by method name
+    pub methods: HashMap<String, String>,
+    /// Whether calls need an API key
+    pub api_key: bool,
+}
+
+impl IngressSpec {
+    /// Parses the service declared by an [`INGRESS_INTERFACE`] host interface.
+    ///
+    /// # Errors
+    /// Returns an error if a key is missing or has an invalid value.
+    pub fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let required = |key: &str| {
+            config
+                .get(key)
+                .filter(|value| !value.is_empty())
+                .with_context(|| format!("gRPC ingress is missing '{key}'"))
+        };
+        let descriptor = BASE64
+            .decode(required(DESCRIPTOR_KEY)?.trim())
+            .with_context(|| format!("{DESCRIPTOR_KEY} is not valid base64"))?;
+        let methods = config
+            .iter()
+            .filter_map(|(key, value)| {
+                let method = key.strip_prefix(METHOD_KEY_PREFIX)?;
+                Some((method.to_string(), value.clone()))
+            })
+            .collect();
+        let api_key = match config.get(API_KEY_KEY).map(String::as_str) {
+            None => false,
+            Some("required") => true,
+            Some(other) => bail!("invalid {API_KEY_KEY} '{other}', expected 'required'"),
+        };
+        Ok(Self {
+            descriptor,
+            service: required(SERVICE_KEY)?.trim_start_matches('.').to_string(),
+            interface: config
+                .get(INTERFACE_KEY)
+                .filter(|value| !value.is_empty())
+                .cloned(),
+            methods,
+            api_key,
+        })
+    }
+
+    /// Returns the services declared in a workload's host interfaces.
+    ///
+    /// # Errors
+    /// Returns an error if any declaration is invalid.
+    pub fn from_host_interfaces(interfaces: &[WitInterface]) -> anyhow::Result<Vec<Self>> {
+        let ingress = WitInterface::from(INGRESS_INTERFACE);
+        interfaces
+            .iter()
+            .filter(|iface| ingress.contains(iface))
+            .map(|iface| Self::from_config(&iface.config))
+            .collect()
+    }
+
+    /// Returns the export a method is mapped to, as `(interface, function)`.
+    ///
+    /// # Errors
+    /// Returns an error if the method has no mapping and no default interface is set.
+    pub fn target(&self, method: &str) -> anyhow::Result<(String, String)> {
+        let target = match self.methods.get(method) {
+            Some(target) => target.clone(),
+            None => kebab_case(method),
+        };
+        if let Some((interface, function)) = target.rsplit_once('#') {
+            ensure!(
+                !interface.is_empty() && !function.is_empty(),
+                "invalid export '{target}' for method '{method}', expected 'interface#function'"
+            );
+            return Ok((interface.to_string(), function.to_string()));
+        }
+        let interface = self.interface.as_ref().with_context(|| {
+            format!("method '{method}' needs '{INTERFACE_KEY}' or '{METHOD_KEY_PREFIX}{method}'")
+        })?;
+        Ok((interface.clone(), target))
+    }
+}
+
+/// Returns a method name as a WIT function name, e.g. `GetStock` as `get-stock`.
+fn kebab_case(name: &str) -> String {
+    let mut kebab = String::with_capacity(name.len() + 4);
+    let mut chars = name.chars().peekable();
+    let mut previous: Option<char> = None;
+    while let Some(c) = chars.next() {
+        if c == '_' {
+            kebab.push('-');
+        } else if c.is_ascii_uppercase() {
+            let next_lower = chars.peek().is_some_and(|n| n.is_ascii_lowercase());
+            if previous.is_some_and(|p| {
+                p.is_ascii_lowercase()
+                    || p.is_ascii_digit()
+                    || (p.is_ascii_uppercase() && next_lower)
+            }) {
+                kebab.push('-');
+            }
+            kebab.push(c.to_ascii_lowercase());
+        } else {
+            kebab.push(c);
+        }
+        previous = Some(c);
+    }
+    kebab
+}
+
+/// A method mapped to the export of a workload.
+struct MethodRoute {
+    workload: ResolvedWorkload,
+    /// The service the method is in
+    spec: Arc<IngressSpec>,
+    descriptors: Arc<Descriptors>,
+    input: String,
+    output: String,
+    /// The exported interface the function is in
+    interface: String,
+    function: String,
+    /// The request message fields passed as parameters, in order
+    params: Vec<String>,
+    /// Whether the function returns a `result` whose `ok` value is the response
+    unwrap_result: bool,
+    /// The response message field the result is set in, or `None` if the result is
+    /// the response message
+    result_field: Option<String>,
+}
+
+impl MethodRoute {
+    /// Checks that a method's messages fit the export it's mapped to.
+    fn new(
+        workload: ResolvedWorkload,
+        spec: Arc<IngressSpec>,
+        descriptors: Arc<Descriptors>,
+        method: &MethodDescriptorProto,
+        export: &ExportedFunction,
+    ) -> anyhow::Result<Self> {
+        let input = method.input_type.as_deref().unwrap_or_default();
+        let output = method.output_type.as_deref().unwrap_or_default();
+        descriptors.check_message(input)?;
+        descriptors.check_message(output)?;
+
+        let fields: Vec<String> = descriptors
+            .message(input)?
+            .field
+            .iter()
+            .map(|f| wire::field_key(f.name()))
+            .collect();
+        let params = export
+            .params
+            .iter()
+            .map(|(name, _)| {
+                ensure!(
+                    fields.contains(name),
+                    "request message '{input}' has no field for parameter '{name}'"
+                );
+                Ok(name.clone())
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let (unwrap_result, response) = match export.results.as_slice() {
+            [] => (false, None),
+            [Type::Result(result)] => (true, result.ok()),
+            [ty] => (false, Some(ty.clone())),
+            _ => bail!("functions returning several values can't be mapped to a method"),
+        };
+        let result_field = match response {
+            None | Some(Type::Record(_)) => None,
+            Some(_) => match descriptors.message(output)?.field.as_slice() {
+                [field] => Some(wire::field_key(field.name())),
+                _ => bail!(
+                    "response message '{output}' must have a single field for the result of '{}'",
+                    export.name
+                ),
+            },
+        };
+
+        Ok(Self {
+            workload,
+            spec,
+            descriptors,
+            input: input.to_string(),
+            output: output.to_string(),
+            interface: export.interface.clone(),
+            function: export.name.clone(),
+            params,
+            unwrap_result,
+            result_field,
+        })
+    }
+
+    /// Calls the export with a request message, returning the response message.
+    async fn call(&self, request: Bytes) -> Result<Bytes, Status> {
+        let mut request = match wire::decode_message(&self.descriptors, &self.input, &request) {
+            Ok(Value::Object(fields)) => fields,
+            Ok(_) => unreachable!("messages decode to objects"),
+            Err(e) => return Err(Status::invalid_argument(format!("{e:#}"))),
+        };
+        let args: Vec<Value> = self
+            .params
+            .iter()
+            .map(|name| request.remove(name).unwrap_or(Value::Null))
+            .collect();
+
+        let mut results = self
+            .workload
+            .call_export_json(&self.interface, &self.function, &args)
+            .await
+            .map_err(|e| call_status(&e))?;
+        let mut result = results.pop().unwrap_or(Value::Null);
+        if self.unwrap_result {
+            result = match result {
+                Value::Object(mut outcome) => match (outcome.remove("ok"), outcome.remove("err")) {
+                    (Some(ok), _) => ok,
+                    (_, Some(err)) => return Err(error_status(err)),
+                    _ => unreachable!("results convert to an ok or err object"),
+                },
+                _ => unreachable!("results convert to objects"),
+            };
+        }
+        let response = match &self.result_field {
+            Some(field) => serde_json::json!({ field: result }),
+            None => result,
+        };
+        wire::encode_message(&self.descriptors, &self.output, &response)
+            .map(Bytes::from)
+            .map_err(|e| Status::internal(format!("invalid response: {e:#}")))
+    }
+}
+
+/// Maps an error calling an export to a status by its [`HostError`] cause, see the
+/// [module docs](self).
+fn call_status(error: &anyhow::Error) -> Status {
+    let message = redact::redact(&format!("{error:#}")).into_owned();
+    match HostError::of(error) {
+        HostError::InvalidRequest => Status::invalid_argument(message),
+        HostError::QuotaExceeded { .. } => Status::resource_exhausted(message),
+        HostError::WorkloadNotRunning { .. } | HostError::PluginUnavailable { .. } => {
+            Status::unavailable(message)
+        }
+        _ => Status::internal(message),
+    }
+}
+
+/// Maps an error returned by a function to a status, see the [module docs](self).
+fn error_status(error: Value) -> Status {
+    let (case, message) = match &error {
+        Value::String(case) => (case.as_str(), None),
+        Value::Object(variant) if variant.len() == 1 => {
+            let (case, payload) = variant.iter().next().expect("one entry");
+            (case.as_str(), payload.as_str())
+        }
+        _ => return Status::unknown(redact::redact(&error.to_string())),
+    };
+    let code = match case {
+        "cancelled" => tonic::Code::Cancelled,
+        "invalid-argument" => tonic::Code::InvalidArgument,
+        "deadline-exceeded" => tonic::Code::DeadlineExceeded,
+        "not-found" => tonic::Code::NotFound,
+        "already-exists" => tonic::Code::AlreadyExists,
+        "permission-denied" => tonic::Code::PermissionDenied,
+        "resource-exhausted" => tonic::Code::ResourceExhausted,
+        "failed-precondition" => tonic::Code::FailedPrecondition,
+        "aborted" => tonic::Code::Aborted,
+        "out-of-range" => tonic::Code::OutOfRange,
+        "unimplemented" => tonic::Code::Unimplemented,
+        "internal" => tonic::Code::Internal,
+        "unavailable" => tonic::Code::Unavailable,
+        "data-loss" => tonic::Code::DataLoss,
+        "unauthenticated" => tonic::Code::Unauthenticated,
+        _ => {
+            let message = match &error {
+                Value::String(message) => message.clone(),
+                other => other.to_string(),
+            };
+            return Status::unknown(redact::redact(&message));
+        }
+    };
+    Status::new(code, redact::redact(message.unwrap_or(case)))
+}
+
+/// The methods served by the ingress, keyed by their gRPC path `/{service}/{method}`.
+#[derive(Default)]
+struct Router {
+    methods: RwLock<HashMap<String, Vec<Arc<MethodRoute>>>>,
+    next: AtomicUsize,
+}
+
+impl Router {
+    /// Picks the route of a method, rotating over the workloads serving it.
+    fn pick(&self, path: &str) -> Option<Arc<MethodRoute>> {
+        let methods = self.methods.read().unwrap_or_else(PoisonError::into_inner);
+        let routes = methods.get(path).filter(|routes| !routes.is_empty())?;
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % routes.len();
+        Some(routes[index].clone())
+    }
+}
+
+/// Serves the gRPC services declared by a host's workloads.
+pub struct GrpcIngress {
+    addr: SocketAddr,
+    router: Arc<Router>,
+    /// The keys accepted by services requiring one
+    api_keys: Option<Arc<ApiKeyStore>>,
+    server: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl GrpcIngress {
+    /// Creates an ingress serving on `addr`. Services requiring an API key reject every
+    /// call when `api_keys` is `None`.
+    pub fn new(addr: SocketAddr, api_keys: Option<Arc<ApiKeyStore>>) -> Self {
+        Self {
+            addr,
+            router: Arc::default(),
+            api_keys,
+            server: Mutex::default(),
+        }
+    }
+
+    /// Starts accepting connections.
+    ///
+    /// # Errors
+    /// Returns an error if the address can't be bound.
+    pub async fn start(&self) -> anyhow::Result<()> {
+        let listener = TcpListener::bind(self.addr)
+            .await
+            .with_context(|| format!("failed to bind gRPC ingress to {}", self.addr))?;
+        debug!(addr = %self.addr, "serving workload gRPC services");
+        let server = tokio::spawn(serve(listener, self.router.clone(), self.api_keys.clone()));
+        if let Some(previous) = self
+            .server
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(server)
+        {
+            previous.abort();
+        }
+        Ok(())
+    }
+
+    /// Stops accepting connections.
+    pub fn stop(&self) {
+        if let Some(server) = self
+            .server
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            server.abort();
+        }
+    }
+
+    /// Serves the gRPC services a workload declares, and those generated for the
+    /// interfaces it publishes, until [`Self::unregister`] is called.
+    ///
+    /// # Errors
+    /// Returns an error, without serving any method, if a declaration is invalid, or a
+    /// method streams or doesn't fit the export it's mapped to.
+    pub async fn register(&self, workload: &ResolvedWorkload) -> anyhow::Result<()> {
+        let mut specs = IngressSpec::from_host_interfaces(workload.host_interfaces())
+            .context("invalid gRPC ingress")?;
+        if specs.is_empty() && workload.service_name().is_none() {
+            return Ok(());
+        }
+
+        let exports = workload.exported_functions().await;
+        specs.extend(generate::services(workload, &exports));
+        let mut routes = Vec::new();
+        for spec in specs {
+            let spec = Arc::new(spec);
+            let descriptors = Arc::new(Descriptors::decode(&spec.descriptor)?);
+            let service = descriptors
+                .services
+                .get(&spec.service)
+                .with_context(|| format!("descriptor set has no service '{}'", spec.service))?;
+            for method in &service.method {
+                let name = method.name.as_deref().unwrap_or_default();
+                ensure!(
+                    !method.client_streaming.unwrap_or(false)
+                        && !method.server_streaming.unwrap_or(false),
+                    "method '{name}' of '{}' streams, which the gRPC ingress doesn't support",
+                    spec.service
+                );
+                let (interface, function) = spec.target(name)?;
+                let wanted = WitInterface::from(interface.as_str());
+                let export = exports
+                    .iter()
+                    .find(|f| {
+                        f.name == function
+                            && (f.interface == interface
+                                || WitInterface::from(f.interface.as_str()).contains(&wanted))
+                    })
+                    .with_context(|| {
+                        format!(
+                            "no component in workload {} exports '{interface}#{function}' for method '{name}'",
+                            workload.id()
+                        )
+                    })?;
+                let route = MethodRoute::new(
+                    workload.clone(),
+                    spec.clone(),
+                    descriptors.clone(),
+                    method,
+                    export,
+                )
+                .with_context(|| {
+                    format!("can't map method '{name}' to '{interface}#{function}'")
+                })?;
+                routes.push((format!("/{}/{name}", spec.service), Arc::new(route)));
+            }
+        }
+
+        let mut methods = self
+            .router
+            .methods
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        for (path, route) in routes {
+            debug!(workload_id = workload.id(), path, "serving gRPC method");
+            methods.entry(path).or_default().push(route);
+        }
+        Ok(())
+    }
+
+    /// Returns the encoded `FileDescriptorSet` describing a service the ingress serves,
+    /// e.g. one [generated](generate) for a published interface, for clients to build
+    /// stubs from. Returns `None` if no workload serves the service.
+    pub fn descriptor_set(&self, service: &str) -> Option<Vec<u8>> {
+        let prefix = format!("/{service}/");
+        let methods = self
+            .router
+            .methods
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        methods
+            .iter()
+            .find(|(path, _)| path.starts_with(&prefix))
+            .and_then(|(_, routes)| routes.first())
+            .map(|route| route.spec.descriptor.clone())
+    }
+
+    /// Checks that a workload on a host without a gRPC ingress declares no services.
+    ///
+    /// # Errors
+    /// Returns an error if the workload declares a service.
+    pub fn check_undeclared(interfaces: &[WitInterface]) -> anyhow::Result<()> {
+        let ingress = WitInterface::from(INGRESS_INTERFACE);
+        ensure!(
+            !interfaces.iter().any(|iface| ingress.contains(iface)),
+            "workload declares a gRPC service, but this host has no gRPC ingress"
+        );
+        Ok(())
+    }
+
+    /// Stops routing calls to a workload. Calls already made are still handled.
+    pub fn unregister(&self, workload_id: &str) {
+        self.router
+            .methods
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|_, routes| {
+                routes.retain(|route| route.workload.id() != workload_id);
+                !routes.is_empty()
+            });
+    }
+}
+
+async fn serve(listener: TcpListener, router: Arc<Router>, api_keys: Option<Arc<ApiKeyStore>>) {
+    loop {
+        let (stream, client_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(err = ?e, "failed to accept gRPC ingress connection");
+                continue;
+            }
+        };
+        let router = router.clone();
+        let api_keys = api_keys.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |req| {
+                let router = router.clone();
+                let api_keys = api_keys.clone();
+                async move { Ok::<_, Infallible>(handle(&router, api_keys.as_deref(), req).await) }
+            });
+            if let Err(e) = hyper::server::conn::http2::Builder::new(TokioExecutor)
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(addr = ?client_addr, err = ?e, "error serving gRPC ingress client");
+            }
+        });
+    }
+}
+
+async fn handle(
+    router: &Router,
+    api_keys: Option<&ApiKeyStore>,
+    req: hyper::Request<hyper::body::Incoming>,
+) -> hyper::Response<tonic::body::Body> {
+    let Some(route) = router.pick(req.uri().path()) else {
+        return Status::unimplemented(format!("unknown method {}", req.uri().path())).into_http();
+    };
+    if let Err(status) = authorize(&route.spec, api_keys, req.headers()) {
+        return status.into_http();
+    }
+    tonic::server::Grpc::new(RawCodec)
+        .unary(MethodCall(route), req)
+        .await
+}
+
+/// Checks the API key of a call to a service requiring one.
+fn authorize(
+    spec: &IngressSpec,
+    api_keys: Option<&ApiKeyStore>,
+    headers: &hyper::HeaderMap,
+) -> Result<(), Status> {
+    if !spec.api_key
+        || crate::host::api_keys::verify_header(api_keys, Some(&spec.service), headers).is_some()
+    {
+        return Ok(());
+    }
+    debug!(
+        service = spec.service,
+        "rejecting gRPC call without a valid API key"
+    );
+    Err(Status::unauthenticated("missing or invalid API key"))
+}
+
+/// Calls a method's export with each request.
+struct MethodCall(Arc<MethodRoute>);
+
+impl tonic::server::UnaryService<Bytes> for MethodCall {
+    type Response = Bytes;
+    type Future = BoxFuture<'static, Result<tonic::Response<Bytes>, Status>>;
+
+    fn call(&mut self, request: tonic::Request<Bytes>) -> Self::Future {
+        let route = self.0.clone();
+        Box::pin(async move {
+            route
+                .call(request.into_inner())
+                .await
+                .map(tonic::Response::new)
+        })
+    }
+}
+
+/// Passes messages through as bytes, leaving their conversion to [`wire`].
+#[derive(Clone, Copy)]
+struct RawCodec;
+
+impl Codec for RawCodec {
+    type Encode = Bytes;
+    type Decode = Bytes;
+    type Encoder = Self;
+    type Decoder = Self;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        Self
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        Self
+    }
+}
+
+impl Encoder for RawCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn encode(&mut self, item: Bytes, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        dst.put_slice(&item);
+        Ok(())
+    }
+}
+
+impl Decoder for RawCodec {
+    type Item = Bytes;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<Bytes>, Status> {
+        Ok(Some(src.copy_to_bytes(src.remaining())))
+    }
+}
+
+/// Runs the tasks of HTTP/2 connections on the tokio runtime.
+#[derive(Clone, Copy)]
+pub(crate) struct TokioExecutor;
+
+impl<F> hyper::rt::Executor<F> for TokioExecutor
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    fn execute(&self, future: F) {
+        tokio::spawn(future);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kebab_case() {
+        assert_eq!(kebab_case("GetStock"), "get-stock");
+        assert_eq!(kebab_case("list_items"), "list-items");
+        assert_eq!(kebab_case("GetHTTPStatus"), "get-http-status");
+        assert_eq!(kebab_case("Reset2Default"), "reset2-default");
+    }
+
+    #[test]
+    fn test_ingress_spec() -> anyhow::Result<()> {
+        let spec = IngressSpec::from_config(&HashMap::from([
+            (DESCRIPTOR_KEY.to_string(), BASE64.encode(b"set")),
+            (SERVICE_KEY.to_string(), ".acme.inventory.Stock".to_string()),
+            (
+                INTERFACE_KEY.to_string(),
+                "acme:inventory/query".to_string(),
+            ),
+            (
+                "method.Reset".to_string(),
+                "acme:inventory/admin#reset".to_string(),
+            ),
+            ("method.Count".to_string(), "total".to_string()),
+            (API_KEY_KEY.to_string(), "required".to_string()),
+        ]))?;
+        assert_eq!(spec.descriptor, b"set");
+        assert_eq!(spec.service, "acme.inventory.Stock");
+        assert!(spec.api_key);
+        assert_eq!(
+            spec.target("GetStock")?,
+            ("acme:inventory/query".to_string(), "get-stock".to_string())
+        );
+        assert_eq!(
+            spec.target("Count")?,
+            ("acme:inventory/query".to_string(), "total".to_string())
+        );
+        assert_eq!(
+            spec.target("Reset")?,
+            ("acme:inventory/admin".to_string(), "reset".to_string())
+        );
+
+        assert!(IngressSpec::from_config(&HashMap::new()).is_err());
+        assert!(
+            IngressSpec::from_config(&HashMap::from([
+                (DESCRIPTOR_KEY.to_string(), "not base64!".to_string()),
+                (SERVICE_KEY.to_string(), "acme.inventory.Stock".to_string()),
+            ]))
+            .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_status() {
+        let status = call_status(&anyhow::Error::new(wasmtime::Trap::OutOfFuel));
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        let status = call_status(&anyhow::Error::new(HostError::WorkloadNotRunning {
+            workload_id: "w".to_string(),
+        }));
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        let status = call_status(
+            &anyhow::anyhow!("expected a string")
+                .context(HostError::InvalidRequest)
+                .context("invalid argument 'sku'"),
+        );
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let status = call_status(&anyhow::Error::new(wasmtime::Trap::UnreachableCodeReached));
+        assert_eq!(status.code(), tonic::Code::Internal);
+    }
+
+    #[test]
+    fn test_authorize() -> anyhow::Result<()> {
+        let store = ApiKeyStore::in_memory();
+        let (_, key) = store.create("client", vec!["acme.inventory.Stock".to_string()])?;
+        let (_, other) = store.create("other", vec!["acme.billing.Ledger".to_string()])?;
+        let mut spec = IngressSpec {
+            descriptor: Vec::new(),
+            service: "acme.inventory.Stock".to_string(),
+            interface: None,
+            methods: HashMap::new(),
+            api_key: true,
+        };
+        let headers = |key: &str| {
+            let mut headers = hyper::HeaderMap::new();
+            headers.insert("x-api-key", key.parse().expect("valid header"));
+            headers
+        };
+
+        assert!(authorize(&spec, Some(&store), &headers(&key)).is_ok());
+        for (store, headers) in [
+            (Some(&store), hyper::HeaderMap::new()),
+            (Some(&store), headers(&other)),
+            (None, headers(&key)),
+        ] {
+            let status = authorize(&spec, store, &headers).expect_err("call should be rejected");
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+
+        spec.api_key = false;
+        assert!(authorize(&spec, None, &hyper::HeaderMap::new()).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_status() {
+        let status = error_status(serde_json::json!("not-found"));
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        let status = error_status(serde_json::json!({ "invalid-argument": "bad sku" }));
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert_eq!(status.message(), "bad sku");
+        let status = error_status(serde_json::json!("out of stock"));
+        assert_eq!(status.code(), tonic::Code::Unknown);
+        assert_eq!(status.message(), "out of stock");
+    }
+}
@@ -0,0 +1,585 @@
+//! Converts protobuf messages to and from the JSON shape [`crate::engine::json`] gives
+//! WIT values, so messages can be passed to and returned from exports:
+//!
+//! | protobuf                  | JSON, as the WIT type it maps to               |
+//! |---------------------------|------------------------------------------------|
+//! | message                   | object keyed by field name, `_` written as `-` |
+//! | scalar                    | number, boolean or string                      |
+//! | `bytes`                   | array of bytes, as a `list<u8>`                |
+//! | enum                      | value name without the enum's prefix, kebab-cased |
+//! | `repeated T`              | array                                          |
+//! | `map<K, V>`               | array of `[key, value]`, as a `list<tuple<K, V>>` |
+//!
+//! Decoded messages have every field: unset scalars get their default value, and unset
+//! message fields and `optional` scalars are `null`, which maps to a WIT `option`.
+//! Unknown fields are skipped.
+
+use anyhow::{Context as _, bail, ensure};
+use serde_json::{Map, Value};
+
+use super::descriptor::{
+    DescriptorProto, Descriptors, EnumDescriptorProto, FieldDescriptorProto, FieldType,
+};
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+/// How deeply messages may nest, so a hostile message can't exhaust the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Returns the name a protobuf field has in JSON, its WIT record field name.
+pub(super) fn field_key(name: &str) -> String {
+    name.to_ascii_lowercase().replace('_', "-")
+}
+
+/// Returns the prefix the values of an enum conventionally have: `DELIVERY_MODE_` for
+/// `DeliveryMode`.
+pub(super) fn enum_prefix(enum_name: &str) -> String {
+    let mut prefix = String::new();
+    for (i, c) in enum_name.chars().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            prefix.push('_');
+        }
+        prefix.push(c.to_ascii_uppercase());
+    }
+    prefix.push('_');
+    prefix
+}
+
+/// Returns the name an enum value has in JSON, its WIT enum case: `COLOR_DARK_RED` of
+/// `Color` becomes `dark-red`.
+pub(super) fn enum_case(enum_name: &str, value_name: &str) -> String {
+    value_name
+        .strip_prefix(&enum_prefix(enum_name))
+        .filter(|rest| !rest.is_empty())
+        .unwrap_or(value_name)
+        .to_ascii_lowercase()
+        .replace('_', "-")
+}
+
+/// Decodes a message of the named type into a JSON object.
+///
+/// # Errors
+/// Returns an error if the bytes aren't a valid encoding of the message.
+pub(super) fn decode_message(
+    descriptors: &Descriptors,
+    message: &str,
+    bytes: &[u8],
+) -> anyhow::Result<Value> {
+    decode(descriptors, descriptors.message(message)?, bytes, 0)
+}
+
+/// Encodes a JSON object as a message of the named type. `null` encodes an empty
+/// message.
+///
+/// # Errors
+/// Returns an error if the JSON doesn't have the shape of the message.
+pub(super) fn encode_message(
+    descriptors: &Descriptors,
+    message: &str,
+    value: &Value,
+) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    encode(
+        descriptors,
+        descriptors.message(message)?,
+        value,
+        &mut buf,
+        0,
+    )?;
+    Ok(buf)
+}
+
+fn decode(
+    descriptors: &Descriptors,
+    message: &DescriptorProto,
+    mut bytes: &[u8],
+    depth: usize,
+) -> anyhow::Result<Value> {
+    ensure!(depth < MAX_DEPTH, "message nests too deeply");
+    let mut fields = Map::new();
+    while !bytes.is_empty() {
+        let key = read_varint(&mut bytes)?;
+        let (number, wire_type) = (key >> 3, (key & 0x7) as u8);
+        let field = message
+            .field
+            .iter()
+            .find(|f| f.number.map(i64::from) == Some(number as i64));
+        let Some(field) = field else {
+            skip(wire_type, &mut bytes)?;
+            continue;
+        };
+        let ty = field.field_type()?;
+        let name = field_key(field.name());
+        let decode_one = |bytes: &mut &[u8], wire_type: u8| {
+            decode_value(descriptors, field, ty, wire_type, bytes, depth)
+                .with_context(|| format!("invalid field '{}'", field.name()))
+        };
+        if field.is_repeated() {
+            let values = fields
+                .entry(name)
+                .or_insert_with(|| Value::Array(Vec::new()))
+                .as_array_mut()
+                .expect("repeated fields are arrays");
+            if wire_type == WIRE_LEN && ty.is_packable() {
+                let mut packed = read_len(&mut bytes)?;
+                while !packed.is_empty() {
+                    values.push(decode_one(&mut packed, wire_type_of(ty))?);
+                }
+            } else {
+                values.push(decode_one(&mut bytes, wire_type)?);
+            }
+        } else {
+            let value = decode_one(&mut bytes, wire_type)?;
+            fields.insert(name, value);
+        }
+    }
+
+    for field in &message.field {
+        let name = field_key(field.name());
+        if !fields.contains_key(&name) {
+            fields.insert(name, default_value(descriptors, field)?);
+        }
+    }
+    Ok(Value::Object(fields))
+}
+
+fn decode_value(
+    descriptors: &Descriptors,
+    field: &FieldDescriptorProto,
+    ty: FieldType,
+    wire_type: u8,
+    bytes: &mut &[u8],
+    depth: usize,
+) -> anyhow::Result<Value> {
+    let expected = wire_type_of(ty);
+    ensure!(
+        wire_type == expected,
+        "expected wire type {expected}, got {wire_type}"
+    );
+    Ok(match ty {
+        FieldType::Double => Value::from(f64::from_le_bytes(read_fixed(bytes)?)),
+        FieldType::Float => Value::from(f32::from_le_bytes(read_fixed(bytes)?) as f64),
+        FieldType::Fixed64 => Value::from(u64::from_le_bytes(read_fixed(bytes)?)),
+        FieldType::Sfixed64 => Value::from(i64::from_le_bytes(read_fixed(bytes)?)),
+        FieldType::Fixed32 => Value::from(u32::from_le_bytes(read_fixed(bytes)?)),
+        FieldType::Sfixed32 => Value::from(i32::from_le_bytes(read_fixed(bytes)?)),
+        FieldType::Int64 => Value::from(read_varint(bytes)? as i64),
+        FieldType::Uint64 => Value::from(read_varint(bytes)?),
+        FieldType::Int32 => Value::from(read_varint(bytes)? as i32),
+        FieldType::Uint32 => Value::from(read_varint(bytes)? as u32),
+        FieldType::Sint32 => {
+            let v = read_varint(bytes)? as u32;
+            Value::from((v >> 1) as i32 ^ -((v & 1) as i32))
+        }
+        FieldType::Sint64 => {
+            let v = read_varint(bytes)?;
+            Value::from((v >> 1) as i64 ^ -((v & 1) as i64))
+        }
+        FieldType::Bool => Value::from(read_varint(bytes)? != 0),
+        FieldType::Enum => {
+            let number = read_varint(bytes)? as i32;
+            let enumeration = descriptors.enumeration(field.full_type_name())?;
+            Value::from(enum_name(field.full_type_name(), enumeration, number)?)
+        }
+        FieldType::String => Value::from(
+            std::str::from_utf8(read_len(bytes)?)
+                .context("string is not valid UTF-8")?
+                .to_string(),
+        ),
+        FieldType::Bytes => {
+            Value::Array(read_len(bytes)?.iter().map(|b| Value::from(*b)).collect())
+        }
+        FieldType::Message => {
+            let message = descriptors.message(field.full_type_name())?;
+            let value = decode(descriptors, message, read_len(bytes)?, depth + 1)?;
+            if message.is_map_entry() {
+                let mut entry = value;
+                Value::Array(vec![entry["key"].take(), entry["value"].take()])
+            } else {
+                value
+            }
+        }
+    })
+}
+
+fn default_value(descriptors: &Descriptors, field: &FieldDescriptorProto) -> anyhow::Result<Value> {
+    if field.is_repeated() {
+        return Ok(Value::Array(Vec::new()));
+    }
+    if field.proto3_optional() {
+        return Ok(Value::Null);
+    }
+    Ok(match field.field_type()? {
+        FieldType::Double | FieldType::Float => Value::from(0.0),
+        FieldType::Bool => Value::from(false),
+        FieldType::String => Value::from(""),
+        FieldType::Bytes => Value::Array(Vec::new()),
+        FieldType::Message => Value::Null,
+        FieldType::Enum => {
+            let enumeration = descriptors.enumeration(field.full_type_name())?;
+            let first = enumeration
+                .value
+                .iter()
+                .find(|v| v.number == Some(0))
+                .or(enumeration.value.first())
+                .with_context(|| format!("enum '{}' has no values", field.full_type_name()))?;
+            Value::from(enum_case(
+                enumeration.name.as_deref().unwrap_or_default(),
+                first.name.as_deref().unwrap_or_default(),
+            ))
+        }
+        _ => Value::from(0),
+    })
+}
+
+fn enum_name(
+    type_name: &str,
+    enumeration: &EnumDescriptorProto,
+    number: i32,
+) -> anyhow::Result<String> {
+    let value = enumeration
+        .value
+        .iter()
+        .find(|v| v.number == Some(number))
+        .with_context(|| format!("enum '{type_name}' has no value {number}"))?;
+    Ok(enum_case(
+        enumeration.name.as_deref().unwrap_or_default(),
+        value.name.as_deref().unwrap_or_default(),
+    ))
+}
+
+fn encode(
+    descriptors: &Descriptors,
+    message: &DescriptorProto,
+    value: &Value,
+    buf: &mut Vec<u8>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    ensure!(depth < MAX_DEPTH, "message nests too deeply");
+    let fields = match value {
+        Value::Null => return Ok(()),
+        Value::Object(fields) => fields,
+        _ => bail!("expected an object"),
+    };
+    for key in fields.keys() {
+        ensure!(
+            message.field.iter().any(|f| &field_key(f.name()) == key),
+            "message '{}' has no field '{key}'",
+            message.name.as_deref().unwrap_or_default()
+        );
+    }
+    for field in &message.field {
+        let value = match fields.get(&field_key(field.name())) {
+            None | Some(Value::Null) => continue,
+            Some(value) => value,
+        };
+        let ty = field.field_type()?;
+        let number = field.number.unwrap_or_default() as u64;
+        let encode_field = |value: &Value, buf: &mut Vec<u8>| {
+            encode_value(descriptors, field, ty, value, buf, depth)
+                .with_context(|| format!("invalid field '{}'", field.name()))
+        };
+        if !field.is_repeated() {
+            write_varint(buf, number << 3 | u64::from(wire_type_of(ty)));
+            encode_field(value, buf)?;
+            continue;
+        }
+        let values = value
+            .as_array()
+            .with_context(|| format!("field '{}' must be an array", field.name()))?;
+        if ty.is_packable() {
+            if values.is_empty() {
+                continue;
+            }
+            let mut packed = Vec::new();
+            for value in values {
+                encode_field(value, &mut packed)?;
+            }
+            write_varint(buf, number << 3 | u64::from(WIRE_LEN));
+            write_len(buf, &packed);
+        } else {
+            for value in values {
+                write_varint(buf, number << 3 | u64::from(WIRE_LEN));
+                encode_field(value, buf)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes a field's value without its key.
+fn encode_value(
+    descriptors: &Descriptors,
+    field: &FieldDescriptorProto,
+    ty: FieldType,
+    value: &Value,
+    buf: &mut Vec<u8>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    let int = || value.as_i64().context("expected an integer");
+    let uint = || value.as_u64().context("expected an unsigned integer");
+    let float = || value.as_f64().context("expected a number");
+    let int32 = || i32::try_from(int()?).context("number out of range");
+    let uint32 = || u32::try_from(uint()?).context("number out of range");
+    match ty {
+        FieldType::Double => buf.extend_from_slice(&float()?.to_le_bytes()),
+        FieldType::Float => buf.extend_from_slice(&(float()? as f32).to_le_bytes()),
+        FieldType::Fixed64 => buf.extend_from_slice(&uint()?.to_le_bytes()),
+        FieldType::Sfixed64 => buf.extend_from_slice(&int()?.to_le_bytes()),
+        FieldType::Fixed32 => buf.extend_from_slice(&uint32()?.to_le_bytes()),
+        FieldType::Sfixed32 => buf.extend_from_slice(&int32()?.to_le_bytes()),
+        FieldType::Int64 => write_varint(buf, int()? as u64),
+        FieldType::Int32 => write_varint(buf, int32()? as i64 as u64),
+        FieldType::Uint64 => write_varint(buf, uint()?),
+        FieldType::Uint32 => write_varint(buf, uint32()?.into()),
+        FieldType::Sint32 => {
+            let v = int32()?;
+            write_varint(buf, ((v << 1) ^ (v >> 31)) as u32 as u64);
+        }
+        FieldType::Sint64 => {
+            let v = int()?;
+            write_varint(buf, ((v << 1) ^ (v >> 63)) as u64);
+        }
+        FieldType::Bool => write_varint(buf, value.as_bool().context("expected a boolean")?.into()),
+        FieldType::Enum => {
+            let enumeration = descriptors.enumeration(field.full_type_name())?;
+            let case = value.as_str().context("expected an enum case")?;
+            let enum_type = enumeration.name.as_deref().unwrap_or_default();
+            let number = enumeration
+                .value
+                .iter()
+                .find(|v| enum_case(enum_type, v.name.as_deref().unwrap_or_default()) == case)
+                .and_then(|v| v.number)
+                .with_context(|| {
+                    format!("enum '{}' has no case '{case}'", field.full_type_name())
+                })?;
+            write_varint(buf, number as i64 as u64);
+        }
+        FieldType::String => {
+            write_len(buf, value.as_str().context("expected a string")?.as_bytes())
+        }
+        FieldType::Bytes => {
+            let bytes = value
+                .as_array()
+                .context("expected an array of bytes")?
+                .iter()
+                .map(|b| {
+                    b.as_u64()
+                        .and_then(|b| u8::try_from(b).ok())
+                        .context("expected a byte")
+                })
+                .collect::<anyhow::Result<Vec<u8>>>()?;
+            write_len(buf, &bytes);
+        }
+        FieldType::Message => {
+            let message = descriptors.message(field.full_type_name())?;
+            let entry;
+            let value = if message.is_map_entry() {
+                let [k, v] = value
+                    .as_array()
+                    .and_then(|pair| <&[Value; 2]>::try_from(pair.as_slice()).ok())
+                    .context("expected a [key, value] pair")?;
+                entry = serde_json::json!({ "key": k, "value": v });
+                &entry
+            } else {
+                value
+            };
+            let mut nested = Vec::new();
+            encode(descriptors, message, value, &mut nested, depth + 1)?;
+            write_len(buf, &nested);
+        }
+    }
+    Ok(())
+}
+
+/// The wire type a field of the type is encoded with, when not packed.
+fn wire_type_of(ty: FieldType) -> u8 {
+    match ty {
+        FieldType::Double | FieldType::Fixed64 | FieldType::Sfixed64 => WIRE_FIXED64,
+        FieldType::Float | FieldType::Fixed32 | FieldType::Sfixed32 => WIRE_FIXED32,
+        FieldType::String | FieldType::Bytes | FieldType::Message => WIRE_LEN,
+        _ => WIRE_VARINT,
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().context("truncated varint")?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    bail!("varint is too long")
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_fixed<const N: usize>(bytes: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    ensure!(bytes.len() >= N, "truncated value");
+    let (value, rest) = bytes.split_at(N);
+    *bytes = rest;
+    Ok(value.try_into().expect("split at N"))
+}
+
+fn read_len<'a>(bytes: &mut &'a [u8]) -> anyhow::Result<&'a [u8]> {
+    let len = usize::try_from(read_varint(bytes)?).context("length out of range")?;
+    ensure!(bytes.len() >= len, "truncated value");
+    let (value, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(value)
+}
+
+fn write_len(buf: &mut Vec<u8>, value: &[u8]) {
+    write_varint(buf, value.len() as u64);
+    buf.extend_from_slice(value);
+}
+
+fn skip(wire_type: u8, bytes: &mut &[u8]) -> anyhow::Result<()> {
+    match wire_type {
+        WIRE_VARINT => {
+            read_varint(bytes)?;
+        }
+        WIRE_FIXED64 => {
+            read_fixed::<8>(bytes)?;
+        }
+        WIRE_LEN => {
+            read_len(bytes)?;
+        }
+        WIRE_FIXED32 => {
+            read_fixed::<4>(bytes)?;
+        }
+        other => bail!("unsupported wire type {other}"),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::descriptor::{
+        DescriptorProto, EnumValueDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+        MessageOptions,
+    };
+    use super::*;
+    use prost::Message as _;
+    use serde_json::json;
+
+    fn field(name: &str, number: i32, ty: i32, type_name: Option<&str>) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(1),
+            r#type: Some(ty),
+            type_name: type_name.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    fn repeated(field: FieldDescriptorProto) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            label: Some(3),
+            ..field
+        }
+    }
+
+    fn descriptors() -> anyhow::Result<Descriptors> {
+        let set = FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                package: Some("acme.inventory".to_string()),
+                message_type: vec![DescriptorProto {
+                    name: Some("Item".to_string()),
+                    field: vec![
+                        field("sku_id", 1, 9, None),
+                        field("count", 2, 17, None),
+                        field("color", 3, 14, Some(".acme.inventory.Color")),
+                        repeated(field("sizes", 4, 13, None)),
+                        repeated(field(
+                            "labels",
+                            5,
+                            11,
+                            Some(".acme.inventory.Item.LabelsEntry"),
+                        )),
+                        field("parent", 6, 11, Some(".acme.inventory.Item")),
+                        field("data", 7, 12, None),
+                        field("weight", 8, 1, None),
+                    ],
+                    nested_type: vec![DescriptorProto {
+                        name: Some("LabelsEntry".to_string()),
+                        field: vec![field("key", 1, 9, None), field("value", 2, 9, None)],
+                        options: Some(MessageOptions {
+                            map_entry: Some(true),
+                        }),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                enum_type: vec![EnumDescriptorProto {
+                    name: Some("Color".to_string()),
+                    value: ["COLOR_UNSPECIFIED", "COLOR_DARK_RED"]
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, name)| EnumValueDescriptorProto {
+                            name: Some(name.to_string()),
+                            number: Some(i as i32),
+                        })
+                        .collect(),
+                }],
+                ..Default::default()
+            }],
+        };
+        Descriptors::decode(&set.encode_to_vec())
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(field_key("sku_id"), "sku-id");
+        assert_eq!(enum_case("Color", "COLOR_DARK_RED"), "dark-red");
+        assert_eq!(enum_case("DeliveryMode", "DELIVERY_MODE_FAST"), "fast");
+        assert_eq!(enum_case("Color", "RED"), "red");
+    }
+
+    #[test]
+    fn test_roundtrip() -> anyhow::Result<()> {
+        let descriptors = descriptors()?;
+        descriptors.check_message("acme.inventory.Item")?;
+        let item = json!({
+            "sku-id": "sku-1",
+            "count": -3,
+            "color": "dark-red",
+            "sizes": [1, 300],
+            "labels": [["size", "xl"]],
+            "parent": { "sku-id": "sku-0" },
+            "data": [0, 255],
+            "weight": 1.5,
+        });
+        let encoded = encode_message(&descriptors, "acme.inventory.Item", &item)?;
+        let decoded = decode_message(&descriptors, "acme.inventory.Item", &encoded)?;
+        assert_eq!(decoded["sku-id"], json!("sku-1"));
+        assert_eq!(decoded["count"], json!(-3));
+        assert_eq!(decoded["color"], json!("dark-red"));
+        assert_eq!(decoded["sizes"], json!([1, 300]));
+        assert_eq!(decoded["labels"], json!([["size", "xl"]]));
+        assert_eq!(decoded["data"], json!([0, 255]));
+        assert_eq!(decoded["weight"], json!(1.5));
+        assert_eq!(decoded["parent"]["sku-id"], json!("sku-0"));
+        // Unset fields of the nested message get their defaults
+        assert_eq!(decoded["parent"]["count"], json!(0));
+        assert_eq!(decoded["parent"]["color"], json!("unspecified"));
+        assert_eq!(decoded["parent"]["parent"], Value::Null);
+
+        assert!(encode_message(&descriptors, "acme.inventory.Item", &json!({"size": 1})).is_err());
+        assert!(decode_message(&descriptors, "acme.inventory.Item", &encoded[..3]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Header rules for the [HTTP server](crate::host::http), so header plumbing doesn't need
+//! a wrapper component.
+//!
+//! A workload sets the rules for its virtual host in its `wasi:http/incoming-handler`
+//! config, for requests before they reach it and for its responses:
+//!
+//! - `request-header-set.<name>` / `response-header-set.<name>` replace a header
+//! - `request-header-add.<name>` / `response-header-add.<name>` add a value to a header
+//! - `request-header-remove` / `response-header-remove` remove the comma separated
+//!   headers, where `hop-by-hop` stands for `Connection`, `Keep-Alive` and the rest of
+//!   the headers that only apply to a single connection
+//! - `request-id` names a header, or is `true` for `X-Request-Id`, that's set to a new
+//!   UUID on requests without one and copied onto the response
+//!
+//! Headers are removed first, then set, then added.
+
+use std::collections::HashMap;
+
+use anyhow::Context as _;
+use hyper::HeaderMap;
+use hyper::header::{HeaderName, HeaderValue};
+
+use crate::engine::workload::ResolvedWorkload;
+use crate::wit::WitInterface;
+
+const REQUEST_ID_KEY: &str = "request-id";
+/// The entry of a `-remove` list standing for every hop-by-hop header.
+const HOP_BY_HOP: &str = "hop-by-hop";
+
+/// The header changes for one direction of an exchange.
+#[derive(Debug, Default, Clone)]
+struct HeaderOps {
+    remove: Vec<HeaderName>,
+    remove_hop_by_hop: bool,
+    set: Vec<(HeaderName, HeaderValue)>,
+    add: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderOps {
+    fn from_config(config: &HashMap<String, String>, direction: &str) -> anyhow::Result<Self> {
+        let mut ops = Self::default();
+        if let Some(remove) = config.get(&format!("{direction}-header-remove")) {
+            for name in remove.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                if name.eq_ignore_ascii_case(HOP_BY_HOP) {
+                    ops.remove_hop_by_hop = true;
+                } else {
+                    ops.remove.push(header_name(name)?);
+                }
+            }
+        }
+        // Sorted so the rules apply in the same order on every request
+        let mut entries: Vec<_> = config.iter().collect();
+        entries.sort();
+        for (key, value) in entries {
+            let header = |name: &str| -> anyhow::Result<(HeaderName, HeaderValue)> {
+                let value = HeaderValue::from_str(value)
+                    .with_context(|| format!("invalid value for header rule '{key}'"))?;
+                Ok((header_name(name)?, value))
+            };
+            if let Some(name) = key.strip_prefix(&format!("{direction}-header-set.")) {
+                ops.set.push(header(name)?);
+            } else if let Some(name) = key.strip_prefix(&format!("{direction}-header-add.")) {
+                ops.add.push(header(name)?);
+            }
+        }
+        Ok(ops)
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        if self.remove_hop_by_hop {
+            crate::host::upstream::strip_hop_by_hop(headers);
+        }
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.set {
+            headers.insert(name.clone(), value.clone());
+        }
+        for (name, value) in &self.add {
+            headers.append(name.clone(), value.clone());
+        }
+    }
+}
+
+/// The header rules of a virtual host.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct HeaderRules {
+    request: HeaderOps,
+    response: HeaderOps,
+    request_id: Option<HeaderName>,
+}
+
+impl HeaderRules {
+    /// Reads the header rules from a workload's `wasi:http/incoming-handler` config.
+    ///
+    /// # Errors
+    /// Returns an error if a rule has an invalid header name or value.
+    pub(crate) fn from_workload(workload: &ResolvedWorkload) -> anyhow::Result<Self> {
+        let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+        match workload
+            .host_interfaces()
+            .iter()
+            .find(|iface| iface.contains(&incoming_handler_interface))
+        {
+            Some(iface) => Self::from_config(&iface.config),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let request_id = match config.get(REQUEST_ID_KEY).map(String::as_str) {
+            None | Some("false") => None,
+            Some("true") => Some(HeaderName::from_static("x-request-id")),
+            Some(name) => Some(header_name(name)?),
+        };
+        Ok(Self {
+            request: HeaderOps::from_config(config, "request")?,
+            response: HeaderOps::from_config(config, "response")?,
+            request_id,
+        })
+    }
+
+    /// Applies the rules to a request.
+    ///
+    /// # Returns
+    /// The request ID to copy onto the response, if the rules set one.
+    pub(crate) fn apply_request(&self, headers: &mut HeaderMap) -> Option<HeaderValue> {
+        self.request.apply(headers);
+        let name = self.request_id.as_ref()?;
+        let id = headers
+            .entry(name)
+            .or_insert_with(|| {
+                HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+                    .expect("UUIDs are valid header values")
+            })
+            .clone();
+        Some(id)
+    }
+
+    /// Applies the rules to a response, along with the request ID of its request.
+    pub(crate) fn apply_response(&self, headers: &mut HeaderMap, request_id: Option<HeaderValue>) {
+        self.response.apply(headers);
+        if let (Some(name), Some(id)) = (&self.request_id, request_id) {
+            headers.insert(name.clone(), id);
+        }
+    }
+}
+
+fn header_name(name: &str) -> anyhow::Result<HeaderName> {
+    HeaderName::from_bytes(name.as_bytes()).with_context(|| format!("invalid header name '{name}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_request_rules() -> anyhow::Result<()> {
+        let rules = HeaderRules::from_config(&config(&[
+            ("host", "app.localhost"),
+            ("request-header-remove", "hop-by-hop, x-internal"),
+            ("request-header-set.x-forwarded-proto", "https"),
+            ("request-header-add.x-tag", "gateway"),
+            ("request-id", "true"),
+        ]))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert("connection", HeaderValue::from_static("close"));
+        headers.insert("x-internal", HeaderValue::from_static("1"));
+        headers.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+        headers.insert("x-tag", HeaderValue::from_static("client"));
+        let id = rules
+            .apply_request(&mut headers)
+            .context("expected a request ID")?;
+
+        assert!(!headers.contains_key("connection"));
+        assert!(!headers.contains_key("x-internal"));
+        assert_eq!(headers["x-forwarded-proto"], "https");
+        assert_eq!(headers.get_all("x-tag").iter().count(), 2);
+        assert_eq!(headers["x-request-id"], id);
+
+        // An existing request ID is kept
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("abc"));
+        assert_eq!(
+            rules.apply_request(&mut headers),
+            Some(HeaderValue::from_static("abc"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_response_rules() -> anyhow::Result<()> {
+        let rules = HeaderRules::from_config(&config(&[
+            ("response-header-remove", "server"),
+            ("response-header-set.cache-control", "no-store"),
+            ("request-id", "x-correlation-id"),
+        ]))?;
+        let mut headers = HeaderMap::new();
+        headers.insert("server", HeaderValue::from_static("component"));
+        rules.apply_response(&mut headers, Some(HeaderValue::from_static("abc")));
+        assert!(!headers.contains_key("server"));
+        assert_eq!(headers["cache-control"], "no-store");
+        assert_eq!(headers["x-correlation-id"], "abc");
+
+        assert!(
+            HeaderRules::from_config(&config(&[("host", "app")]))?
+                .apply_request(&mut headers)
+                .is_none()
+        );
+        assert!(
+            HeaderRules::from_config(&config(&[("request-header-set.bad name", "1")])).is_err()
+        );
+        Ok(())
+    }
+}
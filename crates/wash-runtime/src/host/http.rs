@@ -5,8 +5,30 @@
 //! server implementation with support for:
 //!
 //! - Virtual hosting based on Host headers
-//! - TLS/HTTPS connections
+//! - TLS/HTTPS connections, speaking HTTP/2 with clients that negotiate it via ALPN
+//! - Certificates and routing by the server name of TLS connections, see
+//!   [`crate::host::sni`]
+//! - Certificates reloaded when their files change, see [`crate::host::cert_reload`]
+//! - Certificates ordered from ACME certificate authorities, behind the `acme` feature
 //! - Component isolation per request
+//! - Static files served next to workloads, see [`crate::host::assets`]
+//! - Reverse proxying to upstream servers, see [`crate::host::upstream`]
+//! - Chains of filter workloads in front of a virtual host, see [`crate::host::filters`]
+//! - JWT validation, see [`crate::host::jwt`]
+//! - API key authentication, see [`crate::host::api_keys`]
+//! - Request and response header rules, see [`crate::host::headers`]
+//! - Weighted traffic splits between workloads, see [`crate::host::split`]
+//! - Mirroring requests to shadow workloads, see [`crate::host::mirror`]
+//! - HTTP/3 over QUIC next to HTTPS, behind the `http3` feature
+//! - Limits on connections and their requests, see [`crate::host::connections`]
+//! - Client addresses from a load balancer's PROXY protocol header, see
+//!   [`crate::host::proxy_protocol`]
+//! - Forwarding headers only believed from trusted proxies, see [`crate::host::forwarded`]
+//! - Source address allow and deny lists per listener and virtual host, see
+//!   [`crate::host::ip_filter`]
+//! - Request IDs to correlate logs, workloads and backends, see [`crate::host::request_id`]
+//! - W3C trace context passed through components, see [`crate::host::trace_context`]
+//! - Selected W3C baggage entries passed through components, see [`crate::host::baggage`]
 //! - Graceful shutdown capabilities
 //!
 //! # Architecture
@@ -16,29 +38,77 @@
 //! 2. Routing requests to components based on the Host header
 //! 3. Creating isolated component instances for each request
 //! 4. Managing the request/response lifecycle through WASI-HTTP
+//!
+//! Outgoing requests addressed to `service-name.namespace`, or to the virtual host of
+//! a workload on this host, are handled by invoking that workload in-process rather
+//! than going through DNS and the TCP listener. They pass the same IP filter, API key,
+//! JWT, header rule, mirroring and filter chain steps as requests arriving at the
+//! listener, from a loopback address.
+//!
+//! # Streaming and trailers
+//!
+//! A component's response is sent as soon as it calls `response-outparam.set`, and its
+//! body is written to the client as the component writes it, so server-sent events and
+//! other long-lived responses stream. Trailers the component sets when finishing the
+//! body are sent after it. Over HTTP/2 that always works; over HTTP/1.1 the response has
+//! to be chunked, i.e. without a `content-length`, and declare the trailer names in its
+//! `trailer` header. Trailers on incoming requests are passed to the component either way.
 //! ```
 
-use std::{collections::HashMap, net::SocketAddr, path::Path, sync::Arc};
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, SocketAddr},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
 
 use crate::engine::ctx::Ctx;
 use crate::engine::workload::ResolvedWorkload;
+use crate::host::api_keys::ApiKeyStore;
+use crate::host::assets::StaticMount;
+use crate::host::baggage::{Baggage, BaggageConfig};
+use crate::host::cert_reload::{CertificateWatcher, ReloadableCertificate};
+use crate::host::connections::{ConnectionLimits, ConnectionTracker};
+use crate::host::dns::Resolver;
+use crate::host::filters::FilterConfig;
+use crate::host::forwarded::{ForwardedConfig, ForwardedPolicy};
+use crate::host::grpc::TokioExecutor;
+use crate::host::headers::HeaderRules;
+use crate::host::ip_filter::{ClientAddr, IpFilter};
+use crate::host::jwt::JwtConfig;
+use crate::host::proxy::EgressProxy;
+use crate::host::request_id::{RequestId, RequestIdConfig};
+use crate::host::sni::{SniCertificates, route_by_server_name};
+use crate::host::split::TrafficSplit;
+use crate::host::trace_context::TraceContext;
+use crate::host::upstream::Upstream;
 use crate::wit::WitInterface;
 use anyhow::{Context, ensure};
-use hyper::server::conn::http1;
-use tokio::net::TcpListener;
-use tracing::{debug, error, info, warn};
-use wasmtime::component::InstancePre;
-use wasmtime::{AsContextMut, StoreContextMut};
+use http_body_util::BodyExt;
+use hyper::header::HeaderValue;
+use hyper::server::conn::{http1, http2};
+use tokio::net::{TcpListener, TcpSocket};
+use tracing::{Instrument as _, debug, error, info, warn};
+use wasmtime::Store;
+use wasmtime::component::{Instance, InstancePre};
 use wasmtime_wasi_http::{
     WasiHttpView,
-    bindings::{ProxyPre, http::types::Scheme},
-    body::HyperOutgoingBody,
+    bindings::{
+        Proxy, ProxyPre,
+        http::types::{ErrorCode, Scheme},
+    },
+    body::{HostIncomingBody, HyperIncomingBody, HyperOutgoingBody},
     io::TokioIo,
+    types::{HostIncomingRequest, IncomingResponse, OutgoingRequestConfig},
 };
 
-use rustls::{ServerConfig, pki_types::CertificateDer};
+use rustls::{
+    ServerConfig,
+    pki_types::{CertificateDer, PrivateKeyDer},
+};
 use rustls_pemfile::{certs, private_key};
-use tokio::sync::{RwLock, mpsc};
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore, mpsc};
 use tokio_rustls::TlsAcceptor;
 
 /// Trait defining the routing behavior for HTTP requests
@@ -88,18 +158,16 @@ impl Router for DynamicRouter {
         _component_id: &str,
     ) -> anyhow::Result<()> {
         let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
-        let Some(http_iface) = resolved_handle
+        if !resolved_handle
             .host_interfaces()
             .iter()
-            .find(|iface| iface.contains(&incoming_handler_interface))
-        else {
+            .any(|iface| iface.contains(&incoming_handler_interface))
+        {
             anyhow::bail!("workload did not request wasi:http/incoming-handler interface");
-        };
+        }
 
-        let host_header = http_iface
-            .config
-            .get("host")
-            .cloned()
+        let host_header = virtual_host(resolved_handle)
+            .map(str::to_string)
             .context("No host header found")?;
 
         let mut lock = self.host_to_workload.write().await;
@@ -217,6 +285,19 @@ pub trait HostHandler: Send + Sync + 'static {
         request: hyper::Request<wasmtime_wasi_http::body::HyperOutgoingBody>,
         config: wasmtime_wasi_http::types::OutgoingRequestConfig,
     ) -> wasmtime_wasi_http::HttpResult<wasmtime_wasi_http::types::HostFutureIncomingResponse>;
+
+    /// Splits the traffic of a virtual host between workloads by weight, or removes its
+    /// split when `weights` is empty, see [`crate::host::split`].
+    ///
+    /// # Returns
+    /// The weights of the split that was replaced.
+    async fn set_traffic_split(
+        &self,
+        _host: &str,
+        _weights: Vec<(String, u32)>,
+    ) -> anyhow::Result<Vec<(String, u32)>> {
+        anyhow::bail!("the HTTP handler doesn't support traffic splits")
+    }
 }
 
 impl std::fmt::Debug for dyn HostHandler {
@@ -266,6 +347,13 @@ impl HostHandler for NullServer {
 pub type WorkloadHandles =
     Arc<RwLock<HashMap<String, (ResolvedWorkload, InstancePre<Ctx>, String)>>>;
 
+/// A map from virtual host to the split of its traffic between workloads
+type TrafficSplits = Arc<RwLock<HashMap<String, Arc<TrafficSplit>>>>;
+
+/// How long a guest reading a request body waits for the next chunk, as in
+/// [`WasiHttpView::new_incoming_request`].
+const INCOMING_BETWEEN_BYTES_TIMEOUT: Duration = Duration::from_secs(600);
+
 /// HTTP server plugin that handles incoming HTTP requests for WebAssembly components.
 ///
 /// This plugin implements the `wasi:http/incoming-handler` interface and routes
@@ -275,8 +363,42 @@ pub struct HttpServer<T: Router> {
     router: Arc<T>,
     addr: SocketAddr,
     workload_handles: WorkloadHandles,
+    traffic_splits: TrafficSplits,
     shutdown_tx: Arc<RwLock<Option<mpsc::Sender<()>>>>,
     tls_acceptor: Option<TlsAcceptor>,
+    tls_config: Option<Arc<ServerConfig>>,
+    sni_routing: bool,
+    /// The certificates loaded from files, to reload when they change
+    reloadable_certificates: Vec<Arc<ReloadableCertificate>>,
+    certificate_reload: bool,
+    certificate_watcher: std::sync::Mutex<Option<CertificateWatcher>>,
+    egress_proxy: Option<Arc<EgressProxy>>,
+    resolver: Arc<Resolver>,
+    static_mounts: Arc<[StaticMount]>,
+    upstreams: Arc<[Upstream]>,
+    api_keys: Option<Arc<ApiKeyStore>>,
+    connection_limits: ConnectionLimits,
+    proxy_protocol: bool,
+    forwarded: ForwardedConfig,
+    ip_filter: Arc<IpFilter>,
+    request_ids: Arc<RequestIdConfig>,
+    baggage: Arc<BaggageConfig>,
+    #[cfg(feature = "mdns")]
+    mdns: Option<crate::host::mdns::MdnsAdvertiser>,
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    io_uring: bool,
+    #[cfg(feature = "acme")]
+    acme: Option<Arc<crate::host::acme::Acme>>,
+    /// Whether this server orders the certificates of `acme` rather than only answering
+    /// its challenges
+    #[cfg(feature = "acme")]
+    acme_provisioning: bool,
+    #[cfg(feature = "acme")]
+    acme_task: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
+    #[cfg(feature = "http3")]
+    http3: Option<quinn::ServerConfig>,
+    #[cfg(feature = "http3")]
+    http3_endpoint: std::sync::Mutex<Option<quinn::Endpoint>>,
 }
 
 impl<T: Router> std::fmt::Debug for HttpServer<T> {
@@ -301,8 +423,39 @@ impl<T: Router> HttpServer<T> {
             router: Arc::new(router),
             addr,
             workload_handles: Arc::default(),
+            traffic_splits: Arc::default(),
             shutdown_tx: Arc::new(RwLock::new(None)),
             tls_acceptor: None,
+            tls_config: None,
+            sni_routing: false,
+            reloadable_certificates: Vec::new(),
+            certificate_reload: false,
+            certificate_watcher: std::sync::Mutex::default(),
+            egress_proxy: None,
+            resolver: Arc::default(),
+            static_mounts: Arc::new([]),
+            upstreams: Arc::new([]),
+            api_keys: None,
+            connection_limits: ConnectionLimits::default(),
+            proxy_protocol: false,
+            forwarded: ForwardedConfig::default(),
+            ip_filter: Arc::default(),
+            request_ids: Arc::default(),
+            baggage: Arc::default(),
+            #[cfg(feature = "mdns")]
+            mdns: None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            #[cfg(feature = "acme")]
+            acme: None,
+            #[cfg(feature = "acme")]
+            acme_provisioning: false,
+            #[cfg(feature = "acme")]
+            acme_task: std::sync::Mutex::default(),
+            #[cfg(feature = "http3")]
+            http3: None,
+            #[cfg(feature = "http3")]
+            http3_endpoint: std::sync::Mutex::default(),
         }
     }
 
@@ -327,17 +480,318 @@ impl<T: Router> HttpServer<T> {
         key_path: &Path,
         ca_path: Option<&Path>,
     ) -> anyhow::Result<Self> {
-        let tls_config = load_tls_config(cert_path, key_path, ca_path).await?;
-        let tls_acceptor = TlsAcceptor::from(Arc::new(tls_config));
+        let certificate = Arc::new(ReloadableCertificate::load(cert_path, key_path).await?);
+        let tls_config = Arc::new(load_tls_config(certificate.clone(), ca_path).await?);
+        let tls_acceptor = TlsAcceptor::from(tls_config.clone());
 
         Ok(Self {
             router: Arc::new(router),
             addr,
             workload_handles: Arc::default(),
+            traffic_splits: Arc::default(),
             shutdown_tx: Arc::new(RwLock::new(None)),
             tls_acceptor: Some(tls_acceptor),
+            tls_config: Some(tls_config),
+            sni_routing: false,
+            reloadable_certificates: vec![certificate],
+            certificate_reload: false,
+            certificate_watcher: std::sync::Mutex::default(),
+            egress_proxy: None,
+            resolver: Arc::default(),
+            static_mounts: Arc::new([]),
+            upstreams: Arc::new([]),
+            api_keys: None,
+            connection_limits: ConnectionLimits::default(),
+            proxy_protocol: false,
+            forwarded: ForwardedConfig::default(),
+            ip_filter: Arc::default(),
+            request_ids: Arc::default(),
+            baggage: Arc::default(),
+            #[cfg(feature = "mdns")]
+            mdns: None,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            io_uring: false,
+            #[cfg(feature = "acme")]
+            acme: None,
+            #[cfg(feature = "acme")]
+            acme_provisioning: false,
+            #[cfg(feature = "acme")]
+            acme_task: std::sync::Mutex::default(),
+            #[cfg(feature = "http3")]
+            http3: None,
+            #[cfg(feature = "http3")]
+            http3_endpoint: std::sync::Mutex::default(),
         })
     }
+
+    /// Sends outgoing HTTP requests from every workload through `proxy`. A workload can
+    /// override it with its own proxy, see [`crate::host::proxy`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_egress_proxy(mut self, proxy: EgressProxy) -> Self {
+        self.egress_proxy = Some(Arc::new(proxy));
+        self
+    }
+
+    /// Resolves the host names of workloads' outgoing requests with `resolver`, see
+    /// [`crate::host::dns`]. By default a resolver with the system's configuration is used.
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_resolver(mut self, resolver: Arc<Resolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Serves the files of a [`StaticMount`] ahead of the workloads on its virtual host.
+    /// Mounts are tried in the order they're added.
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_static_mount(mut self, mount: StaticMount) -> Self {
+        self.static_mounts = self.static_mounts.iter().cloned().chain([mount]).collect();
+        self
+    }
+
+    /// Proxies requests to an [`Upstream`]. An upstream for a path prefix takes its
+    /// requests ahead of the workloads on its virtual host, and one for the whole host
+    /// takes the requests no workload serves.
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_upstream(mut self, upstream: Upstream) -> Self {
+        self.upstreams = self.upstreams.iter().cloned().chain([upstream]).collect();
+        self
+    }
+
+    /// Accepts the keys in `store` on virtual hosts whose workloads require API keys,
+    /// see [`crate::host::api_keys`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_api_keys(mut self, store: Arc<ApiKeyStore>) -> Self {
+        self.api_keys = Some(store);
+        self
+    }
+
+    /// Limits the server's connections, see [`crate::host::connections`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_connection_limits(mut self, limits: ConnectionLimits) -> Self {
+        self.connection_limits = limits;
+        self
+    }
+
+    /// Requires every connection to start with a PROXY protocol v2 header, and passes the
+    /// client address it names to workloads, see [`crate::host::proxy_protocol`]. Only
+    /// enable it behind a load balancer that sends the header, as other connections are
+    /// closed. HTTP/3 connections don't carry the header and keep their own address.
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_proxy_protocol(mut self) -> Self {
+        self.proxy_protocol = true;
+        self
+    }
+
+    /// Sets what workloads see in the `forwarded` and `x-forwarded-for` headers of
+    /// requests, and which proxies' headers are believed, see [`crate::host::forwarded`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_forwarded(mut self, config: ForwardedConfig) -> Self {
+        self.forwarded = config;
+        self
+    }
+
+    /// Closes connections from addresses `filter` rejects before reading their requests,
+    /// see [`crate::host::ip_filter`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_ip_filter(mut self, filter: IpFilter) -> Self {
+        self.ip_filter = Arc::new(filter);
+        self
+    }
+
+    /// Sets the header request IDs are carried in and whether incoming IDs are honored,
+    /// see [`crate::host::request_id`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_request_ids(mut self, config: RequestIdConfig) -> Self {
+        self.request_ids = Arc::new(config);
+        self
+    }
+
+    /// Selects the W3C baggage entries passed from requests to workloads and on to the
+    /// requests and messages they send, see [`crate::host::baggage`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_baggage(mut self, config: BaggageConfig) -> Self {
+        self.baggage = Arc::new(config);
+        self
+    }
+
+    /// Advertises the virtual host of each workload served by this server over
+    /// mDNS/DNS-SD, so it can be discovered on the local network.
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    ///
+    /// # Errors
+    /// Returns an error if the mDNS responder can't be started.
+    #[cfg(feature = "mdns")]
+    pub fn with_mdns_advertisement(mut self) -> anyhow::Result<Self> {
+        self.mdns = Some(crate::host::mdns::MdnsAdvertiser::new()?);
+        Ok(self)
+    }
+
+    /// Accepts connections with io_uring rather than epoll, see [`crate::host::uring`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    pub fn with_io_uring(mut self) -> Self {
+        self.io_uring = true;
+        self
+    }
+
+    /// Serves the certificates in `certificates` to clients naming their hosts in the TLS
+    /// handshake, and the server's own certificate to others, see [`crate::host::sni`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    ///
+    /// # Errors
+    /// Returns an error if the server wasn't created with TLS.
+    pub fn with_sni_certificates(mut self, certificates: SniCertificates) -> anyhow::Result<Self> {
+        let tls_config = self
+            .tls_config
+            .as_ref()
+            .context("SNI certificates require the server to be created with TLS")?;
+        let mut tls_config = (**tls_config).clone();
+        self.reloadable_certificates
+            .extend(certificates.reloadable().cloned());
+        tls_config.cert_resolver =
+            Arc::new(certificates.with_fallback(tls_config.cert_resolver.clone()));
+        let tls_config = Arc::new(tls_config);
+        self.tls_acceptor = Some(TlsAcceptor::from(tls_config.clone()));
+        #[cfg(feature = "http3")]
+        if self.http3.is_some() {
+            self.http3 = Some(crate::host::http3::server_config(&tls_config)?);
+        }
+        self.tls_config = Some(tls_config);
+        Ok(self)
+    }
+
+    /// Reloads the server's certificates when their files change while it runs, see
+    /// [`crate::host::cert_reload`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    pub fn with_certificate_reload(mut self) -> Self {
+        self.certificate_reload = true;
+        self
+    }
+
+    /// Routes requests by the server name of their TLS connection rather than by their
+    /// `Host` header, see [`crate::host::sni`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    ///
+    /// # Errors
+    /// Returns an error if the server wasn't created with TLS.
+    pub fn with_sni_routing(mut self) -> anyhow::Result<Self> {
+        ensure!(
+            self.tls_config.is_some(),
+            "SNI routing requires the server to be created with TLS"
+        );
+        self.sni_routing = true;
+        Ok(self)
+    }
+
+    /// Serves HTTPS with the certificates `acme` orders for its domains, and the server's
+    /// own certificate, if created with TLS, to clients naming other hosts. The
+    /// certificates are ordered and renewed while the server runs, see
+    /// [`crate::host::acme`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    ///
+    /// # Errors
+    /// Returns an error if the server's HTTP/3 configuration can't be updated.
+    #[cfg(feature = "acme")]
+    pub fn with_acme(mut self, acme: Arc<crate::host::acme::Acme>) -> anyhow::Result<Self> {
+        let resolver = Arc::new(crate::host::acme::AcmeResolver {
+            acme: acme.clone(),
+            fallback: self
+                .tls_config
+                .as_ref()
+                .map(|tls_config| tls_config.cert_resolver.clone()),
+        });
+        let mut tls_config = match &self.tls_config {
+            Some(tls_config) => {
+                let mut tls_config = (**tls_config).clone();
+                tls_config.cert_resolver = resolver;
+                tls_config
+            }
+            None => {
+                let mut tls_config = ServerConfig::builder()
+                    .with_no_client_auth()
+                    .with_cert_resolver(resolver);
+                tls_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+                tls_config
+            }
+        };
+        tls_config
+            .alpn_protocols
+            .push(crate::host::acme::ACME_TLS_ALPN.to_vec());
+        let tls_config = Arc::new(tls_config);
+        self.tls_acceptor = Some(TlsAcceptor::from(tls_config.clone()));
+        #[cfg(feature = "http3")]
+        if self.http3.is_some() {
+            self.http3 = Some(crate::host::http3::server_config(&tls_config)?);
+        }
+        self.tls_config = Some(tls_config);
+        self.acme = Some(acme);
+        self.acme_provisioning = true;
+        Ok(self)
+    }
+
+    /// Answers the HTTP-01 challenges of `acme`, which another server orders certificates
+    /// with, e.g. on a plain HTTP server listening on port 80, see [`crate::host::acme`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    #[cfg(feature = "acme")]
+    pub fn with_acme_challenges(mut self, acme: Arc<crate::host::acme::Acme>) -> Self {
+        self.acme = Some(acme);
+        self
+    }
+
+    /// Also accepts HTTP/3 over QUIC on the UDP port of the server's address, and
+    /// advertises it to clients with an `alt-svc` header, see [`crate::host::http3`].
+    ///
+    /// # Returns
+    /// The server instance for method chaining.
+    ///
+    /// # Errors
+    /// Returns an error if the server wasn't created with TLS, which QUIC requires, or its
+    /// TLS configuration can't be used for QUIC.
+    #[cfg(feature = "http3")]
+    pub fn with_http3(mut self) -> anyhow::Result<Self> {
+        let tls_config = self
+            .tls_config
+            .as_ref()
+            .context("HTTP/3 requires the server to be created with TLS")?;
+        self.http3 = Some(crate::host::http3::server_config(tls_config)?);
+        Ok(self)
+    }
 }
 
 #[async_trait::async_trait]
@@ -347,23 +801,115 @@ impl<T: Router> HostHandler for HttpServer<T> {
         let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
         let shutdown_tx_clone = self.shutdown_tx.clone();
         let workload_handles = self.workload_handles.clone();
+        let traffic_splits = self.traffic_splits.clone();
         let tls_acceptor = self.tls_acceptor.clone();
+        let static_mounts = self.static_mounts.clone();
+        let upstreams = self.upstreams.clone();
+        let api_keys = self.api_keys.clone();
 
         // Store the shutdown sender
         *shutdown_tx_clone.write().await = Some(shutdown_tx);
 
-        let listener = TcpListener::bind(addr).await?;
+        let limits = self.connection_limits;
+        let proxy_protocol = self.proxy_protocol;
+        let mut forwarded = self.forwarded.clone();
+        if proxy_protocol && forwarded.policy == ForwardedPolicy::Preserve {
+            // The load balancer's address for the client replaces whatever the client claims
+            forwarded.policy = ForwardedPolicy::Overwrite;
+        }
+        let forwarded = Arc::new(forwarded);
+        let ip_filter = self.ip_filter.clone();
+        let sni_routing = self.sni_routing;
+        let listener = bind_tcp(addr, limits.accept_backlog)?;
+        if self.certificate_reload && !self.reloadable_certificates.is_empty() {
+            let watcher = CertificateWatcher::new(self.reloadable_certificates.clone())?;
+            *self
+                .certificate_watcher
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(watcher);
+        }
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        let listener = if self.io_uring {
+            Listener::Uring(crate::host::uring::UringListener::new(
+                listener.into_std()?,
+            )?)
+        } else {
+            Listener::Tokio(listener)
+        };
+        #[cfg(not(all(feature = "io-uring", target_os = "linux")))]
+        let listener = Listener::Tokio(listener);
         debug!(addr = ?addr, "HTTP server listening");
+        #[cfg(feature = "http3")]
+        let http3 = self
+            .http3
+            .as_ref()
+            .map(|config| quinn::Endpoint::server(config.clone(), addr))
+            .transpose()?;
+        #[cfg(feature = "http3")]
+        let alt_svc = match &http3 {
+            Some(endpoint) => Some(crate::host::http3::alt_svc(endpoint.local_addr()?.port())),
+            None => None,
+        };
+        #[cfg(not(feature = "http3"))]
+        let alt_svc = None;
         // Start the HTTP server, any incoming requests call Host::handle and then it's routed
         // to the workload based on host header.
-        let handler = self.router.clone();
+        let service = request_service(
+            self.router.clone(),
+            workload_handles,
+            traffic_splits,
+            static_mounts,
+            upstreams,
+            api_keys,
+            alt_svc,
+        );
+        let service = crate::host::request_id::service(
+            service,
+            self.request_ids.clone(),
+            self.baggage.clone(),
+        );
+        #[cfg(feature = "acme")]
+        let service = crate::host::acme::service(service, self.acme.clone());
+        #[cfg(feature = "acme")]
+        if self.acme_provisioning
+            && let Some(acme) = &self.acme
+        {
+            let task = tokio::spawn(acme.clone().run());
+            if let Some(previous) = self
+                .acme_task
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+                .replace(task)
+            {
+                previous.abort();
+            }
+        }
+        #[cfg(feature = "http3")]
+        if let Some(endpoint) = http3 {
+            debug!(addr = ?addr, "HTTP/3 endpoint listening");
+            *self
+                .http3_endpoint
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner) = Some(endpoint.clone());
+            tokio::spawn(crate::host::http3::serve(
+                endpoint,
+                service.clone(),
+                forwarded.clone(),
+                self.ip_filter.clone(),
+                sni_routing,
+            ));
+        }
         tokio::spawn(async move {
             if let Err(e) = run_http_server(
                 listener,
-                handler,
-                workload_handles,
+                service,
                 &mut shutdown_rx,
                 tls_acceptor,
+                limits,
+                proxy_protocol,
+                forwarded,
+                ip_filter,
+                sni_routing,
             )
             .await
             {
@@ -386,6 +932,33 @@ impl<T: Router> HostHandler for HttpServer<T> {
         if let Some(tx) = shutdown_guard.take() {
             let _ = tx.send(()).await;
         }
+        #[cfg(feature = "mdns")]
+        if let Some(mdns) = &self.mdns {
+            mdns.shutdown().await;
+        }
+        // Dropping the watcher stops reloading certificates
+        self.certificate_watcher
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take();
+        #[cfg(feature = "acme")]
+        if let Some(task) = self
+            .acme_task
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take()
+        {
+            task.abort();
+        }
+        #[cfg(feature = "http3")]
+        if let Some(endpoint) = self
+            .http3_endpoint
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take()
+        {
+            endpoint.close(0u32.into(), b"server stopping");
+        }
         Ok(())
     }
 
@@ -394,9 +967,27 @@ impl<T: Router> HostHandler for HttpServer<T> {
         resolved_handle: &ResolvedWorkload,
         component_id: &str,
     ) -> anyhow::Result<()> {
-        self.router
-            .on_workload_resolved(resolved_handle, component_id)
-            .await?;
+        // Invalid header rules and auth config fail the workload rather than its requests
+        HeaderRules::from_workload(resolved_handle).context("invalid HTTP header rules")?;
+        JwtConfig::from_workload(resolved_handle).context("invalid JWT config")?;
+        crate::host::api_keys::required(resolved_handle).context("invalid API key config")?;
+        IpFilter::from_workload(resolved_handle).context("invalid IP filter")?;
+        // Filters and shadows are only reached through the virtual hosts they're for
+        if FilterConfig::from_workload(resolved_handle)?.is_some() {
+            debug!(
+                workload_id = resolved_handle.id(),
+                "registering HTTP filter"
+            );
+        } else if crate::host::mirror::mirrored_hosts(resolved_handle).is_some() {
+            debug!(
+                workload_id = resolved_handle.id(),
+                "registering HTTP shadow workload"
+            );
+        } else {
+            self.router
+                .on_workload_resolved(resolved_handle, component_id)
+                .await?;
+        }
         let instance_pre = resolved_handle.instantiate_pre(component_id).await?;
 
         self.workload_handles.write().await.insert(
@@ -408,12 +999,25 @@ impl<T: Router> HostHandler for HttpServer<T> {
             ),
         );
 
+        #[cfg(feature = "mdns")]
+        if let Some(mdns) = &self.mdns
+            && let Some(host) = virtual_host(resolved_handle)
+            && let Err(e) = mdns.advertise(resolved_handle.id(), host, self.addr).await
+        {
+            warn!(err = ?e, workload_id = resolved_handle.id(), "failed to advertise virtual host");
+        }
+
         Ok(())
     }
 
     async fn on_workload_unbind(&self, workload_id: &str) -> anyhow::Result<()> {
         self.router.on_workload_unbind(workload_id).await?;
 
+        #[cfg(feature = "mdns")]
+        if let Some(mdns) = &self.mdns {
+            mdns.withdraw(workload_id).await;
+        }
+
         self.workload_handles.write().await.remove(workload_id);
 
         Ok(())
@@ -433,20 +1037,341 @@ impl<T: Router> HostHandler for HttpServer<T> {
 
         // NOTE(lxf): Bring wasi-http code if needed
         // Separate HTTP / GRPC handling
-        Ok(wasmtime_wasi_http::types::default_send_request(
-            request, config,
-        ))
+        let workload_handles = self.workload_handles.clone();
+        let sender = workload_id.to_string();
+        let egress_proxy = request
+            .extensions()
+            .get::<Arc<EgressProxy>>()
+            .or(self.egress_proxy.as_ref())
+            .cloned();
+        let resolver = self.resolver.clone();
+        let traffic_splits = self.traffic_splits.clone();
+        let api_keys = self.api_keys.clone();
+        let handle = wasmtime_wasi::runtime::spawn(async move {
+            // Requests to a workload on this host are invoked directly instead of
+            // looping back through the listener
+            if let Some(authority) = request.uri().authority().map(|a| a.to_string())
+                && let Some(workload) = local_workload(&workload_handles, &authority).await
+            {
+                debug!(
+                    authority = %authority,
+                    workload_id = workload.0.id(),
+                    "routing outgoing request to workload in-process"
+                );
+                return Ok(invoke_local_workload(
+                    &workload_handles,
+                    &traffic_splits,
+                    api_keys.as_deref(),
+                    workload,
+                    &sender,
+                    request,
+                    config,
+                )
+                .await);
+            }
+            Ok(crate::host::proxy::send_request(egress_proxy, &resolver, request, config).await)
+        });
+        Ok(wasmtime_wasi_http::types::HostFutureIncomingResponse::pending(handle))
+    }
+
+    async fn set_traffic_split(
+        &self,
+        host: &str,
+        weights: Vec<(String, u32)>,
+    ) -> anyhow::Result<Vec<(String, u32)>> {
+        let mut splits = self.traffic_splits.write().await;
+        let split = if weights.is_empty() {
+            info!(host, "removing HTTP traffic split");
+            None
+        } else {
+            let handles = self.workload_handles.read().await;
+            for (workload_id, _) in &weights {
+                let (handle, _, _) = handles
+                    .get(workload_id)
+                    .with_context(|| format!("workload '{workload_id}' isn't serving HTTP"))?;
+                ensure!(
+                    virtual_host(handle) == Some(host),
+                    "workload '{workload_id}' doesn't serve virtual host '{host}'"
+                );
+            }
+            info!(host, weights = ?weights, "splitting HTTP traffic");
+            Some(Arc::new(TrafficSplit::new(weights)?))
+        };
+        let previous = match split {
+            Some(split) => splits.insert(host.to_string(), split),
+            None => splits.remove(host),
+        };
+        Ok(previous
+            .map(|split| split.weights().to_vec())
+            .unwrap_or_default())
     }
 }
 
-/// HTTP server implementation that routes to workload components
-async fn run_http_server<T: Router>(
-    listener: TcpListener,
+impl<T: Router> HttpServer<T> {
+    /// Resolves a `service-name.namespace` authority to the virtual host of a workload
+    /// publishing that service on this host.
+    ///
+    /// # Returns
+    /// The virtual host, or `None` if no running workload serving HTTP publishes the service.
+    pub async fn resolve_service(&self, authority: &str) -> Option<String> {
+        resolve_service_host(&self.workload_handles, authority).await
+    }
+}
+
+/// Returns the virtual host a workload serves, from the `host` config on its
+/// `wasi:http/incoming-handler` interface.
+fn virtual_host(workload: &ResolvedWorkload) -> Option<&str> {
+    let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+    workload
+        .host_interfaces()
+        .iter()
+        .find(|iface| iface.contains(&incoming_handler_interface))
+        .and_then(|iface| iface.config.get("host"))
+        .map(String::as_str)
+}
+
+/// Looks up the virtual host of a workload publishing the service named by a
+/// `service-name.namespace` authority. Workloads with paused routing are skipped.
+async fn resolve_service_host(
+    workload_handles: &WorkloadHandles,
+    authority: &str,
+) -> Option<String> {
+    let (name, namespace) = crate::host::services::parse_service_authority(authority)?;
+    workload_handles
+        .read()
+        .await
+        .values()
+        .map(|(handle, _, _)| handle)
+        .filter(|handle| {
+            handle.namespace() == namespace
+                && handle.service_name() == Some(name)
+                && !handle.is_routing_paused()
+        })
+        .filter_map(virtual_host)
+        .min()
+        .map(str::to_string)
+}
+
+/// Finds the workload on this host that serves an outgoing request's authority, either
+/// as a `service-name.namespace` reference or as the workload's virtual host. The
+/// virtual host matches the authority with or without its port.
+async fn local_workload(
+    workload_handles: &WorkloadHandles,
+    authority: &str,
+) -> Option<(ResolvedWorkload, InstancePre<Ctx>, String)> {
+    let target = resolve_service_host(workload_handles, authority)
+        .await
+        .unwrap_or_else(|| authority.to_string());
+    let host = target
+        .rsplit_once(':')
+        .map_or(target.as_str(), |(host, _port)| host);
+
+    let handles = workload_handles.read().await;
+    let mut matching: Vec<_> = handles
+        .values()
+        .filter(|(handle, _, _)| virtual_host(handle).is_some_and(|v| v == target || v == host))
+        .collect();
+    // Prefer a running workload, and a stable choice when several share a virtual host
+    matching.sort_by_key(|(handle, _, _)| (handle.is_routing_paused(), handle.id().to_string()));
+    matching.first().map(|entry| (*entry).clone())
+}
+
+/// Invokes a workload on this host for an outgoing request, as though the request had
+/// arrived at the HTTP server from this host. A request a filter of the virtual host
+/// passes on continues its chain, other requests go through everything requests arriving
+/// at the listener do, see [`serve_workload`]. The request body streams into the workload
+/// as it's read.
+async fn invoke_local_workload(
+    workload_handles: &WorkloadHandles,
+    traffic_splits: &TrafficSplits,
+    api_keys: Option<&ApiKeyStore>,
+    workload: (ResolvedWorkload, InstancePre<Ctx>, String),
+    sender: &str,
+    mut request: hyper::Request<HyperOutgoingBody>,
+    config: OutgoingRequestConfig,
+) -> Result<IncomingResponse, ErrorCode> {
+    let host = virtual_host(&workload.0).map(str::to_string);
+    let from_filter = workload_handles
+        .read()
+        .await
+        .get(sender)
+        .is_some_and(|(handle, _, _)| crate::host::filters::runs_for(handle, host.as_deref()));
+    let serve = async {
+        if from_filter {
+            serve_filter_chain(
+                workload_handles,
+                workload,
+                host.as_deref(),
+                Some(sender),
+                request,
+            )
+            .await
+        } else {
+            // The request comes from this host, as it would over loopback
+            request
+                .extensions_mut()
+                .insert(ClientAddr(SocketAddr::from((Ipv4Addr::LOCALHOST, 0))));
+            serve_workload(
+                workload_handles,
+                traffic_splits,
+                api_keys,
+                workload,
+                request,
+            )
+            .await
+        }
+    };
+    let resp = tokio::time::timeout(config.first_byte_timeout, serve)
+        .await
+        .map_err(|_| ErrorCode::ConnectionReadTimeout)?;
+
+    Ok(IncomingResponse {
+        resp,
+        worker: None,
+        between_bytes_timeout: config.between_bytes_timeout,
+    })
+}
+
+/// Binds the socket the HTTP server listens on, with room for `backlog` connections
+/// waiting to be accepted.
+fn bind_tcp(addr: SocketAddr, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    // As `TcpListener::bind` does
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+/// The socket the HTTP server accepts connections on.
+enum Listener {
+    Tokio(TcpListener),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(crate::host::uring::UringListener),
+}
+
+impl Listener {
+    async fn accept(&mut self) -> std::io::Result<(tokio::net::TcpStream, SocketAddr)> {
+        match self {
+            Self::Tokio(listener) => listener.accept().await,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(listener) => listener.accept().await,
+        }
+    }
+
+    /// Accepts the next connection with a permit from `connections`, if the server limits
+    /// them, waiting for another connection to close first while it's at the limit.
+    async fn accept_within(
+        &mut self,
+        connections: Option<&Arc<Semaphore>>,
+    ) -> std::io::Result<(
+        tokio::net::TcpStream,
+        SocketAddr,
+        Option<OwnedSemaphorePermit>,
+    )> {
+        let permit = match connections {
+            Some(connections) => Some(
+                connections
+                    .clone()
+                    .acquire_owned()
+                    .await
+                    .expect("the connection semaphore is never closed"),
+            ),
+            None => None,
+        };
+        let (client, client_addr) = self.accept().await?;
+        Ok((client, client_addr, permit))
+    }
+
+    /// Whether the listener stopped for good, rather than failing to accept a connection.
+    fn is_closed(&self) -> bool {
+        match self {
+            Self::Tokio(_) => false,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            Self::Uring(listener) => listener.is_closed(),
+        }
+    }
+}
+
+/// Builds the service handling the server's requests, over every protocol it speaks.
+/// Responses carry `alt_svc`, if any, to advertise another protocol to clients.
+fn request_service<T: Router>(
     handler: Arc<T>,
     workload_handles: WorkloadHandles,
+    traffic_splits: TrafficSplits,
+    static_mounts: Arc<[StaticMount]>,
+    upstreams: Arc<[Upstream]>,
+    api_keys: Option<Arc<ApiKeyStore>>,
+    alt_svc: Option<HeaderValue>,
+) -> impl hyper::service::Service<
+    hyper::Request<hyper::body::Incoming>,
+    Response = hyper::Response<HyperOutgoingBody>,
+    Error = hyper::Error,
+    Future = impl Future<Output = Result<hyper::Response<HyperOutgoingBody>, hyper::Error>>
+             + Send
+             + 'static,
+> + Clone
++ Send
++ 'static {
+    hyper::service::service_fn(move |req| {
+        let handler = handler.clone();
+        let handles = workload_handles.clone();
+        let splits = traffic_splits.clone();
+        let mounts = static_mounts.clone();
+        let upstreams = upstreams.clone();
+        let api_keys = api_keys.clone();
+        let alt_svc = alt_svc.clone();
+        async move {
+            let mut response = handle_http_request(
+                handler,
+                req,
+                handles,
+                splits,
+                &mounts,
+                &upstreams,
+                api_keys.as_deref(),
+            )
+            .await?;
+            if let Some(alt_svc) = alt_svc {
+                response
+                    .headers_mut()
+                    .insert(hyper::header::ALT_SVC, alt_svc);
+            }
+            Ok(response)
+        }
+    })
+}
+
+/// HTTP server implementation that routes to workload components
+#[allow(clippy::too_many_arguments)]
+async fn run_http_server<S>(
+    mut listener: Listener,
+    service: S,
     shutdown_rx: &mut mpsc::Receiver<()>,
     tls_acceptor: Option<TlsAcceptor>,
-) -> anyhow::Result<()> {
+    limits: ConnectionLimits,
+    proxy_protocol: bool,
+    forwarded: Arc<ForwardedConfig>,
+    ip_filter: Arc<IpFilter>,
+    sni_routing: bool,
+) -> anyhow::Result<()>
+where
+    S: hyper::service::Service<
+            hyper::Request<hyper::body::Incoming>,
+            Response = hyper::Response<HyperOutgoingBody>,
+            Error = hyper::Error,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let connections = limits
+        .max_connections
+        .map(|max| Arc::new(Semaphore::new(max)));
     loop {
         tokio::select! {
             // Handle shutdown signal
@@ -455,31 +1380,92 @@ async fn run_http_server<T: Router>(
                 break;
             }
             // Accept new connections
-            result = listener.accept() => {
+            result = listener.accept_within(connections.as_ref()) => {
                 match result {
-                    Ok((client, client_addr)) => {
+                    Ok((mut client, client_addr, permit)) => {
                         debug!(addr = ?client_addr, "new HTTP client connection");
 
-                        let handles_clone = workload_handles.clone();
                         let tls_acceptor_clone = tls_acceptor.clone();
-                        let handler_clone = handler.clone();
+                        let tracker = Arc::new(ConnectionTracker::new(limits));
+                        let service = service.clone();
+                        let forwarded = forwarded.clone();
+                        let ip_filter = ip_filter.clone();
                         tokio::spawn(async move {
-                            let service = hyper::service::service_fn(move |req| {
-                                let handles = handles_clone.clone();
-                                let handler = handler_clone.clone();
-                                async move {
-                                    handle_http_request(handler, req, handles).await
+                            // The connection holds its place under the limit until it closes
+                            let _permit = permit;
+                            let peer = if proxy_protocol {
+                                let header = tokio::time::timeout(
+                                    crate::host::proxy_protocol::HEADER_TIMEOUT,
+                                    crate::host::proxy_protocol::read_header(&mut client),
+                                )
+                                .await;
+                                match header {
+                                    // LOCAL connections come from the load balancer itself
+                                    Ok(Ok(addr)) => addr.unwrap_or(client_addr),
+                                    Ok(Err(e)) => {
+                                        warn!(addr = ?client_addr, err = ?e, "invalid PROXY protocol header");
+                                        return;
+                                    }
+                                    Err(_) => {
+                                        warn!(addr = ?client_addr, "timed out waiting for a PROXY protocol header");
+                                        return;
+                                    }
                                 }
-                            });
-
+                            } else {
+                                client_addr
+                            };
+                            if !ip_filter.allows(peer.ip()) {
+                                debug!(addr = ?peer, "closing connection from filtered address");
+                                return;
+                            }
+                            // Requests are routed by the connection's server name, if enabled
+                            let service = |server_name: Option<String>| {
+                                let tracker = tracker.clone();
+                                hyper::service::service_fn(move |mut req| {
+                                    tracker.request_started();
+                                    forwarded.apply(req.headers_mut(), peer);
+                                    req.extensions_mut().insert(ClientAddr(peer));
+                                    if let Some(server_name) = &server_name {
+                                        route_by_server_name(&mut req, server_name);
+                                    }
+                                    service.call(req)
+                                })
+                            };
                             let result = if let Some(acceptor) = tls_acceptor_clone {
                                 // Handle HTTPS connection
                                 match acceptor.accept(client).await {
                                     Ok(tls_stream) => {
-                                        http1::Builder::new()
-                                            .keep_alive(true)
-                                            .serve_connection(TokioIo::new(tls_stream), service)
-                                            .await
+                                        // TLS-ALPN-01 challenges end with the handshake
+                                        #[cfg(feature = "acme")]
+                                        if tls_stream.get_ref().1.alpn_protocol()
+                                            == Some(crate::host::acme::ACME_TLS_ALPN)
+                                        {
+                                            return;
+                                        }
+                                        let h2 = tls_stream.get_ref().1.alpn_protocol()
+                                            == Some(b"h2".as_slice());
+                                        let server_name = tls_stream
+                                            .get_ref()
+                                            .1
+                                            .server_name()
+                                            .filter(|_| sni_routing)
+                                            .map(str::to_string);
+                                        let service = service(server_name);
+                                        let io = TokioIo::new(tls_stream);
+                                        if h2 {
+                                            let connection = http2::Builder::new(TokioExecutor)
+                                                .serve_connection(io, service);
+                                            tracker
+                                                .serve(connection, |c| c.graceful_shutdown())
+                                                .await
+                                        } else {
+                                            let connection = http1::Builder::new()
+                                                .keep_alive(true)
+                                                .serve_connection(io, service);
+                                            tracker
+                                                .serve(connection, |c| c.graceful_shutdown())
+                                                .await
+                                        }
                                     }
                                     Err(e) => {
                                         error!(addr = ?client_addr, err = ?e, "TLS handshake failed");
@@ -488,9 +1474,11 @@ async fn run_http_server<T: Router>(
                                 }
                             } else {
                                 // Handle HTTP connection
-                                http1::Builder::new()
+                                let connection = http1::Builder::new()
                                     .keep_alive(true)
-                                    .serve_connection(TokioIo::new(client), service)
+                                    .serve_connection(TokioIo::new(client), service(None));
+                                tracker
+                                    .serve(connection, |c| c.graceful_shutdown())
                                     .await
                             };
 
@@ -501,6 +1489,10 @@ async fn run_http_server<T: Router>(
                     }
                     Err(e) => {
                         error!(err = ?e, "failed to accept HTTP connection");
+                        ensure!(
+                            !listener.is_closed(),
+                            "the HTTP listener stopped accepting connections"
+                        );
                     }
                 }
             }
@@ -513,13 +1505,34 @@ async fn run_http_server<T: Router>(
 /// Handle individual HTTP requests by looking up workload and invoking component
 async fn handle_http_request<T: Router>(
     handler: Arc<T>,
-    req: hyper::Request<hyper::body::Incoming>,
+    mut req: hyper::Request<hyper::body::Incoming>,
     workload_handles: WorkloadHandles,
+    traffic_splits: TrafficSplits,
+    static_mounts: &[StaticMount],
+    upstreams: &[Upstream],
+    api_keys: Option<&ApiKeyStore>,
 ) -> Result<hyper::Response<HyperOutgoingBody>, hyper::Error> {
     let method = req.method().clone();
     let uri = req.uri().clone();
+    // HTTP/2 and HTTP/3 clients send the host as the URI's authority instead of a header
+    if !req.headers().contains_key(hyper::header::HOST)
+        && let Some(authority) = uri.authority()
+        && let Ok(host) = HeaderValue::from_str(authority.as_str())
+    {
+        req.headers_mut().insert(hyper::header::HOST, host);
+    }
+
+    if let Some(response) = crate::host::assets::serve(static_mounts, &req).await {
+        return Ok(response);
+    }
+    if let Some(upstream) = crate::host::upstream::find(upstreams, &req, true) {
+        return Ok(upstream.proxy(req).await);
+    }
 
     let Ok(workload_id) = handler.route_incoming_request(&req) else {
+        if let Some(upstream) = crate::host::upstream::find(upstreams, &req, false) {
+            return Ok(upstream.proxy(req).await);
+        }
         return Ok(hyper::Response::builder()
             .status(400)
             .body(HyperOutgoingBody::default())
@@ -541,52 +1554,218 @@ async fn handle_http_request<T: Router>(
         debug!(host = %workload_id, "looking up workload handle for host header");
         handles.get(&workload_id).cloned()
     };
-
-    let response = match workload_handle {
-        Some((handle, instance_pre, component_id)) => {
-            match invoke_component_handler(handle, instance_pre, &component_id, req).await {
-                Ok(resp) => resp,
-                Err(e) => {
-                    error!(err = ?e, host = %workload_id, "failed to invoke component");
-                    hyper::Response::builder()
-                        .status(500)
-                        .body(HyperOutgoingBody::default())
-                        // TODO: Add in the actual error message in the response body
-                        // .body(HyperOutgoingBody::new(e.to_string()))
-                        .expect("failed to build 500 response")
-                }
-            }
+    let Some(workload) = workload_handle else {
+        if let Some(upstream) = crate::host::upstream::find(upstreams, &req, false) {
+            return Ok(upstream.proxy(req).await);
         }
-        None => {
-            warn!(host = %workload_id, "No workload bound to host header or wildcard '*'");
+        warn!(host = %workload_id, "No workload bound to host header or wildcard '*'");
+        return Ok(hyper::Response::builder()
+            .status(404)
+            .body(HyperOutgoingBody::default())
+            .expect("failed to build 404 response"));
+    };
+
+    let req = req.map(|body| {
+        body.map_err(wasmtime_wasi_http::hyper_response_error)
+            .boxed()
+    });
+    Ok(serve_workload(&workload_handles, &traffic_splits, api_keys, workload, req).await)
+}
+
+/// Serves a request routed to a workload on this host, whether it arrived at the
+/// listener or was sent in-process by another workload. The request goes to a workload
+/// of the virtual host's traffic split, has to pass the virtual host's IP filter, API key
+/// and JWT checks, gets its header rules applied and is mirrored to the host's shadows,
+/// then runs through the host's filters before reaching the workload.
+///
+/// # Returns
+/// The response to the request. A workload failing to handle it is answered with
+/// `500 Internal Server Error`.
+async fn serve_workload(
+    workload_handles: &WorkloadHandles,
+    traffic_splits: &TrafficSplits,
+    api_keys: Option<&ApiKeyStore>,
+    workload: (ResolvedWorkload, InstancePre<Ctx>, String),
+    mut req: hyper::Request<HyperIncomingBody>,
+) -> hyper::Response<HyperOutgoingBody> {
+    let workload = split_workload(traffic_splits, workload_handles, workload).await;
+    let handle = &workload.0;
+    let host = virtual_host(handle).map(str::to_string);
+
+    // Addresses the virtual host doesn't accept are rejected before anything runs for them
+    if let Some(filter) = IpFilter::from_workload(handle).ok().flatten()
+        && let Some(ClientAddr(peer)) = req.extensions().get::<ClientAddr>()
+        && !filter.allows(peer.ip())
+    {
+        debug!(addr = ?peer, host = ?host, "rejecting request from filtered address");
+        return crate::host::ip_filter::forbidden();
+    }
+    // Identity headers are only ever set by the checks below, never by the client
+    crate::host::api_keys::strip_key_id(req.headers_mut());
+    crate::host::jwt::strip_claims(req.headers_mut());
+    // Requests need a valid API key and token before anything else runs for them
+    if crate::host::api_keys::required(handle).unwrap_or(false)
+        && let Some(rejected) =
+            crate::host::api_keys::authenticate(api_keys, host.as_deref(), req.headers_mut())
+    {
+        return rejected;
+    }
+    if let Some(jwt) = JwtConfig::from_workload(handle).ok().flatten()
+        && let Some(rejected) = crate::host::jwt::authenticate(&jwt, req.headers_mut()).await
+    {
+        return rejected;
+    }
+    // Rules checked when the workload was resolved
+    let header_rules = HeaderRules::from_workload(handle).unwrap_or_default();
+    let request_id = header_rules.apply_request(req.headers_mut());
+
+    // Shadows get a copy of each chunk of the body as the workload reads it
+    let shadows = crate::host::mirror::shadows(workload_handles, host.as_deref()).await;
+    let mut primary_status = None;
+    let req = if shadows.is_empty() {
+        req
+    } else {
+        let (parts, body) = req.into_parts();
+        let (status_tx, status_rx) = tokio::sync::watch::channel(None);
+        let copies = shadows
+            .into_iter()
+            .filter_map(|shadow| {
+                crate::host::mirror::mirror(
+                    shadow,
+                    host.clone().unwrap_or_default(),
+                    &parts,
+                    status_rx.clone(),
+                )
+            })
+            .collect();
+        primary_status = Some(status_tx);
+        hyper::Request::from_parts(parts, crate::host::mirror::tee(body, copies).boxed())
+    };
+
+    let mut response =
+        serve_filter_chain(workload_handles, workload, host.as_deref(), None, req).await;
+    if let Some(status_tx) = primary_status {
+        status_tx.send_replace(Some(response.status().as_u16()));
+    }
+    header_rules.apply_response(response.headers_mut(), request_id);
+    response
+}
+
+/// Passes a request through the filters of a virtual host to the workload serving it.
+///
+/// # Arguments
+/// * `workload` - The workload serving the virtual host
+/// * `host` - The virtual host
+/// * `sender` - The filter passing the request on, or `None` to start the chain
+async fn serve_filter_chain(
+    workload_handles: &WorkloadHandles,
+    workload: (ResolvedWorkload, InstancePre<Ctx>, String),
+    host: Option<&str>,
+    sender: Option<&str>,
+    req: hyper::Request<HyperIncomingBody>,
+) -> hyper::Response<HyperOutgoingBody> {
+    let (handle, instance_pre, component_id) =
+        crate::host::filters::next(workload_handles, workload, host, sender).await;
+    if handle.is_routing_paused() {
+        warn!(host = ?host, workload_id = handle.id(), "routing to workload is paused");
+        return hyper::Response::builder()
+            .status(503)
+            .body(HyperOutgoingBody::default())
+            .expect("failed to build 503 response");
+    }
+    match invoke_component_handler(handle, instance_pre, &component_id, req).await {
+        Ok(resp) => resp,
+        Err(e) => {
+            error!(err = ?e, host = ?host, "failed to invoke component");
             hyper::Response::builder()
-                .status(404)
+                .status(500)
                 .body(HyperOutgoingBody::default())
-                .expect("failed to build 404 response")
+                // TODO: Add in the actual error message in the response body
+                // .body(HyperOutgoingBody::new(e.to_string()))
+                .expect("failed to build 500 response")
         }
-    };
+    }
+}
 
-    Ok(response)
+/// Picks the workload for a request from the traffic split of the routed workload's
+/// virtual host. Requests go to the routed workload when the host has no split, or none
+/// of the split's workloads is available.
+async fn split_workload(
+    traffic_splits: &TrafficSplits,
+    workload_handles: &WorkloadHandles,
+    workload: (ResolvedWorkload, InstancePre<Ctx>, String),
+) -> (ResolvedWorkload, InstancePre<Ctx>, String) {
+    let Some(host) = virtual_host(&workload.0).map(str::to_string) else {
+        return workload;
+    };
+    let Some(split) = traffic_splits.read().await.get(&host).cloned() else {
+        return workload;
+    };
+    let handles = workload_handles.read().await;
+    split
+        .pick(|id| {
+            handles.get(id).is_some_and(|(handle, _, _)| {
+                !handle.is_routing_paused() && virtual_host(handle) == Some(host.as_str())
+            })
+        })
+        .and_then(|id| handles.get(id).cloned())
+        .unwrap_or(workload)
 }
 
 /// Invoke the component handler for the given workload
-async fn invoke_component_handler(
+pub(crate) async fn invoke_component_handler(
     workload_handle: ResolvedWorkload,
     instance_pre: InstancePre<Ctx>,
     component_id: &str,
-    req: hyper::Request<hyper::body::Incoming>,
+    req: hyper::Request<HyperIncomingBody>,
 ) -> anyhow::Result<hyper::Response<HyperOutgoingBody>> {
-    // Create a new store for this request with plugin contexts
-    let mut store = workload_handle.new_store(component_id).await?;
+    // Handle the request with an idle instance if the component reuses them, otherwise
+    // create a new store for it with plugin contexts
+    let generation = workload_handle.idle_generation(component_id);
+    let (mut store, instance) = match workload_handle.take_idle_instance(component_id).await {
+        Some((store, instance)) => (store, Some(instance)),
+        None => (workload_handle.new_store(component_id).await?, None),
+    };
+    if let Some(request_id) = req.extensions().get::<RequestId>() {
+        store.data_mut().set_request_id(request_id.clone());
+    }
+    if let Some(trace_context) = req.extensions().get::<TraceContext>() {
+        store.data_mut().set_trace_context(trace_context.clone());
+    }
+    if let Some(baggage) = req.extensions().get::<Baggage>() {
+        store.data_mut().set_baggage(baggage.clone());
+    }
 
-    handle_component_request(store.as_context_mut(), instance_pre, req).await
+    let reuse = Some((workload_handle, component_id.to_string(), generation));
+    serve_component_request(store, instance_pre, instance, reuse, req).await
 }
 
 /// Handle a component request using WASI HTTP (copied from wash/crates/src/cli/dev.rs)
-pub async fn handle_component_request<'a>(
-    mut store: StoreContextMut<'a, Ctx>,
+///
+/// The request body isn't buffered: the guest's `input-stream` reads pull it from `req`
+/// chunk by chunk, so a client uploading faster than the guest reads is held back by the
+/// connection's flow control.
+///
+/// The response is returned as soon as the guest sets it, while the guest keeps running
+/// in a task of its own, which owns `store`, to write the response body.
+pub async fn handle_component_request(
+    store: Store<Ctx>,
+    pre: InstancePre<Ctx>,
+    req: hyper::Request<HyperIncomingBody>,
+) -> anyhow::Result<hyper::Response<HyperOutgoingBody>> {
+    serve_component_request(store, pre, None, None, req).await
+}
+
+/// Handles a request like [`handle_component_request`], with `instance` if it's already
+/// instantiated in `store`. With `reuse`, the workload and component the instance belongs
+/// to and the generation of the component's idle instances, the instance is offered back
+/// to the workload once it has handled the request, see [`crate::engine::instances`].
+async fn serve_component_request(
+    mut store: Store<Ctx>,
     pre: InstancePre<Ctx>,
-    req: hyper::Request<hyper::body::Incoming>,
+    instance: Option<Instance>,
+    reuse: Option<(ResolvedWorkload, String, u64)>,
+    req: hyper::Request<HyperIncomingBody>,
 ) -> anyhow::Result<hyper::Response<HyperOutgoingBody>> {
     let (sender, receiver) = tokio::sync::oneshot::channel();
     let scheme = match req.uri().scheme() {
@@ -596,17 +1775,52 @@ pub async fn handle_component_request<'a>(
         // Fallback to HTTP if no scheme is present
         None => Scheme::Http,
     };
-    let req = store.data_mut().new_incoming_request(scheme, req)?;
+    store
+        .data_mut()
+        .describe_invocation(|| format!("{} {}", req.method(), req.uri().path()));
+    let (parts, body) = req.into_parts();
+    let body = HostIncomingBody::new(body, INCOMING_BETWEEN_BYTES_TIMEOUT);
+    let req = HostIncomingRequest::new(store.data_mut(), parts, scheme, Some(body))?;
+    let req = WasiHttpView::table(store.data_mut()).push(req)?;
     let out = store.data_mut().new_response_outparam(sender)?;
     let pre = ProxyPre::new(pre).context("failed to instantiate proxy pre")?;
 
-    // Run the http request itself by instantiating and calling the component
-    let proxy = pre.instantiate_async(&mut store).await?;
-
-    proxy
-        .wasi_http_incoming_handler()
-        .call_handle(&mut store, req, out)
-        .await?;
+    // Run the http request itself by instantiating and calling the component, on the
+    // workload's threads if it has any. Waiting for the call to return would hold the
+    // response back until the whole body was written, which never happens for a stream
+    // the client has to read first.
+    let workload = reuse.as_ref().map(|(workload, _, _)| workload.clone());
+    let task = async move {
+        let instance = match instance {
+            Some(instance) => instance,
+            None => pre.instance_pre().instantiate_async(&mut store).await?,
+        };
+        store.data_mut().instance_ready();
+        let proxy = Proxy::new(&mut store, &instance)?;
+        let result = proxy
+            .wasi_http_incoming_handler()
+            .call_handle(&mut store, req, out)
+            .await;
+        if let Err(e) = &result {
+            error!(err = ?e, "component failed handling HTTP request");
+            store.data().record_error(e);
+        }
+        crate::host::slow_invocations::log_if_slow(&store);
+        // An instance that trapped can't be called again
+        if result.is_ok()
+            && let Some((workload, component_id, generation)) = reuse
+        {
+            workload
+                .keep_idle_instance(&component_id, generation, store, instance)
+                .await;
+        }
+        result
+    }
+    .in_current_span();
+    let task = match workload {
+        Some(workload) => workload.spawn(task),
+        None => tokio::spawn(task),
+    };
 
     match receiver.await {
         // If the client calls `response-outparam::set` then one of these
@@ -616,23 +1830,60 @@ pub async fn handle_component_request<'a>(
 
         // Otherwise the `sender` will get dropped along with the `Store`
         // meaning that the oneshot will get disconnected
-        Err(e) => {
-            error!(err = ?e, "error receiving http response");
-            Err(anyhow::anyhow!(
+        Err(_) => match task.await {
+            Ok(Ok(())) => Err(anyhow::anyhow!(
                 "oneshot channel closed but no response was sent"
-            ))
-        }
+            )),
+            Ok(Err(e)) => Err(e.context("component failed before sending a response")),
+            Err(e) => Err(anyhow::Error::new(e).context("component task failed")),
+        },
     }
 }
 
-/// Load TLS configuration from certificate and key files
+/// Load TLS configuration serving a certificate loaded from files
 /// Extracted from wash dev command for reuse in HTTP server plugin
 async fn load_tls_config(
-    cert_path: &Path,
-    key_path: &Path,
+    certificate: Arc<ReloadableCertificate>,
     ca_path: Option<&Path>,
 ) -> anyhow::Result<ServerConfig> {
-    // Load certificate chain
+    // Create rustls server config, resolving the certificate so it can be reloaded
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_cert_resolver(certificate);
+    // HTTP/2 carries trailers without the response having to declare them up front
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    // If CA is provided, configure client certificate verification
+    if let Some(ca_path) = ca_path {
+        let ca_data = tokio::fs::read(ca_path)
+            .await
+            .with_context(|| format!("Failed to read CA file: {}", ca_path.display()))?;
+        let mut ca_reader = std::io::Cursor::new(ca_data);
+        let ca_certs: Vec<CertificateDer<'static>> = certs(&mut ca_reader)
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("Failed to parse CA file: {}", ca_path.display()))?;
+
+        ensure!(
+            !ca_certs.is_empty(),
+            "No CA certificates found in file: {}",
+            ca_path.display()
+        );
+
+        // Note: Client certificate verification configuration would go here
+        // For now, we'll keep it simple without client cert verification
+        debug!("CA certificate loaded, but client certificate verification not yet implemented");
+    }
+
+    Ok(config)
+}
+
+/// Loads a PEM certificate chain.
+///
+/// # Errors
+/// Returns an error if the file can't be read or holds no certificates.
+pub(crate) async fn load_cert_chain(
+    cert_path: &Path,
+) -> anyhow::Result<Vec<CertificateDer<'static>>> {
     let cert_data = tokio::fs::read(cert_path)
         .await
         .with_context(|| format!("Failed to read certificate file: {}", cert_path.display()))?;
@@ -646,42 +1897,162 @@ async fn load_tls_config(
         "No certificates found in file: {}",
         cert_path.display()
     );
+    Ok(cert_chain)
+}
 
-    // Load private key
+/// Loads a PEM private key.
+///
+/// # Errors
+/// Returns an error if the file can't be read or holds no private key.
+pub(crate) async fn load_private_key(key_path: &Path) -> anyhow::Result<PrivateKeyDer<'static>> {
     let key_data = tokio::fs::read(key_path)
         .await
         .with_context(|| format!("Failed to read private key file: {}", key_path.display()))?;
     let mut key_reader = std::io::Cursor::new(key_data);
-    let key = private_key(&mut key_reader)
+    private_key(&mut key_reader)
         .with_context(|| format!("Failed to parse private key file: {}", key_path.display()))?
-        .ok_or_else(|| anyhow::anyhow!("No private key found in file: {}", key_path.display()))?;
+        .ok_or_else(|| anyhow::anyhow!("No private key found in file: {}", key_path.display()))
+}
 
-    // Create rustls server config
-    let config = ServerConfig::builder()
-        .with_no_client_auth()
-        .with_single_cert(cert_chain, key)
-        .with_context(|| "Failed to create TLS configuration")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::workload::{UnresolvedWorkload, WorkloadComponent};
+    use crate::types::LocalResources;
+
+    /// Resolves a workload serving a virtual host with an empty component, which fails
+    /// every request that reaches it.
+    async fn serving_workload(
+        id: &str,
+        config: &[(&str, &str)],
+    ) -> anyhow::Result<(ResolvedWorkload, InstancePre<Ctx>, String)> {
+        let engine = wasmtime::Engine::default();
+        let component = wasmtime::component::Component::new(&engine, b"\0asm\x0d\x00\x01\x00")?;
+        let component = WorkloadComponent::new(
+            id.to_string(),
+            id.to_string(),
+            "default".to_string(),
+            component,
+            wasmtime::component::Linker::new(&engine),
+            Vec::new(),
+            LocalResources::default(),
+        );
+        let component_id = component.id().to_string();
+        let mut interface = WitInterface::from("wasi:http/incoming-handler@0.2.0");
+        for (key, value) in config {
+            interface.config.insert(key.to_string(), value.to_string());
+        }
+        let workload = UnresolvedWorkload::new(
+            id.to_string(),
+            id.to_string(),
+            "default".to_string(),
+            None,
+            vec![component],
+            vec![interface],
+        )
+        .resolve(None, Arc::new(NullServer::default()))
+        .await?;
+        let instance_pre = workload.instantiate_pre(&component_id).await?;
+        Ok((workload, instance_pre, component_id))
+    }
 
-    // If CA is provided, configure client certificate verification
-    if let Some(ca_path) = ca_path {
-        let ca_data = tokio::fs::read(ca_path)
+    fn outgoing_request(key: Option<&str>) -> hyper::Request<HyperOutgoingBody> {
+        let mut request = hyper::Request::builder()
+            .uri("http://api.localhost/")
+            .header("x-api-key-id", "forged")
+            .header("x-jwt-claim-sub", "forged");
+        if let Some(key) = key {
+            request = request.header("x-api-key", key);
+        }
+        request
+            .body(HyperOutgoingBody::default())
+            .expect("failed to build request")
+    }
+
+    fn outgoing_config() -> OutgoingRequestConfig {
+        OutgoingRequestConfig {
+            use_tls: false,
+            connect_timeout: Duration::from_secs(5),
+            first_byte_timeout: Duration::from_secs(5),
+            between_bytes_timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Sends an in-process request from the `caller` workload to `api.localhost`.
+    async fn invoke_local(
+        workload_handles: &WorkloadHandles,
+        traffic_splits: &TrafficSplits,
+        api_keys: Option<&ApiKeyStore>,
+        key: Option<&str>,
+    ) -> anyhow::Result<hyper::StatusCode> {
+        let workload = local_workload(workload_handles, "api.localhost")
             .await
-            .with_context(|| format!("Failed to read CA file: {}", ca_path.display()))?;
-        let mut ca_reader = std::io::Cursor::new(ca_data);
-        let ca_certs: Vec<CertificateDer<'static>> = certs(&mut ca_reader)
-            .collect::<Result<Vec<_>, _>>()
-            .with_context(|| format!("Failed to parse CA file: {}", ca_path.display()))?;
+            .context("expected the workload serving api.localhost")?;
+        let response = invoke_local_workload(
+            workload_handles,
+            traffic_splits,
+            api_keys,
+            workload,
+            "caller",
+            outgoing_request(key),
+            outgoing_config(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("in-process request failed: {e:?}"))?;
+        Ok(response.resp.status())
+    }
 
-        ensure!(
-            !ca_certs.is_empty(),
-            "No CA certificates found in file: {}",
-            ca_path.display()
-        );
+    #[tokio::test]
+    async fn test_in_process_requests_are_authenticated() -> anyhow::Result<()> {
+        let api =
+            serving_workload("api", &[("host", "api.localhost"), ("api-key", "required")]).await?;
+        let caller = serving_workload("caller", &[("host", "caller.localhost")]).await?;
+        let workload_handles: WorkloadHandles = Default::default();
+        workload_handles
+            .write()
+            .await
+            .extend([("api".to_string(), api), ("caller".to_string(), caller)]);
+        let traffic_splits = TrafficSplits::default();
+        let api_keys = ApiKeyStore::in_memory();
+        let (_, key) = api_keys.create("caller", vec!["api.localhost".to_string()])?;
 
-        // Note: Client certificate verification configuration would go here
-        // For now, we'll keep it simple without client cert verification
-        debug!("CA certificate loaded, but client certificate verification not yet implemented");
+        let invoke = async |key: Option<&str>| {
+            invoke_local(&workload_handles, &traffic_splits, Some(&api_keys), key).await
+        };
+
+        // Forged identity headers don't stand in for a key
+        assert_eq!(invoke(None).await?, hyper::StatusCode::UNAUTHORIZED);
+        // With a key, the request reaches the workload, which can't handle it
+        assert_eq!(
+            invoke(Some(&key)).await?,
+            hyper::StatusCode::INTERNAL_SERVER_ERROR
+        );
+        Ok(())
     }
 
-    Ok(config)
+    #[tokio::test]
+    async fn test_paused_workloads_are_unavailable() -> anyhow::Result<()> {
+        let api = serving_workload("api", &[("host", "api.localhost")]).await?;
+        let workload = api.0.clone();
+        let workload_handles: WorkloadHandles = Default::default();
+        workload_handles
+            .write()
+            .await
+            .insert("api".to_string(), api);
+        let traffic_splits = TrafficSplits::default();
+        let status = async || invoke_local(&workload_handles, &traffic_splits, None, None).await;
+
+        // The request reaches the workload, which can't handle it
+        assert_eq!(status().await?, hyper::StatusCode::INTERNAL_SERVER_ERROR);
+
+        workload.pause();
+        assert_eq!(status().await?, hyper::StatusCode::SERVICE_UNAVAILABLE);
+        // Healthy plugins resume routing, but don't lift the pause
+        workload.set_routing_paused(false);
+        assert_eq!(status().await?, hyper::StatusCode::SERVICE_UNAVAILABLE);
+
+        workload.resume();
+        assert_eq!(status().await?, hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        Ok(())
+    }
 }
@@ -0,0 +1,205 @@
+//! HTTP/3 for the HTTP server, behind the `http3` feature.
+//!
+//! A QUIC endpoint on the UDP port of the server's address accepts HTTP/3 connections,
+//! and responses on TCP carry an `alt-svc` header advertising it, so clients that speak
+//! HTTP/3 switch over for their next requests. The requests of each connection are handed
+//! to the same service as those arriving on TCP, through an in-memory HTTP/2 connection,
+//! so they're routed, authenticated and mirrored the same way. See
+//! [`crate::host::http::HttpServer::with_http3`].
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bytes::{Buf as _, Bytes};
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{BodyExt as _, StreamBody};
+use hyper::body::{Frame, Incoming};
+use hyper::client::conn::http2::SendRequest;
+use hyper::header::HeaderValue;
+use hyper::server::conn::http2;
+use rustls::ServerConfig;
+use tracing::debug;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::host::forwarded::ForwardedConfig;
+use crate::host::grpc::TokioExecutor;
+use crate::host::ip_filter::{ClientAddr, IpFilter};
+use crate::host::sni::route_by_server_name;
+
+/// How long clients may remember the `alt-svc` advertisement, in seconds.
+const ALT_SVC_MAX_AGE_SECS: u32 = 86400;
+/// The buffer of the in-memory connection between an HTTP/3 connection and the service.
+const BRIDGE_BUFFER: usize = 64 * 1024;
+
+/// The body of a request arriving over HTTP/3.
+type RequestBody = UnsyncBoxBody<Bytes, h3::error::StreamError>;
+
+/// Builds the QUIC configuration for the server's TLS configuration, offering HTTP/3.
+///
+/// # Errors
+/// Returns an error if the TLS configuration can't be used for QUIC, which requires TLS 1.3.
+pub(crate) fn server_config(tls: &ServerConfig) -> anyhow::Result<quinn::ServerConfig> {
+    let mut tls = tls.clone();
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(tls)
+        .context("the TLS configuration can't be used for QUIC")?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// The `alt-svc` header advertising HTTP/3 on `port`.
+pub(crate) fn alt_svc(port: u16) -> HeaderValue {
+    HeaderValue::from_str(&format!("h3=\":{port}\"; ma={ALT_SVC_MAX_AGE_SECS}"))
+        .expect("alt-svc is a valid header value")
+}
+
+/// Serves the connections `endpoint` accepts with `service` until it's closed, rewriting
+/// forwarding headers with `forwarded`, refusing connections `ip_filter` rejects and, with
+/// `sni_routing`, routing requests by their connection's server name.
+pub(crate) async fn serve<S>(
+    endpoint: quinn::Endpoint,
+    service: S,
+    forwarded: Arc<ForwardedConfig>,
+    ip_filter: Arc<IpFilter>,
+    sni_routing: bool,
+) where
+    S: hyper::service::Service<
+            hyper::Request<Incoming>,
+            Response = hyper::Response<HyperOutgoingBody>,
+            Error = hyper::Error,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    while let Some(incoming) = endpoint.accept().await {
+        let addr = incoming.remote_address();
+        if !ip_filter.allows(addr.ip()) {
+            debug!(addr = ?addr, "refusing HTTP/3 connection from filtered address");
+            incoming.refuse();
+            continue;
+        }
+        debug!(addr = ?addr, "new HTTP/3 client connection");
+        let service = service.clone();
+        let forwarded = forwarded.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_connection(incoming, service, forwarded, sni_routing).await {
+                debug!(addr = ?addr, err = ?e, "error serving HTTP/3 client");
+            }
+        });
+    }
+    debug!("HTTP/3 endpoint closed");
+}
+
+async fn serve_connection<S>(
+    incoming: quinn::Incoming,
+    service: S,
+    forwarded: Arc<ForwardedConfig>,
+    sni_routing: bool,
+) -> anyhow::Result<()>
+where
+    S: hyper::service::Service<
+            hyper::Request<Incoming>,
+            Response = hyper::Response<HyperOutgoingBody>,
+            Error = hyper::Error,
+        > + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    let connection = incoming.await?;
+    let peer = connection.remote_address();
+    let server_name = connection
+        .handshake_data()
+        .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+        .and_then(|data| data.server_name)
+        .filter(|_| sni_routing);
+    let mut connection = h3::server::Connection::new(h3_quinn::Connection::new(connection)).await?;
+
+    // The service only speaks hyper, so the requests reach it over HTTP/2 in memory
+    let (client_io, server_io) = tokio::io::duplex(BRIDGE_BUFFER);
+    let service = hyper::service::service_fn(move |mut req| {
+        req.extensions_mut().insert(ClientAddr(peer));
+        if let Some(server_name) = &server_name {
+            route_by_server_name(&mut req, server_name);
+        }
+        service.call(req)
+    });
+    tokio::spawn(async move {
+        if let Err(e) = http2::Builder::new(TokioExecutor)
+            .serve_connection(TokioIo::new(server_io), service)
+            .await
+        {
+            debug!(err = ?e, "error serving bridged HTTP/3 connection");
+        }
+    });
+    let (sender, bridge) =
+        hyper::client::conn::http2::handshake(TokioExecutor, TokioIo::new(client_io)).await?;
+    tokio::spawn(bridge);
+
+    while let Some(resolver) = connection.accept().await? {
+        let sender = sender.clone();
+        let forwarded = forwarded.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve_request(resolver, sender, &forwarded, peer).await {
+                debug!(err = ?e, "error serving HTTP/3 request");
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn serve_request(
+    resolver: h3::server::RequestResolver<h3_quinn::Connection, Bytes>,
+    mut sender: SendRequest<RequestBody>,
+    forwarded: &ForwardedConfig,
+    peer: SocketAddr,
+) -> anyhow::Result<()> {
+    let (mut req, stream) = resolver.resolve_request().await?;
+    forwarded.apply(req.headers_mut(), peer);
+    let (mut send, recv) = stream.split();
+
+    // The request body streams to the service as it arrives, followed by its trailers
+    let body = futures::stream::unfold(Some(recv), |recv| async move {
+        let mut recv = recv?;
+        match recv.recv_data().await {
+            Ok(Some(mut data)) => {
+                let data = data.copy_to_bytes(data.remaining());
+                Some((Ok(Frame::data(data)), Some(recv)))
+            }
+            Ok(None) => match recv.recv_trailers().await {
+                Ok(Some(trailers)) => Some((Ok(Frame::trailers(trailers)), None)),
+                Ok(None) => None,
+                Err(e) => Some((Err(e), None)),
+            },
+            Err(e) => Some((Err(e), None)),
+        }
+    });
+    let req = req.map(|()| StreamBody::new(body).boxed_unsync());
+
+    let (parts, mut body) = sender.send_request(req).await?.into_parts();
+    send.send_response(hyper::Response::from_parts(parts, ()))
+        .await?;
+    while let Some(frame) = body.frame().await {
+        match frame?.into_data() {
+            Ok(data) => send.send_data(data).await?,
+            Err(frame) => {
+                if let Ok(trailers) = frame.into_trailers() {
+                    send.send_trailers(trailers).await?;
+                }
+            }
+        }
+    }
+    send.finish().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alt_svc() {
+        assert_eq!(alt_svc(8443), "h3=\":8443\"; ma=86400");
+    }
+}
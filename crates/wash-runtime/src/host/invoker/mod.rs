@@ -0,0 +1,557 @@
+//! Invokers that pull messages from work queues and hand each to a workload export.
+//!
+//! A workload declares an invoker by adding a [`INVOKER_INTERFACE`] host interface with
+//! this config:
+//!
+//! | key             | value                                                        |
+//! |-----------------|--------------------------------------------------------------|
+//! | `source`        | name of a [`QueueSource`] registered with the host           |
+//! | `queue`         | the queue to pull from, e.g. a stream name or SQS queue URL  |
+//! | `export`        | the function called per message, as `interface#function`     |
+//! | `concurrency`   | how many messages are handled at once, defaults to 1         |
+//!
+//! The function takes the message body as its only parameter, as a `list<u8>` or a
+//! `string`, and returns nothing or a `result`. A message is acked once the function
+//! returns, and nacked for redelivery when it returns an error, traps, or the body isn't
+//! valid UTF-8 for a `string` parameter. How often a message is redelivered and where it
+//! goes afterwards is up to the queue, e.g. the redrive policy of an SQS queue.
+//!
+//! A workload can declare several invokers. Instances of a workload share a consumer on
+//! queues that have them, so each message is handled by one instance.
+//!
+//! Hosts register sources with [`crate::host::HostBuilder::with_queue_source`]:
+//! [`nats::NatsWorkQueues`] pulls from JetStream work-queue streams, and with the
+//! `sqs-invoker` feature [`sqs::SqsQueues`] pulls from Amazon SQS.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context as _, bail, ensure};
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+use wasmtime::component::{Type, Val};
+
+use crate::engine::workload::ResolvedWorkload;
+use crate::host::saturation;
+use crate::wit::WitInterface;
+
+pub mod nats;
+#[cfg(feature = "sqs-invoker")]
+pub mod sqs;
+
+/// The host interface a workload declares an invoker with.
+pub const INVOKER_INTERFACE: &str = "wasmcloud:invoker/queue";
+/// Config key naming the [`QueueSource`] an invoker pulls from.
+pub const SOURCE_KEY: &str = "source";
+/// Config key naming the queue an invoker pulls from.
+pub const QUEUE_KEY: &str = "queue";
+/// Config key naming the export called per message, as `interface#function`.
+pub const EXPORT_KEY: &str = "export";
+/// Config key for how many messages an invoker handles at once.
+pub const CONCURRENCY_KEY: &str = "concurrency";
+
+/// Upper bound on [`CONCURRENCY_KEY`].
+const MAX_CONCURRENCY: usize = 256;
+/// How long an invoker waits before pulling again after the queue returned an error.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// A kind of work queue the host can pull from, such as SQS or NATS.
+#[async_trait::async_trait]
+pub trait QueueSource: Send + Sync + 'static {
+    /// Opens a queue for an invoker.
+    ///
+    /// # Arguments
+    /// * `queue` - The queue named in the invoker's config
+    /// * `consumer` - A name shared by the instances of the workload, for sources that
+    ///   track delivery per consumer
+    ///
+    /// # Errors
+    /// Returns an error if the queue doesn't exist or can't be reached.
+    async fn open(&self, queue: &str, consumer: &str) -> anyhow::Result<Box<dyn WorkQueue>>;
+}
+
+/// A queue an invoker pulls messages from.
+#[async_trait::async_trait]
+pub trait WorkQueue: Send + Sync {
+    /// Receives up to `max` messages, waiting a while for one if the queue is empty.
+    ///
+    /// Returning no messages is fine, the invoker calls it again.
+    ///
+    /// # Errors
+    /// Returns an error if the queue can't be reached.
+    async fn receive(&self, max: usize) -> anyhow::Result<Vec<Box<dyn QueueMessage>>>;
+}
+
+/// A message received from a [`WorkQueue`], to be acked or nacked once handled.
+#[async_trait::async_trait]
+pub trait QueueMessage: Send + Sync {
+    /// The message body.
+    fn body(&self) -> &[u8];
+
+    /// Marks the message handled, removing it from the queue.
+    async fn ack(self: Box<Self>) -> anyhow::Result<()>;
+
+    /// Returns the message to the queue for redelivery.
+    async fn nack(self: Box<Self>) -> anyhow::Result<()>;
+}
+
+/// An invoker declared by a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvokerSpec {
+    pub source: String,
+    pub queue: String,
+    /// The exported interface the function is in
+    pub interface: String,
+    pub function: String,
+    pub concurrency: usize,
+}
+
+impl InvokerSpec {
+    /// Parses the invoker declared by an [`INVOKER_INTERFACE`] host interface.
+    ///
+    /// # Errors
+    /// Returns an error if a key is missing or has an invalid value.
+    pub fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let required = |key: &str| {
+            config
+                .get(key)
+                .filter(|value| !value.is_empty())
+                .cloned()
+                .with_context(|| format!("invoker is missing '{key}'"))
+        };
+        let export = required(EXPORT_KEY)?;
+        let Some((interface, function)) = export.rsplit_once('#') else {
+            bail!("invalid {EXPORT_KEY} '{export}', expected 'interface#function'");
+        };
+        ensure!(
+            !interface.is_empty() && !function.is_empty(),
+            "invalid {EXPORT_KEY} '{export}', expected 'interface#function'"
+        );
+        let concurrency = match config.get(CONCURRENCY_KEY) {
+            Some(value) => value
+                .parse()
+                .with_context(|| format!("invalid {CONCURRENCY_KEY} '{value}'"))?,
+            None => 1,
+        };
+        ensure!(
+            (1..=MAX_CONCURRENCY).contains(&concurrency),
+            "{CONCURRENCY_KEY} must be between 1 and {MAX_CONCURRENCY}"
+        );
+
+        Ok(Self {
+            source: required(SOURCE_KEY)?,
+            queue: required(QUEUE_KEY)?,
+            interface: interface.to_string(),
+            function: function.to_string(),
+            concurrency,
+        })
+    }
+
+    /// Returns the invokers declared in a workload's host interfaces.
+    ///
+    /// # Errors
+    /// Returns an error if any declaration is invalid.
+    pub fn from_host_interfaces(interfaces: &[WitInterface]) -> anyhow::Result<Vec<Self>> {
+        let invoker = WitInterface::from(INVOKER_INTERFACE);
+        interfaces
+            .iter()
+            .filter(|iface| invoker.contains(iface))
+            .map(|iface| Self::from_config(&iface.config))
+            .collect()
+    }
+}
+
+/// How a message is passed to an invoked function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyParam {
+    Bytes,
+    String,
+}
+
+impl BodyParam {
+    /// Checks that a function takes a message body and returns nothing or a `result`.
+    fn for_signature(params: &[(String, Type)], results: &[Type]) -> anyhow::Result<Self> {
+        let param = match params {
+            [(_, Type::String)] => Self::String,
+            [(_, Type::List(list))] if matches!(list.ty(), Type::U8) => Self::Bytes,
+            _ => bail!("function must take a single list<u8> or string parameter"),
+        };
+        ensure!(
+            matches!(results, [] | [Type::Result(_)]),
+            "function must return nothing or a result"
+        );
+        Ok(param)
+    }
+
+    fn val(self, body: &[u8]) -> anyhow::Result<Val> {
+        Ok(match self {
+            Self::Bytes => Val::List(body.iter().copied().map(Val::U8).collect()),
+            Self::String => Val::String(
+                std::str::from_utf8(body)
+                    .context("message body is not valid UTF-8")?
+                    .to_string(),
+            ),
+        })
+    }
+}
+
+/// Maps the results of an invoked function to whether the message was handled.
+fn outcome(results: &[Val]) -> anyhow::Result<()> {
+    match results {
+        [Val::Result(Err(Some(error)))] => match error.as_ref() {
+            Val::String(message) => bail!("function returned an error: {message}"),
+            other => bail!("function returned an error: {other:?}"),
+        },
+        [Val::Result(Err(None))] => bail!("function returned an error"),
+        _ => Ok(()),
+    }
+}
+
+/// The queue sources registered with a host and the invokers its workloads declared.
+#[derive(Default)]
+pub struct QueueInvokers {
+    sources: HashMap<String, Arc<dyn QueueSource>>,
+    /// Cancels the invokers of each workload, keyed by workload ID
+    running: Mutex<HashMap<String, CancellationToken>>,
+}
+
+impl QueueInvokers {
+    /// Registers a source under the name invokers select it with.
+    ///
+    /// # Errors
+    /// Returns an error if a source with the name is already registered.
+    pub fn add_source(
+        &mut self,
+        name: impl Into<String>,
+        source: Arc<dyn QueueSource>,
+    ) -> anyhow::Result<()> {
+        let name = name.into();
+        ensure!(
+            !self.sources.contains_key(&name),
+            "duplicate queue source '{name}'"
+        );
+        self.sources.insert(name, source);
+        Ok(())
+    }
+
+    /// Starts the invokers a workload declares, which run until [`Self::stop`] is called.
+    ///
+    /// # Errors
+    /// Returns an error, without starting any invoker, if a declaration is invalid, names
+    /// an unknown source or export, or its queue can't be opened.
+    pub async fn start(&self, workload: &ResolvedWorkload) -> anyhow::Result<()> {
+        let specs = InvokerSpec::from_host_interfaces(workload.host_interfaces())
+            .context("invalid queue invoker")?;
+        if specs.is_empty() {
+            return Ok(());
+        }
+
+        let exports = workload.exported_functions().await;
+        let consumer = consumer_name(workload.namespace(), workload.name());
+        let mut invokers = Vec::with_capacity(specs.len());
+        for spec in specs {
+            let source = self.sources.get(&spec.source).with_context(|| {
+                let mut known: Vec<&str> = self.sources.keys().map(String::as_str).collect();
+                known.sort_unstable();
+                format!(
+                    "unknown queue source '{}', expected one of [{}]",
+                    spec.source,
+                    known.join(", ")
+                )
+            })?;
+            let wanted = WitInterface::from(spec.interface.as_str());
+            let export = exports
+                .iter()
+                .find(|f| {
+                    f.name == spec.function
+                        && (f.interface == spec.interface
+                            || WitInterface::from(f.interface.as_str()).contains(&wanted))
+                })
+                .with_context(|| {
+                    format!(
+                        "no component in workload {} exports '{}#{}'",
+                        workload.id(),
+                        spec.interface,
+                        spec.function
+                    )
+                })?;
+            let param =
+                BodyParam::for_signature(&export.params, &export.results).with_context(|| {
+                    format!(
+                        "'{}#{}' can't handle messages",
+                        spec.interface, spec.function
+                    )
+                })?;
+            let queue = source
+                .open(&spec.queue, &consumer)
+                .await
+                .with_context(|| format!("failed to open queue '{}'", spec.queue))?;
+            let invoker = Invoker {
+                workload: workload.clone(),
+                spec,
+                param,
+                results: export.results.len(),
+            };
+            invokers.push((Arc::new(invoker), queue));
+        }
+
+        let cancel = CancellationToken::new();
+        for (invoker, queue) in invokers {
+            tokio::spawn(invoker.run(queue, cancel.child_token()));
+        }
+        if let Some(previous) = self
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(workload.id().to_string(), cancel)
+        {
+            previous.cancel();
+        }
+        Ok(())
+    }
+
+    /// Stops pulling messages for a workload's invokers. Messages already received are
+    /// still handled.
+    pub fn stop(&self, workload_id: &str) {
+        if let Some(cancel) = self
+            .running
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(workload_id)
+        {
+            cancel.cancel();
+        }
+    }
+}
+
+/// Returns the consumer name of a workload, its namespace and name with the characters
+/// consumer names commonly can't contain replaced. Invokers and the `wasmcloud:messaging`
+/// plugin share it, so instances of a workload share their consumers.
+pub(crate) fn consumer_name(namespace: &str, name: &str) -> String {
+    format!("{namespace}-{name}")
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || c == '-' || c == '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// A running invoker of a workload.
+struct Invoker {
+    workload: ResolvedWorkload,
+    spec: InvokerSpec,
+    param: BodyParam,
+    /// The number of results the function returns
+    results: usize,
+}
+
+impl Invoker {
+    /// Pulls messages and handles up to `concurrency` of them at once until cancelled, then
+    /// waits for the messages being handled.
+    async fn run(self: Arc<Self>, queue: Box<dyn WorkQueue>, cancel: CancellationToken) {
+        let permits = Arc::new(Semaphore::new(self.spec.concurrency));
+        let mut handling = JoinSet::new();
+        debug!(
+            workload_id = self.workload.id(),
+            source = self.spec.source,
+            queue = self.spec.queue,
+            "starting queue invoker"
+        );
+        loop {
+            while handling.try_join_next().is_some() {}
+
+            // Nothing is pulled while the workload is paused, leaving messages in the queue
+            tokio::select! {
+                _ = self.workload.resumed() => {}
+                _ = cancel.cancelled() => break,
+            }
+            // Only pull once a message can be handled, so none wait in the host
+            let permit = tokio::select! {
+                permit = permits.clone().acquire_owned() => permit,
+                _ = cancel.cancelled() => break,
+            };
+            let Ok(permit) = permit else {
+                break;
+            };
+            let max = permits.available_permits() + 1;
+            let received = tokio::select! {
+                received = queue.receive(max) => received,
+                _ = cancel.cancelled() => break,
+            };
+            let messages = match received {
+                Ok(messages) => messages,
+                Err(e) => {
+                    warn!(
+                        workload_id = self.workload.id(),
+                        queue = self.spec.queue,
+                        "failed to receive messages, retrying: {e:#}"
+                    );
+                    drop(permit);
+                    tokio::select! {
+                        _ = tokio::time::sleep(RETRY_DELAY) => continue,
+                        _ = cancel.cancelled() => break,
+                    }
+                }
+            };
+
+            // Messages wait for a slot in the workload's queue, see `crate::host::saturation`
+            let messages: Vec<_> = messages
+                .into_iter()
+                .map(|message| (message, saturation::Queued::enter(self.workload.id())))
+                .collect();
+            let mut permit = Some(permit);
+            for (message, queued) in messages {
+                let permit = match permit.take() {
+                    Some(permit) => permit,
+                    None => match permits.clone().acquire_owned().await {
+                        Ok(permit) => permit,
+                        Err(_) => break,
+                    },
+                };
+                drop(queued);
+                let invoker = self.clone();
+                handling.spawn(async move {
+                    invoker.handle(message).await;
+                    drop(permit);
+                });
+            }
+        }
+
+        while handling.join_next().await.is_some() {}
+        debug!(
+            workload_id = self.workload.id(),
+            queue = self.spec.queue,
+            "stopped queue invoker"
+        );
+    }
+
+    /// Calls the function with a message, acking it if the call succeeds and nacking it
+    /// otherwise.
+    async fn handle(&self, message: Box<dyn QueueMessage>) {
+        match self.invoke(message.body()).await {
+            Ok(()) => {
+                if let Err(e) = message.ack().await {
+                    warn!(queue = self.spec.queue, "failed to ack message: {e:#}");
+                }
+            }
+            Err(e) => {
+                warn!(
+                    workload_id = self.workload.id(),
+                    queue = self.spec.queue,
+                    "failed to handle message, returning it to the queue: {e:#}"
+                );
+                if let Err(e) = message.nack().await {
+                    warn!(queue = self.spec.queue, "failed to nack message: {e:#}");
+                }
+            }
+        }
+    }
+
+    async fn invoke(&self, body: &[u8]) -> anyhow::Result<()> {
+        let params = [self.param.val(body)?];
+        let mut results = vec![Val::Bool(false); self.results];
+        self.workload
+            .call_export(
+                &self.spec.interface,
+                &self.spec.function,
+                &params,
+                &mut results,
+            )
+            .await?;
+        outcome(&results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_invoker_spec() -> anyhow::Result<()> {
+        let mut invoker = WitInterface::from(INVOKER_INTERFACE);
+        invoker.config = config(&[
+            (SOURCE_KEY, "sqs"),
+            (
+                QUEUE_KEY,
+                "https://sqs.eu-west-1.amazonaws.com/123456789012/orders",
+            ),
+            (EXPORT_KEY, "acme:orders/worker@0.1.0#process"),
+            (CONCURRENCY_KEY, "8"),
+        ]);
+        let specs = InvokerSpec::from_host_interfaces(&[
+            WitInterface::from("wasi:http/incoming-handler"),
+            invoker,
+        ])?;
+        assert_eq!(
+            specs,
+            [InvokerSpec {
+                source: "sqs".to_string(),
+                queue: "https://sqs.eu-west-1.amazonaws.com/123456789012/orders".to_string(),
+                interface: "acme:orders/worker@0.1.0".to_string(),
+                function: "process".to_string(),
+                concurrency: 8,
+            }]
+        );
+
+        let valid = [
+            (SOURCE_KEY, "nats"),
+            (QUEUE_KEY, "ORDERS"),
+            (EXPORT_KEY, "acme:orders/worker#process"),
+        ];
+        assert_eq!(InvokerSpec::from_config(&config(&valid))?.concurrency, 1);
+        for (key, value) in [
+            (SOURCE_KEY, ""),
+            (EXPORT_KEY, "acme:orders/worker"),
+            (EXPORT_KEY, "acme:orders/worker#"),
+            (CONCURRENCY_KEY, "0"),
+            (CONCURRENCY_KEY, "1000"),
+        ] {
+            let mut invalid = config(&valid);
+            invalid.insert(key.to_string(), value.to_string());
+            assert!(
+                InvokerSpec::from_config(&invalid).is_err(),
+                "{key}={value} should be rejected"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_outcome() {
+        assert!(outcome(&[]).is_ok());
+        assert!(outcome(&[Val::Result(Ok(None))]).is_ok());
+        let err = outcome(&[Val::Result(Err(Some(Box::new(Val::String(
+            "out of stock".to_string(),
+        )))))])
+        .unwrap_err();
+        assert_eq!(err.to_string(), "function returned an error: out of stock");
+        assert!(outcome(&[Val::Result(Err(None))]).is_err());
+    }
+
+    #[test]
+    fn test_body_param() -> anyhow::Result<()> {
+        assert_eq!(
+            BodyParam::Bytes.val(b"hi")?,
+            Val::List(vec![Val::U8(b'h'), Val::U8(b'i')])
+        );
+        assert_eq!(BodyParam::String.val(b"hi")?, Val::String("hi".to_string()));
+        assert!(BodyParam::String.val(&[0xff]).is_err());
+        assert!(BodyParam::for_signature(&[], &[]).is_err());
+        assert_eq!(
+            BodyParam::for_signature(&[("body".to_string(), Type::String)], &[])?,
+            BodyParam::String
+        );
+        Ok(())
+    }
+}
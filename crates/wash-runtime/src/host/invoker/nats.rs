@@ -0,0 +1,99 @@
+//! Work queues on NATS JetStream.
+//!
+//! An invoker's `queue` names a stream, typically one with the work-queue retention
+//! policy. Messages are pulled through a durable consumer named after the workload, which
+//! is created on the stream if it doesn't exist. A nacked message is redelivered right
+//! away; limit redeliveries with the consumer's `max_deliver` when creating it up front.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context as _, anyhow};
+use async_nats::jetstream::consumer::{AckPolicy, PullConsumer, pull};
+use async_nats::jetstream::{self, AckKind};
+use futures::StreamExt as _;
+
+use crate::host::invoker::{QueueMessage, QueueSource, WorkQueue};
+
+/// How long a pull waits for messages when the stream is empty.
+const RECEIVE_WAIT: Duration = Duration::from_secs(20);
+
+/// Pulls messages from JetStream streams.
+#[derive(Clone)]
+pub struct NatsWorkQueues {
+    client: Arc<async_nats::Client>,
+}
+
+impl NatsWorkQueues {
+    pub fn new(client: Arc<async_nats::Client>) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait::async_trait]
+impl QueueSource for NatsWorkQueues {
+    async fn open(&self, queue: &str, consumer: &str) -> anyhow::Result<Box<dyn WorkQueue>> {
+        let consumer = jetstream::new((*self.client).clone())
+            .get_stream(queue)
+            .await
+            .with_context(|| format!("failed to get stream {queue}"))?
+            .get_or_create_consumer(
+                consumer,
+                pull::Config {
+                    durable_name: Some(consumer.to_string()),
+                    ack_policy: AckPolicy::Explicit,
+                    ..Default::default()
+                },
+            )
+            .await
+            .with_context(|| format!("failed to create consumer {consumer} on stream {queue}"))?;
+        Ok(Box::new(NatsWorkQueue { consumer }))
+    }
+}
+
+struct NatsWorkQueue {
+    consumer: PullConsumer,
+}
+
+#[async_trait::async_trait]
+impl WorkQueue for NatsWorkQueue {
+    async fn receive(&self, max: usize) -> anyhow::Result<Vec<Box<dyn QueueMessage>>> {
+        let mut batch = self
+            .consumer
+            .batch()
+            .max_messages(max)
+            .expires(RECEIVE_WAIT)
+            .messages()
+            .await
+            .map_err(|e| anyhow!("failed to pull messages: {e}"))?;
+        let mut messages: Vec<Box<dyn QueueMessage>> = Vec::with_capacity(max);
+        while let Some(message) = batch.next().await {
+            let message = message.map_err(|e| anyhow!("failed to pull messages: {e}"))?;
+            messages.push(Box::new(NatsMessage(message)));
+        }
+        Ok(messages)
+    }
+}
+
+struct NatsMessage(jetstream::Message);
+
+#[async_trait::async_trait]
+impl QueueMessage for NatsMessage {
+    fn body(&self) -> &[u8] {
+        &self.0.payload
+    }
+
+    async fn ack(self: Box<Self>) -> anyhow::Result<()> {
+        self.0
+            .ack()
+            .await
+            .map_err(|e| anyhow!("failed to ack message: {e}"))
+    }
+
+    async fn nack(self: Box<Self>) -> anyhow::Result<()> {
+        self.0
+            .ack_with(AckKind::Nak(None))
+            .await
+            .map_err(|e| anyhow!("failed to nack message: {e}"))
+    }
+}
@@ -0,0 +1,529 @@
+//! Work queues on Amazon SQS.
+//!
+//! An invoker's `queue` is the URL of an SQS queue, such as
+//! `https://sqs.eu-west-1.amazonaws.com/123456789012/orders`. Messages are received with
+//! long polling; an acked message is deleted, and a nacked message is made visible again
+//! right away. Once a message has been received more often than the `maxReceiveCount` of
+//! the queue's redrive policy, SQS moves it to the dead-letter queue.
+//!
+//! While a message is handled its visibility timeout is extended every third of the
+//! queue's `VisibilityTimeout`, so a handler running longer than the timeout doesn't get
+//! the message delivered again concurrently. SQS keeps a message invisible for at most 12
+//! hours after it was received, after which it's delivered again regardless.
+//!
+//! Requests are signed with the credentials from [`AwsCredentials::from_env`]. Queues that
+//! aren't on an `sqs.<region>.amazonaws.com` endpoint, such as those of a local emulator,
+//! are signed for the region set with [`SqsQueues::with_region`].
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context as _, bail};
+use aws_lc_rs::{digest, hmac};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+use serde_json::json;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::warn;
+
+use crate::host::dns::{ReqwestResolver, Resolver};
+use crate::host::invoker::{QueueMessage, QueueSource, WorkQueue};
+
+/// The most messages SQS returns per receive.
+const MAX_MESSAGES_PER_RECEIVE: usize = 10;
+/// How long a receive waits for messages when the queue is empty, the SQS maximum.
+const WAIT_TIME_SECONDS: u64 = 20;
+/// The content type of the SQS JSON protocol.
+const CONTENT_TYPE: &str = "application/x-amz-json-1.0";
+
+/// Credentials requests to AWS are signed with.
+#[derive(Clone)]
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    /// The token of temporary credentials
+    pub session_token: Option<String>,
+}
+
+impl AwsCredentials {
+    /// Reads the credentials from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, for
+    /// temporary credentials, `AWS_SESSION_TOKEN`.
+    ///
+    /// # Errors
+    /// Returns an error if the key ID or secret key isn't set.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        Ok(Self {
+            access_key_id: var("AWS_ACCESS_KEY_ID").context("AWS_ACCESS_KEY_ID is not set")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")
+                .context("AWS_SECRET_ACCESS_KEY is not set")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        })
+    }
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Pulls messages from SQS queues.
+#[derive(Debug, Clone)]
+pub struct SqsQueues {
+    client: reqwest::Client,
+    credentials: AwsCredentials,
+    region: Option<String>,
+}
+
+impl SqsQueues {
+    /// Creates a source that signs requests with `credentials`.
+    ///
+    /// # Errors
+    /// Returns an error if the HTTP client can't be created.
+    pub fn new(credentials: AwsCredentials) -> anyhow::Result<Self> {
+        let client = client_builder()
+            .build()
+            .context("failed to create HTTP client")?;
+        Ok(Self {
+            client,
+            credentials,
+            region: std::env::var("AWS_REGION").ok(),
+        })
+    }
+
+    /// Sets the region requests are signed for when it can't be read from the queue URL.
+    /// Defaults to `AWS_REGION`.
+    pub fn with_region(mut self, region: impl Into<String>) -> Self {
+        self.region = Some(region.into());
+        self
+    }
+
+    /// Resolves the host names of queues with `resolver` instead of the system's
+    /// resolver, see [`crate::host::dns`].
+    ///
+    /// # Errors
+    /// Returns an error if the HTTP client can't be created.
+    pub fn with_resolver(mut self, resolver: Arc<Resolver>) -> anyhow::Result<Self> {
+        self.client = client_builder()
+            .dns_resolver(Arc::new(ReqwestResolver(resolver)))
+            .build()
+            .context("failed to create HTTP client")?;
+        Ok(self)
+    }
+}
+
+fn client_builder() -> reqwest::ClientBuilder {
+    // Long polls hold the request open for up to the wait time
+    reqwest::Client::builder().timeout(Duration::from_secs(WAIT_TIME_SECONDS + 10))
+}
+
+#[async_trait::async_trait]
+impl QueueSource for SqsQueues {
+    async fn open(&self, queue: &str, _consumer: &str) -> anyhow::Result<Box<dyn WorkQueue>> {
+        let url = Url::parse(queue).with_context(|| format!("invalid SQS queue URL '{queue}'"))?;
+        let region = match region_of(&url) {
+            Some(region) => region.to_string(),
+            None => self
+                .region
+                .clone()
+                .with_context(|| format!("no region for SQS queue '{queue}'"))?,
+        };
+        let mut endpoint = url.clone();
+        endpoint.set_path("/");
+        endpoint.set_query(None);
+
+        let mut queue = SqsQueue {
+            client: self.client.clone(),
+            credentials: self.credentials.clone(),
+            region,
+            endpoint,
+            queue_url: queue.to_string(),
+            visibility_timeout: 0,
+        };
+        // Also fails early on a missing queue or bad credentials
+        let attributes = queue
+            .call(
+                "GetQueueAttributes",
+                json!({
+                    "QueueUrl": queue.queue_url,
+                    "AttributeNames": ["VisibilityTimeout"],
+                }),
+            )
+            .await?;
+        queue.visibility_timeout = visibility_timeout(&attributes)?;
+        Ok(Box::new(queue))
+    }
+}
+
+/// Reads the `VisibilityTimeout` of a queue, in seconds, from a `GetQueueAttributes`
+/// response.
+fn visibility_timeout(response: &[u8]) -> anyhow::Result<u64> {
+    let response: GetQueueAttributesResponse =
+        serde_json::from_slice(response).context("invalid SQS GetQueueAttributes response")?;
+    response
+        .attributes
+        .visibility_timeout
+        .parse()
+        .context("invalid SQS queue VisibilityTimeout")
+}
+
+/// Returns the region of a queue on an `sqs.<region>.amazonaws.com` endpoint.
+fn region_of(url: &Url) -> Option<&str> {
+    let host = url.host_str()?;
+    let rest = host.strip_prefix("sqs.")?;
+    let (region, domain) = rest.split_once('.')?;
+    domain.starts_with("amazonaws.").then_some(region)
+}
+
+#[derive(Clone)]
+struct SqsQueue {
+    client: reqwest::Client,
+    credentials: AwsCredentials,
+    region: String,
+    /// Where requests are sent, the root of the queue URL
+    endpoint: Url,
+    queue_url: String,
+    /// How long a received message stays invisible, in seconds
+    visibility_timeout: u64,
+}
+
+/// An error returned by SQS.
+#[derive(Deserialize)]
+struct SqsError {
+    #[serde(rename = "__type", default)]
+    kind: String,
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct GetQueueAttributesResponse {
+    #[serde(rename = "Attributes")]
+    attributes: QueueAttributes,
+}
+
+#[derive(Deserialize)]
+struct QueueAttributes {
+    #[serde(rename = "VisibilityTimeout")]
+    visibility_timeout: String,
+}
+
+#[derive(Deserialize)]
+struct ReceiveMessageResponse {
+    #[serde(rename = "Messages", default)]
+    messages: Vec<ReceivedMessage>,
+}
+
+#[derive(Deserialize)]
+struct ReceivedMessage {
+    #[serde(rename = "ReceiptHandle")]
+    receipt_handle: String,
+    #[serde(rename = "Body")]
+    body: String,
+}
+
+impl SqsQueue {
+    /// Calls an SQS action with the JSON protocol.
+    async fn call(&self, action: &str, body: serde_json::Value) -> anyhow::Result<Bytes> {
+        let body = serde_json::to_vec(&body)?;
+        let target = format!("AmazonSQS.{action}");
+        let mut headers = vec![
+            ("content-type".to_string(), CONTENT_TYPE.to_string()),
+            ("x-amz-target".to_string(), target),
+        ];
+        if let Some(token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token".to_string(), token.clone()));
+        }
+        let signed = sign(
+            &self.credentials,
+            &self.region,
+            "sqs",
+            "POST",
+            &self.endpoint,
+            &headers,
+            &body,
+            Utc::now(),
+        )?;
+
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .headers(signed)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("failed to send SQS {action} request"))?;
+        let status = response.status();
+        let bytes = response
+            .bytes()
+            .await
+            .with_context(|| format!("failed to read SQS {action} response"))?;
+        if !status.is_success() {
+            let error: Option<SqsError> = serde_json::from_slice(&bytes).ok();
+            match error {
+                Some(error) => bail!(
+                    "SQS {action} failed with {status}: {} {}",
+                    error.kind,
+                    error.message
+                ),
+                None => bail!("SQS {action} failed with {status}"),
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkQueue for SqsQueue {
+    async fn receive(&self, max: usize) -> anyhow::Result<Vec<Box<dyn QueueMessage>>> {
+        let response = self
+            .call(
+                "ReceiveMessage",
+                json!({
+                    "QueueUrl": self.queue_url,
+                    "MaxNumberOfMessages": max.clamp(1, MAX_MESSAGES_PER_RECEIVE),
+                    "WaitTimeSeconds": WAIT_TIME_SECONDS,
+                }),
+            )
+            .await?;
+        let response: ReceiveMessageResponse =
+            serde_json::from_slice(&response).context("invalid SQS ReceiveMessage response")?;
+        Ok(response
+            .messages
+            .into_iter()
+            .map(|message| {
+                let extending = (self.visibility_timeout > 0).then(|| {
+                    AbortOnDropHandle::new(tokio::spawn(
+                        self.clone()
+                            .extend_visibility(message.receipt_handle.clone()),
+                    ))
+                });
+                Box::new(SqsMessage {
+                    queue: self.clone(),
+                    receipt_handle: message.receipt_handle,
+                    body: message.body,
+                    extending,
+                }) as Box<dyn QueueMessage>
+            })
+            .collect())
+    }
+}
+
+impl SqsQueue {
+    /// Keeps a received message invisible until the task is aborted, by extending its
+    /// visibility timeout every third of the queue's.
+    async fn extend_visibility(self, receipt_handle: String) {
+        let period = Duration::from_secs(self.visibility_timeout) / 3;
+        let mut extend_at = tokio::time::Instant::now() + period;
+        loop {
+            tokio::time::sleep_until(extend_at).await;
+            extend_at += period;
+            let extended = self
+                .call(
+                    "ChangeMessageVisibility",
+                    json!({
+                        "QueueUrl": self.queue_url,
+                        "ReceiptHandle": receipt_handle,
+                        "VisibilityTimeout": self.visibility_timeout,
+                    }),
+                )
+                .await;
+            if let Err(e) = extended {
+                warn!(queue = self.queue_url, err = ?e, "failed to extend the visibility of an SQS message");
+                return;
+            }
+        }
+    }
+}
+
+struct SqsMessage {
+    queue: SqsQueue,
+    receipt_handle: String,
+    body: String,
+    /// Extends the message's visibility until it's acked, nacked or dropped
+    extending: Option<AbortOnDropHandle<()>>,
+}
+
+#[async_trait::async_trait]
+impl QueueMessage for SqsMessage {
+    fn body(&self) -> &[u8] {
+        self.body.as_bytes()
+    }
+
+    async fn ack(self: Box<Self>) -> anyhow::Result<()> {
+        drop(self.extending);
+        self.queue
+            .call(
+                "DeleteMessage",
+                json!({
+                    "QueueUrl": self.queue.queue_url,
+                    "ReceiptHandle": self.receipt_handle,
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn nack(self: Box<Self>) -> anyhow::Result<()> {
+        drop(self.extending);
+        self.queue
+            .call(
+                "ChangeMessageVisibility",
+                json!({
+                    "QueueUrl": self.queue.queue_url,
+                    "ReceiptHandle": self.receipt_handle,
+                    "VisibilityTimeout": 0,
+                }),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+/// Signs a request with AWS Signature Version 4, returning its headers along with the
+/// `host`, `x-amz-date` and `authorization` headers.
+///
+/// `headers` must have lowercase names.
+#[allow(clippy::too_many_arguments)]
+fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    url: &Url,
+    headers: &[(String, String)],
+    body: &[u8],
+    now: DateTime<Utc>,
+) -> anyhow::Result<reqwest::header::HeaderMap> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = &amz_date[..8];
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => bail!("URL '{url}' has no host"),
+    };
+
+    let mut headers = headers.to_vec();
+    headers.push(("host".to_string(), host));
+    headers.push(("x-amz-date".to_string(), amz_date.clone()));
+    headers.sort();
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let mut query: Vec<&str> = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .collect();
+    query.sort_unstable();
+
+    let canonical_request = format!(
+        "{method}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{}",
+        url.path(),
+        query.join("&"),
+        hex(digest::digest(&digest::SHA256, body).as_ref()),
+    );
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(digest::digest(&digest::SHA256, canonical_request.as_bytes()).as_ref()),
+    );
+
+    let hmac_sign = |key: &[u8], data: &str| {
+        hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+    };
+    let secret = format!("AWS4{}", credentials.secret_access_key);
+    let key = hmac_sign(secret.as_bytes(), date);
+    let key = hmac_sign(key.as_ref(), region);
+    let key = hmac_sign(key.as_ref(), service);
+    let key = hmac_sign(key.as_ref(), "aws4_request");
+    let signature = hex(hmac_sign(key.as_ref(), &string_to_sign).as_ref());
+
+    headers.push((
+        "authorization".to_string(),
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            credentials.access_key_id
+        ),
+    ));
+    let mut map = reqwest::header::HeaderMap::new();
+    for (name, value) in headers {
+        map.insert(
+            reqwest::header::HeaderName::from_bytes(name.as_bytes())?,
+            value.parse()?,
+        );
+    }
+    Ok(map)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign() -> anyhow::Result<()> {
+        // The example from the AWS Signature Version 4 documentation
+        let credentials = AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        };
+        let url = Url::parse("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")?;
+        let headers = [(
+            "content-type".to_string(),
+            "application/x-www-form-urlencoded; charset=utf-8".to_string(),
+        )];
+        let now = DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")?.to_utc();
+        let signed = sign(
+            &credentials,
+            "us-east-1",
+            "iam",
+            "GET",
+            &url,
+            &headers,
+            b"",
+            now,
+        )?;
+        assert_eq!(
+            signed["authorization"],
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/iam/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+        );
+        assert_eq!(signed["x-amz-date"], "20150830T123600Z");
+        Ok(())
+    }
+
+    #[test]
+    fn test_visibility_timeout() -> anyhow::Result<()> {
+        let response = br#"{"Attributes":{"VisibilityTimeout":"45"}}"#;
+        assert_eq!(visibility_timeout(response)?, 45);
+        assert!(visibility_timeout(br#"{"Attributes":{}}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_region_of() -> anyhow::Result<()> {
+        let url = Url::parse("https://sqs.eu-west-1.amazonaws.com/123456789012/orders")?;
+        assert_eq!(region_of(&url), Some("eu-west-1"));
+        let url = Url::parse("http://localhost:4566/000000000000/orders")?;
+        assert_eq!(region_of(&url), None);
+        Ok(())
+    }
+}
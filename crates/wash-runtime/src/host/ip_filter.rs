@@ -0,0 +1,152 @@
+//! Source address filtering for the [HTTP server](crate::host::http), for basic perimeter
+//! control on hosts exposed to networks not everyone on should reach them from.
+//!
+//! An [`IpFilter`] has address ranges to allow and to deny, written as CIDR ranges like
+//! `10.0.0.0/8` or single addresses. An address in a denied range is rejected, and so is
+//! one in no allowed range, unless the filter allows none and only denies. Filters apply:
+//!
+//! - to a whole listener, set with [`crate::host::http::HttpServer::with_ip_filter`]:
+//!   connections from rejected addresses are closed once accepted, before any request is
+//!   read
+//! - to a virtual host, set in its workload's `wasi:http/incoming-handler` config with
+//!   `allowed-ips` and `denied-ips`, comma separated: requests from rejected addresses are
+//!   answered with `403 Forbidden` before any of its filters or components run. Requests
+//!   other workloads on the host send to the virtual host have no source address and
+//!   aren't filtered.
+//!
+//! The address checked is the connection's, or the one named by its PROXY protocol header,
+//! see [`crate::host::proxy_protocol`]. `forwarded` and `x-forwarded-for` headers are
+//! never consulted, so behind a load balancer only the PROXY protocol tells clients apart.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+use anyhow::Context as _;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use crate::engine::workload::ResolvedWorkload;
+use crate::host::forwarded::IpRange;
+use crate::wit::WitInterface;
+
+/// The incoming handler config key listing the ranges a virtual host allows.
+const ALLOWED_IPS_KEY: &str = "allowed-ips";
+/// The incoming handler config key listing the ranges a virtual host denies.
+const DENIED_IPS_KEY: &str = "denied-ips";
+
+/// The address a request's connection came from, which the HTTP server puts in the
+/// request's extensions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct ClientAddr(pub(crate) SocketAddr);
+
+/// The source addresses a listener or virtual host accepts, see the [module docs](self).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IpFilter {
+    /// The ranges addresses must be in, or any address if empty
+    pub allow: Vec<IpRange>,
+    /// The ranges rejected even when allowed
+    pub deny: Vec<IpRange>,
+}
+
+impl IpFilter {
+    /// Whether the filter lets every address through.
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Whether the filter lets `ip` through.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        !self.deny.iter().any(|range| range.contains(ip))
+            && (self.allow.is_empty() || self.allow.iter().any(|range| range.contains(ip)))
+    }
+
+    /// Reads the IP filter of a workload's virtual host.
+    ///
+    /// # Returns
+    /// The filter, or `None` if the workload doesn't filter addresses.
+    ///
+    /// # Errors
+    /// Returns an error if a range is invalid.
+    pub(crate) fn from_workload(workload: &ResolvedWorkload) -> anyhow::Result<Option<Self>> {
+        let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+        match workload
+            .host_interfaces()
+            .iter()
+            .find(|iface| iface.contains(&incoming_handler_interface))
+        {
+            Some(iface) => Self::from_config(&iface.config),
+            None => Ok(None),
+        }
+    }
+
+    fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let ranges = |key: &str| -> anyhow::Result<Vec<IpRange>> {
+            config
+                .get(key)
+                .into_iter()
+                .flat_map(|ranges| ranges.split(','))
+                .map(str::trim)
+                .filter(|range| !range.is_empty())
+                .map(|range| range.parse().with_context(|| format!("invalid {key}")))
+                .collect()
+        };
+        let filter = Self {
+            allow: ranges(ALLOWED_IPS_KEY)?,
+            deny: ranges(DENIED_IPS_KEY)?,
+        };
+        Ok((!filter.is_empty()).then_some(filter))
+    }
+}
+
+/// The response to a request from an address a virtual host doesn't accept.
+pub(crate) fn forbidden() -> hyper::Response<HyperOutgoingBody> {
+    hyper::Response::builder()
+        .status(403)
+        .body(HyperOutgoingBody::default())
+        .expect("failed to build 403 response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() -> anyhow::Result<()> {
+        let filter = IpFilter {
+            allow: vec!["10.0.0.0/8".parse()?, "fd00::/8".parse()?],
+            deny: vec!["10.0.66.0/24".parse()?],
+        };
+        assert!(filter.allows("10.1.2.3".parse()?));
+        assert!(filter.allows("fd00::7".parse()?));
+        assert!(!filter.allows("10.0.66.1".parse()?));
+        assert!(!filter.allows("203.0.113.7".parse()?));
+
+        // Without allowed ranges, every address that isn't denied is allowed
+        let filter = IpFilter {
+            allow: vec![],
+            deny: vec!["203.0.113.0/24".parse()?],
+        };
+        assert!(filter.allows("198.51.100.1".parse()?));
+        assert!(!filter.allows("::ffff:203.0.113.9".parse()?));
+        assert!(IpFilter::default().allows("203.0.113.9".parse()?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_config() -> anyhow::Result<()> {
+        let config = HashMap::from([
+            (
+                ALLOWED_IPS_KEY.to_string(),
+                "10.0.0.0/8, 192.168.1.4".to_string(),
+            ),
+            (DENIED_IPS_KEY.to_string(), "10.0.66.0/24".to_string()),
+        ]);
+        let filter = IpFilter::from_config(&config)?.expect("config sets ranges");
+        assert_eq!(filter.allow.len(), 2);
+        assert_eq!(filter.deny.len(), 1);
+
+        assert_eq!(IpFilter::from_config(&HashMap::new())?, None);
+        let invalid = HashMap::from([(DENIED_IPS_KEY.to_string(), "10.0.0.0/40".to_string())]);
+        assert!(IpFilter::from_config(&invalid).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,629 @@
+//! JWT validation for the [HTTP server](crate::host::http), so components receive
+//! authenticated requests without each bundling a JWT library.
+//!
+//! A workload turns validation on for its virtual host in its `wasi:http/incoming-handler`
+//! config:
+//!
+//! - `jwt-jwks-url` is the JSON Web Key Set the tokens are signed with, e.g.
+//!   `https://issuer.example.com/.well-known/jwks.json`
+//! - `jwt-issuers` and `jwt-audiences` list the accepted `iss` and `aud` values, comma
+//!   separated. Any are accepted when left out.
+//! - `jwt-required-claims` lists claims every token must have
+//! - `jwt-forward-claims` lists claims forwarded to the workload, each as an
+//!   `X-Jwt-Claim-<name>` header
+//!
+//! Requests need an `Authorization: Bearer` token signed with RS*, PS*, ES256, ES384 or
+//! EdDSA, that hasn't expired. Requests without a valid token are answered with
+//! `401 Unauthorized`, and tokens missing a required claim with `403 Forbidden`. The
+//! workload gets the token's payload in `X-Jwt-Payload`, as base64url JSON, and any
+//! `X-Jwt-` headers sent by the client are removed first.
+//!
+//! Key sets are cached for five minutes, and fetched again sooner when a token is signed
+//! with a key the cached set doesn't have.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as _, bail, ensure};
+use aws_lc_rs::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use base64::Engine as _;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use http_body_util::BodyExt as _;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::{StatusCode, Uri};
+use serde::Deserialize;
+use tracing::{debug, warn};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::OutgoingRequestConfig;
+
+use crate::engine::workload::ResolvedWorkload;
+use crate::wit::WitInterface;
+
+/// How long a fetched key set is used before it's fetched again.
+const JWKS_TTL: Duration = Duration::from_secs(300);
+/// How soon a key set may be fetched again for a token signed with an unknown key.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+const JWKS_TIMEOUT: Duration = Duration::from_secs(10);
+/// How far clocks may be apart when checking `exp` and `nbf`.
+const LEEWAY_SECS: u64 = 60;
+
+/// The header the token's payload is forwarded in.
+const PAYLOAD_HEADER: &str = "x-jwt-payload";
+/// The prefix of headers set from a token, which clients can't send.
+const HEADER_PREFIX: &str = "x-jwt-";
+
+static JWKS: LazyLock<JwksCache> = LazyLock::new(JwksCache::default);
+
+/// How a virtual host validates tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct JwtConfig {
+    jwks_url: Uri,
+    issuers: Vec<String>,
+    audiences: Vec<String>,
+    required_claims: Vec<String>,
+    forward_claims: Vec<(String, HeaderName)>,
+}
+
+impl JwtConfig {
+    /// Reads the JWT config of a workload.
+    ///
+    /// # Returns
+    /// The config, or `None` if the workload doesn't validate tokens.
+    ///
+    /// # Errors
+    /// Returns an error if the JWKS URL isn't an `http` or `https` URL, or a forwarded
+    /// claim can't be a header name.
+    pub(crate) fn from_workload(workload: &ResolvedWorkload) -> anyhow::Result<Option<Self>> {
+        let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+        match workload
+            .host_interfaces()
+            .iter()
+            .find(|iface| iface.contains(&incoming_handler_interface))
+        {
+            Some(iface) => Self::from_config(&iface.config),
+            None => Ok(None),
+        }
+    }
+
+    fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Option<Self>> {
+        let Some(url) = config.get("jwt-jwks-url") else {
+            return Ok(None);
+        };
+        let jwks_url: Uri = url
+            .parse()
+            .with_context(|| format!("invalid jwt-jwks-url '{url}'"))?;
+        ensure!(
+            matches!(jwks_url.scheme_str(), Some("http" | "https")) && jwks_url.host().is_some(),
+            "jwt-jwks-url '{url}' must be an http:// or https:// URL"
+        );
+        let list = |key: &str| -> Vec<String> {
+            config
+                .get(key)
+                .map(|values| {
+                    values
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|value| !value.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let forward_claims = list("jwt-forward-claims")
+            .into_iter()
+            .map(|claim| {
+                let header =
+                    HeaderName::from_bytes(format!("{HEADER_PREFIX}claim-{claim}").as_bytes())
+                        .with_context(|| {
+                            format!("claim '{claim}' can't be forwarded in a header")
+                        })?;
+                Ok((claim, header))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(Self {
+            jwks_url,
+            issuers: list("jwt-issuers"),
+            audiences: list("jwt-audiences"),
+            required_claims: list("jwt-required-claims"),
+            forward_claims,
+        }))
+    }
+}
+
+/// Why a request was turned away.
+#[derive(Debug, PartialEq, Eq)]
+enum Rejection {
+    /// The token is missing or invalid
+    Unauthorized(String),
+    /// The token is valid but lacks a required claim
+    Forbidden(String),
+}
+
+/// Removes the `X-Jwt-` headers from a request, which only [`authenticate`] may set.
+pub(crate) fn strip_claims(headers: &mut HeaderMap) {
+    let spoofed: Vec<HeaderName> = headers
+        .keys()
+        .filter(|name| name.as_str().starts_with(HEADER_PREFIX))
+        .cloned()
+        .collect();
+    for name in spoofed {
+        headers.remove(name);
+    }
+}
+
+/// Validates the token of a request, replacing its `X-Jwt-` headers with the token's.
+///
+/// # Returns
+/// The response to answer the request with if it isn't allowed through.
+pub(crate) async fn authenticate(
+    config: &JwtConfig,
+    headers: &mut HeaderMap,
+) -> Option<hyper::Response<HyperOutgoingBody>> {
+    strip_claims(headers);
+
+    let Some(token) = bearer_token(headers) else {
+        return Some(rejection(Rejection::Unauthorized(
+            "missing bearer token".to_string(),
+        )));
+    };
+    let token = match Token::parse(&token) {
+        Ok(token) => token,
+        Err(e) => return Some(rejection(Rejection::Unauthorized(e.to_string()))),
+    };
+
+    let keys = match JWKS.keys(&config.jwks_url, token.kid.as_deref()).await {
+        Ok(keys) => keys,
+        Err(e) => {
+            warn!(err = ?e, url = %config.jwks_url, "failed to fetch JSON Web Key Set");
+            return Some(status(StatusCode::SERVICE_UNAVAILABLE));
+        }
+    };
+    let claims = match token.validate(config, &keys, unix_now()) {
+        Ok(claims) => claims,
+        Err(rejected) => return Some(rejection(rejected)),
+    };
+
+    if let Ok(payload) = HeaderValue::from_str(&token.payload) {
+        headers.insert(HeaderName::from_static(PAYLOAD_HEADER), payload);
+    }
+    for (claim, header) in &config.forward_claims {
+        let value = match claims.get(claim) {
+            Some(serde_json::Value::String(value)) => value.clone(),
+            Some(serde_json::Value::Null) | None => continue,
+            Some(value) => value.to_string(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(header.clone(), value);
+        }
+    }
+    None
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| token.trim().to_string())
+}
+
+/// A compact serialized JWT, split and decoded.
+struct Token {
+    alg: String,
+    kid: Option<String>,
+    /// The base64url payload, as sent
+    payload: String,
+    claims: serde_json::Map<String, serde_json::Value>,
+    /// The header and payload the signature is over
+    signing_input: String,
+    signature: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct TokenHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+impl Token {
+    fn parse(token: &str) -> anyhow::Result<Self> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("malformed token");
+        };
+        let token_header: TokenHeader = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(header)
+                .context("malformed token header")?,
+        )
+        .context("malformed token header")?;
+        let claims = serde_json::from_slice(
+            &URL_SAFE_NO_PAD
+                .decode(payload)
+                .context("malformed token payload")?,
+        )
+        .context("malformed token payload")?;
+        Ok(Self {
+            alg: token_header.alg,
+            kid: token_header.kid,
+            payload: payload.to_string(),
+            claims,
+            signing_input: format!("{header}.{payload}"),
+            signature: URL_SAFE_NO_PAD
+                .decode(signature)
+                .context("malformed token signature")?,
+        })
+    }
+
+    /// Checks the signature and claims of the token.
+    ///
+    /// # Returns
+    /// The token's claims.
+    fn validate(
+        &self,
+        config: &JwtConfig,
+        keys: &[Jwk],
+        now: u64,
+    ) -> Result<&serde_json::Map<String, serde_json::Value>, Rejection> {
+        let unauthorized = |reason: &str| Rejection::Unauthorized(reason.to_string());
+        let verified = keys
+            .iter()
+            .filter(|key| self.kid.is_none() || key.kid == self.kid)
+            .filter(|key| key.alg.as_ref().is_none_or(|alg| *alg == self.alg))
+            .any(|key| key.verify(&self.alg, self.signing_input.as_bytes(), &self.signature));
+        if !verified {
+            return Err(unauthorized("invalid signature"));
+        }
+
+        let claims = &self.claims;
+        let exp = claims
+            .get("exp")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or_else(|| unauthorized("token has no expiry"))?;
+        if exp + LEEWAY_SECS <= now {
+            return Err(unauthorized("token has expired"));
+        }
+        if let Some(nbf) = claims.get("nbf").and_then(serde_json::Value::as_u64)
+            && nbf > now + LEEWAY_SECS
+        {
+            return Err(unauthorized("token isn't valid yet"));
+        }
+        if !config.issuers.is_empty() {
+            let issuer = claims.get("iss").and_then(serde_json::Value::as_str);
+            if !config
+                .issuers
+                .iter()
+                .any(|iss| Some(iss.as_str()) == issuer)
+            {
+                return Err(unauthorized("token has an unaccepted issuer"));
+            }
+        }
+        if !config.audiences.is_empty() {
+            let audiences: Vec<&str> = match claims.get("aud") {
+                Some(serde_json::Value::String(aud)) => vec![aud.as_str()],
+                Some(serde_json::Value::Array(auds)) => {
+                    auds.iter().filter_map(serde_json::Value::as_str).collect()
+                }
+                _ => Vec::new(),
+            };
+            if !config
+                .audiences
+                .iter()
+                .any(|aud| audiences.contains(&aud.as_str()))
+            {
+                return Err(unauthorized("token has no accepted audience"));
+            }
+        }
+        for claim in &config.required_claims {
+            if claims.get(claim).is_none_or(serde_json::Value::is_null) {
+                return Err(Rejection::Forbidden(format!(
+                    "token has no '{claim}' claim"
+                )));
+            }
+        }
+        Ok(claims)
+    }
+}
+
+/// A public key from a JSON Web Key Set.
+#[derive(Debug, Clone, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: Option<String>,
+    alg: Option<String>,
+    crv: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+impl Jwk {
+    /// Whether `signature` is a valid signature of `message` by this key, with `alg`.
+    fn verify(&self, alg: &str, message: &[u8], signature: &[u8]) -> bool {
+        let decode = |value: &Option<String>| {
+            value
+                .as_deref()
+                .and_then(|value| URL_SAFE_NO_PAD.decode(value).ok())
+        };
+        match (self.kty.as_str(), alg) {
+            ("RSA", "RS256" | "RS384" | "RS512" | "PS256" | "PS384" | "PS512") => {
+                let (Some(n), Some(e)) = (decode(&self.n), decode(&self.e)) else {
+                    return false;
+                };
+                let params = match alg {
+                    "RS256" => &signature::RSA_PKCS1_2048_8192_SHA256,
+                    "RS384" => &signature::RSA_PKCS1_2048_8192_SHA384,
+                    "RS512" => &signature::RSA_PKCS1_2048_8192_SHA512,
+                    "PS256" => &signature::RSA_PSS_2048_8192_SHA256,
+                    "PS384" => &signature::RSA_PSS_2048_8192_SHA384,
+                    _ => &signature::RSA_PSS_2048_8192_SHA512,
+                };
+                RsaPublicKeyComponents { n: &n, e: &e }
+                    .verify(params, message, signature)
+                    .is_ok()
+            }
+            ("EC", "ES256" | "ES384") => {
+                let (Some(x), Some(y)) = (decode(&self.x), decode(&self.y)) else {
+                    return false;
+                };
+                let algorithm = match (alg, self.crv.as_deref()) {
+                    ("ES256", Some("P-256")) => &signature::ECDSA_P256_SHA256_FIXED,
+                    ("ES384", Some("P-384")) => &signature::ECDSA_P384_SHA384_FIXED,
+                    _ => return false,
+                };
+                // An uncompressed point
+                let point = [&[4u8][..], &x, &y].concat();
+                UnparsedPublicKey::new(algorithm, point)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            ("OKP", "EdDSA") if self.crv.as_deref() == Some("Ed25519") => {
+                let Some(x) = decode(&self.x) else {
+                    return false;
+                };
+                UnparsedPublicKey::new(&signature::ED25519, x)
+                    .verify(message, signature)
+                    .is_ok()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A fetched key set with when it was fetched.
+type FetchedKeys = (Instant, Arc<[Jwk]>);
+
+/// Fetched key sets by URL.
+#[derive(Default)]
+struct JwksCache {
+    sets: Mutex<HashMap<Uri, FetchedKeys>>,
+}
+
+impl JwksCache {
+    /// Returns the key set at `url`, fetching it if the cached set is stale or doesn't
+    /// have the key `kid`.
+    async fn keys(&self, url: &Uri, kid: Option<&str>) -> anyhow::Result<Arc<[Jwk]>> {
+        let cached = self
+            .sets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(url)
+            .cloned();
+        if let Some((fetched, keys)) = cached {
+            let has_key =
+                kid.is_none_or(|kid| keys.iter().any(|key| key.kid.as_deref() == Some(kid)));
+            let age = fetched.elapsed();
+            if age < JWKS_TTL && (has_key || age < JWKS_MIN_REFRESH) {
+                return Ok(keys);
+            }
+        }
+
+        debug!(url = %url, "fetching JSON Web Key Set");
+        let keys: Arc<[Jwk]> = fetch_jwks(url).await?.into();
+        self.sets
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(url.clone(), (Instant::now(), keys.clone()));
+        Ok(keys)
+    }
+}
+
+async fn fetch_jwks(url: &Uri) -> anyhow::Result<Vec<Jwk>> {
+    let request = hyper::Request::get(url.clone())
+        .header(header::ACCEPT, "application/json")
+        .header(
+            header::HOST,
+            url.authority().map_or("", |authority| authority.as_str()),
+        )
+        .body(HyperOutgoingBody::default())
+        .context("failed to build JWKS request")?;
+    let config = OutgoingRequestConfig {
+        use_tls: url.scheme_str() == Some("https"),
+        connect_timeout: JWKS_TIMEOUT,
+        first_byte_timeout: JWKS_TIMEOUT,
+        between_bytes_timeout: JWKS_TIMEOUT,
+    };
+    let incoming = wasmtime_wasi_http::types::default_send_request_handler(request, config)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to fetch JWKS: {e:?}"))?;
+    // The worker drives the connection until the body is read
+    let _worker = incoming.worker;
+    ensure!(
+        incoming.resp.status().is_success(),
+        "JWKS request failed with status {}",
+        incoming.resp.status()
+    );
+    let body = incoming
+        .resp
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to read JWKS: {e:?}"))?
+        .to_bytes();
+    let set: JwkSet = serde_json::from_slice(&body).context("invalid JWKS")?;
+    Ok(set.keys)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+fn rejection(rejected: Rejection) -> hyper::Response<HyperOutgoingBody> {
+    let (status_code, error) = match &rejected {
+        Rejection::Unauthorized(reason) => {
+            debug!(reason, "rejecting request without a valid token");
+            (StatusCode::UNAUTHORIZED, "invalid_token")
+        }
+        Rejection::Forbidden(reason) => {
+            debug!(reason, "rejecting request with insufficient claims");
+            (StatusCode::FORBIDDEN, "insufficient_scope")
+        }
+    };
+    let mut response = status(status_code);
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_str(&format!("Bearer error=\"{error}\""))
+            .expect("error codes are valid header values"),
+    );
+    response
+}
+
+fn status(status: StatusCode) -> hyper::Response<HyperOutgoingBody> {
+    hyper::Response::builder()
+        .status(status)
+        .body(HyperOutgoingBody::default())
+        .expect("failed to build JWT rejection response")
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_lc_rs::signature::{Ed25519KeyPair, KeyPair as _};
+
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> anyhow::Result<JwtConfig> {
+        let config: HashMap<String, String> = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        JwtConfig::from_config(&config)?.context("expected a JWT config")
+    }
+
+    fn sign(key: &Ed25519KeyPair, claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"EdDSA","kid":"k1"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signature = key.sign(format!("{header}.{payload}").as_bytes());
+        format!(
+            "{header}.{payload}.{}",
+            URL_SAFE_NO_PAD.encode(signature.as_ref())
+        )
+    }
+
+    #[test]
+    fn test_config() -> anyhow::Result<()> {
+        assert_eq!(JwtConfig::from_config(&HashMap::new())?, None);
+        let config = config(&[
+            ("jwt-jwks-url", "https://issuer.example.com/jwks.json"),
+            ("jwt-audiences", "api, web"),
+            ("jwt-forward-claims", "sub"),
+        ])?;
+        assert_eq!(config.audiences, ["api", "web"]);
+        assert_eq!(config.forward_claims[0].1, "x-jwt-claim-sub");
+        assert!(self::config(&[("jwt-jwks-url", "issuer.example.com")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate() -> anyhow::Result<()> {
+        let rng = aws_lc_rs::rand::SystemRandom::new();
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&rng).map_err(|_| anyhow::anyhow!("keygen"))?;
+        let key = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).map_err(|_| anyhow::anyhow!("key"))?;
+        let jwk = Jwk {
+            kty: "OKP".to_string(),
+            kid: Some("k1".to_string()),
+            alg: None,
+            crv: Some("Ed25519".to_string()),
+            n: None,
+            e: None,
+            x: Some(URL_SAFE_NO_PAD.encode(key.public_key().as_ref())),
+            y: None,
+        };
+        let config = config(&[
+            ("jwt-jwks-url", "https://issuer.example.com/jwks.json"),
+            ("jwt-issuers", "https://issuer.example.com"),
+            ("jwt-audiences", "api"),
+            ("jwt-required-claims", "email"),
+        ])?;
+        let now = 1_700_000_000;
+        let claims = serde_json::json!({
+            "iss": "https://issuer.example.com",
+            "aud": ["api", "web"],
+            "exp": now + 300,
+            "sub": "user-1",
+            "email": "user@example.com",
+        });
+
+        let token = Token::parse(&sign(&key, claims.clone()))?;
+        let validated = token.validate(&config, std::slice::from_ref(&jwk), now);
+        assert_eq!(
+            validated.map(|claims| claims["sub"].clone()),
+            Ok("user-1".into())
+        );
+
+        // A token past its expiry, beyond the leeway
+        assert!(matches!(
+            token.validate(&config, std::slice::from_ref(&jwk), now + 600),
+            Err(Rejection::Unauthorized(_))
+        ));
+
+        // A tampered payload
+        let mut tampered = Token::parse(&sign(&key, claims.clone()))?;
+        tampered.signing_input.push('x');
+        assert!(
+            tampered
+                .validate(&config, std::slice::from_ref(&jwk), now)
+                .is_err()
+        );
+
+        let mut no_email = claims.clone();
+        no_email["email"] = serde_json::Value::Null;
+        let token = Token::parse(&sign(&key, no_email))?;
+        assert!(matches!(
+            token.validate(&config, std::slice::from_ref(&jwk), now),
+            Err(Rejection::Forbidden(_))
+        ));
+
+        let mut other_audience = claims;
+        other_audience["aud"] = "web".into();
+        let token = Token::parse(&sign(&key, other_audience))?;
+        assert!(token.validate(&config, &[jwk], now).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer abc.def.ghi"),
+        );
+        assert_eq!(bearer_token(&headers).as_deref(), Some("abc.def.ghi"));
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic dXNlcg=="),
+        );
+        assert_eq!(bearer_token(&headers), None);
+    }
+}
@@ -0,0 +1,257 @@
+//! Lints for workloads: configurations that are valid, but likely not what was meant.
+//!
+//! Unlike [`HostApi::workload_validate`](crate::host::HostApi::workload_validate), which
+//! reports what keeps a workload from starting, the lint pass reports what's suspicious
+//! about a workload as warnings that don't block it, each with a [`Lint`] code:
+//!
+//! - [`Lint::EmptyPool`]: a component with a `pool_size` of 0
+//! - [`Lint::MemoryBelowMinimum`]: a `memory_limit_mb` below the initial size of the
+//!   component's memories, so no instance fits in it
+//! - [`Lint::UnusedInterface`]: a host interface none of the workload's components
+//!   import or export, so its config has no effect
+//! - [`Lint::DuplicateVirtualHost`]: a virtual host served by another of the workload's
+//!   interfaces, or by a workload already running on the host
+
+use std::collections::HashMap;
+
+use crate::types::{Workload, WorkloadWarning};
+use crate::wit::{WitInterface, WitWorld};
+
+/// A suspicious configuration the lint pass warns about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Lint {
+    /// A component's `pool_size` is 0, which keeps at most one idle instance warm
+    EmptyPool,
+    /// A component's `memory_limit_mb` is below the initial size of its memories
+    MemoryBelowMinimum,
+    /// A host interface no component imports or exports
+    UnusedInterface,
+    /// A virtual host served by another interface or workload
+    DuplicateVirtualHost,
+}
+
+impl Lint {
+    /// The stable, machine-readable code of the lint, e.g. `empty_pool`.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::EmptyPool => "empty_pool",
+            Self::MemoryBelowMinimum => "memory_below_minimum",
+            Self::UnusedInterface => "unused_interface",
+            Self::DuplicateVirtualHost => "duplicate_virtual_host",
+        }
+    }
+}
+
+/// What compiling a component tells the lint pass about it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CompiledComponent {
+    pub world: WitWorld,
+    /// The initial size of the component's largest memory
+    pub min_memory_bytes: u64,
+}
+
+/// Lints a workload.
+///
+/// # Arguments
+/// * `workload` - The workload to lint
+/// * `compiled` - What compiling the workload's components, followed by its service,
+///   tells about them, `None` for those that don't compile
+/// * `served_hosts` - The virtual hosts of the workloads running on the host, with the
+///   ID of the workload serving each
+///
+/// # Returns
+/// A warning for each suspicious configuration, in the order of the workload's spec.
+pub(crate) fn lint(
+    workload: &Workload,
+    compiled: &[Option<CompiledComponent>],
+    served_hosts: &HashMap<String, String>,
+) -> Vec<WorkloadWarning> {
+    let mut warnings = Vec::new();
+    let mut warn = |location: String, lint: Lint, message: String| {
+        warnings.push(WorkloadWarning {
+            location,
+            code: lint.code().to_string(),
+            message,
+        });
+    };
+
+    let targets = workload
+        .components
+        .iter()
+        .enumerate()
+        .map(|(i, component)| (format!("components[{i}]"), &component.local_resources))
+        .chain(
+            workload
+                .service
+                .iter()
+                .map(|service| ("service".to_string(), &service.local_resources)),
+        );
+    for (i, (location, resources)) in targets.enumerate() {
+        if workload
+            .components
+            .get(i)
+            .is_some_and(|component| component.pool_size == 0)
+        {
+            warn(
+                location.clone(),
+                Lint::EmptyPool,
+                "pool_size is 0, so at most one idle instance is kept warm".to_string(),
+            );
+        }
+        if let Ok(limit_mb @ 1..) = u64::try_from(resources.memory_limit_mb)
+            && let Some(Some(compiled)) = compiled.get(i)
+            && compiled.min_memory_bytes > limit_mb * 1024 * 1024
+        {
+            warn(
+                location,
+                Lint::MemoryBelowMinimum,
+                format!(
+                    "memory_limit_mb is {limit_mb}, but the component's memories start at \
+                     {} bytes, so it can't be instantiated",
+                    compiled.min_memory_bytes
+                ),
+            );
+        }
+    }
+
+    // Whether an interface is used can't be told without every component's world
+    if compiled.iter().all(Option::is_some) {
+        for (i, interface) in workload.host_interfaces.iter().enumerate() {
+            let used = compiled.iter().flatten().any(|component| {
+                component
+                    .world
+                    .imports
+                    .iter()
+                    .chain(&component.world.exports)
+                    .any(|used| overlaps(interface, used))
+            });
+            if !used {
+                warn(
+                    format!("host_interfaces[{i}]"),
+                    Lint::UnusedInterface,
+                    format!("no component imports or exports {interface}"),
+                );
+            }
+        }
+    }
+
+    let mut hosts: HashMap<&str, usize> = HashMap::new();
+    for (i, interface) in workload.host_interfaces.iter().enumerate() {
+        let Some(host) = interface_virtual_host(interface) else {
+            continue;
+        };
+        if let Some(first) = hosts.get(host) {
+            warn(
+                format!("host_interfaces[{i}]"),
+                Lint::DuplicateVirtualHost,
+                format!(
+                    "virtual host '{host}' is also set on host_interfaces[{first}], only one \
+                     of them is routed to"
+                ),
+            );
+            continue;
+        }
+        hosts.insert(host, i);
+        if let Some(workload_id) = served_hosts.get(host) {
+            warn(
+                format!("host_interfaces[{i}]"),
+                Lint::DuplicateVirtualHost,
+                format!(
+                    "virtual host '{host}' is already served by workload '{workload_id}', \
+                     requests are routed to the workload started last unless traffic is split"
+                ),
+            );
+        }
+    }
+    warnings
+}
+
+/// Returns the virtual host a workload serves, from the first `host` config on its
+/// `wasi:http/incoming-handler` interfaces.
+pub(crate) fn virtual_host(interfaces: &[WitInterface]) -> Option<&str> {
+    interfaces.iter().find_map(interface_virtual_host)
+}
+
+fn interface_virtual_host(interface: &WitInterface) -> Option<&str> {
+    let incoming_handler = WitInterface::from("wasi:http/incoming-handler");
+    if !interface.contains(&incoming_handler) {
+        return None;
+    }
+    interface.config.get("host").map(String::as_str)
+}
+
+/// Whether a component importing or exporting `used` uses any of `declared`.
+fn overlaps(declared: &WitInterface, used: &WitInterface) -> bool {
+    declared.namespace == used.namespace
+        && declared.package == used.package
+        && declared.version_compatible(used)
+        && (declared.interfaces.is_empty() || !declared.interfaces.is_disjoint(&used.interfaces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Component, LocalResources};
+
+    fn http_interface(host: &str) -> WitInterface {
+        let mut interface = WitInterface::from("wasi:http/incoming-handler@0.2.0");
+        interface
+            .config
+            .insert("host".to_string(), host.to_string());
+        interface
+    }
+
+    #[test]
+    fn test_lint() {
+        let workload = Workload {
+            namespace: "default".to_string(),
+            name: "lint".to_string(),
+            annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
+            service: None,
+            components: vec![Component {
+                local_resources: LocalResources {
+                    memory_limit_mb: 1,
+                    ..Default::default()
+                },
+                pool_size: 0,
+                ..Default::default()
+            }],
+            host_interfaces: vec![
+                http_interface("api.example.com"),
+                http_interface("api.example.com"),
+                WitInterface::from("wasi:keyvalue/store@0.2.0-draft"),
+                http_interface("www.example.com"),
+            ],
+            volumes: vec![],
+        };
+        let compiled = CompiledComponent {
+            world: WitWorld {
+                imports: Default::default(),
+                exports: [WitInterface::from("wasi:http/incoming-handler@0.2.0")].into(),
+            },
+            min_memory_bytes: 2 * 1024 * 1024,
+        };
+        let served_hosts = HashMap::from([("www.example.com".to_string(), "other".to_string())]);
+        let warnings = lint(&workload, &[Some(compiled)], &served_hosts);
+        let codes: Vec<_> = warnings
+            .iter()
+            .map(|w| (w.location.as_str(), w.code.as_str()))
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                ("components[0]", "empty_pool"),
+                ("components[0]", "memory_below_minimum"),
+                ("host_interfaces[2]", "unused_interface"),
+                ("host_interfaces[1]", "duplicate_virtual_host"),
+                ("host_interfaces[3]", "duplicate_virtual_host"),
+            ]
+        );
+
+        // Interfaces aren't reported unused when a component's world is unknown
+        let warnings = lint(&workload, &[None], &HashMap::new());
+        assert!(warnings.iter().all(|w| w.code != "unused_interface"));
+    }
+}
@@ -0,0 +1,146 @@
+//! mDNS/DNS-SD advertisement of the virtual hosts served by the HTTP server.
+//!
+//! Each workload's virtual host is registered as an [`HTTP_SERVICE_TYPE`] service
+//! instance, so devices on the local network can discover it without manual DNS
+//! entries. A virtual host under `.local` is also advertised as the host name of its
+//! address records, which makes it resolvable through mDNS directly. Other virtual
+//! hosts are advertised under this machine's `.local` host name, with the virtual host
+//! in the `host` TXT property.
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use anyhow::Context;
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+/// The DNS-SD service type advertised for HTTP virtual hosts.
+pub const HTTP_SERVICE_TYPE: &str = "_http._tcp.local.";
+
+/// Advertises virtual hosts on the local network over mDNS.
+pub struct MdnsAdvertiser {
+    daemon: ServiceDaemon,
+    /// Host name advertised for virtual hosts outside `.local`
+    host_name: String,
+    /// Full service names registered for each workload ID
+    registered: Mutex<HashMap<String, String>>,
+}
+
+impl MdnsAdvertiser {
+    /// Starts an mDNS responder for this host.
+    ///
+    /// # Errors
+    /// Returns an error if the mDNS daemon can't be started, e.g. when multicast
+    /// sockets aren't available.
+    pub fn new() -> anyhow::Result<Self> {
+        let daemon = ServiceDaemon::new().context("failed to start mDNS daemon")?;
+        let host_name = hostname::get()
+            .map(|h| h.to_string_lossy().to_string())
+            .unwrap_or_else(|_| "wash-runtime".to_string());
+        Ok(Self {
+            daemon,
+            host_name: format!("{host_name}.local."),
+            registered: Mutex::default(),
+        })
+    }
+
+    /// Advertises the virtual host of a workload served on `addr`. Advertising another
+    /// virtual host for the same workload replaces the previous advertisement.
+    ///
+    /// # Errors
+    /// Returns an error if the virtual host can't be represented as a service instance
+    /// or the registration fails.
+    pub async fn advertise(
+        &self,
+        workload_id: &str,
+        virtual_host: &str,
+        addr: SocketAddr,
+    ) -> anyhow::Result<()> {
+        let host = virtual_host
+            .rsplit_once(':')
+            .map_or(virtual_host, |(host, _port)| host);
+        let host_name = if host.ends_with(".local") {
+            format!("{host}.")
+        } else {
+            self.host_name.clone()
+        };
+        // Dots separate labels in the full service name, so they can't appear in the instance name
+        let instance = host.replace('.', "-");
+        let properties = [("host", virtual_host), ("path", "/")];
+
+        let info = if addr.ip().is_unspecified() {
+            ServiceInfo::new(
+                HTTP_SERVICE_TYPE,
+                &instance,
+                &host_name,
+                (),
+                addr.port(),
+                &properties[..],
+            )
+            .map(ServiceInfo::enable_addr_auto)
+        } else {
+            ServiceInfo::new(
+                HTTP_SERVICE_TYPE,
+                &instance,
+                &host_name,
+                addr.ip(),
+                addr.port(),
+                &properties[..],
+            )
+        }
+        .with_context(|| format!("invalid mDNS service for virtual host '{virtual_host}'"))?;
+        let fullname = info.get_fullname().to_string();
+
+        self.withdraw(workload_id).await;
+        self.daemon
+            .register(info)
+            .with_context(|| format!("failed to advertise virtual host '{virtual_host}'"))?;
+        debug!(
+            workload_id,
+            virtual_host,
+            service = %fullname,
+            "advertising virtual host over mDNS"
+        );
+        self.registered
+            .lock()
+            .await
+            .insert(workload_id.to_string(), fullname);
+        Ok(())
+    }
+
+    /// Stops advertising the virtual host of a workload, if it has one.
+    pub async fn withdraw(&self, workload_id: &str) {
+        let Some(fullname) = self.registered.lock().await.remove(workload_id) else {
+            return;
+        };
+        debug!(
+            workload_id,
+            service = %fullname,
+            "withdrawing mDNS advertisement"
+        );
+        if let Err(e) = self.daemon.unregister(&fullname) {
+            warn!(err = %e, service = %fullname, "failed to withdraw mDNS advertisement");
+        }
+    }
+
+    /// Withdraws every advertisement and stops the mDNS responder.
+    pub async fn shutdown(&self) {
+        for (_, fullname) in self.registered.lock().await.drain() {
+            if let Err(e) = self.daemon.unregister(&fullname) {
+                warn!(err = %e, service = %fullname, "failed to withdraw mDNS advertisement");
+            }
+        }
+        if let Err(e) = self.daemon.shutdown() {
+            warn!(err = %e, "failed to stop mDNS daemon");
+        }
+    }
+}
+
+impl std::fmt::Debug for MdnsAdvertiser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MdnsAdvertiser")
+            .field("host_name", &self.host_name)
+            .finish_non_exhaustive()
+    }
+}
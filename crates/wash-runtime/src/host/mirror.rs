@@ -0,0 +1,369 @@
+//! Traffic mirroring for the [HTTP server](crate::host::http): incoming requests to a
+//! virtual host are copied to shadow workloads, e.g. a rewrite being validated against
+//! production traffic, without affecting the responses clients get.
+//!
+//! A shadow is a workload serving `wasi:http/incoming-handler` whose interface config
+//! sets `mirror` to the virtual hosts it shadows, comma separated, or `*` for every host.
+//! Like a [filter](crate::host::filters) it has no `host` of its own. Shadows run
+//! alongside the workload serving the request, and get each chunk of the request body as
+//! that workload reads it, rather than the body being buffered. A shadow that falls more
+//! than [`SHADOW_BUFFER_CHUNKS`] chunks behind is cut off, as is every shadow when the
+//! workload doesn't read the whole body, and its request fails. Their responses are
+//! discarded, and their outcomes recorded as OpenTelemetry metrics:
+//!
+//! - `http_mirror_requests_total`, by `host`, `shadow`, the shadow's `status` or `error`,
+//!   and `matches_primary`, whether the status matched the workload serving the request
+//! - `http_mirror_duration_seconds`, the time until a shadow's response was complete
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::task::{Context, Poll, ready};
+use std::time::Instant;
+
+use bytes::Bytes;
+use http_body_util::BodyExt as _;
+use hyper::body::{Body, Frame, SizeHint};
+use opentelemetry::KeyValue;
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, warn};
+use wasmtime::component::InstancePre;
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+
+use crate::engine::ctx::Ctx;
+use crate::engine::workload::ResolvedWorkload;
+use crate::host::http::WorkloadHandles;
+use crate::wit::WitInterface;
+
+/// The incoming handler config key listing the virtual hosts a shadow mirrors.
+const MIRROR_KEY: &str = "mirror";
+/// How many chunks of the request body a shadow can fall behind the workload serving the
+/// request before it's cut off.
+pub const SHADOW_BUFFER_CHUNKS: usize = 16;
+
+/// A workload serving HTTP, with the instance and component that handle its requests.
+type Handler = (ResolvedWorkload, InstancePre<Ctx>, String);
+
+static METRICS: LazyLock<MirrorMetrics> =
+    LazyLock::new(|| MirrorMetrics::new(&opentelemetry::global::meter("http-mirror")));
+
+struct MirrorMetrics {
+    requests_total: opentelemetry::metrics::Counter<u64>,
+    duration_seconds: opentelemetry::metrics::Histogram<f64>,
+}
+
+impl MirrorMetrics {
+    fn new(meter: &opentelemetry::metrics::Meter) -> Self {
+        let requests_total = meter
+            .u64_counter("http_mirror_requests_total")
+            .with_description("Total number of requests mirrored to shadow workloads")
+            .build();
+        let duration_seconds = meter
+            .f64_histogram("http_mirror_duration_seconds")
+            .with_description("Time shadow workloads took to complete mirrored requests")
+            .with_unit("s")
+            .build();
+        Self {
+            requests_total,
+            duration_seconds,
+        }
+    }
+}
+
+/// Returns the virtual hosts a workload shadows, if it's a shadow.
+pub(crate) fn mirrored_hosts(workload: &ResolvedWorkload) -> Option<Vec<String>> {
+    let incoming_handler_interface = WitInterface::from("wasi:http/incoming-handler");
+    workload
+        .host_interfaces()
+        .iter()
+        .find(|iface| iface.contains(&incoming_handler_interface))
+        .and_then(|iface| hosts_from_config(&iface.config))
+}
+
+fn hosts_from_config(config: &HashMap<String, String>) -> Option<Vec<String>> {
+    config.get(MIRROR_KEY).map(|hosts| {
+        hosts
+            .split(',')
+            .map(str::trim)
+            .filter(|host| !host.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+/// Returns the shadows of a virtual host whose routing isn't paused.
+pub(crate) async fn shadows(
+    workload_handles: &WorkloadHandles,
+    host: Option<&str>,
+) -> Vec<Handler> {
+    workload_handles
+        .read()
+        .await
+        .values()
+        .filter(|(handle, _, _)| {
+            !handle.is_routing_paused()
+                && mirrored_hosts(handle).is_some_and(|hosts| {
+                    hosts
+                        .iter()
+                        .any(|mirrored| mirrored == "*" || Some(mirrored.as_str()) == host)
+                })
+        })
+        .cloned()
+        .collect()
+}
+
+/// Sends a copy of a request to a shadow in the background, recording its outcome.
+///
+/// # Arguments
+/// * `host` - The mirrored virtual host, for the recorded metrics
+/// * `primary_status` - Receives the status of the workload serving the request, to
+///   compare with the shadow's
+///
+/// # Returns
+/// The sender to [`tee`] the request body into, or `None` if the request couldn't be
+/// copied.
+pub(crate) fn mirror(
+    shadow: Handler,
+    host: String,
+    parts: &hyper::http::request::Parts,
+    mut primary_status: watch::Receiver<Option<u16>>,
+) -> Option<ShadowSender> {
+    let mut request = hyper::Request::builder()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version);
+    if let Some(headers) = request.headers_mut() {
+        headers.clone_from(&parts.headers);
+    }
+    let (sender, body) = shadow_body();
+    let request = match request.body(body.boxed()) {
+        Ok(request) => request,
+        Err(e) => {
+            warn!(err = ?e, "failed to copy request for shadow workload");
+            return None;
+        }
+    };
+
+    tokio::spawn(async move {
+        let (handle, instance_pre, component_id) = shadow;
+        let shadow_id = handle.id().to_string();
+        let start = Instant::now();
+        let result = async {
+            let response = crate::host::http::invoke_component_handler(
+                handle,
+                instance_pre,
+                &component_id,
+                request,
+            )
+            .await?;
+            let status = response.status().as_u16();
+            // Discard the body, but only count the response as complete once it's read
+            response
+                .into_body()
+                .collect()
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to read shadow response: {e:?}"))?;
+            anyhow::Ok(status)
+        }
+        .await;
+        let elapsed = start.elapsed();
+
+        let primary = primary_status
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|status| *status);
+        let status = match &result {
+            Ok(status) => status.to_string(),
+            Err(e) => {
+                warn!(err = ?e, host, shadow = shadow_id, "shadow workload failed");
+                "error".to_string()
+            }
+        };
+        let matches_primary = result.as_ref().ok().is_some_and(|s| Some(*s) == primary);
+        debug!(
+            host,
+            shadow = shadow_id,
+            status,
+            ?primary,
+            elapsed = ?elapsed,
+            "mirrored request to shadow workload"
+        );
+
+        let attributes = [
+            KeyValue::new("host", host),
+            KeyValue::new("shadow", shadow_id),
+            KeyValue::new("status", status),
+            KeyValue::new("matches_primary", matches_primary),
+        ];
+        METRICS.requests_total.add(1, &attributes);
+        METRICS
+            .duration_seconds
+            .record(elapsed.as_secs_f64(), &attributes[..2]);
+    });
+    Some(sender)
+}
+
+/// The end of a shadow's request body that the chunks of the original body are sent to.
+pub(crate) struct ShadowSender {
+    chunks: mpsc::Sender<Bytes>,
+    /// Set once the original body was read to its end
+    complete: Arc<AtomicBool>,
+}
+
+impl ShadowSender {
+    fn complete(self) {
+        self.complete.store(true, Ordering::Release);
+    }
+}
+
+/// A shadow's copy of a request body, which fails unless the original was read to its end.
+struct ShadowBody {
+    chunks: mpsc::Receiver<Bytes>,
+    complete: Arc<AtomicBool>,
+}
+
+fn shadow_body() -> (ShadowSender, ShadowBody) {
+    let (tx, rx) = mpsc::channel(SHADOW_BUFFER_CHUNKS);
+    let complete = Arc::new(AtomicBool::new(false));
+    (
+        ShadowSender {
+            chunks: tx,
+            complete: complete.clone(),
+        },
+        ShadowBody {
+            chunks: rx,
+            complete,
+        },
+    )
+}
+
+impl Body for ShadowBody {
+    type Data = Bytes;
+    type Error = ErrorCode;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, ErrorCode>>> {
+        Poll::Ready(match ready!(self.chunks.poll_recv(cx)) {
+            Some(chunk) => Some(Ok(Frame::data(chunk))),
+            None if self.complete.load(Ordering::Acquire) => None,
+            None => Some(Err(ErrorCode::InternalError(Some(
+                "the shadow fell behind the mirrored request body, or it wasn't read in full"
+                    .to_string(),
+            )))),
+        })
+    }
+}
+
+/// A request body that copies its chunks to shadows as it's read, see [`tee`].
+pub(crate) struct Tee<B> {
+    body: B,
+    shadows: Vec<ShadowSender>,
+}
+
+/// Copies each chunk of a request body to the shadows as the workload serving the request
+/// reads it. Chunks are reference counted rather than copied, and a shadow that can't
+/// keep up is cut off rather than slowing the request down.
+pub(crate) fn tee<B: Body<Data = Bytes> + Unpin>(body: B, shadows: Vec<ShadowSender>) -> Tee<B> {
+    let mut shadows = shadows;
+    // An empty body may never be polled
+    if body.is_end_stream() {
+        shadows.drain(..).for_each(ShadowSender::complete);
+    }
+    Tee { body, shadows }
+}
+
+impl<B: Body<Data = Bytes> + Unpin> Body for Tee<B> {
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, B::Error>>> {
+        let this = &mut *self;
+        let frame = ready!(Pin::new(&mut this.body).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(chunk) = frame.data_ref() {
+                    this.shadows
+                        .retain(|shadow| shadow.chunks.try_send(chunk.clone()).is_ok());
+                }
+            }
+            Some(Err(_)) => this.shadows.clear(),
+            None => this.shadows.drain(..).for_each(ShadowSender::complete),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hosts_from_config() {
+        let config = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        assert_eq!(
+            hosts_from_config(&config(&[("host", "app.localhost")])),
+            None
+        );
+        assert_eq!(
+            hosts_from_config(&config(&[("mirror", "app.localhost, api.localhost")])),
+            Some(vec![
+                "app.localhost".to_string(),
+                "api.localhost".to_string()
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tee() {
+        let chunks = |count: usize| {
+            http_body_util::StreamBody::new(futures::stream::iter(
+                (0..count).map(|i| Ok::<_, ErrorCode>(Frame::data(Bytes::from(i.to_string())))),
+            ))
+        };
+        async fn read(body: impl Body<Data = Bytes>) -> Option<Bytes> {
+            body.collect().await.ok().map(|body| body.to_bytes())
+        }
+
+        let (sender, shadow) = shadow_body();
+        assert_eq!(
+            read(tee(chunks(3), vec![sender])).await.as_deref(),
+            Some(&b"012"[..])
+        );
+        assert_eq!(read(shadow).await.as_deref(), Some(&b"012"[..]));
+
+        // A shadow that isn't read falls behind and is cut off, without holding up the
+        // workload serving the request
+        let (sender, shadow) = shadow_body();
+        assert!(
+            read(tee(chunks(SHADOW_BUFFER_CHUNKS + 1), vec![sender]))
+                .await
+                .is_some()
+        );
+        assert!(read(shadow).await.is_none());
+
+        // As is a shadow of a body that isn't read to its end
+        let (sender, shadow) = shadow_body();
+        drop(tee(chunks(1), vec![sender]));
+        assert!(read(shadow).await.is_none());
+    }
+}
@@ -40,31 +40,91 @@
 //! # }
 //! ```
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::future::Future;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use anyhow::{Context, bail};
 use names::{Generator, Name};
 use tokio::sync::RwLock;
-use tracing::{debug, trace, warn};
+use tracing::{debug, info, trace, warn};
 
 use crate::engine::Engine;
 use crate::engine::workload::ResolvedWorkload;
-use crate::plugin::HostPlugin;
+use crate::host::api_keys::ApiKeyStore;
+use crate::host::artifacts::{ArtifactStore, SharedArtifacts};
+use crate::host::blob_volumes::{BlobVolumes, VolumeBlobstore};
+use crate::host::cgroups::Cgroups;
+use crate::host::egress::EgressLog;
+use crate::host::error::HostError;
+use crate::host::fs_audit::FsAudit;
+use crate::host::grpc::GrpcIngress;
+use crate::host::invoker::{QueueInvokers, QueueSource};
+use crate::host::selector::LabelSelector;
+use crate::host::services::ServiceRegistry;
+use crate::host::usage::UsageLog;
+use crate::host::wrpc::WrpcTransport;
+use crate::plugin::{HostPlugin, PluginDependency};
+use crate::redact;
 use crate::types::*;
-use crate::wit::WitWorld;
+use crate::wit::{WitInterface, WitWorld};
 
 mod sysinfo;
 use sysinfo::SystemMonitor;
 
+#[cfg(feature = "acme")]
+pub mod acme;
+pub mod api_keys;
+pub mod artifacts;
+pub mod assets;
+pub mod baggage;
+pub mod bandwidth;
+pub mod blob_volumes;
+pub mod cert_reload;
+pub mod cgroups;
+pub mod connections;
+pub mod dns;
+pub mod egress;
+pub mod error;
+pub mod filters;
+pub mod forwarded;
+pub mod fs_audit;
+pub mod grpc;
+pub mod headers;
 pub mod http;
+#[cfg(feature = "http3")]
+pub mod http3;
+pub mod invoker;
+pub mod ip_filter;
+pub mod jwt;
+pub mod lint;
+#[cfg(feature = "mdns")]
+pub mod mdns;
+pub mod mirror;
+pub mod proxy;
+pub mod proxy_protocol;
+pub mod request_id;
+pub mod saturation;
+pub mod selector;
+pub mod services;
+pub mod slow_invocations;
+pub mod sni;
+pub mod split;
+pub mod trace_context;
+pub mod upstream;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
+pub mod usage;
+pub mod volumes;
+pub mod wrpc;
 
 /// The API for interacting with a wasmcloud host.
 ///
 /// This trait defines the core operations for managing workloads on a host,
 /// including starting, stopping, and querying workload status, as well as
-/// retrieving host health information.
+/// retrieving host health information. [`HostError::of`] tells why a request failed.
 pub trait HostApi {
     /// Request a heartbeat containing the host's current state and system information.
     ///
@@ -88,6 +148,42 @@ pub trait HostApi {
         &self,
         request: WorkloadStartRequest,
     ) -> impl Future<Output = anyhow::Result<WorkloadStartResponse>>;
+    /// Check that a workload would start on this host without starting it, e.g. to gate
+    /// a deploy in CI. The host checks its volume policy, the components' resources and
+    /// cgroup layout, compiles the components, and matches their imports against the
+    /// host, its plugins and the workload's other components, but doesn't bind plugins,
+    /// instantiate the components or route to them.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload configuration to check
+    ///
+    /// # Returns
+    /// A `WorkloadValidateResponse` with every problem found, none if the workload would
+    /// start.
+    ///
+    /// # Errors
+    /// Problems with the workload are reported in the response rather than returned.
+    fn workload_validate(
+        &self,
+        request: WorkloadValidateRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadValidateResponse>>;
+    /// Lint a workload for configurations that are valid but likely mistakes, such as a
+    /// memory limit below what its components need or a virtual host another workload on
+    /// this host already serves. See [`lint`] for the lints.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload configuration to lint
+    ///
+    /// # Returns
+    /// A `WorkloadLintResponse` with a warning for each suspicious configuration.
+    ///
+    /// # Errors
+    /// Components that don't compile aren't linted rather than failing the request, see
+    /// [`HostApi::workload_validate`] for errors that keep a workload from starting.
+    fn workload_lint(
+        &self,
+        request: WorkloadLintRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadLintResponse>>;
     /// Query the status of a running workload.
     ///
     /// # Arguments
@@ -116,6 +212,289 @@ pub trait HostApi {
         &self,
         request: WorkloadStopRequest,
     ) -> impl Future<Output = anyhow::Result<WorkloadStopResponse>>;
+    /// List the running workloads, optionally those whose annotations match a label
+    /// selector.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the label selector, see [`selector`]
+    ///
+    /// # Returns
+    /// A `WorkloadListResponse` with the matching workloads, sorted by ID.
+    ///
+    /// # Errors
+    /// Returns an error if the selector is invalid.
+    fn workload_list(
+        &self,
+        request: WorkloadListRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadListResponse>>;
+    /// Stop every running workload whose annotations match a label selector.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the label selector, see [`selector`]
+    ///
+    /// # Returns
+    /// A `WorkloadStopSelectedResponse` with the final status of each stopped workload.
+    ///
+    /// # Errors
+    /// Returns an error if the selector is empty or invalid.
+    fn workload_stop_selected(
+        &self,
+        request: WorkloadStopSelectedRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadStopSelectedResponse>>;
+    /// Inspect a component without starting it.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the component bytes to inspect
+    ///
+    /// # Returns
+    /// A `ComponentInspectResponse` with the component's imports, exports, WIT world,
+    /// embedded metadata, estimated memory needs and any imports this host can't satisfy.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes are not a valid component.
+    fn component_inspect(
+        &self,
+        request: ComponentInspectRequest,
+    ) -> impl Future<Output = anyhow::Result<ComponentInspectResponse>>;
+    /// Compare the interfaces of a running component with those of a replacement, so an
+    /// update can be held back when the replacement would break the workload.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload, the component in it and the replacement's
+    ///   bytes
+    ///
+    /// # Returns
+    /// A `ComponentDiffResponse` with every change to the component's imports and
+    /// exports. [`ComponentDiffResponse::is_breaking`] tells whether any can break the
+    /// workload.
+    ///
+    /// # Errors
+    /// Returns an error if the workload is not running, has no such component, has
+    /// several components and none was named, or the replacement doesn't compile.
+    fn component_diff(
+        &self,
+        request: ComponentDiffRequest,
+    ) -> impl Future<Output = anyhow::Result<ComponentDiffResponse>>;
+    /// Query what the running workloads are made of and where it came from.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID to report, or none for every workload
+    ///
+    /// # Returns
+    /// A `WorkloadInventoryResponse` with the digest, source image, embedded metadata and
+    /// producers of each component, as recorded when its workload was started.
+    ///
+    /// # Errors
+    /// Returns an error if the requested workload is not found.
+    fn workload_inventory(
+        &self,
+        request: WorkloadInventoryRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadInventoryResponse>>;
+    /// List the distinct components loaded on this host, e.g. to find out whether a
+    /// component with a known vulnerability is running.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the digest to look for, or none for every component
+    ///
+    /// # Returns
+    /// A `ComponentsListResponse` with each component's digest, name, version, claims,
+    /// source images and the workloads using it. It's empty if no component matches.
+    fn components_list(
+        &self,
+        request: ComponentsListRequest,
+    ) -> impl Future<Output = anyhow::Result<ComponentsListResponse>>;
+    /// Query the recent outbound connections of a workload.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID and how many recent records to return
+    ///
+    /// # Returns
+    /// A `WorkloadEgressResponse` with the workload's recent outbound connections,
+    /// including those blocked by policy, and totals per destination.
+    ///
+    /// # Errors
+    /// Returns an error if the workload is not found.
+    fn workload_egress(
+        &self,
+        request: WorkloadEgressRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadEgressResponse>>;
+    /// Get the resource usage of a running workload, to find the heavy tenants on a
+    /// shared host.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID
+    ///
+    /// # Returns
+    /// A `WorkloadUsageResponse` with the workload's current and peak guest memory
+    /// and its table elements across its instances, the memory of each live instance
+    /// against its limit, the CPU time and fuel it consumed, in total and per invocation,
+    /// and its invocation and plugin operation counts.
+    ///
+    /// # Errors
+    /// Returns an error if the workload is not found.
+    fn workload_usage(
+        &self,
+        request: WorkloadUsageRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadUsageResponse>>;
+    /// List the live instances of a running workload, each in a store of its own, to
+    /// see how they're doing.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID
+    ///
+    /// # Returns
+    /// A `WorkloadInstancesResponse` with each live instance's ID, component, age, number
+    /// of calls into it, whether it's executing guest code and the last error a call into
+    /// it failed with, oldest first.
+    ///
+    /// # Errors
+    /// Returns an error if the workload is not found.
+    fn workload_instances(
+        &self,
+        request: WorkloadInstancesRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadInstancesResponse>>;
+    /// Pause a running workload, e.g. to suspend a misbehaving tenant during an incident
+    /// without losing its warm state. Incoming HTTP requests are rejected, messages wait
+    /// and scheduled jobs are skipped until the workload is resumed; invocations already
+    /// running and its service aren't interrupted.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID
+    ///
+    /// # Returns
+    /// A `WorkloadPauseResponse` saying whether the workload was already paused.
+    ///
+    /// # Errors
+    /// Returns an error if the workload isn't running.
+    fn workload_pause(
+        &self,
+        request: WorkloadPauseRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadPauseResponse>>;
+    /// Resume a workload paused with [`HostApi::workload_pause`].
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID
+    ///
+    /// # Returns
+    /// A `WorkloadResumeResponse` saying whether the workload was paused.
+    ///
+    /// # Errors
+    /// Returns an error if the workload isn't running.
+    fn workload_resume(
+        &self,
+        request: WorkloadResumeRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadResumeResponse>>;
+    /// Change the instance pool size of a running workload's components without updating
+    /// or redeploying it. Live instances are never interrupted when shrinking, they drain
+    /// by finishing their invocation.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID, optionally a component ID, and the pool size
+    ///
+    /// # Returns
+    /// A `WorkloadScaleResponse` with the pool sizes that were replaced.
+    ///
+    /// # Errors
+    /// Returns an error if the workload isn't running or has no such component.
+    fn workload_scale(
+        &self,
+        request: WorkloadScaleRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadScaleResponse>>;
+    /// Change the memory limit, CPU limit, allowed hosts, config or environment of a
+    /// running workload's components without downtime, e.g. to flip a feature flag. The
+    /// changes apply to the instances created afterwards, and as each invocation gets its
+    /// own instance, live ones are replaced as their invocations return. Plugins serving
+    /// config, such as `wasi:config/store`, are updated as well.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID, optionally a component ID, and the changes
+    ///
+    /// # Returns
+    /// A `WorkloadUpdateResourcesResponse` with the IDs of the updated components.
+    ///
+    /// # Errors
+    /// Returns an error if the workload isn't running, has no such component, or the
+    /// changes are invalid.
+    fn workload_update_resources(
+        &self,
+        request: WorkloadUpdateResourcesRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadUpdateResourcesResponse>>;
+    /// Call a function exported by a running workload, such as a business function in a
+    /// custom interface, without going through HTTP.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the workload ID, the exported interface and function, and
+    ///   the arguments as component model values or WAVE text
+    ///
+    /// # Returns
+    /// A `WorkloadInvokeResponse` with the values the function returned, in the same
+    /// encoding as the arguments.
+    ///
+    /// # Errors
+    /// Returns an error if the workload isn't running, doesn't export the function, the
+    /// arguments don't match its parameters, or the call traps.
+    fn workload_invoke(
+        &self,
+        request: WorkloadInvokeRequest,
+    ) -> impl Future<Output = anyhow::Result<WorkloadInvokeResponse>>;
+    /// Split the HTTP traffic of a virtual host between workloads by weight, e.g. for an
+    /// A/B experiment, or remove its split.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the virtual host and the workload IDs with their weights
+    ///
+    /// # Returns
+    /// An `HttpTrafficSplitResponse` with the split that was replaced.
+    ///
+    /// # Errors
+    /// Returns an error if the host has no HTTP server that supports splits, or a
+    /// workload isn't serving the virtual host.
+    fn http_traffic_split(
+        &self,
+        request: HttpTrafficSplitRequest,
+    ) -> impl Future<Output = anyhow::Result<HttpTrafficSplitResponse>>;
+    /// Create an API key that callers can authenticate to virtual hosts with.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the key's name and the virtual hosts it's accepted on
+    ///
+    /// # Returns
+    /// An `ApiKeyCreateResponse` with the key's ID and the key, which isn't returned again.
+    ///
+    /// # Errors
+    /// Returns an error if the host has no API key store, or the key can't be saved.
+    fn api_key_create(
+        &self,
+        request: ApiKeyCreateRequest,
+    ) -> impl Future<Output = anyhow::Result<ApiKeyCreateResponse>>;
+    /// Replace the secret of an API key.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the key's ID
+    ///
+    /// # Returns
+    /// An `ApiKeyRotateResponse` with the new key.
+    ///
+    /// # Errors
+    /// Returns an error if the key is not found, or can't be saved.
+    fn api_key_rotate(
+        &self,
+        request: ApiKeyRotateRequest,
+    ) -> impl Future<Output = anyhow::Result<ApiKeyRotateResponse>>;
+    /// Revoke an API key.
+    ///
+    /// # Arguments
+    /// * `request` - Contains the key's ID
+    ///
+    /// # Returns
+    /// An `ApiKeyRevokeResponse` for the revoked key.
+    ///
+    /// # Errors
+    /// Returns an error if the key is not found, or the change can't be saved.
+    fn api_key_revoke(
+        &self,
+        request: ApiKeyRevokeRequest,
+    ) -> impl Future<Output = anyhow::Result<ApiKeyRevokeResponse>>;
 }
 
 // Helper trait impl that helps with Arc-ing the Host
@@ -129,18 +508,138 @@ impl<T: HostApi> HostApi for Arc<T> {
     ) -> anyhow::Result<WorkloadStartResponse> {
         self.as_ref().workload_start(request).await
     }
+    async fn workload_validate(
+        &self,
+        request: WorkloadValidateRequest,
+    ) -> anyhow::Result<WorkloadValidateResponse> {
+        self.as_ref().workload_validate(request).await
+    }
+    async fn workload_lint(
+        &self,
+        request: WorkloadLintRequest,
+    ) -> anyhow::Result<WorkloadLintResponse> {
+        self.as_ref().workload_lint(request).await
+    }
     async fn workload_stop(
         &self,
         request: WorkloadStopRequest,
     ) -> anyhow::Result<WorkloadStopResponse> {
         self.as_ref().workload_stop(request).await
     }
+    async fn workload_list(
+        &self,
+        request: WorkloadListRequest,
+    ) -> anyhow::Result<WorkloadListResponse> {
+        self.as_ref().workload_list(request).await
+    }
+    async fn workload_stop_selected(
+        &self,
+        request: WorkloadStopSelectedRequest,
+    ) -> anyhow::Result<WorkloadStopSelectedResponse> {
+        self.as_ref().workload_stop_selected(request).await
+    }
     async fn workload_status(
         &self,
         request: WorkloadStatusRequest,
     ) -> anyhow::Result<WorkloadStatusResponse> {
         self.as_ref().workload_status(request).await
     }
+    async fn component_inspect(
+        &self,
+        request: ComponentInspectRequest,
+    ) -> anyhow::Result<ComponentInspectResponse> {
+        self.as_ref().component_inspect(request).await
+    }
+    async fn component_diff(
+        &self,
+        request: ComponentDiffRequest,
+    ) -> anyhow::Result<ComponentDiffResponse> {
+        self.as_ref().component_diff(request).await
+    }
+    async fn workload_inventory(
+        &self,
+        request: WorkloadInventoryRequest,
+    ) -> anyhow::Result<WorkloadInventoryResponse> {
+        self.as_ref().workload_inventory(request).await
+    }
+    async fn components_list(
+        &self,
+        request: ComponentsListRequest,
+    ) -> anyhow::Result<ComponentsListResponse> {
+        self.as_ref().components_list(request).await
+    }
+    async fn workload_egress(
+        &self,
+        request: WorkloadEgressRequest,
+    ) -> anyhow::Result<WorkloadEgressResponse> {
+        self.as_ref().workload_egress(request).await
+    }
+    async fn workload_usage(
+        &self,
+        request: WorkloadUsageRequest,
+    ) -> anyhow::Result<WorkloadUsageResponse> {
+        self.as_ref().workload_usage(request).await
+    }
+    async fn workload_instances(
+        &self,
+        request: WorkloadInstancesRequest,
+    ) -> anyhow::Result<WorkloadInstancesResponse> {
+        self.as_ref().workload_instances(request).await
+    }
+    async fn workload_pause(
+        &self,
+        request: WorkloadPauseRequest,
+    ) -> anyhow::Result<WorkloadPauseResponse> {
+        self.as_ref().workload_pause(request).await
+    }
+    async fn workload_resume(
+        &self,
+        request: WorkloadResumeRequest,
+    ) -> anyhow::Result<WorkloadResumeResponse> {
+        self.as_ref().workload_resume(request).await
+    }
+    async fn workload_scale(
+        &self,
+        request: WorkloadScaleRequest,
+    ) -> anyhow::Result<WorkloadScaleResponse> {
+        self.as_ref().workload_scale(request).await
+    }
+    async fn workload_update_resources(
+        &self,
+        request: WorkloadUpdateResourcesRequest,
+    ) -> anyhow::Result<WorkloadUpdateResourcesResponse> {
+        self.as_ref().workload_update_resources(request).await
+    }
+    async fn workload_invoke(
+        &self,
+        request: WorkloadInvokeRequest,
+    ) -> anyhow::Result<WorkloadInvokeResponse> {
+        self.as_ref().workload_invoke(request).await
+    }
+    async fn http_traffic_split(
+        &self,
+        request: HttpTrafficSplitRequest,
+    ) -> anyhow::Result<HttpTrafficSplitResponse> {
+        self.as_ref().http_traffic_split(request).await
+    }
+    async fn api_key_create(
+        &self,
+        request: ApiKeyCreateRequest,
+    ) -> anyhow::Result<ApiKeyCreateResponse> {
+        self.as_ref().api_key_create(request).await
+    }
+    async fn api_key_rotate(
+        &self,
+        request: ApiKeyRotateRequest,
+    ) -> anyhow::Result<ApiKeyRotateResponse> {
+        self.as_ref().api_key_rotate(request).await
+    }
+    async fn api_key_revoke(
+        &self,
+        request: ApiKeyRevokeRequest,
+    ) -> anyhow::Result<ApiKeyRevokeResponse> {
+        self.as_ref().api_key_revoke(request).await
+    }
 }
 
 /// Internal representation of a workload's state within the host.
@@ -180,6 +679,36 @@ pub struct Host {
     workloads: Arc<RwLock<HashMap<String, HostWorkload>>>,
     /// Plugins in a map from their ID to the plugin itself
     plugins: HashMap<&'static str, Arc<dyn HostPlugin>>,
+    /// Plugin IDs in dependency order, resolved when the host is built
+    plugin_order: Vec<&'static str>,
+    /// Whether routing to workloads is paused while a plugin they depend on is unhealthy
+    pause_degraded_routing: bool,
+    /// Services published by running workloads
+    services: Arc<ServiceRegistry>,
+    /// Outbound connections made by running workloads
+    egress: Arc<EgressLog>,
+    /// What running workloads are made of, recorded when they're started
+    inventory: RwLock<HashMap<String, WorkloadInventory>>,
+    /// Resource usage of running workloads
+    usage: Arc<UsageLog>,
+    /// Creates the cgroups enforcing the CPU limits and pinning of running workloads, if enabled
+    cgroups: Option<Arc<Cgroups>>,
+    /// The directories host path volumes must lie within, or `None` to allow any
+    host_path_volumes: Option<Vec<PathBuf>>,
+    /// The blobstore containers downloaded for running workloads' volumes
+    blob_volumes: BlobVolumes,
+    /// Logs what running workloads do in the directories they mount, if enabled
+    fs_audit: Option<Arc<FsAudit>>,
+    /// Where compiled components are shared with other hosts, if enabled
+    artifacts: Option<SharedArtifacts>,
+    /// Queue sources and the invokers pulling from them for running workloads
+    invokers: QueueInvokers,
+    /// Transport to services on other hosts, which also serves this host's services
+    wrpc: Option<Arc<WrpcTransport>>,
+    /// Serves the gRPC services declared by running workloads
+    grpc: Option<Arc<GrpcIngress>>,
+    /// The API keys managed through the host API
+    api_keys: Option<Arc<ApiKeyStore>>,
     /// Host metadata
     id: String,
     hostname: String,
@@ -215,8 +744,12 @@ impl Host {
             .await
             .context("failed to start HTTP handler")?;
 
-        // Start all plugins, any errors means the host fails to start.
-        for (id, plugin) in &self.plugins {
+        if let Some(grpc) = &self.grpc {
+            grpc.start().await.context("failed to start gRPC ingress")?;
+        }
+
+        // Start all plugins in dependency order, any errors means the host fails to start.
+        for (id, plugin) in self.ordered_plugins() {
             if let Err(e) = plugin.start().await {
                 tracing::error!(id = id, err = ?e, "failed to start plugin");
                 bail!(e)
@@ -240,8 +773,12 @@ impl Host {
             .await
             .context("failed to stop HTTP handler")?;
 
-        // Stop all plugins, log errors but continue stopping others
-        for (id, plugin) in &self.plugins {
+        if let Some(grpc) = &self.grpc {
+            grpc.stop();
+        }
+
+        // Stop all plugins in reverse dependency order, log errors but continue stopping others
+        for (id, plugin) in self.ordered_plugins().rev() {
             let stop_fut = plugin.stop();
             match tokio::time::timeout(std::time::Duration::from_secs(3), stop_fut).await {
                 Ok(Err(e)) => {
@@ -257,6 +794,100 @@ impl Host {
         Ok(())
     }
 
+    /// Returns the API key store, if the host manages API keys.
+    fn api_keys(&self) -> anyhow::Result<&ApiKeyStore> {
+        self.api_keys
+            .as_deref()
+            .context("this host doesn't manage API keys")
+    }
+
+    /// Iterates over plugins in dependency order, so that every plugin
+    /// comes after the plugins it depends on.
+    fn ordered_plugins(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (&'static str, &Arc<dyn HostPlugin>)> {
+        self.plugin_order
+            .iter()
+            .filter_map(|id| self.plugins.get(id).map(|plugin| (*id, plugin)))
+    }
+
+    /// Polls every plugin for its current health.
+    ///
+    /// Plugins that don't respond within 3 seconds are reported as unhealthy.
+    ///
+    /// # Returns
+    /// A map from plugin ID to its [`PluginHealth`].
+    pub async fn plugin_health(&self) -> HashMap<&'static str, PluginHealth> {
+        let mut health = HashMap::with_capacity(self.plugins.len());
+        for (id, plugin) in self.ordered_plugins() {
+            let plugin_health = match tokio::time::timeout(
+                std::time::Duration::from_secs(3),
+                plugin.health(),
+            )
+            .await
+            {
+                Ok(plugin_health) => plugin_health,
+                Err(_) => PluginHealth::Unhealthy("health check timed out".to_string()),
+            };
+            if !plugin_health.is_healthy() {
+                warn!(id, health = ?plugin_health, "plugin is not healthy");
+            }
+            health.insert(id, plugin_health);
+        }
+        health
+    }
+
+    /// Looks up a running workload.
+    ///
+    /// # Errors
+    /// Returns an error if the workload isn't found or isn't running.
+    async fn running_workload(&self, workload_id: &str) -> anyhow::Result<ResolvedWorkload> {
+        match self.workloads.read().await.get(workload_id) {
+            Some(HostWorkload::Running(workload)) => Ok(workload.as_ref().clone()),
+            Some(other) => Err(anyhow::anyhow!("it is {:?}", WorkloadState::from(other))).context(
+                HostError::WorkloadNotRunning {
+                    workload_id: workload_id.to_string(),
+                },
+            ),
+            None => bail!(HostError::WorkloadNotFound {
+                workload_id: workload_id.to_string(),
+            }),
+        }
+    }
+
+    /// Checks the health of the plugins bound to a running workload. When the host is
+    /// configured to pause routing for degraded workloads, routing is paused or resumed
+    /// to match.
+    ///
+    /// # Returns
+    /// The ID and health of the first unhealthy plugin the workload is bound to, if any.
+    async fn check_workload_health(
+        &self,
+        workload: &ResolvedWorkload,
+        plugin_health: &HashMap<&'static str, PluginHealth>,
+    ) -> Option<(&'static str, PluginHealth)> {
+        let mut bound: Vec<&'static str> = workload.plugin_ids().await.into_iter().collect();
+        bound.sort_unstable();
+        let unhealthy = bound.into_iter().find_map(|id| {
+            plugin_health
+                .get(id)
+                .filter(|health| !health.is_healthy())
+                .map(|health| (id, health.clone()))
+        });
+
+        if self.pause_degraded_routing
+            && workload.set_routing_paused(unhealthy.is_some()) != unhealthy.is_some()
+        {
+            debug!(
+                workload_id = workload.id(),
+                paused = unhealthy.is_some(),
+                "updated workload routing from plugin health"
+            );
+        }
+
+        unhealthy
+    }
+
     /// Get a label value by key.
     ///
     /// # Arguments
@@ -284,6 +915,31 @@ impl Host {
         &self.friendly_name
     }
 
+    /// Stops serving a workload's service to other hosts once it has no instances left on
+    /// this host.
+    async fn stop_serving_if_unused(&self, workload: &ResolvedWorkload) {
+        if let Some(wrpc) = &self.wrpc
+            && let Some(service) = workload.service_name()
+            && self
+                .services
+                .instance_count(workload.namespace(), service)
+                .await
+                == 0
+        {
+            wrpc.stop_serving(workload.namespace(), service);
+        }
+    }
+
+    /// Returns the registry of services published by this host's workloads.
+    pub fn services(&self) -> &Arc<ServiceRegistry> {
+        &self.services
+    }
+
+    /// Returns the log of outbound connections made by this host's workloads.
+    pub fn egress(&self) -> &Arc<EgressLog> {
+        &self.egress
+    }
+
     /// Returns the WIT (imports, exports) that this host can provide to any component.
     ///
     /// Put another way, this represents a simplified version of the host world. For
@@ -344,31 +1000,236 @@ impl Host {
         WitWorld { imports, exports }
     }
 
-    /// Returns a three-tuple of (OS architecture, OS name, OS kernel)
-    async fn get_system_info(&self) -> (String, String, String) {
-        // Get OS information
-        let os_name = std::env::consts::OS.to_string();
-        let os_arch = std::env::consts::ARCH.to_string();
-        let os_kernel = std::env::consts::FAMILY.to_string();
-        (os_arch, os_name, os_kernel)
-    }
-
-    /// Returns a tuple of (total memory, free memory)
-    async fn get_memory_info(&self) -> anyhow::Result<(u64, u64)> {
-        let monitor = self.system_monitor.read().await;
-        let mem = monitor.memory_usage();
-        Ok((mem.total_memory, mem.free_memory))
-    }
+    /// Initializes, resolves and runs a workload.
+    async fn start_workload(
+        &self,
+        mut request: WorkloadStartRequest,
+    ) -> anyhow::Result<WorkloadStartResponse> {
+        if let Some(allowed) = &self.host_path_volumes {
+            volumes::check(allowed, &request.workload.volumes).context(HostError::PolicyDenied)?;
+        }
+        // Create the cgroup before anything else is set up for the workload, so there's
+        // nothing to clean up if it fails. Dropping it removes it again.
+        let cgroup = match &self.cgroups {
+            Some(cgroups) => cgroups.create(
+                &request.workload_id,
+                request
+                    .workload
+                    .components
+                    .iter()
+                    .map(|component| &component.local_resources)
+                    .chain(
+                        request
+                            .workload
+                            .service
+                            .iter()
+                            .map(|service| &service.local_resources),
+                    ),
+            )?,
+            None => None,
+        };
 
-    /// Returns the current global CPU usage as a percentage
-    async fn get_cpu_usage(&self) -> anyhow::Result<f32> {
-        let monitor = self.system_monitor.read().await;
-        Ok(monitor.cpu_usage().global_usage)
-    }
-}
+        // Blobstore volumes become host path volumes of the downloaded containers
+        self.blob_volumes
+            .materialize(&request.workload_id, &mut request.workload)
+            .await?;
 
-impl HostApi for Host {
-    async fn heartbeat(&self) -> anyhow::Result<HostHeartbeat> {
+        // Store the workload with initial state
+        self.workloads
+            .write()
+            .await
+            .insert(request.workload_id.clone(), HostWorkload::Starting);
+
+        let service_present = request.workload.service.is_some();
+        let inventory = workload_inventory(&request.workload_id, &request.workload);
+
+        // Load the components other hosts compiled rather than compiling them again
+        let compiled = match &self.artifacts {
+            Some(artifacts) => artifacts.fetch(&self.engine, &request.workload).await,
+            None => Vec::new(),
+        };
+
+        // Initialize the workload using the engine, receiving the unresolved workload
+        let unresolved_workload = match self
+            .engine
+            .initialize_workload(&request.workload_id, request.workload)
+        {
+            Ok(unresolved_workload) => {
+                if let Some(artifacts) = &self.artifacts {
+                    artifacts.publish(&self.engine, compiled);
+                }
+                unresolved_workload
+            }
+            Err(e) => {
+                self.workloads.write().await.remove(&request.workload_id);
+                self.blob_volumes.release(&request.workload_id).await;
+                bail!(e);
+            }
+        };
+        let mut unresolved_workload = unresolved_workload
+            .with_service_registry(self.services.clone())
+            .with_egress_log(self.egress.clone())
+            .with_usage(self.usage.workload(&request.workload_id));
+        if let Some(wrpc) = &self.wrpc {
+            unresolved_workload = unresolved_workload.with_wrpc_transport(wrpc.clone());
+        }
+        if let Some(cgroup) = cgroup {
+            unresolved_workload = unresolved_workload.with_cgroup(cgroup);
+        }
+
+        let mut resolved_workload = match unresolved_workload
+            .resolve(Some(&self.plugins), self.http_handler.clone())
+            .await
+        {
+            Ok(resolved_workload) => resolved_workload,
+            Err(e) => {
+                self.workloads.write().await.remove(&request.workload_id);
+                self.blob_volumes.release(&request.workload_id).await;
+                bail!(e);
+            }
+        };
+
+        // Publish the workload's service, if named, for other workloads to import
+        if let Err(e) = self.services.register(&resolved_workload).await {
+            let _ = resolved_workload.unbind_all_plugins().await;
+            self.workloads.write().await.remove(&request.workload_id);
+            self.blob_volumes.release(&request.workload_id).await;
+            bail!(e);
+        }
+
+        // Let other hosts call the workload's service
+        if let Some(wrpc) = &self.wrpc
+            && let Some(service) = resolved_workload.service_name()
+            && let Err(e) = wrpc
+                .serve(
+                    self.services.clone(),
+                    resolved_workload.namespace(),
+                    service,
+                )
+                .await
+        {
+            self.services.deregister(&request.workload_id).await;
+            let _ = resolved_workload.unbind_all_plugins().await;
+            self.workloads.write().await.remove(&request.workload_id);
+            self.blob_volumes.release(&request.workload_id).await;
+            bail!(e);
+        }
+
+        // Serve the gRPC services the workload declares
+        let grpc = match &self.grpc {
+            Some(grpc) => grpc.register(&resolved_workload).await,
+            None => GrpcIngress::check_undeclared(resolved_workload.host_interfaces()),
+        };
+        if let Err(e) = grpc {
+            self.services.deregister(&request.workload_id).await;
+            self.stop_serving_if_unused(&resolved_workload).await;
+            let _ = resolved_workload.unbind_all_plugins().await;
+            self.workloads.write().await.remove(&request.workload_id);
+            self.blob_volumes.release(&request.workload_id).await;
+            bail!(e);
+        }
+
+        // Start pulling from the work queues the workload declares invokers for
+        if let Err(e) = self.invokers.start(&resolved_workload).await {
+            if let Some(grpc) = &self.grpc {
+                grpc.unregister(&request.workload_id);
+            }
+            self.services.deregister(&request.workload_id).await;
+            self.stop_serving_if_unused(&resolved_workload).await;
+            let _ = resolved_workload.unbind_all_plugins().await;
+            self.workloads.write().await.remove(&request.workload_id);
+            self.blob_volumes.release(&request.workload_id).await;
+            bail!(e);
+        }
+
+        // Audit the files the workload touches, without failing it if its volumes can't be
+        // watched, e.g. past the OS's limit on watches
+        if let Some(fs_audit) = &self.fs_audit
+            && let Err(e) = fs_audit.start(&resolved_workload).await
+        {
+            warn!(
+                workload_id = request.workload_id,
+                err = ?e,
+                "failed to audit workload volumes"
+            );
+        }
+
+        // If the service didn't run and we had one, warn
+        match resolved_workload.execute_service().await {
+            Ok(executed) if executed != service_present => warn!(
+                workload_id = request.workload_id,
+                "service did not properly execute"
+            ),
+            Ok(_) => {}
+            Err(e) => {
+                self.services.deregister(&request.workload_id).await;
+                self.stop_serving_if_unused(&resolved_workload).await;
+                self.invokers.stop(&request.workload_id);
+                if let Some(fs_audit) = &self.fs_audit {
+                    fs_audit.stop(&request.workload_id);
+                }
+                if let Some(grpc) = &self.grpc {
+                    grpc.unregister(&request.workload_id);
+                }
+                resolved_workload.stop_service();
+                let _ = resolved_workload.unbind_all_plugins().await;
+                self.workloads.write().await.remove(&request.workload_id);
+                self.blob_volumes.release(&request.workload_id).await;
+                bail!(e);
+            }
+        }
+
+        self.inventory
+            .write()
+            .await
+            .insert(request.workload_id.clone(), inventory);
+
+        // Update the workload state to `Running`
+        self.workloads
+            .write()
+            .await
+            .entry(request.workload_id.clone())
+            .and_modify(|workload| {
+                *workload = HostWorkload::Running(Box::new(resolved_workload));
+            });
+
+        Ok(WorkloadStartResponse {
+            workload_status: WorkloadStatus {
+                workload_id: request.workload_id,
+                workload_state: WorkloadState::Running,
+                message: "Workload started successfully".to_string(),
+                command_result: None,
+                job_status: None,
+                cron_job_status: None,
+            },
+        })
+    }
+
+    /// Returns a three-tuple of (OS architecture, OS name, OS kernel)
+    async fn get_system_info(&self) -> (String, String, String) {
+        // Get OS information
+        let os_name = std::env::consts::OS.to_string();
+        let os_arch = std::env::consts::ARCH.to_string();
+        let os_kernel = std::env::consts::FAMILY.to_string();
+        (os_arch, os_name, os_kernel)
+    }
+
+    /// Returns a tuple of (total memory, free memory)
+    async fn get_memory_info(&self) -> anyhow::Result<(u64, u64)> {
+        let monitor = self.system_monitor.read().await;
+        let mem = monitor.memory_usage();
+        Ok((mem.total_memory, mem.free_memory))
+    }
+
+    /// Returns the current global CPU usage as a percentage
+    async fn get_cpu_usage(&self) -> anyhow::Result<f32> {
+        let monitor = self.system_monitor.read().await;
+        Ok(monitor.cpu_usage().global_usage)
+    }
+}
+
+impl HostApi for Host {
+    async fn heartbeat(&self) -> anyhow::Result<HostHeartbeat> {
         // Refresh system info before reporting
         {
             let mut monitor = self.system_monitor.write().await;
@@ -386,6 +1247,8 @@ impl HostApi for Host {
             .await
             .context("failed to get CPU usage")?;
 
+        let plugin_health = self.plugin_health().await;
+
         // Count components and providers from workloads
         let (workload_count, component_count) = {
             let workloads = self.workloads.read().await;
@@ -394,6 +1257,7 @@ impl HostApi for Host {
             for workload in workloads.values() {
                 if let HostWorkload::Running(workload) = workload {
                     component_count += workload.component_count().await as u64;
+                    self.check_workload_health(workload, &plugin_health).await;
                 }
             }
             (workload_count, component_count)
@@ -426,6 +1290,10 @@ impl HostApi for Host {
             workload_count,
             imports,
             exports,
+            plugin_health: plugin_health
+                .into_iter()
+                .map(|(id, health)| (id.to_string(), health))
+                .collect(),
         })
     }
 
@@ -434,46 +1302,149 @@ impl HostApi for Host {
         &self,
         request: WorkloadStartRequest,
     ) -> anyhow::Result<WorkloadStartResponse> {
-        // Store the workload with initial state
-        self.workloads
-            .write()
+        // Secret values are registered first so they're redacted from start errors too
+        let workload_id = request.workload_id.clone();
+        redact::register(&workload_id, redact::secret_values(&request.workload));
+        let result = self
+            .start_workload(request)
             .await
-            .insert(request.workload_id.clone(), HostWorkload::Starting);
+            .map_err(redact::redact_error);
+        if result.is_err() {
+            redact::unregister(&workload_id);
+        }
+        result
+    }
 
-        let service_present = request.workload.service.is_some();
+    async fn workload_validate(
+        &self,
+        request: WorkloadValidateRequest,
+    ) -> anyhow::Result<WorkloadValidateResponse> {
+        let workload = &request.workload;
+        // Secret values are redacted from the report like from start errors
+        let validation_id = uuid::Uuid::new_v4().to_string();
+        redact::register(&validation_id, redact::secret_values(workload));
+        let mut diagnostics = Vec::new();
+        let mut report = |location: &str, error: anyhow::Error| {
+            diagnostics.push(WorkloadDiagnostic {
+                location: location.to_string(),
+                code: HostError::of(&error).code().to_string(),
+                message: redact::redact(&format!("{error:#}")).into_owned(),
+            });
+        };
 
-        // Initialize the workload using the engine, receiving the unresolved workload
-        let unresolved_workload = self
-            .engine
-            .initialize_workload(&request.workload_id, request.workload)?;
+        if let Some(allowed) = &self.host_path_volumes
+            && let Err(e) = volumes::check(allowed, &workload.volumes)
+        {
+            report("volumes", e.context(HostError::PolicyDenied));
+        }
 
-        let mut resolved_workload = unresolved_workload
-            .resolve(Some(&self.plugins), self.http_handler.clone())
-            .await?;
+        let components: Vec<(String, &[u8], &LocalResources)> = workload
+            .components
+            .iter()
+            .enumerate()
+            .map(|(i, component)| {
+                (
+                    format!("components[{i}]"),
+                    &component.bytes[..],
+                    &component.local_resources,
+                )
+            })
+            .chain(workload.service.iter().map(|service| {
+                (
+                    "service".to_string(),
+                    &service.bytes[..],
+                    &service.local_resources,
+                )
+            }))
+            .collect();
+        if let Some(cgroups) = &self.cgroups
+            && let Err(e) = cgroups.check(components.iter().map(|(_, _, resources)| *resources))
+        {
+            report("workload", e.context(HostError::InvalidRequest));
+        }
 
-        // If the service didn't run and we had one, warn
-        if resolved_workload.execute_service().await? != service_present {
-            warn!(
-                workload_id = request.workload_id,
-                "service did not properly execute"
-            );
+        let mut worlds = Vec::with_capacity(components.len());
+        for (location, bytes, resources) in &components {
+            if let Err(e) = crate::engine::workload::check_resources(resources) {
+                report(location, e.context(HostError::InvalidRequest));
+            }
+            match self.engine.compile_component(bytes) {
+                Ok(component) => worlds.push((
+                    location,
+                    crate::engine::inspect::component_world(&component),
+                )),
+                Err(e) => report(location, e.context(HostError::CompilationFailed)),
+            }
         }
 
-        // Update the workload state to `Running`
-        self.workloads
-            .write()
-            .await
-            .entry(request.workload_id.clone())
-            .and_modify(|workload| {
-                *workload = HostWorkload::Running(Box::new(resolved_workload));
-            });
+        // Imports are satisfied by the host and its plugins, the workload's other
+        // components, or the services the workload references
+        let host_world = self.wit_world();
+        for (location, world) in &worlds {
+            let mut unsatisfied: Vec<&WitInterface> = world
+                .imports
+                .iter()
+                .filter(|import| {
+                    !host_world.includes_bidirectional(import)
+                        && !worlds.iter().any(|(other, other_world)| {
+                            other != location
+                                && other_world.exports.iter().any(|e| e.contains(import))
+                        })
+                        && !workload.host_interfaces.iter().any(|interface| {
+                            services::service_reference(interface).is_some()
+                                && interface.contains(import)
+                        })
+                })
+                .collect();
+            unsatisfied.sort_by_key(|import| import.instance());
+            for import in unsatisfied {
+                report(
+                    location,
+                    anyhow::Error::new(HostError::InterfaceUnsatisfied {
+                        iface: import.to_string(),
+                    }),
+                );
+            }
+        }
 
-        Ok(WorkloadStartResponse {
-            workload_status: WorkloadStatus {
-                workload_id: request.workload_id,
-                workload_state: WorkloadState::Running,
-                message: "Workload started successfully".to_string(),
-            },
+        redact::unregister(&validation_id);
+        Ok(WorkloadValidateResponse { diagnostics })
+    }
+
+    async fn workload_lint(
+        &self,
+        request: WorkloadLintRequest,
+    ) -> anyhow::Result<WorkloadLintResponse> {
+        let workload = &request.workload;
+        let compiled: Vec<Option<lint::CompiledComponent>> = workload
+            .components
+            .iter()
+            .map(|component| &component.bytes)
+            .chain(workload.service.iter().map(|service| &service.bytes))
+            .map(|bytes| {
+                // Components that don't compile are reported by workload_validate
+                let component = self.engine.compile_component(bytes).ok()?;
+                Some(lint::CompiledComponent {
+                    world: crate::engine::inspect::component_world(&component),
+                    min_memory_bytes: crate::engine::inspect::min_memory_bytes(&component),
+                })
+            })
+            .collect();
+        let served_hosts: HashMap<String, String> = self
+            .workloads
+            .read()
+            .await
+            .iter()
+            .filter_map(|(id, workload)| match workload {
+                HostWorkload::Running(resolved) => Some((
+                    lint::virtual_host(resolved.host_interfaces())?.to_string(),
+                    id.clone(),
+                )),
+                _ => None,
+            })
+            .collect();
+        Ok(WorkloadLintResponse {
+            warnings: lint::lint(workload, &compiled, &served_hosts),
         })
     }
 
@@ -481,17 +1452,99 @@ impl HostApi for Host {
         &self,
         request: WorkloadStatusRequest,
     ) -> anyhow::Result<WorkloadStatusResponse> {
-        if let Some(workload) = self.workloads.read().await.get(&request.workload_id) {
-            let workload_state = workload.into();
+        let workload = self
+            .workloads
+            .read()
+            .await
+            .get(&request.workload_id)
+            .cloned();
+        if let Some(workload) = workload {
+            if let HostWorkload::Running(resolved) = &workload
+                && let Some(result) = resolved.command_result()
+            {
+                // Captured output can contain secret values like any other status message
+                let mut result = result.clone();
+                for output in [&mut result.stdout, &mut result.stderr] {
+                    if let Ok(text) = std::str::from_utf8(output)
+                        && let Cow::Owned(redacted) = redact::redact(text)
+                    {
+                        *output = redacted.into();
+                    }
+                }
+                // A job's status is final once its last run's result is recorded
+                let job_status = resolved.job_status();
+                let (workload_state, message) = match &job_status {
+                    Some(job) if job.state == JobState::Succeeded => (
+                        WorkloadState::Completed,
+                        format!("Job succeeded after {} run(s)", job.succeeded),
+                    ),
+                    Some(job) => (
+                        WorkloadState::Error,
+                        redact::redact(&format!("Job failed: {}", job.message)).into_owned(),
+                    ),
+                    None if result.exit_code == 0 => (
+                        WorkloadState::Completed,
+                        "Command exited with code 0".to_string(),
+                    ),
+                    None => (
+                        WorkloadState::Error,
+                        format!("Command exited with code {}", result.exit_code),
+                    ),
+                };
+                return Ok(WorkloadStatusResponse {
+                    workload_status: WorkloadStatus {
+                        workload_id: request.workload_id,
+                        workload_state,
+                        message,
+                        command_result: Some(result),
+                        job_status,
+                        cron_job_status: None,
+                    },
+                });
+            }
+            let (job_status, cron_job_status) = match &workload {
+                HostWorkload::Running(resolved) => {
+                    (resolved.job_status(), resolved.cron_job_status())
+                }
+                _ => (None, None),
+            };
+            if let HostWorkload::Running(resolved) = &workload {
+                let plugin_health = self.plugin_health().await;
+                if let Some((plugin_id, health)) =
+                    self.check_workload_health(resolved, &plugin_health).await
+                {
+                    return Ok(WorkloadStatusResponse {
+                        workload_status: WorkloadStatus {
+                            workload_id: request.workload_id,
+                            workload_state: WorkloadState::Degraded,
+                            message: redact::redact(&format!(
+                                "Workload is Degraded: plugin '{plugin_id}' is not healthy: {}",
+                                health.message().unwrap_or_default()
+                            ))
+                            .into_owned(),
+                            command_result: None,
+                            job_status,
+                            cron_job_status,
+                        },
+                    });
+                }
+            }
+
+            let workload_state = (&workload).into();
             Ok(WorkloadStatusResponse {
                 workload_status: WorkloadStatus {
                     workload_id: request.workload_id,
                     message: format!("Workload is {workload_state:?}"),
                     workload_state,
+                    command_result: None,
+                    job_status,
+                    cron_job_status,
                 },
             })
         } else {
-            anyhow::bail!("Workload not found: {}", request.workload_id)
+            bail!(HostError::WorkloadNotFound {
+                workload_id: request.workload_id,
+            })
         }
     }
 
@@ -533,7 +1586,16 @@ impl HostApi for Host {
                     "stopping workload"
                 );
 
-                // Stop the service if running
+                // Stop routing calls and messages to this workload, then stop the service
+                self.services.deregister(&request.workload_id).await;
+                self.stop_serving_if_unused(&resolved_workload).await;
+                self.invokers.stop(&request.workload_id);
+                if let Some(fs_audit) = &self.fs_audit {
+                    fs_audit.stop(&request.workload_id);
+                }
+                if let Some(grpc) = &self.grpc {
+                    grpc.unregister(&request.workload_id);
+                }
                 resolved_workload.stop_service();
 
                 // Unbind all plugins from the workload
@@ -549,6 +1611,11 @@ impl HostApi for Host {
             // Remove the workload from the active workloads map
             // This will drop the workload and clean up wasmtime resources
             self.workloads.write().await.remove(&request.workload_id);
+            self.egress.remove(&request.workload_id);
+            self.inventory.write().await.remove(&request.workload_id);
+            self.usage.remove(&request.workload_id);
+            self.blob_volumes.release(&request.workload_id).await;
+            redact::unregister(&request.workload_id);
 
             debug!(
                 workload_id = request.workload_id,
@@ -568,9 +1635,420 @@ impl HostApi for Host {
                 workload_id: request.workload_id,
                 workload_state,
                 message,
+                command_result: None,
+                job_status: None,
+                cron_job_status: None,
             },
         })
     }
+
+    async fn workload_list(
+        &self,
+        request: WorkloadListRequest,
+    ) -> anyhow::Result<WorkloadListResponse> {
+        let selector: LabelSelector = request
+            .selector
+            .as_deref()
+            .unwrap_or_default()
+            .parse()
+            .context(HostError::InvalidRequest)?;
+        let mut workloads: Vec<WorkloadSummary> = self
+            .workloads
+            .read()
+            .await
+            .iter()
+            .filter_map(|(id, workload)| match workload {
+                HostWorkload::Running(resolved) if selector.matches(resolved.annotations()) => {
+                    Some(WorkloadSummary {
+                        workload_id: id.clone(),
+                        namespace: resolved.namespace().to_string(),
+                        name: resolved.name().to_string(),
+                        annotations: resolved.annotations().clone(),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        workloads.sort_by(|a, b| a.workload_id.cmp(&b.workload_id));
+        Ok(WorkloadListResponse { workloads })
+    }
+
+    async fn workload_stop_selected(
+        &self,
+        request: WorkloadStopSelectedRequest,
+    ) -> anyhow::Result<WorkloadStopSelectedResponse> {
+        let selector: LabelSelector = request
+            .selector
+            .parse()
+            .context(HostError::InvalidRequest)?;
+        if selector.is_empty() {
+            bail!(
+                anyhow::Error::new(HostError::InvalidRequest)
+                    .context("a selector is required to stop workloads by selector")
+            );
+        }
+        let selected = self
+            .workload_list(WorkloadListRequest {
+                selector: Some(request.selector),
+            })
+            .await?;
+
+        let mut workload_statuses = Vec::with_capacity(selected.workloads.len());
+        for workload in selected.workloads {
+            debug!(
+                workload_id = workload.workload_id,
+                "stopping workload matching selector"
+            );
+            let response = self
+                .workload_stop(WorkloadStopRequest {
+                    workload_id: workload.workload_id,
+                })
+                .await?;
+            workload_statuses.push(response.workload_status);
+        }
+        Ok(WorkloadStopSelectedResponse { workload_statuses })
+    }
+
+    async fn component_inspect(
+        &self,
+        request: ComponentInspectRequest,
+    ) -> anyhow::Result<ComponentInspectResponse> {
+        // Compile the way a workload would, so adapted imports are reported as satisfied
+        let component = self
+            .engine
+            .compile_component(&request.bytes)
+            .context(HostError::CompilationFailed)?;
+
+        let world = crate::engine::inspect::component_world(&component);
+        let (imports, exports) = crate::engine::inspect::instance_names(&component);
+
+        let host_world = self.wit_world();
+        let mut unsatisfied_imports: Vec<WitInterface> = world
+            .imports
+            .iter()
+            .filter(|import| !host_world.includes_bidirectional(import))
+            .cloned()
+            .collect();
+        unsatisfied_imports.sort_by_key(|import| import.instance());
+
+        Ok(ComponentInspectResponse {
+            metadata: crate::engine::inspect::embedded_metadata(&request.bytes),
+            estimated_memory_bytes: crate::engine::inspect::estimated_memory_bytes(&component),
+            world,
+            imports,
+            exports,
+            unsatisfied_imports,
+        })
+    }
+
+    async fn component_diff(
+        &self,
+        request: ComponentDiffRequest,
+    ) -> anyhow::Result<ComponentDiffResponse> {
+        let workload = self.running_workload(&request.workload_id).await?;
+        let (component_id, running) = {
+            let components = workload.components();
+            let components = components.read().await;
+            let component = match &request.component_id {
+                Some(component_id) => components.get_key_value(component_id.as_str()),
+                None if components.len() == 1 => components.iter().next(),
+                None => bail!(
+                    anyhow::Error::new(HostError::InvalidRequest).context(format!(
+                        "workload {} has {} components, a component ID is required",
+                        request.workload_id,
+                        components.len()
+                    ))
+                ),
+            };
+            let Some((component_id, component)) = component else {
+                bail!(
+                    anyhow::Error::new(HostError::InvalidRequest).context(format!(
+                        "workload {} has no component '{}'",
+                        request.workload_id,
+                        request.component_id.unwrap_or_default()
+                    ))
+                );
+            };
+            (component_id.to_string(), component.metadata().interfaces())
+        };
+
+        let replacement = self
+            .engine
+            .compile_component(&request.bytes)
+            .context(HostError::CompilationFailed)?;
+        let changes = crate::engine::compat::diff(
+            &running,
+            &crate::engine::compat::ComponentInterfaces::of(&replacement),
+        );
+        debug!(
+            workload_id = request.workload_id,
+            component_id,
+            changes = changes.len(),
+            "compared component with its replacement"
+        );
+        Ok(ComponentDiffResponse {
+            workload_id: request.workload_id,
+            component_id,
+            changes,
+        })
+    }
+
+    async fn workload_inventory(
+        &self,
+        request: WorkloadInventoryRequest,
+    ) -> anyhow::Result<WorkloadInventoryResponse> {
+        let inventory = self.inventory.read().await;
+        let mut workloads: Vec<WorkloadInventory> = match &request.workload_id {
+            Some(workload_id) => vec![inventory.get(workload_id).cloned().with_context(|| {
+                HostError::WorkloadNotFound {
+                    workload_id: workload_id.clone(),
+                }
+            })?],
+            None => inventory.values().cloned().collect(),
+        };
+        workloads.sort_by(|a, b| a.workload_id.cmp(&b.workload_id));
+        Ok(WorkloadInventoryResponse { workloads })
+    }
+
+    async fn components_list(
+        &self,
+        request: ComponentsListRequest,
+    ) -> anyhow::Result<ComponentsListResponse> {
+        let inventory = self.inventory.read().await;
+        let mut components: BTreeMap<&str, LoadedComponent> = BTreeMap::new();
+        for workload in inventory.values() {
+            for component in workload.components.iter().chain(&workload.service) {
+                let loaded = components
+                    .entry(&component.sha256)
+                    .or_insert_with(|| loaded_component(component));
+                if let Some(source) = &component.source
+                    && !loaded.sources.contains(source)
+                {
+                    loaded.sources.push(source.clone());
+                }
+                if !loaded.workloads.contains(&workload.workload_id) {
+                    loaded.workloads.push(workload.workload_id.clone());
+                }
+            }
+        }
+
+        let components = components
+            .into_values()
+            .filter(|component| {
+                request
+                    .digest
+                    .as_deref()
+                    .is_none_or(|digest| has_digest(component, digest))
+            })
+            .map(|mut component| {
+                component.workloads.sort();
+                component
+            })
+            .collect();
+        Ok(ComponentsListResponse { components })
+    }
+
+    async fn workload_egress(
+        &self,
+        request: WorkloadEgressRequest,
+    ) -> anyhow::Result<WorkloadEgressResponse> {
+        if !self
+            .workloads
+            .read()
+            .await
+            .contains_key(&request.workload_id)
+        {
+            bail!(HostError::WorkloadNotFound {
+                workload_id: request.workload_id,
+            });
+        }
+
+        Ok(WorkloadEgressResponse {
+            records: self.egress.recent(&request.workload_id, request.limit),
+            destinations: self.egress.destinations(&request.workload_id),
+        })
+    }
+
+    async fn workload_usage(
+        &self,
+        request: WorkloadUsageRequest,
+    ) -> anyhow::Result<WorkloadUsageResponse> {
+        if !self
+            .workloads
+            .read()
+            .await
+            .contains_key(&request.workload_id)
+        {
+            bail!(HostError::WorkloadNotFound {
+                workload_id: request.workload_id,
+            });
+        }
+
+        Ok(self
+            .usage
+            .get(&request.workload_id)
+            .unwrap_or_else(|| WorkloadUsageResponse {
+                workload_id: request.workload_id,
+                memory_bytes: 0,
+                peak_memory_bytes: 0,
+                table_elements: 0,
+                instances: Vec::new(),
+                cpu_time: std::time::Duration::ZERO,
+                cpu_time_per_invocation: None,
+                fuel_consumed: None,
+                fuel_per_invocation: None,
+                invocations: 0,
+                plugin_ops: HashMap::new(),
+            }))
+    }
+
+    async fn workload_instances(
+        &self,
+        request: WorkloadInstancesRequest,
+    ) -> anyhow::Result<WorkloadInstancesResponse> {
+        if !self
+            .workloads
+            .read()
+            .await
+            .contains_key(&request.workload_id)
+        {
+            bail!(HostError::WorkloadNotFound {
+                workload_id: request.workload_id,
+            });
+        }
+
+        Ok(WorkloadInstancesResponse {
+            instances: self
+                .usage
+                .instances(&request.workload_id)
+                .unwrap_or_default(),
+            workload_id: request.workload_id,
+        })
+    }
+
+    async fn workload_pause(
+        &self,
+        request: WorkloadPauseRequest,
+    ) -> anyhow::Result<WorkloadPauseResponse> {
+        let was_paused = self.running_workload(&request.workload_id).await?.pause();
+        if !was_paused {
+            info!(workload_id = request.workload_id, "paused workload");
+        }
+        Ok(WorkloadPauseResponse {
+            workload_id: request.workload_id,
+            was_paused,
+        })
+    }
+
+    async fn workload_resume(
+        &self,
+        request: WorkloadResumeRequest,
+    ) -> anyhow::Result<WorkloadResumeResponse> {
+        let was_paused = self.running_workload(&request.workload_id).await?.resume();
+        if was_paused {
+            info!(workload_id = request.workload_id, "resumed workload");
+        }
+        Ok(WorkloadResumeResponse {
+            workload_id: request.workload_id,
+            was_paused,
+        })
+    }
+
+    async fn workload_scale(
+        &self,
+        request: WorkloadScaleRequest,
+    ) -> anyhow::Result<WorkloadScaleResponse> {
+        let previous = self
+            .running_workload(&request.workload_id)
+            .await?
+            .scale(request.component_id.as_deref(), request.pool_size)
+            .await?;
+        info!(
+            workload_id = request.workload_id,
+            component_id = request.component_id.as_deref(),
+            pool_size = request.pool_size,
+            "scaled workload"
+        );
+        Ok(WorkloadScaleResponse {
+            workload_id: request.workload_id,
+            previous,
+        })
+    }
+
+    async fn workload_update_resources(
+        &self,
+        request: WorkloadUpdateResourcesRequest,
+    ) -> anyhow::Result<WorkloadUpdateResourcesResponse> {
+        let component_ids = self
+            .running_workload(&request.workload_id)
+            .await?
+            .update_resources(request.component_id.as_deref(), &request.update)
+            .await?;
+        info!(
+            workload_id = request.workload_id,
+            ?component_ids,
+            "updated workload resources"
+        );
+        Ok(WorkloadUpdateResourcesResponse {
+            workload_id: request.workload_id,
+            component_ids,
+        })
+    }
+
+    async fn workload_invoke(
+        &self,
+        request: WorkloadInvokeRequest,
+    ) -> anyhow::Result<WorkloadInvokeResponse> {
+        let workload = self.running_workload(&request.workload_id).await?;
+
+        let results = match &request.params {
+            InvokeValues::Values(params) => workload
+                .invoke(&request.interface, &request.function, params)
+                .await
+                .map(InvokeValues::Values),
+            InvokeValues::Wave(args) => workload
+                .invoke_wave(&request.interface, &request.function, args)
+                .await
+                .map(InvokeValues::Wave),
+        }
+        .map_err(redact::redact_error)?;
+        Ok(WorkloadInvokeResponse { results })
+    }
+
+    async fn http_traffic_split(
+        &self,
+        request: HttpTrafficSplitRequest,
+    ) -> anyhow::Result<HttpTrafficSplitResponse> {
+        let previous = self
+            .http_handler
+            .set_traffic_split(&request.host, request.weights)
+            .await?;
+        Ok(HttpTrafficSplitResponse { previous })
+    }
+
+    async fn api_key_create(
+        &self,
+        request: ApiKeyCreateRequest,
+    ) -> anyhow::Result<ApiKeyCreateResponse> {
+        let (id, key) = self.api_keys()?.create(&request.name, request.hosts)?;
+        Ok(ApiKeyCreateResponse { id, key })
+    }
+
+    async fn api_key_rotate(
+        &self,
+        request: ApiKeyRotateRequest,
+    ) -> anyhow::Result<ApiKeyRotateResponse> {
+        let key = self.api_keys()?.rotate(&request.id)?;
+        Ok(ApiKeyRotateResponse { key })
+    }
+
+    async fn api_key_revoke(
+        &self,
+        request: ApiKeyRevokeRequest,
+    ) -> anyhow::Result<ApiKeyRevokeResponse> {
+        self.api_keys()?.revoke(&request.id)?;
+        Ok(ApiKeyRevokeResponse { id: request.id })
+    }
 }
 
 impl std::fmt::Debug for Host {
@@ -596,6 +2074,16 @@ pub struct HostBuilder {
     friendly_name: Option<String>,
     labels: HashMap<String, String>,
     http_handler: Option<Arc<dyn crate::host::http::HostHandler>>,
+    pause_degraded_routing: bool,
+    invokers: QueueInvokers,
+    wrpc: Option<Arc<WrpcTransport>>,
+    grpc_addr: Option<std::net::SocketAddr>,
+    api_keys: Option<Arc<ApiKeyStore>>,
+    cgroups: Option<Arc<Cgroups>>,
+    host_path_volumes: Option<Vec<PathBuf>>,
+    volume_blobstore: Option<Arc<dyn VolumeBlobstore>>,
+    fs_audit: Option<Arc<FsAudit>>,
+    artifact_store: Option<Arc<dyn ArtifactStore>>,
 }
 
 impl Default for HostBuilder {
@@ -608,6 +2096,16 @@ impl Default for HostBuilder {
             friendly_name: Default::default(),
             labels: Default::default(),
             http_handler: Default::default(),
+            pause_degraded_routing: false,
+            invokers: Default::default(),
+            wrpc: None,
+            grpc_addr: None,
+            api_keys: None,
+            cgroups: None,
+            host_path_volumes: None,
+            volume_blobstore: None,
+            fs_audit: None,
+            artifact_store: None,
         }
     }
 }
@@ -644,6 +2142,79 @@ impl HostBuilder {
         Ok(self)
     }
 
+    /// Registers a work queue source that workloads pull messages from with
+    /// [queue invokers](invoker), under the name they select it with.
+    ///
+    /// # Errors
+    /// Returns an error if a source with the name is already registered.
+    pub fn with_queue_source(
+        mut self,
+        name: impl Into<String>,
+        source: Arc<dyn QueueSource>,
+    ) -> anyhow::Result<Self> {
+        self.invokers.add_source(name, source)?;
+        Ok(self)
+    }
+
+    /// Sets the [wRPC transport](wrpc) used to call services that have no instances on this
+    /// host, and to serve this host's services to other hosts.
+    pub fn with_wrpc_transport(mut self, wrpc: Arc<WrpcTransport>) -> Self {
+        self.wrpc = Some(wrpc);
+        self
+    }
+
+    /// Serves the gRPC services workloads declare, and those generated for the interfaces
+    /// they publish, on the given address, see [`grpc`]. Services requiring an API key
+    /// accept the keys of [`Self::with_api_keys`].
+    pub fn with_grpc_ingress_addr(mut self, addr: std::net::SocketAddr) -> Self {
+        self.grpc_addr = Some(addr);
+        self
+    }
+
+    /// Manages API keys in `store` through the host API. Share the store with the HTTP
+    /// server to accept the keys there, see [`api_keys`].
+    pub fn with_api_keys(mut self, store: Arc<ApiKeyStore>) -> Self {
+        self.api_keys = Some(store);
+        self
+    }
+
+    /// Enforces the CPU limits of workloads and pins them to CPUs and NUMA nodes with
+    /// cgroups created under `cgroups`, see [`cgroups`].
+    pub fn with_cgroups(mut self, cgroups: Arc<Cgroups>) -> Self {
+        self.cgroups = Some(cgroups);
+        self
+    }
+
+    /// Only starts workloads whose host path volumes lie within one of `dirs`, see
+    /// [`volumes`]. Without it workloads can mount any directory of the host; with an
+    /// empty list they can't mount any.
+    pub fn with_host_path_volumes(mut self, dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.host_path_volumes = Some(dirs.into_iter().collect());
+        self
+    }
+
+    /// Lets workloads mount the containers of `blobstore` as volumes, see
+    /// [`blob_volumes`]. Without it workloads with blobstore volumes can't be started.
+    pub fn with_volume_blobstore(mut self, blobstore: Arc<dyn VolumeBlobstore>) -> Self {
+        self.volume_blobstore = Some(blobstore);
+        self
+    }
+
+    /// Logs a sample of the files running workloads open, create and delete in the
+    /// directories they mount, see [`fs_audit`].
+    pub fn with_fs_audit(mut self, audit: Arc<FsAudit>) -> Self {
+        self.fs_audit = Some(audit);
+        self
+    }
+
+    /// Shares the components the host compiles with other hosts through `store`, and loads
+    /// those they compiled from it, see [`artifacts`]. Only use a store no one but the
+    /// cluster's hosts can write to.
+    pub fn with_artifact_store(mut self, store: Arc<dyn ArtifactStore>) -> Self {
+        self.artifact_store = Some(store);
+        self
+    }
+
     /// Sets the hostname for this host.
     ///
     /// # Arguments
@@ -685,6 +2256,23 @@ impl HostBuilder {
         self
     }
 
+    /// Pauses routing of incoming HTTP requests to workloads while a plugin they are
+    /// bound to reports itself as unhealthy. Paused workloads respond with
+    /// `503 Service Unavailable` until the plugin recovers.
+    ///
+    /// Plugin health is evaluated whenever a heartbeat is produced or a workload's
+    /// status is queried.
+    ///
+    /// # Arguments
+    /// * `pause` - Whether to pause routing to degraded workloads
+    ///
+    /// # Returns
+    /// The builder instance for method chaining.
+    pub fn with_degraded_routing_paused(mut self, pause: bool) -> Self {
+        self.pause_degraded_routing = pause;
+        self
+    }
+
     /// Builds and returns a configured [`Host`].
     ///
     /// This method finalizes the configuration and creates the host.
@@ -696,8 +2284,20 @@ impl HostBuilder {
     /// A new `Host` instance ready to be started.
     ///
     /// # Errors
-    /// Returns an error if the default engine cannot be created (when no engine is provided).
+    /// Returns an error if the default engine cannot be created (when no engine is provided),
+    /// if two plugins provide the same interface, if plugin dependencies cannot be resolved,
+    /// or if a plugin declares an invalid config schema.
     pub fn build(self) -> anyhow::Result<Host> {
+        let plugin_order =
+            resolve_plugin_order(&self.plugins).context("failed to resolve host plugins")?;
+        for (id, plugin) in &self.plugins {
+            if let Some(schema) = plugin.config_schema() {
+                schema
+                    .check()
+                    .with_context(|| format!("invalid config schema for plugin '{id}'"))?;
+            }
+        }
+
         let engine = if let Some(engine) = self.engine {
             engine
         } else {
@@ -730,6 +2330,23 @@ impl HostBuilder {
             engine,
             workloads: Arc::default(),
             plugins: self.plugins,
+            plugin_order,
+            pause_degraded_routing: self.pause_degraded_routing,
+            services: Arc::default(),
+            egress: Arc::default(),
+            inventory: RwLock::default(),
+            usage: Arc::default(),
+            cgroups: self.cgroups,
+            host_path_volumes: self.host_path_volumes,
+            blob_volumes: BlobVolumes::new(self.volume_blobstore),
+            fs_audit: self.fs_audit,
+            artifacts: self.artifact_store.map(SharedArtifacts::new),
+            invokers: self.invokers,
+            wrpc: self.wrpc,
+            grpc: self
+                .grpc_addr
+                .map(|addr| Arc::new(GrpcIngress::new(addr, self.api_keys.clone()))),
+            api_keys: self.api_keys,
             id: self.id,
             hostname,
             friendly_name,
@@ -741,3 +2358,712 @@ impl HostBuilder {
         })
     }
 }
+
+/// Records what a workload is made of and where it came from, before it's started.
+fn workload_inventory(workload_id: &str, workload: &Workload) -> WorkloadInventory {
+    WorkloadInventory {
+        workload_id: workload_id.to_string(),
+        namespace: workload.namespace.clone(),
+        name: workload.name.clone(),
+        components: workload
+            .components
+            .iter()
+            .map(|component| {
+                crate::engine::inspect::component_inventory(
+                    &component.bytes,
+                    component.source.clone(),
+                )
+            })
+            .collect(),
+        service: workload.service.as_ref().map(|service| {
+            crate::engine::inspect::component_inventory(&service.bytes, service.source.clone())
+        }),
+    }
+}
+
+/// Starts the entry of a component in [`HostApi::components_list`], without the sources and
+/// workloads it's loaded from.
+fn loaded_component(component: &ComponentInventory) -> LoadedComponent {
+    let annotation = |key: &str| {
+        component
+            .source
+            .as_ref()
+            .and_then(|source| source.annotations.get(key))
+            .cloned()
+    };
+    let claims = component.claims.as_ref();
+    LoadedComponent {
+        sha256: component.sha256.clone(),
+        name: claims
+            .and_then(|claims| claims.name.clone())
+            .or_else(|| annotation("org.opencontainers.image.title")),
+        version: component
+            .metadata
+            .get("version")
+            .cloned()
+            .or_else(|| claims.and_then(|claims| claims.version.clone()))
+            .or_else(|| annotation("org.opencontainers.image.version")),
+        claims: component.claims.clone(),
+        sources: Vec::new(),
+        workloads: Vec::new(),
+    }
+}
+
+/// Whether a component has a digest, either of its bytes or of an image it was pulled from.
+fn has_digest(component: &LoadedComponent, digest: &str) -> bool {
+    let sha256 = digest.strip_prefix("sha256:").unwrap_or(digest);
+    component.sha256.eq_ignore_ascii_case(sha256)
+        || component
+            .sources
+            .iter()
+            .any(|source| source.digest.eq_ignore_ascii_case(digest))
+}
+
+/// Resolves the order in which plugins are started, placing every plugin after
+/// the plugins it depends on.
+///
+/// Plugins are visited in ID order so that the resulting order (and any error
+/// message) is deterministic.
+///
+/// # Errors
+/// Returns an error if two plugins provide the same interface, if a dependency is
+/// not satisfied by any registered plugin, or if the dependencies form a cycle.
+fn resolve_plugin_order(
+    plugins: &HashMap<&'static str, Arc<dyn HostPlugin>>,
+) -> anyhow::Result<Vec<&'static str>> {
+    let mut ids: Vec<&'static str> = plugins.keys().copied().collect();
+    ids.sort_unstable();
+
+    let worlds: HashMap<&'static str, WitWorld> =
+        plugins.iter().map(|(id, p)| (*id, p.world())).collect();
+
+    // Two plugins providing the same interface would both try to link it
+    for (i, a) in ids.iter().enumerate() {
+        for b in &ids[i + 1..] {
+            let (Some(world_a), Some(world_b)) = (worlds.get(a), worlds.get(b)) else {
+                continue;
+            };
+            for ia in &world_a.imports {
+                if let Some(name) = world_b
+                    .imports
+                    .iter()
+                    .find_map(|ib| overlapping_interface(ia, ib))
+                {
+                    bail!(
+                        "plugins '{a}' and '{b}' both provide '{}:{}/{name}'",
+                        ia.namespace,
+                        ia.package
+                    );
+                }
+            }
+        }
+    }
+
+    let mut edges: HashMap<&'static str, Vec<&'static str>> = HashMap::new();
+    for id in &ids {
+        let Some(plugin) = plugins.get(id) else {
+            continue;
+        };
+        let mut deps = Vec::new();
+        for dependency in plugin.dependencies() {
+            let resolved = match &dependency {
+                PluginDependency::Plugin(dep) => plugins.contains_key(dep).then_some(*dep),
+                PluginDependency::Interface(interface) => ids.iter().copied().find(|other| {
+                    worlds
+                        .get(other)
+                        .is_some_and(|w| w.imports.iter().any(|i| i.contains(interface)))
+                }),
+            };
+            match resolved {
+                Some(dep) if dep == *id => bail!("plugin '{id}' cannot depend on itself"),
+                Some(dep) => deps.push(dep),
+                None => bail!(
+                    "plugin '{id}' depends on {dependency}, which is not provided by any registered plugin"
+                ),
+            }
+        }
+        edges.insert(*id, deps);
+    }
+
+    let mut order = Vec::with_capacity(ids.len());
+    let mut visited = HashSet::new();
+    for id in &ids {
+        visit_plugin(id, &edges, &mut visited, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Depth-first visit used by [`resolve_plugin_order`], pushing `id` onto `order`
+/// after all of its dependencies.
+fn visit_plugin(
+    id: &'static str,
+    edges: &HashMap<&'static str, Vec<&'static str>>,
+    visited: &mut HashSet<&'static str>,
+    path: &mut Vec<&'static str>,
+    order: &mut Vec<&'static str>,
+) -> anyhow::Result<()> {
+    if visited.contains(&id) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|p| *p == id) {
+        let cycle = path[start..]
+            .iter()
+            .chain(std::iter::once(&id))
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" -> ");
+        bail!("plugin dependency cycle detected: {cycle}");
+    }
+
+    path.push(id);
+    for dep in edges.get(&id).into_iter().flatten() {
+        visit_plugin(dep, edges, visited, path, order)?;
+    }
+    path.pop();
+
+    visited.insert(id);
+    order.push(id);
+    Ok(())
+}
+
+/// Returns an interface name that both `a` and `b` provide, if any. Unversioned
+/// interfaces are treated as compatible with every version.
+fn overlapping_interface(a: &WitInterface, b: &WitInterface) -> Option<String> {
+    if a.namespace != b.namespace || a.package != b.package {
+        return None;
+    }
+    // Plugins on different compatibility tracks (e.g. 0.2.x and 0.3.x) can coexist
+    if !a.version_compatible(b) {
+        return None;
+    }
+    a.interfaces.intersection(&b.interfaces).min().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestPlugin {
+        id: &'static str,
+        world: &'static str,
+        dependencies: Vec<PluginDependency>,
+        health: PluginHealth,
+    }
+
+    #[async_trait::async_trait]
+    impl HostPlugin for TestPlugin {
+        fn id(&self) -> &'static str {
+            self.id
+        }
+
+        fn world(&self) -> WitWorld {
+            WitWorld {
+                imports: HashSet::from([WitInterface::from(self.world)]),
+                ..Default::default()
+            }
+        }
+
+        fn dependencies(&self) -> Vec<PluginDependency> {
+            self.dependencies.clone()
+        }
+
+        async fn health(&self) -> PluginHealth {
+            self.health.clone()
+        }
+    }
+
+    fn plugin(
+        id: &'static str,
+        world: &'static str,
+        dependencies: Vec<PluginDependency>,
+    ) -> Arc<TestPlugin> {
+        Arc::new(TestPlugin {
+            id,
+            world,
+            dependencies,
+            health: PluginHealth::Healthy,
+        })
+    }
+
+    #[test]
+    fn test_plugin_order_respects_dependencies() -> anyhow::Result<()> {
+        let host = HostBuilder::new()
+            .with_plugin(plugin(
+                "a-metrics",
+                "wasmcloud:metrics/counter",
+                vec![PluginDependency::Interface("wasi:keyvalue/store".into())],
+            ))?
+            .with_plugin(plugin(
+                "b-keyvalue",
+                "wasi:keyvalue/store,atomics@0.2.0-draft",
+                vec![PluginDependency::Plugin("c-logging")],
+            ))?
+            .with_plugin(plugin("c-logging", "wasi:logging/logging", vec![]))?
+            .build()?;
+
+        assert_eq!(
+            host.plugin_order,
+            vec!["c-logging", "b-keyvalue", "a-metrics"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_interface_conflict() -> anyhow::Result<()> {
+        let err = HostBuilder::new()
+            .with_plugin(plugin("kv-one", "wasi:keyvalue/store,atomics", vec![]))?
+            .with_plugin(plugin("kv-two", "wasi:keyvalue/store@0.2.0-draft", vec![]))?
+            .build()
+            .expect_err("conflicting plugins should fail to build");
+        assert!(
+            format!("{err:#}")
+                .contains("plugins 'kv-one' and 'kv-two' both provide 'wasi:keyvalue/store'")
+        );
+
+        // Distinct versions of the same interface can be provided side by side
+        HostBuilder::new()
+            .with_plugin(plugin("kv-one", "wasi:keyvalue/store@0.2.0-draft", vec![]))?
+            .with_plugin(plugin("kv-two", "wasi:keyvalue/store@0.2.0", vec![]))?
+            .build()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_dependency_errors() -> anyhow::Result<()> {
+        let err = HostBuilder::new()
+            .with_plugin(plugin(
+                "metrics",
+                "wasmcloud:metrics/counter",
+                vec![PluginDependency::Plugin("missing")],
+            ))?
+            .build()
+            .expect_err("missing dependency should fail to build");
+        assert!(format!("{err:#}").contains("plugin 'metrics' depends on plugin 'missing'"));
+
+        let err = HostBuilder::new()
+            .with_plugin(plugin(
+                "a",
+                "wasmcloud:a/a",
+                vec![PluginDependency::Plugin("b")],
+            ))?
+            .with_plugin(plugin(
+                "b",
+                "wasmcloud:b/b",
+                vec![PluginDependency::Plugin("a")],
+            ))?
+            .build()
+            .expect_err("cyclic dependencies should fail to build");
+        assert!(format!("{err:#}").contains("plugin dependency cycle detected: a -> b -> a"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_plugin_health_in_heartbeat() -> anyhow::Result<()> {
+        let host = HostBuilder::new()
+            .with_plugin(plugin("logging", "wasi:logging/logging", vec![]))?
+            .with_plugin(Arc::new(TestPlugin {
+                id: "keyvalue",
+                world: "wasi:keyvalue/store",
+                dependencies: vec![],
+                health: PluginHealth::Unhealthy("redis unreachable".to_string()),
+            }))?
+            .build()?;
+
+        let heartbeat = host.heartbeat().await?;
+        assert_eq!(
+            heartbeat.plugin_health.get("logging"),
+            Some(&PluginHealth::Healthy)
+        );
+        assert_eq!(
+            heartbeat.plugin_health.get("keyvalue"),
+            Some(&PluginHealth::Unhealthy("redis unreachable".to_string()))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_workload_invoke_unknown_workload() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let err = host
+            .workload_invoke(WorkloadInvokeRequest {
+                workload_id: "missing".to_string(),
+                interface: "acme:inventory/query".to_string(),
+                function: "stock".to_string(),
+                params: InvokeValues::Wave(vec!["\"sku-1\"".to_string()]),
+            })
+            .await
+            .expect_err("invoking an unknown workload should fail");
+        assert_eq!(err.to_string(), "workload 'missing' not found");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_workload_list_by_selector() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let response = host
+            .workload_list(WorkloadListRequest {
+                selector: Some("app=checkout,env in (prod)".to_string()),
+            })
+            .await?;
+        assert!(response.workloads.is_empty());
+        assert!(
+            host.workload_list(WorkloadListRequest {
+                selector: Some("env in prod".to_string()),
+            })
+            .await
+            .is_err()
+        );
+
+        // Stopping by selector needs one, so every workload isn't stopped by mistake
+        let err = host
+            .workload_stop_selected(WorkloadStopSelectedRequest {
+                selector: " ".to_string(),
+            })
+            .await
+            .expect_err("an empty selector should be rejected");
+        assert!(err.to_string().contains("selector is required"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_workload_inventory() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let response = host
+            .workload_inventory(WorkloadInventoryRequest::default())
+            .await?;
+        assert!(response.workloads.is_empty());
+        assert!(
+            host.workload_inventory(WorkloadInventoryRequest {
+                workload_id: Some("missing".to_string()),
+            })
+            .await
+            .is_err()
+        );
+
+        let workload = Workload {
+            namespace: "default".to_string(),
+            name: "counter".to_string(),
+            components: vec![Component {
+                bytes: bytes::Bytes::from_static(include_bytes!(
+                    "../../tests/fixtures/http_counter.wasm"
+                )),
+                source: Some(ComponentSource {
+                    image: "ghcr.io/wasmcloud/components/http-counter:0.1.0".to_string(),
+                    digest: "sha256:abcd1234".to_string(),
+                    annotations: HashMap::new(),
+                }),
+                ..Default::default()
+            }],
+            annotations: HashMap::new(),
+            service: None,
+            host_interfaces: vec![],
+            volumes: vec![],
+            secret_config_keys: HashSet::new(),
+        };
+        let inventory = workload_inventory("counter-1", &workload);
+        assert_eq!(inventory.components.len(), 1);
+        assert_eq!(
+            inventory.components[0]
+                .source
+                .as_ref()
+                .map(|source| source.digest.as_str()),
+            Some("sha256:abcd1234")
+        );
+        // Rust components list their language in their producers
+        assert!(inventory.components[0].producers.contains_key("language"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_workload_validate() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let workload = Workload {
+            namespace: "default".to_string(),
+            name: "counter".to_string(),
+            components: vec![
+                Component {
+                    bytes: bytes::Bytes::from_static(include_bytes!(
+                        "../../tests/fixtures/http_counter.wasm"
+                    )),
+                    ..Default::default()
+                },
+                Component {
+                    bytes: bytes::Bytes::from_static(b"not a component"),
+                    ..Default::default()
+                },
+            ],
+            annotations: HashMap::new(),
+            service: None,
+            host_interfaces: vec![],
+            volumes: vec![],
+            secret_config_keys: HashSet::new(),
+        };
+        let response = host
+            .workload_validate(WorkloadValidateRequest { workload })
+            .await?;
+        assert!(
+            response
+                .diagnostics
+                .iter()
+                .any(|d| d.location == "components[1]" && d.code == "compilation_failed")
+        );
+        // The counter compiles, but the host has no plugins for its imports
+        assert!(
+            response
+                .diagnostics
+                .iter()
+                .filter(|d| d.location == "components[0]")
+                .all(|d| d.code == "interface_unsatisfied")
+        );
+        // Nothing was started
+        assert!(host.workloads.read().await.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_components_list() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let component = ComponentInventory {
+            sha256: "ab".repeat(32),
+            source: Some(ComponentSource {
+                image: "ghcr.io/acme/api:1.0.0".to_string(),
+                digest: "sha256:1234".to_string(),
+                annotations: HashMap::from([(
+                    "org.opencontainers.image.title".to_string(),
+                    "api".to_string(),
+                )]),
+            }),
+            metadata: HashMap::from([("version".to_string(), "1.0.0".to_string())]),
+            ..Default::default()
+        };
+        for workload_id in ["b", "a"] {
+            host.inventory.write().await.insert(
+                workload_id.to_string(),
+                WorkloadInventory {
+                    workload_id: workload_id.to_string(),
+                    namespace: "default".to_string(),
+                    name: "api".to_string(),
+                    components: vec![component.clone()],
+                    service: None,
+                },
+            );
+        }
+
+        let response = host
+            .components_list(ComponentsListRequest::default())
+            .await?;
+        let [loaded] = response.components.as_slice() else {
+            panic!("expected one distinct component");
+        };
+        assert_eq!(loaded.name.as_deref(), Some("api"));
+        assert_eq!(loaded.version.as_deref(), Some("1.0.0"));
+        assert_eq!(loaded.sources.len(), 1);
+        assert_eq!(loaded.workloads, ["a", "b"]);
+
+        // Components are found by the digest of their bytes or of their image
+        for digest in [
+            format!("sha256:{}", "AB".repeat(32)),
+            "sha256:1234".to_string(),
+        ] {
+            let response = host
+                .components_list(ComponentsListRequest {
+                    digest: Some(digest),
+                })
+                .await?;
+            assert_eq!(response.components.len(), 1);
+        }
+        let response = host
+            .components_list(ComponentsListRequest {
+                digest: Some("sha256:5678".to_string()),
+            })
+            .await?;
+        assert!(response.components.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_component_inspect() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let response = host
+            .component_inspect(ComponentInspectRequest {
+                bytes: bytes::Bytes::from_static(include_bytes!(
+                    "../../tests/fixtures/http_counter.wasm"
+                )),
+            })
+            .await?;
+
+        assert!(
+            response
+                .exports
+                .iter()
+                .any(|e| e.starts_with("wasi:http/incoming-handler"))
+        );
+        assert!(!response.imports.is_empty());
+        assert!(response.estimated_memory_bytes > 0);
+        // No blobstore plugin is registered, so that import can't be satisfied
+        assert!(
+            response
+                .unsatisfied_imports
+                .iter()
+                .any(|i| i.namespace == "wasi" && i.package == "blobstore")
+        );
+        Ok(())
+    }
+
+    /// Adds a running workload with an empty component, which imports and exports nothing,
+    /// to a host, returning the workload and the component's ID.
+    async fn add_running_workload(
+        host: &Host,
+        workload_id: &str,
+    ) -> anyhow::Result<(ResolvedWorkload, String)> {
+        use crate::engine::workload::{UnresolvedWorkload, WorkloadComponent};
+
+        let engine = wasmtime::Engine::default();
+        let component = WorkloadComponent::new(
+            workload_id.to_string(),
+            "api".to_string(),
+            "default".to_string(),
+            wasmtime::component::Component::new(&engine, b"\0asm\x0d\x00\x01\x00")?,
+            wasmtime::component::Linker::new(&engine),
+            Vec::new(),
+            LocalResources::default(),
+        );
+        let component_id = component.id().to_string();
+        let workload = UnresolvedWorkload::new(
+            workload_id.to_string(),
+            "api".to_string(),
+            "default".to_string(),
+            None,
+            vec![component],
+            vec![],
+        )
+        .resolve(None, Arc::new(crate::host::http::NullServer::default()))
+        .await?;
+        host.workloads.write().await.insert(
+            workload_id.to_string(),
+            HostWorkload::Running(Box::new(workload.clone())),
+        );
+        Ok((workload, component_id))
+    }
+
+    #[tokio::test]
+    async fn test_workload_pause_and_resume() -> anyhow::Result<()> {
+        let host = HostBuilder::new()
+            .with_plugin(plugin("logging", "wasi:logging/logging", vec![]))?
+            .with_degraded_routing_paused(true)
+            .build()?;
+        let (workload, _) = add_running_workload(&host, "api").await?;
+        let pause = async || {
+            host.workload_pause(WorkloadPauseRequest {
+                workload_id: "api".to_string(),
+            })
+            .await
+        };
+        let resume = async || {
+            host.workload_resume(WorkloadResumeRequest {
+                workload_id: "api".to_string(),
+            })
+            .await
+        };
+
+        assert!(!pause().await?.was_paused);
+        assert!(pause().await?.was_paused);
+        assert!(workload.is_routing_paused());
+
+        // Every plugin is healthy, which resumes routing, but not an operator's pause
+        let plugin_health = host.plugin_health().await;
+        assert_eq!(
+            host.check_workload_health(&workload, &plugin_health).await,
+            None
+        );
+        assert!(workload.is_routing_paused());
+
+        assert!(resume().await?.was_paused);
+        assert!(!resume().await?.was_paused);
+        assert!(!workload.is_routing_paused());
+
+        let err = host
+            .workload_pause(WorkloadPauseRequest {
+                workload_id: "missing".to_string(),
+            })
+            .await
+            .expect_err("pausing an unknown workload should fail");
+        assert!(matches!(
+            HostError::of(&err),
+            HostError::WorkloadNotFound { .. }
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_workload_scale_and_update_resources() -> anyhow::Result<()> {
+        let host = HostBuilder::new().build()?;
+        let (_, component_id) = add_running_workload(&host, "api").await?;
+
+        let scale = async |workload_id: &str, component_id: Option<&str>| {
+            host.workload_scale(WorkloadScaleRequest {
+                workload_id: workload_id.to_string(),
+                component_id: component_id.map(str::to_string),
+                pool_size: 2,
+            })
+            .await
+        };
+        let err = scale("api", Some("missing"))
+            .await
+            .expect_err("scaling an unknown component should fail");
+        assert_eq!(HostError::of(&err), HostError::InvalidRequest);
+        let err = scale("missing", None)
+            .await
+            .expect_err("scaling an unknown workload should fail");
+        assert!(matches!(
+            HostError::of(&err),
+            HostError::WorkloadNotFound { .. }
+        ));
+        assert_eq!(
+            scale("api", Some(&component_id)).await?.previous,
+            vec![(component_id.clone(), 0)]
+        );
+
+        let update = async |component_id: Option<&str>| {
+            host.workload_update_resources(WorkloadUpdateResourcesRequest {
+                workload_id: "api".to_string(),
+                component_id: component_id.map(str::to_string),
+                update: LocalResourcesUpdate {
+                    memory_limit_mb: Some(64),
+                    ..Default::default()
+                },
+            })
+            .await
+        };
+        let err = update(Some("missing"))
+            .await
+            .expect_err("updating an unknown component should fail");
+        assert_eq!(HostError::of(&err), HostError::InvalidRequest);
+        assert_eq!(update(None).await?.component_ids, vec![component_id]);
+
+        // The workload has no live instances
+        let usage = host
+            .workload_usage(WorkloadUsageRequest {
+                workload_id: "api".to_string(),
+            })
+            .await?;
+        assert!(usage.instances.is_empty());
+        let instances = host
+            .workload_instances(WorkloadInstancesRequest {
+                workload_id: "api".to_string(),
+            })
+            .await?;
+        assert!(instances.instances.is_empty());
+        let err = host
+            .workload_instances(WorkloadInstancesRequest {
+                workload_id: "missing".to_string(),
+            })
+            .await
+            .expect_err("an unknown workload has no instances");
+        assert!(matches!(
+            HostError::of(&err),
+            HostError::WorkloadNotFound { .. }
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+//! PROXY protocol v2 on the HTTP server's listener, for hosts behind a TCP load balancer
+//! such as HAProxy or an AWS NLB.
+//!
+//! With [`crate::host::http::HttpServer::with_proxy_protocol`], every connection has to
+//! start with a [PROXY protocol v2] header naming the client the load balancer accepted
+//! the connection from. Connections without one are closed, since a header from anyone
+//! but the load balancer can't be trusted. The load balancer's own health checks, sent
+//! with the `LOCAL` command, keep the load balancer's address.
+//!
+//! Workloads see the client's address in the `forwarded` and `x-forwarded-for` headers
+//! of each request. They replace any the client sent, unless the server's
+//! [`crate::host::forwarded::ForwardedPolicy`] says otherwise.
+//!
+//! [PROXY protocol v2]: https://www.haproxy.org/download/2.9/doc/proxy-protocol.txt
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// How long a connection has to send its PROXY protocol header.
+pub const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The bytes every PROXY protocol v2 header starts with.
+const SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// Reads the PROXY protocol v2 header at the start of a connection, leaving the rest of
+/// the connection unread.
+///
+/// # Returns
+/// The client's address, or `None` for the load balancer's own connections and clients
+/// whose address doesn't fit in a socket address, such as over a Unix socket.
+///
+/// # Errors
+/// Returns an error if the connection doesn't start with a valid header.
+pub async fn read_header<R: AsyncRead + Unpin>(stream: &mut R) -> io::Result<Option<SocketAddr>> {
+    let mut header = [0u8; 16];
+    stream.read_exact(&mut header).await?;
+    if header[..12] != SIGNATURE {
+        return Err(invalid(
+            "the connection didn't start with a PROXY protocol v2 header",
+        ));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+    let mut addresses = vec![0u8; usize::from(u16::from_be_bytes([header[14], header[15]]))];
+    stream.read_exact(&mut addresses).await?;
+
+    match header[12] & 0x0f {
+        // LOCAL, sent by the load balancer for its own connections
+        0x0 => return Ok(None),
+        // PROXY
+        0x1 => {}
+        _ => return Err(invalid("unknown PROXY protocol command")),
+    }
+    // Anything after the addresses is TLVs, which are ignored
+    match header[13] >> 4 {
+        // AF_INET
+        0x1 => {
+            let addresses: &[u8; 12] = addresses
+                .first_chunk()
+                .ok_or_else(|| invalid("PROXY protocol IPv4 addresses are truncated"))?;
+            let ip = Ipv4Addr::from(*addresses.first_chunk::<4>().expect("the chunk fits"));
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(IpAddr::V4(ip), port)))
+        }
+        // AF_INET6
+        0x2 => {
+            let addresses: &[u8; 36] = addresses
+                .first_chunk()
+                .ok_or_else(|| invalid("PROXY protocol IPv6 addresses are truncated"))?;
+            let ip = Ipv6Addr::from(*addresses.first_chunk::<16>().expect("the chunk fits"));
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(IpAddr::V6(ip), port)))
+        }
+        // AF_UNSPEC and AF_UNIX
+        _ => Ok(None),
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = SIGNATURE.to_vec();
+        header.extend([0x20 | command, family << 4 | 0x1]);
+        header.extend((addresses.len() as u16).to_be_bytes());
+        header.extend(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn test_read_header() -> anyhow::Result<()> {
+        let mut ipv4 = vec![203, 0, 113, 7, 10, 0, 0, 1];
+        ipv4.extend(51234u16.to_be_bytes());
+        ipv4.extend(443u16.to_be_bytes());
+        // A TLV the header may carry
+        ipv4.extend([0x04, 0x00, 0x01, 0x00]);
+        let mut stream = header(0x1, 0x1, &ipv4);
+        stream.extend(b"GET / HTTP/1.1\r\n");
+
+        let mut reader = stream.as_slice();
+        let client = read_header(&mut reader).await?;
+        assert_eq!(client, Some("203.0.113.7:51234".parse()?));
+        assert_eq!(reader, b"GET / HTTP/1.1\r\n", "the request is left unread");
+
+        let mut ipv6 = "2001:db8::7".parse::<Ipv6Addr>()?.octets().to_vec();
+        ipv6.extend([0; 16]);
+        ipv6.extend(51234u16.to_be_bytes());
+        ipv6.extend(443u16.to_be_bytes());
+        let client = read_header(&mut header(0x1, 0x2, &ipv6).as_slice()).await?;
+        assert_eq!(client, Some("[2001:db8::7]:51234".parse()?));
+
+        let client = read_header(&mut header(0x0, 0x0, &[]).as_slice()).await?;
+        assert_eq!(client, None, "LOCAL connections keep their address");
+
+        for stream in [
+            b"GET / HTTP/1.1\r\nHost: example.com\r\n".to_vec(),
+            header(0x1, 0x1, &[203, 0, 113, 7]),
+        ] {
+            assert!(read_header(&mut stream.as_slice()).await.is_err());
+        }
+        Ok(())
+    }
+}
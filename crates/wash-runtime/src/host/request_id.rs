@@ -0,0 +1,187 @@
+//! Request IDs for the HTTP server, so a single request can be followed through the
+//! host's logs, the workload handling it and the backends it calls.
+//!
+//! Every request gets an ID as it arrives: the one in its request ID header, if it has a
+//! usable one and incoming IDs are honored, or else a new UUID. The ID is:
+//!
+//! - set in the request's header, so workloads and filters see it
+//! - recorded on the `http_request` tracing span the request is handled in, which the
+//!   logs of the component and the plugins it calls are nested in. The span also records
+//!   the trace ID of the request's trace context, see [`crate::host::trace_context`].
+//!   Its baggage is read alongside, see [`crate::host::baggage`].
+//! - set on the outgoing HTTP requests the component sends without one
+//! - copied onto the response, and logged along with its status and latency under the
+//!   `wash_runtime::access` target at debug level
+//!
+//! The header and whether incoming IDs are honored are set with
+//! [`crate::host::http::HttpServer::with_request_ids`]. A workload's `request-id` header
+//! rule, see [`crate::host::headers`], still applies on top for other header names.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::Context as _;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::{Instrument as _, debug};
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+
+use crate::host::baggage::BaggageConfig;
+use crate::host::trace_context::TraceContext;
+
+/// The header request IDs are carried in by default.
+pub const DEFAULT_HEADER: &str = "x-request-id";
+/// The longest incoming request ID that's honored.
+const MAX_INCOMING_LEN: usize = 200;
+
+/// How the HTTP server assigns request IDs.
+#[derive(Clone, Debug)]
+pub struct RequestIdConfig {
+    /// The header request IDs are read from and written to
+    pub header: HeaderName,
+    /// Whether a request's own ID is kept, rather than always generating one
+    pub honor_incoming: bool,
+}
+
+impl Default for RequestIdConfig {
+    fn default() -> Self {
+        Self {
+            header: HeaderName::from_static(DEFAULT_HEADER),
+            honor_incoming: true,
+        }
+    }
+}
+
+impl RequestIdConfig {
+    /// Carries request IDs in the header `name`.
+    ///
+    /// # Errors
+    /// Returns an error if `name` isn't a valid header name.
+    pub fn with_header(mut self, name: &str) -> anyhow::Result<Self> {
+        self.header = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("invalid request ID header '{name}'"))?;
+        Ok(self)
+    }
+
+    /// Sets the ID of a request in its headers, keeping the one it has if allowed.
+    fn assign(&self, headers: &mut HeaderMap) -> HeaderValue {
+        if self.honor_incoming
+            && let Some(id) = headers.get(&self.header)
+            && is_usable(id)
+        {
+            return id.clone();
+        }
+        let id = HeaderValue::from_str(&uuid::Uuid::new_v4().to_string())
+            .expect("UUIDs are valid header values");
+        headers.insert(self.header.clone(), id.clone());
+        id
+    }
+}
+
+/// The ID of a request, in the extensions of the requests the HTTP server handles.
+#[derive(Clone, Debug)]
+pub struct RequestId {
+    /// The header the ID is carried in
+    pub header: HeaderName,
+    /// The ID
+    pub id: HeaderValue,
+}
+
+/// Incoming IDs end up in logs, so only short IDs of visible ASCII characters are kept.
+fn is_usable(id: &HeaderValue) -> bool {
+    let id = id.as_bytes();
+    !id.is_empty() && id.len() <= MAX_INCOMING_LEN && id.iter().all(u8::is_ascii_graphic)
+}
+
+/// Wraps the HTTP server's service, assigning each request an ID and reading its trace
+/// context and the baggage selected by `baggage` before `service` handles it in a span
+/// carrying the ID.
+pub(crate) fn service<S>(
+    service: S,
+    config: Arc<RequestIdConfig>,
+    baggage: Arc<BaggageConfig>,
+) -> impl hyper::service::Service<
+    hyper::Request<hyper::body::Incoming>,
+    Response = hyper::Response<HyperOutgoingBody>,
+    Error = hyper::Error,
+    Future = impl Future<Output = Result<hyper::Response<HyperOutgoingBody>, hyper::Error>>
+             + Send
+             + 'static,
+> + Clone
++ Send
++ 'static
+where
+    S: hyper::service::Service<
+            hyper::Request<hyper::body::Incoming>,
+            Response = hyper::Response<HyperOutgoingBody>,
+            Error = hyper::Error,
+        > + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    hyper::service::service_fn(move |mut req: hyper::Request<hyper::body::Incoming>| {
+        let started = Instant::now();
+        let id = config.assign(req.headers_mut());
+        let trace_context = TraceContext::extract(req.headers_mut());
+        let span = tracing::info_span!(
+            "http_request",
+            request_id = id.to_str().unwrap_or_default(),
+            trace_id = trace_context.as_ref().map(TraceContext::trace_id),
+            method = %req.method(),
+            uri = %req.uri(),
+        );
+        req.extensions_mut().insert(RequestId {
+            header: config.header.clone(),
+            id: id.clone(),
+        });
+        if let Some(trace_context) = trace_context {
+            req.extensions_mut().insert(trace_context);
+        }
+        if let Some(baggage) = baggage.extract(req.headers_mut()) {
+            req.extensions_mut().insert(baggage);
+        }
+        let header = config.header.clone();
+        let response = span.in_scope(|| service.call(req));
+        async move {
+            let mut response = response.await?;
+            response.headers_mut().insert(header, id);
+            debug!(
+                target: "wash_runtime::access",
+                status = response.status().as_u16(),
+                latency = ?started.elapsed(),
+                "HTTP request served"
+            );
+            Ok(response)
+        }
+        .instrument(span)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assign() {
+        let config = RequestIdConfig::default();
+        let mut headers = HeaderMap::new();
+        let id = config.assign(&mut headers);
+        assert_eq!(headers["x-request-id"], id);
+        assert_eq!(id.len(), 36, "new IDs are UUIDs");
+
+        headers.insert("x-request-id", HeaderValue::from_static("abc-123"));
+        assert_eq!(config.assign(&mut headers), "abc-123");
+
+        // IDs that could break up a log line are replaced
+        headers.insert("x-request-id", HeaderValue::from_static("abc 123"));
+        assert_ne!(config.assign(&mut headers), "abc 123");
+
+        let config = RequestIdConfig {
+            honor_incoming: false,
+            ..Default::default()
+        };
+        headers.insert("x-request-id", HeaderValue::from_static("abc-123"));
+        assert_ne!(config.assign(&mut headers), "abc-123");
+        assert_ne!(headers["x-request-id"], "abc-123");
+    }
+}
@@ -0,0 +1,113 @@
+//! Saturation metrics for each workload, so autoscaling and capacity alerts can act on how
+//! busy workloads are rather than on request rates alone.
+//!
+//! Unless a component reuses its instances, see [`crate::engine::instances`], every
+//! invocation is handled by a new instance in a store of its own, which is dropped once
+//! the invocation finishes. The metrics, each carrying a `workload_id` attribute,
+//! therefore measure:
+//!
+//! - `workload_instances_active`, the pool's occupancy: the stores alive for the workload,
+//!   idle ones included, from their creation until they're dropped
+//! - `workload_instance_acquire_duration_seconds`, the time an HTTP request, a message or
+//!   a call through [`crate::engine::workload::ResolvedWorkload::call_export`] waits for
+//!   its instance, from creating the store until the component is instantiated, or from
+//!   taking an idle instance until it's ready
+//! - `workload_queue_depth`, the messages a queue invoker has received and that wait for
+//!   one of the invoker's `concurrency` slots, see [`crate::host::invoker`]
+//! - `workload_queue_wait_seconds`, how long those messages waited for a slot
+//!
+//! HTTP requests and `wasmcloud:messaging` messages aren't queued by the host, they start
+//! their instance as soon as they arrive, so their waiting shows up as acquisition latency.
+
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use opentelemetry::KeyValue;
+
+static METRICS: LazyLock<SaturationMetrics> =
+    LazyLock::new(|| SaturationMetrics::new(&opentelemetry::global::meter("workload-saturation")));
+
+struct SaturationMetrics {
+    instances_active: opentelemetry::metrics::UpDownCounter<i64>,
+    acquire_duration_seconds: opentelemetry::metrics::Histogram<f64>,
+    queue_depth: opentelemetry::metrics::UpDownCounter<i64>,
+    queue_wait_seconds: opentelemetry::metrics::Histogram<f64>,
+}
+
+impl SaturationMetrics {
+    fn new(meter: &opentelemetry::metrics::Meter) -> Self {
+        let instances_active = meter
+            .i64_up_down_counter("workload_instances_active")
+            .with_description("Number of instances alive for a workload")
+            .build();
+        let acquire_duration_seconds = meter
+            .f64_histogram("workload_instance_acquire_duration_seconds")
+            .with_description("Time taken to create and instantiate an instance for an invocation")
+            .with_unit("s")
+            .build();
+        let queue_depth = meter
+            .i64_up_down_counter("workload_queue_depth")
+            .with_description("Number of received messages waiting for a queue invoker slot")
+            .build();
+        let queue_wait_seconds = meter
+            .f64_histogram("workload_queue_wait_seconds")
+            .with_description("Time received messages waited for a queue invoker slot")
+            .with_unit("s")
+            .build();
+        Self {
+            instances_active,
+            acquire_duration_seconds,
+            queue_depth,
+            queue_wait_seconds,
+        }
+    }
+}
+
+fn attributes(workload_id: &str) -> [KeyValue; 1] {
+    [KeyValue::new("workload_id", workload_id.to_string())]
+}
+
+/// Records a store being created for a workload.
+pub(crate) fn instance_created(workload_id: &str) {
+    METRICS.instances_active.add(1, &attributes(workload_id));
+}
+
+/// Records a workload's store being dropped.
+pub(crate) fn instance_dropped(workload_id: &str) {
+    METRICS.instances_active.add(-1, &attributes(workload_id));
+}
+
+/// Records how long an invocation waited for its instance.
+pub(crate) fn record_acquisition(workload_id: &str, elapsed: Duration) {
+    METRICS
+        .acquire_duration_seconds
+        .record(elapsed.as_secs_f64(), &attributes(workload_id));
+}
+
+/// A message waiting in a workload's queue, counted in the queue's depth until it's
+/// dropped, which records how long it waited.
+pub(crate) struct Queued {
+    attributes: [KeyValue; 1],
+    since: Instant,
+}
+
+impl Queued {
+    /// Adds a message to a workload's queue.
+    pub(crate) fn enter(workload_id: &str) -> Self {
+        let attributes = attributes(workload_id);
+        METRICS.queue_depth.add(1, &attributes);
+        Self {
+            attributes,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        METRICS.queue_depth.add(-1, &self.attributes);
+        METRICS
+            .queue_wait_seconds
+            .record(self.since.elapsed().as_secs_f64(), &self.attributes);
+    }
+}
@@ -0,0 +1,185 @@
+//! Label selectors over [workload annotations](crate::types::Workload::annotations), in the
+//! syntax of Kubernetes label selectors, e.g. `app=checkout,env in (prod,staging),!canary`.
+//!
+//! A selector is a comma separated list of requirements that must all hold:
+//!
+//! - `key=value` or `key==value`, the annotation is set to the value
+//! - `key!=value`, the annotation isn't set to the value, or isn't set at all
+//! - `key in (a,b)`, the annotation is set to one of the values
+//! - `key notin (a,b)`, the annotation isn't set to any of the values, or isn't set at all
+//! - `key`, the annotation is set
+//! - `!key`, the annotation isn't set
+//!
+//! An empty selector matches every workload.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use anyhow::{Context as _, bail, ensure};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Requirement {
+    Equals(String, String),
+    NotEquals(String, String),
+    In(String, Vec<String>),
+    NotIn(String, Vec<String>),
+    Exists(String),
+    NotExists(String),
+}
+
+impl Requirement {
+    fn matches(&self, annotations: &HashMap<String, String>) -> bool {
+        match self {
+            Self::Equals(key, value) => annotations.get(key) == Some(value),
+            Self::NotEquals(key, value) => annotations.get(key) != Some(value),
+            Self::In(key, values) => annotations.get(key).is_some_and(|v| values.contains(v)),
+            Self::NotIn(key, values) => !annotations.get(key).is_some_and(|v| values.contains(v)),
+            Self::Exists(key) => annotations.contains_key(key),
+            Self::NotExists(key) => !annotations.contains_key(key),
+        }
+    }
+
+    fn parse(requirement: &str) -> anyhow::Result<Self> {
+        if let Some(key) = requirement.strip_prefix('!') {
+            return Ok(Self::NotExists(key_name(key)?));
+        }
+        for (operator, set) in [(" notin ", false), (" in ", true)] {
+            if let Some((key, values)) = requirement.split_once(operator) {
+                let values = values
+                    .trim()
+                    .strip_prefix('(')
+                    .and_then(|values| values.strip_suffix(')'))
+                    .with_context(|| format!("expected a list of values in '{requirement}'"))?
+                    .split(',')
+                    .map(|value| value.trim().to_string())
+                    .collect();
+                let key = key_name(key)?;
+                return Ok(if set {
+                    Self::In(key, values)
+                } else {
+                    Self::NotIn(key, values)
+                });
+            }
+        }
+        if let Some((key, value)) = requirement.split_once("!=") {
+            return Ok(Self::NotEquals(key_name(key)?, value.trim().to_string()));
+        }
+        if let Some((key, value)) = requirement
+            .split_once("==")
+            .or_else(|| requirement.split_once('='))
+        {
+            return Ok(Self::Equals(key_name(key)?, value.trim().to_string()));
+        }
+        Ok(Self::Exists(key_name(requirement)?))
+    }
+}
+
+fn key_name(key: &str) -> anyhow::Result<String> {
+    let key = key.trim();
+    ensure!(!key.is_empty(), "a selector requirement needs a key");
+    if let Some(c) = key
+        .chars()
+        .find(|c| c.is_whitespace() || "!=(),".contains(*c))
+    {
+        bail!("invalid character '{c}' in selector key '{key}'");
+    }
+    Ok(key.to_string())
+}
+
+/// A parsed label selector.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LabelSelector {
+    requirements: Vec<Requirement>,
+}
+
+impl LabelSelector {
+    /// Whether the selector has no requirements, and so matches every workload.
+    pub fn is_empty(&self) -> bool {
+        self.requirements.is_empty()
+    }
+
+    /// Whether a workload's annotations meet every requirement of the selector.
+    pub fn matches(&self, annotations: &HashMap<String, String>) -> bool {
+        self.requirements
+            .iter()
+            .all(|requirement| requirement.matches(annotations))
+    }
+}
+
+impl FromStr for LabelSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(selector: &str) -> anyhow::Result<Self> {
+        // Split on the commas between requirements, not those in a list of values
+        let mut requirements = Vec::new();
+        let (mut depth, mut start) = (0usize, 0);
+        for (i, c) in selector.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .with_context(|| format!("unbalanced ')' in selector '{selector}'"))?;
+                }
+                ',' if depth == 0 => {
+                    requirements.push(&selector[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        ensure!(depth == 0, "unbalanced '(' in selector '{selector}'");
+        requirements.push(&selector[start..]);
+
+        let requirements = requirements
+            .into_iter()
+            .map(str::trim)
+            .filter(|requirement| !requirement.is_empty())
+            .map(Requirement::parse)
+            .collect::<anyhow::Result<_>>()
+            .with_context(|| format!("invalid selector '{selector}'"))?;
+        Ok(Self { requirements })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotations(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_matches() -> anyhow::Result<()> {
+        let checkout = annotations(&[("app", "checkout"), ("env", "prod")]);
+        let cart = annotations(&[("app", "cart"), ("env", "staging"), ("canary", "true")]);
+
+        let matching = |selector: &str| -> anyhow::Result<Vec<bool>> {
+            let selector: LabelSelector = selector.parse()?;
+            Ok(vec![selector.matches(&checkout), selector.matches(&cart)])
+        };
+        assert_eq!(matching("app=checkout,env=prod")?, [true, false]);
+        assert_eq!(matching("app==cart")?, [false, true]);
+        assert_eq!(matching("env!=prod")?, [false, true]);
+        assert_eq!(matching("env in (prod, staging)")?, [true, true]);
+        assert_eq!(matching("app notin (cart),env")?, [true, false]);
+        assert_eq!(matching("!canary")?, [true, false]);
+        assert_eq!(matching("canary")?, [false, true]);
+        assert_eq!(matching("")?, [true, true]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for selector in ["=prod", "env in prod", "env in (prod", "app=a)", "!"] {
+            assert!(
+                selector.parse::<LabelSelector>().is_err(),
+                "expected '{selector}' to be invalid"
+            );
+        }
+    }
+}
@@ -0,0 +1,205 @@
+//! Services published by workloads for other workloads on the same host.
+//!
+//! A workload whose [`crate::types::Service`] has a name publishes the interfaces listed
+//! in its `exports`. Another workload in the same namespace imports one of those
+//! interfaces by adding it to its host interfaces with the [`SERVICE_CONFIG_KEY`] config
+//! key set to the service name, e.g. `acme:inventory/query` with `service=inventory`.
+//! Instead of binding a plugin, the host links the import to a function that forwards
+//! each call to a running instance of the service.
+//!
+//! Every workload started with the same service name in a namespace is an instance of
+//! that service. Calls are distributed across instances round-robin, and stopping a
+//! workload removes it from rotation. On a host with a [wRPC transport](crate::host::wrpc),
+//! services with no local instances are called on other hosts instead.
+//!
+//! Named services are also addressable over outgoing HTTP as `service-name.namespace`
+//! (see [`parse_service_authority`]), so components can call a sibling workload without
+//! hardcoding the address the host listens on.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::bail;
+use tokio::sync::RwLock;
+use tracing::{debug, trace};
+
+use crate::engine::workload::ResolvedWorkload;
+use crate::wit::WitInterface;
+
+/// Config key on a host interface that references a service instead of a plugin.
+pub const SERVICE_CONFIG_KEY: &str = "service";
+
+/// Returns the name of the service a host interface references, if any.
+pub fn service_reference(interface: &WitInterface) -> Option<&str> {
+    interface.config.get(SERVICE_CONFIG_KEY).map(String::as_str)
+}
+
+/// Splits an outgoing request authority of the form `service-name.namespace`, with an
+/// optional port, into the service name and namespace.
+///
+/// # Returns
+/// `None` if the authority doesn't have exactly two non-empty labels.
+pub fn parse_service_authority(authority: &str) -> Option<(&str, &str)> {
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    let (name, namespace) = host.split_once('.')?;
+    if name.is_empty() || namespace.is_empty() || namespace.contains('.') {
+        return None;
+    }
+    Some((name, namespace))
+}
+
+/// The running instances of a single service.
+#[derive(Default)]
+struct ServiceEntry {
+    exports: Vec<WitInterface>,
+    instances: Vec<ResolvedWorkload>,
+    next: AtomicUsize,
+}
+
+/// Registry of the services published by running workloads, keyed by namespace and name.
+#[derive(Default)]
+pub struct ServiceRegistry {
+    services: RwLock<HashMap<(String, String), ServiceEntry>>,
+}
+
+impl ServiceRegistry {
+    /// Publishes a workload as an instance of its named service. Workloads without a
+    /// named service are ignored.
+    ///
+    /// # Errors
+    /// Returns an error if the workload's components don't export every published
+    /// interface, or if running instances of the service publish different interfaces.
+    pub async fn register(&self, workload: &ResolvedWorkload) -> anyhow::Result<()> {
+        let Some(name) = workload.service_name() else {
+            return Ok(());
+        };
+        let exports = workload.service_exports().to_vec();
+
+        let mut exported = Vec::new();
+        for component in workload.components().read().await.values() {
+            exported.extend(component.world().exports);
+        }
+        for export in &exports {
+            if !exported.iter().any(|e| e.contains(export)) {
+                bail!(
+                    "service '{name}' publishes '{export}', which no component in workload {} exports",
+                    workload.id()
+                );
+            }
+        }
+
+        let key = (workload.namespace().to_string(), name.to_string());
+        let mut services = self.services.write().await;
+        let entry = services.entry(key).or_default();
+        if entry.instances.is_empty() {
+            entry.exports = exports;
+        } else if entry.exports != exports {
+            bail!(
+                "service '{name}' is already published in namespace '{}' with different exports",
+                workload.namespace()
+            );
+        }
+        debug!(
+            workload_id = workload.id(),
+            service = name,
+            namespace = workload.namespace(),
+            "registering service instance"
+        );
+        entry.instances.push(workload.clone());
+        Ok(())
+    }
+
+    /// Removes a workload from every service it's an instance of.
+    pub async fn deregister(&self, workload_id: &str) {
+        let mut services = self.services.write().await;
+        for entry in services.values_mut() {
+            entry.instances.retain(|w| w.id() != workload_id);
+        }
+        services.retain(|_, entry| !entry.instances.is_empty());
+    }
+
+    /// Returns the interfaces published by a service, or `None` if the service has no
+    /// running instances.
+    pub async fn exports(&self, namespace: &str, name: &str) -> Option<Vec<WitInterface>> {
+        self.services
+            .read()
+            .await
+            .get(&(namespace.to_string(), name.to_string()))
+            .map(|entry| entry.exports.clone())
+    }
+
+    /// Returns the number of running instances of a service.
+    pub async fn instance_count(&self, namespace: &str, name: &str) -> usize {
+        self.services
+            .read()
+            .await
+            .get(&(namespace.to_string(), name.to_string()))
+            .map_or(0, |entry| entry.instances.len())
+    }
+
+    /// Picks the instance of a service that should handle the next call.
+    pub async fn pick(&self, namespace: &str, name: &str) -> Option<ResolvedWorkload> {
+        let services = self.services.read().await;
+        let entry = services.get(&(namespace.to_string(), name.to_string()))?;
+        if entry.instances.is_empty() {
+            return None;
+        }
+        let idx = entry.next.fetch_add(1, Ordering::Relaxed) % entry.instances.len();
+        trace!(
+            service = name,
+            namespace,
+            instance = idx,
+            "picked service instance"
+        );
+        entry.instances.get(idx).cloned()
+    }
+}
+
+impl std::fmt::Debug for ServiceRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceRegistry").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_reference() {
+        let mut interface = WitInterface::from("acme:inventory/query");
+        assert_eq!(service_reference(&interface), None);
+
+        interface
+            .config
+            .insert(SERVICE_CONFIG_KEY.to_string(), "inventory".to_string());
+        assert_eq!(service_reference(&interface), Some("inventory"));
+    }
+
+    #[test]
+    fn test_parse_service_authority() {
+        assert_eq!(
+            parse_service_authority("inventory.default"),
+            Some(("inventory", "default"))
+        );
+        assert_eq!(
+            parse_service_authority("inventory.default:8080"),
+            Some(("inventory", "default"))
+        );
+        assert_eq!(parse_service_authority("inventory"), None);
+        assert_eq!(parse_service_authority("inventory."), None);
+        assert_eq!(parse_service_authority(".default"), None);
+        assert_eq!(parse_service_authority("api.example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_service() {
+        let registry = ServiceRegistry::default();
+        assert!(registry.pick("default", "inventory").await.is_none());
+        assert!(registry.exports("default", "inventory").await.is_none());
+        assert_eq!(registry.instance_count("default", "inventory").await, 0);
+    }
+}
@@ -0,0 +1,165 @@
+//! Logging of slow invocations, so "why was that request slow" can be answered from the
+//! host's logs without tracing every request.
+//!
+//! A component opts in with a threshold in milliseconds under [`SLOW_INVOCATION_CONFIG`]
+//! in its [`LocalResources::config`]. Its invocations that take longer, from creating
+//! their store until the component returns, are logged at WARN with:
+//!
+//! - what was invoked: the method and path of an HTTP request, the subject of a message or
+//!   the function called through
+//!   [`crate::engine::workload::ResolvedWorkload::call_export`]
+//! - the duration, and the part of it spent executing guest code
+//! - the fuel the component used, when the engine meters fuel
+//! - how many times the component called each plugin
+//!
+//! Without a threshold nothing is measured beyond the workload's usage.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use anyhow::Context as _;
+use tracing::warn;
+
+use crate::engine::ctx::Ctx;
+use crate::types::LocalResources;
+
+/// The key in a component's [`LocalResources::config`] setting how many milliseconds its
+/// invocations may take before they're logged, e.g. `500`.
+pub const SLOW_INVOCATION_CONFIG: &str = "slow-invocation-ms";
+
+/// Reads a component's slow invocation threshold.
+///
+/// # Returns
+/// The threshold, or `None` if the component doesn't set one.
+///
+/// # Errors
+/// Returns an error if the threshold isn't a number of milliseconds.
+pub(crate) fn threshold(resources: &LocalResources) -> anyhow::Result<Option<Duration>> {
+    resources
+        .config
+        .get(SLOW_INVOCATION_CONFIG)
+        .map(|millis| {
+            millis
+                .trim()
+                .parse()
+                .map(Duration::from_millis)
+                .with_context(|| format!("invalid {SLOW_INVOCATION_CONFIG} '{millis}'"))
+        })
+        .transpose()
+}
+
+/// Measures an invocation of a component with a slow invocation threshold.
+#[derive(Debug)]
+pub(crate) struct InvocationTimer {
+    threshold: Duration,
+    started: Instant,
+    /// What was invoked, if known
+    invocation: Option<String>,
+    /// Calls by plugin ID
+    plugin_calls: Mutex<BTreeMap<String, u64>>,
+}
+
+impl InvocationTimer {
+    pub(crate) fn new(threshold: Duration, started: Instant) -> Self {
+        Self {
+            threshold,
+            started,
+            invocation: None,
+            plugin_calls: Mutex::default(),
+        }
+    }
+
+    /// Starts measuring another invocation of a reused instance.
+    pub(crate) fn restart(&mut self, started: Instant) {
+        self.started = started;
+        self.invocation = None;
+        self.plugin_calls
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    pub(crate) fn set_invocation(&mut self, invocation: String) {
+        self.invocation = Some(invocation);
+    }
+
+    pub(crate) fn record_plugin_call(&self, plugin_id: &str) {
+        let mut plugin_calls = self
+            .plugin_calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match plugin_calls.get_mut(plugin_id) {
+            Some(count) => *count += 1,
+            None => {
+                plugin_calls.insert(plugin_id.to_string(), 1);
+            }
+        }
+    }
+
+    /// The plugin calls, as `plugin=count` pairs.
+    fn plugin_calls(&self) -> String {
+        self.plugin_calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(plugin_id, count)| format!("{plugin_id}={count}"))
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Logs the invocation a store handled if it took longer than its component's threshold.
+/// Call once the component has returned.
+pub(crate) fn log_if_slow(store: &wasmtime::Store<Ctx>) {
+    let ctx = store.data();
+    let Some(timer) = ctx.invocation_timer() else {
+        return;
+    };
+    let duration = timer.started.elapsed();
+    if duration <= timer.threshold {
+        return;
+    }
+    warn!(
+        workload_id = ctx.workload_id.as_ref(),
+        component_id = ctx.component_id.as_ref(),
+        invocation = timer.invocation.as_deref().unwrap_or("unknown"),
+        ?duration,
+        guest_time = ?ctx.cpu_time(),
+        fuel_used = ctx.fuel_used(),
+        plugin_calls = timer.plugin_calls(),
+        threshold = ?timer.threshold,
+        "slow invocation"
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold() -> anyhow::Result<()> {
+        let mut resources = LocalResources::default();
+        assert_eq!(threshold(&resources)?, None);
+
+        resources
+            .config
+            .insert(SLOW_INVOCATION_CONFIG.to_string(), "250".to_string());
+        assert_eq!(threshold(&resources)?, Some(Duration::from_millis(250)));
+
+        resources
+            .config
+            .insert(SLOW_INVOCATION_CONFIG.to_string(), "1s".to_string());
+        assert!(threshold(&resources).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plugin_calls() {
+        let timer = InvocationTimer::new(Duration::ZERO, Instant::now());
+        timer.record_plugin_call("wasi-keyvalue");
+        timer.record_plugin_call("wasi-blobstore");
+        timer.record_plugin_call("wasi-keyvalue");
+        assert_eq!(timer.plugin_calls(), "wasi-blobstore=1,wasi-keyvalue=2");
+    }
+}
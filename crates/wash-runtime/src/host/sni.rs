@@ -0,0 +1,155 @@
+//! Certificates selected by the server name clients send in their TLS handshake (SNI), so
+//! many TLS virtual hosts with their own certificates can share one listener.
+//!
+//! [`SniCertificates`] hold a certificate for each hostname, or for every subdomain of a
+//! domain with a wildcard like `*.example.com`, and are set with
+//! [`crate::host::http::HttpServer::with_sni_certificates`]. Clients naming no host, or one
+//! without a certificate, get the certificate the server was created with.
+//!
+//! With [`crate::host::http::HttpServer::with_sni_routing`], requests are also routed by
+//! the server name of their connection rather than by their `Host` header, which replaces
+//! it, keeping its port. A client then can't send requests for one virtual host over a
+//! connection it opened, and had the certificate checked, for another.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::ensure;
+use hyper::header::{HOST, HeaderValue};
+use hyper::http::uri::Authority;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+
+use crate::host::cert_reload::ReloadableCertificate;
+
+/// Certificates by the hostnames they're served for.
+#[derive(Debug, Default)]
+pub struct SniCertificates {
+    /// Certificates by lowercase hostname, with wildcards kept as `*.example.com`
+    certificates: HashMap<String, Arc<ReloadableCertificate>>,
+    /// Resolves the certificate for clients naming no host with a certificate
+    fallback: Option<Arc<dyn ResolvesServerCert>>,
+}
+
+impl SniCertificates {
+    /// Loads a certificate chain and its private key, as PEM, for a hostname.
+    ///
+    /// # Arguments
+    /// * `hostname` - The host the certificate is served for, e.g. `api.example.com`, or
+    ///   `*.example.com` for each of its subdomains
+    /// * `cert_path` - Path to the certificate chain
+    /// * `key_path` - Path to the private key
+    ///
+    /// # Errors
+    /// Returns an error if the files can't be read, or the key can't be used for signing.
+    pub async fn add(
+        &mut self,
+        hostname: &str,
+        cert_path: &Path,
+        key_path: &Path,
+    ) -> anyhow::Result<()> {
+        let hostname = hostname.trim().trim_end_matches('.').to_ascii_lowercase();
+        ensure!(!hostname.is_empty(), "SNI certificate needs a hostname");
+        let certificate = ReloadableCertificate::load(cert_path, key_path).await?;
+        self.certificates.insert(hostname, Arc::new(certificate));
+        Ok(())
+    }
+
+    /// Whether no hostname has a certificate.
+    pub fn is_empty(&self) -> bool {
+        self.certificates.is_empty()
+    }
+
+    /// The certificates, to reload when their files change.
+    pub(crate) fn reloadable(&self) -> impl Iterator<Item = &Arc<ReloadableCertificate>> {
+        self.certificates.values()
+    }
+
+    /// Serves the certificate `fallback` resolves to clients naming no host with a
+    /// certificate.
+    pub(crate) fn with_fallback(mut self, fallback: Arc<dyn ResolvesServerCert>) -> Self {
+        self.fallback = Some(fallback);
+        self
+    }
+}
+
+/// Looks up the entry for `server_name`, preferring one for the exact host to a wildcard.
+fn find<'a, V>(entries: &'a HashMap<String, V>, server_name: &str) -> Option<&'a V> {
+    let server_name = server_name.to_ascii_lowercase();
+    entries.get(&server_name).or_else(|| {
+        let (_, domain) = server_name.split_once('.')?;
+        entries.get(&format!("*.{domain}"))
+    })
+}
+
+impl ResolvesServerCert for SniCertificates {
+    fn resolve(&self, client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        if let Some(certificate) = client_hello
+            .server_name()
+            .and_then(|name| find(&self.certificates, name))
+        {
+            return Some(certificate.current());
+        }
+        self.fallback
+            .as_ref()
+            .and_then(|fallback| fallback.resolve(client_hello))
+    }
+}
+
+/// Routes a request by the server name of its connection, replacing its `Host` header
+/// and keeping the port it names.
+pub(crate) fn route_by_server_name<B>(req: &mut hyper::Request<B>, server_name: &str) {
+    let port = req
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .and_then(|host| host.parse::<Authority>().ok())
+        .and_then(|authority| authority.port_u16())
+        .or_else(|| req.uri().port_u16());
+    let host = match port {
+        Some(port) => format!("{server_name}:{port}"),
+        None => server_name.to_string(),
+    };
+    // Server names are DNS names, so they're valid header values
+    if let Ok(host) = HeaderValue::from_str(&host) {
+        req.headers_mut().insert(HOST, host);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find() {
+        let entries = HashMap::from([
+            ("api.example.com".to_string(), "api"),
+            ("*.example.com".to_string(), "wildcard"),
+        ]);
+        assert_eq!(find(&entries, "API.example.com"), Some(&"api"));
+        assert_eq!(find(&entries, "www.example.com"), Some(&"wildcard"));
+        // Wildcards cover one label
+        assert_eq!(find(&entries, "a.b.example.com"), None);
+        assert_eq!(find(&entries, "example.com"), None);
+    }
+
+    #[test]
+    fn test_route_by_server_name() {
+        let mut req = hyper::Request::builder()
+            .uri("/")
+            .header(HOST, "other.example.com:8443")
+            .body(())
+            .unwrap();
+        route_by_server_name(&mut req, "api.example.com");
+        assert_eq!(req.headers()[HOST], "api.example.com:8443");
+
+        // HTTP/2 requests name their host in the URI
+        let mut req = hyper::Request::builder()
+            .uri("https://other.example.com/")
+            .body(())
+            .unwrap();
+        route_by_server_name(&mut req, "api.example.com");
+        assert_eq!(req.headers()[HOST], "api.example.com");
+    }
+}
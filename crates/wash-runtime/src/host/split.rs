@@ -0,0 +1,96 @@
+//! Weighted splits of a virtual host's traffic between workloads, for A/B experiments
+//! between entirely different components, set at runtime through
+//! [`HostApi::http_traffic_split`](crate::host::HostApi::http_traffic_split).
+//!
+//! Requests are spread with smooth weighted round-robin, so a 90/10 split sends every
+//! tenth request to the second workload rather than ten in a row. Workloads of a split
+//! that are stopped or have paused routing are skipped, and their share goes to the rest.
+
+use std::sync::{Mutex, PoisonError};
+
+use anyhow::ensure;
+
+/// The workloads a virtual host's requests are split between.
+#[derive(Debug)]
+pub(crate) struct TrafficSplit {
+    weights: Vec<(String, u32)>,
+    /// The current weight of each workload in the round-robin
+    current: Mutex<Vec<i64>>,
+}
+
+impl TrafficSplit {
+    /// Creates a split between workloads by relative weight.
+    ///
+    /// # Errors
+    /// Returns an error if no workload has a weight, or one is listed twice.
+    pub(crate) fn new(weights: Vec<(String, u32)>) -> anyhow::Result<Self> {
+        ensure!(
+            weights.iter().any(|(_, weight)| *weight > 0),
+            "a traffic split needs a workload with a weight above 0"
+        );
+        for (i, (id, _)) in weights.iter().enumerate() {
+            ensure!(
+                !weights[..i].iter().any(|(other, _)| other == id),
+                "workload '{id}' is listed twice in the traffic split"
+            );
+        }
+        Ok(Self {
+            current: Mutex::new(vec![0; weights.len()]),
+            weights,
+        })
+    }
+
+    /// The workloads and their weights.
+    pub(crate) fn weights(&self) -> &[(String, u32)] {
+        &self.weights
+    }
+
+    /// Picks the workload for the next request among those that are `available`.
+    ///
+    /// # Returns
+    /// The workload ID, or `None` if no workload with a weight is available.
+    pub(crate) fn pick(&self, available: impl Fn(&str) -> bool) -> Option<&str> {
+        let mut current = self.current.lock().unwrap_or_else(PoisonError::into_inner);
+        let candidates: Vec<usize> = (0..self.weights.len())
+            .filter(|&i| self.weights[i].1 > 0 && available(&self.weights[i].0))
+            .collect();
+        let total: i64 = candidates
+            .iter()
+            .map(|&i| i64::from(self.weights[i].1))
+            .sum();
+        for &i in &candidates {
+            current[i] += i64::from(self.weights[i].1);
+        }
+        // The first of the highest current weights wins, and pays the total back
+        let chosen = *candidates.iter().rev().max_by_key(|&&i| current[i])?;
+        current[chosen] -= total;
+        Some(&self.weights[chosen].0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick() -> anyhow::Result<()> {
+        let split = TrafficSplit::new(vec![("a".to_string(), 3), ("b".to_string(), 1)])?;
+        let picks: Vec<_> = (0..8)
+            .map(|_| split.pick(|_| true).map(str::to_string))
+            .collect::<Option<_>>()
+            .expect("every pick has a workload");
+        assert_eq!(picks, ["a", "a", "b", "a", "a", "a", "b", "a"]);
+
+        // An unavailable workload's share goes to the others
+        assert!((0..4).all(|_| split.pick(|id| id == "b") == Some("b")));
+        assert_eq!(split.pick(|_| false), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_new() {
+        assert!(TrafficSplit::new(vec![]).is_err());
+        assert!(TrafficSplit::new(vec![("a".to_string(), 0)]).is_err());
+        assert!(TrafficSplit::new(vec![("a".to_string(), 1), ("a".to_string(), 2)]).is_err());
+    }
+}
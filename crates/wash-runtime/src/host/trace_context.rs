@@ -0,0 +1,187 @@
+//! [W3C trace context] for requests passing through components, so distributed traces
+//! continue past them.
+//!
+//! The HTTP server reads the `traceparent` and `tracestate` headers of incoming requests:
+//!
+//! - A valid context reaches the component in the request's headers, where its own
+//!   telemetry can pick it up, and its trace ID is recorded on the `http_request` span
+//!   the request is handled in, see [`crate::host::request_id`].
+//! - An invalid `traceparent` is removed along with `tracestate`, as the specification
+//!   requires, rather than being passed on.
+//! - The outgoing HTTP requests the component sends are given the context unless they
+//!   carry a `traceparent` of their own, so backends join the trace even when the
+//!   component doesn't take part in it.
+//!
+//! The host doesn't record spans of its own into the trace, so the context is passed on
+//! unchanged.
+//!
+//! [W3C trace context]: https://www.w3.org/TR/trace-context/
+
+use hyper::header::{HeaderMap, HeaderValue};
+
+const TRACEPARENT: &str = "traceparent";
+const TRACESTATE: &str = "tracestate";
+
+/// The trace context of an incoming request, in the extensions of the requests the HTTP
+/// server handles.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: String,
+    parent_id: String,
+    flags: u8,
+    tracestate: Option<HeaderValue>,
+}
+
+impl TraceContext {
+    /// Reads the trace context of a request, removing its trace context headers if
+    /// `traceparent` is invalid.
+    pub(crate) fn extract(headers: &mut HeaderMap) -> Option<Self> {
+        let context = headers
+            .get(TRACEPARENT)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Self::parse(value, tracestate(headers)));
+        if context.is_none() {
+            headers.remove(TRACEPARENT);
+            headers.remove(TRACESTATE);
+        }
+        context
+    }
+
+    /// Parses a `traceparent` header, reading versions newer than `00` as `00` as the
+    /// specification allows.
+    fn parse(traceparent: &str, tracestate: Option<HeaderValue>) -> Option<Self> {
+        let mut fields = traceparent.trim().split('-');
+        let version = fields.next().filter(|v| is_hex(v, 2) && *v != "ff")?;
+        let trace_id = fields.next().filter(|id| is_hex(id, 32) && !is_zero(id))?;
+        let parent_id = fields.next().filter(|id| is_hex(id, 16) && !is_zero(id))?;
+        let flags = fields.next().filter(|flags| is_hex(flags, 2))?;
+        if version == "00" && fields.next().is_some() {
+            return None;
+        }
+        Some(Self {
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+            tracestate,
+        })
+    }
+
+    /// The ID of the trace, as 32 hex digits.
+    pub fn trace_id(&self) -> &str {
+        &self.trace_id
+    }
+
+    /// Whether the caller may have recorded the trace.
+    pub fn is_sampled(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+
+    /// The context as a `traceparent` header value.
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.parent_id, self.flags)
+    }
+
+    /// Adds the context to an outgoing request, unless it has a `traceparent` already.
+    pub(crate) fn inject(&self, headers: &mut HeaderMap) {
+        if headers.contains_key(TRACEPARENT) {
+            return;
+        }
+        let traceparent =
+            HeaderValue::from_str(&self.traceparent()).expect("hex digits are valid header values");
+        headers.insert(TRACEPARENT, traceparent);
+        if let Some(tracestate) = &self.tracestate {
+            headers.insert(TRACESTATE, tracestate.clone());
+        }
+    }
+}
+
+/// The request's `tracestate`, joining its headers into one.
+fn tracestate(headers: &HeaderMap) -> Option<HeaderValue> {
+    let values: Vec<&str> = headers
+        .get_all(TRACESTATE)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+    HeaderValue::from_str(&values.join(",")).ok()
+}
+
+fn is_hex(field: &str, len: usize) -> bool {
+    field.len() == len
+        && field
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+fn is_zero(field: &str) -> bool {
+    field.bytes().all(|b| b == b'0')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRACEPARENT_VALUE: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_extract_and_inject() {
+        let mut incoming = HeaderMap::new();
+        incoming.insert(TRACEPARENT, HeaderValue::from_static(TRACEPARENT_VALUE));
+        incoming.append(TRACESTATE, HeaderValue::from_static("congo=t61rcWkgMzE"));
+        incoming.append(
+            TRACESTATE,
+            HeaderValue::from_static("rojo=00f067aa0ba902b7"),
+        );
+        let context = TraceContext::extract(&mut incoming).expect("the context is valid");
+        assert_eq!(context.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert!(context.is_sampled());
+
+        let mut outgoing = HeaderMap::new();
+        context.inject(&mut outgoing);
+        assert_eq!(outgoing[TRACEPARENT], TRACEPARENT_VALUE);
+        assert_eq!(
+            outgoing[TRACESTATE],
+            "congo=t61rcWkgMzE,rojo=00f067aa0ba902b7"
+        );
+
+        // A component's own context is kept
+        let mut outgoing = HeaderMap::new();
+        let own = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-00";
+        outgoing.insert(TRACEPARENT, HeaderValue::from_static(own));
+        context.inject(&mut outgoing);
+        assert_eq!(outgoing[TRACEPARENT], own);
+    }
+
+    #[test]
+    fn test_invalid_traceparent() {
+        for invalid in [
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            let mut headers = HeaderMap::new();
+            headers.insert(TRACEPARENT, HeaderValue::from_static(invalid));
+            headers.insert(TRACESTATE, HeaderValue::from_static("congo=t61rcWkgMzE"));
+            assert!(TraceContext::extract(&mut headers).is_none(), "{invalid}");
+            assert!(headers.is_empty(), "{invalid}");
+        }
+
+        // Newer versions are read as version 00
+        let context = TraceContext::parse(
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-extra",
+            None,
+        )
+        .expect("newer versions are accepted");
+        assert_eq!(
+            context.traceparent(),
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"
+        );
+    }
+}
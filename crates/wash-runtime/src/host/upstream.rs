@@ -0,0 +1,320 @@
+//! Reverse proxying from the [HTTP server](crate::host::http) to existing backends, so
+//! legacy services can move to components a route at a time behind one listener.
+//!
+//! An [`Upstream`] forwards requests for a virtual host, optionally only those under a
+//! path prefix, to a backend URL:
+//!
+//! - An upstream with a path prefix, such as `/legacy`, takes the requests under it
+//!   even when a workload serves the virtual host.
+//! - An upstream for the whole host takes the requests no workload serves.
+//!
+//! Requests keep their path and query, appended to the path of the upstream URL. The
+//! `Host` header is set to the upstream's authority, with the original in
+//! `X-Forwarded-Host`. Upstreams that can't be reached answer `502 Bad Gateway`, and
+//! ones that time out `504 Gateway Timeout`.
+
+use std::time::Duration;
+
+use anyhow::{Context as _, bail, ensure};
+use http_body_util::BodyExt as _;
+use hyper::header::{self, HeaderName, HeaderValue};
+use hyper::{StatusCode, Uri};
+use tracing::{debug, warn};
+use wasmtime_wasi_http::bindings::http::types::ErrorCode;
+use wasmtime_wasi_http::body::HyperOutgoingBody;
+use wasmtime_wasi_http::types::OutgoingRequestConfig;
+
+/// How long to wait for a connection to an upstream.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for the start of an upstream's response, and between its chunks.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Headers that apply to a single connection, which proxies don't forward.
+const HOP_BY_HOP_HEADERS: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// A backend that requests for a virtual host, or a path prefix of it, are proxied to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Upstream {
+    host: String,
+    prefix: String,
+    use_tls: bool,
+    authority: String,
+    base_path: String,
+}
+
+impl Upstream {
+    /// Creates an upstream for requests to `host` under `prefix`.
+    ///
+    /// # Arguments
+    /// * `host` - The virtual host, matched against the `Host` header with or without its
+    ///   port, or `*` for every host
+    /// * `prefix` - The path the upstream serves, or `/` for the whole host
+    /// * `url` - The backend, such as `http://legacy.internal:8080` or
+    ///   `https://api.example.com/v1`
+    ///
+    /// # Errors
+    /// Returns an error if the URL isn't an `http` or `https` URL.
+    pub fn new(host: impl Into<String>, prefix: &str, url: &str) -> anyhow::Result<Self> {
+        let uri: Uri = url
+            .parse()
+            .with_context(|| format!("invalid upstream URL '{url}'"))?;
+        let use_tls = match uri.scheme_str() {
+            Some("http") => false,
+            Some("https") => true,
+            _ => bail!("upstream URL '{url}' must start with http:// or https://"),
+        };
+        let authority = uri
+            .authority()
+            .with_context(|| format!("upstream URL '{url}' has no host"))?;
+        ensure!(
+            uri.query().is_none(),
+            "upstream URL '{url}' must not have a query"
+        );
+        Ok(Self {
+            host: host.into(),
+            prefix: prefix.trim_end_matches('/').to_string(),
+            use_tls,
+            authority: authority.to_string(),
+            base_path: uri.path().trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Whether the upstream serves only a path prefix rather than the whole host.
+    fn has_prefix(&self) -> bool {
+        !self.prefix.is_empty()
+    }
+
+    fn matches(&self, host: Option<&str>, path: &str) -> bool {
+        if self.host != "*" {
+            let Some(host) = host else {
+                return false;
+            };
+            let without_port = host.rsplit_once(':').map_or(host, |(host, _port)| host);
+            if self.host != host && self.host != without_port {
+                return false;
+            }
+        }
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// Returns the URI a request is forwarded to.
+    fn target(&self, uri: &Uri) -> anyhow::Result<Uri> {
+        let path_and_query = uri.path_and_query().map_or("/", |p| p.as_str());
+        let scheme = if self.use_tls { "https" } else { "http" };
+        format!(
+            "{scheme}://{}{}{path_and_query}",
+            self.authority, self.base_path
+        )
+        .parse()
+        .context("invalid upstream request URI")
+    }
+
+    /// Forwards a request to the upstream, returning its response or an error status.
+    pub(crate) async fn proxy<B>(
+        &self,
+        req: hyper::Request<B>,
+    ) -> hyper::Response<HyperOutgoingBody>
+    where
+        B: hyper::body::Body<Data = bytes::Bytes, Error = hyper::Error> + Send + Sync + 'static,
+    {
+        let (mut parts, body) = req.into_parts();
+        let original_host = parts.headers.get(header::HOST).cloned().or_else(|| {
+            parts
+                .uri
+                .authority()
+                .and_then(|a| HeaderValue::from_str(a.as_str()).ok())
+        });
+        let target = match self.target(&parts.uri) {
+            Ok(target) => target,
+            Err(e) => {
+                warn!(err = ?e, uri = %parts.uri, "failed to build upstream request");
+                return status(StatusCode::BAD_REQUEST);
+            }
+        };
+        debug!(uri = %parts.uri, upstream = %target, "proxying request to upstream");
+
+        strip_hop_by_hop(&mut parts.headers);
+        if let Some(original_host) = original_host {
+            parts
+                .headers
+                .insert(HeaderName::from_static("x-forwarded-host"), original_host);
+        }
+        parts.headers.insert(
+            HeaderName::from_static("x-forwarded-proto"),
+            HeaderValue::from_static(match parts.uri.scheme_str() {
+                Some("https") => "https",
+                _ => "http",
+            }),
+        );
+        match HeaderValue::from_str(target.authority().map_or("", |a| a.as_str())) {
+            Ok(host) => {
+                parts.headers.insert(header::HOST, host);
+            }
+            Err(_) => return status(StatusCode::BAD_GATEWAY),
+        }
+        parts.uri = target;
+
+        let request = hyper::Request::from_parts(
+            parts,
+            body.map_err(wasmtime_wasi_http::hyper_request_error)
+                .boxed(),
+        );
+        let config = OutgoingRequestConfig {
+            use_tls: self.use_tls,
+            connect_timeout: CONNECT_TIMEOUT,
+            first_byte_timeout: RESPONSE_TIMEOUT,
+            between_bytes_timeout: RESPONSE_TIMEOUT,
+        };
+        match wasmtime_wasi_http::types::default_send_request_handler(request, config).await {
+            Ok(incoming) => {
+                // The worker drives the connection, so it has to live as long as the body
+                let worker = incoming.worker;
+                let mut resp = incoming.resp.map(|body| {
+                    body.map_frame(move |frame| {
+                        let _connection = &worker;
+                        frame
+                    })
+                    .boxed()
+                });
+                strip_hop_by_hop(resp.headers_mut());
+                resp
+            }
+            Err(ErrorCode::ConnectionTimeout | ErrorCode::ConnectionReadTimeout) => {
+                warn!(upstream = self.authority, "upstream timed out");
+                status(StatusCode::GATEWAY_TIMEOUT)
+            }
+            Err(e) => {
+                warn!(upstream = self.authority, err = ?e, "failed to proxy request to upstream");
+                status(StatusCode::BAD_GATEWAY)
+            }
+        }
+    }
+}
+
+/// Returns the upstream a request goes to, if any.
+///
+/// # Arguments
+/// * `prefixed` - Whether to look at upstreams for path prefixes, which take requests
+///   before workloads do, or at upstreams for whole hosts, which take the rest
+pub(crate) fn find<'a, B>(
+    upstreams: &'a [Upstream],
+    req: &hyper::Request<B>,
+    prefixed: bool,
+) -> Option<&'a Upstream> {
+    let host = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|h| h.to_str().ok())
+        .or_else(|| req.uri().authority().map(|a| a.as_str()));
+    upstreams
+        .iter()
+        .filter(|upstream| upstream.has_prefix() == prefixed)
+        .filter(|upstream| upstream.matches(host, req.uri().path()))
+        // The longest prefix wins, then the first added
+        .min_by_key(|upstream| std::cmp::Reverse(upstream.prefix.len()))
+}
+
+/// Removes the headers that only apply to a single connection.
+pub(crate) fn strip_hop_by_hop(headers: &mut hyper::HeaderMap) {
+    // Headers named in `Connection` are hop-by-hop too
+    let named: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok())
+        .collect();
+    for name in named {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+fn status(status: StatusCode) -> hyper::Response<HyperOutgoingBody> {
+    hyper::Response::builder()
+        .status(status)
+        .body(HyperOutgoingBody::default())
+        .expect("failed to build upstream error response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_target() -> anyhow::Result<()> {
+        let upstream = Upstream::new("app.localhost", "/", "http://legacy.internal:8080")?;
+        assert_eq!(
+            upstream.target(&"/orders?id=1".parse()?)?.to_string(),
+            "http://legacy.internal:8080/orders?id=1"
+        );
+        let upstream = Upstream::new("*", "/api", "https://api.example.com/v1/")?;
+        assert_eq!(
+            upstream.target(&"/api/orders".parse()?)?.to_string(),
+            "https://api.example.com/v1/api/orders"
+        );
+        assert!(Upstream::new("*", "/", "ftp://files.example.com").is_err());
+        assert!(Upstream::new("*", "/", "legacy.internal").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find() -> anyhow::Result<()> {
+        let upstreams = [
+            Upstream::new("app.localhost", "/", "http://legacy:8080")?,
+            Upstream::new("app.localhost", "/legacy", "http://legacy:8080")?,
+            Upstream::new("app.localhost", "/legacy/reports", "http://reports:8080")?,
+            Upstream::new("*", "/", "http://default:8080")?,
+        ];
+        let request = |host: &str, path: &str| {
+            hyper::Request::get(path)
+                .header(header::HOST, host)
+                .body(())
+                .unwrap()
+        };
+        let find_authority = |req: &hyper::Request<()>, prefixed| {
+            find(&upstreams, req, prefixed).map(|u| u.authority.clone())
+        };
+
+        let req = request("app.localhost:8000", "/legacy/reports/1");
+        assert_eq!(find_authority(&req, true).as_deref(), Some("reports:8080"));
+        let req = request("app.localhost", "/legacy");
+        assert_eq!(find_authority(&req, true).as_deref(), Some("legacy:8080"));
+        let req = request("app.localhost", "/legacyish");
+        assert_eq!(find_authority(&req, true), None);
+        assert_eq!(find_authority(&req, false).as_deref(), Some("legacy:8080"));
+        let req = request("other.localhost", "/");
+        assert_eq!(find_authority(&req, false).as_deref(), Some("default:8080"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut headers = hyper::HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("close, x-trace"),
+        );
+        headers.insert("x-trace", HeaderValue::from_static("1"));
+        headers.insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+        headers.insert(header::ACCEPT, HeaderValue::from_static("*/*"));
+        strip_hop_by_hop(&mut headers);
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(header::ACCEPT));
+    }
+}
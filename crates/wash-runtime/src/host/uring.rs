@@ -0,0 +1,131 @@
+//! An io_uring-backed TCP listener for the HTTP server on Linux, behind the `io-uring`
+//! feature.
+//!
+//! A dedicated thread keeps a multishot accept armed on an io_uring instance, so a single
+//! submission accepts any number of connections without an `accept` syscall each. Accepted
+//! connections are handed to the Tokio runtime, which serves them as usual. It's opt-in,
+//! see [`crate::host::http::HttpServer::with_io_uring`]: only enable it where benchmarks
+//! at the host's connection rates show a gain.
+//!
+//! Multishot accept requires Linux 5.19 or later.
+
+use std::io;
+use std::net::SocketAddr;
+use std::os::fd::{AsRawFd, FromRawFd};
+
+use io_uring::{IoUring, cqueue, opcode, types};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Entries in the ring's submission queue. Only the accept is ever in flight.
+const RING_ENTRIES: u32 = 8;
+/// How often the accept thread checks whether the listener was dropped, in nanoseconds.
+const SHUTDOWN_POLL_NANOS: u32 = 100_000_000;
+/// The errors waiting on the ring returns when it times out or is interrupted.
+const ETIME: i32 = 62;
+const EINTR: i32 = 4;
+/// The error an accept completes with when the kernel doesn't support multishot accept.
+const EINVAL: i32 = 22;
+
+/// A TCP listener that accepts connections with io_uring.
+#[derive(Debug)]
+pub struct UringListener {
+    accepted: mpsc::UnboundedReceiver<io::Result<std::net::TcpStream>>,
+    local_addr: SocketAddr,
+}
+
+impl UringListener {
+    /// Starts accepting connections on `listener` on a dedicated thread, which stops once
+    /// the listener is dropped.
+    ///
+    /// # Errors
+    /// Returns an error if io_uring isn't available.
+    pub fn new(listener: std::net::TcpListener) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let ring = IoUring::new(RING_ENTRIES)?;
+        let (tx, accepted) = mpsc::unbounded_channel();
+        std::thread::Builder::new()
+            .name(format!("io-uring-accept-{local_addr}"))
+            .spawn(move || {
+                if let Err(e) = accept_loop(ring, &listener, &tx) {
+                    warn!(addr = ?local_addr, err = ?e, "io_uring accept loop failed");
+                    let _ = tx.send(Err(e));
+                }
+            })?;
+        debug!(addr = ?local_addr, "accepting HTTP connections with io_uring");
+        Ok(Self {
+            accepted,
+            local_addr,
+        })
+    }
+
+    /// The address the listener is bound to.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Whether the accept thread stopped, after which no more connections are accepted.
+    pub fn is_closed(&self) -> bool {
+        self.accepted.is_closed()
+    }
+
+    /// Waits for the next connection.
+    ///
+    /// # Errors
+    /// Returns an error if accepting the connection failed, or the accept thread stopped.
+    pub async fn accept(&mut self) -> io::Result<(TcpStream, SocketAddr)> {
+        let stream = self
+            .accepted
+            .recv()
+            .await
+            .ok_or_else(|| io::Error::other("the io_uring accept thread stopped"))??;
+        stream.set_nonblocking(true)?;
+        let peer = stream.peer_addr()?;
+        Ok((TcpStream::from_std(stream)?, peer))
+    }
+}
+
+/// Accepts connections until the receiving [`UringListener`] is dropped.
+fn accept_loop(
+    mut ring: IoUring,
+    listener: &std::net::TcpListener,
+    tx: &mpsc::UnboundedSender<io::Result<std::net::TcpStream>>,
+) -> io::Result<()> {
+    let accept = opcode::AcceptMulti::new(types::Fd(listener.as_raw_fd())).build();
+    let mut armed = false;
+    let timeout = types::Timespec::new().nsec(SHUTDOWN_POLL_NANOS);
+    let args = types::SubmitArgs::new().timespec(&timeout);
+    while !tx.is_closed() {
+        if !armed {
+            // SAFETY: the accept doesn't reference any buffers, and the listener it accepts
+            // on outlives the ring
+            unsafe { ring.submission().push(&accept) }
+                .map_err(|_| io::Error::other("the io_uring submission queue is full"))?;
+            armed = true;
+        }
+        match ring.submitter().submit_with_args(1, &args) {
+            Ok(_) => {}
+            Err(e) if matches!(e.raw_os_error(), Some(ETIME | EINTR)) => continue,
+            Err(e) => return Err(e),
+        }
+        for completion in ring.completion() {
+            // The accept stays armed as long as the kernel says there's more to come
+            armed &= cqueue::more(completion.flags());
+            let accepted = match completion.result() {
+                // SAFETY: a successful accept returns a new socket that nothing else owns
+                fd if fd >= 0 => Ok(unsafe { std::net::TcpStream::from_raw_fd(fd) }),
+                errno if -errno == EINVAL => {
+                    return Err(io::Error::other(
+                        "multishot accept was rejected, it requires Linux 5.19 or later",
+                    ));
+                }
+                errno => Err(io::Error::from_raw_os_error(-errno)),
+            };
+            if tx.send(accepted).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,418 @@
+//! Resource usage of each workload, so operators can find the heavy tenants on a shared
+//! host.
+//!
+//! Every store a workload's components run in reports to the workload's
+//! [`WorkloadUsage`] in the host's [`UsageLog`]:
+//!
+//! - Guest memory, as linear memories are created and grow, and table elements, as tables
+//!   grow, until the store is dropped. Both are also kept for each live store, along with
+//!   its component's memory limit, to show how much of the limit instances use.
+//! - CPU time, measured while the store executes guest code. Time spent in host functions,
+//!   including waiting on I/O, isn't counted.
+//! - Fuel, when the engine meters it, see [`wasmtime::Config::consume_fuel`]
+//! - The CPU time and fuel of each invocation once its store is dropped, kept for the
+//!   last [`MAX_SAMPLES`] invocations to report percentiles, e.g. for tuning CPU limits
+//! - Invocations, as each one runs in a new instance
+//! - Plugin operations, each time a host function looks up the plugin implementing it
+//!
+//! Usage is queried with [`crate::host::HostApi::workload_usage`], and the state of the
+//! live stores with [`crate::host::HostApi::workload_instances`].
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::types::{InstanceInfo, InstanceUsage, Percentiles, WorkloadUsageResponse};
+
+/// How many recent invocations per-invocation percentiles are computed over.
+pub const MAX_SAMPLES: usize = 1024;
+
+/// The resource usage of every running workload.
+#[derive(Debug, Default)]
+pub struct UsageLog {
+    workloads: Mutex<HashMap<String, Arc<WorkloadUsage>>>,
+}
+
+impl UsageLog {
+    /// Returns the usage of a workload, starting it if the workload has none yet.
+    pub fn workload(&self, workload_id: &str) -> Arc<WorkloadUsage> {
+        self.workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(workload_id.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Returns the usage of a workload so far, if it has any.
+    pub fn get(&self, workload_id: &str) -> Option<WorkloadUsageResponse> {
+        self.workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(workload_id)
+            .map(|usage| usage.snapshot(workload_id))
+    }
+
+    /// Returns the state of a workload's live stores, if it has usage.
+    pub fn instances(&self, workload_id: &str) -> Option<Vec<InstanceInfo>> {
+        self.workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(workload_id)
+            .map(|usage| usage.instances())
+    }
+
+    /// Forgets the usage of a stopped workload.
+    pub fn remove(&self, workload_id: &str) {
+        self.workloads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(workload_id);
+    }
+}
+
+/// The resource usage of one workload, shared by the stores it runs in.
+#[derive(Debug, Default)]
+pub struct WorkloadUsage {
+    memory_bytes: AtomicU64,
+    peak_memory_bytes: AtomicU64,
+    table_elements: AtomicU64,
+    cpu_time_nanos: AtomicU64,
+    invocations: AtomicU64,
+    /// Operations by plugin ID
+    plugin_ops: Mutex<HashMap<String, u64>>,
+    /// Live stores by ID
+    stores: Mutex<HashMap<String, Arc<StoreUsage>>>,
+    consumption: Mutex<Consumption>,
+}
+
+/// What finished invocations consumed.
+#[derive(Debug, Default)]
+struct Consumption {
+    /// Fuel consumed in total, once an invocation has been metered
+    fuel: Option<u64>,
+    /// The CPU time and fuel of recent invocations, oldest first
+    samples: VecDeque<(Duration, Option<u64>)>,
+}
+
+/// The memory and state of one live store of a workload.
+#[derive(Debug)]
+pub struct StoreUsage {
+    component_id: String,
+    memory_limit_bytes: Option<u64>,
+    memory_bytes: AtomicU64,
+    table_elements: AtomicU64,
+    created: Instant,
+    calls: AtomicU64,
+    busy: AtomicBool,
+    last_error: Mutex<Option<String>>,
+}
+
+impl StoreUsage {
+    /// Records the host calling into the store's guest code.
+    pub(crate) fn record_call(&self) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records whether the store is executing guest code.
+    pub(crate) fn set_busy(&self, busy: bool) {
+        self.busy.store(busy, Ordering::Relaxed);
+    }
+
+    /// Records the error a call into the store failed with.
+    pub(crate) fn record_error(&self, error: String) {
+        *self
+            .last_error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(error);
+    }
+
+    fn info(&self, store_id: &str) -> InstanceInfo {
+        InstanceInfo {
+            instance_id: store_id.to_string(),
+            component_id: self.component_id.clone(),
+            age: self.created.elapsed(),
+            calls: self.calls.load(Ordering::Relaxed),
+            busy: self.busy.load(Ordering::Relaxed),
+            last_error: self
+                .last_error
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        }
+    }
+
+    fn snapshot(&self, store_id: &str) -> InstanceUsage {
+        InstanceUsage {
+            instance_id: store_id.to_string(),
+            component_id: self.component_id.clone(),
+            memory_bytes: self.memory_bytes.load(Ordering::Relaxed),
+            memory_limit_bytes: self.memory_limit_bytes,
+            table_elements: self.table_elements.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl WorkloadUsage {
+    /// Starts tracking the memory of a new store of one of the workload's components.
+    pub(crate) fn store_created(
+        &self,
+        store_id: &str,
+        component_id: &str,
+        memory_limit_bytes: Option<u64>,
+    ) -> Arc<StoreUsage> {
+        let store = Arc::new(StoreUsage {
+            component_id: component_id.to_string(),
+            memory_limit_bytes,
+            memory_bytes: AtomicU64::new(0),
+            table_elements: AtomicU64::new(0),
+            created: Instant::now(),
+            calls: AtomicU64::new(0),
+            busy: AtomicBool::new(false),
+            last_error: Mutex::new(None),
+        });
+        self.stores
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(store_id.to_string(), store.clone());
+        store
+    }
+
+    /// Releases the memory and table elements of a dropped store.
+    pub(crate) fn store_dropped(&self, store_id: &str) {
+        let Some(store) = self
+            .stores
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(store_id)
+        else {
+            return;
+        };
+        saturating_sub(
+            &self.memory_bytes,
+            store.memory_bytes.load(Ordering::Relaxed),
+        );
+        saturating_sub(
+            &self.table_elements,
+            store.table_elements.load(Ordering::Relaxed),
+        );
+    }
+
+    /// Records a store allocating guest memory, e.g. when a linear memory grows.
+    pub(crate) fn memory_grown(&self, store: &StoreUsage, bytes: u64) {
+        store.memory_bytes.fetch_add(bytes, Ordering::Relaxed);
+        let current = self.memory_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        self.peak_memory_bytes.fetch_max(current, Ordering::Relaxed);
+    }
+
+    /// Records a store's table growing by `elements`.
+    pub(crate) fn table_grown(&self, store: &StoreUsage, elements: u64) {
+        store.table_elements.fetch_add(elements, Ordering::Relaxed);
+        self.table_elements.fetch_add(elements, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cpu_time(&self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        self.cpu_time_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_invocation(&self) {
+        self.invocations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records what a finished invocation consumed, with `fuel` if the engine meters it.
+    pub(crate) fn record_consumption(&self, cpu_time: Duration, fuel: Option<u64>) {
+        let mut consumption = self
+            .consumption
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(fuel) = fuel {
+            consumption.fuel = Some(consumption.fuel.unwrap_or(0).saturating_add(fuel));
+        }
+        if consumption.samples.len() == MAX_SAMPLES {
+            consumption.samples.pop_front();
+        }
+        consumption.samples.push_back((cpu_time, fuel));
+    }
+
+    pub(crate) fn record_plugin_op(&self, plugin_id: &str) {
+        let mut plugin_ops = self
+            .plugin_ops
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        match plugin_ops.get_mut(plugin_id) {
+            Some(count) => *count += 1,
+            None => {
+                plugin_ops.insert(plugin_id.to_string(), 1);
+            }
+        }
+    }
+
+    fn instances(&self) -> Vec<InstanceInfo> {
+        let mut instances: Vec<InstanceInfo> = self
+            .stores
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(store_id, store)| store.info(store_id))
+            .collect();
+        // Oldest first
+        instances.sort_by(|a, b| b.age.cmp(&a.age));
+        instances
+    }
+
+    fn snapshot(&self, workload_id: &str) -> WorkloadUsageResponse {
+        let consumption = self
+            .consumption
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        WorkloadUsageResponse {
+            workload_id: workload_id.to_string(),
+            memory_bytes: self.memory_bytes.load(Ordering::Relaxed),
+            peak_memory_bytes: self.peak_memory_bytes.load(Ordering::Relaxed),
+            table_elements: self.table_elements.load(Ordering::Relaxed),
+            instances: self
+                .stores
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .iter()
+                .map(|(store_id, store)| store.snapshot(store_id))
+                .collect(),
+            cpu_time: Duration::from_nanos(self.cpu_time_nanos.load(Ordering::Relaxed)),
+            cpu_time_per_invocation: percentiles(
+                consumption.samples.iter().map(|(cpu_time, _)| *cpu_time),
+            ),
+            fuel_consumed: consumption.fuel,
+            fuel_per_invocation: percentiles(
+                consumption.samples.iter().filter_map(|(_, fuel)| *fuel),
+            ),
+            invocations: self.invocations.load(Ordering::Relaxed),
+            plugin_ops: self
+                .plugin_ops
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone(),
+        }
+    }
+}
+
+/// The nearest-rank percentiles of `samples`, or `None` if there are none.
+fn percentiles<T: Copy + Ord>(samples: impl Iterator<Item = T>) -> Option<Percentiles<T>> {
+    let mut samples: Vec<T> = samples.collect();
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort_unstable();
+    let rank = |percent: usize| samples[(samples.len() * percent).div_ceil(100) - 1];
+    Some(Percentiles {
+        p50: rank(50),
+        p90: rank(90),
+        p99: rank(99),
+        max: rank(100),
+        count: samples.len(),
+    })
+}
+
+/// Saturate rather than wrap if growth and release were ever mismatched.
+fn saturating_sub(counter: &AtomicU64, amount: u64) {
+    let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        Some(current.saturating_sub(amount))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workload_usage() {
+        let log = UsageLog::default();
+        let usage = log.workload("a");
+        let first = usage.store_created("first", "component", Some(1024 * 1024));
+        let second = usage.store_created("second", "component", None);
+        usage.memory_grown(&first, 64 * 1024);
+        usage.memory_grown(&second, 128 * 1024);
+        usage.table_grown(&first, 10);
+        usage.table_grown(&second, 5);
+        usage.store_dropped("second");
+        usage.record_cpu_time(Duration::from_millis(5));
+        usage.record_invocation();
+        usage.record_plugin_op("wasi-keyvalue");
+        usage.record_plugin_op("wasi-keyvalue");
+
+        let snapshot = log.get("a").expect("the workload has usage");
+        assert_eq!(snapshot.memory_bytes, 64 * 1024);
+        assert_eq!(snapshot.peak_memory_bytes, 192 * 1024);
+        assert_eq!(snapshot.table_elements, 10);
+        assert_eq!(
+            snapshot.instances,
+            vec![InstanceUsage {
+                instance_id: "first".to_string(),
+                component_id: "component".to_string(),
+                memory_bytes: 64 * 1024,
+                memory_limit_bytes: Some(1024 * 1024),
+                table_elements: 10,
+            }]
+        );
+        assert_eq!(snapshot.cpu_time, Duration::from_millis(5));
+        assert_eq!(snapshot.invocations, 1);
+        assert_eq!(snapshot.plugin_ops.get("wasi-keyvalue"), Some(&2));
+
+        assert_eq!(snapshot.cpu_time_per_invocation, None);
+
+        first.record_call();
+        first.set_busy(true);
+        first.record_error("wasm trap: unreachable".to_string());
+        let instances = log.instances("a").expect("the workload has usage");
+        assert_eq!(instances.len(), 1);
+        assert_eq!(instances[0].instance_id, "first");
+        assert_eq!(instances[0].calls, 1);
+        assert!(instances[0].busy);
+        assert_eq!(
+            instances[0].last_error.as_deref(),
+            Some("wasm trap: unreachable")
+        );
+        assert_eq!(snapshot.fuel_consumed, None);
+
+        // Stores that outlive their workload's entry don't bring it back
+        log.remove("a");
+        usage.record_invocation();
+        assert!(log.get("a").is_none());
+    }
+
+    #[test]
+    fn test_consumption_percentiles() {
+        let usage = WorkloadUsage::default();
+        for millis in 1..=100 {
+            usage.record_consumption(Duration::from_millis(millis), Some(millis * 10));
+        }
+        // Invocations of stores without fuel don't count towards fuel
+        usage.record_consumption(Duration::from_millis(1), None);
+
+        let snapshot = usage.snapshot("a");
+        let cpu_time = snapshot
+            .cpu_time_per_invocation
+            .expect("invocations finished");
+        assert_eq!(cpu_time.p50, Duration::from_millis(50));
+        assert_eq!(cpu_time.p99, Duration::from_millis(99));
+        assert_eq!(cpu_time.max, Duration::from_millis(100));
+        assert_eq!(cpu_time.count, 101);
+        assert_eq!(snapshot.fuel_consumed, Some(50_500));
+        let fuel = snapshot
+            .fuel_per_invocation
+            .expect("invocations were metered");
+        assert_eq!((fuel.p90, fuel.count), (900, 100));
+
+        for _ in 0..MAX_SAMPLES {
+            usage.record_consumption(Duration::ZERO, None);
+        }
+        let snapshot = usage.snapshot("a");
+        assert_eq!(
+            snapshot.cpu_time_per_invocation.map(|p| p.max),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(snapshot.fuel_per_invocation, None);
+        assert_eq!(snapshot.fuel_consumed, Some(50_500));
+    }
+}
@@ -0,0 +1,85 @@
+//! Restrictions on the host directories workloads mount, so a workload spec can't expose
+//! arbitrary files of the node, such as its credentials, to components.
+//!
+//! A [`HostPathVolume`] mounts a directory of the node, e.g. certificates or a data set,
+//! into the components whose `volume_mounts` name the volume, at their `mount_path` and
+//! read-only if the mount says so. Hosts configured with
+//! [`crate::host::HostBuilder::with_host_path_volumes`] only start workloads whose host
+//! path volumes lie within one of the listed directories, after resolving symlinks and
+//! `..`. Hosts without the list accept any directory, as `wash dev` relies on.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context as _, bail};
+
+use crate::types::{HostPathVolume, Volume, VolumeType};
+
+/// Checks that the host path volumes of a workload lie within the `allowed` directories.
+///
+/// # Errors
+/// Returns an error naming the first volume whose directory doesn't exist or isn't allowed.
+pub(crate) fn check(allowed: &[PathBuf], volumes: &[Volume]) -> anyhow::Result<()> {
+    for volume in volumes {
+        let VolumeType::HostPath(HostPathVolume { local_path }) = &volume.volume_type else {
+            continue;
+        };
+        let path = Path::new(local_path).canonicalize().with_context(|| {
+            format!(
+                "host path '{local_path}' of volume '{}' isn't accessible",
+                volume.name
+            )
+        })?;
+        let permitted = allowed
+            .iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .any(|dir| path.starts_with(dir));
+        if !permitted {
+            bail!(
+                "host path '{local_path}' of volume '{}' isn't in a directory the host allows mounting",
+                volume.name
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::EmptyDirVolume;
+
+    fn host_path(name: &str, path: &Path) -> Volume {
+        Volume {
+            name: name.to_string(),
+            volume_type: VolumeType::HostPath(HostPathVolume {
+                local_path: path.display().to_string(),
+            }),
+        }
+    }
+
+    #[test]
+    fn test_check() -> anyhow::Result<()> {
+        let root = tempfile::tempdir()?;
+        let certs = root.path().join("certs");
+        let secrets = root.path().join("secrets");
+        std::fs::create_dir_all(certs.join("ca"))?;
+        std::fs::create_dir(&secrets)?;
+        let allowed = [certs.clone()];
+
+        check(&allowed, &[host_path("certs", &certs)])?;
+        check(&allowed, &[host_path("ca", &certs.join("ca"))])?;
+        check(
+            &allowed,
+            &[Volume {
+                name: "scratch".to_string(),
+                volume_type: VolumeType::EmptyDir(EmptyDirVolume {}),
+            }],
+        )?;
+
+        assert!(check(&allowed, &[host_path("secrets", &secrets)]).is_err());
+        assert!(check(&allowed, &[host_path("escape", &certs.join("../secrets"))]).is_err());
+        assert!(check(&allowed, &[host_path("missing", &certs.join("missing"))]).is_err());
+        assert!(check(&[], &[host_path("certs", &certs)]).is_err());
+        Ok(())
+    }
+}
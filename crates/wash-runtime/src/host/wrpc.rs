@@ -0,0 +1,692 @@
+//! Invocation of services published on other hosts, over wRPC on NATS.
+//!
+//! A host with a [`WrpcTransport`] serves every named service (see
+//! [`crate::host::services`]) its workloads publish, so other hosts on the same NATS
+//! cluster can call it. When a workload imports a service that has no instances on its
+//! own host, the import is linked to the transport instead, and each call is sent to
+//! whichever host serves the service. Local instances are always preferred.
+//!
+//! Calls follow wRPC's NATS conventions: a function is invoked on the subject
+//! `{prefix}.{namespace}.{service}.wrpc.0.0.1.{interface}.{function}`, with the
+//! parameters as the request payload and the results as the reply. Hosts serving the same
+//! service share a queue group, so each call is handled once. Values use wRPC's value
+//! encoding:
+//!
+//! | WIT type                       | encoding                                           |
+//! |--------------------------------|----------------------------------------------------|
+//! | `bool`, `u8`, `s8`             | one byte                                           |
+//! | `u16`, `u32`, `u64`            | unsigned LEB128                                    |
+//! | `s16`, `s32`, `s64`            | signed LEB128                                      |
+//! | `f32`, `f64`                   | little-endian IEEE 754                             |
+//! | `char`                         | UTF-8                                              |
+//! | `string`, `list<T>`            | LEB128 length, then the bytes or elements          |
+//! | `record`, `tuple<..>`          | fields in order                                    |
+//! | `variant`, `enum`              | LEB128 case index, then the payload if any         |
+//! | `option<T>`, `result<T, E>`    | a byte, `0` for `none`/`ok` and `1` for `some`/`err`, then the payload if any |
+//! | `flags`                        | little-endian bit set, one bit per flag            |
+//!
+//! Only a single request and reply are exchanged, so functions taking or returning
+//! resources, futures or streams can't be called remotely. A failed call is answered
+//! with an empty reply carrying the error in the [`ERROR_HEADER`] header.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::{Context as _, anyhow, bail, ensure};
+use futures::StreamExt as _;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, trace, warn};
+use wasmtime::component::{Type, Val};
+
+use crate::engine::json::type_name;
+use crate::host::services::ServiceRegistry;
+use crate::redact;
+use crate::wit::WitInterface;
+
+/// Prefix of the subjects services are served on, unless overridden.
+pub const DEFAULT_PREFIX: &str = "wasmcloud";
+
+/// Header carrying the error of a failed call.
+pub const ERROR_HEADER: &str = "Wasmcloud-Invocation-Error";
+
+/// The wRPC protocol version in invocation subjects.
+const WRPC_VERSION: &str = "wrpc.0.0.1";
+
+/// How long a call waits for a reply, unless overridden.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Sends calls to services on other hosts and serves the services on this one.
+pub struct WrpcTransport {
+    client: Arc<async_nats::Client>,
+    prefix: String,
+    timeout: Duration,
+    /// The services this host serves, keyed by namespace and name
+    serving: Mutex<HashMap<(String, String), CancellationToken>>,
+}
+
+impl WrpcTransport {
+    pub fn new(client: Arc<async_nats::Client>) -> Self {
+        Self {
+            client,
+            prefix: DEFAULT_PREFIX.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            serving: Mutex::default(),
+        }
+    }
+
+    /// Sets the subject prefix, which separates hosts sharing a NATS cluster that
+    /// shouldn't call each other's services.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Sets how long a call waits for a reply.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the subject prefix for a service's functions, up to the interface.
+    fn service_subject(&self, namespace: &str, service: &str) -> String {
+        format!("{}.{namespace}.{service}.{WRPC_VERSION}", self.prefix)
+    }
+
+    /// Calls a function of a service on another host.
+    ///
+    /// # Arguments
+    /// * `params` - The arguments, with `param_types` the types of the function's parameters
+    /// * `result_types` - The types of the function's results
+    ///
+    /// # Errors
+    /// Returns an error if no host serves the service, the call times out, or the remote
+    /// call fails.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn invoke(
+        &self,
+        namespace: &str,
+        service: &str,
+        interface: &str,
+        function: &str,
+        params: &[Val],
+        param_types: &[Type],
+        result_types: &[Type],
+    ) -> anyhow::Result<Vec<Val>> {
+        let mut payload = Vec::new();
+        for (ty, val) in param_types.iter().zip(params) {
+            encode_val(ty, val, &mut payload)?;
+        }
+        let subject = format!(
+            "{}.{interface}.{function}",
+            self.service_subject(namespace, service)
+        );
+        trace!(subject, "invoking remote service");
+        let reply = self
+            .client
+            .send_request(
+                subject,
+                async_nats::Request::new()
+                    .payload(payload.into())
+                    .timeout(Some(self.timeout)),
+            )
+            .await
+            .map_err(|e| {
+                anyhow!("failed to call '{interface}#{function}' of service '{service}': {e}")
+            })?;
+        if let Some(error) = reply.headers.as_ref().and_then(|h| h.get(ERROR_HEADER)) {
+            bail!(
+                "service '{service}' failed to handle '{interface}#{function}': {}",
+                error.as_str()
+            );
+        }
+
+        let mut buf = &reply.payload[..];
+        let results = result_types
+            .iter()
+            .map(|ty| decode_val(ty, &mut buf))
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("failed to decode results")?;
+        ensure!(buf.is_empty(), "unexpected trailing bytes after results");
+        Ok(results)
+    }
+
+    /// Starts serving a service to other hosts, calling its local instances. Does nothing
+    /// if the service is already served.
+    ///
+    /// # Errors
+    /// Returns an error if subscribing to the service's subject fails.
+    pub async fn serve(
+        &self,
+        registry: Arc<ServiceRegistry>,
+        namespace: &str,
+        service: &str,
+    ) -> anyhow::Result<()> {
+        let key = (namespace.to_string(), service.to_string());
+        if self
+            .serving
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(&key)
+        {
+            return Ok(());
+        }
+
+        let prefix = self.service_subject(namespace, service);
+        let mut subscriber = self
+            .client
+            .queue_subscribe(format!("{prefix}.>"), prefix.clone())
+            .await
+            .with_context(|| format!("failed to serve service '{service}' over wRPC"))?;
+        let cancel = CancellationToken::new();
+        if let Some(previous) = self
+            .serving
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, cancel.clone())
+        {
+            previous.cancel();
+        }
+
+        debug!(namespace, service, "serving service over wRPC");
+        let client = self.client.clone();
+        let namespace = namespace.to_string();
+        let service = service.to_string();
+        tokio::spawn(async move {
+            loop {
+                let message = tokio::select! {
+                    message = subscriber.next() => message,
+                    _ = cancel.cancelled() => break,
+                };
+                let Some(message) = message else {
+                    break;
+                };
+                let Some(reply) = message.reply.clone() else {
+                    continue;
+                };
+                let client = client.clone();
+                let registry = registry.clone();
+                let namespace = namespace.clone();
+                let service = service.clone();
+                let prefix = prefix.clone();
+                tokio::spawn(async move {
+                    let handled = handle(&registry, &namespace, &service, &prefix, &message).await;
+                    let published = match handled {
+                        Ok(results) => client.publish(reply, results.into()).await,
+                        Err(e) => {
+                            let e = redact::redact_error(e);
+                            debug!(
+                                subject = message.subject.as_str(),
+                                "remote invocation failed: {e:#}"
+                            );
+                            let mut headers = async_nats::HeaderMap::new();
+                            headers.insert(ERROR_HEADER, format!("{e:#}").as_str());
+                            client
+                                .publish_with_headers(reply, headers, Default::default())
+                                .await
+                        }
+                    };
+                    if let Err(e) = published {
+                        warn!("failed to reply to remote invocation: {e}");
+                    }
+                });
+            }
+            let _ = subscriber.unsubscribe().await;
+            debug!(namespace, service, "stopped serving service over wRPC");
+        });
+        Ok(())
+    }
+
+    /// Stops serving a service to other hosts.
+    pub fn stop_serving(&self, namespace: &str, service: &str) {
+        if let Some(cancel) = self
+            .serving
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&(namespace.to_string(), service.to_string()))
+        {
+            cancel.cancel();
+        }
+    }
+}
+
+impl std::fmt::Debug for WrpcTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WrpcTransport")
+            .field("prefix", &self.prefix)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Calls a local instance of a service with the parameters of an invocation, returning
+/// the encoded results.
+async fn handle(
+    registry: &ServiceRegistry,
+    namespace: &str,
+    service: &str,
+    prefix: &str,
+    message: &async_nats::Message,
+) -> anyhow::Result<Vec<u8>> {
+    let Some((interface, function)) = message
+        .subject
+        .strip_prefix(prefix)
+        .and_then(|rest| rest.strip_prefix('.'))
+        .and_then(|rest| rest.rsplit_once('.'))
+    else {
+        bail!("invalid invocation subject '{}'", message.subject);
+    };
+    // Only published interfaces are reachable, so a workload's internal exports stay private
+    let wanted = WitInterface::from(interface);
+    let exports = registry
+        .exports(namespace, service)
+        .await
+        .unwrap_or_default();
+    ensure!(
+        exports.iter().any(|e| e.contains(&wanted)),
+        "service '{service}' does not publish '{interface}'"
+    );
+    let target = registry
+        .pick(namespace, service)
+        .await
+        .with_context(|| format!("service '{service}' has no running instances"))?;
+
+    let export = target
+        .exported_functions()
+        .await
+        .into_iter()
+        .find(|f| {
+            f.name == function
+                && (f.interface == interface
+                    || WitInterface::from(f.interface.as_str()).contains(&wanted))
+        })
+        .with_context(|| format!("service '{service}' does not export '{interface}#{function}'"))?;
+
+    let mut buf = &message.payload[..];
+    let params = export
+        .params
+        .iter()
+        .map(|(name, ty)| {
+            decode_val(ty, &mut buf).with_context(|| format!("invalid argument '{name}'"))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    ensure!(buf.is_empty(), "unexpected trailing bytes after arguments");
+
+    let results = target.invoke(&export.interface, function, &params).await?;
+    let mut payload = Vec::new();
+    for (ty, val) in export.results.iter().zip(&results) {
+        encode_val(ty, val, &mut payload)?;
+    }
+    Ok(payload)
+}
+
+/// Appends the wRPC encoding of a value of the given type.
+fn encode_val(ty: &Type, val: &Val, buf: &mut Vec<u8>) -> anyhow::Result<()> {
+    match (ty, val) {
+        (Type::Bool, Val::Bool(v)) => buf.push(u8::from(*v)),
+        (Type::S8, Val::S8(v)) => buf.push(*v as u8),
+        (Type::U8, Val::U8(v)) => buf.push(*v),
+        (Type::S16, Val::S16(v)) => write_signed(buf, (*v).into()),
+        (Type::U16, Val::U16(v)) => write_unsigned(buf, (*v).into()),
+        (Type::S32, Val::S32(v)) => write_signed(buf, (*v).into()),
+        (Type::U32, Val::U32(v)) => write_unsigned(buf, (*v).into()),
+        (Type::S64, Val::S64(v)) => write_signed(buf, *v),
+        (Type::U64, Val::U64(v)) => write_unsigned(buf, *v),
+        (Type::Float32, Val::Float32(v)) => buf.extend_from_slice(&v.to_le_bytes()),
+        (Type::Float64, Val::Float64(v)) => buf.extend_from_slice(&v.to_le_bytes()),
+        (Type::Char, Val::Char(v)) => {
+            buf.extend_from_slice(v.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+        (Type::String, Val::String(v)) => {
+            write_unsigned(buf, v.len() as u64);
+            buf.extend_from_slice(v.as_bytes());
+        }
+        (Type::List(list), Val::List(vs)) => {
+            let element = list.ty();
+            write_unsigned(buf, vs.len() as u64);
+            for v in vs {
+                encode_val(&element, v, buf)?;
+            }
+        }
+        (Type::Tuple(tuple), Val::Tuple(vs)) => {
+            ensure!(
+                tuple.types().len() == vs.len(),
+                "expected a tuple of {} elements, got {}",
+                tuple.types().len(),
+                vs.len()
+            );
+            for (ty, v) in tuple.types().zip(vs) {
+                encode_val(&ty, v, buf)?;
+            }
+        }
+        (Type::Record(record), Val::Record(fields)) => {
+            for field in record.fields() {
+                let (_, v) = fields
+                    .iter()
+                    .find(|(name, _)| name == field.name)
+                    .with_context(|| format!("missing field '{}'", field.name))?;
+                encode_val(&field.ty, v, buf)?;
+            }
+        }
+        (Type::Variant(variant), Val::Variant(name, payload)) => {
+            let (index, case) = variant
+                .cases()
+                .enumerate()
+                .find(|(_, c)| c.name == name)
+                .with_context(|| format!("unknown variant case '{name}'"))?;
+            write_unsigned(buf, index as u64);
+            match (case.ty, payload) {
+                (Some(ty), Some(payload)) => encode_val(&ty, payload, buf)?,
+                (None, None) => {}
+                _ => bail!("mismatched payload for variant case '{name}'"),
+            }
+        }
+        (Type::Enum(enum_), Val::Enum(name)) => {
+            let index = enum_
+                .names()
+                .position(|n| n == name)
+                .with_context(|| format!("unknown enum case '{name}'"))?;
+            write_unsigned(buf, index as u64);
+        }
+        (Type::Option(option), Val::Option(v)) => match v {
+            None => buf.push(0),
+            Some(v) => {
+                buf.push(1);
+                encode_val(&option.ty(), v, buf)?;
+            }
+        },
+        (Type::Result(result), Val::Result(v)) => {
+            let (tag, ty, payload) = match v {
+                Ok(payload) => (0, result.ok(), payload),
+                Err(payload) => (1, result.err(), payload),
+            };
+            buf.push(tag);
+            match (ty, payload) {
+                (Some(ty), Some(payload)) => encode_val(&ty, payload, buf)?,
+                (None, None) => {}
+                _ => bail!("mismatched payload for result"),
+            }
+        }
+        (Type::Flags(flags), Val::Flags(set)) => {
+            let names: Vec<&str> = flags.names().collect();
+            let mut bits = vec![0u8; names.len().div_ceil(8)];
+            for name in set {
+                let index = names
+                    .iter()
+                    .position(|n| n == name)
+                    .with_context(|| format!("unknown flag '{name}'"))?;
+                bits[index / 8] |= 1 << (index % 8);
+            }
+            buf.extend_from_slice(&bits);
+        }
+        (ty, _) => bail!(
+            "can't send a value of type {} to another host",
+            type_name(ty)
+        ),
+    }
+    Ok(())
+}
+
+/// Reads a value of the given type from the front of `buf`.
+fn decode_val(ty: &Type, buf: &mut &[u8]) -> anyhow::Result<Val> {
+    Ok(match ty {
+        Type::Bool => match read_byte(buf)? {
+            0 => Val::Bool(false),
+            1 => Val::Bool(true),
+            other => bail!("invalid bool {other}"),
+        },
+        Type::S8 => Val::S8(read_byte(buf)? as i8),
+        Type::U8 => Val::U8(read_byte(buf)?),
+        Type::S16 => Val::S16(read_signed(buf)?.try_into().context("s16 out of range")?),
+        Type::U16 => Val::U16(read_unsigned(buf)?.try_into().context("u16 out of range")?),
+        Type::S32 => Val::S32(read_signed(buf)?.try_into().context("s32 out of range")?),
+        Type::U32 => Val::U32(read_unsigned(buf)?.try_into().context("u32 out of range")?),
+        Type::S64 => Val::S64(read_signed(buf)?),
+        Type::U64 => Val::U64(read_unsigned(buf)?),
+        Type::Float32 => Val::Float32(f32::from_le_bytes(read_array(buf)?)),
+        Type::Float64 => Val::Float64(f64::from_le_bytes(read_array(buf)?)),
+        Type::Char => {
+            let len = match buf.first() {
+                Some(b) if *b < 0x80 => 1,
+                Some(b) if *b >= 0xf0 => 4,
+                Some(b) if *b >= 0xe0 => 3,
+                Some(_) => 2,
+                None => bail!("unexpected end of input"),
+            };
+            let bytes = read_bytes(buf, len)?;
+            let c = std::str::from_utf8(bytes)
+                .ok()
+                .and_then(|s| s.chars().next())
+                .context("invalid char")?;
+            Val::Char(c)
+        }
+        Type::String => {
+            let len = read_len(buf)?;
+            let bytes = read_bytes(buf, len)?;
+            Val::String(String::from_utf8(bytes.to_vec()).context("string is not valid UTF-8")?)
+        }
+        Type::List(list) => {
+            let element = list.ty();
+            let len = read_len(buf)?;
+            // Each element takes at least a byte, which bounds the allocation
+            ensure!(len <= buf.len(), "list length {len} exceeds the input");
+            Val::List(
+                (0..len)
+                    .map(|_| decode_val(&element, buf))
+                    .collect::<anyhow::Result<_>>()?,
+            )
+        }
+        Type::Tuple(tuple) => Val::Tuple(
+            tuple
+                .types()
+                .map(|ty| decode_val(&ty, buf))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Type::Record(record) => Val::Record(
+            record
+                .fields()
+                .map(|field| Ok((field.name.to_string(), decode_val(&field.ty, buf)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        Type::Variant(variant) => {
+            let index = read_len(buf)?;
+            let case = variant
+                .cases()
+                .nth(index)
+                .with_context(|| format!("invalid variant case {index}"))?;
+            let payload = case
+                .ty
+                .map(|ty| decode_val(&ty, buf).map(Box::new))
+                .transpose()?;
+            Val::Variant(case.name.to_string(), payload)
+        }
+        Type::Enum(enum_) => {
+            let index = read_len(buf)?;
+            let name = enum_
+                .names()
+                .nth(index)
+                .with_context(|| format!("invalid enum case {index}"))?;
+            Val::Enum(name.to_string())
+        }
+        Type::Option(option) => match read_byte(buf)? {
+            0 => Val::Option(None),
+            1 => Val::Option(Some(Box::new(decode_val(&option.ty(), buf)?))),
+            other => bail!("invalid option tag {other}"),
+        },
+        Type::Result(result) => {
+            let is_ok = match read_byte(buf)? {
+                0 => true,
+                1 => false,
+                other => bail!("invalid result tag {other}"),
+            };
+            let ty = if is_ok { result.ok() } else { result.err() };
+            let payload = ty
+                .map(|ty| decode_val(&ty, buf).map(Box::new))
+                .transpose()?;
+            Val::Result(if is_ok { Ok(payload) } else { Err(payload) })
+        }
+        Type::Flags(flags) => {
+            let names: Vec<&str> = flags.names().collect();
+            let bits = read_bytes(buf, names.len().div_ceil(8))?;
+            Val::Flags(
+                names
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| bits[i / 8] & (1 << (i % 8)) != 0)
+                    .map(|(_, name)| name.to_string())
+                    .collect(),
+            )
+        }
+        other => bail!(
+            "can't receive a value of type {} from another host",
+            type_name(other)
+        ),
+    })
+}
+
+fn write_unsigned(buf: &mut Vec<u8>, mut v: u64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_signed(buf: &mut Vec<u8>, mut v: i64) {
+    loop {
+        let byte = (v & 0x7f) as u8;
+        v >>= 7;
+        // Done once the remaining bits are all copies of the sign bit just written
+        if (v == 0 && byte & 0x40 == 0) || (v == -1 && byte & 0x40 != 0) {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn read_byte(buf: &mut &[u8]) -> anyhow::Result<u8> {
+    let (byte, rest) = buf.split_first().context("unexpected end of input")?;
+    *buf = rest;
+    Ok(*byte)
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8], len: usize) -> anyhow::Result<&'a [u8]> {
+    ensure!(buf.len() >= len, "unexpected end of input");
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn read_array<const N: usize>(buf: &mut &[u8]) -> anyhow::Result<[u8; N]> {
+    Ok(read_bytes(buf, N)?.try_into()?)
+}
+
+fn read_unsigned(buf: &mut &[u8]) -> anyhow::Result<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(buf)?;
+        let bits = u64::from(byte & 0x7f);
+        ensure!(shift < 63 || bits <= 1, "LEB128 value overflows 64 bits");
+        v |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+    bail!("LEB128 value overflows 64 bits")
+}
+
+fn read_signed(buf: &mut &[u8]) -> anyhow::Result<i64> {
+    let mut v = 0i64;
+    for shift in (0..64).step_by(7) {
+        let byte = read_byte(buf)?;
+        v |= i64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            // Sign-extend from the last bit read
+            if shift < 57 && byte & 0x40 != 0 {
+                v |= -1 << (shift + 7);
+            }
+            return Ok(v);
+        }
+    }
+    bail!("LEB128 value overflows 64 bits")
+}
+
+fn read_len(buf: &mut &[u8]) -> anyhow::Result<usize> {
+    let len = read_unsigned(buf)?;
+    u32::try_from(len)
+        .map(|len| len as usize)
+        .context("length out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leb128() -> anyhow::Result<()> {
+        for v in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut buf = Vec::new();
+            write_unsigned(&mut buf, v);
+            assert_eq!(read_unsigned(&mut &buf[..])?, v);
+        }
+        for v in [
+            0,
+            1,
+            -1,
+            63,
+            64,
+            -64,
+            -65,
+            i64::from(i32::MIN),
+            i64::MIN,
+            i64::MAX,
+        ] {
+            let mut buf = Vec::new();
+            write_signed(&mut buf, v);
+            assert_eq!(read_signed(&mut &buf[..])?, v, "{v}");
+        }
+
+        let mut buf = Vec::new();
+        write_unsigned(&mut buf, 624485);
+        assert_eq!(buf, [0xe5, 0x8e, 0x26]);
+        buf.clear();
+        write_signed(&mut buf, -123456);
+        assert_eq!(buf, [0xc0, 0xbb, 0x78]);
+
+        assert!(read_unsigned(&mut &[0x80, 0x80][..]).is_err());
+        assert!(read_unsigned(&mut &[0xff; 10][..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_primitive_roundtrip() -> anyhow::Result<()> {
+        for (ty, val) in [
+            (Type::Bool, Val::Bool(true)),
+            (Type::S8, Val::S8(-5)),
+            (Type::U16, Val::U16(u16::MAX)),
+            (Type::S32, Val::S32(-70000)),
+            (Type::U64, Val::U64(u64::MAX)),
+            (Type::Float32, Val::Float32(1.5)),
+            (Type::Float64, Val::Float64(-0.25)),
+            (Type::Char, Val::Char('😀')),
+            (Type::String, Val::String("héllo".to_string())),
+        ] {
+            let mut buf = Vec::new();
+            encode_val(&ty, &val, &mut buf)?;
+            let mut rest = &buf[..];
+            assert_eq!(decode_val(&ty, &mut rest)?, val);
+            assert!(rest.is_empty());
+        }
+
+        assert!(encode_val(&Type::U8, &Val::S8(1), &mut Vec::new()).is_err());
+        assert!(decode_val(&Type::Bool, &mut &[2][..]).is_err());
+        assert!(decode_val(&Type::U16, &mut &[0xff, 0xff, 0x04][..]).is_err());
+        assert!(decode_val(&Type::String, &mut &[3, b'a'][..]).is_err());
+        Ok(())
+    }
+}
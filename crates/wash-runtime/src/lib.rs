@@ -3,6 +3,7 @@
 pub mod engine;
 pub mod host;
 pub mod plugin;
+pub mod redact;
 pub mod types;
 pub mod wit;
 
@@ -50,6 +51,7 @@ mod test {
                 namespace: "test".to_string(),
                 name: "test-workload".to_string(),
                 annotations: HashMap::new(),
+                secret_config_keys: Default::default(),
                 service: None,
                 components: vec![],
                 host_interfaces: vec![],
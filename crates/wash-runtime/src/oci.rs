@@ -158,6 +158,12 @@ impl CacheManager {
         cache_dir.join("digest")
     }
 
+    /// Get the cache path for the manifest annotations file
+    fn get_annotations_path(&self, reference: &str) -> PathBuf {
+        let cache_dir = self.get_cache_dir(reference);
+        cache_dir.join("annotations.json")
+    }
+
     /// Check if an artifact is cached (both component and digest must exist)
     async fn is_cached(&self, reference: &str) -> bool {
         let component_path = self.get_component_path(reference);
@@ -190,6 +196,44 @@ impl CacheManager {
         Ok((component_data, digest.trim().to_string()))
     }
 
+    /// Read the cached manifest annotations of an artifact. Artifacts cached before
+    /// annotations were kept have none.
+    async fn read_cached_annotations(&self, reference: &str) -> Result<HashMap<String, String>> {
+        let annotations_path = self.get_annotations_path(reference);
+        match tokio::fs::read(&annotations_path).await {
+            Ok(annotations) => serde_json::from_slice(&annotations).with_context(|| {
+                format!(
+                    "failed to parse cached annotations at {}",
+                    annotations_path.display()
+                )
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(e) => Err(e).with_context(|| {
+                format!(
+                    "failed to read cached annotations at {}",
+                    annotations_path.display()
+                )
+            }),
+        }
+    }
+
+    /// Write the manifest annotations of an artifact to cache
+    async fn write_annotations_to_cache(
+        &self,
+        reference: &str,
+        annotations: &HashMap<String, String>,
+    ) -> Result<()> {
+        let annotations_path = self.get_annotations_path(reference);
+        tokio::fs::write(&annotations_path, serde_json::to_vec(annotations)?)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to write annotations to cache at {}",
+                    annotations_path.display()
+                )
+            })
+    }
+
     /// Write artifact and digest to cache
     async fn write_to_cache(&self, reference: &str, data: &[u8], digest: &str) -> Result<()> {
         let component_path = self.get_component_path(reference);
@@ -319,8 +363,23 @@ impl CredentialResolver {
 ///     Ok(())
 /// }
 /// ```
-#[instrument(skip(config), fields(reference = %reference))]
 pub async fn pull_component(reference: &str, config: OciConfig) -> Result<(Vec<u8>, String)> {
+    let (component_data, digest, _) = pull_component_with_annotations(reference, config).await?;
+    Ok((component_data, digest))
+}
+
+/// Pull a WebAssembly component from an OCI registry along with the annotations of its
+/// manifest, e.g. `org.opencontainers.image.source`, to record where it came from.
+///
+/// Behaves like [`pull_component`] otherwise.
+///
+/// # Returns
+/// The raw bytes of the component, its digest and its manifest annotations
+#[instrument(skip(config), fields(reference = %reference))]
+pub async fn pull_component_with_annotations(
+    reference: &str,
+    config: OciConfig,
+) -> Result<(Vec<u8>, String, HashMap<String, String>)> {
     info!(reference = %reference, "Pulling component");
 
     // Parse OCI reference
@@ -337,7 +396,8 @@ pub async fn pull_component(reference: &str, config: OciConfig) -> Result<(Vec<u
         if cache_manager.is_cached(reference).await {
             debug!("Found cached artifact");
             let (component_data, digest) = cache_manager.read_cached(reference).await?;
-            return Ok((component_data, digest));
+            let annotations = cache_manager.read_cached_annotations(reference).await?;
+            return Ok((component_data, digest, annotations));
         }
     }
 
@@ -397,6 +457,12 @@ pub async fn pull_component(reference: &str, config: OciConfig) -> Result<(Vec<u
     let digest = image_data
         .digest
         .ok_or_else(|| anyhow!("no digest found in pulled artifact"))?;
+    let annotations: HashMap<String, String> = image_data
+        .manifest
+        .and_then(|manifest| manifest.annotations)
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
 
     // Validate that it's a valid WebAssembly component
     validate_component(&component_data)
@@ -409,10 +475,100 @@ pub async fn pull_component(reference: &str, config: OciConfig) -> Result<(Vec<u
             .write_to_cache(reference, &component_data, &digest)
             .await
             .with_context(|| "failed to cache component")?;
+        cache_manager
+            .write_annotations_to_cache(reference, &annotations)
+            .await
+            .with_context(|| "failed to cache component annotations")?;
     }
 
     info!(size = component_data.len(), digest = %digest, "Successfully pulled component");
-    Ok((component_data, digest))
+    Ok((component_data, digest, annotations))
+}
+
+/// Where [`pull_component_from`] looks for the layer of a component before the registry,
+/// e.g. the hosts of a cluster that pulled it already.
+#[async_trait::async_trait]
+pub trait LayerSource: Send + Sync {
+    /// Fetches the layer with `digest`, e.g. `sha256:...`, if the source has it.
+    async fn fetch_layer(&self, digest: &str) -> Option<Vec<u8>>;
+}
+
+/// Pull a WebAssembly component, fetching only its manifest from the registry when
+/// `layers` has the layer the manifest names.
+///
+/// The layer is checked against the digest in the manifest, so a source can't substitute
+/// other bytes. The component is pulled like [`pull_component_with_annotations`] if the
+/// manifest can't be fetched, `layers` doesn't have the layer or the check fails.
+///
+/// # Returns
+/// The raw bytes of the component, its digest and its manifest annotations
+#[instrument(skip(config, layers), fields(reference = %reference))]
+pub async fn pull_component_from(
+    reference: &str,
+    config: OciConfig,
+    layers: &dyn LayerSource,
+) -> Result<(Vec<u8>, String, HashMap<String, String>)> {
+    match pull_layer_from(reference, &config, layers).await {
+        Ok(Some(pulled)) => {
+            info!(size = pulled.0.len(), digest = %pulled.1, "Fetched component layer without pulling it");
+            return Ok(pulled);
+        }
+        Ok(None) => debug!("layer source doesn't have the component layer"),
+        Err(e) => warn!(error = ?e, "failed to fetch component layer, pulling it instead"),
+    }
+    pull_component_with_annotations(reference, config).await
+}
+
+/// Fetches the manifest of a component from its registry and its layer from `layers`.
+async fn pull_layer_from(
+    reference: &str,
+    config: &OciConfig,
+    layers: &dyn LayerSource,
+) -> Result<Option<(Vec<u8>, String, HashMap<String, String>)>> {
+    let reference_parsed = Reference::try_from(reference)
+        .with_context(|| format!("invalid OCI reference: {reference}"))?;
+    let auth = CredentialResolver::new(config.credentials.clone())
+        .resolve_credentials(reference_parsed.registry())
+        .await;
+    let client = Client::new(ClientConfig {
+        protocol: if config.insecure {
+            ClientProtocol::Http
+        } else {
+            ClientProtocol::Https
+        },
+        ..Default::default()
+    });
+    let (manifest, digest) = client
+        .pull_image_manifest(&reference_parsed, &auth)
+        .await
+        .with_context(|| format!("failed to pull manifest of {reference}"))?;
+
+    #[allow(deprecated)]
+    let Some(layer) = manifest.layers.iter().find(|layer| {
+        layer.media_type == WASM_LAYER_MEDIA_TYPE || layer.media_type == WASMCLOUD_MEDIA_TYPE
+    }) else {
+        return Ok(None);
+    };
+    let Some(component_data) = layers.fetch_layer(&layer.digest).await else {
+        return Ok(None);
+    };
+    let actual = format!("sha256:{:x}", Sha256::digest(&component_data));
+    if actual != layer.digest {
+        bail!(
+            "fetched layer has digest {actual}, the manifest names {}",
+            layer.digest
+        );
+    }
+    validate_component(&component_data)
+        .await
+        .with_context(|| "fetched layer is not a valid WebAssembly component")?;
+
+    let annotations = manifest
+        .annotations
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    Ok(Some((component_data, digest, annotations)))
 }
 
 /// Push a WebAssembly component to an OCI registry
@@ -685,6 +841,42 @@ mod tests {
         assert_eq!(cached_digest, test_digest);
     }
 
+    #[tokio::test]
+    async fn test_cache_manager_annotations() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_manager = CacheManager::new(temp_dir.path().to_path_buf());
+        let reference = "localhost:5000/test:v1.0.0";
+        cache_manager
+            .write_to_cache(reference, b"test component data", "sha256:abcd1234")
+            .await
+            .unwrap();
+
+        // Artifacts cached without annotations have none
+        assert!(
+            cache_manager
+                .read_cached_annotations(reference)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let annotations = HashMap::from([(
+            "org.opencontainers.image.source".to_string(),
+            "https://github.com/wasmcloud/wasmcloud".to_string(),
+        )]);
+        cache_manager
+            .write_annotations_to_cache(reference, &annotations)
+            .await
+            .unwrap();
+        assert_eq!(
+            cache_manager
+                .read_cached_annotations(reference)
+                .await
+                .unwrap(),
+            annotations
+        );
+    }
+
     #[tokio::test]
     async fn test_validate_component_invalid_data() {
         let invalid_data = b"not wasm data";
@@ -0,0 +1,260 @@
+//! Per-container policies shared by the `wasi:blobstore` backends.
+//!
+//! Policies are read from the config on a workload's `wasi:blobstore` interface. Each key
+//! holds a comma-separated list of `container=value` entries, where the container `*`
+//! applies to every container without an entry of its own:
+//!
+//! - [`MAX_OBJECT_SIZE_KEY`]: the largest object, in bytes, that may be written, e.g.
+//!   `uploads=10485760,*=1048576`
+//! - [`OBJECT_TTL_KEY`]: how long objects live before they are deleted, as seconds or with
+//!   an `s`, `m`, `h` or `d` suffix, e.g. `tmp=1h,cache=30m`
+//! - [`IMMUTABLE_CONTAINERS_KEY`]: containers whose objects are write-once, which can be
+//!   neither overwritten nor deleted, e.g. `audit,invoices`
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, bail, ensure};
+
+use crate::plugin::schema::{ConfigSchema, ConfigValueKind};
+
+/// Config key for the maximum object size of containers.
+pub const MAX_OBJECT_SIZE_KEY: &str = "max_object_size";
+/// Config key for the object TTL of containers.
+pub const OBJECT_TTL_KEY: &str = "object_ttl";
+/// Config key for the containers whose objects are write-once.
+pub const IMMUTABLE_CONTAINERS_KEY: &str = "immutable_containers";
+
+/// The container name that applies to every container without a policy of its own.
+const ANY_CONTAINER: &str = "*";
+
+/// The policy enforced on a single container.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ContainerPolicy {
+    /// The largest object, in bytes, that may be written
+    pub max_object_size: Option<u64>,
+    /// How long objects live after they are written
+    pub ttl: Option<Duration>,
+    /// Whether objects can't be overwritten or deleted once written
+    pub immutable: bool,
+}
+
+impl ContainerPolicy {
+    /// Checks that an object of `size` bytes may be written to `container`.
+    ///
+    /// # Errors
+    /// Returns the error to report to the component if the object is too large.
+    pub fn check_size(&self, container: &str, size: u64) -> Result<(), String> {
+        match self.max_object_size {
+            Some(max) if size > max => Err(format!(
+                "object of {size} bytes exceeds the {max} byte limit of container '{container}'"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that an existing object in `container` may be overwritten or deleted.
+    ///
+    /// # Errors
+    /// Returns the error to report to the component if the container is immutable.
+    pub fn check_mutable(&self, container: &str, object: &str) -> Result<(), String> {
+        if self.immutable {
+            Err(format!(
+                "object '{object}' in immutable container '{container}' can't be changed"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns whether an object written at `written_at`, in seconds since the Unix
+    /// epoch, has outlived the TTL at `now`.
+    pub fn is_expired(&self, written_at: u64, now: u64) -> bool {
+        self.ttl
+            .is_some_and(|ttl| now.saturating_sub(written_at) >= ttl.as_secs())
+    }
+}
+
+/// The policies of every container a workload uses.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContainerPolicies {
+    policies: HashMap<String, ContainerPolicy>,
+}
+
+impl ContainerPolicies {
+    /// Parses the policies from the config on a `wasi:blobstore` interface, see the
+    /// [module docs](self) for the format. Other keys are ignored.
+    ///
+    /// # Errors
+    /// Returns an error naming the first entry that can't be parsed.
+    pub fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut policies: HashMap<String, ContainerPolicy> = HashMap::new();
+
+        if let Some(value) = config.get(MAX_OBJECT_SIZE_KEY) {
+            for (container, size) in entries(MAX_OBJECT_SIZE_KEY, value)? {
+                let size = size.parse().with_context(|| {
+                    format!("invalid {MAX_OBJECT_SIZE_KEY} '{size}' for container '{container}'")
+                })?;
+                policies.entry(container).or_default().max_object_size = Some(size);
+            }
+        }
+        if let Some(value) = config.get(OBJECT_TTL_KEY) {
+            for (container, ttl) in entries(OBJECT_TTL_KEY, value)? {
+                let ttl = parse_ttl(&ttl).with_context(|| {
+                    format!("invalid {OBJECT_TTL_KEY} '{ttl}' for container '{container}'")
+                })?;
+                policies.entry(container).or_default().ttl = Some(ttl);
+            }
+        }
+        if let Some(value) = config.get(IMMUTABLE_CONTAINERS_KEY) {
+            for container in value.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+                policies.entry(container.to_string()).or_default().immutable = true;
+            }
+        }
+
+        Ok(Self { policies })
+    }
+
+    /// Returns the policy of `container`, with settings it doesn't set itself taken
+    /// from the `*` policy.
+    pub fn for_container(&self, container: &str) -> ContainerPolicy {
+        let any = self
+            .policies
+            .get(ANY_CONTAINER)
+            .copied()
+            .unwrap_or_default();
+        match self.policies.get(container) {
+            Some(policy) => ContainerPolicy {
+                max_object_size: policy.max_object_size.or(any.max_object_size),
+                ttl: policy.ttl.or(any.ttl),
+                immutable: policy.immutable || any.immutable,
+            },
+            None => any,
+        }
+    }
+
+    /// Adds the policy keys to a plugin's config schema.
+    pub fn with_schema_fields(schema: ConfigSchema) -> ConfigSchema {
+        schema
+            .with_field(
+                MAX_OBJECT_SIZE_KEY,
+                ConfigValueKind::List,
+                "largest object in bytes per container, as container=bytes",
+            )
+            .with_field(
+                OBJECT_TTL_KEY,
+                ConfigValueKind::List,
+                "lifetime of objects per container, as container=duration",
+            )
+            .with_field(
+                IMMUTABLE_CONTAINERS_KEY,
+                ConfigValueKind::List,
+                "containers whose objects are write-once",
+            )
+    }
+}
+
+/// Splits a list of `container=value` entries.
+fn entries(key: &str, value: &str) -> anyhow::Result<Vec<(String, String)>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((container, value)) if !container.trim().is_empty() => {
+                Ok((container.trim().to_string(), value.trim().to_string()))
+            }
+            _ => bail!("invalid {key} entry '{entry}', expected container=value"),
+        })
+        .collect()
+}
+
+/// Parses a TTL given in seconds or with an `s`, `m`, `h` or `d` suffix.
+fn parse_ttl(ttl: &str) -> anyhow::Result<Duration> {
+    let (amount, unit) = match ttl.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => (&ttl[..i], unit),
+        _ => (ttl, 's'),
+    };
+    let amount: u64 = amount.parse().context("expected a number of seconds")?;
+    let seconds = match unit {
+        's' => amount,
+        'm' => amount.saturating_mul(60),
+        'h' => amount.saturating_mul(60 * 60),
+        'd' => amount.saturating_mul(24 * 60 * 60),
+        other => bail!("unknown unit '{other}', expected s, m, h or d"),
+    };
+    ensure!(seconds > 0, "TTL must be positive");
+    Ok(Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_container_policies() -> anyhow::Result<()> {
+        let policies = ContainerPolicies::from_config(&config(&[
+            (MAX_OBJECT_SIZE_KEY, "uploads=100, *=10"),
+            (OBJECT_TTL_KEY, "tmp=1h,cache=90"),
+            (IMMUTABLE_CONTAINERS_KEY, "audit"),
+            ("buckets", "unrelated"),
+        ]))?;
+
+        let uploads = policies.for_container("uploads");
+        assert_eq!(uploads.max_object_size, Some(100));
+        assert!(uploads.check_size("uploads", 100).is_ok());
+        assert!(uploads.check_size("uploads", 101).is_err());
+
+        let tmp = policies.for_container("tmp");
+        assert_eq!(tmp.max_object_size, Some(10));
+        assert_eq!(tmp.ttl, Some(Duration::from_secs(3600)));
+        assert!(!tmp.is_expired(1_000, 1_000 + 3599));
+        assert!(tmp.is_expired(1_000, 1_000 + 3600));
+        assert_eq!(
+            policies.for_container("cache").ttl,
+            Some(Duration::from_secs(90))
+        );
+
+        let audit = policies.for_container("audit");
+        assert!(audit.check_mutable("audit", "log-1").is_err());
+        assert!(
+            policies
+                .for_container("other")
+                .check_mutable("other", "x")
+                .is_ok()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_policies() -> anyhow::Result<()> {
+        let policy = ContainerPolicies::from_config(&HashMap::new())?.for_container("any");
+        assert_eq!(policy, ContainerPolicy::default());
+        assert!(policy.check_size("any", u64::MAX).is_ok());
+        assert!(!policy.is_expired(0, u64::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_policies() {
+        for (key, value) in [
+            (MAX_OBJECT_SIZE_KEY, "uploads"),
+            (MAX_OBJECT_SIZE_KEY, "uploads=big"),
+            (MAX_OBJECT_SIZE_KEY, "=10"),
+            (OBJECT_TTL_KEY, "tmp=1w"),
+            (OBJECT_TTL_KEY, "tmp=0"),
+        ] {
+            assert!(
+                ContainerPolicies::from_config(&config(&[(key, value)])).is_err(),
+                "'{key}={value}' should be rejected"
+            );
+        }
+    }
+}
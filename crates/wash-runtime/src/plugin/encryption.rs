@@ -0,0 +1,432 @@
+//! Client-side encryption of data written by storage plugins.
+//!
+//! A storage plugin configured with an [`Encryptor`] seals data with AES-256-GCM before it's
+//! written to the backend and opens it again when it's read, so the backend only ever holds
+//! ciphertext. Keys come from a [`KeyProvider`]: [`LocalKeyring`] holds keys managed by the
+//! host, and a KMS integration implements the trait to fetch or unwrap keys from the KMS.
+//!
+//! Sealed data is laid out as `magic | key id | nonce | ciphertext | tag`. The header and the
+//! container and name the data is stored under are authenticated along with the ciphertext,
+//! so data can't be moved to another name without being detected. The key ID lets keys be
+//! rotated: new data is sealed with the provider's current key, while data sealed with an
+//! older key still opens as long as the provider knows it.
+//!
+//! Data that isn't sealed is rejected, as anyone who can write to the backend could plant it.
+//! While a backend that already holds plaintext data is migrated, it can be returned as is
+//! instead, see [`Encryptor::with_plaintext_migration`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context as _, anyhow, ensure};
+use aws_lc_rs::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
+/// Prefix that marks sealed data.
+const MAGIC: &[u8; 4] = b"WEv1";
+/// Length of the authentication tag appended to the ciphertext.
+const TAG_LEN: usize = 16;
+/// Length of the header before the ciphertext.
+const HEADER_LEN: usize = MAGIC.len() + size_of::<u32>() + NONCE_LEN;
+
+/// Number of bytes sealing adds to the plaintext.
+pub const SEALED_OVERHEAD: usize = HEADER_LEN + TAG_LEN;
+
+/// A 256-bit AES key. The key bytes are zeroed when it's dropped and never printed.
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Creates a key from its raw bytes.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parses a base64-encoded key.
+    ///
+    /// # Errors
+    /// Returns an error if the value isn't base64 or doesn't decode to 32 bytes.
+    pub fn from_base64(value: &str) -> anyhow::Result<Self> {
+        let bytes = BASE64
+            .decode(value.trim())
+            .context("encryption key is not valid base64")?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|b: Vec<u8>| anyhow!("encryption key is {} bytes, expected 32", b.len()))?;
+        Ok(Self(bytes))
+    }
+
+    /// Reads a base64-encoded key from a file.
+    ///
+    /// # Errors
+    /// Returns an error if the file can't be read or doesn't hold a valid key.
+    pub fn from_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let value = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read encryption key {}", path.display()))?;
+        Self::from_base64(&value)
+            .with_context(|| format!("invalid encryption key in {}", path.display()))
+    }
+
+    /// Generates a random key.
+    ///
+    /// # Errors
+    /// Returns an error if the system random number generator fails.
+    pub fn generate() -> anyhow::Result<Self> {
+        let mut bytes = [0u8; 32];
+        aws_lc_rs::rand::fill(&mut bytes).map_err(|_| anyhow!("failed to generate key"))?;
+        Ok(Self(bytes))
+    }
+
+    fn aead(&self) -> anyhow::Result<LessSafeKey> {
+        let key = UnboundKey::new(&AES_256_GCM, &self.0)
+            .map_err(|_| anyhow!("invalid AES-256-GCM key"))?;
+        Ok(LessSafeKey::new(key))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+impl Drop for EncryptionKey {
+    fn drop(&mut self) {
+        self.0.fill(0);
+        // Keep the zeroed bytes observable so the fill isn't optimized away
+        std::hint::black_box(&self.0);
+    }
+}
+
+/// Source of the keys used to seal and open data.
+#[async_trait::async_trait]
+pub trait KeyProvider: Send + Sync {
+    /// ID of the key new data is sealed with.
+    fn current_key_id(&self) -> u32;
+
+    /// Returns the key with the given ID.
+    ///
+    /// # Errors
+    /// Returns an error if the key is unknown or can't be fetched.
+    async fn key(&self, id: u32) -> anyhow::Result<Arc<EncryptionKey>>;
+}
+
+/// Keys managed by the host: the current key and any retired keys that older data may
+/// still be sealed with.
+#[derive(Debug)]
+pub struct LocalKeyring {
+    current: u32,
+    keys: HashMap<u32, Arc<EncryptionKey>>,
+}
+
+impl LocalKeyring {
+    /// Creates a keyring that seals data with `key`.
+    pub fn new(id: u32, key: EncryptionKey) -> Self {
+        Self {
+            current: id,
+            keys: HashMap::from([(id, Arc::new(key))]),
+        }
+    }
+
+    /// Adds a retired key, used only to open data sealed before the key was rotated.
+    ///
+    /// # Errors
+    /// Returns an error if a key with the ID already exists.
+    pub fn with_retired_key(mut self, id: u32, key: EncryptionKey) -> anyhow::Result<Self> {
+        ensure!(
+            !self.keys.contains_key(&id),
+            "duplicate encryption key ID {id}"
+        );
+        self.keys.insert(id, Arc::new(key));
+        Ok(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl KeyProvider for LocalKeyring {
+    fn current_key_id(&self) -> u32 {
+        self.current
+    }
+
+    async fn key(&self, id: u32) -> anyhow::Result<Arc<EncryptionKey>> {
+        self.keys
+            .get(&id)
+            .cloned()
+            .with_context(|| format!("unknown encryption key ID {id}"))
+    }
+}
+
+/// Seals and opens data with the keys of a [`KeyProvider`].
+#[derive(Clone)]
+pub struct Encryptor {
+    provider: Arc<dyn KeyProvider>,
+    /// Whether data that isn't sealed is opened as is
+    plaintext_migration: bool,
+}
+
+impl fmt::Debug for Encryptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Encryptor")
+            .field("current_key_id", &self.provider.current_key_id())
+            .field("plaintext_migration", &self.plaintext_migration)
+            .finish()
+    }
+}
+
+impl Encryptor {
+    /// Creates an encryptor using the keys of `provider`.
+    pub fn new(provider: impl KeyProvider + 'static) -> Self {
+        Self {
+            provider: Arc::new(provider),
+            plaintext_migration: false,
+        }
+    }
+
+    /// Opens data that isn't sealed as is, so encryption can be enabled on a backend that
+    /// already holds plaintext data, which is sealed as it's rewritten.
+    ///
+    /// Only enable this while migrating: anyone who can write to the backend can then plant
+    /// plaintext that's read as authentic. Plaintext that happens to start like sealed data
+    /// can't be read, and has to be rewritten before encryption is enabled.
+    pub fn with_plaintext_migration(mut self) -> Self {
+        self.plaintext_migration = true;
+        self
+    }
+
+    /// Returns whether `data` was sealed by an encryptor.
+    pub fn is_sealed(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Returns the length of the plaintext of stored `data`.
+    pub fn plaintext_len(data: &[u8]) -> usize {
+        if Self::is_sealed(data) {
+            data.len().saturating_sub(SEALED_OVERHEAD)
+        } else {
+            data.len()
+        }
+    }
+
+    /// Seals `plaintext` with the current key, to be stored as `name` in `container`.
+    ///
+    /// # Errors
+    /// Returns an error if the key can't be fetched or the data can't be encrypted.
+    pub async fn seal(
+        &self,
+        container: &str,
+        name: &str,
+        plaintext: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let id = self.provider.current_key_id();
+        let key = self.provider.key(id).await?;
+        let mut nonce = [0u8; NONCE_LEN];
+        aws_lc_rs::rand::fill(&mut nonce).map_err(|_| anyhow!("failed to generate nonce"))?;
+
+        let mut sealed = Vec::with_capacity(plaintext.len() + SEALED_OVERHEAD);
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&id.to_be_bytes());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(plaintext);
+
+        let (header, body) = sealed.split_at_mut(HEADER_LEN);
+        let tag = key
+            .aead()?
+            .seal_in_place_separate_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad(header, container, name)),
+                body,
+            )
+            .map_err(|_| anyhow!("failed to encrypt data"))?;
+        sealed.extend_from_slice(tag.as_ref());
+        Ok(sealed)
+    }
+
+    /// Opens data [sealed](Encryptor::seal) to be stored as `name` in `container`.
+    ///
+    /// # Errors
+    /// Returns an error if the data isn't sealed, unless plaintext is
+    /// [migrated](Encryptor::with_plaintext_migration), if the key is unknown, or if the
+    /// data was changed, sealed with a different key or for another name.
+    pub async fn open(&self, container: &str, name: &str, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if !Self::is_sealed(data) {
+            ensure!(self.plaintext_migration, "data is not encrypted");
+            return Ok(data.to_vec());
+        }
+        ensure!(data.len() >= SEALED_OVERHEAD, "sealed data is truncated");
+
+        let (header, body) = data.split_at(HEADER_LEN);
+        let id = u32::from_be_bytes(header[MAGIC.len()..MAGIC.len() + 4].try_into()?);
+        let nonce: [u8; NONCE_LEN] = header[MAGIC.len() + 4..].try_into()?;
+        let key = self.provider.key(id).await?;
+
+        let mut plaintext = body.to_vec();
+        let len = key
+            .aead()?
+            .open_in_place(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad(header, container, name)),
+                &mut plaintext,
+            )
+            .map_err(|_| {
+                anyhow!(
+                    "failed to decrypt data, it was modified, sealed with another key or stored under another name"
+                )
+            })?
+            .len();
+        plaintext.truncate(len);
+        Ok(plaintext)
+    }
+
+    /// Seals data stored as `from` again for storing it as `to`, as a copy of an object
+    /// under another name.
+    ///
+    /// # Errors
+    /// Returns an error if the data can't be [opened](Encryptor::open) or
+    /// [sealed](Encryptor::seal).
+    pub async fn reseal(
+        &self,
+        from: (&str, &str),
+        to: (&str, &str),
+        data: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        let plaintext = self.open(from.0, from.1, data).await?;
+        self.seal(to.0, to.1, &plaintext).await
+    }
+}
+
+/// The additional authenticated data of data sealed to be stored as `name` in `container`:
+/// the header, and the container and name, each prefixed with its length.
+fn aad(header: &[u8], container: &str, name: &str) -> Vec<u8> {
+    let mut aad = header.to_vec();
+    for part in [container, name] {
+        aad.extend_from_slice(&(part.len() as u64).to_be_bytes());
+        aad.extend_from_slice(part.as_bytes());
+    }
+    aad
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_seal_and_open() -> anyhow::Result<()> {
+        let encryptor = Encryptor::new(LocalKeyring::new(1, EncryptionKey::generate()?));
+
+        let sealed = encryptor
+            .seal("docs", "statement", b"account statement")
+            .await?;
+        assert!(Encryptor::is_sealed(&sealed));
+        assert_eq!(sealed.len(), b"account statement".len() + SEALED_OVERHEAD);
+        assert_eq!(
+            Encryptor::plaintext_len(&sealed),
+            b"account statement".len()
+        );
+        assert!(!sealed.windows(7).any(|w| w == b"account"));
+        assert_eq!(
+            encryptor.open("docs", "statement", &sealed).await?,
+            b"account statement"
+        );
+
+        // Each seal uses a fresh nonce
+        assert_ne!(
+            encryptor
+                .seal("docs", "statement", b"account statement")
+                .await?,
+            sealed
+        );
+
+        // Sealed data can't be moved to another name, but can be sealed again for it
+        assert!(encryptor.open("docs", "other", &sealed).await.is_err());
+        assert!(encryptor.open("other", "statement", &sealed).await.is_err());
+        let copy = encryptor
+            .reseal(("docs", "statement"), ("other", "statement"), &sealed)
+            .await?;
+        assert_eq!(
+            encryptor.open("other", "statement", &copy).await?,
+            b"account statement"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_plaintext_migration() -> anyhow::Result<()> {
+        let encryptor = Encryptor::new(LocalKeyring::new(1, EncryptionKey::generate()?));
+        assert!(encryptor.open("docs", "legacy", b"legacy").await.is_err());
+
+        // Plaintext written before encryption was enabled is returned as is while migrating
+        let migrating = encryptor.with_plaintext_migration();
+        assert_eq!(
+            migrating.open("docs", "legacy", b"legacy").await?,
+            b"legacy"
+        );
+        let sealed = migrating.seal("docs", "legacy", b"legacy").await?;
+        assert_eq!(migrating.open("docs", "legacy", &sealed).await?, b"legacy");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_open_rejects_modified_data() -> anyhow::Result<()> {
+        let encryptor = Encryptor::new(LocalKeyring::new(1, EncryptionKey::generate()?));
+        let sealed = encryptor.seal("c", "o", b"payload").await?;
+
+        let mut modified = sealed.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(encryptor.open("c", "o", &modified).await.is_err());
+
+        let mut modified = sealed.clone();
+        modified[HEADER_LEN] ^= 1;
+        assert!(encryptor.open("c", "o", &modified).await.is_err());
+
+        assert!(
+            encryptor
+                .open("c", "o", &sealed[..SEALED_OVERHEAD - 1])
+                .await
+                .is_err()
+        );
+
+        let other = Encryptor::new(LocalKeyring::new(1, EncryptionKey::generate()?));
+        assert!(other.open("c", "o", &sealed).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_key_rotation() -> anyhow::Result<()> {
+        let old_key = [7u8; 32];
+        let old = Encryptor::new(LocalKeyring::new(1, EncryptionKey::from_bytes(old_key)));
+        let sealed_with_old = old.seal("c", "o", b"before rotation").await?;
+
+        let rotated = Encryptor::new(
+            LocalKeyring::new(2, EncryptionKey::generate()?)
+                .with_retired_key(1, EncryptionKey::from_bytes(old_key))?,
+        );
+        assert_eq!(
+            rotated.open("c", "o", &sealed_with_old).await?,
+            b"before rotation"
+        );
+
+        let sealed_with_new = rotated.seal("c", "o", b"after rotation").await?;
+        let err = old
+            .open("c", "o", &sealed_with_new)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("unknown encryption key ID 2"), "{err}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_from_base64() {
+        let encoded = BASE64.encode([1u8; 32]);
+        assert!(EncryptionKey::from_base64(&format!("{encoded}\n")).is_ok());
+        assert!(EncryptionKey::from_base64(&BASE64.encode([1u8; 16])).is_err());
+        assert!(EncryptionKey::from_base64("not base64!").is_err());
+        assert_eq!(
+            format!("{:?}", EncryptionKey::from_bytes([1u8; 32])),
+            "EncryptionKey(..)"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Per-bucket read consistency for the `wasi:keyvalue` backends that replicate buckets.
+//!
+//! Settings are read from the config on a workload's `wasi:keyvalue` interface. Each key
+//! holds a comma-separated list of `bucket=value` entries, where the bucket `*` applies to
+//! every bucket without an entry of its own:
+//!
+//! - [`CONSISTENCY_KEY`]: how up to date reads are, see [`Consistency`], e.g.
+//!   `sessions=read-your-writes,*=eventual`
+//! - [`READ_REPLICAS_KEY`]: a replica of the bucket reads are served from, such as a
+//!   mirror in the host's own cluster, while writes still go to the bucket, e.g.
+//!   `catalog=catalog-eu`. Replicas lag behind the bucket, so they can't be combined
+//!   with `read-your-writes`.
+//!
+//! Read-heavy workloads can spread their reads over replicas without code changes.
+//! Backends keeping a single copy of each bucket, like the in-memory one, ignore these
+//! settings, as their reads always see every write.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, ensure};
+
+use crate::plugin::schema::{ConfigSchema, ConfigValueKind};
+
+/// Config key for the read consistency of buckets.
+pub const CONSISTENCY_KEY: &str = "consistency";
+/// Config key for the replicas buckets are read from.
+pub const READ_REPLICAS_KEY: &str = "read_replicas";
+
+/// The bucket name that applies to every bucket without settings of its own.
+const ANY_BUCKET: &str = "*";
+
+/// How up to date the values a bucket's reads return are.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Consistency {
+    /// Reads may be served by any copy of the bucket, and may miss recent writes
+    #[default]
+    Eventual,
+    /// Reads are served by the copy writes go to, so they see every acknowledged write,
+    /// including the workload's own
+    ReadYourWrites,
+}
+
+impl std::str::FromStr for Consistency {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "eventual" => Ok(Self::Eventual),
+            "read-your-writes" => Ok(Self::ReadYourWrites),
+            other => bail!("unknown consistency '{other}', expected eventual or read-your-writes"),
+        }
+    }
+}
+
+/// How a single bucket is read.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BucketConsistency {
+    pub consistency: Consistency,
+    /// The replica reads are served from, if not the bucket itself
+    pub read_replica: Option<String>,
+}
+
+/// The read settings of every bucket a workload uses.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BucketConsistencies {
+    consistency: HashMap<String, Consistency>,
+    read_replicas: HashMap<String, String>,
+}
+
+impl BucketConsistencies {
+    /// Parses the settings from the config on a `wasi:keyvalue` interface, see the
+    /// [module docs](self) for the format. Other keys are ignored.
+    ///
+    /// # Errors
+    /// Returns an error naming the first entry that can't be parsed, or a bucket read from
+    /// a replica with `read-your-writes` consistency.
+    pub fn from_config(config: &HashMap<String, String>) -> anyhow::Result<Self> {
+        let mut consistencies = Self::default();
+        if let Some(value) = config.get(CONSISTENCY_KEY) {
+            for (bucket, consistency) in entries(CONSISTENCY_KEY, value)? {
+                let consistency = consistency.parse()?;
+                consistencies.consistency.insert(bucket, consistency);
+            }
+        }
+        if let Some(value) = config.get(READ_REPLICAS_KEY) {
+            for (bucket, replica) in entries(READ_REPLICAS_KEY, value)? {
+                consistencies.read_replicas.insert(bucket, replica);
+            }
+        }
+        for bucket in consistencies.read_replicas.keys() {
+            ensure!(
+                consistencies.for_bucket(bucket).consistency != Consistency::ReadYourWrites,
+                "bucket '{bucket}' can't be read from a replica with read-your-writes consistency"
+            );
+        }
+        Ok(consistencies)
+    }
+
+    /// Returns the settings of `bucket`, with those it doesn't set itself taken from the
+    /// `*` entries. Replicas are only taken from `*` for buckets with eventual consistency.
+    pub fn for_bucket(&self, bucket: &str) -> BucketConsistency {
+        let consistency = self
+            .consistency
+            .get(bucket)
+            .or_else(|| self.consistency.get(ANY_BUCKET))
+            .copied()
+            .unwrap_or_default();
+        let read_replica = match self.read_replicas.get(bucket) {
+            Some(replica) => Some(replica.clone()),
+            None if consistency == Consistency::Eventual => {
+                self.read_replicas.get(ANY_BUCKET).cloned()
+            }
+            None => None,
+        };
+        BucketConsistency {
+            consistency,
+            read_replica,
+        }
+    }
+
+    /// Adds the consistency keys to a plugin's config schema.
+    pub fn with_schema_fields(schema: ConfigSchema) -> ConfigSchema {
+        schema
+            .with_field(
+                CONSISTENCY_KEY,
+                ConfigValueKind::List,
+                "read consistency per bucket, as bucket=eventual or bucket=read-your-writes",
+            )
+            .with_field(
+                READ_REPLICAS_KEY,
+                ConfigValueKind::List,
+                "replica to read from per bucket, as bucket=replica",
+            )
+    }
+}
+
+/// Splits a list of `bucket=value` entries.
+fn entries(key: &str, value: &str) -> anyhow::Result<Vec<(String, String)>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((bucket, value)) if !bucket.trim().is_empty() && !value.trim().is_empty() => {
+                Ok((bucket.trim().to_string(), value.trim().to_string()))
+            }
+            _ => bail!("invalid {key} entry '{entry}', expected bucket=value"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_bucket_consistencies() -> anyhow::Result<()> {
+        let consistencies = BucketConsistencies::from_config(&config(&[
+            (CONSISTENCY_KEY, "sessions=read-your-writes, *=eventual"),
+            (READ_REPLICAS_KEY, "catalog=catalog-eu"),
+        ]))?;
+        assert_eq!(
+            consistencies.for_bucket("sessions"),
+            BucketConsistency {
+                consistency: Consistency::ReadYourWrites,
+                read_replica: None,
+            }
+        );
+        assert_eq!(
+            consistencies.for_bucket("catalog").read_replica.as_deref(),
+            Some("catalog-eu")
+        );
+        assert_eq!(
+            BucketConsistencies::from_config(&HashMap::new())?.for_bucket("any"),
+            BucketConsistency::default()
+        );
+
+        // Buckets with read-your-writes consistency don't take the default replica
+        let consistencies = BucketConsistencies::from_config(&config(&[
+            (CONSISTENCY_KEY, "sessions=read-your-writes"),
+            (READ_REPLICAS_KEY, "*=local"),
+        ]))?;
+        assert_eq!(consistencies.for_bucket("sessions").read_replica, None);
+        assert_eq!(
+            consistencies.for_bucket("other").read_replica.as_deref(),
+            Some("local")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_consistencies() {
+        for entries in [
+            &[(CONSISTENCY_KEY, "sessions=strong")][..],
+            &[(CONSISTENCY_KEY, "sessions")],
+            &[(READ_REPLICAS_KEY, "catalog=")],
+            &[
+                (CONSISTENCY_KEY, "*=read-your-writes"),
+                (READ_REPLICAS_KEY, "catalog=catalog-eu"),
+            ],
+        ] {
+            assert!(
+                BucketConsistencies::from_config(&config(entries)).is_err(),
+                "{entries:?} should be rejected"
+            );
+        }
+    }
+}
@@ -23,15 +23,32 @@
 
 use crate::{
     engine::workload::{ResolvedWorkload, UnresolvedWorkload, WorkloadComponent},
-    wit::WitWorld,
+    plugin::schema::ConfigSchema,
+    types::PluginHealth,
+    wit::{WitInterface, WitWorld},
 };
 
+#[cfg(any(
+    feature = "wasi-blobstore",
+    feature = "wasi-blobstore-gcs",
+    feature = "washlet"
+))]
+pub mod blobstore_policy;
+pub mod encryption;
+#[cfg(feature = "washlet")]
+pub mod keyvalue_consistency;
+pub mod profiles;
+pub mod schema;
+
 #[cfg(feature = "wasi-config")]
 pub mod wasi_config;
 
 #[cfg(feature = "wasi-blobstore")]
 pub mod wasi_blobstore;
 
+#[cfg(feature = "wasi-blobstore-gcs")]
+pub mod wasi_blobstore_gcs;
+
 #[cfg(feature = "wasi-keyvalue")]
 pub mod wasi_keyvalue;
 
@@ -41,6 +58,27 @@ pub mod wasi_logging;
 #[cfg(feature = "wasi-webgpu")]
 pub mod wasi_webgpu;
 
+/// A dependency that a [`HostPlugin`] declares on another plugin registered with the same host.
+///
+/// Dependencies are resolved by [`crate::host::HostBuilder::build`], which orders plugins so
+/// that a plugin is always started after the plugins it depends on (and stopped before them).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PluginDependency {
+    /// Depends on the plugin with the given ID
+    Plugin(&'static str),
+    /// Depends on whichever plugin provides the given interface to components
+    Interface(WitInterface),
+}
+
+impl std::fmt::Display for PluginDependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PluginDependency::Plugin(id) => write!(f, "plugin '{id}'"),
+            PluginDependency::Interface(interface) => write!(f, "interface '{interface}'"),
+        }
+    }
+}
+
 /// The [`HostPlugin`] trait provides an interface for implementing built-in plugins for the host.
 /// A plugin is primarily responsible for implementing a specific [`WitWorld`] as a collection of
 /// imports and exports that will be directly linked to the workload's [`wasmtime::component::Linker`].
@@ -71,6 +109,18 @@ pub trait HostPlugin: std::any::Any + Send + Sync + 'static {
     /// A `WitWorld` containing the plugin's imports and exports.
     fn world(&self) -> WitWorld;
 
+    /// Returns the plugins that this plugin depends on.
+    ///
+    /// For example, a plugin that wraps `wasi:keyvalue` with metrics can depend on
+    /// `PluginDependency::Interface("wasi:keyvalue/store".into())` to ensure the keyvalue
+    /// plugin is started first. The default implementation declares no dependencies.
+    ///
+    /// # Returns
+    /// A list of [`PluginDependency`] entries that must be registered with the host.
+    fn dependencies(&self) -> Vec<PluginDependency> {
+        Vec::new()
+    }
+
     /// Called when the plugin is started during host initialization.
     ///
     /// This method allows plugins to perform any necessary setup before
@@ -86,6 +136,33 @@ pub trait HostPlugin: std::any::Any + Send + Sync + 'static {
         Ok(())
     }
 
+    /// Returns the schema for the configuration this plugin reads from
+    /// [`WitInterface::config`] on the interfaces it provides.
+    ///
+    /// When a schema is returned, the configuration of every interface bound to this
+    /// plugin is validated against it before [`HostPlugin::on_workload_bind`] is called.
+    /// The default implementation returns `None`, which skips validation.
+    ///
+    /// # Returns
+    /// The plugin's [`ConfigSchema`], if it declares one.
+    fn config_schema(&self) -> Option<ConfigSchema> {
+        None
+    }
+
+    /// Reports the current health of the plugin.
+    ///
+    /// The host polls this when producing a heartbeat or workload status. Workloads
+    /// bound to a plugin that is not [`PluginHealth::Healthy`] are reported as
+    /// [`crate::types::WorkloadState::Degraded`]. Implementations should return quickly,
+    /// e.g. by reporting the result of the last backend operation rather than probing it.
+    /// The default implementation always reports healthy.
+    ///
+    /// # Returns
+    /// The plugin's [`PluginHealth`].
+    async fn health(&self) -> PluginHealth {
+        PluginHealth::Healthy
+    }
+
     /// Called when a workload is binding to this plugin.
     ///
     /// This method is invoked when a workload is in the process of being bound to the plugin,
@@ -132,6 +209,25 @@ pub trait HostPlugin: std::any::Any + Send + Sync + 'static {
         Ok(())
     }
 
+    /// Called when the [`LocalResources`](crate::types::LocalResources) of a component
+    /// bound to this plugin change on a running workload, see
+    /// [`ResolvedWorkload::update_resources`].
+    ///
+    /// Plugins that copy a component's config or environment when it's bound should
+    /// update their copy here. The default implementation does nothing.
+    ///
+    /// # Arguments
+    /// * `component` - The component, with its new resources
+    ///
+    /// # Returns
+    /// Ok if the plugin applied the new resources.
+    ///
+    /// # Errors
+    /// Returns an error if the plugin can't apply the new resources.
+    async fn on_component_update(&self, _component: &WorkloadComponent) -> anyhow::Result<()> {
+        Ok(())
+    }
+
     /// Called when a workload has been fully resolved and is ready for use.
     ///
     /// This optional callback allows plugins to perform actions after a workload
@@ -0,0 +1,117 @@
+//! Named backend profiles for storage plugins.
+//!
+//! A storage plugin is registered with a default backend, and optionally with more
+//! backends under a profile name such as `gcs-prod` or `nats-edge`. A workload picks one by
+//! setting [`PROFILE_CONFIG_KEY`] in the config of the plugin's interface, and uses the
+//! default backend otherwise.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context as _, ensure};
+
+use crate::plugin::schema::{ConfigSchema, ConfigValueKind};
+
+/// Config key a workload sets to select a backend profile.
+pub const PROFILE_CONFIG_KEY: &str = "profile";
+
+/// The backends a plugin can store data in: a default and any number of named profiles.
+#[derive(Debug)]
+pub struct BackendProfiles<B> {
+    default: Arc<B>,
+    profiles: HashMap<String, Arc<B>>,
+}
+
+impl<B> Clone for BackendProfiles<B> {
+    fn clone(&self) -> Self {
+        Self {
+            default: self.default.clone(),
+            profiles: self.profiles.clone(),
+        }
+    }
+}
+
+impl<B> BackendProfiles<B> {
+    /// Creates the profiles with the backend workloads use when they don't select one.
+    pub fn new(default: B) -> Self {
+        Self {
+            default: Arc::new(default),
+            profiles: HashMap::new(),
+        }
+    }
+
+    /// Adds a named profile.
+    ///
+    /// # Errors
+    /// Returns an error if the name is empty or a profile with the name already exists.
+    pub fn add(&mut self, name: impl Into<String>, backend: B) -> anyhow::Result<()> {
+        let name = name.into();
+        ensure!(!name.is_empty(), "backend profile name must not be empty");
+        ensure!(
+            !self.profiles.contains_key(&name),
+            "duplicate backend profile '{name}'"
+        );
+        self.profiles.insert(name, Arc::new(backend));
+        Ok(())
+    }
+
+    /// The default backend.
+    pub fn default_backend(&self) -> &Arc<B> {
+        &self.default
+    }
+
+    /// The names of the named profiles, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<_> = self.profiles.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
+    }
+
+    /// Returns the backend selected by an interface config.
+    ///
+    /// # Errors
+    /// Returns an error listing the known profiles if the selected profile doesn't exist.
+    pub fn select(&self, config: &HashMap<String, String>) -> anyhow::Result<Arc<B>> {
+        let Some(name) = config.get(PROFILE_CONFIG_KEY) else {
+            return Ok(self.default.clone());
+        };
+        self.profiles.get(name).cloned().with_context(|| {
+            format!(
+                "unknown backend profile '{name}', expected one of [{}]",
+                self.names().join(", ")
+            )
+        })
+    }
+
+    /// Adds the profile key to a plugin's config schema.
+    pub fn with_schema_field(schema: ConfigSchema) -> ConfigSchema {
+        schema.with_field(
+            PROFILE_CONFIG_KEY,
+            ConfigValueKind::String,
+            "named backend profile to store data in, defaults to the host's default backend",
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_profile() -> anyhow::Result<()> {
+        let mut profiles = BackendProfiles::new("default");
+        profiles.add("prod", "prod")?;
+        profiles.add("dev", "dev")?;
+        assert!(profiles.add("dev", "other").is_err());
+        assert!(profiles.add("", "other").is_err());
+
+        assert_eq!(*profiles.select(&HashMap::new())?, "default");
+        let config = HashMap::from([(PROFILE_CONFIG_KEY.to_string(), "prod".to_string())]);
+        assert_eq!(*profiles.select(&config)?, "prod");
+
+        let config = HashMap::from([(PROFILE_CONFIG_KEY.to_string(), "staging".to_string())]);
+        let err = profiles.select(&config).unwrap_err().to_string();
+        assert!(err.contains("[dev, prod]"), "{err}");
+        Ok(())
+    }
+}
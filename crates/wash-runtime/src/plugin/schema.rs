@@ -0,0 +1,267 @@
+//! Configuration schemas for [`super::HostPlugin`]s.
+//!
+//! Plugins read their settings from [`crate::wit::WitInterface::config`], which is an
+//! untyped string map supplied by the workload. A plugin that returns a [`ConfigSchema`]
+//! from [`super::HostPlugin::config_schema`] has that map validated before it is bound
+//! to a workload, so a misspelled key or malformed value fails the workload start with
+//! a precise error instead of silently falling back to a default.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::bail;
+
+/// The expected type of a configuration value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigValueKind {
+    /// Any string value
+    String,
+    /// `true` or `false`
+    Bool,
+    /// A signed 64-bit integer
+    Integer,
+    /// A comma-separated list of strings
+    List,
+}
+
+impl ConfigValueKind {
+    fn accepts(&self, value: &str) -> bool {
+        match self {
+            ConfigValueKind::String | ConfigValueKind::List => true,
+            ConfigValueKind::Bool => value == "true" || value == "false",
+            ConfigValueKind::Integer => value.parse::<i64>().is_ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for ConfigValueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigValueKind::String => write!(f, "a string"),
+            ConfigValueKind::Bool => write!(f, "a boolean"),
+            ConfigValueKind::Integer => write!(f, "an integer"),
+            ConfigValueKind::List => write!(f, "a comma-separated list"),
+        }
+    }
+}
+
+/// A single configuration key accepted by a plugin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConfigField {
+    pub key: &'static str,
+    pub kind: ConfigValueKind,
+    pub required: bool,
+    pub description: &'static str,
+}
+
+/// The set of configuration keys a plugin accepts on the interfaces it provides.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConfigSchema {
+    fields: Vec<ConfigField>,
+    allow_unknown_keys: bool,
+}
+
+impl ConfigSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an optional configuration key to the schema.
+    ///
+    /// # Arguments
+    /// * `key` - The configuration key
+    /// * `kind` - The expected type of the value
+    /// * `description` - A short description surfaced in error messages and tooling
+    ///
+    /// # Returns
+    /// The schema for method chaining.
+    pub fn with_field(
+        mut self,
+        key: &'static str,
+        kind: ConfigValueKind,
+        description: &'static str,
+    ) -> Self {
+        self.fields.push(ConfigField {
+            key,
+            kind,
+            required: false,
+            description,
+        });
+        self
+    }
+
+    /// Adds a configuration key that must be present on every bound interface.
+    ///
+    /// # Arguments
+    /// * `key` - The configuration key
+    /// * `kind` - The expected type of the value
+    /// * `description` - A short description surfaced in error messages and tooling
+    ///
+    /// # Returns
+    /// The schema for method chaining.
+    pub fn with_required_field(
+        mut self,
+        key: &'static str,
+        kind: ConfigValueKind,
+        description: &'static str,
+    ) -> Self {
+        self.fields.push(ConfigField {
+            key,
+            kind,
+            required: true,
+            description,
+        });
+        self
+    }
+
+    /// Accepts keys that aren't declared in the schema, while still checking the
+    /// ones that are. Useful for plugins that pass through arbitrary settings.
+    pub fn allow_unknown_keys(mut self) -> Self {
+        self.allow_unknown_keys = true;
+        self
+    }
+
+    /// Returns the fields declared in this schema.
+    pub fn fields(&self) -> &[ConfigField] {
+        &self.fields
+    }
+
+    /// Checks that the schema itself is well-formed.
+    ///
+    /// # Errors
+    /// Returns an error if a key is declared more than once.
+    pub fn check(&self) -> anyhow::Result<()> {
+        let mut seen = HashSet::new();
+        for field in &self.fields {
+            if !seen.insert(field.key) {
+                bail!("config key '{}' is declared more than once", field.key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates a configuration map against this schema.
+    ///
+    /// # Arguments
+    /// * `config` - The configuration supplied on a [`crate::wit::WitInterface`]
+    ///
+    /// # Errors
+    /// Returns an error listing every unknown key, missing required key and
+    /// value of the wrong type.
+    pub fn validate(&self, config: &HashMap<String, String>) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+
+        for field in &self.fields {
+            match config.get(field.key) {
+                Some(value) if !field.kind.accepts(value) => problems.push(format!(
+                    "config key '{}' expects {}, got '{value}'",
+                    field.key, field.kind
+                )),
+                None if field.required => problems.push(format!(
+                    "missing required config key '{}' ({})",
+                    field.key, field.description
+                )),
+                _ => {}
+            }
+        }
+
+        if !self.allow_unknown_keys {
+            let mut unknown: Vec<&String> = config
+                .keys()
+                .filter(|key| !self.fields.iter().any(|f| f.key == key.as_str()))
+                .collect();
+            unknown.sort();
+            for key in unknown {
+                match self.closest_key(key) {
+                    Some(suggestion) => problems.push(format!(
+                        "unknown config key '{key}' (did you mean '{suggestion}'?)"
+                    )),
+                    None => problems.push(format!("unknown config key '{key}'")),
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            bail!(problems.join("; "));
+        }
+        Ok(())
+    }
+
+    /// Returns the declared key closest to `key`, if it's close enough to be a likely typo.
+    fn closest_key(&self, key: &str) -> Option<&'static str> {
+        self.fields
+            .iter()
+            .map(|f| (f.key, edit_distance(key, f.key)))
+            .filter(|(candidate, distance)| *distance <= candidate.len().max(key.len()) / 3)
+            .min_by_key(|(_, distance)| *distance)
+            .map(|(candidate, _)| candidate)
+    }
+}
+
+/// Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> ConfigSchema {
+        ConfigSchema::new()
+            .with_field("buckets", ConfigValueKind::List, "buckets to expose")
+            .with_field("read_only", ConfigValueKind::Bool, "reject writes")
+            .with_required_field("max_size", ConfigValueKind::Integer, "maximum object size")
+    }
+
+    fn config(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_validate_accepts_valid_config() {
+        let config = config(&[
+            ("buckets", "a,b"),
+            ("read_only", "true"),
+            ("max_size", "10"),
+        ]);
+        assert!(schema().validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_validate_reports_all_problems() {
+        let config = config(&[("bucket", "a"), ("read_only", "yes")]);
+        let err = schema()
+            .validate(&config)
+            .expect_err("invalid config should fail validation")
+            .to_string();
+        assert!(err.contains("config key 'read_only' expects a boolean, got 'yes'"));
+        assert!(err.contains("missing required config key 'max_size'"));
+        assert!(err.contains("unknown config key 'bucket' (did you mean 'buckets'?)"));
+    }
+
+    #[test]
+    fn test_allow_unknown_keys() {
+        let config = config(&[("max_size", "1"), ("anything", "goes")]);
+        assert!(schema().validate(&config).is_err());
+        assert!(schema().allow_unknown_keys().validate(&config).is_ok());
+    }
+
+    #[test]
+    fn test_check_rejects_duplicate_keys() {
+        let schema = schema().with_field("buckets", ConfigValueKind::String, "duplicate");
+        assert!(schema.check().is_err());
+    }
+}
@@ -10,6 +10,8 @@ use std::{
 };
 
 const WASI_BLOBSTORE_ID: &str = "wasi-blobstore";
+use anyhow::Context as _;
+use bytes::Bytes;
 use tokio::sync::RwLock;
 use wasmtime::component::{HasSelf, Resource};
 use wasmtime_wasi::p2::{
@@ -19,8 +21,13 @@ use wasmtime_wasi::p2::{
 
 use crate::{
     engine::ctx::Ctx,
-    engine::workload::WorkloadComponent,
-    plugin::HostPlugin,
+    engine::workload::{UnresolvedWorkload, WorkloadComponent},
+    plugin::{
+        HostPlugin,
+        blobstore_policy::{ContainerPolicies, ContainerPolicy},
+        encryption::Encryptor,
+        schema::ConfigSchema,
+    },
     wit::{WitInterface, WitWorld},
 };
 
@@ -51,7 +58,7 @@ use bindings::wasi::blobstore::{
 pub struct ObjectData {
     pub name: String,
     pub container: String,
-    pub data: Vec<u8>,
+    pub data: Bytes,
     pub created_at: u64,
 }
 
@@ -63,8 +70,27 @@ pub struct ContainerData {
     pub objects: HashMap<String, ObjectData>,
 }
 
+impl ContainerData {
+    /// Returns an object unless it has outlived the container's TTL.
+    fn live_object(&self, name: &str, policy: &ContainerPolicy) -> Option<&ObjectData> {
+        let now = WasiBlobstore::get_timestamp();
+        self.objects
+            .get(name)
+            .filter(|object| !policy.is_expired(object.created_at, now))
+    }
+
+    /// Deletes the objects that have outlived the container's TTL.
+    fn remove_expired(&mut self, policy: &ContainerPolicy) {
+        let now = WasiBlobstore::get_timestamp();
+        self.objects
+            .retain(|_, object| !policy.is_expired(object.created_at, now));
+    }
+}
+
 /// Resource representation for an incoming value (data being read)
-pub type IncomingValueHandle = Vec<u8>;
+/// A range of an object's data. It shares the stored buffer, so reading an object
+/// through a stream never copies it before it's written to its destination.
+pub type IncomingValueHandle = Bytes;
 
 /// Resource representation for an outgoing value (data being written)
 pub struct OutgoingValueHandle {
@@ -89,6 +115,10 @@ pub struct WasiBlobstore {
     storage: Arc<RwLock<HashMap<String, HashMap<String, ContainerData>>>>,
     /// The maximum size for objects stored in the blobstore
     max_object_size: usize,
+    /// Container policies of each workload, keyed by workload ID
+    policies: Arc<RwLock<HashMap<String, ContainerPolicies>>>,
+    /// Encrypts objects before they are stored, if set
+    encryptor: Option<Encryptor>,
 }
 
 impl WasiBlobstore {
@@ -96,9 +126,43 @@ impl WasiBlobstore {
         Self {
             storage: Arc::new(RwLock::new(HashMap::new())),
             max_object_size: max_object_size.unwrap_or(1_000_000), // 1mb limit by default
+            policies: Arc::default(),
+            encryptor: None,
+        }
+    }
+
+    /// Encrypts objects with `encryptor` before they are stored.
+    pub fn with_encryption(mut self, encryptor: Encryptor) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// Returns the plaintext of the data of object `name` in `container`.
+    async fn open(&self, container: &str, name: &str, data: &Bytes) -> anyhow::Result<Bytes> {
+        match &self.encryptor {
+            Some(encryptor) => Ok(encryptor.open(container, name, data).await?.into()),
+            None => Ok(data.clone()),
         }
     }
 
+    /// Returns the size of the plaintext of stored object data.
+    fn plaintext_len(&self, data: &[u8]) -> usize {
+        match &self.encryptor {
+            Some(_) => Encryptor::plaintext_len(data),
+            None => data.len(),
+        }
+    }
+
+    /// Returns the policy a workload configured for a container.
+    async fn container_policy(&self, workload_id: &str, container: &str) -> ContainerPolicy {
+        self.policies
+            .read()
+            .await
+            .get(workload_id)
+            .map(|policies| policies.for_container(container))
+            .unwrap_or_default()
+    }
+
     fn get_timestamp() -> u64 {
         SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -163,9 +227,19 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin.container_policy(&self.workload_id, &name).await;
         let mut storage = plugin.storage.write().await;
         let workload_storage = storage.entry(self.id.clone()).or_default();
 
+        if let Some(container_data) = workload_storage.get_mut(&name) {
+            container_data.remove_expired(&policy);
+            if let Some(object) = container_data.objects.keys().next()
+                && let Err(e) = policy.check_mutable(&name, object)
+            {
+                return Ok(Err(e));
+            }
+        }
+
         workload_storage.remove(&name);
         Ok(Ok(()))
     }
@@ -194,6 +268,12 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let src_policy = plugin
+            .container_policy(&self.workload_id, &src.container)
+            .await;
+        let dest_policy = plugin
+            .container_policy(&self.workload_id, &dest.container)
+            .await;
         let mut storage = plugin.storage.write().await;
         let workload_storage = storage.entry(self.id.clone()).or_default();
 
@@ -209,7 +289,7 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
                 }
             };
 
-            match src_container.objects.get(&src.object) {
+            match src_container.live_object(&src.object, &src_policy) {
                 Some(object) => object.clone(),
                 None => {
                     return Ok(Err(format!(
@@ -231,7 +311,37 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             }
         };
 
+        dest_container.remove_expired(&dest_policy);
+        let size = plugin.plaintext_len(&src_object_data.data) as u64;
+        if let Err(e) = dest_policy.check_size(&dest.container, size) {
+            return Ok(Err(e));
+        }
+        if dest_container.objects.contains_key(&dest.object)
+            && let Err(e) = dest_policy.check_mutable(&dest.container, &dest.object)
+        {
+            return Ok(Err(e));
+        }
+
         let mut copied_object = src_object_data;
+        // Encrypted objects are bound to their name
+        if let Some(encryptor) = &plugin.encryptor {
+            match encryptor
+                .reseal(
+                    (&src.container, &src.object),
+                    (&dest.container, &dest.object),
+                    &copied_object.data,
+                )
+                .await
+            {
+                Ok(sealed) => copied_object.data = sealed.into(),
+                Err(e) => {
+                    return Ok(Err(format!(
+                        "failed to encrypt object '{}': {e:#}",
+                        dest.object
+                    )));
+                }
+            }
+        }
         copied_object.name = dest.object.clone();
         copied_object.container = dest.container.clone();
         copied_object.created_at = WasiBlobstore::get_timestamp();
@@ -245,13 +355,23 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
         src: ObjectId,
         dest: ObjectId,
     ) -> anyhow::Result<Result<(), BlobstoreError>> {
-        // First copy the object
-        let _ = self.copy_object(src.clone(), dest).await?;
-
         let Some(plugin) = self.get_plugin::<WasiBlobstore>(WASI_BLOBSTORE_ID) else {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        // Moving deletes the source, which immutable containers don't allow
+        let src_policy = plugin
+            .container_policy(&self.workload_id, &src.container)
+            .await;
+        if let Err(e) = src_policy.check_mutable(&src.container, &src.object) {
+            return Ok(Err(e));
+        }
+
+        // First copy the object
+        if let Err(e) = self.copy_object(src.clone(), dest).await? {
+            return Ok(Err(e));
+        }
+
         // Then delete the source
         let mut storage = plugin.storage.write().await;
         let workload_storage = storage.entry(self.id.clone()).or_default();
@@ -320,21 +440,30 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let storage = plugin.storage.read().await;
         let empty_map = HashMap::new();
         let workload_storage = storage.get(&self.id).unwrap_or(&empty_map);
 
         match workload_storage.get(container_name) {
-            Some(container_data) => match container_data.objects.get(&name) {
+            Some(container_data) => match container_data.live_object(&name, &policy) {
                 Some(object_data) => {
-                    let start_idx = start.min(object_data.data.len() as u64) as usize;
-                    let end_idx = end.min(object_data.data.len() as u64) as usize;
-                    let data_slice = object_data.data[start_idx..end_idx].to_vec();
+                    let data = match plugin.open(container_name, &name, &object_data.data).await {
+                        Ok(data) => data,
+                        Err(e) => {
+                            return Ok(Err(format!("failed to decrypt object '{name}': {e:#}")));
+                        }
+                    };
+                    let end_idx = end.min(data.len() as u64) as usize;
+                    let start_idx = (start as usize).min(end_idx);
+                    let data_slice = data.slice(start_idx..end_idx);
 
                     tracing::debug!(
                         container = container_name,
                         object = name,
-                        original_size = object_data.data.len(),
+                        original_size = data.len(),
                         slice_size = data_slice.len(),
                         start_idx = start_idx,
                         end_idx = end_idx,
@@ -423,13 +552,21 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let storage = plugin.storage.read().await;
         let empty_map = HashMap::new();
         let workload_storage = storage.get(&self.id).unwrap_or(&empty_map);
 
         match workload_storage.get(container_name) {
             Some(container_data) => {
-                let objects: Vec<String> = container_data.objects.keys().cloned().collect();
+                let objects: Vec<String> = container_data
+                    .objects
+                    .keys()
+                    .filter(|name| container_data.live_object(name, &policy).is_some())
+                    .cloned()
+                    .collect();
                 let handle = StreamObjectNamesHandle {
                     container_name: container_name.clone(),
                     workload_id: self.id.clone(),
@@ -454,11 +591,20 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let mut storage = plugin.storage.write().await;
         let workload_storage = storage.entry(self.id.clone()).or_default();
 
         match workload_storage.get_mut(container_name) {
             Some(container_data) => {
+                container_data.remove_expired(&policy);
+                if container_data.objects.contains_key(&name)
+                    && let Err(e) = policy.check_mutable(container_name, &name)
+                {
+                    return Ok(Err(e));
+                }
                 container_data.objects.remove(&name);
                 Ok(Ok(()))
             }
@@ -477,11 +623,22 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let mut storage = plugin.storage.write().await;
         let workload_storage = storage.entry(self.id.clone()).or_default();
 
         match workload_storage.get_mut(container_name) {
             Some(container_data) => {
+                container_data.remove_expired(&policy);
+                if let Some(name) = names
+                    .iter()
+                    .find(|name| container_data.objects.contains_key(*name))
+                    && let Err(e) = policy.check_mutable(container_name, name)
+                {
+                    return Ok(Err(e));
+                }
                 for name in names {
                     container_data.objects.remove(&name);
                 }
@@ -502,12 +659,15 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let storage = plugin.storage.read().await;
         let empty_map = HashMap::new();
         let workload_storage = storage.get(&self.id).unwrap_or(&empty_map);
 
         match workload_storage.get(container_name) {
-            Some(container_data) => Ok(Ok(container_data.objects.contains_key(&name))),
+            Some(container_data) => Ok(Ok(container_data.live_object(&name, &policy).is_some())),
             None => Ok(Err(format!("container '{container_name}' does not exist"))),
         }
     }
@@ -523,17 +683,20 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let storage = plugin.storage.read().await;
         let empty_map = HashMap::new();
         let workload_storage = storage.get(&self.id).unwrap_or(&empty_map);
 
         match workload_storage.get(container_name) {
-            Some(container_data) => match container_data.objects.get(&name) {
+            Some(container_data) => match container_data.live_object(&name, &policy) {
                 Some(object_data) => Ok(Ok(ObjectMetadata {
                     name: object_data.name.clone(),
                     container: object_data.container.clone(),
                     created_at: object_data.created_at,
-                    size: object_data.data.len() as u64,
+                    size: plugin.plaintext_len(&object_data.data) as u64,
                 })),
                 None => Ok(Err(format!("object '{name}' does not exist"))),
             },
@@ -551,11 +714,20 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             return Ok(Err("blobstore plugin not available".to_string()));
         };
 
+        let policy = plugin
+            .container_policy(&self.workload_id, container_name)
+            .await;
         let mut storage = plugin.storage.write().await;
         let workload_storage = storage.entry(self.id.clone()).or_default();
 
         match workload_storage.get_mut(container_name) {
             Some(container_data) => {
+                container_data.remove_expired(&policy);
+                if let Some(object) = container_data.objects.keys().next()
+                    && let Err(e) = policy.check_mutable(container_name, object)
+                {
+                    return Ok(Err(e));
+                }
                 container_data.objects.clear();
                 Ok(Ok(()))
             }
@@ -766,15 +938,44 @@ impl bindings::wasi::blobstore::types::HostOutgoingValue for Ctx {
                 "Retrieved data from pipe in finish()"
             );
 
+            let policy = plugin
+                .container_policy(&self.workload_id, container_name)
+                .await;
+            if let Err(e) = policy.check_size(container_name, data_bytes.len() as u64) {
+                return Ok(Err(e));
+            }
+            let data_bytes = match &plugin.encryptor {
+                Some(encryptor) => match encryptor
+                    .seal(container_name, object_name, &data_bytes)
+                    .await
+                {
+                    Ok(sealed) => Bytes::from(sealed),
+                    Err(e) => {
+                        return Ok(Err(format!(
+                            "failed to encrypt object '{object_name}': {e:#}"
+                        )));
+                    }
+                },
+                None => data_bytes,
+            };
+            self.bandwidth().throttle(data_bytes.len()).await;
+
             let mut storage = plugin.storage.write().await;
             let workload_storage = storage.entry(self.id.clone()).or_default();
 
             match workload_storage.get_mut(container_name) {
                 Some(container_data) => {
+                    container_data.remove_expired(&policy);
+                    if container_data.objects.contains_key(object_name)
+                        && let Err(e) = policy.check_mutable(container_name, object_name)
+                    {
+                        return Ok(Err(e));
+                    }
+                    let size = data_bytes.len();
                     let object_data = ObjectData {
                         name: object_name.clone(),
                         container: container_name.clone(),
-                        data: data_bytes.to_vec(),
+                        data: data_bytes,
                         created_at: WasiBlobstore::get_timestamp(),
                     };
                     container_data
@@ -784,7 +985,7 @@ impl bindings::wasi::blobstore::types::HostOutgoingValue for Ctx {
                     tracing::debug!(
                         container = container_name,
                         object = object_name,
-                        size = data_bytes.len(),
+                        size,
                         "Stored object data to container"
                     );
                 }
@@ -830,8 +1031,9 @@ impl bindings::wasi::blobstore::types::HostIncomingValue for Ctx {
             data_size = data.len(),
             "incoming_value_consume_sync returning data"
         );
+        self.bandwidth().throttle(data.len()).await;
 
-        Ok(Ok(data))
+        Ok(Ok(data.to_vec()))
     }
 
     async fn incoming_value_consume_async(
@@ -840,15 +1042,19 @@ impl bindings::wasi::blobstore::types::HostIncomingValue for Ctx {
     ) -> anyhow::Result<
         Result<Resource<bindings::wasi::blobstore::types::IncomingValueAsyncBody>, BlobstoreError>,
     > {
-        let data = self.table.get(&incoming_value)?;
+        let data = self.table.delete(incoming_value)?;
 
         tracing::debug!(
             workload_id = self.id,
             data_size = data.len(),
             "incoming_value_consume_async creating MemoryInputPipe with data"
         );
+        self.bandwidth().throttle(data.len()).await;
 
-        let stream: Box<dyn InputStream> = Box::new(MemoryInputPipe::new(data.clone()));
+        // The pipe hands out slices of the stored object, so a component that splices the
+        // stream into an HTTP response body moves the data to hyper without copying it
+        // through its own memory.
+        let stream: Box<dyn InputStream> = Box::new(MemoryInputPipe::new(data));
         let stream = self.table.push(stream)?;
 
         tracing::debug!(
@@ -946,6 +1152,32 @@ impl HostPlugin for WasiBlobstore {
         Ok(())
     }
 
+    fn config_schema(&self) -> Option<ConfigSchema> {
+        Some(ContainerPolicies::with_schema_fields(ConfigSchema::new()))
+    }
+
+    async fn on_workload_bind(
+        &self,
+        workload: &UnresolvedWorkload,
+        host_interfaces: std::collections::HashSet<crate::wit::WitInterface>,
+    ) -> anyhow::Result<()> {
+        let Some(interface) = host_interfaces
+            .iter()
+            .find(|i| i.namespace == "wasi" && i.package == "blobstore")
+        else {
+            return Ok(());
+        };
+
+        let policies = ContainerPolicies::from_config(&interface.config)
+            .context("invalid blobstore container policies")?;
+        self.policies
+            .write()
+            .await
+            .insert(workload.id().to_string(), policies);
+
+        Ok(())
+    }
+
     async fn on_workload_unbind(
         &self,
         workload_id: &str,
@@ -954,6 +1186,7 @@ impl HostPlugin for WasiBlobstore {
         // Clean up storage for this workload
         let mut storage = self.storage.write().await;
         storage.remove(workload_id);
+        self.policies.write().await.remove(workload_id);
 
         tracing::debug!("WasiBlobstore plugin unbound from workload '{workload_id}'");
 
@@ -982,13 +1215,13 @@ mod tests {
         let data = ObjectData {
             name: "test.txt".to_string(),
             container: "test-container".to_string(),
-            data: b"hello world".to_vec(),
+            data: Bytes::from_static(b"hello world"),
             created_at: WasiBlobstore::get_timestamp(),
         };
 
         assert_eq!(data.name, "test.txt");
         assert_eq!(data.container, "test-container");
-        assert_eq!(data.data, b"hello world");
+        assert_eq!(data.data, &b"hello world"[..]);
         assert!(data.created_at > 0);
     }
 
@@ -1005,6 +1238,155 @@ mod tests {
         assert!(container.objects.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_incoming_value_shares_stored_data() -> anyhow::Result<()> {
+        use bindings::wasi::blobstore::container::HostContainer as _;
+        use bindings::wasi::blobstore::types::HostIncomingValue as _;
+
+        let blobstore = Arc::new(WasiBlobstore::new(None));
+        let plugin: Arc<dyn HostPlugin + Send + Sync> = blobstore.clone();
+        let mut ctx = Ctx::builder("workload", "component")
+            .with_plugins(HashMap::from([(WASI_BLOBSTORE_ID, plugin)]))
+            .build();
+
+        let stored = Bytes::from_static(b"hello world");
+        let container = ContainerData {
+            name: "files".to_string(),
+            created_at: WasiBlobstore::get_timestamp(),
+            objects: HashMap::from([(
+                "hello.txt".to_string(),
+                ObjectData {
+                    name: "hello.txt".to_string(),
+                    container: "files".to_string(),
+                    data: stored.clone(),
+                    created_at: WasiBlobstore::get_timestamp(),
+                },
+            )]),
+        };
+        blobstore.storage.write().await.insert(
+            ctx.id.clone(),
+            HashMap::from([("files".to_string(), container)]),
+        );
+
+        let container = ctx.table.push("files".to_string())?;
+        let value = ctx
+            .get_data(container, "hello.txt".to_string(), 6, 11)
+            .await?
+            .expect("object exists");
+        let stream = ctx
+            .incoming_value_consume_async(value)
+            .await?
+            .expect("stream is created");
+
+        let chunk = ctx.table.get_mut(&stream)?.read(1024)?;
+        assert_eq!(chunk, &b"world"[..]);
+        // The stream reads the stored buffer rather than a copy of it
+        assert_eq!(chunk.as_ptr(), stored[6..].as_ptr());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_objects() -> anyhow::Result<()> {
+        use crate::plugin::encryption::{EncryptionKey, LocalKeyring};
+        use bindings::wasi::blobstore::blobstore::Host as _;
+        use bindings::wasi::blobstore::container::HostContainer as _;
+        use bindings::wasi::blobstore::types::{HostIncomingValue as _, HostOutgoingValue as _};
+
+        let encryptor = Encryptor::new(LocalKeyring::new(1, EncryptionKey::generate()?));
+        let blobstore = Arc::new(WasiBlobstore::new(None).with_encryption(encryptor));
+        let plugin: Arc<dyn HostPlugin + Send + Sync> = blobstore.clone();
+        let mut ctx = Ctx::builder("workload", "component")
+            .with_plugins(HashMap::from([(WASI_BLOBSTORE_ID, plugin)]))
+            .build();
+
+        ctx.create_container("files".to_string())
+            .await?
+            .expect("container is created");
+        let mut pipe = MemoryOutputPipe::new(1024);
+        pipe.write(Bytes::from_static(b"card 4242"))?;
+        let value = ctx.table.push(OutgoingValueHandle {
+            pipe,
+            container_name: Some("files".to_string()),
+            object_name: Some("card.txt".to_string()),
+        })?;
+        ctx.finish(value).await?.expect("object is stored");
+
+        let stored = blobstore.storage.read().await[&ctx.id]["files"].objects["card.txt"]
+            .data
+            .clone();
+        assert!(Encryptor::is_sealed(&stored));
+        assert!(!stored.windows(4).any(|w| w == b"4242"));
+
+        let container = ctx.table.push("files".to_string())?;
+        let info = ctx
+            .object_info(container, "card.txt".to_string())
+            .await?
+            .expect("object exists");
+        assert_eq!(info.size, 9);
+
+        let container = ctx.table.push("files".to_string())?;
+        let value = ctx
+            .get_data(container, "card.txt".to_string(), 5, 9)
+            .await?
+            .expect("object exists");
+        let data = ctx
+            .incoming_value_consume_sync(value)
+            .await?
+            .expect("data is read");
+        assert_eq!(data, b"4242");
+
+        // Copies are sealed for their own name
+        let object = |object: &str| ObjectId {
+            container: "files".to_string(),
+            object: object.to_string(),
+        };
+        ctx.copy_object(object("card.txt"), object("copy.txt"))
+            .await?
+            .expect("object is copied");
+        let container = ctx.table.push("files".to_string())?;
+        let value = ctx
+            .get_data(container, "copy.txt".to_string(), 0, 9)
+            .await?
+            .expect("object exists");
+        let data = ctx
+            .incoming_value_consume_sync(value)
+            .await?
+            .expect("data is read");
+        assert_eq!(data, b"card 4242");
+
+        // Sealed data planted under another name, or plaintext, doesn't open
+        for (name, data) in [
+            ("moved.txt", stored),
+            ("planted.txt", Bytes::from("card 0000")),
+        ] {
+            blobstore
+                .storage
+                .write()
+                .await
+                .get_mut(&ctx.id)
+                .and_then(|storage| storage.get_mut("files"))
+                .expect("container exists")
+                .objects
+                .insert(
+                    name.to_string(),
+                    ObjectData {
+                        name: name.to_string(),
+                        container: "files".to_string(),
+                        data,
+                        created_at: WasiBlobstore::get_timestamp(),
+                    },
+                );
+            let container = ctx.table.push("files".to_string())?;
+            assert!(
+                ctx.get_data(container, name.to_string(), 0, 9)
+                    .await?
+                    .is_err(),
+                "expected '{name}' not to open"
+            );
+        }
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_storage_operations() {
         let blobstore = WasiBlobstore::new(None);
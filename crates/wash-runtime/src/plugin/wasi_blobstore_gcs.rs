@@ -0,0 +1,1595 @@
+//! # WASI Blobstore Google Cloud Storage Plugin
+//!
+//! This module implements the `wasi:blobstore@0.2.0-draft` interface on Google Cloud Storage.
+//! Containers map to buckets in a GCP project and objects to objects in those buckets.
+//!
+//! The plugin authenticates either with a service account key, or with the workload identity
+//! served by the GCE metadata server when running on GCE, GKE or Cloud Run. See
+//! [`GcsAuth::from_env`] for how the credentials are picked.
+//!
+//! The plugin can hold several [`GcsBackend`]s, such as one per project, as named
+//! [profiles](crate::plugin::profiles) that workloads select in their interface config.
+//!
+//! With [`WasiBlobstoreGcs::with_encryption`] objects are [sealed](crate::plugin::encryption)
+//! before they are uploaded. Encrypted objects are downloaded whole when they're read or
+//! copied, and their sizes are reported without the sealing overhead.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+const WASI_BLOBSTORE_ID: &str = "wasi-blobstore";
+
+use anyhow::{Context as _, bail, ensure};
+use base64::Engine as _;
+use bytes::Bytes;
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
+use tokio::sync::{Mutex, RwLock};
+use wasmtime::component::{HasSelf, Resource};
+use wasmtime_wasi::p2::{
+    InputStream, OutputStream,
+    pipe::{AsyncReadStream, AsyncWriteStream, MemoryInputPipe},
+};
+
+use crate::{
+    engine::ctx::Ctx,
+    engine::workload::{UnresolvedWorkload, WorkloadComponent},
+    host::bandwidth::ThrottledReader,
+    host::dns::{ReqwestResolver, Resolver},
+    plugin::{
+        HostPlugin,
+        blobstore_policy::{ContainerPolicies, ContainerPolicy},
+        encryption::{Encryptor, SEALED_OVERHEAD},
+        profiles::BackendProfiles,
+        schema::{ConfigSchema, ConfigValueKind},
+    },
+    wit::{WitInterface, WitWorld},
+};
+
+/// The public Google Cloud Storage endpoint
+pub const DEFAULT_GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+/// The endpoint of the GCE metadata server, which serves workload identity tokens
+const METADATA_ENDPOINT: &str = "http://metadata.google.internal/computeMetadata/v1";
+/// The OAuth scope requested for storage access
+const STORAGE_SCOPE: &str = "https://www.googleapis.com/auth/devstorage.read_write";
+/// How long before an access token expires it is refreshed
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+/// How many object names are fetched per list request
+const LIST_PAGE_SIZE: u64 = 1000;
+
+mod bindings {
+    wasmtime::component::bindgen!({
+        world: "blobstore",
+        imports: { default: async | trappable },
+        with: {
+            "wasi:io": ::wasmtime_wasi::p2::bindings::io,
+            "wasi:blobstore/container/container": String,
+            "wasi:blobstore/container/stream-object-names": crate::plugin::wasi_blobstore_gcs::StreamObjectNamesHandle,
+            "wasi:blobstore/types/incoming-value": crate::plugin::wasi_blobstore_gcs::IncomingValueHandle,
+            "wasi:blobstore/types/outgoing-value": crate::plugin::wasi_blobstore_gcs::OutgoingValueHandle,
+        },
+    });
+}
+
+use bindings::wasi::blobstore::{
+    container::Error as ContainerError,
+    types::{
+        ContainerMetadata, ContainerName, Error as BlobstoreError, ObjectId, ObjectMetadata,
+        ObjectName,
+    },
+};
+
+/// How the plugin authenticates to Google Cloud Storage
+#[derive(Clone)]
+pub enum GcsAuth {
+    /// Sign tokens with a service account key
+    ServiceAccount(ServiceAccountKey),
+    /// Fetch tokens for the workload identity from the GCE metadata server
+    WorkloadIdentity,
+}
+
+impl GcsAuth {
+    /// Uses the service account key file named by `GOOGLE_APPLICATION_CREDENTIALS` if it is
+    /// set, and the workload identity otherwise.
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var_os("GOOGLE_APPLICATION_CREDENTIALS") {
+            Some(path) => Self::service_account_file(path),
+            None => Ok(Self::WorkloadIdentity),
+        }
+    }
+
+    /// Loads a service account key from its JSON key file.
+    pub fn service_account_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let key = std::fs::read(path)
+            .with_context(|| format!("failed to read service account key {}", path.display()))?;
+        let key = serde_json::from_slice(&key)
+            .with_context(|| format!("invalid service account key {}", path.display()))?;
+        Ok(Self::ServiceAccount(key))
+    }
+
+    /// The project of the service account, if known.
+    pub fn project_id(&self) -> Option<&str> {
+        match self {
+            Self::ServiceAccount(key) => key.project_id.as_deref(),
+            Self::WorkloadIdentity => None,
+        }
+    }
+}
+
+impl std::fmt::Debug for GcsAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ServiceAccount(key) => f
+                .debug_tuple("ServiceAccount")
+                .field(&key.client_email)
+                .finish(),
+            Self::WorkloadIdentity => f.write_str("WorkloadIdentity"),
+        }
+    }
+}
+
+/// The fields of a service account JSON key file used to sign access token requests
+#[derive(Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub client_email: String,
+    pub private_key: String,
+    #[serde(default = "default_token_uri")]
+    pub token_uri: String,
+    #[serde(default)]
+    pub project_id: Option<String>,
+}
+
+fn default_token_uri() -> String {
+    "https://oauth2.googleapis.com/token".to_string()
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Hands out access tokens, refreshing them shortly before they expire
+struct TokenSource {
+    auth: GcsAuth,
+    http: reqwest::Client,
+    cached: Mutex<Option<(String, Instant)>>,
+}
+
+impl TokenSource {
+    async fn token(&self) -> anyhow::Result<String> {
+        let mut cached = self.cached.lock().await;
+        if let Some((token, expires_at)) = cached.as_ref()
+            && Instant::now() + TOKEN_REFRESH_MARGIN < *expires_at
+        {
+            return Ok(token.clone());
+        }
+
+        let response = match &self.auth {
+            GcsAuth::ServiceAccount(key) => {
+                let assertion = signed_jwt(key, unix_now())?;
+                self.http
+                    .post(&key.token_uri)
+                    .form(&[
+                        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+                        ("assertion", assertion.as_str()),
+                    ])
+                    .send()
+                    .await
+            }
+            GcsAuth::WorkloadIdentity => {
+                self.http
+                    .get(format!(
+                        "{METADATA_ENDPOINT}/instance/service-accounts/default/token"
+                    ))
+                    .header("Metadata-Flavor", "Google")
+                    .send()
+                    .await
+            }
+        }
+        .context("failed to request GCS access token")?;
+        let response: TokenResponse = error_for_status(response)
+            .await
+            .context("failed to get GCS access token")?
+            .json()
+            .await
+            .context("invalid GCS access token response")?;
+
+        let expires_at = Instant::now() + Duration::from_secs(response.expires_in);
+        *cached = Some((response.access_token.clone(), expires_at));
+        Ok(response.access_token)
+    }
+}
+
+/// Builds the RS256 signed JWT a service account exchanges for an access token.
+fn signed_jwt(key: &ServiceAccountKey, now: u64) -> anyhow::Result<String> {
+    let encode = |value: serde_json::Value| {
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(value.to_string())
+    };
+    let message = format!(
+        "{}.{}",
+        encode(serde_json::json!({ "alg": "RS256", "typ": "JWT" })),
+        encode(serde_json::json!({
+            "iss": key.client_email,
+            "scope": STORAGE_SCOPE,
+            "aud": key.token_uri,
+            "iat": now,
+            "exp": now + 3600,
+        })),
+    );
+
+    let private_key = rustls_pemfile::private_key(&mut key.private_key.as_bytes())
+        .context("invalid service account private key")?
+        .context("service account key has no private key")?;
+    let signer = rustls::crypto::aws_lc_rs::sign::any_supported_type(&private_key)
+        .context("unsupported service account private key")?
+        .choose_scheme(&[rustls::SignatureScheme::RSA_PKCS1_SHA256])
+        .context("service account private key is not an RSA key")?;
+    let signature = signer
+        .sign(message.as_bytes())
+        .context("failed to sign access token request")?;
+
+    Ok(format!(
+        "{message}.{}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(signature)
+    ))
+}
+
+/// Turns an error status into an error carrying the response body, which holds the reason.
+async fn error_for_status(response: reqwest::Response) -> anyhow::Result<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    bail!("request failed with status {status}: {body}")
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// The `Range` header value for the object bytes from `start` up to, but excluding, `end`.
+/// Returns `None` when the range is empty.
+fn range_header(start: u64, end: u64) -> Option<String> {
+    (end > start).then(|| format!("bytes={start}-{}", end - 1))
+}
+
+/// The lifecycle age, in whole days, at which GCS deletes objects with the given TTL.
+/// GCS only expires objects by day, objects are hidden as soon as their TTL passes.
+fn lifecycle_age_days(ttl: Duration) -> u64 {
+    ttl.as_secs().div_ceil(24 * 60 * 60).max(1)
+}
+
+/// A bucket as returned by the JSON API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BucketResource {
+    #[serde(default)]
+    time_created: Option<String>,
+}
+
+/// An object as returned by the JSON API
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectResource {
+    name: String,
+    /// The JSON API encodes the 64-bit size as a string
+    #[serde(default)]
+    size: String,
+    #[serde(default)]
+    time_created: Option<String>,
+}
+
+impl ObjectResource {
+    fn size(&self) -> u64 {
+        self.size.parse().unwrap_or_default()
+    }
+
+    /// The size of the object's data, without the sealing overhead if it's encrypted.
+    fn plaintext_size(&self, encrypted: bool) -> u64 {
+        if encrypted {
+            self.size().saturating_sub(SEALED_OVERHEAD as u64)
+        } else {
+            self.size()
+        }
+    }
+
+    fn created_at(&self) -> u64 {
+        parse_timestamp(self.time_created.as_deref())
+    }
+
+    fn is_expired(&self, policy: &ContainerPolicy) -> bool {
+        policy.is_expired(self.created_at(), unix_now())
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ObjectList {
+    #[serde(default)]
+    items: Vec<ObjectResource>,
+    #[serde(default)]
+    next_page_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RewriteResponse {
+    done: bool,
+    #[serde(default)]
+    rewrite_token: Option<String>,
+}
+
+/// Parses an RFC 3339 timestamp into seconds since the Unix epoch, or 0 if it's missing.
+fn parse_timestamp(timestamp: Option<&str>) -> u64 {
+    timestamp
+        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+        .map(|t| t.timestamp().max(0) as u64)
+        .unwrap_or_default()
+}
+
+/// A GCP project the plugin stores buckets in, along with the credentials to access it
+pub struct GcsBackend {
+    http: reqwest::Client,
+    tokens: TokenSource,
+    project: String,
+    endpoint: Url,
+}
+
+impl GcsBackend {
+    /// Creates a backend that stores containers as buckets in the given GCP project.
+    pub fn new(project: impl Into<String>, auth: GcsAuth) -> anyhow::Result<Self> {
+        Self::with_endpoint(project, auth, DEFAULT_GCS_ENDPOINT)
+    }
+
+    /// Like [`Self::new`], but against another endpoint serving the GCS JSON API, such as
+    /// an emulator.
+    pub fn with_endpoint(
+        project: impl Into<String>,
+        auth: GcsAuth,
+        endpoint: &str,
+    ) -> anyhow::Result<Self> {
+        let endpoint = Url::parse(endpoint).context("invalid GCS endpoint")?;
+        ensure!(
+            !endpoint.cannot_be_a_base(),
+            "invalid GCS endpoint '{endpoint}'"
+        );
+        let http = reqwest::Client::builder()
+            .build()
+            .context("failed to build GCS HTTP client")?;
+        Ok(Self {
+            tokens: TokenSource {
+                auth,
+                http: http.clone(),
+                cached: Mutex::new(None),
+            },
+            http,
+            project: project.into(),
+            endpoint,
+        })
+    }
+
+    /// Resolves the host names of GCS and its token endpoints with `resolver` instead of
+    /// the system's resolver, see [`crate::host::dns`].
+    pub fn with_resolver(mut self, resolver: Arc<Resolver>) -> anyhow::Result<Self> {
+        let http = reqwest::Client::builder()
+            .dns_resolver(Arc::new(ReqwestResolver(resolver)))
+            .build()
+            .context("failed to build GCS HTTP client")?;
+        self.tokens.http = http.clone();
+        self.http = http;
+        Ok(self)
+    }
+
+    /// Builds a URL from the endpoint and path segments, percent-encoding each segment.
+    fn url(&self, segments: &[&str]) -> Url {
+        let mut url = self.endpoint.clone();
+        url.path_segments_mut()
+            .expect("GCS endpoint is validated to be a base URL")
+            .pop_if_empty()
+            .extend(segments);
+        url
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let token = self.tokens.token().await?;
+        request
+            .bearer_auth(token)
+            .send()
+            .await
+            .context("failed to send request to GCS")
+    }
+
+    async fn bucket(&self, bucket: &str) -> anyhow::Result<Option<BucketResource>> {
+        let response = self
+            .send(self.http.get(self.url(&["storage", "v1", "b", bucket])))
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(error_for_status(response).await?.json().await?))
+    }
+
+    async fn create_bucket(&self, bucket: &str, ttl: Option<Duration>) -> anyhow::Result<()> {
+        let mut body = serde_json::json!({ "name": bucket });
+        if let Some(ttl) = ttl {
+            body["lifecycle"] = serde_json::json!({
+                "rule": [{
+                    "action": { "type": "Delete" },
+                    "condition": { "age": lifecycle_age_days(ttl) },
+                }],
+            });
+        }
+        let request = self
+            .http
+            .post(self.url(&["storage", "v1", "b"]))
+            .query(&[("project", self.project.as_str())])
+            .json(&body);
+        error_for_status(self.send(request).await?).await?;
+        Ok(())
+    }
+
+    async fn delete_bucket(&self, bucket: &str) -> anyhow::Result<()> {
+        let request = self.http.delete(self.url(&["storage", "v1", "b", bucket]));
+        error_for_status(self.send(request).await?).await?;
+        Ok(())
+    }
+
+    async fn object(&self, bucket: &str, object: &str) -> anyhow::Result<Option<ObjectResource>> {
+        let response = self
+            .send(
+                self.http
+                    .get(self.url(&["storage", "v1", "b", bucket, "o", object])),
+            )
+            .await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        Ok(Some(error_for_status(response).await?.json().await?))
+    }
+
+    async fn list_objects(
+        &self,
+        bucket: &str,
+        page_token: Option<&str>,
+    ) -> anyhow::Result<ObjectList> {
+        let max_results = LIST_PAGE_SIZE.to_string();
+        let mut request = self
+            .http
+            .get(self.url(&["storage", "v1", "b", bucket, "o"]))
+            .query(&[
+                ("fields", "items(name,size,timeCreated),nextPageToken"),
+                ("maxResults", max_results.as_str()),
+            ]);
+        if let Some(page_token) = page_token {
+            request = request.query(&[("pageToken", page_token)]);
+        }
+        Ok(error_for_status(self.send(request).await?)
+            .await?
+            .json()
+            .await?)
+    }
+
+    /// Lists every live object of a bucket.
+    async fn all_objects(
+        &self,
+        bucket: &str,
+        policy: &ContainerPolicy,
+    ) -> anyhow::Result<Vec<ObjectResource>> {
+        let mut objects = Vec::new();
+        let mut page_token = None;
+        loop {
+            let page = self.list_objects(bucket, page_token.as_deref()).await?;
+            objects.extend(page.items.into_iter().filter(|o| !o.is_expired(policy)));
+            match page.next_page_token {
+                Some(token) => page_token = Some(token),
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    async fn download(
+        &self,
+        bucket: &str,
+        object: &str,
+        range: Option<String>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let mut request = self
+            .http
+            .get(self.url(&["storage", "v1", "b", bucket, "o", object]))
+            .query(&[("alt", "media")]);
+        if let Some(range) = range {
+            request = request.header(reqwest::header::RANGE, range);
+        }
+        error_for_status(self.send(request).await?).await
+    }
+
+    /// Uploads an object. When `if_absent` is set the upload only succeeds if the object
+    /// doesn't exist yet, and `false` is returned if it does.
+    async fn upload(
+        &self,
+        bucket: &str,
+        object: &str,
+        body: reqwest::Body,
+        size: u64,
+        if_absent: bool,
+    ) -> anyhow::Result<bool> {
+        let mut request = self
+            .http
+            .post(self.url(&["upload", "storage", "v1", "b", bucket, "o"]))
+            .query(&[("uploadType", "media"), ("name", object)])
+            .header(reqwest::header::CONTENT_LENGTH, size)
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .body(body);
+        if if_absent {
+            request = request.query(&[("ifGenerationMatch", "0")]);
+        }
+        let response = self.send(request).await?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Ok(false);
+        }
+        error_for_status(response).await?;
+        Ok(true)
+    }
+
+    /// Copies an object within GCS. Large objects take several rewrite calls.
+    async fn rewrite(
+        &self,
+        src: &ObjectId,
+        dest: &ObjectId,
+        if_absent: bool,
+    ) -> anyhow::Result<bool> {
+        let url = self.url(&[
+            "storage",
+            "v1",
+            "b",
+            &src.container,
+            "o",
+            &src.object,
+            "rewriteTo",
+            "b",
+            &dest.container,
+            "o",
+            &dest.object,
+        ]);
+        let mut rewrite_token: Option<String> = None;
+        loop {
+            let mut request = self.http.post(url.clone());
+            if if_absent {
+                request = request.query(&[("ifGenerationMatch", "0")]);
+            }
+            if let Some(token) = &rewrite_token {
+                request = request.query(&[("rewriteToken", token)]);
+            }
+            let response = self.send(request).await?;
+            if response.status() == StatusCode::PRECONDITION_FAILED {
+                return Ok(false);
+            }
+            let response: RewriteResponse = error_for_status(response).await?.json().await?;
+            if response.done {
+                return Ok(true);
+            }
+            rewrite_token = Some(
+                response
+                    .rewrite_token
+                    .context("GCS rewrite is incomplete but has no rewrite token")?,
+            );
+        }
+    }
+
+    async fn delete_object(&self, bucket: &str, object: &str) -> anyhow::Result<()> {
+        let response = self
+            .send(
+                self.http
+                    .delete(self.url(&["storage", "v1", "b", bucket, "o", object])),
+            )
+            .await?;
+        // Deleting an object that's already gone is not an error
+        if response.status() != StatusCode::NOT_FOUND {
+            error_for_status(response).await?;
+        }
+        Ok(())
+    }
+
+    /// Checks that an existing object may be overwritten or deleted, and deletes it if it
+    /// has outlived its TTL so an immutable container accepts a new object in its place.
+    async fn check_mutable(
+        &self,
+        container: &str,
+        object: &str,
+        policy: &ContainerPolicy,
+    ) -> anyhow::Result<Result<(), String>> {
+        if !policy.immutable {
+            return Ok(Ok(()));
+        }
+        match self.object(container, object).await? {
+            Some(existing) if existing.is_expired(policy) => {
+                self.delete_object(container, object).await?;
+                Ok(Ok(()))
+            }
+            Some(_) => Ok(policy.check_mutable(container, object)),
+            None => Ok(Ok(())),
+        }
+    }
+
+    /// Returns an object unless it's missing or has outlived its TTL.
+    async fn live_object(
+        &self,
+        container: &str,
+        object: &str,
+        policy: &ContainerPolicy,
+    ) -> anyhow::Result<Option<ObjectResource>> {
+        Ok(self
+            .object(container, object)
+            .await?
+            .filter(|o| !o.is_expired(policy)))
+    }
+}
+
+/// Per-workload settings of the GCS blobstore
+struct WorkloadData {
+    /// The backend selected by the workload's profile
+    backend: Arc<GcsBackend>,
+    /// The buckets the workload may access, or `None` for every bucket in the project
+    buckets: Option<HashSet<String>>,
+    policies: ContainerPolicies,
+}
+
+/// Resource representation for an incoming value (data being read). The object is only
+/// downloaded once the value is consumed, unless it's encrypted.
+pub struct IncomingValueHandle {
+    backend: Arc<GcsBackend>,
+    pub container: String,
+    pub object: String,
+    pub start: u64,
+    pub end: u64,
+    /// The decrypted object, which `start` and `end` index into, if it's encrypted
+    pub plaintext: Option<Bytes>,
+}
+
+impl IncomingValueHandle {
+    fn size(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    /// The requested range of the decrypted object, if it's encrypted.
+    fn plaintext_range(&self) -> Option<Bytes> {
+        self.plaintext
+            .as_ref()
+            .map(|data| data.slice(self.start as usize..self.end as usize))
+    }
+}
+
+/// Resource representation for an outgoing value (data being written).
+/// The data is buffered in a temporary file and uploaded when `finish` is called.
+pub struct OutgoingValueHandle {
+    pub temp_file: tempfile::NamedTempFile,
+    pub container: Option<String>,
+    pub object_name: Option<String>,
+}
+
+/// Resource representation for streaming object names, fetched a page at a time
+pub struct StreamObjectNamesHandle {
+    backend: Arc<GcsBackend>,
+    pub container: String,
+    pub policy: ContainerPolicy,
+    pub names: VecDeque<String>,
+    pub next_page_token: Option<String>,
+    pub exhausted: bool,
+}
+
+/// Google Cloud Storage blobstore plugin
+#[derive(Clone)]
+pub struct WasiBlobstoreGcs {
+    profiles: BackendProfiles<GcsBackend>,
+    /// Settings of each bound workload, keyed by workload ID
+    workloads: Arc<RwLock<HashMap<String, WorkloadData>>>,
+    /// Encrypts objects before they are uploaded, if set
+    encryptor: Option<Encryptor>,
+}
+
+impl WasiBlobstoreGcs {
+    /// Creates a plugin that stores containers in the given backend, unless a workload
+    /// selects a named profile.
+    pub fn new(backend: GcsBackend) -> Self {
+        Self {
+            profiles: BackendProfiles::new(backend),
+            workloads: Arc::default(),
+            encryptor: None,
+        }
+    }
+
+    /// Encrypts objects with `encryptor` before they are uploaded.
+    pub fn with_encryption(mut self, encryptor: Encryptor) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// Adds a named backend profile that workloads can select.
+    pub fn with_profile(
+        mut self,
+        name: impl Into<String>,
+        backend: GcsBackend,
+    ) -> anyhow::Result<Self> {
+        self.profiles.add(name, backend)?;
+        Ok(self)
+    }
+
+    /// Returns the backend of a workload and the policy it configured for a container, or
+    /// an error if the workload may not access the container.
+    async fn container_access(
+        &self,
+        workload_id: &str,
+        container: &str,
+    ) -> Result<(Arc<GcsBackend>, ContainerPolicy), String> {
+        let workloads = self.workloads.read().await;
+        let Some(data) = workloads.get(workload_id) else {
+            return Ok((
+                self.profiles.default_backend().clone(),
+                ContainerPolicy::default(),
+            ));
+        };
+        if let Some(buckets) = &data.buckets
+            && !buckets.contains(container)
+        {
+            return Err(format!("unauthorized access to container '{container}'"));
+        }
+        Ok((data.backend.clone(), data.policies.for_container(container)))
+    }
+}
+
+/// Formats a GCS error for the component.
+fn gcs_error(action: &str, e: anyhow::Error) -> String {
+    format!("failed to {action}: {e:#}")
+}
+
+/// Returns the plugin's encryptor, if it encrypts objects.
+fn encryptor(ctx: &Ctx) -> Option<Encryptor> {
+    ctx.get_plugin::<WasiBlobstoreGcs>(WASI_BLOBSTORE_ID)
+        .and_then(|plugin| plugin.encryptor.clone())
+}
+
+/// Downloads a whole object and decrypts it.
+async fn download_plaintext(
+    backend: &GcsBackend,
+    bucket: &str,
+    object: &str,
+    encryptor: &Encryptor,
+) -> anyhow::Result<Bytes> {
+    let sealed = backend
+        .download(bucket, object, None)
+        .await?
+        .bytes()
+        .await?;
+    Ok(encryptor.open(bucket, object, &sealed).await?.into())
+}
+
+/// Resolves the backend and policy for a container, returning the error to the component
+/// if there are none.
+macro_rules! backend_and_policy {
+    ($ctx:expr, $container:expr) => {{
+        let Some(plugin) = $ctx.get_plugin::<WasiBlobstoreGcs>(WASI_BLOBSTORE_ID) else {
+            return Ok(Err("blobstore plugin not available".to_string()));
+        };
+        match plugin.container_access(&$ctx.workload_id, $container).await {
+            Ok(access) => access,
+            Err(e) => return Ok(Err(e)),
+        }
+    }};
+}
+
+// Implementation for the main blobstore interface
+impl bindings::wasi::blobstore::blobstore::Host for Ctx {
+    async fn create_container(
+        &mut self,
+        name: ContainerName,
+    ) -> anyhow::Result<Result<Resource<String>, BlobstoreError>> {
+        let (backend, policy) = backend_and_policy!(self, &name);
+
+        if let Err(e) = backend.create_bucket(&name, policy.ttl).await {
+            return Ok(Err(gcs_error("create bucket", e)));
+        }
+
+        let resource = self.table.push(name)?;
+        Ok(Ok(resource))
+    }
+
+    async fn get_container(
+        &mut self,
+        name: ContainerName,
+    ) -> anyhow::Result<Result<Resource<String>, BlobstoreError>> {
+        let (backend, _policy) = backend_and_policy!(self, &name);
+
+        match backend.bucket(&name).await {
+            Ok(Some(_)) => Ok(Ok(self.table.push(name)?)),
+            Ok(None) => Ok(Err(format!("container '{name}' does not exist"))),
+            Err(e) => Ok(Err(gcs_error("get bucket", e))),
+        }
+    }
+
+    async fn delete_container(
+        &mut self,
+        name: ContainerName,
+    ) -> anyhow::Result<Result<(), BlobstoreError>> {
+        let (backend, policy) = backend_and_policy!(self, &name);
+
+        // GCS only deletes empty buckets
+        let objects = match backend.all_objects(&name, &policy).await {
+            Ok(objects) => objects,
+            Err(e) => return Ok(Err(gcs_error("list objects", e))),
+        };
+        if let Some(object) = objects.first()
+            && let Err(e) = policy.check_mutable(&name, &object.name)
+        {
+            return Ok(Err(e));
+        }
+        for object in objects {
+            if let Err(e) = backend.delete_object(&name, &object.name).await {
+                return Ok(Err(gcs_error("delete object", e)));
+            }
+        }
+
+        match backend.delete_bucket(&name).await {
+            Ok(()) => Ok(Ok(())),
+            Err(e) => Ok(Err(gcs_error("delete bucket", e))),
+        }
+    }
+
+    async fn container_exists(
+        &mut self,
+        name: ContainerName,
+    ) -> anyhow::Result<Result<bool, BlobstoreError>> {
+        let (backend, _policy) = backend_and_policy!(self, &name);
+
+        match backend.bucket(&name).await {
+            Ok(bucket) => Ok(Ok(bucket.is_some())),
+            Err(e) => Ok(Err(gcs_error("get bucket", e))),
+        }
+    }
+
+    async fn copy_object(
+        &mut self,
+        src: ObjectId,
+        dest: ObjectId,
+    ) -> anyhow::Result<Result<(), BlobstoreError>> {
+        let (backend, src_policy) = backend_and_policy!(self, &src.container);
+        let (_, dest_policy) = backend_and_policy!(self, &dest.container);
+
+        let src_object = match backend
+            .live_object(&src.container, &src.object, &src_policy)
+            .await
+        {
+            Ok(Some(object)) => object,
+            Ok(None) => {
+                return Ok(Err(format!(
+                    "source object '{}' does not exist",
+                    src.object
+                )));
+            }
+            Err(e) => return Ok(Err(gcs_error("get source object", e))),
+        };
+        let size = src_object.plaintext_size(encryptor(self).is_some());
+        if let Err(e) = dest_policy.check_size(&dest.container, size) {
+            return Ok(Err(e));
+        }
+        match backend
+            .check_mutable(&dest.container, &dest.object, &dest_policy)
+            .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Ok(Err(e)),
+            Err(e) => return Ok(Err(gcs_error("get destination object", e))),
+        }
+
+        // Encrypted objects are sealed for their new name, so they can't be copied within GCS
+        let copied = match encryptor(self) {
+            Some(encryptor) => {
+                let sealed =
+                    match download_plaintext(&backend, &src.container, &src.object, &encryptor)
+                        .await
+                    {
+                        Ok(plaintext) => {
+                            encryptor
+                                .seal(&dest.container, &dest.object, &plaintext)
+                                .await
+                        }
+                        Err(e) => return Ok(Err(gcs_error("download source object", e))),
+                    };
+                match sealed {
+                    Ok(sealed) => {
+                        let size = sealed.len() as u64;
+                        backend
+                            .upload(
+                                &dest.container,
+                                &dest.object,
+                                sealed.into(),
+                                size,
+                                dest_policy.immutable,
+                            )
+                            .await
+                    }
+                    Err(e) => return Ok(Err(gcs_error("encrypt object", e))),
+                }
+            }
+            None => backend.rewrite(&src, &dest, dest_policy.immutable).await,
+        };
+        match copied {
+            Ok(true) => Ok(Ok(())),
+            Ok(false) => Ok(dest_policy.check_mutable(&dest.container, &dest.object)),
+            Err(e) => Ok(Err(gcs_error("copy object", e))),
+        }
+    }
+
+    async fn move_object(
+        &mut self,
+        src: ObjectId,
+        dest: ObjectId,
+    ) -> anyhow::Result<Result<(), BlobstoreError>> {
+        let (backend, src_policy) = backend_and_policy!(self, &src.container);
+
+        match backend
+            .check_mutable(&src.container, &src.object, &src_policy)
+            .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Ok(Err(e)),
+            Err(e) => return Ok(Err(gcs_error("get source object", e))),
+        }
+
+        if let Err(e) = self.copy_object(src.clone(), dest).await? {
+            return Ok(Err(format!("failed to copy object during move: {e}")));
+        }
+
+        match backend.delete_object(&src.container, &src.object).await {
+            Ok(()) => Ok(Ok(())),
+            Err(e) => Ok(Err(gcs_error("delete source object", e))),
+        }
+    }
+}
+
+impl bindings::wasi::blobstore::container::HostContainer for Ctx {
+    async fn name(
+        &mut self,
+        container: Resource<String>,
+    ) -> anyhow::Result<Result<String, ContainerError>> {
+        Ok(Ok(self.table.get(&container)?.clone()))
+    }
+
+    async fn info(
+        &mut self,
+        container: Resource<String>,
+    ) -> anyhow::Result<Result<ContainerMetadata, ContainerError>> {
+        let name = self.table.get(&container)?.clone();
+        let (backend, _policy) = backend_and_policy!(self, &name);
+
+        match backend.bucket(&name).await {
+            Ok(Some(bucket)) => Ok(Ok(ContainerMetadata {
+                created_at: parse_timestamp(bucket.time_created.as_deref()),
+                name,
+            })),
+            Ok(None) => Ok(Err(format!("container '{name}' does not exist"))),
+            Err(e) => Ok(Err(gcs_error("get bucket", e))),
+        }
+    }
+
+    async fn get_data(
+        &mut self,
+        container: Resource<String>,
+        name: ObjectName,
+        start: u64,
+        end: u64,
+    ) -> anyhow::Result<Result<Resource<IncomingValueHandle>, ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        let object = match backend.live_object(&container_name, &name, &policy).await {
+            Ok(Some(object)) => object,
+            Ok(None) => return Ok(Err(format!("object '{name}' does not exist"))),
+            Err(e) => return Ok(Err(gcs_error("get object", e))),
+        };
+
+        let (plaintext, size) = match encryptor(self) {
+            Some(encryptor) => {
+                match download_plaintext(&backend, &container_name, &name, &encryptor).await {
+                    Ok(data) => {
+                        let size = data.len() as u64;
+                        (Some(data), size)
+                    }
+                    Err(e) => return Ok(Err(gcs_error("download object", e))),
+                }
+            }
+            None => (None, object.size()),
+        };
+        let end = end.min(size);
+        let resource = self.table.push(IncomingValueHandle {
+            backend,
+            container: container_name,
+            object: name,
+            start: start.min(end),
+            end,
+            plaintext,
+        })?;
+        Ok(Ok(resource))
+    }
+
+    async fn write_data(
+        &mut self,
+        container: Resource<String>,
+        name: ObjectName,
+        data: Resource<OutgoingValueHandle>,
+    ) -> anyhow::Result<Result<(), ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+
+        // The upload happens on 'finish'
+        let handle = self.table.get_mut(&data)?;
+        handle.container = Some(container_name);
+        handle.object_name = Some(name);
+        Ok(Ok(()))
+    }
+
+    async fn list_objects(
+        &mut self,
+        container: Resource<String>,
+    ) -> anyhow::Result<Result<Resource<StreamObjectNamesHandle>, ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        let resource = self.table.push(StreamObjectNamesHandle {
+            backend,
+            container: container_name,
+            policy,
+            names: VecDeque::new(),
+            next_page_token: None,
+            exhausted: false,
+        })?;
+        Ok(Ok(resource))
+    }
+
+    async fn delete_object(
+        &mut self,
+        container: Resource<String>,
+        name: ObjectName,
+    ) -> anyhow::Result<Result<(), ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        match backend.check_mutable(&container_name, &name, &policy).await {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Ok(Err(e)),
+            Err(e) => return Ok(Err(gcs_error("get object", e))),
+        }
+
+        match backend.delete_object(&container_name, &name).await {
+            Ok(()) => Ok(Ok(())),
+            Err(e) => Ok(Err(gcs_error("delete object", e))),
+        }
+    }
+
+    async fn delete_objects(
+        &mut self,
+        container: Resource<String>,
+        names: Vec<ObjectName>,
+    ) -> anyhow::Result<Result<(), ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        for name in &names {
+            match backend.check_mutable(&container_name, name, &policy).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => return Ok(Err(e)),
+                Err(e) => return Ok(Err(gcs_error("get object", e))),
+            }
+        }
+        for name in names {
+            if let Err(e) = backend.delete_object(&container_name, &name).await {
+                return Ok(Err(gcs_error("delete object", e)));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    async fn has_object(
+        &mut self,
+        container: Resource<String>,
+        name: ObjectName,
+    ) -> anyhow::Result<Result<bool, ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        match backend.live_object(&container_name, &name, &policy).await {
+            Ok(object) => Ok(Ok(object.is_some())),
+            Err(e) => Ok(Err(gcs_error("get object", e))),
+        }
+    }
+
+    async fn object_info(
+        &mut self,
+        container: Resource<String>,
+        name: ObjectName,
+    ) -> anyhow::Result<Result<ObjectMetadata, ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        match backend.live_object(&container_name, &name, &policy).await {
+            Ok(Some(object)) => Ok(Ok(ObjectMetadata {
+                size: object.plaintext_size(encryptor(self).is_some()),
+                created_at: object.created_at(),
+                name: object.name,
+                container: container_name,
+            })),
+            Ok(None) => Ok(Err(format!("object '{name}' does not exist"))),
+            Err(e) => Ok(Err(gcs_error("get object", e))),
+        }
+    }
+
+    async fn clear(
+        &mut self,
+        container: Resource<String>,
+    ) -> anyhow::Result<Result<(), ContainerError>> {
+        let container_name = self.table.get(&container)?.clone();
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        let objects = match backend.all_objects(&container_name, &policy).await {
+            Ok(objects) => objects,
+            Err(e) => return Ok(Err(gcs_error("list objects", e))),
+        };
+        if let Some(object) = objects.first()
+            && let Err(e) = policy.check_mutable(&container_name, &object.name)
+        {
+            return Ok(Err(e));
+        }
+        for object in objects {
+            if let Err(e) = backend.delete_object(&container_name, &object.name).await {
+                return Ok(Err(gcs_error("delete object", e)));
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    async fn drop(&mut self, rep: Resource<String>) -> anyhow::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl bindings::wasi::blobstore::container::HostStreamObjectNames for Ctx {
+    async fn read_stream_object_names(
+        &mut self,
+        stream: Resource<StreamObjectNamesHandle>,
+        len: u64,
+    ) -> anyhow::Result<Result<(Vec<ObjectName>, bool), ContainerError>> {
+        let handle = self.table.get_mut(&stream)?;
+
+        let mut names = Vec::new();
+        while (names.len() as u64) < len {
+            if let Some(name) = handle.names.pop_front() {
+                names.push(name);
+                continue;
+            }
+            if handle.exhausted {
+                return Ok(Ok((names, true)));
+            }
+            let page = match handle
+                .backend
+                .list_objects(&handle.container, handle.next_page_token.as_deref())
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => return Ok(Err(gcs_error("list objects", e))),
+            };
+            handle.names.extend(
+                page.items
+                    .into_iter()
+                    .filter(|o| !o.is_expired(&handle.policy))
+                    .map(|o| o.name),
+            );
+            handle.exhausted = page.next_page_token.is_none();
+            handle.next_page_token = page.next_page_token;
+        }
+
+        let end = handle.exhausted && handle.names.is_empty();
+        Ok(Ok((names, end)))
+    }
+
+    async fn skip_stream_object_names(
+        &mut self,
+        stream: Resource<StreamObjectNamesHandle>,
+        num: u64,
+    ) -> anyhow::Result<Result<(u64, bool), ContainerError>> {
+        Ok(self
+            .read_stream_object_names(stream, num)
+            .await?
+            .map(|(names, end)| (names.len() as u64, end)))
+    }
+
+    async fn drop(&mut self, rep: Resource<StreamObjectNamesHandle>) -> anyhow::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl bindings::wasi::blobstore::types::HostOutgoingValue for Ctx {
+    async fn new_outgoing_value(&mut self) -> anyhow::Result<Resource<OutgoingValueHandle>> {
+        let temp_file = tempfile::Builder::new()
+            .tempfile()
+            .context("failed to create buffer file")?;
+
+        let resource = self.table.push(OutgoingValueHandle {
+            temp_file,
+            container: None,
+            object_name: None,
+        })?;
+        Ok(resource)
+    }
+
+    async fn outgoing_value_write_body(
+        &mut self,
+        outgoing_value: Resource<OutgoingValueHandle>,
+    ) -> anyhow::Result<Result<Resource<bindings::wasi::io0_2_1::streams::OutputStream>, ()>> {
+        let handle = self.table.get_mut(&outgoing_value)?;
+
+        let file = tokio::fs::File::from_std(handle.temp_file.reopen()?);
+        let stream: Box<dyn OutputStream> = Box::new(AsyncWriteStream::new(8192, file));
+
+        let resource = self.table.push(stream)?;
+        Ok(Ok(resource))
+    }
+
+    async fn finish(
+        &mut self,
+        outgoing_value: Resource<OutgoingValueHandle>,
+    ) -> anyhow::Result<Result<(), BlobstoreError>> {
+        let handle = self.table.delete(outgoing_value)?;
+        let (Some(container_name), Some(object_name)) = (handle.container, handle.object_name)
+        else {
+            return Ok(Err(
+                "outgoing value not associated with a container object".to_string()
+            ));
+        };
+        let (backend, policy) = backend_and_policy!(self, &container_name);
+
+        let size = handle.temp_file.as_file().metadata()?.len();
+        if let Err(e) = policy.check_size(&container_name, size) {
+            return Ok(Err(e));
+        }
+        match backend
+            .check_mutable(&container_name, &object_name, &policy)
+            .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Ok(Err(e)),
+            Err(e) => return Ok(Err(gcs_error("get object", e))),
+        }
+
+        let (body, size) = match encryptor(self) {
+            Some(encryptor) => {
+                let data = tokio::fs::read(handle.temp_file.path()).await?;
+                match encryptor.seal(&container_name, &object_name, &data).await {
+                    Ok(sealed) => {
+                        let size = sealed.len() as u64;
+                        let body = ThrottledReader::new(
+                            std::io::Cursor::new(sealed),
+                            self.bandwidth().clone(),
+                        );
+                        let body =
+                            reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(body));
+                        (body, size)
+                    }
+                    Err(e) => return Ok(Err(gcs_error("encrypt object", e))),
+                }
+            }
+            None => {
+                let file = tokio::fs::File::from_std(handle.temp_file.reopen()?);
+                let body = ThrottledReader::new(file, self.bandwidth().clone());
+                let body = reqwest::Body::wrap_stream(tokio_util::io::ReaderStream::new(body));
+                (body, size)
+            }
+        };
+        match backend
+            .upload(&container_name, &object_name, body, size, policy.immutable)
+            .await
+        {
+            Ok(true) => Ok(Ok(())),
+            // Another writer created the object since it was checked
+            Ok(false) => Ok(policy.check_mutable(&container_name, &object_name)),
+            Err(e) => Ok(Err(gcs_error("upload object", e))),
+        }
+    }
+
+    async fn drop(&mut self, rep: Resource<OutgoingValueHandle>) -> anyhow::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+impl bindings::wasi::blobstore::types::HostIncomingValue for Ctx {
+    async fn incoming_value_consume_sync(
+        &mut self,
+        incoming_value: Resource<IncomingValueHandle>,
+    ) -> anyhow::Result<Result<Vec<u8>, BlobstoreError>> {
+        let handle = self.table.delete(incoming_value)?;
+        if let Some(data) = handle.plaintext_range() {
+            self.bandwidth().throttle(data.len()).await;
+            return Ok(Ok(data.to_vec()));
+        }
+        let Some(range) = range_header(handle.start, handle.end) else {
+            return Ok(Ok(Vec::new()));
+        };
+        let response = match handle
+            .backend
+            .download(&handle.container, &handle.object, Some(range))
+            .await
+        {
+            Ok(response) => response,
+            Err(e) => return Ok(Err(gcs_error("download object", e))),
+        };
+        match response.bytes().await {
+            Ok(data) => {
+                self.bandwidth().throttle(data.len()).await;
+                Ok(Ok(data.to_vec()))
+            }
+            Err(e) => Ok(Err(format!("failed to read object data: {e}"))),
+        }
+    }
+
+    async fn incoming_value_consume_async(
+        &mut self,
+        incoming_value: Resource<IncomingValueHandle>,
+    ) -> anyhow::Result<
+        Result<Resource<bindings::wasi::blobstore::types::IncomingValueAsyncBody>, BlobstoreError>,
+    > {
+        let handle = self.table.delete(incoming_value)?;
+        let stream: Box<dyn InputStream> = match (
+            handle.plaintext_range(),
+            range_header(handle.start, handle.end),
+        ) {
+            (Some(data), _) => {
+                self.bandwidth().throttle(data.len()).await;
+                Box::new(MemoryInputPipe::new(data))
+            }
+            (None, Some(range)) => {
+                let response = match handle
+                    .backend
+                    .download(&handle.container, &handle.object, Some(range))
+                    .await
+                {
+                    Ok(response) => response,
+                    Err(e) => return Ok(Err(gcs_error("download object", e))),
+                };
+                // The body is streamed from GCS as the component reads it
+                let body = tokio_util::io::StreamReader::new(futures::TryStreamExt::map_err(
+                    Box::pin(response.bytes_stream()),
+                    std::io::Error::other,
+                ));
+                Box::new(AsyncReadStream::new(ThrottledReader::new(
+                    body,
+                    self.bandwidth().clone(),
+                )))
+            }
+            (None, None) => Box::new(wasmtime_wasi::p2::pipe::ClosedInputStream),
+        };
+
+        let stream = self.table.push(stream)?;
+        Ok(Ok(stream))
+    }
+
+    async fn size(&mut self, incoming_value: Resource<IncomingValueHandle>) -> anyhow::Result<u64> {
+        Ok(self.table.get(&incoming_value)?.size())
+    }
+
+    async fn drop(&mut self, rep: Resource<IncomingValueHandle>) -> anyhow::Result<()> {
+        self.table.delete(rep)?;
+        Ok(())
+    }
+}
+
+// Implement the main types Host trait that combines all resource types
+impl bindings::wasi::blobstore::types::Host for Ctx {}
+
+// Implement the main container Host trait that combines all resource types
+impl bindings::wasi::blobstore::container::Host for Ctx {}
+
+#[async_trait::async_trait]
+impl HostPlugin for WasiBlobstoreGcs {
+    fn id(&self) -> &'static str {
+        WASI_BLOBSTORE_ID
+    }
+
+    fn world(&self) -> WitWorld {
+        WitWorld {
+            imports: HashSet::from([WitInterface::from(
+                "wasi:blobstore/blobstore,container,types@0.2.0-draft",
+            )]),
+            ..Default::default()
+        }
+    }
+
+    async fn on_component_bind(
+        &self,
+        component: &mut WorkloadComponent,
+        interfaces: HashSet<WitInterface>,
+    ) -> anyhow::Result<()> {
+        if !interfaces
+            .iter()
+            .any(|i| i.namespace == "wasi" && i.package == "blobstore")
+        {
+            return Ok(());
+        }
+
+        tracing::debug!(
+            workload_id = component.workload_id(),
+            component_id = component.id(),
+            "Adding GCS blobstore interfaces"
+        );
+        let linker = component.linker();
+        bindings::wasi::blobstore::blobstore::add_to_linker::<_, HasSelf<Ctx>>(linker, |ctx| ctx)?;
+        bindings::wasi::blobstore::container::add_to_linker::<_, HasSelf<Ctx>>(linker, |ctx| ctx)?;
+        bindings::wasi::blobstore::types::add_to_linker::<_, HasSelf<Ctx>>(linker, |ctx| ctx)?;
+
+        Ok(())
+    }
+
+    fn config_schema(&self) -> Option<ConfigSchema> {
+        Some(ContainerPolicies::with_schema_fields(BackendProfiles::<
+            GcsBackend,
+        >::with_schema_field(
+            ConfigSchema::new().with_field(
+                "buckets",
+                ConfigValueKind::List,
+                "GCS buckets the workload may access, defaults to every bucket in the project",
+            ),
+        )))
+    }
+
+    async fn on_workload_bind(
+        &self,
+        workload: &UnresolvedWorkload,
+        interfaces: HashSet<WitInterface>,
+    ) -> anyhow::Result<()> {
+        let Some(interface) = interfaces
+            .iter()
+            .find(|i| i.namespace == "wasi" && i.package == "blobstore")
+        else {
+            return Ok(());
+        };
+
+        let buckets = interface.config.get("buckets").map(|buckets| {
+            buckets
+                .split(',')
+                .map(str::trim)
+                .filter(|b| !b.is_empty())
+                .map(String::from)
+                .collect()
+        });
+        let policies = ContainerPolicies::from_config(&interface.config)
+            .context("invalid blobstore container policies")?;
+        let backend = self.profiles.select(&interface.config)?;
+
+        self.workloads.write().await.insert(
+            workload.id().to_string(),
+            WorkloadData {
+                backend,
+                buckets,
+                policies,
+            },
+        );
+        Ok(())
+    }
+
+    async fn on_workload_unbind(
+        &self,
+        workload_id: &str,
+        _interfaces: HashSet<WitInterface>,
+    ) -> anyhow::Result<()> {
+        self.workloads.write().await.remove(workload_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend() -> GcsBackend {
+        GcsBackend::with_endpoint(
+            "project",
+            GcsAuth::WorkloadIdentity,
+            "http://localhost:4443/",
+        )
+        .expect("endpoint is valid")
+    }
+
+    #[test]
+    fn test_object_urls_are_encoded() {
+        let url = backend().url(&["storage", "v1", "b", "bucket", "o", "dir/file name.txt"]);
+        assert_eq!(
+            url.as_str(),
+            "http://localhost:4443/storage/v1/b/bucket/o/dir%2Ffile%20name.txt"
+        );
+    }
+
+    #[test]
+    fn test_invalid_endpoint() {
+        assert!(
+            GcsBackend::with_endpoint("project", GcsAuth::WorkloadIdentity, "data:text").is_err()
+        );
+    }
+
+    #[test]
+    fn test_range_header() {
+        assert_eq!(range_header(0, 10).as_deref(), Some("bytes=0-9"));
+        assert_eq!(range_header(5, 6).as_deref(), Some("bytes=5-5"));
+        assert_eq!(range_header(5, 5), None);
+    }
+
+    #[test]
+    fn test_lifecycle_age_days() {
+        assert_eq!(lifecycle_age_days(Duration::from_secs(60)), 1);
+        assert_eq!(lifecycle_age_days(Duration::from_secs(24 * 60 * 60)), 1);
+        assert_eq!(lifecycle_age_days(Duration::from_secs(24 * 60 * 60 + 1)), 2);
+    }
+
+    #[test]
+    fn test_object_resource() -> anyhow::Result<()> {
+        let object: ObjectResource = serde_json::from_str(
+            r#"{"name":"a.txt","size":"42","timeCreated":"2024-01-01T00:00:00.000Z"}"#,
+        )?;
+        assert_eq!(object.size(), 42);
+        assert_eq!(object.created_at(), 1_704_067_200);
+
+        let policy = ContainerPolicy {
+            ttl: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        assert!(object.is_expired(&policy));
+        assert!(!object.is_expired(&ContainerPolicy::default()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_container_access() -> anyhow::Result<()> {
+        let plugin = WasiBlobstoreGcs::new(backend()).with_profile(
+            "other-project",
+            GcsBackend::with_endpoint(
+                "other-project",
+                GcsAuth::WorkloadIdentity,
+                "http://localhost:4443/",
+            )?,
+        )?;
+        let config = HashMap::from([(
+            crate::plugin::profiles::PROFILE_CONFIG_KEY.to_string(),
+            "other-project".to_string(),
+        )]);
+        plugin.workloads.write().await.insert(
+            "workload".to_string(),
+            WorkloadData {
+                backend: plugin.profiles.select(&config)?,
+                buckets: Some(HashSet::from(["allowed".to_string()])),
+                policies: ContainerPolicies::default(),
+            },
+        );
+
+        let (backend, _) = plugin
+            .container_access("workload", "allowed")
+            .await
+            .expect("bucket is allowed");
+        assert_eq!(backend.project, "other-project");
+        assert!(plugin.container_access("workload", "other").await.is_err());
+
+        let (backend, _) = plugin
+            .container_access("unbound", "any")
+            .await
+            .expect("unbound workloads use the default backend");
+        assert_eq!(backend.project, "project");
+        Ok(())
+    }
+}
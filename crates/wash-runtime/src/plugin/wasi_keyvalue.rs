@@ -550,4 +550,97 @@ mod tests {
         assert!(results[0].is_some());
         assert!(results[1].is_none());
     }
+
+    #[tokio::test]
+    async fn test_batch_operations() -> anyhow::Result<()> {
+        use bindings::wasi::keyvalue::batch::Host as _;
+        use bindings::wasi::keyvalue::store::{Host as _, HostBucket as _};
+
+        let plugin: Arc<dyn HostPlugin + Send + Sync> = Arc::new(WasiKeyvalue::new());
+        let mut ctx = Ctx::builder("workload", "component")
+            .with_plugins(HashMap::from([(WASI_KEYVALUE_ID, plugin)]))
+            .build();
+
+        let bucket = ctx.open("bucket".to_string()).await?.expect("bucket opens");
+        let bucket_rep = || Resource::<BucketHandle>::new_borrow(bucket.rep());
+
+        ctx.set_many(
+            bucket_rep(),
+            vec![
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec()),
+            ],
+        )
+        .await?
+        .expect("set_many succeeds");
+        let values = ctx
+            .get_many(bucket_rep(), vec!["b".to_string(), "missing".to_string()])
+            .await?
+            .expect("get_many succeeds");
+        assert_eq!(values, vec![Some(("b".to_string(), b"2".to_vec())), None]);
+
+        ctx.delete_many(bucket_rep(), vec!["a".to_string(), "missing".to_string()])
+            .await?
+            .expect("delete_many succeeds");
+        let exists = ctx.exists(bucket_rep(), "a".to_string()).await?;
+        assert!(!exists.expect("exists succeeds"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_draft2_component() -> anyhow::Result<()> {
+        use bindings::wasi::keyvalue::store::{Host as _, HostBucket as _};
+
+        // Built with `wit-component` against the upstream `wasi:keyvalue@0.2.0-draft2` WIT,
+        // `run` opens the `default` bucket and sets `counter` to `default`
+        const KEYVALUE_DRAFT2_WASM: &[u8] =
+            include_bytes!("../../tests/fixtures/keyvalue_draft2.wasm");
+
+        let linker = |engine: &crate::engine::Engine| -> anyhow::Result<_> {
+            let mut linker = wasmtime::component::Linker::<Ctx>::new(engine.inner());
+            bindings::wasi::keyvalue::store::add_to_linker::<_, HasSelf<Ctx>>(
+                &mut linker,
+                |ctx| ctx,
+            )?;
+            Ok(linker)
+        };
+
+        let engine = crate::engine::Engine::builder()
+            .with_pooling_allocator(false)
+            .build()?;
+
+        let plugin: Arc<dyn HostPlugin + Send + Sync> = Arc::new(WasiKeyvalue::new());
+        let ctx = Ctx::builder("workload", "component")
+            .with_plugins(HashMap::from([(WASI_KEYVALUE_ID, plugin)]))
+            .build();
+        let mut store = wasmtime::Store::new(engine.inner(), ctx);
+        let component = engine.compile_component(KEYVALUE_DRAFT2_WASM)?;
+        let instance = linker(&engine)?
+            .instantiate_async(&mut store, &component)
+            .await?;
+        instance
+            .get_typed_func::<(), ()>(&mut store, "run")?
+            .call_async(&mut store, ())
+            .await?;
+
+        let ctx = store.data_mut();
+        let bucket = ctx
+            .open("default".to_string())
+            .await?
+            .expect("bucket opens");
+        let value = ctx
+            .get(Resource::new_borrow(bucket.rep()), "counter".to_string())
+            .await?
+            .expect("get succeeds");
+        assert_eq!(value, Some(b"default".to_vec()));
+
+        // Without the adapter the draft2 import doesn't resolve
+        let engine = crate::engine::Engine::builder()
+            .with_pooling_allocator(false)
+            .with_version_adapters(false)
+            .build()?;
+        let component = engine.compile_component(KEYVALUE_DRAFT2_WASM)?;
+        assert!(linker(&engine)?.instantiate_pre(&component).is_err());
+        Ok(())
+    }
 }
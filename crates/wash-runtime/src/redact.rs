@@ -0,0 +1,199 @@
+//! Redaction of config values sourced from secret backends.
+//!
+//! A workload lists the config keys whose values the operator resolved from a secret
+//! backend in [`Workload::secret_config_keys`]. While the workload runs, the values of those
+//! keys in its component, service and host interface config and environment are registered
+//! here, and the host replaces them with [`REDACTED`] in the errors and status messages it
+//! returns. Hosts that format their own tracing output wrap its writer in a
+//! [`RedactingWriter`] so the values never reach the logs either.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::{LazyLock, RwLock};
+
+use crate::host::error::HostError;
+use crate::types::Workload;
+
+/// The text secret values are replaced with.
+pub const REDACTED: &str = "[REDACTED]";
+
+/// The secret values of each running workload, keyed by workload ID.
+static SECRETS: LazyLock<RwLock<HashMap<String, Vec<String>>>> = LazyLock::new(Default::default);
+
+/// Returns the values of a workload's secret config keys.
+pub fn secret_values(workload: &Workload) -> Vec<String> {
+    if workload.secret_config_keys.is_empty() {
+        return Vec::new();
+    }
+    let resources = workload
+        .service
+        .iter()
+        .map(|service| &service.local_resources)
+        .chain(workload.components.iter().map(|c| &c.local_resources));
+    let maps = resources
+        .flat_map(|resources| [&resources.config, &resources.environment])
+        .chain(workload.host_interfaces.iter().map(|i| &i.config));
+
+    let mut values: Vec<String> = maps
+        .flat_map(|map| map.iter())
+        .filter(|(key, value)| !value.is_empty() && workload.secret_config_keys.contains(*key))
+        .map(|(_, value)| value.clone())
+        .collect();
+    values.sort_unstable();
+    values.dedup();
+    values
+}
+
+/// Registers the secret values of a workload, to be redacted until it's unregistered.
+pub fn register(workload_id: &str, values: Vec<String>) {
+    let mut secrets = SECRETS.write().unwrap_or_else(|e| e.into_inner());
+    if values.is_empty() {
+        secrets.remove(workload_id);
+    } else {
+        secrets.insert(workload_id.to_string(), values);
+    }
+}
+
+/// Unregisters the secret values of a workload.
+pub fn unregister(workload_id: &str) {
+    SECRETS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(workload_id);
+}
+
+/// Replaces every registered secret value in `text` with [`REDACTED`].
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.read().unwrap_or_else(|e| e.into_inner());
+    let mut values: Vec<&str> = secrets
+        .values()
+        .flatten()
+        .map(String::as_str)
+        .filter(|value| text.contains(value))
+        .collect();
+    if values.is_empty() {
+        return Cow::Borrowed(text);
+    }
+    // Longer values first, so a secret containing another is replaced whole
+    values.sort_unstable_by_key(|value| std::cmp::Reverse(value.len()));
+    let mut redacted = text.to_string();
+    for value in values {
+        redacted = redacted.replace(value, REDACTED);
+    }
+    Cow::Owned(redacted)
+}
+
+/// Redacts an error, keeping its whole chain of causes in the message, and its
+/// [`HostError`] if it has one.
+pub fn redact_error(error: anyhow::Error) -> anyhow::Error {
+    let message = format!("{error:#}");
+    match (redact(&message), HostError::of(&error)) {
+        (Cow::Borrowed(_), _) => error,
+        (Cow::Owned(redacted), HostError::Internal) => anyhow::Error::msg(redacted),
+        (Cow::Owned(redacted), cause) => anyhow::Error::new(cause).context(redacted),
+    }
+}
+
+/// A writer that redacts registered secret values from what's written to it.
+///
+/// Tracing formatters write each event in a single call, so a value is never split across
+/// writes.
+#[derive(Debug)]
+pub struct RedactingWriter<W> {
+    inner: W,
+}
+
+impl<W: Write> RedactingWriter<W> {
+    /// Wraps `inner`.
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match std::str::from_utf8(buf) {
+            Ok(text) => self.inner.write_all(redact(text).as_bytes())?,
+            Err(_) => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Component, LocalResources};
+    use crate::wit::WitInterface;
+
+    fn workload() -> Workload {
+        let mut interface = WitInterface::from("wasi:blobstore/blobstore");
+        interface
+            .config
+            .insert("token".to_string(), "gcs-token-123".to_string());
+        Workload {
+            namespace: "test".to_string(),
+            name: "secrets".to_string(),
+            annotations: HashMap::new(),
+            secret_config_keys: ["api_key", "DB_PASSWORD", "token"].map(String::from).into(),
+            service: None,
+            components: vec![Component {
+                local_resources: LocalResources {
+                    config: HashMap::from([
+                        ("api_key".to_string(), "sk-live-abc".to_string()),
+                        ("region".to_string(), "eu-west-1".to_string()),
+                    ]),
+                    environment: HashMap::from([(
+                        "DB_PASSWORD".to_string(),
+                        "hunter2hunter2".to_string(),
+                    )]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            }],
+            host_interfaces: vec![interface],
+            volumes: vec![],
+        }
+    }
+
+    #[test]
+    fn test_secret_values() {
+        assert_eq!(
+            secret_values(&workload()),
+            ["gcs-token-123", "hunter2hunter2", "sk-live-abc"]
+        );
+    }
+
+    #[test]
+    fn test_redact() -> anyhow::Result<()> {
+        let workload_id = uuid::Uuid::new_v4().to_string();
+        register(&workload_id, secret_values(&workload()));
+
+        let text = "connecting with key sk-live-abc to eu-west-1";
+        assert_eq!(redact(text), "connecting with key [REDACTED] to eu-west-1");
+        assert!(matches!(redact("nothing secret"), Cow::Borrowed(_)));
+
+        let error =
+            anyhow::anyhow!("password hunter2hunter2 rejected").context("failed to connect");
+        assert_eq!(
+            redact_error(error).to_string(),
+            "failed to connect: password [REDACTED] rejected"
+        );
+
+        let error = anyhow::anyhow!("key sk-live-abc is invalid").context(HostError::PolicyDenied);
+        assert_eq!(HostError::of(&redact_error(error)), HostError::PolicyDenied);
+
+        let mut output = Vec::new();
+        write!(RedactingWriter::new(&mut output), "token=gcs-token-123")?;
+        assert_eq!(output, b"token=[REDACTED]");
+
+        unregister(&workload_id);
+        assert_eq!(redact(text), text);
+        Ok(())
+    }
+}
@@ -4,20 +4,45 @@
 //!
 //! ## Public API Types (used in [`crate::host::HostApi`])
 //! - Request/Response types: [`WorkloadStartRequest`], [`WorkloadStartResponse`],
+//!   [`WorkloadValidateRequest`], [`WorkloadValidateResponse`], [`WorkloadDiagnostic`],
+//!   [`WorkloadLintRequest`], [`WorkloadLintResponse`], [`WorkloadWarning`],
 //!   [`WorkloadStatusRequest`], [`WorkloadStatusResponse`],
-//!   [`WorkloadStopRequest`], [`WorkloadStopResponse`]
-//! - Host information: [`HostHeartbeat`]
+//!   [`WorkloadStopRequest`], [`WorkloadStopResponse`],
+//!   [`WorkloadListRequest`], [`WorkloadListResponse`], [`WorkloadSummary`],
+//!   [`WorkloadStopSelectedRequest`], [`WorkloadStopSelectedResponse`],
+//!   [`ComponentInspectRequest`], [`ComponentInspectResponse`],
+//!   [`ComponentDiffRequest`], [`ComponentDiffResponse`], [`WitChange`],
+//!   [`WorkloadInventoryRequest`], [`WorkloadInventoryResponse`], [`WorkloadInventory`],
+//!   [`ComponentInventory`], [`ComponentClaims`], [`ComponentsListRequest`],
+//!   [`ComponentsListResponse`], [`LoadedComponent`],
+//!   [`WorkloadEgressRequest`], [`WorkloadEgressResponse`],
+//!   [`WorkloadUsageRequest`], [`WorkloadUsageResponse`], [`InstanceUsage`],
+//!   [`Percentiles`], [`WorkloadInstancesRequest`], [`WorkloadInstancesResponse`],
+//!   [`InstanceInfo`], [`WorkloadPauseRequest`], [`WorkloadPauseResponse`],
+//!   [`WorkloadResumeRequest`], [`WorkloadResumeResponse`], [`WorkloadScaleRequest`],
+//!   [`WorkloadScaleResponse`], [`WorkloadUpdateResourcesRequest`],
+//!   [`WorkloadUpdateResourcesResponse`], [`LocalResourcesUpdate`],
+//!   [`WorkloadInvokeRequest`], [`WorkloadInvokeResponse`], [`InvokeValues`],
+//!   [`HttpTrafficSplitRequest`], [`HttpTrafficSplitResponse`],
+//!   [`ApiKeyCreateRequest`], [`ApiKeyCreateResponse`], [`ApiKeyRotateRequest`],
+//!   [`ApiKeyRotateResponse`], [`ApiKeyRevokeRequest`], [`ApiKeyRevokeResponse`]
+//! - Host information: [`HostHeartbeat`], [`PluginHealth`]
+//! - Egress auditing: [`EgressRecord`], [`EgressKind`], [`EgressOutcome`],
+//!   [`DestinationMetrics`]
 //!
 //! ## Core Workload Types (used internally)
 //! - Workload definition: [`Workload`], [`WorkloadState`], [`WorkloadStatus`]
-//! - Component configuration: [`Component`], [`Service`], [`LocalResources`]
+//! - Component configuration: [`Component`], [`Service`], [`LocalResources`],
+//!   [`ComponentSource`]
 //! - Volume management: [`Volume`], [`VolumeType`], [`VolumeMount`],
-//!   [`EmptyDirVolume`], [`HostPathVolume`]
+//!   [`EmptyDirVolume`], [`HostPathVolume`], [`ScratchVolume`], [`BlobstoreVolume`]
 
 use bytes::Bytes;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+use wasmtime::component::Val;
 
-use crate::wit::WitInterface;
+use crate::wit::{WitInterface, WitWorld};
 
 /// Represents a deployable workload containing one or more WebAssembly components.
 /// A workload defines the complete runtime configuration including components,
@@ -27,6 +52,9 @@ pub struct Workload {
     pub namespace: String,
     pub name: String,
     pub annotations: HashMap<String, String>,
+    /// Config keys whose values were resolved from a secret backend. Their values in the
+    /// config and environment of the workload are redacted, see [`crate::redact`].
+    pub secret_config_keys: HashSet<String>,
     pub service: Option<Service>,
     pub components: Vec<Component>,
     pub host_interfaces: Vec<WitInterface>,
@@ -42,15 +70,198 @@ pub enum WorkloadState {
     Completed,
     Stopping,
     Error,
+    /// The workload is running, but a plugin it is bound to is not healthy
+    Degraded,
 }
 
 /// Configuration for a long-running service component that handles requests.
 /// Services can be restarted if they fail and have resource limits.
+///
+/// A service with a `name` also publishes the workload to other workloads on the host.
+/// The interfaces listed in `exports` must be exported by the workload's components, and
+/// other workloads in the same namespace import them by adding a host interface whose
+/// config sets `service` to the name (see [`crate::host::services`]). Workloads that
+/// share a service name are instances of the same service, and calls are balanced
+/// across them.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Service {
     pub bytes: Bytes,
     pub local_resources: LocalResources,
     pub max_restarts: u64,
+    /// Stable name other workloads use to reference this service
+    pub name: Option<String>,
+    /// Interfaces published to other workloads when `name` is set
+    pub exports: Vec<WitInterface>,
+    /// Runs the service to completion as a `wasi:cli/run` command, e.g. a batch job
+    pub command: Option<Command>,
+    /// Where the service's bytes were pulled from, if they came from a registry
+    pub source: Option<ComponentSource>,
+}
+
+/// Options for a service that runs to completion instead of for the lifetime of its
+/// workload. The command's arguments are the service's [`LocalResources::arguments`].
+///
+/// Its stdout and stderr are captured rather than inherited from the host, and once it
+/// exits the workload's status reports [`WorkloadState::Completed`] with a zero exit code or
+/// [`WorkloadState::Error`] otherwise, along with the [`CommandResult`]. A command that
+/// fails is run again up to the service's `max_restarts` times.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Command {
+    /// Bytes the command reads from stdin
+    pub stdin: Bytes,
+    /// Runs the command as a [`Job`] instead of once
+    pub job: Option<Job>,
+    /// Starts a [`Job`] of the command on a schedule, using `job` or the default job
+    pub cron_job: Option<CronJob>,
+}
+
+/// A command run until it has succeeded a number of times, similar to a Kubernetes Job.
+///
+/// Up to `parallelism` runs of the command are active at once, until `completions` of them
+/// have exited with a zero exit code and the job has [`JobState::Succeeded`]. The job has
+/// [`JobState::Failed`] once more than `backoff_limit` runs have failed or it has been active
+/// for longer than `active_deadline`. A failed run is replaced after `backoff`, doubled after
+/// every failure up to [`Job::MAX_BACKOFF`]. The service's `max_restarts` doesn't apply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Job {
+    /// Runs that must succeed for the job to succeed
+    pub completions: u32,
+    /// Runs that may be active at once
+    pub parallelism: u32,
+    /// Failed runs tolerated before the job fails
+    pub backoff_limit: u32,
+    /// Delay before replacing the first failed run
+    pub backoff: Duration,
+    /// How long the job may be active before it fails and its runs are stopped
+    pub active_deadline: Option<Duration>,
+}
+
+impl Job {
+    /// The longest delay before replacing a failed run.
+    pub const MAX_BACKOFF: Duration = Duration::from_secs(6 * 60);
+}
+
+impl Default for Job {
+    fn default() -> Self {
+        Self {
+            completions: 1,
+            parallelism: 1,
+            backoff_limit: 6,
+            backoff: Duration::from_secs(10),
+            active_deadline: None,
+        }
+    }
+}
+
+/// The state of a [`Job`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    /// The job still has runs to complete
+    #[default]
+    Active,
+    /// The job completed all its runs
+    Succeeded,
+    /// Too many runs failed or the job passed its deadline
+    Failed,
+}
+
+/// The progress of a [`Job`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct JobStatus {
+    pub state: JobState,
+    /// Runs currently active
+    pub active: u32,
+    /// Runs that exited with a zero exit code
+    pub succeeded: u32,
+    /// Runs that exited with a non-zero exit code or trapped
+    pub failed: u32,
+    /// Why the job failed, empty otherwise
+    pub message: String,
+}
+
+/// Starts a [`Job`] on a schedule, similar to a Kubernetes CronJob.
+///
+/// A command with a cron job doesn't complete: its workload keeps running and reports a
+/// [`CronJobStatus`] until it's stopped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronJob {
+    /// When to start jobs, as a cron expression in UTC, see [`crate::engine::cron`]
+    pub schedule: String,
+    /// What to do when a job is due while an earlier one is still active
+    pub concurrency_policy: ConcurrencyPolicy,
+    /// Finished jobs that succeeded to keep in the status
+    pub successful_jobs_history_limit: u32,
+    /// Finished jobs that failed to keep in the status
+    pub failed_jobs_history_limit: u32,
+    /// How late a job may start after its scheduled time. Jobs that would start later,
+    /// e.g. after host downtime, are skipped.
+    pub starting_deadline: Option<Duration>,
+    /// When a job was last scheduled, as reported in the [`CronJobStatus`] of an earlier
+    /// instance of the workload. When set, the most recent job missed since then is started
+    /// right away if it's still within the starting deadline.
+    pub last_schedule_time: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Default for CronJob {
+    fn default() -> Self {
+        Self {
+            schedule: String::new(),
+            concurrency_policy: ConcurrencyPolicy::default(),
+            successful_jobs_history_limit: 3,
+            failed_jobs_history_limit: 1,
+            starting_deadline: None,
+            last_schedule_time: None,
+        }
+    }
+}
+
+/// What a [`CronJob`] does when a job is due while an earlier one is still active.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConcurrencyPolicy {
+    /// Start the new job alongside the active ones
+    #[default]
+    Allow,
+    /// Skip the new job
+    Forbid,
+    /// Stop the active jobs and start the new one
+    Replace,
+}
+
+/// A job started by a [`CronJob`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronJobRun {
+    /// The time the job was scheduled for
+    pub scheduled_at: chrono::DateTime<chrono::Utc>,
+    /// When the job finished, unset while it's active
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub status: JobStatus,
+    /// The result of the job's last run, once it has finished
+    pub result: Option<CommandResult>,
+}
+
+/// The progress of a [`CronJob`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CronJobStatus {
+    /// When a job was last scheduled, whether or not it was started
+    pub last_schedule_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// When a job last succeeded
+    pub last_successful_time: Option<chrono::DateTime<chrono::Utc>>,
+    /// The jobs that are active
+    pub active: Vec<CronJobRun>,
+    /// Finished jobs within the history limits, most recent first
+    pub history: Vec<CronJobRun>,
+}
+
+/// The outcome of a [`Command`] that ran to completion.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CommandResult {
+    /// The exit code, `0` on success
+    pub exit_code: i32,
+    /// Everything the command wrote to stdout
+    pub stdout: Bytes,
+    /// Everything the command wrote to stderr, followed by the error it failed with if it
+    /// trapped
+    pub stderr: Bytes,
 }
 
 /// A WebAssembly component that can be executed as part of a workload.
@@ -61,6 +272,19 @@ pub struct Component {
     pub local_resources: LocalResources,
     pub pool_size: i32,
     pub max_invocations: i32,
+    /// Where the component's bytes were pulled from, if they came from a registry
+    pub source: Option<ComponentSource>,
+}
+
+/// The OCI image a component or service was pulled from.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ComponentSource {
+    /// The image reference, e.g. `ghcr.io/wasmcloud/components/http-hello-world:0.1.0`
+    pub image: String,
+    /// The digest of the image manifest
+    pub digest: String,
+    /// The annotations of the image manifest, e.g. `org.opencontainers.image.source`
+    pub annotations: HashMap<String, String>,
 }
 
 /// Resource limits and configuration for a component or service.
@@ -77,6 +301,79 @@ pub struct LocalResources {
     pub environment: HashMap<String, String>,
     pub volume_mounts: Vec<VolumeMount>,
     pub allowed_hosts: Vec<String>,
+    /// wasi:cli/environment arguments, starting with the program name. Empty leaves argv empty.
+    pub arguments: Vec<String>,
+    /// Host environment variables the component inherits, by name or by prefix with a
+    /// trailing `*` such as `AWS_*`. Values in `environment` take precedence.
+    pub inherited_environment: Vec<String>,
+}
+
+/// Changes to a component's [`LocalResources`] on a running workload, leaving those that
+/// are `None` or not listed as they are.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LocalResourcesUpdate {
+    pub memory_limit_mb: Option<i32>,
+    pub cpu_limit: Option<i32>,
+    pub allowed_hosts: Option<Vec<String>>,
+    /// Config entries to set, or to remove where the value is `None`
+    pub config: HashMap<String, Option<String>>,
+    /// Environment variables to set, or to remove where the value is `None`
+    pub environment: HashMap<String, Option<String>>,
+}
+
+impl LocalResourcesUpdate {
+    /// Applies the changes to `resources`.
+    pub fn apply(&self, resources: &mut LocalResources) {
+        if let Some(memory_limit_mb) = self.memory_limit_mb {
+            resources.memory_limit_mb = memory_limit_mb;
+        }
+        if let Some(cpu_limit) = self.cpu_limit {
+            resources.cpu_limit = cpu_limit;
+        }
+        if let Some(allowed_hosts) = &self.allowed_hosts {
+            resources.allowed_hosts = allowed_hosts.clone();
+        }
+        for (map, patch) in [
+            (&mut resources.config, &self.config),
+            (&mut resources.environment, &self.environment),
+        ] {
+            for (key, value) in patch {
+                match value {
+                    Some(value) => map.insert(key.clone(), value.clone()),
+                    None => map.remove(key),
+                };
+            }
+        }
+    }
+}
+
+impl LocalResources {
+    /// Returns the environment variables of the component: the variables of `host_env`
+    /// allowed by [`Self::inherited_environment`], overridden by [`Self::environment`].
+    pub fn guest_environment(
+        &self,
+        host_env: impl IntoIterator<Item = (String, String)>,
+    ) -> Vec<(String, String)> {
+        let mut env: HashMap<String, String> = host_env
+            .into_iter()
+            .filter(|(name, _)| {
+                self.inherited_environment
+                    .iter()
+                    .any(|allowed| match allowed.strip_suffix('*') {
+                        Some(prefix) => name.starts_with(prefix),
+                        None => name == allowed,
+                    })
+            })
+            .collect();
+        env.extend(
+            self.environment
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        );
+        let mut env: Vec<_> = env.into_iter().collect();
+        env.sort_unstable();
+        env
+    }
 }
 
 impl Default for LocalResources {
@@ -88,6 +385,8 @@ impl Default for LocalResources {
             environment: HashMap::new(),
             volume_mounts: Vec::new(),
             allowed_hosts: Vec::new(),
+            arguments: Vec::new(),
+            inherited_environment: Vec::new(),
         }
     }
 }
@@ -99,11 +398,14 @@ pub struct Volume {
     pub volume_type: VolumeType,
 }
 
-/// The type of volume - either host path or empty directory.
+/// The type of volume - a host path, an empty directory, scratch space or a blobstore
+/// container.
 #[derive(Debug, Clone, PartialEq)]
 pub enum VolumeType {
     HostPath(HostPathVolume),
     EmptyDir(EmptyDirVolume),
+    Scratch(ScratchVolume),
+    Blobstore(BlobstoreVolume),
 }
 
 /// Describes how a volume should be mounted into a component.
@@ -112,12 +414,33 @@ pub struct VolumeMount {
     pub name: String,
     pub mount_path: String,
     pub read_only: bool,
+    /// The most bytes the mounted directory may hold while writable, or `None` for no
+    /// quota, see [`crate::engine::mounts`]
+    pub quota_bytes: Option<u64>,
 }
 
 /// An ephemeral empty directory volume that exists for the lifetime of the workload.
 #[derive(Debug, Clone, PartialEq)]
 pub struct EmptyDirVolume {}
 
+/// Scratch space that each instance gets a new, empty directory of, removed when the
+/// instance is dropped, see [`crate::engine::scratch`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ScratchVolume {
+    /// The most bytes the instance may store in the directory, or `None` for no limit
+    pub size_limit_bytes: Option<u64>,
+}
+
+/// A blobstore container downloaded into a directory when the workload starts, see
+/// [`crate::host::blob_volumes`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BlobstoreVolume {
+    pub container: String,
+    /// Whether files components write are put back into the container. Otherwise the
+    /// volume is mounted read-only.
+    pub sync_writes: bool,
+}
+
 /// A volume that mounts a directory from the host filesystem.
 #[derive(Debug, Clone, PartialEq)]
 pub struct HostPathVolume {
@@ -147,6 +470,34 @@ pub struct HostHeartbeat {
     pub workload_count: u64,
     pub imports: Vec<WitInterface>,
     pub exports: Vec<WitInterface>,
+    /// Health of each plugin registered with the host, keyed by plugin ID
+    pub plugin_health: HashMap<String, PluginHealth>,
+}
+
+/// The health of a plugin as reported by [`crate::plugin::HostPlugin::health`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum PluginHealth {
+    #[default]
+    Healthy,
+    /// The plugin is operational but impaired, e.g. its backend is responding slowly
+    Degraded(String),
+    /// The plugin cannot serve requests, e.g. its backend is unreachable
+    Unhealthy(String),
+}
+
+impl PluginHealth {
+    /// Returns `true` if the plugin is fully healthy.
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, PluginHealth::Healthy)
+    }
+
+    /// Returns the reason the plugin is not healthy, if any.
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            PluginHealth::Healthy => None,
+            PluginHealth::Degraded(message) | PluginHealth::Unhealthy(message) => Some(message),
+        }
+    }
 }
 
 /// Status information about a workload including its ID, state, and any messages.
@@ -155,6 +506,13 @@ pub struct WorkloadStatus {
     pub workload_id: String,
     pub workload_state: WorkloadState,
     pub message: String,
+    /// The result of the workload's [`Command`], once it has run to completion. For a
+    /// [`Job`], the result of its last run.
+    pub command_result: Option<CommandResult>,
+    /// The progress of the workload's [`Job`], if its command runs as one
+    pub job_status: Option<JobStatus>,
+    /// The progress of the workload's [`CronJob`], if its command has one
+    pub cron_job_status: Option<CronJobStatus>,
 }
 
 /// Request to start a new workload on the host.
@@ -170,6 +528,51 @@ pub struct WorkloadStartResponse {
     pub workload_status: WorkloadStatus,
 }
 
+/// Request to check that a workload would start on the host, without starting it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadValidateRequest {
+    pub workload: Workload,
+}
+
+/// The problems that would keep a workload from starting.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkloadValidateResponse {
+    /// Every problem found, empty if the workload would start
+    pub diagnostics: Vec<WorkloadDiagnostic>,
+}
+
+/// A problem that would keep a workload from starting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadDiagnostic {
+    /// What the problem is with, e.g. `components[0]`, `service` or `volumes`
+    pub location: String,
+    /// The [code](crate::host::error::HostError::code) of the problem's cause
+    pub code: String,
+    pub message: String,
+}
+
+/// Request to lint a workload for suspicious configurations, see [`crate::host::lint`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadLintRequest {
+    pub workload: Workload,
+}
+
+/// The suspicious configurations of a workload.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkloadLintResponse {
+    pub warnings: Vec<WorkloadWarning>,
+}
+
+/// A suspicious configuration of a workload, which doesn't keep it from starting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadWarning {
+    /// What the warning is about, e.g. `components[0]` or `host_interfaces[1]`
+    pub location: String,
+    /// The [code](crate::host::lint::Lint::code) of the lint
+    pub code: String,
+    pub message: String,
+}
+
 /// Request to get the status of a specific workload.
 #[derive(Debug, Clone, PartialEq)]
 pub struct WorkloadStatusRequest {
@@ -193,3 +596,574 @@ pub struct WorkloadStopRequest {
 pub struct WorkloadStopResponse {
     pub workload_status: WorkloadStatus,
 }
+
+/// Request for the running workloads, optionally those matching a label selector.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkloadListRequest {
+    /// A [label selector](crate::host::selector) over workload annotations, e.g.
+    /// `app=checkout,env=prod`. Every running workload is listed when unset.
+    pub selector: Option<String>,
+}
+
+/// The running workloads matching a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadListResponse {
+    pub workloads: Vec<WorkloadSummary>,
+}
+
+/// A running workload and the annotations it was started with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadSummary {
+    pub workload_id: String,
+    pub namespace: String,
+    pub name: String,
+    pub annotations: HashMap<String, String>,
+}
+
+/// Request to stop every running workload matching a label selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadStopSelectedRequest {
+    /// A [label selector](crate::host::selector) over workload annotations. It can't be
+    /// empty, so every workload isn't stopped by mistake.
+    pub selector: String,
+}
+
+/// The statuses of the workloads stopped by selector.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadStopSelectedResponse {
+    pub workload_statuses: Vec<WorkloadStatus>,
+}
+
+/// Request for the resource usage of a running workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadUsageRequest {
+    pub workload_id: String,
+}
+
+/// The resource usage of a workload since it started, across all its instances.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadUsageResponse {
+    pub workload_id: String,
+    /// Guest memory currently allocated by the workload's live instances
+    pub memory_bytes: u64,
+    /// The most guest memory allocated at once
+    pub peak_memory_bytes: u64,
+    /// Table elements currently allocated by the workload's live instances
+    pub table_elements: u64,
+    /// The workload's live instances, each in a store of its own
+    pub instances: Vec<InstanceUsage>,
+    /// Time spent executing guest code, excluding time spent in host functions
+    pub cpu_time: Duration,
+    /// The CPU time of recent finished invocations, if any finished yet
+    pub cpu_time_per_invocation: Option<Percentiles<Duration>>,
+    /// Fuel consumed by finished invocations, if the engine meters fuel
+    pub fuel_consumed: Option<u64>,
+    /// The fuel consumed by recent finished invocations, if the engine meters fuel
+    pub fuel_per_invocation: Option<Percentiles<u64>>,
+    /// Number of instances created, one per invocation
+    pub invocations: u64,
+    /// Number of host operations served by each plugin, keyed by plugin ID
+    pub plugin_ops: HashMap<String, u64>,
+}
+
+/// The distribution of a measurement over recent invocations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Percentiles<T> {
+    pub p50: T,
+    pub p90: T,
+    pub p99: T,
+    pub max: T,
+    /// The number of invocations measured
+    pub count: usize,
+}
+
+/// The memory used by a live instance of a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceUsage {
+    /// The ID of the instance's store
+    pub instance_id: String,
+    pub component_id: String,
+    /// Guest memory allocated by the instance's linear memories
+    pub memory_bytes: u64,
+    /// The component's `memory_limit_mb` in bytes, if it sets one
+    pub memory_limit_bytes: Option<u64>,
+    /// Elements allocated by the instance's tables
+    pub table_elements: u64,
+}
+
+/// Request for the live instances of a running workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadInstancesRequest {
+    pub workload_id: String,
+}
+
+/// The live instances of a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadInstancesResponse {
+    pub workload_id: String,
+    pub instances: Vec<InstanceInfo>,
+}
+
+/// The state of a live instance of a workload, in a store of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceInfo {
+    /// The ID of the instance's store
+    pub instance_id: String,
+    pub component_id: String,
+    /// How long ago the instance's store was created
+    pub age: Duration,
+    /// Number of times the host called into the instance's guest code, e.g. its exports
+    pub calls: u64,
+    /// Whether the instance is executing guest code, rather than waiting on the host,
+    /// e.g. for I/O
+    pub busy: bool,
+    /// The last error a call into the instance failed with, if any
+    pub last_error: Option<String>,
+}
+
+/// Request to pause a running workload, see
+/// [`crate::engine::workload::ResolvedWorkload::pause`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadPauseRequest {
+    pub workload_id: String,
+}
+
+/// The outcome of pausing a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadPauseResponse {
+    pub workload_id: String,
+    /// Whether the workload was already paused
+    pub was_paused: bool,
+}
+
+/// Request to resume a paused workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadResumeRequest {
+    pub workload_id: String,
+}
+
+/// The outcome of resuming a workload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadResumeResponse {
+    pub workload_id: String,
+    /// Whether the workload was paused
+    pub was_paused: bool,
+}
+
+/// Request to change the instance pool size of a running workload's components, see
+/// [`crate::engine::workload::ResolvedWorkload::scale`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadScaleRequest {
+    pub workload_id: String,
+    /// The component to scale, as listed by [`InstanceInfo::component_id`], or `None`
+    /// for every component of the workload
+    pub component_id: Option<String>,
+    /// The number of warm instances to keep
+    pub pool_size: usize,
+}
+
+/// The pool sizes a scale request replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadScaleResponse {
+    pub workload_id: String,
+    /// The scaled component IDs and their previous pool sizes
+    pub previous: Vec<(String, usize)>,
+}
+
+/// Request to change the resources of a running workload's components, see
+/// [`crate::engine::workload::ResolvedWorkload::update_resources`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadUpdateResourcesRequest {
+    pub workload_id: String,
+    /// The component to update, as listed by [`InstanceInfo::component_id`], or `None`
+    /// for every component of the workload
+    pub component_id: Option<String>,
+    pub update: LocalResourcesUpdate,
+}
+
+/// The components whose resources were changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkloadUpdateResourcesResponse {
+    pub workload_id: String,
+    pub component_ids: Vec<String>,
+}
+
+/// Request to call a function exported by a running workload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadInvokeRequest {
+    pub workload_id: String,
+    /// The exported interface, e.g. `acme:inventory/query@0.1.0`. The version may be left
+    /// out.
+    pub interface: String,
+    pub function: String,
+    /// The arguments, in the order of the function's parameters
+    pub params: InvokeValues,
+}
+
+/// The results of a call to a workload export.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadInvokeResponse {
+    /// The results, in the same encoding as the request's params
+    pub results: InvokeValues,
+}
+
+/// The arguments or results of a call to a workload export.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InvokeValues {
+    /// Component model values
+    Values(Vec<Val>),
+    /// Values written in WAVE, see [`crate::engine::wave`]
+    Wave(Vec<String>),
+}
+
+/// Request to split the HTTP traffic of a virtual host between workloads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpTrafficSplitRequest {
+    /// The virtual host, as set in the `host` config of the workloads' HTTP interface
+    pub host: String,
+    /// Workload IDs and their relative weights, e.g. 90 and 10. An empty list removes
+    /// the split, sending requests to the workload the host routes to by default.
+    pub weights: Vec<(String, u32)>,
+}
+
+/// The split a traffic split request replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpTrafficSplitResponse {
+    /// The previous workload IDs and weights, empty if the host had no split
+    pub previous: Vec<(String, u32)>,
+}
+
+/// Request to create an API key, see [`crate::host::api_keys`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyCreateRequest {
+    /// A name describing the key's caller, e.g. `billing-service`
+    pub name: String,
+    /// The virtual hosts the key is accepted on, or `*` for every host
+    pub hosts: Vec<String>,
+}
+
+/// A newly created API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyCreateResponse {
+    pub id: String,
+    /// The key, which the host only keeps a hash of
+    pub key: String,
+}
+
+/// Request to replace the secret of an API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyRotateRequest {
+    pub id: String,
+}
+
+/// The new secret of a rotated API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyRotateResponse {
+    /// The new key. The old key is no longer accepted.
+    pub key: String,
+}
+
+/// Request to revoke an API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyRevokeRequest {
+    pub id: String,
+}
+
+/// Response for a revoked API key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiKeyRevokeResponse {
+    pub id: String,
+}
+
+/// Request to inspect a component without starting it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentInspectRequest {
+    pub bytes: Bytes,
+}
+
+/// Response describing a component's interfaces, metadata and resource needs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentInspectResponse {
+    /// The component's WIT world, with interfaces grouped by package
+    pub world: WitWorld,
+    /// Fully qualified names of the instances the component imports
+    pub imports: Vec<String>,
+    /// Fully qualified names of the instances the component exports
+    pub exports: Vec<String>,
+    /// Metadata embedded in the component, e.g. `version` or `authors`
+    pub metadata: HashMap<String, String>,
+    /// Estimated bytes needed to instantiate the component once
+    pub estimated_memory_bytes: u64,
+    /// Imports that neither the host nor its plugins provide. A component in a
+    /// multi-component workload may still have these satisfied by its siblings.
+    pub unsatisfied_imports: Vec<WitInterface>,
+}
+
+/// Request to compare the interfaces of a running component with those of a replacement,
+/// before updating the component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentDiffRequest {
+    pub workload_id: String,
+    /// The running component, as listed by [`InstanceInfo::component_id`]. May be `None`
+    /// for workloads with a single component.
+    pub component_id: Option<String>,
+    /// The replacement component
+    pub bytes: Bytes,
+}
+
+/// The interface changes a replacement would make to a running component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComponentDiffResponse {
+    pub workload_id: String,
+    pub component_id: String,
+    /// Every change, see [`crate::engine::compat::diff`]
+    pub changes: Vec<WitChange>,
+}
+
+impl ComponentDiffResponse {
+    /// Whether any change can break the workload, so the update shouldn't be applied.
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(WitChange::is_breaking)
+    }
+}
+
+/// A change to the interfaces of a component, naming instances like
+/// `wasi:keyvalue/store@0.2.0-draft`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WitChange {
+    /// An instance is no longer exported
+    ExportRemoved { interface: String },
+    /// An instance is exported at another version
+    ExportVersionChanged {
+        interface: String,
+        replacement: String,
+    },
+    /// A function of an instance that's still exported is no longer exported
+    FunctionRemoved { interface: String, function: String },
+    /// A function of an instance that's still imported or exported has another
+    /// signature, rendered like `func(key: string) -> option<list<u8>>`
+    FunctionChanged {
+        interface: String,
+        function: String,
+        signature: String,
+        replacement: String,
+    },
+    /// An instance is newly exported
+    ExportAdded { interface: String },
+    /// An instance is newly imported
+    ImportAdded { interface: String },
+    /// An instance is imported at another version
+    ImportVersionChanged {
+        interface: String,
+        replacement: String,
+    },
+    /// A function of an instance that's still imported is newly imported
+    ImportFunctionAdded { interface: String, function: String },
+    /// An instance is no longer imported
+    ImportRemoved { interface: String },
+}
+
+impl WitChange {
+    /// Whether the change can break the workload: callers may use what's no longer
+    /// exported, the host or workload may not provide what's newly imported, and neither
+    /// side of a function whose signature changed can call the other. Version changes are
+    /// breaking unless the versions are [compatible](WitInterface::version_compatible).
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            Self::ExportRemoved { .. }
+            | Self::FunctionRemoved { .. }
+            | Self::FunctionChanged { .. }
+            | Self::ImportAdded { .. }
+            | Self::ImportFunctionAdded { .. } => true,
+            Self::ExportVersionChanged {
+                interface,
+                replacement,
+            }
+            | Self::ImportVersionChanged {
+                interface,
+                replacement,
+            } => !WitInterface::from(interface.as_str())
+                .version_compatible(&WitInterface::from(replacement.as_str())),
+            Self::ExportAdded { .. } | Self::ImportRemoved { .. } => false,
+        }
+    }
+}
+
+/// Request for what the host's workloads are running and where it came from.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WorkloadInventoryRequest {
+    /// Only report this workload. Every running workload is reported when unset.
+    pub workload_id: Option<String>,
+}
+
+/// The components of the host's running workloads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadInventoryResponse {
+    pub workloads: Vec<WorkloadInventory>,
+}
+
+/// The components of a running workload, recorded when it was started.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadInventory {
+    pub workload_id: String,
+    pub namespace: String,
+    pub name: String,
+    /// The workload's components, in the order they were given
+    pub components: Vec<ComponentInventory>,
+    pub service: Option<ComponentInventory>,
+}
+
+/// What a component is and where it came from, read from the component itself and the
+/// image it was pulled from.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ComponentInventory {
+    /// The hex SHA-256 digest of the component's bytes
+    pub sha256: String,
+    /// The image the component was pulled from, if it came from a registry
+    pub source: Option<ComponentSource>,
+    /// Metadata embedded in the component, e.g. `version` or `source`
+    pub metadata: HashMap<String, String>,
+    /// The tools that produced the component and its modules, from their `producers`
+    /// sections: the `language`, `processed-by` and `sdk` fields, each with the sorted
+    /// `(name, version)` pairs listed under it
+    pub producers: BTreeMap<String, Vec<(String, String)>>,
+    /// The JSON `registry-metadata` section written by older `wasm-tools`, if present
+    pub registry_metadata: Option<serde_json::Value>,
+    /// The claims embedded in the component, if it was signed
+    pub claims: Option<ComponentClaims>,
+}
+
+/// Claims embedded in a component's `jwt` section, see [`crate::engine::claims`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ComponentClaims {
+    /// The public key of the account that signed the claims
+    pub issuer: String,
+    /// The public key of the component
+    pub subject: String,
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub revision: Option<i32>,
+    pub tags: Vec<String>,
+    /// Seconds since the Unix epoch
+    pub issued_at: Option<u64>,
+    /// Seconds since the Unix epoch
+    pub expires_at: Option<u64>,
+    /// Whether the issuer signed the claims and they're for this component. Claims that
+    /// aren't verified only say what the component claims to be.
+    pub verified: bool,
+}
+
+/// Request for the components loaded on the host.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ComponentsListRequest {
+    /// Only list the component with this digest, either the SHA-256 of its bytes or the
+    /// digest of the image it was pulled from. Every component is listed when unset.
+    pub digest: Option<String>,
+}
+
+/// The components loaded on the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentsListResponse {
+    pub components: Vec<LoadedComponent>,
+}
+
+/// A component loaded on the host, and the workloads that use it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedComponent {
+    /// The hex SHA-256 digest of the component's bytes
+    pub sha256: String,
+    /// The name from the component's claims or image annotations
+    pub name: Option<String>,
+    /// The version from the component's metadata, claims or image annotations
+    pub version: Option<String>,
+    pub claims: Option<ComponentClaims>,
+    /// The images the component was pulled from
+    pub sources: Vec<ComponentSource>,
+    /// The IDs of the running workloads using the component
+    pub workloads: Vec<String>,
+}
+
+/// Request for the recent outbound connections of a workload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadEgressRequest {
+    pub workload_id: String,
+    /// Maximum number of recent records to return, newest first. All retained
+    /// records are returned when unset.
+    pub limit: Option<usize>,
+}
+
+/// Recent outbound connections of a workload and totals per destination.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadEgressResponse {
+    /// Recent outbound connections, newest first
+    pub records: Vec<EgressRecord>,
+    /// Totals since the workload started, keyed by destination
+    pub destinations: HashMap<String, DestinationMetrics>,
+}
+
+/// The kind of an outbound connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EgressKind {
+    /// An outgoing `wasi:http` request
+    Http,
+    /// A `wasi:sockets` connection or datagram
+    Socket,
+}
+
+/// How an outbound connection ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EgressOutcome {
+    /// The connection was made. For HTTP this means a response was received,
+    /// whatever its status.
+    Allowed,
+    /// The destination isn't in the component's `allowed_hosts`
+    BlockedByPolicy,
+    /// The connection was allowed but failed, e.g. on DNS or a timeout
+    Failed(String),
+}
+
+/// A single outbound connection made by a component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EgressRecord {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub workload_id: String,
+    pub component_id: String,
+    pub kind: EgressKind,
+    /// `scheme://host:port` for HTTP requests, the socket address for sockets
+    pub destination: String,
+    /// The HTTP method, for HTTP requests
+    pub method: Option<String>,
+    /// The HTTP response status, for HTTP requests that received one
+    pub status: Option<u16>,
+    /// Request body bytes sent
+    pub bytes_sent: u64,
+    /// Response body bytes received
+    pub bytes_received: u64,
+    /// Time until the response headers were received
+    pub latency: Duration,
+    pub outcome: EgressOutcome,
+}
+
+/// Totals for the outbound connections of a workload to a single destination.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DestinationMetrics {
+    /// Connections attempted, including blocked and failed ones
+    pub connections: u64,
+    pub blocked: u64,
+    pub failed: u64,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// Sum of the latencies of allowed connections
+    pub total_latency: Duration,
+}
+
+impl DestinationMetrics {
+    /// Returns the mean latency of allowed connections, if any were made.
+    pub fn mean_latency(&self) -> Option<Duration> {
+        let allowed = self.connections - self.blocked - self.failed;
+        u32::try_from(allowed)
+            .ok()
+            .filter(|allowed| *allowed > 0)
+            .map(|allowed| self.total_latency / allowed)
+    }
+}
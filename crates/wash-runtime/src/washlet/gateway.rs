@@ -0,0 +1,364 @@
+//! JSON over HTTP gateway to the interfaces workloads publish as named services (see
+//! [`crate::host::services`]), so simple components get an HTTP API without
+//! implementing `wasi:http` themselves.
+//!
+//! Each published function gets a route derived from its WIT name:
+//!
+//! | route                                                     | action                          |
+//! |-----------------------------------------------------------|---------------------------------|
+//! | `GET /{namespace}/{service}`                              | list the published functions    |
+//! | `POST /{namespace}/{service}/{package}/{interface}/{function}` | call a function             |
+//!
+//! For example, `stock` in `acme:inventory/query` of the `inventory` service is called
+//! with `POST /default/inventory/acme:inventory/query/stock`. The request body holds the
+//! arguments, either as a JSON array in parameter order or as an object keyed by
+//! parameter name; a function without parameters may be called with an empty body.
+//! Arguments and results are converted following the mapping in [`crate::engine::json`].
+//! The response is the function's result, `null` if it has none, or an array if it has
+//! several. Errors are returned as `{"error": "..."}`.
+//!
+//! Like the gRPC [`exports`](super::exports) gateway, only interfaces listed in a
+//! service's `exports` are reachable.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{Method, StatusCode};
+use serde_json::json;
+use tokio::net::TcpListener;
+use tracing::{debug, warn};
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::engine::json::type_name;
+use crate::engine::workload::ExportedFunction;
+use crate::host::Host;
+use crate::redact;
+use crate::wit::WitInterface;
+
+/// The largest request body the gateway accepts.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// A route of the gateway.
+#[derive(Debug, PartialEq)]
+enum Route<'a> {
+    Describe {
+        namespace: &'a str,
+        service: &'a str,
+    },
+    Invoke {
+        namespace: &'a str,
+        service: &'a str,
+        interface: String,
+        function: &'a str,
+    },
+}
+
+impl<'a> Route<'a> {
+    /// Parses a request's method and path, returning `None` if it matches no route.
+    fn parse(method: &Method, path: &'a str) -> Option<Self> {
+        let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+        if segments.iter().any(|s| s.is_empty()) {
+            return None;
+        }
+        match (method, segments.as_slice()) {
+            (&Method::GET, &[namespace, service]) => Some(Route::Describe { namespace, service }),
+            (&Method::POST, &[namespace, service, package, interface, function])
+                if package.contains(':') =>
+            {
+                Some(Route::Invoke {
+                    namespace,
+                    service,
+                    interface: format!("{package}/{interface}"),
+                    function,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An error response.
+struct GatewayError(StatusCode, String);
+
+impl GatewayError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self(status, message.into())
+    }
+}
+
+/// Serves the gateway for a host until the returned future is dropped or fails.
+pub async fn serve(host: Arc<Host>, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind JSON gateway to {addr}"))?;
+    debug!(%addr, "serving published workload exports as JSON over HTTP");
+    loop {
+        let (stream, client_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(err = ?e, "failed to accept JSON gateway connection");
+                continue;
+            }
+        };
+        let host = host.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |req| {
+                let host = host.clone();
+                async move { Ok::<_, std::convert::Infallible>(handle(&host, req).await) }
+            });
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .keep_alive(true)
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(addr = ?client_addr, err = ?e, "error serving JSON gateway client");
+            }
+        });
+    }
+}
+
+/// Handles a gateway request, turning errors into JSON error responses.
+async fn handle(
+    host: &Host,
+    req: hyper::Request<hyper::body::Incoming>,
+) -> hyper::Response<Full<Bytes>> {
+    let (status, body) = match route(host, req).await {
+        Ok(value) => (StatusCode::OK, value),
+        Err(GatewayError(status, message)) => {
+            (status, json!({ "error": redact::redact(&message) }))
+        }
+    };
+    hyper::Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .expect("failed to build gateway response")
+}
+
+async fn route(
+    host: &Host,
+    req: hyper::Request<hyper::body::Incoming>,
+) -> Result<serde_json::Value, GatewayError> {
+    let (parts, body) = req.into_parts();
+    let Some(route) = Route::parse(&parts.method, parts.uri.path()) else {
+        return Err(GatewayError::new(StatusCode::NOT_FOUND, "no such route"));
+    };
+    match route {
+        Route::Describe { namespace, service } => {
+            let functions = published_functions(host, namespace, service).await?;
+            Ok(functions
+                .into_iter()
+                .map(|f| {
+                    json!({
+                        "interface": f.interface,
+                        "name": f.name,
+                        "params": f.params.iter().map(|(name, ty)| {
+                            json!({ "name": name, "type": type_name(ty) })
+                        }).collect::<Vec<_>>(),
+                        "results": f.results.iter().map(type_name).collect::<Vec<_>>(),
+                    })
+                })
+                .collect())
+        }
+        Route::Invoke {
+            namespace,
+            service,
+            interface,
+            function,
+        } => {
+            let body = Limited::new(body, MAX_BODY_BYTES)
+                .collect()
+                .await
+                .map_err(|e| {
+                    GatewayError::new(StatusCode::BAD_REQUEST, format!("invalid body: {e}"))
+                })?
+                .to_bytes();
+            invoke(host, namespace, service, &interface, function, &body).await
+        }
+    }
+}
+
+/// Lists the functions of a service's published interfaces.
+async fn published_functions(
+    host: &Host,
+    namespace: &str,
+    service: &str,
+) -> Result<Vec<ExportedFunction>, GatewayError> {
+    let published = host
+        .services()
+        .exports(namespace, service)
+        .await
+        .ok_or_else(|| {
+            GatewayError::new(
+                StatusCode::NOT_FOUND,
+                format!("service '{service}' has no running instances in namespace '{namespace}'"),
+            )
+        })?;
+    let instance = host
+        .services()
+        .pick(namespace, service)
+        .await
+        .ok_or_else(|| {
+            GatewayError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "service has no running instances",
+            )
+        })?;
+    Ok(instance
+        .exported_functions()
+        .await
+        .into_iter()
+        .filter(|f| {
+            let interface = WitInterface::from(f.interface.as_str());
+            published.iter().any(|p| p.contains(&interface))
+        })
+        .collect())
+}
+
+async fn invoke(
+    host: &Host,
+    namespace: &str,
+    service: &str,
+    interface: &str,
+    function: &str,
+    body: &[u8],
+) -> Result<serde_json::Value, GatewayError> {
+    let wanted = WitInterface::from(interface);
+    let Some(export) = published_functions(host, namespace, service)
+        .await?
+        .into_iter()
+        .find(|f| {
+            f.name == function
+                && (f.interface == interface
+                    || WitInterface::from(f.interface.as_str()).contains(&wanted))
+        })
+    else {
+        return Err(GatewayError::new(
+            StatusCode::NOT_FOUND,
+            format!("service '{service}' doesn't publish '{interface}#{function}'"),
+        ));
+    };
+
+    let args = parse_args(&export, body)
+        .map_err(|e| GatewayError::new(StatusCode::BAD_REQUEST, format!("{e:#}")))?;
+    let Some(instance) = host.services().pick(namespace, service).await else {
+        return Err(GatewayError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "service has no running instances",
+        ));
+    };
+    debug!(
+        service,
+        namespace,
+        interface,
+        function,
+        workload_id = instance.id(),
+        "invoking published export over JSON gateway"
+    );
+    let mut results = instance
+        .call_export_json(&export.interface, function, &args)
+        .await
+        .map_err(|e| GatewayError::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{e:#}")))?;
+    Ok(match results.len() {
+        0 => serde_json::Value::Null,
+        1 => results.remove(0),
+        _ => serde_json::Value::Array(results),
+    })
+}
+
+/// Reads a function's arguments from a request body, which is empty, an array of the
+/// arguments in order, or an object keyed by parameter name.
+fn parse_args(export: &ExportedFunction, body: &[u8]) -> anyhow::Result<Vec<serde_json::Value>> {
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Ok(Vec::new());
+    }
+    match serde_json::from_slice(body).context("body is not valid JSON")? {
+        serde_json::Value::Array(args) => Ok(args),
+        serde_json::Value::Object(mut fields) => {
+            let args = export
+                .params
+                .iter()
+                .map(|(name, _)| fields.remove(name).unwrap_or(serde_json::Value::Null))
+                .collect();
+            if let Some(unknown) = fields.keys().next() {
+                anyhow::bail!("'{}' has no parameter '{unknown}'", export.name);
+            }
+            Ok(args)
+        }
+        _ => anyhow::bail!("expected the arguments as a JSON array or object"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::component::Type;
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(
+            Route::parse(&Method::GET, "/default/inventory"),
+            Some(Route::Describe {
+                namespace: "default",
+                service: "inventory"
+            })
+        );
+        assert_eq!(
+            Route::parse(
+                &Method::POST,
+                "/default/inventory/acme:inventory/query@0.1.0/stock"
+            ),
+            Some(Route::Invoke {
+                namespace: "default",
+                service: "inventory",
+                interface: "acme:inventory/query@0.1.0".to_string(),
+                function: "stock",
+            })
+        );
+        assert_eq!(
+            Route::parse(
+                &Method::GET,
+                "/default/inventory/acme:inventory/query/stock"
+            ),
+            None
+        );
+        assert_eq!(
+            Route::parse(&Method::POST, "/default/inventory/acme/query/stock"),
+            None
+        );
+        assert_eq!(Route::parse(&Method::GET, "/default//inventory"), None);
+        assert_eq!(Route::parse(&Method::GET, "/"), None);
+    }
+
+    #[test]
+    fn test_parse_args() -> anyhow::Result<()> {
+        let export = ExportedFunction {
+            interface: "acme:inventory/query".to_string(),
+            name: "stock".to_string(),
+            params: vec![
+                ("sku".to_string(), Type::String),
+                ("warehouse".to_string(), Type::U32),
+            ],
+            results: vec![Type::U32],
+        };
+        assert_eq!(
+            parse_args(&export, br#"["sku-1", 3]"#)?,
+            vec![json!("sku-1"), json!(3)]
+        );
+        assert_eq!(
+            parse_args(&export, br#"{"warehouse": 3, "sku": "sku-1"}"#)?,
+            vec![json!("sku-1"), json!(3)]
+        );
+        assert_eq!(
+            parse_args(&export, b" \n")?,
+            Vec::<serde_json::Value>::new()
+        );
+        assert!(parse_args(&export, br#"{"size": 1}"#).is_err());
+        assert!(parse_args(&export, b"42").is_err());
+        assert!(parse_args(&export, b"{").is_err());
+        Ok(())
+    }
+}
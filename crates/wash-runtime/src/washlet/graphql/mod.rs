@@ -0,0 +1,498 @@
+//! GraphQL gateway to the interfaces workloads publish as named services (see
+//! [`crate::host::services`]), a higher-level ingress for data-centric components.
+//!
+//! Each service gets a GraphQL endpoint whose schema is derived from the WIT types of
+//! the functions it publishes:
+//!
+//! | route                               | action                                     |
+//! |-------------------------------------|--------------------------------------------|
+//! | `GET /{namespace}/{service}`        | the schema, in the schema definition language |
+//! | `POST /{namespace}/{service}`       | execute a query, as `{"query": ..., "variables": ..., "operationName": ...}` |
+//!
+//! Every published function is a field of the `Query` type if its name starts with a read
+//! verb such as `get` or `list`, and of the `Mutation` type otherwise. Records and tuples
+//! become object types, enums become enums, and options become nullable types. A function
+//! returning a `result` resolves to its `ok` value, with an `err` value reported as a
+//! field error. Integers that don't fit GraphQL's 32-bit `Int` use a `BigInt` scalar, and
+//! variants, results and flags elsewhere use a `JSON` scalar holding the value in the
+//! host's JSON form (see [`crate::engine::json`]).
+//!
+//! Operations may use variables, aliases and nested selections. Fragments, directives,
+//! subscriptions and introspection queries aren't supported; fetch the schema with a
+//! `GET` request instead.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::{Context as _, bail, ensure};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use tokio::net::TcpListener;
+use tracing::{debug, warn};
+use wasmtime_wasi_http::io::TokioIo;
+
+use crate::host::Host;
+use crate::redact;
+use crate::wit::WitInterface;
+
+mod parser;
+mod schema;
+
+use parser::{InputValue, Operation, Selection};
+use schema::{OperationKind, RootField, Schema, Shape};
+
+/// The largest request body the gateway accepts.
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// A GraphQL request, as sent over HTTP.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlRequest {
+    query: String,
+    #[serde(default)]
+    variables: Option<Map<String, Value>>,
+    #[serde(default)]
+    operation_name: Option<String>,
+}
+
+/// Serves the gateway for a host until the returned future is dropped or fails.
+pub async fn serve(host: Arc<Host>, addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind GraphQL gateway to {addr}"))?;
+    debug!(%addr, "serving published workload exports over GraphQL");
+    loop {
+        let (stream, client_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(err = ?e, "failed to accept GraphQL gateway connection");
+                continue;
+            }
+        };
+        let host = host.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |req| {
+                let host = host.clone();
+                async move { Ok::<_, std::convert::Infallible>(handle(&host, req).await) }
+            });
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .keep_alive(true)
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(addr = ?client_addr, err = ?e, "error serving GraphQL gateway client");
+            }
+        });
+    }
+}
+
+async fn handle(
+    host: &Host,
+    req: hyper::Request<hyper::body::Incoming>,
+) -> hyper::Response<Full<Bytes>> {
+    let (parts, body) = req.into_parts();
+    let segments: Vec<&str> = parts.uri.path().trim_matches('/').split('/').collect();
+    let &[namespace, service] = segments.as_slice() else {
+        return error_response(StatusCode::NOT_FOUND, "no such route");
+    };
+    let schema = match service_schema(host, namespace, service).await {
+        Ok(schema) => schema,
+        Err(e) => return error_response(StatusCode::NOT_FOUND, &format!("{e:#}")),
+    };
+
+    match parts.method {
+        Method::GET => hyper::Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Full::new(Bytes::from(schema.sdl())))
+            .expect("failed to build schema response"),
+        Method::POST => {
+            let request = match Limited::new(body, MAX_BODY_BYTES).collect().await {
+                Ok(body) => serde_json::from_slice::<GraphqlRequest>(&body.to_bytes())
+                    .context("body is not a GraphQL request"),
+                Err(e) => Err(anyhow::anyhow!("invalid body: {e}")),
+            };
+            let request = match request {
+                Ok(request) => request,
+                Err(e) => return error_response(StatusCode::BAD_REQUEST, &format!("{e:#}")),
+            };
+            let response = execute(host, namespace, &schema, request).await;
+            json_response(StatusCode::OK, &response)
+        }
+        _ => error_response(StatusCode::METHOD_NOT_ALLOWED, "use GET or POST"),
+    }
+}
+
+fn json_response(status: StatusCode, body: &Value) -> hyper::Response<Full<Bytes>> {
+    hyper::Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, "application/json")
+        .body(Full::new(Bytes::from(body.to_string())))
+        .expect("failed to build GraphQL response")
+}
+
+fn error_response(status: StatusCode, message: &str) -> hyper::Response<Full<Bytes>> {
+    json_response(
+        status,
+        &json!({ "errors": [{ "message": redact::redact(message) }] }),
+    )
+}
+
+/// Derives the schema of a service from the functions it publishes.
+async fn service_schema(host: &Host, namespace: &str, service: &str) -> anyhow::Result<Schema> {
+    let published = host
+        .services()
+        .exports(namespace, service)
+        .await
+        .with_context(|| {
+            format!("service '{service}' has no running instances in namespace '{namespace}'")
+        })?;
+    let instance = host
+        .services()
+        .pick(namespace, service)
+        .await
+        .context("service has no running instances")?;
+    let functions = instance
+        .exported_functions()
+        .await
+        .into_iter()
+        .filter(|f| {
+            let interface = WitInterface::from(f.interface.as_str());
+            published.iter().any(|p| p.contains(&interface))
+        })
+        .collect();
+    Schema::derive(service, functions)
+}
+
+/// Executes a request, returning the GraphQL response with its `data` and `errors`.
+async fn execute(host: &Host, namespace: &str, schema: &Schema, request: GraphqlRequest) -> Value {
+    let operation = match select_operation(&request) {
+        Ok(operation) => operation,
+        Err(e) => return json!({ "errors": [{ "message": format!("{e:#}") }] }),
+    };
+    let variables = request.variables.unwrap_or_default();
+
+    let mut data = Map::new();
+    let mut errors = Vec::new();
+    // Fields are resolved one after another, as GraphQL requires for mutations
+    for selection in &operation.selections {
+        let key = selection.response_key().to_string();
+        match resolve_root(host, namespace, schema, &operation, selection, &variables).await {
+            Ok(value) => {
+                data.insert(key, value);
+            }
+            Err(e) => {
+                errors.push(json!({
+                    "message": redact::redact(&format!("{e:#}")),
+                    "path": [key.clone()],
+                }));
+                data.insert(key, Value::Null);
+            }
+        }
+    }
+    let mut response = json!({ "data": data });
+    if !errors.is_empty() {
+        response["errors"] = Value::Array(errors);
+    }
+    response
+}
+
+/// Picks the operation to execute, by name if the document has several.
+fn select_operation(request: &GraphqlRequest) -> anyhow::Result<Operation> {
+    let mut operations = parser::parse_document(&request.query)?;
+    match &request.operation_name {
+        Some(name) => {
+            let index = operations
+                .iter()
+                .position(|o| o.name.as_deref() == Some(name))
+                .with_context(|| format!("no operation named '{name}'"))?;
+            Ok(operations.swap_remove(index))
+        }
+        None => {
+            ensure!(
+                operations.len() == 1,
+                "the document has several operations, pick one with operationName"
+            );
+            Ok(operations.remove(0))
+        }
+    }
+}
+
+/// Resolves a root field by calling the function behind it.
+async fn resolve_root(
+    host: &Host,
+    namespace: &str,
+    schema: &Schema,
+    operation: &Operation,
+    selection: &Selection,
+    variables: &Map<String, Value>,
+) -> anyhow::Result<Value> {
+    let type_name = operation.kind.type_name();
+    match selection.name.as_str() {
+        "__typename" => return Ok(Value::String(type_name.to_string())),
+        "_service" if operation.kind == OperationKind::Query => {
+            return Ok(Value::String(schema.service.clone()));
+        }
+        _ => {}
+    }
+    let Some(field) = schema.field(operation.kind, &selection.name) else {
+        bail!("{type_name} has no field '{}'", selection.name);
+    };
+    let args = field_args(field, operation, selection, variables)?;
+
+    let instance = host
+        .services()
+        .pick(namespace, &schema.service)
+        .await
+        .context("service has no running instances")?;
+    debug!(
+        service = schema.service,
+        namespace,
+        field = field.name,
+        workload_id = instance.id(),
+        "resolving GraphQL field"
+    );
+    let mut results = instance
+        .call_export_json(&field.function.interface, &field.function.name, &args)
+        .await?;
+    let mut value = results.pop().unwrap_or(Value::Bool(true));
+    if field.unwrap_result {
+        value = match value {
+            Value::Object(mut result) if result.contains_key("err") => {
+                match result.remove("err").unwrap_or_default() {
+                    Value::String(message) => bail!("{message}"),
+                    Value::Null => bail!("{} failed", field.name),
+                    other => bail!("{other}"),
+                }
+            }
+            Value::Object(mut result) => match result.remove("ok") {
+                Some(Value::Null) | None => Value::Bool(true),
+                Some(ok) => ok,
+            },
+            other => other,
+        };
+    }
+    select(&field.output, value, &selection.selections)
+}
+
+/// Collects the JSON arguments of a function from a field's GraphQL arguments.
+fn field_args(
+    field: &RootField,
+    operation: &Operation,
+    selection: &Selection,
+    variables: &Map<String, Value>,
+) -> anyhow::Result<Vec<Value>> {
+    if let Some((unknown, _)) = selection
+        .arguments
+        .iter()
+        .find(|(name, _)| !field.args.iter().any(|(arg, _)| arg == name))
+    {
+        bail!("field '{}' has no argument '{unknown}'", field.name);
+    }
+    field
+        .args
+        .iter()
+        .map(|(name, shape)| {
+            let value = match selection.arguments.iter().find(|(arg, _)| arg == name) {
+                Some((_, value)) => substitute(value, operation, variables)?,
+                None => Value::Null,
+            };
+            shape
+                .input(value)
+                .with_context(|| format!("invalid argument '{name}'"))
+        })
+        .collect()
+}
+
+/// Converts an input value to JSON, replacing variables with their values.
+fn substitute(
+    value: &InputValue,
+    operation: &Operation,
+    variables: &Map<String, Value>,
+) -> anyhow::Result<Value> {
+    Ok(match value {
+        InputValue::Variable(name) => match variables.get(name) {
+            Some(value) => value.clone(),
+            None => {
+                let Some((_, default)) = operation.variables.iter().find(|(v, _)| v == name) else {
+                    bail!("variable '${name}' is not defined");
+                };
+                match default {
+                    Some(default) => substitute(default, operation, variables)?,
+                    None => Value::Null,
+                }
+            }
+        },
+        InputValue::Int(v) => json!(v),
+        InputValue::Float(v) => json!(v),
+        InputValue::String(v) | InputValue::Enum(v) => Value::String(v.clone()),
+        InputValue::Boolean(v) => Value::Bool(*v),
+        InputValue::Null => Value::Null,
+        InputValue::List(values) => Value::Array(
+            values
+                .iter()
+                .map(|v| substitute(v, operation, variables))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+        InputValue::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(name, v)| Ok((name.clone(), substitute(v, operation, variables)?)))
+                .collect::<anyhow::Result<_>>()?,
+        ),
+    })
+}
+
+/// Shapes a result in the host's JSON form into the fields a query selects.
+fn select(shape: &Shape, value: Value, selections: &[Selection]) -> anyhow::Result<Value> {
+    match (shape, value) {
+        (Shape::Nullable(_), Value::Null) => Ok(Value::Null),
+        (Shape::Nullable(inner), value) => select(inner, value, selections),
+        (Shape::Scalar(name), value) => {
+            ensure!(selections.is_empty(), "{name} has no fields to select");
+            Ok(value)
+        }
+        (Shape::Enum { name, values }, Value::String(case)) => {
+            ensure!(selections.is_empty(), "{name} has no fields to select");
+            values
+                .iter()
+                .find(|(_, wit)| *wit == case)
+                .map(|(gql, _)| Value::String(gql.clone()))
+                .with_context(|| format!("'{case}' is not a value of enum {name}"))
+        }
+        (Shape::List(element), Value::Array(values)) => values
+            .into_iter()
+            .map(|v| select(element, v, selections))
+            .collect::<anyhow::Result<_>>()
+            .map(Value::Array),
+        (
+            Shape::Object {
+                name,
+                fields,
+                tuple,
+            },
+            value,
+        ) => {
+            ensure!(!selections.is_empty(), "fields of {name} must be selected");
+            let mut object = Map::new();
+            for selection in selections {
+                let key = selection.response_key().to_string();
+                ensure!(
+                    selection.arguments.is_empty(),
+                    "field '{}' of {name} takes no arguments",
+                    selection.name
+                );
+                if selection.name == "__typename" {
+                    object.insert(key, Value::String(name.clone()));
+                    continue;
+                }
+                let Some((_, wit, field_shape)) =
+                    fields.iter().find(|(gql, _, _)| *gql == selection.name)
+                else {
+                    bail!("{name} has no field '{}'", selection.name);
+                };
+                let field_value = if *tuple {
+                    wit.parse::<usize>()
+                        .ok()
+                        .and_then(|i| value.get(i))
+                        .cloned()
+                } else {
+                    value.get(wit).cloned()
+                };
+                object.insert(
+                    key,
+                    select(
+                        field_shape,
+                        field_value.unwrap_or(Value::Null),
+                        &selection.selections,
+                    )?,
+                );
+            }
+            Ok(Value::Object(object))
+        }
+        (shape, value) => bail!(
+            "unexpected value {value} for type {}",
+            shape.nullable_type_ref()
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selections(query: &str) -> anyhow::Result<Vec<Selection>> {
+        Ok(parser::parse_document(query)?.remove(0).selections)
+    }
+
+    #[test]
+    fn test_select() -> anyhow::Result<()> {
+        let shape = Shape::List(Box::new(Shape::Object {
+            name: "GetItemsResult".to_string(),
+            fields: vec![
+                (
+                    "skuId".to_string(),
+                    "sku-id".to_string(),
+                    Shape::Scalar("String"),
+                ),
+                (
+                    "color".to_string(),
+                    "color".to_string(),
+                    Shape::Nullable(Box::new(Shape::Enum {
+                        name: "GetItemsResultColor".to_string(),
+                        values: vec![("DARK_RED".to_string(), "dark-red".to_string())],
+                    })),
+                ),
+            ],
+            tuple: false,
+        }));
+        let value = json!([
+            {"sku-id": "a", "color": "dark-red"},
+            {"sku-id": "b", "color": null},
+        ]);
+
+        let root = selections("{ items { id: skuId, color, __typename } }")?;
+        assert_eq!(
+            select(&shape, value.clone(), &root[0].selections)?,
+            json!([
+                {"id": "a", "color": "DARK_RED", "__typename": "GetItemsResult"},
+                {"id": "b", "color": null, "__typename": "GetItemsResult"},
+            ])
+        );
+
+        let unknown = selections("{ items { price } }")?;
+        assert!(select(&shape, value.clone(), &unknown[0].selections).is_err());
+        assert!(select(&shape, value, &[]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_substitute() -> anyhow::Result<()> {
+        let operation =
+            parser::parse_document("query ($limit: Int = 10, $sku: String) { a(x: 1) }")?.remove(0);
+        let variables = Map::from_iter([("sku".to_string(), json!("sku-1"))]);
+        let value = InputValue::Object(vec![
+            ("sku".to_string(), InputValue::Variable("sku".to_string())),
+            (
+                "limit".to_string(),
+                InputValue::Variable("limit".to_string()),
+            ),
+            ("color".to_string(), InputValue::Enum("RED".to_string())),
+        ]);
+        assert_eq!(
+            substitute(&value, &operation, &variables)?,
+            json!({"sku": "sku-1", "limit": 10, "color": "RED"})
+        );
+        assert!(
+            substitute(
+                &InputValue::Variable("missing".to_string()),
+                &operation,
+                &variables
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+}
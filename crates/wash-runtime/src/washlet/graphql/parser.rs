@@ -0,0 +1,440 @@
+//! Parses the subset of GraphQL query documents the gateway executes: operations with
+//! variables, fields with aliases and arguments, and nested selections. Fragments and
+//! directives are rejected.
+
+use anyhow::{Context as _, bail, ensure};
+
+use super::schema::OperationKind;
+
+/// An input value in a query.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum InputValue {
+    Variable(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Boolean(bool),
+    Null,
+    Enum(String),
+    List(Vec<InputValue>),
+    Object(Vec<(String, InputValue)>),
+}
+
+/// A field in a selection set.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Selection {
+    pub alias: Option<String>,
+    pub name: String,
+    pub arguments: Vec<(String, InputValue)>,
+    pub selections: Vec<Selection>,
+}
+
+impl Selection {
+    /// The key of the field in the response.
+    pub(super) fn response_key(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// An operation in a query document.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Operation {
+    pub kind: OperationKind,
+    pub name: Option<String>,
+    /// Variable names and their default values
+    pub variables: Vec<(String, Option<InputValue>)>,
+    pub selections: Vec<Selection>,
+}
+
+/// Parses a query document into its operations.
+///
+/// # Errors
+/// Returns an error if the document isn't valid GraphQL, has no operations, or uses
+/// fragments, directives or subscriptions.
+pub(super) fn parse_document(text: &str) -> anyhow::Result<Vec<Operation>> {
+    let mut parser = Parser { text, pos: 0 };
+    let mut operations = Vec::new();
+    loop {
+        parser.skip_ignored();
+        if parser.rest().is_empty() {
+            break;
+        }
+        operations.push(parser.operation()?);
+    }
+    ensure!(!operations.is_empty(), "the document has no operations");
+    Ok(operations)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    /// Skips whitespace, commas and comments, which GraphQL ignores.
+    fn skip_ignored(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed =
+                rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',' || c == '\u{feff}');
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with('#') {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_ignored();
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> anyhow::Result<()> {
+        ensure!(
+            self.eat(c),
+            "expected '{c}' at offset {}, found {}",
+            self.pos,
+            self.found()
+        );
+        Ok(())
+    }
+
+    fn found(&self) -> String {
+        match self.rest().chars().next() {
+            Some(c) => format!("'{c}'"),
+            None => "end of document".to_string(),
+        }
+    }
+
+    fn name(&mut self) -> anyhow::Result<&'a str> {
+        self.skip_ignored();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        ensure!(
+            len > 0 && !rest.starts_with(|c: char| c.is_ascii_digit()),
+            "expected a name at offset {}, found {}",
+            self.pos,
+            self.found()
+        );
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn reject_unsupported(&mut self) -> anyhow::Result<()> {
+        match self.peek() {
+            Some('@') => bail!("directives aren't supported"),
+            Some('.') => bail!("fragments aren't supported"),
+            _ => Ok(()),
+        }
+    }
+
+    fn operation(&mut self) -> anyhow::Result<Operation> {
+        if self.peek() == Some('{') {
+            return Ok(Operation {
+                kind: OperationKind::Query,
+                name: None,
+                variables: Vec::new(),
+                selections: self.selection_set()?,
+            });
+        }
+        let kind = match self.name()? {
+            "query" => OperationKind::Query,
+            "mutation" => OperationKind::Mutation,
+            "subscription" => bail!("subscriptions aren't supported"),
+            "fragment" => bail!("fragments aren't supported"),
+            other => bail!("expected an operation, found '{other}'"),
+        };
+        let name = match self.peek() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => Some(self.name()?.to_string()),
+            _ => None,
+        };
+        let mut variables = Vec::new();
+        if self.eat('(') {
+            while !self.eat(')') {
+                self.expect('$')?;
+                let variable = self.name()?.to_string();
+                self.expect(':')?;
+                self.type_ref()?;
+                let default = if self.eat('=') {
+                    Some(self.value(true)?)
+                } else {
+                    None
+                };
+                variables.push((variable, default));
+            }
+        }
+        self.reject_unsupported()?;
+        Ok(Operation {
+            kind,
+            name,
+            variables,
+            selections: self.selection_set()?,
+        })
+    }
+
+    /// Skips a variable's type, which the gateway doesn't need since arguments are
+    /// checked against the field they're passed to.
+    fn type_ref(&mut self) -> anyhow::Result<()> {
+        if self.eat('[') {
+            self.type_ref()?;
+            self.expect(']')?;
+        } else {
+            self.name()?;
+        }
+        self.eat('!');
+        Ok(())
+    }
+
+    fn selection_set(&mut self) -> anyhow::Result<Vec<Selection>> {
+        self.expect('{')?;
+        let mut selections = Vec::new();
+        while !self.eat('}') {
+            self.reject_unsupported()?;
+            let name = self.name()?.to_string();
+            let (alias, name) = if self.eat(':') {
+                (Some(name), self.name()?.to_string())
+            } else {
+                (None, name)
+            };
+            let mut arguments = Vec::new();
+            if self.eat('(') {
+                while !self.eat(')') {
+                    let argument = self.name()?.to_string();
+                    self.expect(':')?;
+                    arguments.push((argument, self.value(false)?));
+                }
+            }
+            self.reject_unsupported()?;
+            let selections_of_field = if self.peek() == Some('{') {
+                self.selection_set()?
+            } else {
+                Vec::new()
+            };
+            selections.push(Selection {
+                alias,
+                name,
+                arguments,
+                selections: selections_of_field,
+            });
+        }
+        ensure!(!selections.is_empty(), "selection sets can't be empty");
+        Ok(selections)
+    }
+
+    /// Parses an input value. Default values of variables must be constant.
+    fn value(&mut self, constant: bool) -> anyhow::Result<InputValue> {
+        Ok(match self.peek() {
+            Some('$') if constant => bail!("default values can't reference variables"),
+            Some('$') => {
+                self.pos += 1;
+                InputValue::Variable(self.name()?.to_string())
+            }
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                while !self.eat(']') {
+                    values.push(self.value(constant)?);
+                }
+                InputValue::List(values)
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    let name = self.name()?.to_string();
+                    self.expect(':')?;
+                    fields.push((name, self.value(constant)?));
+                }
+                InputValue::Object(fields)
+            }
+            Some('"') => InputValue::String(self.string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number()?,
+            Some(_) => match self.name()? {
+                "true" => InputValue::Boolean(true),
+                "false" => InputValue::Boolean(false),
+                "null" => InputValue::Null,
+                other => InputValue::Enum(other.to_string()),
+            },
+            None => bail!("expected a value, found end of document"),
+        })
+    }
+
+    fn number(&mut self) -> anyhow::Result<InputValue> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.')))
+            .unwrap_or(rest.len());
+        let literal = &rest[..len];
+        self.pos += len;
+        if literal.contains(['.', 'e', 'E']) {
+            literal
+                .parse()
+                .map(InputValue::Float)
+                .with_context(|| format!("invalid number '{literal}'"))
+        } else {
+            literal
+                .parse()
+                .map(InputValue::Int)
+                .with_context(|| format!("invalid integer '{literal}'"))
+        }
+    }
+
+    fn string(&mut self) -> anyhow::Result<String> {
+        self.expect('"')?;
+        ensure!(
+            !self.rest().starts_with("\"\""),
+            "block strings aren't supported"
+        );
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(value);
+                }
+                '\\' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .with_context(|| format!("invalid escape '\\u{hex}'"))?
+                        }
+                        other => bail!("invalid escape '\\{}'", other.unwrap_or(' ')),
+                    };
+                    value.push(escaped);
+                }
+                '\n' | '\r' => bail!("unterminated string"),
+                c => value.push(c),
+            }
+        }
+        bail!("unterminated string")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_document() -> anyhow::Result<()> {
+        let operations = parse_document(
+            r#"
+            # Look up a product
+            query Stock($sku: String!, $ids: [BigInt!] = [1, 2]) {
+              level: getStock(sku: $sku, filter: {color: DARK_RED, note: "a \"b\"\n"}) {
+                count, warehouse { id }
+              }
+            }
+            mutation { reset }
+            "#,
+        )?;
+        assert_eq!(
+            operations[0],
+            Operation {
+                kind: OperationKind::Query,
+                name: Some("Stock".to_string()),
+                variables: vec![
+                    ("sku".to_string(), None),
+                    (
+                        "ids".to_string(),
+                        Some(InputValue::List(vec![
+                            InputValue::Int(1),
+                            InputValue::Int(2)
+                        ]))
+                    ),
+                ],
+                selections: vec![Selection {
+                    alias: Some("level".to_string()),
+                    name: "getStock".to_string(),
+                    arguments: vec![
+                        ("sku".to_string(), InputValue::Variable("sku".to_string())),
+                        (
+                            "filter".to_string(),
+                            InputValue::Object(vec![
+                                (
+                                    "color".to_string(),
+                                    InputValue::Enum("DARK_RED".to_string())
+                                ),
+                                (
+                                    "note".to_string(),
+                                    InputValue::String("a \"b\"\n".to_string())
+                                ),
+                            ])
+                        ),
+                    ],
+                    selections: vec![
+                        Selection {
+                            alias: None,
+                            name: "count".to_string(),
+                            arguments: vec![],
+                            selections: vec![],
+                        },
+                        Selection {
+                            alias: None,
+                            name: "warehouse".to_string(),
+                            arguments: vec![],
+                            selections: vec![Selection {
+                                alias: None,
+                                name: "id".to_string(),
+                                arguments: vec![],
+                                selections: vec![],
+                            }],
+                        },
+                    ],
+                }],
+            }
+        );
+        assert_eq!(operations[1].kind, OperationKind::Mutation);
+        assert_eq!(operations[1].name, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsupported_documents() {
+        for document in [
+            "",
+            "{ }",
+            "{ a(x: ) }",
+            "{ ...Fields }",
+            "{ a @skip(if: true) }",
+            "subscription { a }",
+            "fragment F on Query { a }",
+            "query ($x: Int = $y) { a }",
+            "{ a(x: \"unterminated) }",
+            "{ a(x: -) }",
+        ] {
+            assert!(
+                parse_document(document).is_err(),
+                "'{document}' should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,519 @@
+//! Derives a GraphQL schema from the functions a service publishes.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use anyhow::{Context as _, bail, ensure};
+use serde_json::Value;
+use wasmtime::component::Type;
+
+use crate::engine::workload::ExportedFunction;
+
+/// Function name prefixes that make a function a query rather than a mutation.
+const QUERY_PREFIXES: [&str; 7] = ["get", "list", "find", "search", "count", "lookup", "query"];
+
+/// The GraphQL shape of a WIT type.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Shape {
+    /// A built-in or custom scalar, see [`scalar_definition`]
+    Scalar(&'static str),
+    Enum {
+        name: String,
+        /// GraphQL value names and the WIT case names they stand for
+        values: Vec<(String, String)>,
+    },
+    /// A record, or a tuple with fields `_0`, `_1`, ...
+    Object {
+        name: String,
+        /// GraphQL field names, the WIT field names they stand for, and their shapes
+        fields: Vec<(String, String, Shape)>,
+        tuple: bool,
+    },
+    List(Box<Shape>),
+    /// An option, the only WIT type that can be null
+    Nullable(Box<Shape>),
+}
+
+impl Shape {
+    /// Derives the shape of a type, naming the object and enum types it contains after
+    /// `name`. Input types get an `Input` suffix, since GraphQL keeps them apart from
+    /// output types.
+    pub(super) fn of(ty: &Type, name: &str, input: bool) -> Self {
+        let type_name = |name: &str| {
+            if input {
+                format!("{name}Input")
+            } else {
+                name.to_string()
+            }
+        };
+        match ty {
+            Type::Bool => Shape::Scalar("Boolean"),
+            Type::S8 | Type::U8 | Type::S16 | Type::U16 | Type::S32 => Shape::Scalar("Int"),
+            // GraphQL's Int is a signed 32-bit integer
+            Type::U32 | Type::S64 | Type::U64 => Shape::Scalar("BigInt"),
+            Type::Float32 | Type::Float64 => Shape::Scalar("Float"),
+            Type::Char | Type::String => Shape::Scalar("String"),
+            Type::List(list) => Shape::List(Box::new(Shape::of(&list.ty(), name, input))),
+            Type::Option(option) => match option.ty() {
+                // A nested option can't be told apart from the outer one
+                Type::Option(_) => Shape::Scalar("JSON"),
+                inner => Shape::Nullable(Box::new(Shape::of(&inner, name, input))),
+            },
+            Type::Record(record) if record.fields().len() > 0 => Shape::Object {
+                name: type_name(name),
+                fields: record
+                    .fields()
+                    .map(|field| {
+                        let nested = format!("{name}{}", pascal_case(field.name));
+                        (
+                            camel_case(field.name),
+                            field.name.to_string(),
+                            Shape::of(&field.ty, &nested, input),
+                        )
+                    })
+                    .collect(),
+                tuple: false,
+            },
+            Type::Tuple(tuple) => Shape::Object {
+                name: type_name(name),
+                fields: tuple
+                    .types()
+                    .enumerate()
+                    .map(|(i, ty)| {
+                        let nested = format!("{name}{i}");
+                        (
+                            format!("_{i}"),
+                            i.to_string(),
+                            Shape::of(&ty, &nested, input),
+                        )
+                    })
+                    .collect(),
+                tuple: true,
+            },
+            Type::Enum(enum_) => Shape::Enum {
+                name: name.to_string(),
+                values: enum_
+                    .names()
+                    .map(|case| (screaming_case(case), case.to_string()))
+                    .collect(),
+            },
+            // Variants, results and flags have no GraphQL equivalent usable for both input
+            // and output, so they are passed through in their JSON form
+            _ => Shape::Scalar("JSON"),
+        }
+    }
+
+    /// Returns the GraphQL type reference for the shape, e.g. `[String!]!`.
+    pub(super) fn type_ref(&self) -> String {
+        match self {
+            Shape::Nullable(inner) => inner.nullable_type_ref(),
+            other => format!("{}!", other.nullable_type_ref()),
+        }
+    }
+
+    /// Returns the type reference without the outer non-null marker.
+    pub(super) fn nullable_type_ref(&self) -> String {
+        match self {
+            Shape::Scalar(name) => name.to_string(),
+            Shape::Enum { name, .. } | Shape::Object { name, .. } => name.clone(),
+            Shape::List(element) => format!("[{}]", element.type_ref()),
+            Shape::Nullable(inner) => inner.nullable_type_ref(),
+        }
+    }
+
+    /// Converts a GraphQL input value, with variables already substituted, into the JSON
+    /// form of the WIT type (see [`crate::engine::json`]).
+    pub(super) fn input(&self, value: Value) -> anyhow::Result<Value> {
+        match (self, value) {
+            (Shape::Nullable(_), Value::Null) => Ok(Value::Null),
+            (Shape::Nullable(inner), value) => inner.input(value),
+            (Shape::Scalar("JSON"), value) => Ok(value),
+            (_, Value::Null) => bail!("expected a value of type {}, got null", self.type_ref()),
+            (Shape::Scalar("BigInt"), Value::String(s)) => {
+                Ok(Value::Number(s.parse().with_context(|| {
+                    format!("expected an integer, got '{s}'")
+                })?))
+            }
+            (Shape::Scalar(_), value) => Ok(value),
+            (Shape::Enum { name, values }, Value::String(s)) => values
+                .iter()
+                .find(|(gql, _)| *gql == s)
+                .map(|(_, wit)| Value::String(wit.clone()))
+                .with_context(|| format!("'{s}' is not a value of enum {name}")),
+            (Shape::Enum { name, .. }, _) => bail!("expected a value of enum {name}"),
+            (Shape::List(element), Value::Array(values)) => values
+                .into_iter()
+                .map(|v| element.input(v))
+                .collect::<anyhow::Result<_>>()
+                .map(Value::Array),
+            // GraphQL coerces a single value to a list of one
+            (Shape::List(element), value) => Ok(Value::Array(vec![element.input(value)?])),
+            (
+                Shape::Object {
+                    name,
+                    fields,
+                    tuple,
+                },
+                Value::Object(mut object),
+            ) => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (gql, wit, shape) in fields {
+                    let value = shape
+                        .input(object.remove(gql).unwrap_or(Value::Null))
+                        .with_context(|| format!("in field '{gql}'"))?;
+                    values.push((wit.clone(), value));
+                }
+                if let Some(unknown) = object.keys().next() {
+                    bail!("input type {name} has no field '{unknown}'");
+                }
+                Ok(if *tuple {
+                    Value::Array(values.into_iter().map(|(_, v)| v).collect())
+                } else {
+                    Value::Object(values.into_iter().collect())
+                })
+            }
+            (Shape::Object { name, .. }, _) => bail!("expected an object of type {name}"),
+        }
+    }
+
+    /// Adds the object and enum types the shape contains to `types`, keyed by name.
+    fn collect_types(&self, input: bool, types: &mut BTreeMap<String, String>) {
+        match self {
+            Shape::Scalar(name) => {
+                if let Some(definition) = scalar_definition(name) {
+                    types.insert(name.to_string(), definition.to_string());
+                }
+            }
+            Shape::Enum { name, values } => {
+                let mut definition = format!("enum {name} {{\n");
+                for (gql, _) in values {
+                    let _ = writeln!(definition, "  {gql}");
+                }
+                definition.push('}');
+                types.insert(name.clone(), definition);
+            }
+            Shape::Object { name, fields, .. } => {
+                let keyword = if input { "input" } else { "type" };
+                let mut definition = format!("{keyword} {name} {{\n");
+                for (gql, _, shape) in fields {
+                    let _ = writeln!(definition, "  {gql}: {}", shape.type_ref());
+                    shape.collect_types(input, types);
+                }
+                definition.push('}');
+                types.insert(name.clone(), definition);
+            }
+            Shape::List(inner) | Shape::Nullable(inner) => inner.collect_types(input, types),
+        }
+    }
+}
+
+/// Returns the definition of a custom scalar, or `None` for built-in scalars.
+fn scalar_definition(name: &str) -> Option<&'static str> {
+    match name {
+        "BigInt" => Some(
+            "\"An integer that may not fit in 32 bits, as a number or a string of digits\"\nscalar BigInt",
+        ),
+        "JSON" => Some("\"A variant, result or flags value in the host's JSON form\"\nscalar JSON"),
+        _ => None,
+    }
+}
+
+/// Whether a root field is a query or a mutation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum OperationKind {
+    Query,
+    Mutation,
+}
+
+impl OperationKind {
+    pub(super) fn type_name(self) -> &'static str {
+        match self {
+            OperationKind::Query => "Query",
+            OperationKind::Mutation => "Mutation",
+        }
+    }
+}
+
+/// A root field, backed by an exported function.
+#[derive(Debug, Clone)]
+pub(super) struct RootField {
+    pub name: String,
+    pub kind: OperationKind,
+    pub function: ExportedFunction,
+    /// GraphQL argument names and the shapes of the parameters
+    pub args: Vec<(String, Shape)>,
+    /// The shape of the result, `Boolean` for functions without one
+    pub output: Shape,
+    /// Whether the function returns a `result` whose `ok` value is the field's value and
+    /// whose `err` value becomes a field error
+    pub unwrap_result: bool,
+}
+
+/// The schema of a service.
+#[derive(Debug, Clone)]
+pub(super) struct Schema {
+    pub service: String,
+    pub fields: Vec<RootField>,
+}
+
+impl Schema {
+    /// Derives the schema of a service from the functions it publishes.
+    ///
+    /// Functions named like reads, starting with `get`, `list`, `find`, `search`, `count`,
+    /// `lookup` or `query`, are query fields, and all others are mutation fields. Fields
+    /// are named after their function in camel case, prefixed with the interface name if
+    /// several interfaces export a function with the same name.
+    ///
+    /// # Errors
+    /// Returns an error if a function returns more than one value.
+    pub(super) fn derive(service: &str, functions: Vec<ExportedFunction>) -> anyhow::Result<Self> {
+        let mut fields = Vec::with_capacity(functions.len());
+        for function in &functions {
+            ensure!(
+                function.results.len() <= 1,
+                "'{}#{}' returns more than one value",
+                function.interface,
+                function.name
+            );
+            let shared = functions.iter().filter(|f| f.name == function.name).count() > 1;
+            let name = if shared {
+                let interface = function
+                    .interface
+                    .rsplit_once('/')
+                    .map_or(function.interface.as_str(), |(_, name)| name);
+                let interface = interface
+                    .split_once('@')
+                    .map_or(interface, |(name, _)| name);
+                format!("{}{}", camel_case(interface), pascal_case(&function.name))
+            } else {
+                camel_case(&function.name)
+            };
+            let type_prefix = pascal_case(&name);
+
+            let first_word = function.name.split('-').next().unwrap_or_default();
+            let kind = if QUERY_PREFIXES.contains(&first_word) {
+                OperationKind::Query
+            } else {
+                OperationKind::Mutation
+            };
+            let args = function
+                .params
+                .iter()
+                .map(|(param, ty)| {
+                    let shape =
+                        Shape::of(ty, &format!("{type_prefix}{}", pascal_case(param)), true);
+                    (camel_case(param), shape)
+                })
+                .collect();
+            let result_name = format!("{type_prefix}Result");
+            let (output, unwrap_result) = match function.results.first() {
+                None => (Shape::Scalar("Boolean"), false),
+                Some(Type::Result(result)) => match result.ok() {
+                    Some(ok) => (Shape::of(&ok, &result_name, false), true),
+                    None => (Shape::Scalar("Boolean"), true),
+                },
+                Some(ty) => (Shape::of(ty, &result_name, false), false),
+            };
+            fields.push(RootField {
+                name,
+                kind,
+                function: function.clone(),
+                args,
+                output,
+                unwrap_result,
+            });
+        }
+        Ok(Self {
+            service: service.to_string(),
+            fields,
+        })
+    }
+
+    /// Returns the root field of an operation with the given name.
+    pub(super) fn field(&self, kind: OperationKind, name: &str) -> Option<&RootField> {
+        self.fields
+            .iter()
+            .find(|f| f.kind == kind && f.name == name)
+    }
+
+    /// Renders the schema in the GraphQL schema definition language.
+    pub(super) fn sdl(&self) -> String {
+        let mut types = BTreeMap::new();
+        let mut sdl = String::new();
+        for kind in [OperationKind::Query, OperationKind::Mutation] {
+            let fields: Vec<&RootField> = self.fields.iter().filter(|f| f.kind == kind).collect();
+            // The query type can't be empty, so it always has a field naming the service
+            if fields.is_empty() && kind == OperationKind::Mutation {
+                continue;
+            }
+            let _ = writeln!(sdl, "type {} {{", kind.type_name());
+            if kind == OperationKind::Query {
+                sdl.push_str("  \"The name of the service\"\n  _service: String!\n");
+            }
+            for field in fields {
+                let _ = writeln!(
+                    sdl,
+                    "  \"{}#{}\"",
+                    field.function.interface, field.function.name
+                );
+                let args = field
+                    .args
+                    .iter()
+                    .map(|(name, shape)| format!("{name}: {}", shape.type_ref()))
+                    .collect::<Vec<_>>();
+                let args = if args.is_empty() {
+                    String::new()
+                } else {
+                    format!("({})", args.join(", "))
+                };
+                // Root fields are nullable so a failed call doesn't null the whole response
+                let _ = writeln!(
+                    sdl,
+                    "  {}{args}: {}",
+                    field.name,
+                    field.output.nullable_type_ref()
+                );
+                for (_, shape) in &field.args {
+                    shape.collect_types(true, &mut types);
+                }
+                field.output.collect_types(false, &mut types);
+            }
+            sdl.push_str("}\n\n");
+        }
+        for definition in types.values() {
+            sdl.push_str(definition);
+            sdl.push_str("\n\n");
+        }
+        sdl.truncate(sdl.trim_end().len());
+        sdl.push('\n');
+        sdl
+    }
+}
+
+/// Converts a kebab-case WIT name to camelCase, e.g. `get-stock` to `getStock`.
+pub(super) fn camel_case(name: &str) -> String {
+    let pascal = pascal_case(name);
+    let mut chars = pascal.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Converts a kebab-case WIT name to PascalCase, e.g. `get-stock` to `GetStock`.
+pub(super) fn pascal_case(name: &str) -> String {
+    name.split('-')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+/// Converts a kebab-case WIT name to SCREAMING_SNAKE_CASE, e.g. `in-stock` to `IN_STOCK`.
+pub(super) fn screaming_case(name: &str) -> String {
+    name.replace('-', "_").to_ascii_uppercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn function(
+        interface: &str,
+        name: &str,
+        params: &[(&str, Type)],
+        results: &[Type],
+    ) -> ExportedFunction {
+        ExportedFunction {
+            interface: interface.to_string(),
+            name: name.to_string(),
+            params: params
+                .iter()
+                .map(|(name, ty)| (name.to_string(), ty.clone()))
+                .collect(),
+            results: results.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(camel_case("get-stock-level"), "getStockLevel");
+        assert_eq!(pascal_case("get-HTTP-status"), "GetHTTPStatus");
+        assert_eq!(screaming_case("in-stock"), "IN_STOCK");
+    }
+
+    #[test]
+    fn test_sdl() -> anyhow::Result<()> {
+        let schema = Schema::derive(
+            "inventory",
+            vec![
+                function(
+                    "acme:inventory/query@0.1.0",
+                    "get-stock",
+                    &[("sku", Type::String), ("warehouse-id", Type::U64)],
+                    &[Type::S32],
+                ),
+                function("acme:inventory/admin", "reset", &[], &[]),
+                function(
+                    "acme:inventory/audit",
+                    "reset",
+                    &[("reason", Type::String)],
+                    &[],
+                ),
+            ],
+        )?;
+        assert_eq!(
+            schema.sdl(),
+            r#"type Query {
+  "The name of the service"
+  _service: String!
+  "acme:inventory/query@0.1.0#get-stock"
+  getStock(sku: String!, warehouseId: BigInt!): Int
+}
+
+type Mutation {
+  "acme:inventory/admin#reset"
+  adminReset: Boolean
+  "acme:inventory/audit#reset"
+  auditReset(reason: String!): Boolean
+}
+
+"An integer that may not fit in 32 bits, as a number or a string of digits"
+scalar BigInt
+"#
+        );
+        assert!(schema.field(OperationKind::Query, "getStock").is_some());
+        assert!(schema.field(OperationKind::Mutation, "getStock").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_input() -> anyhow::Result<()> {
+        let big = Shape::of(&Type::U64, "Id", true);
+        assert_eq!(big.input(json!("18446744073709551615"))?, json!(u64::MAX));
+        assert_eq!(big.input(json!(7))?, json!(7));
+        assert!(big.input(json!("seven")).is_err());
+        assert!(big.input(json!(null)).is_err());
+
+        let list = Shape::List(Box::new(Shape::Scalar("String")));
+        assert_eq!(list.input(json!("a"))?, json!(["a"]));
+        assert_eq!(list.type_ref(), "[String!]!");
+
+        let nullable = Shape::Nullable(Box::new(Shape::Scalar("Int")));
+        assert_eq!(nullable.input(json!(null))?, json!(null));
+        assert_eq!(nullable.type_ref(), "Int");
+
+        let color = Shape::Enum {
+            name: "Color".to_string(),
+            values: vec![("DARK_RED".to_string(), "dark-red".to_string())],
+        };
+        assert_eq!(color.input(json!("DARK_RED"))?, json!("dark-red"));
+        assert!(color.input(json!("BLUE")).is_err());
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
+use crate::host::error::HostError;
 use crate::host::{Host, HostApi};
 use crate::oci::{self, OciConfig};
 use crate::plugin::HostPlugin;
@@ -12,7 +14,12 @@ use opentelemetry_semantic_conventions::resource;
 use sysinfo::System;
 use tokio::sync::oneshot;
 
+pub mod gateway;
+pub mod graphql;
+pub mod peers;
 pub mod plugins;
+#[cfg(feature = "profiling")]
+pub mod profiler;
 
 pub const HOST_API_PREFIX: &str = "runtime.host";
 pub const OPERATOR_API_PREFIX: &str = "runtime.operator";
@@ -34,6 +41,11 @@ pub struct ClusterHostBuilder {
     host_group: Option<String>,
     host_name: Option<String>,
     heartbeat_interval: Option<Duration>,
+    json_gateway_addr: Option<SocketAddr>,
+    graphql_gateway_addr: Option<SocketAddr>,
+    peer_components: bool,
+    #[cfg(feature = "profiling")]
+    profiler_addr: Option<SocketAddr>,
 }
 
 impl ClusterHostBuilder {
@@ -62,6 +74,110 @@ impl ClusterHostBuilder {
         Ok(self)
     }
 
+    /// Registers a work queue source for queue invokers, see [`crate::host::invoker`].
+    pub fn with_queue_source(
+        mut self,
+        name: impl Into<String>,
+        source: Arc<dyn crate::host::invoker::QueueSource>,
+    ) -> anyhow::Result<Self> {
+        self.host_builder = self.host_builder.with_queue_source(name, source)?;
+        Ok(self)
+    }
+
+    /// Lets workloads call services on other hosts, and other hosts call this host's
+    /// services, see [`crate::host::wrpc`].
+    pub fn with_wrpc_transport(mut self, wrpc: Arc<crate::host::wrpc::WrpcTransport>) -> Self {
+        self.host_builder = self.host_builder.with_wrpc_transport(wrpc);
+        self
+    }
+
+    /// Serves the gRPC services workloads declare, and those generated for the interfaces
+    /// they publish, on the given address, see [`crate::host::grpc`].
+    pub fn with_grpc_ingress_addr(mut self, addr: SocketAddr) -> Self {
+        self.host_builder = self.host_builder.with_grpc_ingress_addr(addr);
+        self
+    }
+
+    /// Manages API keys in `store` through the host API, see [`crate::host::api_keys`].
+    pub fn with_api_keys(mut self, store: Arc<crate::host::api_keys::ApiKeyStore>) -> Self {
+        self.host_builder = self.host_builder.with_api_keys(store);
+        self
+    }
+
+    /// Enforces the CPU limits and pinning of workloads with cgroups, see
+    /// [`crate::host::cgroups`].
+    pub fn with_cgroups(mut self, cgroups: Arc<crate::host::cgroups::Cgroups>) -> Self {
+        self.host_builder = self.host_builder.with_cgroups(cgroups);
+        self
+    }
+
+    /// Only starts workloads whose host path volumes lie within one of `dirs`, see
+    /// [`crate::host::volumes`].
+    pub fn with_host_path_volumes(
+        mut self,
+        dirs: impl IntoIterator<Item = std::path::PathBuf>,
+    ) -> Self {
+        self.host_builder = self.host_builder.with_host_path_volumes(dirs);
+        self
+    }
+
+    /// Lets workloads mount the containers of `blobstore` as volumes, see
+    /// [`crate::host::blob_volumes`].
+    pub fn with_volume_blobstore(
+        mut self,
+        blobstore: Arc<dyn crate::host::blob_volumes::VolumeBlobstore>,
+    ) -> Self {
+        self.host_builder = self.host_builder.with_volume_blobstore(blobstore);
+        self
+    }
+
+    /// Logs a sample of the files running workloads touch in their volumes, see
+    /// [`crate::host::fs_audit`].
+    pub fn with_fs_audit(mut self, audit: Arc<crate::host::fs_audit::FsAudit>) -> Self {
+        self.host_builder = self.host_builder.with_fs_audit(audit);
+        self
+    }
+
+    /// Shares the components the host compiles with the cluster's other hosts through
+    /// `store`, see [`crate::host::artifacts`].
+    pub fn with_artifact_store(
+        mut self,
+        store: Arc<dyn crate::host::artifacts::ArtifactStore>,
+    ) -> Self {
+        self.host_builder = self.host_builder.with_artifact_store(store);
+        self
+    }
+
+    /// Serves the interfaces published by named workload services as JSON over HTTP on
+    /// the given address. See [`gateway`].
+    pub fn with_json_gateway_addr(mut self, addr: SocketAddr) -> Self {
+        self.json_gateway_addr = Some(addr);
+        self
+    }
+
+    /// Serves a GraphQL endpoint for each named workload service on the given address.
+    /// See [`graphql`].
+    pub fn with_graphql_gateway_addr(mut self, addr: SocketAddr) -> Self {
+        self.graphql_gateway_addr = Some(addr);
+        self
+    }
+
+    /// Fetches the components workloads need from the cluster's other hosts when they
+    /// have them, rather than from the registry, and serves the components this host pulls
+    /// to them. See [`peers`].
+    pub fn with_peer_components(mut self, enable: bool) -> Self {
+        self.peer_components = enable;
+        self
+    }
+
+    /// Serves CPU and heap profiles of the host process on the given address. See
+    /// [`profiler`].
+    #[cfg(feature = "profiling")]
+    pub fn with_profiler_addr(mut self, addr: SocketAddr) -> Self {
+        self.profiler_addr = Some(addr);
+        self
+    }
+
     pub fn with_http_handler(
         mut self,
         http_handler: Arc<dyn crate::host::http::HostHandler>,
@@ -87,10 +203,18 @@ impl ClusterHostBuilder {
         }
         let heartbeat_interval = self.heartbeat_interval.unwrap_or(HEARTBEAT_INTERVAL);
         let host = builder.build()?;
+        let peer_components = self
+            .peer_components
+            .then(|| Arc::new(peers::PeerComponents::new(nats_client.clone())));
         Ok(ClusterHost {
             prepared_host: host,
             nats_client,
+            peer_components,
             heartbeat_interval,
+            json_gateway_addr: self.json_gateway_addr,
+            graphql_gateway_addr: self.graphql_gateway_addr,
+            #[cfg(feature = "profiling")]
+            profiler_addr: self.profiler_addr,
         })
     }
 }
@@ -98,7 +222,12 @@ impl ClusterHostBuilder {
 pub struct ClusterHost {
     prepared_host: Host,
     nats_client: Arc<async_nats::Client>,
+    peer_components: Option<Arc<peers::PeerComponents>>,
     heartbeat_interval: Duration,
+    json_gateway_addr: Option<SocketAddr>,
+    graphql_gateway_addr: Option<SocketAddr>,
+    #[cfg(feature = "profiling")]
+    profiler_addr: Option<SocketAddr>,
 }
 
 impl ClusterHost {
@@ -122,6 +251,21 @@ pub async fn run_cluster_host(
     let host_id = host.id().to_string();
     let host = host.clone();
 
+    let json_gateway = cluster_host
+        .json_gateway_addr
+        .map(|addr| tokio::spawn(gateway::serve(host.clone(), addr)));
+    let graphql_gateway = cluster_host
+        .graphql_gateway_addr
+        .map(|addr| tokio::spawn(graphql::serve(host.clone(), addr)));
+    #[cfg(feature = "profiling")]
+    let profiler = cluster_host
+        .profiler_addr
+        .map(|addr| tokio::spawn(profiler::serve(addr)));
+    let peer_components = cluster_host.peer_components.clone();
+    let peer_server = peer_components
+        .clone()
+        .map(|peers| tokio::spawn(peers.serve()));
+
     let task = tokio::task::spawn(async move {
         let host_subject = host_subject(host_id.as_ref());
 
@@ -149,7 +293,7 @@ pub async fn run_cluster_host(
                 }
                 // Handle API requests
                 Some(msg) = api_subscription.next() => {
-                    let response = handle_command(host.as_ref(), &msg).await;
+                    let response = handle_command(host.as_ref(), peer_components.as_deref(), &msg).await;
                     match response {
                         Ok(resp_bytes) => {
                             if let Some(reply_to) = msg.reply {
@@ -158,6 +302,14 @@ pub async fn run_cluster_host(
                         }
                         Err(e) => {
                             eprintln!("Error handling command: {}", e);
+                            // Tell the caller why its request failed, rather than letting it time out
+                            if let Some(reply_to) = msg.reply {
+                                let error = serde_json::json!({
+                                    "error": format!("{e:#}"),
+                                    "code": HostError::of(&e).code(),
+                                });
+                                nats_client.publish(reply_to, error.to_string().into()).await.context("failed to publish API error")?;
+                            }
                         }
                     }
                 }
@@ -167,6 +319,19 @@ pub async fn run_cluster_host(
 
     Ok(async move {
         let _ = one_shot_tx.send(());
+        if let Some(json_gateway) = json_gateway {
+            json_gateway.abort();
+        }
+        if let Some(graphql_gateway) = graphql_gateway {
+            graphql_gateway.abort();
+        }
+        if let Some(peer_server) = peer_server {
+            peer_server.abort();
+        }
+        #[cfg(feature = "profiling")]
+        if let Some(profiler) = profiler {
+            profiler.abort();
+        }
         task.await?
     })
 }
@@ -208,6 +373,7 @@ fn from_api<'de, T: serde::Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, anyh
 
 async fn handle_command(
     host: &impl HostApi,
+    peers: Option<&peers::PeerComponents>,
     msg: &async_nats::Message,
 ) -> Result<Vec<u8>, anyhow::Error> {
     let command = msg.subject.split('.').skip(3).collect::<Vec<_>>().join(".");
@@ -221,7 +387,7 @@ async fn handle_command(
         }
         "workload.start" => {
             let req: types::v2::WorkloadStartRequest = from_api(payload)?;
-            let res = workload_start(host, req).await?;
+            let res = workload_start(host, peers, req).await?;
             to_api(&res)
         }
         "workload.stop" => {
@@ -255,8 +421,24 @@ async fn host_heartbeat(host: &impl HostApi) -> anyhow::Result<types::v2::HostHe
     Ok(hb.into())
 }
 
+/// Pulls a component image, from the cluster's other hosts if `peers` is set and they
+/// have it.
+async fn pull_component(
+    image: &str,
+    config: OciConfig,
+    peers: Option<&peers::PeerComponents>,
+) -> anyhow::Result<(Vec<u8>, String, std::collections::HashMap<String, String>)> {
+    let Some(peers) = peers else {
+        return oci::pull_component_with_annotations(image, config).await;
+    };
+    let pulled = oci::pull_component_from(image, config, peers).await?;
+    peers.insert(pulled.0.clone().into());
+    Ok(pulled)
+}
+
 async fn workload_start(
     host: &impl HostApi,
+    peers: Option<&peers::PeerComponents>,
     req: types::v2::WorkloadStartRequest,
 ) -> anyhow::Result<types::v2::WorkloadStartResponse> {
     let Some(types::v2::Workload {
@@ -266,31 +448,48 @@ async fn workload_start(
         service,
         wit_world,
         volumes,
+        secret_config_keys,
     }) = req.workload
     else {
         anyhow::bail!("workload is required");
     };
+    // A dry run reports every image that can't be pulled before giving up
+    let mut diagnostics = Vec::new();
     let (components, host_interfaces) = if let Some(wit_world) = wit_world {
         let mut pulled_components = Vec::with_capacity(wit_world.components.len());
-        for component in &wit_world.components {
+        for (i, component) in wit_world.components.iter().enumerate() {
             let oci_config = image_pull_secret_to_oci_config(&component.image_pull_secret);
-            let bytes = match oci::pull_component(&component.image, oci_config).await {
-                Ok(bytes) => bytes,
-                Err(e) => {
-                    return Ok(types::v2::WorkloadStartResponse {
-                        workload_status: Some(types::v2::WorkloadStatus {
-                            workload_id: "".into(),
-                            workload_state: types::v2::WorkloadState::Error.into(),
-                            message: format!(
-                                "failed to pull component image {}: {}",
-                                component.image, e
-                            ),
-                        }),
-                    });
-                }
-            };
+            let (bytes, digest, annotations) =
+                match pull_component(&component.image, oci_config, peers).await {
+                    Ok(pulled) => pulled,
+                    Err(e) if req.dry_run => {
+                        diagnostics.push(pull_diagnostic(
+                            format!("components[{i}]"),
+                            &component.image,
+                            e,
+                        ));
+                        continue;
+                    }
+                    Err(e) => {
+                        return Ok(types::v2::WorkloadStartResponse {
+                            workload_status: Some(types::v2::WorkloadStatus {
+                                workload_id: "".into(),
+                                workload_state: types::v2::WorkloadState::Error.into(),
+                                message: format!(
+                                    "failed to pull component image {}: {}",
+                                    component.image, e
+                                ),
+                                command_result: None,
+                                job_status: None,
+                                cron_job_status: None,
+                            }),
+                            diagnostics: Vec::new(),
+                            warnings: Vec::new(),
+                        });
+                    }
+                };
             pulled_components.push(crate::types::Component {
-                bytes: bytes.0.into(),
+                bytes: bytes.into(),
                 local_resources: component
                     .local_resources
                     .clone()
@@ -298,6 +497,11 @@ async fn workload_start(
                     .unwrap_or_default(),
                 pool_size: component.pool_size,
                 max_invocations: component.max_invocations,
+                source: Some(crate::types::ComponentSource {
+                    image: component.image.clone(),
+                    digest,
+                    annotations,
+                }),
             })
         }
         (
@@ -314,30 +518,59 @@ async fn workload_start(
 
     let service = if let Some(service) = service {
         let oci_config = image_pull_secret_to_oci_config(&service.image_pull_secret);
-        let bytes = match oci::pull_component(&service.image, oci_config).await {
-            Ok(bytes) => bytes,
+        let (bytes, digest, annotations) = match pull_component(&service.image, oci_config, peers)
+            .await
+        {
+            Ok(pulled) => pulled,
+            Err(e) if req.dry_run => {
+                diagnostics.push(pull_diagnostic("service".to_string(), &service.image, e));
+                return Ok(dry_run_response(diagnostics));
+            }
             Err(e) => {
                 return Ok(types::v2::WorkloadStartResponse {
                     workload_status: Some(types::v2::WorkloadStatus {
                         workload_id: "".into(),
                         workload_state: types::v2::WorkloadState::Error.into(),
                         message: format!("failed to pull service image {}: {}", service.image, e),
+                        command_result: None,
+                        job_status: None,
+                        cron_job_status: None,
                     }),
+                    diagnostics: Vec::new(),
+                    warnings: Vec::new(),
                 });
             }
         };
         Some(crate::types::Service {
-            bytes: bytes.0.into(),
+            bytes: bytes.into(),
             local_resources: service
                 .local_resources
                 .clone()
                 .map(Into::into)
                 .unwrap_or_default(),
             max_restarts: service.max_restarts,
+            name: (!service.name.is_empty()).then(|| service.name.clone()),
+            exports: service.exports.iter().cloned().map(Into::into).collect(),
+            command: service
+                .command
+                .as_ref()
+                .map(|command| crate::types::Command {
+                    stdin: command.stdin.clone().into(),
+                    job: command.job.as_ref().map(Into::into),
+                    cron_job: command.cron_job.as_ref().map(Into::into),
+                }),
+            source: Some(crate::types::ComponentSource {
+                image: service.image.clone(),
+                digest,
+                annotations,
+            }),
         })
     } else {
         None
     };
+    if !diagnostics.is_empty() {
+        return Ok(dry_run_response(diagnostics));
+    }
 
     let volumes = volumes.into_iter().map(Into::into).collect();
 
@@ -347,6 +580,7 @@ async fn workload_start(
             namespace,
             name,
             annotations,
+            secret_config_keys: secret_config_keys.into_iter().collect(),
             service,
             components,
             host_interfaces,
@@ -354,7 +588,100 @@ async fn workload_start(
         },
     };
 
-    Ok(host.workload_start(request).await?.into())
+    // Warnings don't keep the workload from starting, they're returned with its status
+    let warnings = lint_workload(host, &request.workload).await;
+    let mut response = if req.dry_run {
+        let validated = host
+            .workload_validate(crate::types::WorkloadValidateRequest {
+                workload: request.workload,
+            })
+            .await?;
+        dry_run_response(validated.diagnostics.into_iter().map(Into::into).collect())
+    } else {
+        host.workload_start(request).await?.into()
+    };
+    response.warnings = warnings;
+    Ok(response)
+}
+
+/// Lints a workload before it's started, logging its warnings.
+async fn lint_workload(
+    host: &impl HostApi,
+    workload: &crate::types::Workload,
+) -> Vec<types::v2::WorkloadWarning> {
+    let linted = host
+        .workload_lint(crate::types::WorkloadLintRequest {
+            workload: workload.clone(),
+        })
+        .await;
+    match linted {
+        Ok(linted) => linted
+            .warnings
+            .into_iter()
+            .inspect(|warning| {
+                tracing::warn!(
+                    workload = %workload.name,
+                    location = %warning.location,
+                    code = %warning.code,
+                    "{}",
+                    warning.message
+                );
+            })
+            .map(Into::into)
+            .collect(),
+        Err(e) => {
+            tracing::warn!(err = ?e, workload = %workload.name, "failed to lint workload");
+            Vec::new()
+        }
+    }
+}
+
+/// Reports an image a dry run couldn't pull.
+fn pull_diagnostic(
+    location: String,
+    image: &str,
+    error: anyhow::Error,
+) -> types::v2::WorkloadDiagnostic {
+    let error = error.context(HostError::FetchFailed {
+        image: image.to_string(),
+    });
+    types::v2::WorkloadDiagnostic {
+        location,
+        code: HostError::of(&error).code().to_string(),
+        message: format!("{error:#}"),
+    }
+}
+
+/// The response to a dry run, with the problems it found.
+fn dry_run_response(
+    diagnostics: Vec<types::v2::WorkloadDiagnostic>,
+) -> types::v2::WorkloadStartResponse {
+    let (workload_state, message) = if diagnostics.is_empty() {
+        (
+            types::v2::WorkloadState::Unspecified,
+            "Dry run: workload would start".to_string(),
+        )
+    } else {
+        (
+            types::v2::WorkloadState::Error,
+            format!(
+                "Dry run: workload would not start, found {} problem(s)",
+                diagnostics.len()
+            ),
+        )
+    };
+    types::v2::WorkloadStartResponse {
+        workload_status: Some(types::v2::WorkloadStatus {
+            workload_id: String::new(),
+            workload_state: workload_state.into(),
+            message,
+            command_result: None,
+            job_status: None,
+            cron_job_status: None,
+        }),
+        diagnostics,
+        warnings: Vec::new(),
+    }
 }
 
 async fn workload_stop(
@@ -421,14 +748,20 @@ pub fn resource_builder() -> ResourceBuilder {
 
 impl From<types::v2::WitInterface> for crate::wit::WitInterface {
     fn from(wi: types::v2::WitInterface) -> Self {
+        // The version field holds either an exact version or a requirement like `^0.2`
+        let (version, version_req) = if wi.version.is_empty() {
+            (None, None)
+        } else {
+            match wi.version.parse::<semver::Version>() {
+                Ok(version) => (Some(version), None),
+                Err(_) => (None, wi.version.parse::<semver::VersionReq>().ok()),
+            }
+        };
         crate::wit::WitInterface {
             namespace: wi.namespace,
             package: wi.package,
-            version: if wi.version.is_empty() {
-                None
-            } else {
-                wi.version.parse::<semver::Version>().ok()
-            },
+            version,
+            version_req,
             interfaces: wi.interfaces.into_iter().collect(),
             config: wi.config,
         }
@@ -440,6 +773,7 @@ impl From<types::v2::VolumeMount> for crate::types::VolumeMount {
             name: vm.name,
             mount_path: vm.mount_path,
             read_only: vm.read_only,
+            quota_bytes: Some(vm.quota_bytes).filter(|&bytes| bytes > 0),
         }
     }
 }
@@ -458,6 +792,18 @@ impl From<types::v2::Volume> for crate::types::Volume {
                     types::v2::volume::VolumeType::EmptyDir(_) => {
                         crate::types::VolumeType::EmptyDir(crate::types::EmptyDirVolume {})
                     }
+                    types::v2::volume::VolumeType::Scratch(scratch) => {
+                        crate::types::VolumeType::Scratch(crate::types::ScratchVolume {
+                            size_limit_bytes: Some(scratch.size_limit_bytes)
+                                .filter(|&bytes| bytes > 0),
+                        })
+                    }
+                    types::v2::volume::VolumeType::Blobstore(blobstore) => {
+                        crate::types::VolumeType::Blobstore(crate::types::BlobstoreVolume {
+                            container: blobstore.container,
+                            sync_writes: blobstore.sync_writes,
+                        })
+                    }
                 },
                 None => crate::types::VolumeType::EmptyDir(crate::types::EmptyDirVolume {}),
             },
@@ -474,6 +820,8 @@ impl From<types::v2::LocalResources> for crate::types::LocalResources {
             volume_mounts: lr.volume_mounts.into_iter().map(Into::into).collect(),
             allowed_hosts: lr.allowed_hosts,
             environment: lr.environment,
+            arguments: lr.arguments,
+            inherited_environment: lr.inherited_environment,
         }
     }
 }
@@ -497,6 +845,28 @@ impl From<crate::types::HostHeartbeat> for types::v2::HostHeartbeat {
             system_memory_free: hb.system_memory_free,
             labels: hb.labels,
             friendly_name: hb.friendly_name,
+            plugin_health: hb
+                .plugin_health
+                .into_iter()
+                .map(|(plugin_id, health)| {
+                    let state = match &health {
+                        crate::types::PluginHealth::Healthy => {
+                            types::v2::PluginHealthState::Healthy
+                        }
+                        crate::types::PluginHealth::Degraded(_) => {
+                            types::v2::PluginHealthState::Degraded
+                        }
+                        crate::types::PluginHealth::Unhealthy(_) => {
+                            types::v2::PluginHealthState::Unhealthy
+                        }
+                    };
+                    types::v2::PluginHealth {
+                        plugin_id,
+                        state: state.into(),
+                        message: health.message().unwrap_or_default().to_string(),
+                    }
+                })
+                .collect(),
         }
     }
 }
@@ -506,7 +876,11 @@ impl From<crate::wit::WitInterface> for types::v2::WitInterface {
         types::v2::WitInterface {
             namespace: wi.namespace,
             package: wi.package,
-            version: wi.version.map(|v| v.to_string()).unwrap_or_default(),
+            version: wi
+                .version
+                .map(|v| v.to_string())
+                .or_else(|| wi.version_req.map(|req| req.to_string()))
+                .unwrap_or_default(),
             interfaces: wi.interfaces.into_iter().collect(),
             config: wi.config,
         }
@@ -537,6 +911,28 @@ impl From<crate::types::WorkloadStartResponse> for types::v2::WorkloadStartRespo
     fn from(resp: crate::types::WorkloadStartResponse) -> Self {
         types::v2::WorkloadStartResponse {
             workload_status: Some(resp.workload_status.into()),
+            diagnostics: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+}
+
+impl From<crate::types::WorkloadDiagnostic> for types::v2::WorkloadDiagnostic {
+    fn from(diagnostic: crate::types::WorkloadDiagnostic) -> Self {
+        types::v2::WorkloadDiagnostic {
+            location: diagnostic.location,
+            code: diagnostic.code,
+            message: diagnostic.message,
+        }
+    }
+}
+
+impl From<crate::types::WorkloadWarning> for types::v2::WorkloadWarning {
+    fn from(warning: crate::types::WorkloadWarning) -> Self {
+        types::v2::WorkloadWarning {
+            location: warning.location,
+            code: warning.code,
+            message: warning.message,
         }
     }
 }
@@ -563,6 +959,100 @@ impl From<crate::types::WorkloadStatus> for types::v2::WorkloadStatus {
             workload_id: status.workload_id,
             workload_state: status.workload_state as i32,
             message: status.message,
+            command_result: status.command_result.map(Into::into),
+            job_status: status.job_status.map(Into::into),
+            cron_job_status: status.cron_job_status.map(Into::into),
+        }
+    }
+}
+
+impl From<crate::types::CommandResult> for types::v2::CommandResult {
+    fn from(result: crate::types::CommandResult) -> Self {
+        types::v2::CommandResult {
+            exit_code: result.exit_code,
+            stdout: result.stdout.into(),
+            stderr: result.stderr.into(),
+        }
+    }
+}
+
+impl From<crate::types::JobStatus> for types::v2::JobStatus {
+    fn from(job: crate::types::JobStatus) -> Self {
+        types::v2::JobStatus {
+            state: match job.state {
+                crate::types::JobState::Active => types::v2::JobState::Active,
+                crate::types::JobState::Succeeded => types::v2::JobState::Succeeded,
+                crate::types::JobState::Failed => types::v2::JobState::Failed,
+            }
+            .into(),
+            active: job.active,
+            succeeded: job.succeeded,
+            failed: job.failed,
+            message: job.message,
+        }
+    }
+}
+
+impl From<crate::types::CronJobRun> for types::v2::CronJobRun {
+    fn from(run: crate::types::CronJobRun) -> Self {
+        types::v2::CronJobRun {
+            scheduled_at: Some(run.scheduled_at.into()),
+            finished_at: run.finished_at.map(Into::into),
+            status: Some(run.status.into()),
+            result: run.result.map(Into::into),
+        }
+    }
+}
+
+impl From<crate::types::CronJobStatus> for types::v2::CronJobStatus {
+    fn from(status: crate::types::CronJobStatus) -> Self {
+        types::v2::CronJobStatus {
+            last_schedule_time: status.last_schedule_time.map(Into::into),
+            last_successful_time: status.last_successful_time.map(Into::into),
+            active: status.active.into_iter().map(Into::into).collect(),
+            history: status.history.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<&types::v2::CronJob> for crate::types::CronJob {
+    fn from(cron_job: &types::v2::CronJob) -> Self {
+        let defaults = crate::types::CronJob::default();
+        crate::types::CronJob {
+            schedule: cron_job.schedule.clone(),
+            concurrency_policy: match cron_job.concurrency_policy() {
+                types::v2::ConcurrencyPolicy::Allow => crate::types::ConcurrencyPolicy::Allow,
+                types::v2::ConcurrencyPolicy::Forbid => crate::types::ConcurrencyPolicy::Forbid,
+                types::v2::ConcurrencyPolicy::Replace => crate::types::ConcurrencyPolicy::Replace,
+            },
+            successful_jobs_history_limit: cron_job
+                .successful_jobs_history_limit
+                .unwrap_or(defaults.successful_jobs_history_limit),
+            failed_jobs_history_limit: cron_job
+                .failed_jobs_history_limit
+                .unwrap_or(defaults.failed_jobs_history_limit),
+            starting_deadline: (cron_job.starting_deadline_seconds > 0)
+                .then(|| Duration::from_secs(cron_job.starting_deadline_seconds)),
+            last_schedule_time: cron_job.last_schedule_time.as_ref().and_then(|time| {
+                chrono::DateTime::from_timestamp(time.seconds, time.nanos.try_into().ok()?)
+            }),
+        }
+    }
+}
+
+impl From<&types::v2::Job> for crate::types::Job {
+    fn from(job: &types::v2::Job) -> Self {
+        let defaults = crate::types::Job::default();
+        crate::types::Job {
+            completions: job.completions.max(1),
+            parallelism: job.parallelism.max(1),
+            backoff_limit: job.backoff_limit.unwrap_or(defaults.backoff_limit),
+            backoff: match job.backoff_seconds {
+                0 => defaults.backoff,
+                seconds => Duration::from_secs(seconds),
+            },
+            active_deadline: (job.active_deadline_seconds > 0)
+                .then(|| Duration::from_secs(job.active_deadline_seconds)),
         }
     }
 }
@@ -592,4 +1082,23 @@ mod tests {
             Some(("testuser".to_string(), "testpass".to_string()))
         );
     }
+
+    #[test]
+    fn test_job_defaults() {
+        let job = crate::types::Job::from(&types::v2::Job::default());
+        assert_eq!(job, crate::types::Job::default());
+
+        let job = crate::types::Job::from(&types::v2::Job {
+            completions: 5,
+            parallelism: 2,
+            backoff_limit: Some(0),
+            backoff_seconds: 1,
+            active_deadline_seconds: 60,
+        });
+        assert_eq!(job.completions, 5);
+        assert_eq!(job.parallelism, 2);
+        assert_eq!(job.backoff_limit, 0);
+        assert_eq!(job.backoff, Duration::from_secs(1));
+        assert_eq!(job.active_deadline, Some(Duration::from_secs(60)));
+    }
 }
@@ -0,0 +1,272 @@
+//! Peer-to-peer streaming of component bytes, so a host scheduled with a component another
+//! host of the cluster already pulled fetches it from that host rather than from the
+//! registry, cutting the registry's egress for large fleets.
+//!
+//! Hosts built with [`crate::washlet::ClusterHostBuilder::with_peer_components`] keep the
+//! components they pull in memory, up to [`MAX_CACHED_BYTES`], by the digest of their
+//! layer, and answer requests for them on [`FETCH_SUBJECT`]. To start a workload they
+//! still fetch each component's manifest from the registry, then ask their peers for the
+//! layer it names: the first peer to answer streams the layer back in chunks of
+//! [`CHUNK_SIZE`]. The layer is checked against the manifest's digest, so peers can't
+//! substitute other bytes, and it's pulled from the registry as usual if no peer answers
+//! within [`ANSWER_TIMEOUT`], a chunk doesn't arrive, or the check fails.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use futures::StreamExt as _;
+use sha2::{Digest as _, Sha256};
+use tracing::{debug, warn};
+
+use crate::oci::LayerSource;
+
+/// The subject hosts ask their peers for component layers on, with the layer's digest as
+/// the payload.
+pub const FETCH_SUBJECT: &str = "runtime.components.fetch";
+
+/// The most bytes of a layer sent in one message, below NATS' default payload limit.
+pub const CHUNK_SIZE: usize = 512 * 1024;
+
+/// The most bytes of components a host keeps for its peers.
+pub const MAX_CACHED_BYTES: usize = 1024 * 1024 * 1024;
+
+/// How long a host waits for a peer to answer before pulling from the registry.
+pub const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// How long a host waits for each further chunk of a layer.
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Identifies the peer that sent a chunk, so chunks of other peers answering too are
+/// ignored.
+const PEER_HEADER: &str = "Wash-Peer";
+
+/// The number of chunks a layer is sent in.
+const CHUNKS_HEADER: &str = "Wash-Chunks";
+
+/// The component layers a host pulled, least recently used first.
+struct LayerCache {
+    layers: VecDeque<(String, Bytes)>,
+    bytes: usize,
+    max_bytes: usize,
+}
+
+impl LayerCache {
+    fn new(max_bytes: usize) -> Self {
+        Self {
+            layers: VecDeque::new(),
+            bytes: 0,
+            max_bytes,
+        }
+    }
+
+    fn get(&mut self, digest: &str) -> Option<Bytes> {
+        let index = self
+            .layers
+            .iter()
+            .position(|(cached, _)| cached == digest)?;
+        let entry = self.layers.remove(index)?;
+        let layer = entry.1.clone();
+        self.layers.push_back(entry);
+        Some(layer)
+    }
+
+    fn insert(&mut self, digest: String, layer: Bytes) {
+        if layer.len() > self.max_bytes || self.get(&digest).is_some() {
+            return;
+        }
+        while self.bytes + layer.len() > self.max_bytes {
+            let Some((_, evicted)) = self.layers.pop_front() else {
+                break;
+            };
+            self.bytes -= evicted.len();
+        }
+        self.bytes += layer.len();
+        self.layers.push_back((digest, layer));
+    }
+}
+
+/// The digest a component's layer is addressed by.
+fn layer_digest(layer: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(layer))
+}
+
+/// Shares the components a host pulls with its peers, and fetches those it needs from
+/// them.
+pub struct PeerComponents {
+    client: Arc<async_nats::Client>,
+    /// Tells this host's answers apart from other peers'
+    peer_id: String,
+    cache: Mutex<LayerCache>,
+}
+
+impl PeerComponents {
+    pub fn new(client: Arc<async_nats::Client>) -> Self {
+        Self {
+            client,
+            peer_id: uuid::Uuid::new_v4().to_string(),
+            cache: Mutex::new(LayerCache::new(MAX_CACHED_BYTES)),
+        }
+    }
+
+    /// Keeps a pulled component for peers to fetch.
+    pub(crate) fn insert(&self, component: Bytes) {
+        let digest = layer_digest(&component);
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(digest, component);
+    }
+
+    fn get(&self, digest: &str) -> Option<Bytes> {
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(digest)
+    }
+
+    /// Answers peers' requests for the layers this host has until the subscription ends.
+    ///
+    /// # Errors
+    /// Returns an error if [`FETCH_SUBJECT`] can't be subscribed to.
+    pub(crate) async fn serve(self: Arc<Self>) -> anyhow::Result<()> {
+        let mut requests = self
+            .client
+            .subscribe(FETCH_SUBJECT)
+            .await
+            .context("failed to subscribe for component layer requests")?;
+        while let Some(request) = requests.next().await {
+            let Some(reply) = request.reply else {
+                continue;
+            };
+            let digest = String::from_utf8_lossy(&request.payload);
+            let Some(layer) = self.get(&digest) else {
+                continue;
+            };
+            debug!(%digest, size = layer.len(), "streaming component layer to peer");
+            let peers = self.clone();
+            tokio::spawn(async move {
+                if let Err(e) = peers.stream(reply, layer).await {
+                    warn!(err = ?e, "failed to stream component layer to peer");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Sends a layer to a peer in chunks.
+    async fn stream(&self, reply: async_nats::Subject, layer: Bytes) -> anyhow::Result<()> {
+        let chunks = layer.len().div_ceil(CHUNK_SIZE).max(1);
+        let mut headers = async_nats::HeaderMap::new();
+        headers.insert(PEER_HEADER, self.peer_id.as_str());
+        headers.insert(CHUNKS_HEADER, chunks.to_string().as_str());
+        for index in 0..chunks {
+            let start = index * CHUNK_SIZE;
+            let chunk = layer.slice(start..layer.len().min(start + CHUNK_SIZE));
+            self.client
+                .publish_with_headers(reply.clone(), headers.clone(), chunk)
+                .await
+                .context("failed to publish layer chunk")?;
+        }
+        Ok(())
+    }
+
+    /// Asks peers for a layer and receives it from the first one answering.
+    async fn request(&self, digest: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let inbox = self.client.new_inbox();
+        let mut answers = self
+            .client
+            .subscribe(inbox.clone())
+            .await
+            .context("failed to subscribe for layer chunks")?;
+        self.client
+            .publish_with_reply(FETCH_SUBJECT, inbox, digest.to_string().into())
+            .await
+            .context("failed to request layer from peers")?;
+
+        let Ok(Some(first)) = tokio::time::timeout(ANSWER_TIMEOUT, answers.next()).await else {
+            return Ok(None);
+        };
+        let header = |message: &async_nats::Message, name: &str| {
+            message
+                .headers
+                .as_ref()
+                .and_then(|headers| headers.get(name))
+                .map(|value| value.as_str().to_string())
+        };
+        let peer = header(&first, PEER_HEADER).context("layer chunk names no peer")?;
+        let chunks: usize = header(&first, CHUNKS_HEADER)
+            .and_then(|chunks| chunks.parse().ok())
+            .filter(|chunks| *chunks <= MAX_CACHED_BYTES.div_ceil(CHUNK_SIZE))
+            .context("layer chunk has no valid chunk count")?;
+
+        let mut layer = first.payload.to_vec();
+        let mut received = 1;
+        while received < chunks {
+            let chunk = tokio::time::timeout(CHUNK_TIMEOUT, answers.next())
+                .await
+                .ok()
+                .flatten()
+                .with_context(|| {
+                    format!("peer {peer} stopped streaming after {received} chunks")
+                })?;
+            // Other peers may answer too
+            if header(&chunk, PEER_HEADER).as_deref() != Some(peer.as_str()) {
+                continue;
+            }
+            layer.extend_from_slice(&chunk.payload);
+            received += 1;
+        }
+        debug!(
+            digest,
+            peer,
+            size = layer.len(),
+            "fetched component layer from peer"
+        );
+        Ok(Some(layer))
+    }
+}
+
+#[async_trait::async_trait]
+impl LayerSource for PeerComponents {
+    async fn fetch_layer(&self, digest: &str) -> Option<Vec<u8>> {
+        if let Some(layer) = self.get(digest) {
+            return Some(layer.to_vec());
+        }
+        match self.request(digest).await {
+            Ok(layer) => layer,
+            Err(e) => {
+                warn!(digest, err = ?e, "failed to fetch component layer from peers");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer_cache() {
+        let mut cache = LayerCache::new(16);
+        let small = Bytes::from_static(b"small");
+        cache.insert(layer_digest(&small), small.clone());
+        assert_eq!(cache.get(&layer_digest(&small)), Some(small.clone()));
+        assert_eq!(cache.get("sha256:missing"), None);
+
+        // Filling the cache evicts the least recently used layers
+        let large = Bytes::from_static(b"a larger layer");
+        cache.insert(layer_digest(&large), large.clone());
+        assert_eq!(cache.get(&layer_digest(&small)), None);
+        assert_eq!(cache.get(&layer_digest(&large)), Some(large.clone()));
+
+        cache.insert(layer_digest(&large), large.clone());
+        assert_eq!(cache.bytes, large.len());
+        let oversized = Bytes::from_static(b"a layer over the limit");
+        cache.insert(layer_digest(&oversized), oversized.clone());
+        assert_eq!(cache.get(&layer_digest(&oversized)), None);
+    }
+}
@@ -3,7 +3,12 @@ use std::sync::Arc;
 
 use crate::engine::ctx::Ctx;
 use crate::engine::workload::WorkloadComponent;
+use crate::host::bandwidth::ThrottledReader;
 use crate::plugin::HostPlugin;
+use crate::plugin::blobstore_policy::{ContainerPolicies, ContainerPolicy};
+use crate::plugin::encryption::Encryptor;
+use crate::plugin::profiles::BackendProfiles;
+use crate::plugin::schema::{ConfigSchema, ConfigValueKind};
 use crate::washlet::plugins::WorkloadTracker;
 use crate::wit::{WitInterface, WitWorld};
 use anyhow::Context;
@@ -12,10 +17,12 @@ use futures::StreamExt;
 use tokio::io::AsyncReadExt;
 use tokio::sync::RwLock;
 use wasmtime::component::{HasSelf, Resource};
-use wasmtime_wasi::p2::pipe::{AsyncReadStream, AsyncWriteStream};
+use wasmtime_wasi::p2::pipe::{AsyncReadStream, AsyncWriteStream, MemoryInputPipe};
 use wasmtime_wasi::p2::{InputStream, OutputStream};
 
 const PLUGIN_BLOBSTORE_ID: &str = "wasi-blobstore";
+/// Object metadata key holding the size of an encrypted object's plaintext.
+const PLAINTEXT_SIZE_KEY: &str = "wash-plaintext-size";
 
 mod bindings {
     wasmtime::component::bindgen!({
@@ -50,6 +57,35 @@ pub struct ObjectData {
 pub struct ContainerData {
     pub name: String,
     pub store: ObjectStore,
+    /// The policy the workload configured for this container
+    pub policy: ContainerPolicy,
+}
+
+impl ContainerData {
+    /// Returns whether an object has outlived the container's TTL. Containers created
+    /// with a TTL expire objects natively, this covers containers created without one.
+    fn is_expired(&self, info: &object_store::ObjectInfo) -> bool {
+        let Some(modified) = info.modified else {
+            return false;
+        };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.policy
+            .is_expired(modified.unix_timestamp().max(0) as u64, now)
+    }
+
+    /// Checks that an existing object may be overwritten or deleted.
+    async fn check_mutable(&self, object: &str) -> Result<(), String> {
+        if !self.policy.immutable {
+            return Ok(());
+        }
+        match self.store.info(object).await {
+            Ok(info) if !self.is_expired(&info) => self.policy.check_mutable(&self.name, object),
+            _ => Ok(()),
+        }
+    }
 }
 
 pub struct WorkloadData {
@@ -57,15 +93,45 @@ pub struct WorkloadData {
     pub buckets: HashSet<String>,
     /// Whether the blobstore is read-only for this workload
     pub read_only: bool,
+    /// Policies of the containers the workload uses
+    pub policies: ContainerPolicies,
+    /// The JetStream context of the backend profile the workload selected
+    pub jetstream: Arc<async_nats::jetstream::Context>,
     /// Cancellation token for any ongoing operations
     pub cancel_token: tokio_util::sync::CancellationToken,
 }
 
+/// Returns the size of an object's plaintext, which differs from its stored size when
+/// it's encrypted.
+fn plaintext_size(info: &object_store::ObjectInfo) -> u64 {
+    info.metadata
+        .get(PLAINTEXT_SIZE_KEY)
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(info.size as u64)
+}
+
 /// Resource representation for an incoming value (data being read)
 pub struct IncomingValueHandle {
     pub object: Object,
     pub start: u64,
     pub end: u64,
+    /// Decrypts the object if the plugin encrypts objects
+    pub encryptor: Option<Encryptor>,
+}
+
+impl IncomingValueHandle {
+    /// Reads and decrypts the whole object, returning the requested range of it.
+    async fn read_plaintext(&mut self, encryptor: &Encryptor) -> anyhow::Result<Vec<u8>> {
+        let mut sealed = Vec::new();
+        self.object.read_to_end(&mut sealed).await?;
+        let info = self.object.info();
+        let mut data = encryptor.open(&info.bucket, &info.name, &sealed).await?;
+        let end = self.end.min(data.len() as u64) as usize;
+        let start = (self.start as usize).min(end);
+        data.truncate(end);
+        data.drain(..start);
+        Ok(data)
+    }
 }
 
 /// Resource representation for an outgoing value (data being written)
@@ -85,18 +151,61 @@ pub struct StreamObjectNamesHandle {
 /// NATS blobstore plugin
 #[derive(Clone)]
 pub struct WasiBlobstore {
-    client: Arc<async_nats::jetstream::Context>,
+    profiles: BackendProfiles<async_nats::jetstream::Context>,
     tracker: Arc<RwLock<WorkloadTracker<WorkloadData, ()>>>,
+    /// Encrypts objects before they are written to NATS, if set
+    encryptor: Option<Encryptor>,
 }
 
 impl WasiBlobstore {
     pub fn new(client: Arc<async_nats::Client>) -> Self {
         Self {
-            client: async_nats::jetstream::new((*client).clone()).into(),
+            profiles: BackendProfiles::new(async_nats::jetstream::new((*client).clone())),
             tracker: Arc::default(),
+            encryptor: None,
         }
     }
 
+    /// Encrypts objects with `encryptor` before they are written to NATS.
+    pub fn with_encryption(mut self, encryptor: Encryptor) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// Adds a named backend profile, such as another NATS cluster or account, that
+    /// workloads can select.
+    pub fn with_profile(
+        mut self,
+        name: impl Into<String>,
+        client: Arc<async_nats::Client>,
+    ) -> anyhow::Result<Self> {
+        self.profiles
+            .add(name, async_nats::jetstream::new((*client).clone()))?;
+        Ok(self)
+    }
+
+    /// Returns the JetStream context of the profile a workload selected.
+    async fn jetstream(&self, workload_id: &str) -> Arc<async_nats::jetstream::Context> {
+        let tracker = self.tracker.read().await;
+        tracker
+            .workloads
+            .get(workload_id)
+            .and_then(|item| item.workload_data.as_ref())
+            .map(|data| data.jetstream.clone())
+            .unwrap_or_else(|| self.profiles.default_backend().clone())
+    }
+
+    /// Returns the policy a workload configured for a container.
+    async fn container_policy(&self, workload_id: &str, container_name: &str) -> ContainerPolicy {
+        let tracker = self.tracker.read().await;
+        tracker
+            .workloads
+            .get(workload_id)
+            .and_then(|item| item.workload_data.as_ref())
+            .map(|data| data.policies.for_container(container_name))
+            .unwrap_or_default()
+    }
+
     async fn workload_permit(
         &self,
         workload_id: &str,
@@ -138,10 +247,14 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             }
         };
 
+        let policy = plugin.container_policy(&self.workload_id, &name).await;
         let store = match plugin
-            .client
+            .jetstream(&self.workload_id)
+            .await
             .create_object_store(object_store::Config {
                 bucket: name.to_string(),
+                // JetStream expires objects itself when the bucket is created with a TTL
+                max_age: policy.ttl.unwrap_or_default(),
                 ..Default::default()
             })
             .await
@@ -155,6 +268,7 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
         let container_data = ContainerData {
             name: name.clone(),
             store,
+            policy,
         };
 
         let resource = self.table.push(container_data)?;
@@ -179,7 +293,12 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             }
         };
 
-        let store = match plugin.client.get_object_store(name.to_string()).await {
+        let store = match plugin
+            .jetstream(&self.workload_id)
+            .await
+            .get_object_store(name.to_string())
+            .await
+        {
             Ok(store) => store,
             Err(e) => {
                 return Ok(Err(format!("failed to get bucket: {e}")));
@@ -189,6 +308,7 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
         let container_data = ContainerData {
             name: name.clone(),
             store,
+            policy: plugin.container_policy(&self.workload_id, &name).await,
         };
 
         let resource = self.table.push(container_data)?;
@@ -210,7 +330,25 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             }
         };
 
-        if let Err(e) = plugin.client.delete_object_store(name.to_string()).await {
+        let policy = plugin.container_policy(&self.workload_id, &name).await;
+        if policy.immutable
+            && let Ok(store) = plugin
+                .jetstream(&self.workload_id)
+                .await
+                .get_object_store(name.to_string())
+                .await
+            && let Ok(mut objects) = store.list().await
+            && let Some(Ok(object)) = objects.next().await
+        {
+            return Ok(Err(policy.check_mutable(&name, &object.name).unwrap_err()));
+        }
+
+        if let Err(e) = plugin
+            .jetstream(&self.workload_id)
+            .await
+            .delete_object_store(name.to_string())
+            .await
+        {
             return Ok(Err(format!("failed to delete bucket: {e}")));
         };
 
@@ -232,7 +370,12 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             }
         };
 
-        match plugin.client.get_object_store(name.to_string()).await {
+        match plugin
+            .jetstream(&self.workload_id)
+            .await
+            .get_object_store(name.to_string())
+            .await
+        {
             Ok(_) => Ok(Ok(true)),
             Err(_) => Ok(Ok(false)),
         }
@@ -267,27 +410,89 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
             }
         };
 
-        let read_store = match plugin.client.get_object_store(src.container.clone()).await {
+        let read_store = match plugin
+            .jetstream(&self.workload_id)
+            .await
+            .get_object_store(src.container.clone())
+            .await
+        {
             Ok(store) => store,
             Err(e) => {
                 return Ok(Err(format!("failed to get source bucket: {e}")));
             }
         };
 
-        let write_store = match plugin.client.get_object_store(dest.container.clone()).await {
+        let write_store = match plugin
+            .jetstream(&self.workload_id)
+            .await
+            .get_object_store(dest.container.clone())
+            .await
+        {
             Ok(store) => store,
             Err(e) => {
                 return Ok(Err(format!("failed to get destination bucket: {e}")));
             }
         };
 
+        let dest_data = ContainerData {
+            name: dest.container.clone(),
+            store: write_store.clone(),
+            policy: plugin
+                .container_policy(&self.workload_id, &dest.container)
+                .await,
+        };
+        if let Err(e) = dest_data.check_mutable(&dest.object).await {
+            return Ok(Err(e));
+        }
+
         match read_store.get(&src.object).await {
-            Ok(mut object) => match write_store.put(dest.object.as_str(), &mut object).await {
-                Ok(_) => Ok(Ok(())),
-                Err(e) => Ok(Err(format!(
-                    "failed to write data to destination object: {e}"
-                ))),
-            },
+            Ok(mut object) => {
+                if let Err(e) = dest_data
+                    .policy
+                    .check_size(&dest.container, plaintext_size(object.info()))
+                {
+                    return Ok(Err(e));
+                }
+                // Encrypted objects are sealed for their new name and keep their plaintext size
+                let metadata = object_store::ObjectMetadata {
+                    name: dest.object.clone(),
+                    metadata: object.info().metadata.clone(),
+                    ..Default::default()
+                };
+                let encryptor = plugin.encryptor.clone();
+                let result = match encryptor {
+                    Some(encryptor) => {
+                        let mut sealed = Vec::new();
+                        if let Err(e) = object.read_to_end(&mut sealed).await {
+                            return Ok(Err(format!("failed to read source object: {e}")));
+                        }
+                        let sealed = match encryptor
+                            .reseal(
+                                (&src.container, &src.object),
+                                (&dest.container, &dest.object),
+                                &sealed,
+                            )
+                            .await
+                        {
+                            Ok(sealed) => sealed,
+                            Err(e) => {
+                                return Ok(Err(format!(
+                                    "failed to encrypt object '{}': {e:#}",
+                                    dest.object
+                                )));
+                            }
+                        };
+                        write_store.put(metadata, &mut sealed.as_slice()).await
+                    }
+                    None => write_store.put(metadata, &mut object).await,
+                };
+                match result {
+                    Ok(_) => Ok(Ok(())),
+                    Err(e) => Ok(Err(format!(
+                        "failed to write data to destination object: {e}"
+                    ))),
+                }
+            }
             Err(e) => Ok(Err(format!("failed to get source object: {e}"))),
         }
     }
@@ -311,12 +516,27 @@ impl bindings::wasi::blobstore::blobstore::Host for Ctx {
                 return Ok(Err("unauthorized delete".to_string()));
             }
         };
-        let delete_store = match plugin.client.get_object_store(src.container.clone()).await {
+        let delete_store = match plugin
+            .jetstream(&self.workload_id)
+            .await
+            .get_object_store(src.container.clone())
+            .await
+        {
             Ok(store) => store,
             Err(e) => {
                 return Ok(Err(format!("failed to get source bucket: {e}")));
             }
         };
+        let src_data = ContainerData {
+            name: src.container.clone(),
+            store: delete_store.clone(),
+            policy: plugin
+                .container_policy(&self.workload_id, &src.container)
+                .await,
+        };
+        if let Err(e) = src_data.check_mutable(&src.object).await {
+            return Ok(Err(e));
+        }
 
         let copy = self.copy_object(src.clone(), dest.clone()).await?;
         if let Err(e) = copy {
@@ -361,6 +581,9 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
         let container_data = self.table.get(&container)?;
 
         let object = match container_data.store.get(name.as_str()).await {
+            Ok(obj) if container_data.is_expired(obj.info()) => {
+                return Ok(Err(format!("object '{name}' does not exist")));
+            }
             Ok(obj) => obj,
             Err(e) => {
                 tracing::warn!(
@@ -372,9 +595,15 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             }
         };
 
-        let resource = self
-            .table
-            .push(IncomingValueHandle { object, start, end })?;
+        let encryptor = self
+            .get_plugin::<WasiBlobstore>(PLUGIN_BLOBSTORE_ID)
+            .and_then(|plugin| plugin.encryptor.clone());
+        let resource = self.table.push(IncomingValueHandle {
+            object,
+            start,
+            end,
+            encryptor,
+        })?;
 
         Ok(Ok(resource))
     }
@@ -453,6 +682,10 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             }
         };
 
+        if let Err(e) = container_data.check_mutable(&name).await {
+            return Ok(Err(e));
+        }
+
         match container_data.store.delete(name.as_str()).await {
             Ok(_) => Ok(Ok(())),
             Err(e) => Ok(Err(format!("failed to delete object: {e}"))),
@@ -479,6 +712,12 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
             }
         };
 
+        for name in &names {
+            if let Err(e) = container_data.check_mutable(name).await {
+                return Ok(Err(e));
+            }
+        }
+
         for name in names {
             if let Err(e) = container_data.store.delete(name.as_str()).await {
                 return Ok(Err(format!("failed to delete object: {e}")));
@@ -495,7 +734,7 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
     ) -> anyhow::Result<Result<bool, ContainerError>> {
         let container_data = self.table.get(&container)?;
         match container_data.store.info(name.as_str()).await {
-            Ok(_) => Ok(Ok(true)),
+            Ok(info) => Ok(Ok(!container_data.is_expired(&info))),
             Err(_) => Ok(Ok(false)),
         }
     }
@@ -507,11 +746,14 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
     ) -> anyhow::Result<Result<ObjectMetadata, ContainerError>> {
         let container_data = self.table.get(&container)?;
         match container_data.store.info(name.as_str()).await {
+            Ok(info) if container_data.is_expired(&info) => {
+                Ok(Err(format!("object '{name}' does not exist")))
+            }
             Ok(info) => Ok(Ok(ObjectMetadata {
+                size: plaintext_size(&info),
                 name: info.name,
                 container: container_data.name.clone(),
                 created_at: 0,
-                size: info.size as u64,
             })),
             Err(_) => Ok(Err(format!("object '{name}' does not exist"))),
         }
@@ -549,6 +791,12 @@ impl bindings::wasi::blobstore::container::HostContainer for Ctx {
         while let Some(object) = object_list.next().await {
             match object {
                 Ok(obj) => {
+                    if container_data.policy.immutable && !container_data.is_expired(&obj) {
+                        return Ok(Err(container_data
+                            .policy
+                            .check_mutable(&container_data.name, &obj.name)
+                            .unwrap_err()));
+                    }
                     if let Err(e) = container_data.store.delete(&obj.name).await {
                         return Ok(Err(format!(
                             "failed to delete object '{}' in container '{}': {e}",
@@ -698,17 +946,58 @@ impl bindings::wasi::blobstore::types::HostOutgoingValue for Ctx {
             }
         };
 
-        let mut file = tokio::fs::File::from_std(handle.temp_file.reopen()?);
+        let size = handle.temp_file.as_file().metadata()?.len();
+        if let Err(e) = container_data.policy.check_size(&container_data.name, size) {
+            return Ok(Err(e));
+        }
+        if let Err(e) = container_data.check_mutable(&object_name).await {
+            return Ok(Err(e));
+        }
 
-        match container_data
-            .store
-            .put(object_name.as_str(), &mut file)
-            .await
-        {
-            Ok(_) => {}
-            Err(e) => {
-                return Ok(Err(format!("failed to write object data: {e}")));
+        let encryptor = self
+            .get_plugin::<WasiBlobstore>(PLUGIN_BLOBSTORE_ID)
+            .and_then(|plugin| plugin.encryptor.clone());
+        let bandwidth = self.bandwidth().clone();
+        let result = match encryptor {
+            Some(encryptor) => {
+                let data = tokio::fs::read(handle.temp_file.path()).await?;
+                let sealed = match encryptor
+                    .seal(&container_data.name, &object_name, &data)
+                    .await
+                {
+                    Ok(sealed) => sealed,
+                    Err(e) => {
+                        return Ok(Err(format!(
+                            "failed to encrypt object '{object_name}': {e:#}"
+                        )));
+                    }
+                };
+                let metadata = object_store::ObjectMetadata {
+                    name: object_name.clone(),
+                    metadata: [(PLAINTEXT_SIZE_KEY.to_string(), size.to_string())].into(),
+                    ..Default::default()
+                };
+                container_data
+                    .store
+                    .put(
+                        metadata,
+                        &mut ThrottledReader::new(sealed.as_slice(), bandwidth),
+                    )
+                    .await
             }
+            None => {
+                let file = tokio::fs::File::from_std(handle.temp_file.reopen()?);
+                container_data
+                    .store
+                    .put(
+                        object_name.as_str(),
+                        &mut ThrottledReader::new(file, bandwidth),
+                    )
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            return Ok(Err(format!("failed to write object data: {e}")));
         }
 
         Ok(Ok(()))
@@ -726,12 +1015,24 @@ impl bindings::wasi::blobstore::types::HostIncomingValue for Ctx {
         incoming_value: Resource<IncomingValueHandle>,
     ) -> anyhow::Result<Result<Vec<u8>, BlobstoreError>> {
         let mut data = self.table.delete(incoming_value)?;
-        let mut buf = Vec::new();
-
-        match data.object.read(&mut buf).await {
-            Ok(_) => Ok(Ok(buf)),
-            Err(e) => Ok(Err(format!("failed to read object data: {e}"))),
+        let read = match data.encryptor.clone() {
+            Some(encryptor) => data
+                .read_plaintext(&encryptor)
+                .await
+                .map_err(|e| format!("failed to read object data: {e:#}")),
+            None => {
+                let mut buf = Vec::new();
+                data.object
+                    .read(&mut buf)
+                    .await
+                    .map(|_| buf)
+                    .map_err(|e| format!("failed to read object data: {e}"))
+            }
+        };
+        if let Ok(buf) = &read {
+            self.bandwidth().throttle(buf.len()).await;
         }
+        Ok(read)
     }
 
     async fn incoming_value_consume_async(
@@ -740,9 +1041,23 @@ impl bindings::wasi::blobstore::types::HostIncomingValue for Ctx {
     ) -> anyhow::Result<
         Result<Resource<bindings::wasi::blobstore::types::IncomingValueAsyncBody>, BlobstoreError>,
     > {
-        let data = self.table.delete(incoming_value)?;
+        let mut data = self.table.delete(incoming_value)?;
 
-        let stream: Box<dyn InputStream> = Box::new(AsyncReadStream::new(data.object));
+        // Encrypted objects are authenticated as a whole, so they're decrypted before any
+        // of their data is handed to the component
+        let stream: Box<dyn InputStream> = match data.encryptor.clone() {
+            Some(encryptor) => match data.read_plaintext(&encryptor).await {
+                Ok(buf) => {
+                    self.bandwidth().throttle(buf.len()).await;
+                    Box::new(MemoryInputPipe::new(buf))
+                }
+                Err(e) => return Ok(Err(format!("failed to read object data: {e:#}"))),
+            },
+            None => Box::new(AsyncReadStream::new(ThrottledReader::new(
+                data.object,
+                self.bandwidth().clone(),
+            ))),
+        };
         let stream = self.table.push(stream)?;
 
         Ok(Ok(stream))
@@ -750,7 +1065,7 @@ impl bindings::wasi::blobstore::types::HostIncomingValue for Ctx {
 
     async fn size(&mut self, incoming_value: Resource<IncomingValueHandle>) -> anyhow::Result<u64> {
         let data = self.table.get(&incoming_value)?;
-        Ok(data.object.info().size as u64)
+        Ok(plaintext_size(data.object.info()))
     }
 
     async fn drop(&mut self, rep: Resource<IncomingValueHandle>) -> anyhow::Result<()> {
@@ -810,6 +1125,24 @@ impl HostPlugin for WasiBlobstore {
         Ok(())
     }
 
+    fn config_schema(&self) -> Option<ConfigSchema> {
+        Some(ContainerPolicies::with_schema_fields(
+            BackendProfiles::<async_nats::jetstream::Context>::with_schema_field(
+                ConfigSchema::new(),
+            )
+            .with_field(
+                "buckets",
+                ConfigValueKind::List,
+                "object store buckets the workload may access",
+            )
+            .with_field(
+                "read_only",
+                ConfigValueKind::Bool,
+                "reject writes to the configured buckets",
+            ),
+        ))
+    }
+
     async fn on_workload_bind(
         &self,
         workload: &crate::engine::workload::UnresolvedWorkload,
@@ -832,11 +1165,17 @@ impl HostPlugin for WasiBlobstore {
             .get("read_only")
             .is_some_and(|v| v == "true");
 
+        let policies = ContainerPolicies::from_config(&interface.config)
+            .context("invalid blobstore container policies")?;
+        let jetstream = self.profiles.select(&interface.config)?;
+
         self.tracker.write().await.add_unresolved_workload(
             workload,
             WorkloadData {
                 buckets: HashSet::from_iter(buckets),
                 read_only,
+                policies,
+                jetstream,
                 cancel_token: tokio_util::sync::CancellationToken::new(),
             },
         );
@@ -104,4 +104,12 @@ impl HostPlugin for WasiConfig {
 
         Ok(())
     }
+
+    async fn on_component_update(&self, component: &WorkloadComponent) -> anyhow::Result<()> {
+        // Keep the mirror in step with environment changes on the running workload
+        if let Some(config) = self.config.write().await.get_mut(component.id()) {
+            config.clone_from(&component.local_resources().environment);
+        }
+        Ok(())
+    }
 }
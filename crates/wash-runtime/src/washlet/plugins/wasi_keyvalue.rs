@@ -3,8 +3,18 @@
 //! This module implements `wasi:keyvalue@0.2.0-draft` interfaces using
 //! NATS JetStream as the backend storage.
 //! Atomics are stored in Network Byte Order (big-endian) format.
+//!
+//! With [`WasiKeyvalue::with_encryption`] values are [sealed](crate::plugin::encryption)
+//! before they are written, so the JetStream file store, such as a leaf node on an edge
+//! device, never holds them in plaintext. Keys are stored as they are.
+//!
+//! Buckets are read with the [consistency](crate::plugin::keyvalue_consistency) the
+//! workload configures for them: by default reads are direct gets, which any replica of
+//! a bucket allowing them answers, `read-your-writes` reads are answered by the leader of
+//! the bucket's stream, and buckets with a read replica, such as a mirror in a leaf node,
+//! are read from it.
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use bytes::{Buf, Bytes};
@@ -13,11 +23,21 @@ const PLUGIN_KEYVALUE_ID: &str = "wasi-keyvalue";
 use crate::engine::ctx::Ctx;
 use crate::engine::workload::WorkloadComponent;
 use crate::plugin::HostPlugin;
+use crate::plugin::encryption::Encryptor;
+use crate::plugin::keyvalue_consistency::{BucketConsistencies, Consistency};
+use crate::plugin::profiles::BackendProfiles;
+use crate::plugin::schema::ConfigSchema;
 use crate::wit::{WitInterface, WitWorld};
-use futures::StreamExt;
+use async_nats::jetstream::stream::LastRawMessageErrorKind;
+use futures::{StreamExt, TryStreamExt};
+use tokio::sync::RwLock;
 use wasmtime::component::{HasSelf, Resource};
 
 const LIST_KEYS_BATCH_SIZE: usize = 1000;
+/// The header marking deletes and purges of keys in a bucket's stream
+const KV_OPERATION_HEADER: &str = "KV-Operation";
+/// Maximum number of JetStream requests a batch operation has in flight at once
+const BATCH_CONCURRENCY: usize = 64;
 
 mod bindings {
     wasmtime::component::bindgen!({
@@ -34,13 +54,80 @@ use bindings::wasi::keyvalue::store::{Error as StoreError, KeyResponse};
 /// Resource representation for a bucket (key-value store)
 pub struct BucketHandle {
     kv: async_nats::jetstream::kv::Store,
+    encryptor: Option<Encryptor>,
+    consistency: Consistency,
+    /// The replica reads are served from, if not the bucket itself
+    read_replica: Option<async_nats::jetstream::kv::Store>,
+}
+
+impl BucketHandle {
+    /// Reads the value of a key with the bucket's consistency.
+    async fn read(&self, key: &str) -> Result<Option<Bytes>, async_nats::Error> {
+        if let Some(replica) = &self.read_replica {
+            return Ok(replica.get(key).await?);
+        }
+        match self.consistency {
+            Consistency::Eventual => Ok(self.kv.get(key).await?),
+            Consistency::ReadYourWrites => {
+                // Unlike direct gets, message gets are answered by the stream's leader
+                let subject = format!("{}{}", self.kv.prefix, key);
+                match self
+                    .kv
+                    .stream
+                    .get_last_raw_message_by_subject(&subject)
+                    .await
+                {
+                    Ok(message) => {
+                        let deleted = message
+                            .headers
+                            .get(KV_OPERATION_HEADER)
+                            .is_some_and(|operation| operation.as_str() != "PUT");
+                        Ok((!deleted).then_some(message.payload))
+                    }
+                    Err(e) if matches!(e.kind(), LastRawMessageErrorKind::NoMessageFound) => {
+                        Ok(None)
+                    }
+                    Err(e) => Err(e.into()),
+                }
+            }
+        }
+    }
+
+    /// Encrypts the value of `key` before it's written, if the plugin encrypts values.
+    async fn seal(&self, key: &str, value: Vec<u8>) -> Result<Bytes, StoreError> {
+        match &self.encryptor {
+            Some(encryptor) => encryptor
+                .seal(&self.kv.name, key, &value)
+                .await
+                .map(Bytes::from)
+                .map_err(|e| StoreError::Other(format!("failed to encrypt value: {e:#}"))),
+            None => Ok(value.into()),
+        }
+    }
+
+    /// Decrypts the value of `key` that was read, if the plugin encrypts values.
+    async fn open(&self, key: &str, value: Bytes) -> Result<Vec<u8>, StoreError> {
+        match &self.encryptor {
+            Some(encryptor) => encryptor
+                .open(&self.kv.name, key, &value)
+                .await
+                .map_err(|e| StoreError::Other(format!("failed to decrypt value: {e:#}"))),
+            None => Ok(value.to_vec()),
+        }
+    }
 }
 
 /// Memory-based keyvalue plugin
 #[derive(Clone)]
 pub struct WasiKeyvalue {
-    client: Arc<async_nats::jetstream::Context>,
+    profiles: BackendProfiles<async_nats::jetstream::Context>,
+    /// The JetStream context of the backend profile each workload selected
+    workloads: Arc<RwLock<HashMap<String, Arc<async_nats::jetstream::Context>>>>,
+    /// The bucket consistency each workload configured
+    consistencies: Arc<RwLock<HashMap<String, Arc<BucketConsistencies>>>>,
     metrics: Arc<WasiKeyvalueMetrics>,
+    /// Encrypts values before they are written to JetStream, if set
+    encryptor: Option<Encryptor>,
 }
 
 struct WasiKeyvalueMetrics {
@@ -62,11 +149,52 @@ impl WasiKeyvalue {
         let meter = opentelemetry::global::meter("wasi-keyvalue");
         let metrics = WasiKeyvalueMetrics::new(&meter);
         Self {
-            client: async_nats::jetstream::new((*client).clone()).into(),
+            profiles: BackendProfiles::new(async_nats::jetstream::new((*client).clone())),
+            workloads: Arc::default(),
+            consistencies: Arc::default(),
             metrics: Arc::new(metrics),
+            encryptor: None,
         }
     }
 
+    /// Encrypts values with `encryptor` before they are written to JetStream.
+    pub fn with_encryption(mut self, encryptor: Encryptor) -> Self {
+        self.encryptor = Some(encryptor);
+        self
+    }
+
+    /// Adds a named backend profile, such as another NATS cluster or account, that
+    /// workloads can select.
+    pub fn with_profile(
+        mut self,
+        name: impl Into<String>,
+        client: Arc<async_nats::Client>,
+    ) -> anyhow::Result<Self> {
+        self.profiles
+            .add(name, async_nats::jetstream::new((*client).clone()))?;
+        Ok(self)
+    }
+
+    /// Returns the JetStream context of the profile a workload selected.
+    async fn jetstream(&self, workload_id: &str) -> Arc<async_nats::jetstream::Context> {
+        self.workloads
+            .read()
+            .await
+            .get(workload_id)
+            .cloned()
+            .unwrap_or_else(|| self.profiles.default_backend().clone())
+    }
+
+    /// Returns the bucket consistency a workload configured.
+    async fn consistencies(&self, workload_id: &str) -> Arc<BucketConsistencies> {
+        self.consistencies
+            .read()
+            .await
+            .get(workload_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
     fn record_operation(&self, operation: &str) {
         let attributes = [opentelemetry::KeyValue::new(
             "operation",
@@ -89,7 +217,8 @@ impl bindings::wasi::keyvalue::store::Host for Ctx {
         };
         plugin.record_operation("open");
 
-        let kv = match plugin.client.get_key_value(&identifier).await {
+        let jetstream = plugin.jetstream(&self.workload_id).await;
+        let kv = match jetstream.get_key_value(&identifier).await {
             Ok(kv) => {
                 tracing::debug!("Opened existing bucket in JetStream");
                 kv
@@ -102,7 +231,29 @@ impl bindings::wasi::keyvalue::store::Host for Ctx {
             }
         };
 
-        let bucket = BucketHandle { kv };
+        let consistency = plugin
+            .consistencies(&self.workload_id)
+            .await
+            .for_bucket(&identifier);
+        let read_replica = match &consistency.read_replica {
+            Some(replica) => match jetstream.get_key_value(replica).await {
+                Ok(replica) => Some(replica),
+                Err(e) => {
+                    tracing::error!("Read replica not found in JetStream({replica}): {e}");
+                    return Ok(Err(StoreError::Other(format!(
+                        "failed to get read replica {replica} of {identifier} from JetStream"
+                    ))));
+                }
+            },
+            None => None,
+        };
+
+        let bucket = BucketHandle {
+            kv,
+            encryptor: plugin.encryptor.clone(),
+            consistency: consistency.consistency,
+            read_replica,
+        };
 
         let resource = self.table.push(bucket)?;
         Ok(Ok(resource))
@@ -125,7 +276,7 @@ impl bindings::wasi::keyvalue::store::HostBucket for Ctx {
 
         let bucket_handle = self.table.get(&bucket)?;
 
-        let entry = match bucket_handle.kv.get(key).await {
+        let entry = match bucket_handle.read(&key).await {
             Ok(entry) => entry,
             Err(e) => {
                 tracing::error!("JetStream error getting key: {}", e);
@@ -134,7 +285,7 @@ impl bindings::wasi::keyvalue::store::HostBucket for Ctx {
         };
 
         match entry {
-            Some(e) => Ok(Ok(Some(e.to_vec()))),
+            Some(e) => Ok(bucket_handle.open(&key, e).await.map(Some)),
             None => Ok(Ok(None)),
         }
     }
@@ -150,11 +301,15 @@ impl bindings::wasi::keyvalue::store::HostBucket for Ctx {
                 "keyvalue plugin not available".to_string(),
             )));
         };
-        plugin.record_operation("get");
+        plugin.record_operation("set");
 
         let bucket_handle = self.table.get(&bucket)?;
+        let value = match bucket_handle.seal(&key, value).await {
+            Ok(value) => value,
+            Err(e) => return Ok(Err(e)),
+        };
 
-        match bucket_handle.kv.put(key, value.into()).await {
+        match bucket_handle.kv.put(key, value).await {
             Ok(_) => Ok(Ok(())),
             Err(e) => {
                 tracing::error!("JetStream error setting key: {}", e);
@@ -200,7 +355,7 @@ impl bindings::wasi::keyvalue::store::HostBucket for Ctx {
 
         let bucket_handle = self.table.get(&bucket)?;
 
-        match bucket_handle.kv.get(key).await {
+        match bucket_handle.read(&key).await {
             Ok(Some(_)) => Ok(Ok(true)),
             Ok(None) => Ok(Ok(false)),
             Err(e) => {
@@ -285,10 +440,13 @@ impl bindings::wasi::keyvalue::atomics::Host for Ctx {
         let bucket_handle = self.table.get(&bucket)?;
 
         let (entry_revision, entry_value) = match bucket_handle.kv.entry(&key).await {
-            Ok(Some(mut e)) => {
+            Ok(Some(e)) => {
                 let revision = Some(e.revision);
-                let value = e.value.get_u64();
-                (revision, value)
+                let mut value = match bucket_handle.open(&key, e.value).await {
+                    Ok(value) => Bytes::from(value),
+                    Err(e) => return Ok(Err(e)),
+                };
+                (revision, value.get_u64())
             }
             Ok(None) => (None, 0),
             Err(e) => {
@@ -298,7 +456,13 @@ impl bindings::wasi::keyvalue::atomics::Host for Ctx {
         };
 
         let new_value = entry_value + delta;
-        let entry_bytes = Bytes::from((new_value).to_be_bytes().to_vec());
+        let entry_bytes = match bucket_handle
+            .seal(&key, new_value.to_be_bytes().to_vec())
+            .await
+        {
+            Ok(entry_bytes) => entry_bytes,
+            Err(e) => return Ok(Err(e)),
+        };
 
         // Here's were CAS happens
         // If we have a revision, we try to update the entry with it
@@ -344,29 +508,26 @@ impl bindings::wasi::keyvalue::batch::Host for Ctx {
 
         let bucket_handle = self.table.get(&bucket)?;
 
-        let values = futures::stream::FuturesOrdered::from_iter(keys.iter().map(|key| async {
-            match bucket_handle.kv.get(key.clone()).await {
-                Ok(Some(entry)) => Ok(Some((key.clone(), entry.to_vec()))),
-                Ok(None) => Ok(None),
-                Err(e) => {
-                    tracing::error!("JetStream error getting key: {}", e);
-                    Err(StoreError::Other(format!("JetStream error: {}", e)))
+        // JetStream KV has no multi-get, so gets are pipelined in order, stopping at the first error
+        let result = futures::stream::iter(keys)
+            .map(|key| async move {
+                match bucket_handle.read(&key).await {
+                    Ok(Some(entry)) => bucket_handle
+                        .open(&key, entry)
+                        .await
+                        .map(|v| Some((key, v))),
+                    Ok(None) => Ok(None),
+                    Err(e) => {
+                        tracing::error!("JetStream error getting key: {}", e);
+                        Err(StoreError::Other(format!("JetStream error: {}", e)))
+                    }
                 }
-            }
-        }))
-        .collect::<Vec<_>>()
-        .await;
-
-        // Remove the outer Result, propagate the first error if any
-        let mut result = Vec::with_capacity(values.len());
-        for entry in values {
-            match entry {
-                Ok(v) => result.push(v),
-                Err(e) => return Ok(Err(e)),
-            }
-        }
+            })
+            .buffered(BATCH_CONCURRENCY)
+            .try_collect::<Vec<_>>()
+            .await;
 
-        Ok(Ok(result))
+        Ok(result)
     }
 
     async fn set_many(
@@ -383,32 +544,22 @@ impl bindings::wasi::keyvalue::batch::Host for Ctx {
 
         let bucket_handle = self.table.get(&bucket)?;
 
-        let values = futures::stream::FuturesOrdered::from_iter(key_values.iter().map(
-            |(key, value)| async {
-                match bucket_handle
-                    .kv
-                    .put(key.clone(), value.to_vec().into())
-                    .await
-                {
+        let result = futures::stream::iter(key_values)
+            .map(|(key, value)| async move {
+                let value = bucket_handle.seal(&key, value).await?;
+                match bucket_handle.kv.put(key, value).await {
                     Ok(_) => Ok(()),
                     Err(e) => {
                         tracing::error!("JetStream error putting key: {}", e);
                         Err(StoreError::Other(format!("JetStream error: {}", e)))
                     }
                 }
-            },
-        ))
-        .collect::<Vec<_>>()
-        .await;
-
-        // Remove the outer Result, propagate the first error if any
-        for entry in values {
-            if let Err(e) = entry {
-                return Ok(Err(e));
-            }
-        }
+            })
+            .buffer_unordered(BATCH_CONCURRENCY)
+            .try_collect::<()>()
+            .await;
 
-        Ok(Ok(()))
+        Ok(result)
     }
 
     async fn delete_many(
@@ -425,26 +576,21 @@ impl bindings::wasi::keyvalue::batch::Host for Ctx {
 
         let bucket_handle = self.table.get(&bucket)?;
 
-        let values = futures::stream::FuturesOrdered::from_iter(keys.iter().map(|key| async {
-            match bucket_handle.kv.delete(key.clone()).await {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    tracing::error!("JetStream error deleting key: {}", e);
-                    Err(StoreError::Other(format!("JetStream error: {}", e)))
+        let result = futures::stream::iter(keys)
+            .map(|key| async move {
+                match bucket_handle.kv.delete(&key).await {
+                    Ok(_) => Ok(()),
+                    Err(e) => {
+                        tracing::error!("JetStream error deleting key: {}", e);
+                        Err(StoreError::Other(format!("JetStream error: {}", e)))
+                    }
                 }
-            }
-        }))
-        .collect::<Vec<_>>()
-        .await;
-
-        // Remove the outer Result, propagate the first error if any
-        for entry in values {
-            if let Err(e) = entry {
-                return Ok(Err(e));
-            }
-        }
+            })
+            .buffer_unordered(BATCH_CONCURRENCY)
+            .try_collect::<()>()
+            .await;
 
-        Ok(Ok(()))
+        Ok(result)
     }
 }
 
@@ -500,11 +646,48 @@ impl HostPlugin for WasiKeyvalue {
         Ok(())
     }
 
+    fn config_schema(&self) -> Option<ConfigSchema> {
+        // Other keys were never validated, so they stay accepted
+        Some(
+            BackendProfiles::<async_nats::jetstream::Context>::with_schema_field(
+                BucketConsistencies::with_schema_fields(ConfigSchema::new()),
+            )
+            .allow_unknown_keys(),
+        )
+    }
+
+    async fn on_workload_bind(
+        &self,
+        workload: &crate::engine::workload::UnresolvedWorkload,
+        interfaces: HashSet<WitInterface>,
+    ) -> anyhow::Result<()> {
+        let Some(interface) = interfaces
+            .iter()
+            .find(|i| i.namespace == "wasi" && i.package == "keyvalue")
+        else {
+            return Ok(());
+        };
+
+        let jetstream = self.profiles.select(&interface.config)?;
+        let consistencies = BucketConsistencies::from_config(&interface.config)?;
+        self.workloads
+            .write()
+            .await
+            .insert(workload.id().to_string(), jetstream);
+        self.consistencies
+            .write()
+            .await
+            .insert(workload.id().to_string(), Arc::new(consistencies));
+        Ok(())
+    }
+
     async fn on_workload_unbind(
         &self,
         workload_id: &str,
         _interfaces: std::collections::HashSet<crate::wit::WitInterface>,
     ) -> anyhow::Result<()> {
+        self.workloads.write().await.remove(workload_id);
+        self.consistencies.write().await.remove(workload_id);
         tracing::debug!("WasiKeyvalue plugin unbound from workload '{workload_id}'");
 
         Ok(())
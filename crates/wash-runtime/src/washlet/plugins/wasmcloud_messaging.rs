@@ -1,18 +1,72 @@
-use std::collections::HashSet;
+//! The `wasmcloud:messaging` plugin, backed by NATS.
+//!
+//! Components exporting the handler receive the messages published to the subjects listed
+//! in the `subscriptions` config of the interface. By default those come from core NATS
+//! subscriptions, so a message is lost if the handler traps. Setting [`DELIVERY_KEY`] to
+//! `at-least-once` reads them from a durable consumer on the JetStream stream named by
+//! [`STREAM_KEY`] instead: a message is acked once the handler returns successfully, and
+//! redelivered when it traps or returns an error, up to [`MAX_REDELIVERIES_KEY`] times.
+//! After its last delivery fails the message is published to [`DEAD_LETTER_SUBJECT_KEY`],
+//! if set, and dropped. Deliveries that aren't acked in time, e.g. because the host
+//! stopped while handling them, count too: the consumer doesn't limit deliveries itself,
+//! so a message delivered more often than allowed is dead-lettered without being handled
+//! again.
+//!
+//! Dead letters are published with JetStream, so [`DEAD_LETTER_SUBJECT_KEY`] must be bound
+//! to a stream. The message is only dropped once the stream acks its dead letter;
+//! otherwise it's redelivered after 30 seconds to be dead-lettered again.
+//!
+//! The consumer is named after the workload's namespace and name unless [`CONSUMER_KEY`] is
+//! set, so instances of a workload share it and each message is handled by one of them.
+//!
+//! Messages carry the W3C baggage of the request or message the component is handling
+//! in a `baggage` header, see [`crate::host::baggage`].
+
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use crate::engine::ctx::Ctx;
 use crate::engine::workload::{ResolvedWorkload, WorkloadComponent};
+use crate::host::baggage::{BAGGAGE_HEADER, Baggage};
+use crate::host::invoker::consumer_name;
+use crate::host::slow_invocations;
 use crate::plugin::HostPlugin;
+use crate::plugin::schema::{ConfigSchema, ConfigValueKind};
+use crate::redact;
 use crate::wit::{WitInterface, WitWorld};
-use anyhow::Context;
+use anyhow::{Context, bail, ensure};
 use async_nats::Subscriber;
+use async_nats::jetstream::AckKind;
+use async_nats::jetstream::consumer::{AckPolicy, pull};
 use futures::stream::StreamExt;
 use tokio::sync::RwLock;
 use tracing::{debug, instrument, warn};
 
 const PLUGIN_MESSAGING_ID: &str = "wasmcloud-messaging";
 
+/// Config key selecting `at-most-once` (the default) or `at-least-once` delivery.
+pub const DELIVERY_KEY: &str = "delivery";
+/// Config key naming the JetStream stream messages are read from with at-least-once delivery.
+pub const STREAM_KEY: &str = "stream";
+/// Config key naming the durable consumer used with at-least-once delivery.
+pub const CONSUMER_KEY: &str = "consumer";
+/// Config key for how many times a failed message is redelivered.
+pub const MAX_REDELIVERIES_KEY: &str = "max_redeliveries";
+/// Config key for the subject messages are published to after their last delivery fails.
+pub const DEAD_LETTER_SUBJECT_KEY: &str = "dead_letter_subject";
+
+/// Redeliveries of a failed message when [`MAX_REDELIVERIES_KEY`] isn't set.
+const DEFAULT_MAX_REDELIVERIES: u32 = 5;
+
+/// How long a message waits to be dead-lettered again after publishing its dead letter
+/// failed.
+const DEAD_LETTER_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Header carrying the subject a dead-lettered message was published to.
+const ORIGINAL_SUBJECT_HEADER: &str = "Wasmcloud-Original-Subject";
+/// Header carrying why the last delivery of a dead-lettered message failed.
+const DELIVERY_ERROR_HEADER: &str = "Wasmcloud-Delivery-Error";
+
 mod bindings {
     crate::wasmtime::component::bindgen!({
         world: "messaging",
@@ -29,9 +83,75 @@ use crate::washlet::plugins::WorkloadTracker;
 
 pub struct ComponentData {
     subscriptions: Vec<String>,
+    delivery: Delivery,
     cancel_token: tokio_util::sync::CancellationToken,
 }
 
+/// How messages are delivered to a component's handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Delivery {
+    /// From core NATS subscriptions, dropping messages the handler fails on
+    AtMostOnce,
+    /// From a durable JetStream consumer, redelivering messages the handler fails on
+    AtLeastOnce(AtLeastOnce),
+}
+
+/// Settings of at-least-once delivery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct AtLeastOnce {
+    stream: String,
+    consumer: String,
+    max_redeliveries: u32,
+    dead_letter_subject: Option<String>,
+}
+
+impl Delivery {
+    /// Parses the delivery settings from the config on a `wasmcloud:messaging` interface.
+    fn from_config(
+        config: &HashMap<String, String>,
+        default_consumer: &str,
+    ) -> anyhow::Result<Self> {
+        match config.get(DELIVERY_KEY).map(String::as_str) {
+            None | Some("at-most-once") => Ok(Self::AtMostOnce),
+            Some("at-least-once") => {
+                let stream = config
+                    .get(STREAM_KEY)
+                    .filter(|stream| !stream.is_empty())
+                    .with_context(|| format!("at-least-once delivery requires '{STREAM_KEY}'"))?;
+                let consumer = config
+                    .get(CONSUMER_KEY)
+                    .cloned()
+                    .unwrap_or_else(|| default_consumer.to_string());
+                ensure!(
+                    !consumer.is_empty()
+                        && consumer
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                    "invalid consumer name '{consumer}'"
+                );
+                let max_redeliveries = match config.get(MAX_REDELIVERIES_KEY) {
+                    Some(max) => max
+                        .parse()
+                        .with_context(|| format!("invalid {MAX_REDELIVERIES_KEY} '{max}'"))?,
+                    None => DEFAULT_MAX_REDELIVERIES,
+                };
+                Ok(Self::AtLeastOnce(AtLeastOnce {
+                    stream: stream.clone(),
+                    consumer,
+                    max_redeliveries,
+                    dead_letter_subject: config
+                        .get(DEAD_LETTER_SUBJECT_KEY)
+                        .filter(|subject| !subject.is_empty())
+                        .cloned(),
+                }))
+            }
+            Some(other) => {
+                bail!("invalid {DELIVERY_KEY} '{other}', expected at-most-once or at-least-once")
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct WasmcloudMessaging {
     tracker: Arc<RwLock<WorkloadTracker<(), ComponentData>>>,
@@ -47,6 +167,245 @@ impl WasmcloudMessaging {
     }
 }
 
+/// Delivers messages to a component's handler export, each in a new instance.
+struct MessageHandler {
+    workload: ResolvedWorkload,
+    component_id: String,
+    pre: bindings::MessagingPre<Ctx>,
+}
+
+impl MessageHandler {
+    /// Calls the handler with a message, failing if it traps or returns an error.
+    async fn handle(
+        &self,
+        msg: &types::BrokerMessage,
+        headers: Option<&async_nats::HeaderMap>,
+    ) -> anyhow::Result<()> {
+        // Messages wait while the workload is paused rather than being dropped
+        self.workload.resumed().await;
+        // The handler may execute on the workload's threads, so it owns what it needs
+        let workload = self.workload.clone();
+        let component_id = self.component_id.clone();
+        let pre = self.pre.clone();
+        let msg = msg.clone();
+        let baggage = headers.and_then(message_baggage);
+        let handled = self.workload.execute(async move {
+            let mut store = workload
+                .new_store(&component_id)
+                .await
+                .with_context(|| format!("failed to create store for component {component_id}"))?;
+            if let Some(baggage) = baggage {
+                store.data_mut().set_baggage(baggage);
+            }
+            let proxy = pre
+                .instantiate_async(&mut store)
+                .await
+                .with_context(|| format!("failed to instantiate component {component_id}"))?;
+            store.data_mut().instance_ready();
+            store
+                .data_mut()
+                .describe_invocation(|| format!("message {}", msg.subject));
+            let result = proxy
+                .wasmcloud_messaging_handler()
+                .call_handle_message(&mut store, &msg)
+                .await;
+            if let Err(e) = &result {
+                store.data().record_error(e);
+            }
+            slow_invocations::log_if_slow(&store);
+            result?.map_err(anyhow::Error::msg)
+        });
+        handled.await?
+    }
+}
+
+impl WasmcloudMessaging {
+    /// Delivers the messages of core NATS subscriptions to a handler until cancelled.
+    async fn deliver_at_most_once(
+        &self,
+        handler: MessageHandler,
+        subjects: Vec<String>,
+        cancel_token: tokio_util::sync::CancellationToken,
+    ) -> anyhow::Result<()> {
+        let mut subscriptions = Vec::<Subscriber>::new();
+        for subject in subjects {
+            let sub = match self.client.subscribe(subject.clone()).await {
+                Ok(sub) => sub,
+                Err(e) => {
+                    for sub in subscriptions {
+                        drop(sub);
+                    }
+                    return Err(anyhow::Error::new(e))
+                        .context(format!("failed to subscribe to {subject}"));
+                }
+            };
+
+            subscriptions.push(sub);
+        }
+
+        let mut messages = futures::stream::select_all(subscriptions);
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    maybe_msg = messages.next() => {
+                        let msg = match maybe_msg {
+                            None => {
+                                break;
+                            }
+                            Some(msg) => {
+                                msg
+                            }
+                        };
+                        let reply_to = msg.reply.as_ref().map(|r| r.to_string());
+                        let headers = msg.headers;
+                        let msg = types::BrokerMessage {
+                            subject: msg.subject.to_string(),
+                            reply_to,
+                            body: msg.payload.into(),
+                        };
+                        match handler.handle(&msg, headers.as_ref()).await {
+                            Ok(()) => {
+                                debug!("Message handled successfully");
+                            }
+                            Err(e) => {
+                                warn!("Error handling message: {e:#}");
+                            }
+                        }
+                    }
+                    _ = cancel_token.cancelled() => {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Delivers the messages of a durable JetStream consumer to a handler until cancelled,
+    /// acking each once it's handled.
+    async fn deliver_at_least_once(
+        &self,
+        handler: MessageHandler,
+        subjects: Vec<String>,
+        cancel_token: tokio_util::sync::CancellationToken,
+        settings: AtLeastOnce,
+    ) -> anyhow::Result<()> {
+        let AtLeastOnce {
+            stream,
+            consumer,
+            max_redeliveries,
+            dead_letter_subject,
+        } = settings;
+        let jetstream = async_nats::jetstream::new((*self.client).clone());
+        let consumer = jetstream
+            .get_stream(&stream)
+            .await
+            .with_context(|| format!("failed to get stream {stream}"))?
+            .get_or_create_consumer(
+                &consumer,
+                pull::Config {
+                    durable_name: Some(consumer.clone()),
+                    filter_subjects: subjects,
+                    ack_policy: AckPolicy::Explicit,
+                    // Deliveries are counted here, so messages out of deliveries are
+                    // dead-lettered instead of being left on the stream by the server
+                    ..Default::default()
+                },
+            )
+            .await
+            .with_context(|| format!("failed to create consumer {consumer} on stream {stream}"))?;
+        let mut messages = consumer
+            .messages()
+            .await
+            .context("failed to read messages from consumer")?;
+        let deliveries = i64::from(max_redeliveries) + 1;
+
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    maybe_msg = messages.next() => {
+                        let msg = match maybe_msg {
+                            None => {
+                                break;
+                            }
+                            Some(Err(e)) => {
+                                warn!("failed to receive message: {e}");
+                                continue;
+                            }
+                            Some(Ok(msg)) => {
+                                msg
+                            }
+                        };
+                        let delivered = msg.info().map(|info| info.delivered).unwrap_or(1);
+                        let e = if delivered > deliveries {
+                            // Earlier deliveries weren't acked in time, or dead-lettering failed
+                            anyhow::anyhow!("message wasn't handled in {deliveries} deliveries")
+                        } else {
+                            let message = types::BrokerMessage {
+                                subject: msg.subject.to_string(),
+                                reply_to: None,
+                                body: msg.payload.to_vec(),
+                            };
+                            match handler.handle(&message, msg.headers.as_ref()).await {
+                                Ok(()) => {
+                                    debug!("Message handled successfully");
+                                    if let Err(e) = msg.ack().await {
+                                        warn!("failed to ack message: {e}");
+                                    }
+                                    continue;
+                                }
+                                Err(e) => e,
+                            }
+                        };
+
+                        if delivered < deliveries {
+                            warn!(delivered, "Error handling message, redelivering: {e:#}");
+                            if let Err(e) = msg.ack_with(AckKind::Nak(None)).await {
+                                warn!("failed to nack message: {e}");
+                            }
+                            continue;
+                        }
+
+                        warn!(delivered, "Error handling message, giving up: {e:#}");
+                        if let Some(subject) = &dead_letter_subject {
+                            let error = format!("{e:#}");
+                            let mut headers = async_nats::HeaderMap::new();
+                            headers.insert(ORIGINAL_SUBJECT_HEADER, msg.subject.as_str());
+                            headers.insert(DELIVERY_ERROR_HEADER, redact::redact(&error).as_ref());
+                            let published = match jetstream
+                                .publish_with_headers(subject.clone(), headers, msg.payload.clone())
+                                .await
+                            {
+                                Ok(ack) => ack.await.map(drop),
+                                Err(e) => Err(e),
+                            };
+                            if let Err(e) = published {
+                                // Redeliver the message to dead-letter it again rather than lose it
+                                warn!("failed to dead-letter message to {subject}: {e}");
+                                let retry = AckKind::Nak(Some(DEAD_LETTER_RETRY_DELAY));
+                                if let Err(e) = msg.ack_with(retry).await {
+                                    warn!("failed to nack message: {e}");
+                                }
+                                continue;
+                            }
+                        }
+                        if let Err(e) = msg.ack_with(AckKind::Term).await {
+                            warn!("failed to terminate message: {e}");
+                        }
+                    }
+                    _ = cancel_token.cancelled() => {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
 impl Host for Ctx {
     #[instrument(level = "debug", skip_all, fields(subject = %subject, timeout_ms))]
     async fn request(
@@ -60,7 +419,10 @@ impl Host for Ctx {
         };
 
         let timeout_duration = std::time::Duration::from_millis(timeout_ms as u64);
-        let request_future = plugin.client.request(subject, body.into());
+        let request_future =
+            plugin
+                .client
+                .request_with_headers(subject, self.message_headers(), body.into());
 
         let resp = match tokio::time::timeout(timeout_duration, request_future).await {
             Ok(Ok(msg)) => msg,
@@ -89,7 +451,7 @@ impl Host for Ctx {
 
         plugin
             .client
-            .publish(msg.subject, msg.body.into())
+            .publish_with_headers(msg.subject, self.message_headers(), msg.body.into())
             .await
             .context("failed to send message")?;
         Ok(Ok(()))
@@ -98,6 +460,27 @@ impl Host for Ctx {
 
 impl types::Host for Ctx {}
 
+impl Ctx {
+    /// The headers of the messages the component sends, carrying on its baggage.
+    fn message_headers(&self) -> async_nats::HeaderMap {
+        let mut headers = async_nats::HeaderMap::new();
+        if let Some(baggage) = self.baggage() {
+            headers.insert(BAGGAGE_HEADER, baggage.to_string().as_str());
+        }
+        headers
+    }
+}
+
+/// The baggage of a delivered message.
+fn message_baggage(headers: &async_nats::HeaderMap) -> Option<Baggage> {
+    let baggage = Baggage::parse(
+        headers
+            .get_all(BAGGAGE_HEADER)
+            .map(async_nats::HeaderValue::as_str),
+    );
+    (!baggage.is_empty()).then_some(baggage)
+}
+
 #[async_trait::async_trait]
 impl HostPlugin for WasmcloudMessaging {
     fn id(&self) -> &'static str {
@@ -114,6 +497,42 @@ impl HostPlugin for WasmcloudMessaging {
         }
     }
 
+    fn config_schema(&self) -> Option<ConfigSchema> {
+        Some(
+            ConfigSchema::new()
+                .with_field(
+                    "subscriptions",
+                    ConfigValueKind::List,
+                    "subjects delivered to the component's handler export",
+                )
+                .with_field(
+                    DELIVERY_KEY,
+                    ConfigValueKind::String,
+                    "at-most-once or at-least-once, defaults to at-most-once",
+                )
+                .with_field(
+                    STREAM_KEY,
+                    ConfigValueKind::String,
+                    "JetStream stream to read messages from with at-least-once delivery",
+                )
+                .with_field(
+                    CONSUMER_KEY,
+                    ConfigValueKind::String,
+                    "durable consumer name, defaults to the workload's namespace and name",
+                )
+                .with_field(
+                    MAX_REDELIVERIES_KEY,
+                    ConfigValueKind::Integer,
+                    "redeliveries of a message the handler failed on, defaults to 5",
+                )
+                .with_field(
+                    DEAD_LETTER_SUBJECT_KEY,
+                    ConfigValueKind::String,
+                    "subject messages are published to after their last delivery fails",
+                ),
+        )
+    }
+
     async fn on_component_bind(
         &self,
         component_handle: &mut WorkloadComponent,
@@ -140,11 +559,18 @@ impl HostPlugin for WasmcloudMessaging {
                 Some(subs) => subs.split(',').map(|s| s.to_string()).collect(),
                 None => vec![],
             };
+            let default_consumer = consumer_name(
+                component_handle.workload_namespace(),
+                component_handle.workload_name(),
+            );
+            let delivery = Delivery::from_config(&interface.config, &default_consumer)
+                .context("invalid wasmcloud:messaging delivery config")?;
             self.tracker.write().await.add_component(
                 component_handle,
                 ComponentData {
                     cancel_token: tokio_util::sync::CancellationToken::new(),
                     subscriptions: raw_subscriptions,
+                    delivery,
                 },
             );
         }
@@ -157,10 +583,14 @@ impl HostPlugin for WasmcloudMessaging {
         workload: &ResolvedWorkload,
         component_id: &str,
     ) -> anyhow::Result<()> {
-        let (cancel_token, subjects) = {
+        let (cancel_token, subjects, delivery) = {
             let lock = self.tracker.read().await;
             match lock.get_component_data(component_id) {
-                Some(data) => (data.cancel_token.clone(), data.subscriptions.clone()),
+                Some(data) => (
+                    data.cancel_token.clone(),
+                    data.subscriptions.clone(),
+                    data.delivery.clone(),
+                ),
                 None => return Ok(()),
             }
         };
@@ -174,79 +604,22 @@ impl HostPlugin for WasmcloudMessaging {
         let pre = bindings::MessagingPre::new(instance_pre)
             .context("failed to instantiate messaging pre")?;
 
-        let workload = workload.clone();
-        let component_id = component_id.to_string();
-
-        let mut subscriptions = Vec::<Subscriber>::new();
-        for subject in subjects {
-            let sub = match self.client.subscribe(subject.clone()).await {
-                Ok(sub) => sub,
-                Err(e) => {
-                    for sub in subscriptions {
-                        drop(sub);
-                    }
-                    return Err(anyhow::Error::new(e))
-                        .context(format!("failed to subscribe to {subject}"));
-                }
-            };
-
-            subscriptions.push(sub);
-        }
-
-        let mut messages = futures::stream::select_all(subscriptions);
-
-        tokio::spawn(async move {
-            loop {
-                tokio::select! {
-                    maybe_msg = messages.next() => {
-                        let msg = match maybe_msg {
-                            None => {
-                                break;
-                            }
-                            Some(msg) => {
-                                msg
-                            }
-                        };
-                        let mut store = match workload.new_store(&component_id).await {
-                            Err(e) => {
-                                warn!("failed to create store for component {component_id}: {e}");
-                                continue;
-                            }
-                            Ok(s) => s,
-                        };
-                        let proxy = match pre.instantiate_async(&mut store).await {
-                            Err(e) => {
-                                warn!("failed to instantiate component {component_id}: {e}");
-                                continue;
-                            }
-                            Ok(p) => p,
-                        };
-                        let reply_to = msg.reply.as_ref().map(|r| r.to_string());
-                        let msg = types::BrokerMessage {
-                            subject: msg.subject.to_string(),
-                            reply_to,
-                            body: msg.payload.into(),
-                        };
-                        match proxy
-                        .wasmcloud_messaging_handler()
-                        .call_handle_message(store, &msg).await {
-                            Ok(_) => {
-                                debug!("Message handled successfully");
-                            }
-                            Err(e) => {
-                                warn!("Error handling message: {e}");
-                            }
-                        }
+        let handler = MessageHandler {
+            workload: workload.clone(),
+            component_id: component_id.to_string(),
+            pre,
+        };
 
-                    }
-                    _ = cancel_token.cancelled() => {
-                        break;
-                    }
-                }
+        match delivery {
+            Delivery::AtMostOnce => {
+                self.deliver_at_most_once(handler, subjects, cancel_token)
+                    .await
             }
-        });
-
-        Ok(())
+            Delivery::AtLeastOnce(settings) => {
+                self.deliver_at_least_once(handler, subjects, cancel_token, settings)
+                    .await
+            }
+        }
     }
 
     async fn on_workload_unbind(
@@ -268,3 +641,77 @@ impl HostPlugin for WasmcloudMessaging {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_delivery_from_config() -> anyhow::Result<()> {
+        let consumer = consumer_name("default", "orders.v2");
+        assert_eq!(consumer, "default-orders_v2");
+
+        assert_eq!(
+            Delivery::from_config(&config(&[]), &consumer)?,
+            Delivery::AtMostOnce
+        );
+        assert_eq!(
+            Delivery::from_config(
+                &config(&[(DELIVERY_KEY, "at-least-once"), (STREAM_KEY, "ORDERS")]),
+                &consumer
+            )?,
+            Delivery::AtLeastOnce(AtLeastOnce {
+                stream: "ORDERS".to_string(),
+                consumer: "default-orders_v2".to_string(),
+                max_redeliveries: DEFAULT_MAX_REDELIVERIES,
+                dead_letter_subject: None,
+            })
+        );
+        assert_eq!(
+            Delivery::from_config(
+                &config(&[
+                    (DELIVERY_KEY, "at-least-once"),
+                    (STREAM_KEY, "ORDERS"),
+                    (CONSUMER_KEY, "billing"),
+                    (MAX_REDELIVERIES_KEY, "2"),
+                    (DEAD_LETTER_SUBJECT_KEY, "orders.dead"),
+                ]),
+                &consumer
+            )?,
+            Delivery::AtLeastOnce(AtLeastOnce {
+                stream: "ORDERS".to_string(),
+                consumer: "billing".to_string(),
+                max_redeliveries: 2,
+                dead_letter_subject: Some("orders.dead".to_string()),
+            })
+        );
+
+        for invalid in [
+            config(&[(DELIVERY_KEY, "exactly-once")]),
+            config(&[(DELIVERY_KEY, "at-least-once")]),
+            config(&[
+                (DELIVERY_KEY, "at-least-once"),
+                (STREAM_KEY, "ORDERS"),
+                (MAX_REDELIVERIES_KEY, "-1"),
+            ]),
+            config(&[
+                (DELIVERY_KEY, "at-least-once"),
+                (STREAM_KEY, "ORDERS"),
+                (CONSUMER_KEY, "orders.billing"),
+            ]),
+        ] {
+            assert!(
+                Delivery::from_config(&invalid, &consumer).is_err(),
+                "{invalid:?} should be rejected"
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! On-demand CPU profiling of the host process over HTTP, so operators can find host-side
+//! hotspots, like routing, serialization or plugin backends, on a live host.
+//!
+//! | route                                   | response                                    |
+//! |-----------------------------------------|---------------------------------------------|
+//! | `GET /debug/pprof/profile?seconds=N`    | a [pprof] profile, e.g. for `go tool pprof` |
+//! | `GET /debug/pprof/flamegraph?seconds=N` | an SVG flamegraph                           |
+//! | `GET /debug/pprof/heap`                 | a pprof profile of the live heap            |
+//!
+//! CPU profiles sample every thread of the process for `seconds`, 10 by default and at
+//! most [`MAX_SECONDS`], before responding. Only one is taken at a time; requests made
+//! meanwhile are turned away with `409 Conflict`.
+//!
+//! Heap profiles need the `heap-profiling` feature and the process to allocate with
+//! jemalloc with profiling enabled, e.g. `prof:true,prof_active:true` in its
+//! `malloc_conf`. They list the allocations still live, sampled as jemalloc's
+//! `lg_prof_sample` says, and are gzip-compressed as pprof tools expect. Without the
+//! feature the route answers `501 Not Implemented`.
+//!
+//! Profiles reveal the host's internals, so the endpoint should only be reachable by
+//! operators, e.g. by serving it on a loopback address.
+//!
+//! [pprof]: https://github.com/google/pprof
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use http_body_util::Full;
+use hyper::{Method, StatusCode};
+use pprof::protos::Message as _;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+use wasmtime_wasi_http::io::TokioIo;
+
+/// How long profiles sample for unless the request says otherwise.
+const DEFAULT_SECONDS: u64 = 10;
+/// The longest a profile can sample for.
+pub const MAX_SECONDS: u64 = 300;
+/// How often threads are sampled, in Hz. Off a round number so sampling doesn't line up
+/// with periodic work.
+const FREQUENCY: i32 = 99;
+
+/// The format of a CPU profile.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Pprof,
+    Flamegraph,
+}
+
+/// A profile asked for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Profile {
+    Cpu(Format, Duration),
+    Heap,
+}
+
+/// Parses a request's method, path and query into the profile asked for.
+///
+/// # Errors
+/// Returns the response status and message for requests that aren't for a profile.
+fn parse(
+    method: &Method,
+    path: &str,
+    query: Option<&str>,
+) -> Result<Profile, (StatusCode, String)> {
+    let format = match (method, path) {
+        (&Method::GET, "/debug/pprof/profile") => Format::Pprof,
+        (&Method::GET, "/debug/pprof/flamegraph") => Format::Flamegraph,
+        (&Method::GET, "/debug/pprof/heap") => return Ok(Profile::Heap),
+        _ => return Err((StatusCode::NOT_FOUND, "no such route".to_string())),
+    };
+    let seconds = match query
+        .unwrap_or_default()
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("seconds="))
+    {
+        Some(seconds) => seconds
+            .parse()
+            .ok()
+            .filter(|seconds| (1..=MAX_SECONDS).contains(seconds))
+            .ok_or_else(|| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("seconds must be a number from 1 to {MAX_SECONDS}"),
+                )
+            })?,
+        None => DEFAULT_SECONDS,
+    };
+    Ok(Profile::Cpu(format, Duration::from_secs(seconds)))
+}
+
+/// Samples the process for `duration`, blocking the calling thread meanwhile.
+fn profile(format: Format, duration: Duration) -> anyhow::Result<Vec<u8>> {
+    let guard = pprof::ProfilerGuardBuilder::default()
+        .frequency(FREQUENCY)
+        .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+        .build()
+        .context("failed to start profiler")?;
+    std::thread::sleep(duration);
+    let report = guard.report().build().context("failed to build profile")?;
+    let mut body = Vec::new();
+    match format {
+        Format::Pprof => report
+            .pprof()
+            .context("failed to build pprof profile")?
+            .encode(&mut body)
+            .context("failed to encode pprof profile")?,
+        Format::Flamegraph => report
+            .flamegraph(&mut body)
+            .context("failed to render flamegraph")?,
+    }
+    Ok(body)
+}
+
+/// Dumps a profile of the live heap from jemalloc.
+///
+/// # Errors
+/// Returns the response status and message if heap profiling isn't available.
+#[cfg(feature = "heap-profiling")]
+async fn heap_profile() -> Result<Vec<u8>, (StatusCode, String)> {
+    let unavailable = |message: &str| (StatusCode::NOT_IMPLEMENTED, message.to_string());
+    let prof_ctl = jemalloc_pprof::PROF_CTL
+        .as_ref()
+        .ok_or_else(|| unavailable("the host doesn't allocate with profiling enabled"))?;
+    let mut prof_ctl = prof_ctl.lock().await;
+    if !prof_ctl.activated() {
+        return Err(unavailable("heap profiling isn't active"));
+    }
+    prof_ctl
+        .dump_pprof()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))
+}
+
+#[cfg(not(feature = "heap-profiling"))]
+async fn heap_profile() -> Result<Vec<u8>, (StatusCode, String)> {
+    Err((
+        StatusCode::NOT_IMPLEMENTED,
+        "the host wasn't built with heap profiling".to_string(),
+    ))
+}
+
+/// Serves the profiler until the returned future is dropped or fails.
+pub async fn serve(addr: SocketAddr) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind profiler to {addr}"))?;
+    debug!(%addr, "serving host CPU profiles");
+    let profiling = Arc::new(Mutex::new(()));
+    loop {
+        let (stream, client_addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!(err = ?e, "failed to accept profiler connection");
+                continue;
+            }
+        };
+        let profiling = profiling.clone();
+        tokio::spawn(async move {
+            let service = hyper::service::service_fn(move |req| {
+                let profiling = profiling.clone();
+                async move { Ok::<_, std::convert::Infallible>(handle(&profiling, req).await) }
+            });
+            if let Err(e) = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(addr = ?client_addr, err = ?e, "error serving profiler client");
+            }
+        });
+    }
+}
+
+/// Handles a profiler request.
+async fn handle(
+    profiling: &Mutex<()>,
+    req: hyper::Request<hyper::body::Incoming>,
+) -> hyper::Response<Full<Bytes>> {
+    let (format, duration) = match parse(req.method(), req.uri().path(), req.uri().query()) {
+        Ok(Profile::Cpu(format, duration)) => (format, duration),
+        Ok(Profile::Heap) => {
+            return match heap_profile().await {
+                Ok(body) => response(StatusCode::OK, "application/octet-stream", body),
+                Err((status, message)) => response(status, "text/plain", message.into_bytes()),
+            };
+        }
+        Err((status, message)) => return response(status, "text/plain", message.into_bytes()),
+    };
+    let Ok(_profiling) = profiling.try_lock() else {
+        return response(
+            StatusCode::CONFLICT,
+            "text/plain",
+            b"a profile is already being taken".to_vec(),
+        );
+    };
+    info!(?format, ?duration, "profiling host CPU");
+    match tokio::task::spawn_blocking(move || profile(format, duration)).await {
+        Ok(Ok(body)) => match format {
+            Format::Pprof => response(StatusCode::OK, "application/octet-stream", body),
+            Format::Flamegraph => response(StatusCode::OK, "image/svg+xml", body),
+        },
+        Ok(Err(e)) => response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "text/plain",
+            format!("{e:#}").into_bytes(),
+        ),
+        Err(e) => response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "text/plain",
+            format!("profiler failed: {e}").into_bytes(),
+        ),
+    }
+}
+
+fn response(status: StatusCode, content_type: &str, body: Vec<u8>) -> hyper::Response<Full<Bytes>> {
+    hyper::Response::builder()
+        .status(status)
+        .header(hyper::header::CONTENT_TYPE, content_type)
+        .body(Full::new(Bytes::from(body)))
+        .expect("failed to build profiler response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            parse(&Method::GET, "/debug/pprof/profile", None),
+            Ok(Profile::Cpu(
+                Format::Pprof,
+                Duration::from_secs(DEFAULT_SECONDS)
+            ))
+        );
+        assert_eq!(
+            parse(
+                &Method::GET,
+                "/debug/pprof/flamegraph",
+                Some("x=1&seconds=30")
+            ),
+            Ok(Profile::Cpu(Format::Flamegraph, Duration::from_secs(30)))
+        );
+        for query in ["seconds=0", "seconds=301", "seconds=ten"] {
+            let (status, _) = parse(&Method::GET, "/debug/pprof/profile", Some(query))
+                .expect_err("the duration is rejected");
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+        assert_eq!(
+            parse(&Method::GET, "/debug/pprof/heap", Some("seconds=0")),
+            Ok(Profile::Heap)
+        );
+        let (status, _) =
+            parse(&Method::POST, "/debug/pprof/profile", None).expect_err("only GET is served");
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -14,6 +14,15 @@
 //! The [`WitInterface::contains`] method is used to determine if one interface
 //! specification can satisfy another. This is crucial for matching component
 //! requirements with plugin capabilities.
+//!
+//! # Version Negotiation
+//!
+//! Versions are matched by semver compatibility rather than exact equality, following
+//! the same rules wasmtime uses when linking imports: a component importing
+//! `wasi:http@0.2.1` can be served by a plugin providing `wasi:http@0.2.2`, but not
+//! `wasi:http@0.3.0`. Prerelease versions such as `0.2.0-draft` only match exactly.
+//! A workload can also ask for an explicit range with a requirement like
+//! `wasi:http@>=0.2.1, <0.2.5` or `wasi:keyvalue@^0.2`, see [`WitInterface::version_req`].
 
 use std::{
     collections::{HashMap, HashSet},
@@ -48,21 +57,15 @@ impl WitWorld {
     /// [`WitInterface`] there may be both imports and exports.
     pub fn includes_bidirectional(&self, interface: &WitInterface) -> bool {
         let import_match = self.imports.iter().find(|i| {
-            if let Some(v) = &interface.version
-                && let Some(ov) = &i.version
-                && v != ov
-            {
+            if !interface.version_compatible(i) {
                 return false;
             }
             i.namespace == interface.namespace && i.package == interface.package
         });
 
         let export_match = self.exports.iter().find(|e| {
-            // If both interfaces specify a version, they must match
-            if let Some(v) = &interface.version
-                && let Some(ov) = &e.version
-                && v != ov
-            {
+            // If both interfaces specify a version, they must be compatible
+            if !interface.version_compatible(e) {
                 return false;
             }
             e.namespace == interface.namespace && e.package == interface.package
@@ -149,10 +152,12 @@ pub struct WitInterface {
     pub package: String,
     /// The specific interfaces within the package (e.g., "incoming-handler", "types")
     pub interfaces: HashSet<String>,
-    // TODO: This is a nice way to represent a version, but it doesn't account for
-    // compatible versions. We should revisit this and implement https://docs.rs/semver/1.0.27/semver/struct.VersionReq.html
     /// Optional semantic version for the interface
     pub version: Option<semver::Version>,
+    /// Optional version requirement, e.g. `^0.2` or `>=0.2.1, <0.3`. Only set when the
+    /// interface was specified with a range instead of a single version, in which case
+    /// [`WitInterface::version`] is `None`.
+    pub version_req: Option<semver::VersionReq>,
     /// Additional configuration parameters for this interface
     pub config: HashMap<String, String>,
 }
@@ -191,7 +196,8 @@ impl WitInterface {
     /// # Returns
     /// `true` if:
     /// - The namespace and package match exactly
-    /// - If this interface has a version, it must match the other's version
+    /// - If both interfaces are versioned, the versions are compatible
+    ///   (see [`WitInterface::version_compatible`])
     /// - The other's interfaces are a subset of this interface's interfaces
     pub fn contains(&self, other: &WitInterface) -> bool {
         // Namespace and package must match
@@ -199,15 +205,56 @@ impl WitInterface {
             return false;
         }
 
-        // If both interfaces specify a version, they must match
-        if let Some(v) = &self.version
+        if !self.version_compatible(other) {
+            return false;
+        }
+
+        self.interfaces.is_superset(&other.interfaces)
+    }
+
+    /// Checks whether the versions of this interface and another are compatible,
+    /// ignoring namespace, package and interfaces.
+    ///
+    /// # Returns
+    /// `true` if:
+    /// - Either side is unversioned
+    /// - Any [`WitInterface::version_req`] matches the other side's version
+    /// - Both versions are equal, or semver compatible: same major version, or same
+    ///   minor version for `0.x` releases. Prerelease versions must be equal.
+    pub fn version_compatible(&self, other: &WitInterface) -> bool {
+        if let Some(req) = &self.version_req
             && let Some(ov) = &other.version
-            && v != ov
+            && !req.matches(ov)
+        {
+            return false;
+        }
+        if let Some(req) = &other.version_req
+            && let Some(v) = &self.version
+            && !req.matches(v)
         {
             return false;
         }
 
-        self.interfaces.is_superset(&other.interfaces)
+        match (&self.version, &other.version) {
+            (Some(v), Some(ov)) => semver_compatible(v, ov),
+            _ => true,
+        }
+    }
+}
+
+/// Returns whether two versions are on the same semver compatibility track, using
+/// the same rules as wasmtime's linker: `1.2.3` and `1.4.0` are compatible, `0.2.1`
+/// and `0.2.2` are compatible, `0.0.1` and `0.0.2` are not.
+fn semver_compatible(a: &semver::Version, b: &semver::Version) -> bool {
+    if a == b {
+        return true;
+    }
+    if !a.pre.is_empty() || !b.pre.is_empty() {
+        return false;
+    }
+    match (a.major, b.major) {
+        (0, 0) => a.minor != 0 && a.minor == b.minor,
+        (major, other_major) => major == other_major,
     }
 }
 
@@ -221,6 +268,8 @@ impl Display for WitInterface {
         }
         if let Some(v) = &self.version {
             write!(f, "@{}", v)?;
+        } else if let Some(req) = &self.version_req {
+            write!(f, "@{req}")?;
         }
         Ok(())
     }
@@ -234,6 +283,7 @@ impl std::hash::Hash for WitInterface {
             iface.hash(state);
         }
         self.version.hash(state);
+        self.version_req.hash(state);
         for (k, v) in &self.config {
             k.hash(state);
             v.hash(state);
@@ -267,13 +317,21 @@ impl From<&str> for WitInterface {
                 .collect(),
             None => HashSet::new(),
         };
-        let version = version.and_then(|v| semver::Version::parse(v).ok());
+        // A single version is the common case, anything else is treated as a requirement
+        let (version, version_req) = match version.map(str::trim) {
+            Some(v) => match semver::Version::parse(v) {
+                Ok(version) => (Some(version), None),
+                Err(_) => (None, semver::VersionReq::parse(v).ok()),
+            },
+            None => (None, None),
+        };
 
         WitInterface {
             namespace: namespace.to_string(),
             package: package.to_string(),
             interfaces,
             version,
+            version_req,
             config: HashMap::new(),
         }
     }
@@ -296,6 +354,7 @@ mod tests {
             package: package.to_string(),
             interfaces: interfaces.iter().map(|s| s.to_string()).collect(),
             version: None,
+            version_req: None,
             config: HashMap::new(),
         }
     }
@@ -311,6 +370,7 @@ mod tests {
             package: package.to_string(),
             interfaces: interfaces.iter().map(|s| s.to_string()).collect(),
             version: Some(semver::Version::parse(version).unwrap()),
+            version_req: None,
             config: HashMap::new(),
         }
     }
@@ -394,7 +454,7 @@ mod tests {
         let wit2 = WitInterface::from("wasi:blobstore/types@0.2.0");
         assert!(wit1.contains(&wit2));
 
-        // Different versions should not match
+        // Incompatible versions should not match
         let wit3 = WitInterface::from("wasi:blobstore/types@0.2.0");
         let wit4 = WitInterface::from("wasi:blobstore/types@0.3.0");
         assert!(!wit3.contains(&wit4));
@@ -427,6 +487,53 @@ mod tests {
         assert!(!interface_a.contains(&interface_c));
     }
 
+    #[test]
+    fn test_version_compatibility() {
+        // A newer patch release satisfies an older import
+        let plugin = WitInterface::from("wasi:http/incoming-handler@0.2.2");
+        let component = WitInterface::from("wasi:http/incoming-handler@0.2.1");
+        assert!(plugin.contains(&component));
+        assert!(component.version_compatible(&plugin));
+
+        // Minor releases are breaking before 1.0, major releases after
+        let v1 = WitInterface::from("wasi:http@1.0.0");
+        assert!(v1.version_compatible(&WitInterface::from("wasi:http@1.3.0")));
+        assert!(!v1.version_compatible(&WitInterface::from("wasi:http@2.0.0")));
+        let v0 = WitInterface::from("wasi:http@0.0.1");
+        assert!(!v0.version_compatible(&WitInterface::from("wasi:http@0.0.2")));
+
+        // Prereleases only match exactly
+        let draft = WitInterface::from("wasi:keyvalue/store@0.2.0-draft");
+        assert!(draft.version_compatible(&WitInterface::from("wasi:keyvalue@0.2.0-draft")));
+        assert!(!draft.version_compatible(&WitInterface::from("wasi:keyvalue@0.2.0-draft2")));
+        assert!(!draft.version_compatible(&WitInterface::from("wasi:keyvalue@0.2.0")));
+    }
+
+    #[test]
+    fn test_version_requirements() {
+        let ranged = WitInterface::from("wasi:http/incoming-handler@>=0.2.1, <0.2.5");
+        assert!(ranged.version.is_none());
+        assert_eq!(
+            ranged.version_req,
+            Some(semver::VersionReq::parse(">=0.2.1, <0.2.5").unwrap())
+        );
+        assert!(ranged.version_compatible(&WitInterface::from("wasi:http@0.2.3")));
+        assert!(!ranged.version_compatible(&WitInterface::from("wasi:http@0.2.6")));
+        assert!(!ranged.version_compatible(&WitInterface::from("wasi:http@0.2.0")));
+        // Unversioned interfaces still match anything
+        assert!(ranged.version_compatible(&WitInterface::from("wasi:http")));
+
+        let caret = WitInterface::from("wasi:keyvalue/store@^0.2");
+        let world = WitWorld {
+            imports: HashSet::new(),
+            exports: [WitInterface::from("wasi:keyvalue/store,atomics@0.2.4")]
+                .into_iter()
+                .collect(),
+        };
+        assert!(world.includes_bidirectional(&caret));
+        assert!(!world.includes_bidirectional(&WitInterface::from("wasi:keyvalue/store@^0.3")));
+    }
+
     #[test]
     fn test_contains_config_ignored() {
         // Config doesn't affect contains logic, only namespace, package, interfaces, and version matter
@@ -687,6 +794,12 @@ mod tests {
             "wasi:http/incoming-handler@0.2.0"
         );
 
+        let iface_with_req = WitInterface::from("wasi:http/incoming-handler@^0.2");
+        assert_eq!(
+            format!("{}", iface_with_req),
+            "wasi:http/incoming-handler@^0.2"
+        );
+
         let iface_no_interfaces = create_interface("wasi", "logging", &[]);
         assert_eq!(format!("{}", iface_no_interfaces), "wasi:logging");
     }
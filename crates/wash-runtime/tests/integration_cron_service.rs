@@ -12,8 +12,9 @@ use std::io::Read;
 
 use wash_runtime::{
     engine::Engine,
-    host::{HostApi, HostBuilder},
-    types::{Component, Service, Workload, WorkloadStartRequest},
+    host::{HostApi, HostBuilder, services::SERVICE_CONFIG_KEY},
+    types::{Component, Service, Workload, WorkloadStartRequest, WorkloadState},
+    wit::WitInterface,
 };
 
 const CRON_SERVICE_WASM: &[u8] = include_bytes!("fixtures/cron_service.wasm");
@@ -46,16 +47,22 @@ async fn test_cron_service_integration() -> Result<()> {
             namespace: "test".to_string(),
             name: "cron-service-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: Some(Service {
                 bytes: bytes::Bytes::from_static(CRON_SERVICE_WASM),
                 local_resources: Default::default(),
                 max_restarts: 0,
+                name: None,
+                exports: vec![],
+                command: None,
+                source: None,
             }),
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(CRON_COMPONENT_WASM),
                 local_resources: Default::default(),
                 max_invocations: 1,
                 pool_size: 0,
+                source: None,
             }],
             host_interfaces: vec![],
             volumes: vec![],
@@ -108,3 +115,85 @@ async fn test_cron_service_integration() -> Result<()> {
 
     Ok(())
 }
+
+fn cron_service(name: Option<&str>) -> Service {
+    Service {
+        bytes: bytes::Bytes::from_static(CRON_SERVICE_WASM),
+        local_resources: Default::default(),
+        max_restarts: 0,
+        name: name.map(str::to_string),
+        exports: vec![WitInterface::from("wasmcloud:example/cron@0.0.1")],
+        command: None,
+        source: None,
+    }
+}
+
+#[tokio::test]
+async fn test_cron_service_published_to_other_workloads() -> Result<()> {
+    let engine = Engine::builder().build()?;
+    let host = HostBuilder::new().with_engine(engine).build()?;
+    let host = host.start().await.context("Failed to start host")?;
+
+    // Publish the cron component under the "cron" service
+    host.workload_start(WorkloadStartRequest {
+        workload_id: uuid::Uuid::new_v4().to_string(),
+        workload: Workload {
+            namespace: "test".to_string(),
+            name: "cron-provider".to_string(),
+            annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
+            service: Some(cron_service(Some("cron"))),
+            components: vec![Component {
+                bytes: bytes::Bytes::from_static(CRON_COMPONENT_WASM),
+                local_resources: Default::default(),
+                max_invocations: 1,
+                pool_size: 0,
+                source: None,
+            }],
+            host_interfaces: vec![],
+            volumes: vec![],
+        },
+    })
+    .await
+    .context("Failed to start cron-provider workload")?;
+
+    // A workload without its own cron component imports it from the service
+    let consumer = |service: &str| {
+        let mut cron = WitInterface::from("wasmcloud:example/cron");
+        cron.config
+            .insert(SERVICE_CONFIG_KEY.to_string(), service.to_string());
+        WorkloadStartRequest {
+            workload_id: uuid::Uuid::new_v4().to_string(),
+            workload: Workload {
+                namespace: "test".to_string(),
+                name: "cron-consumer".to_string(),
+                annotations: HashMap::new(),
+                secret_config_keys: Default::default(),
+                service: Some(cron_service(None)),
+                components: vec![],
+                host_interfaces: vec![cron],
+                volumes: vec![],
+            },
+        }
+    };
+
+    let response = host
+        .workload_start(consumer("cron"))
+        .await
+        .context("Failed to start cron-consumer workload")?;
+    assert_eq!(
+        response.workload_status.workload_state,
+        WorkloadState::Running
+    );
+
+    let err = host
+        .workload_start(consumer("missing"))
+        .await
+        .expect_err("referencing an unknown service should fail");
+    assert!(
+        err.to_string().contains("service 'missing'"),
+        "unexpected error: {err}"
+    );
+
+    Ok(())
+}
@@ -70,6 +70,7 @@ async fn test_blobby_integration() -> Result<()> {
             namespace: "test".to_string(),
             name: "blobby-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(BLOBBY_WASM),
@@ -80,9 +81,12 @@ async fn test_blobby_integration() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 100,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -90,6 +94,7 @@ async fn test_blobby_integration() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: None,
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "blobby-test".to_string());
@@ -107,6 +112,7 @@ async fn test_blobby_integration() -> Result<()> {
                     .into_iter()
                     .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -114,6 +120,7 @@ async fn test_blobby_integration() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
@@ -271,6 +278,7 @@ async fn test_blobby_error_handling() -> Result<()> {
             namespace: "error-test".to_string(),
             name: "blobby-error-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(BLOBBY_WASM),
@@ -281,9 +289,12 @@ async fn test_blobby_error_handling() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 50,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -291,6 +302,7 @@ async fn test_blobby_error_handling() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "blobby-error-test".to_string());
@@ -308,6 +320,7 @@ async fn test_blobby_error_handling() -> Result<()> {
                     .into_iter()
                     .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -315,6 +328,7 @@ async fn test_blobby_error_handling() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
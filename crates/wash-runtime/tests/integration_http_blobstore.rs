@@ -70,6 +70,7 @@ async fn test_http_blobstore_integration() -> Result<()> {
             namespace: "test".to_string(),
             name: "test-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(HTTP_BLOBSTORE_WASM),
@@ -80,9 +81,12 @@ async fn test_http_blobstore_integration() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 100,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -90,6 +94,7 @@ async fn test_http_blobstore_integration() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "foo".to_string());
@@ -107,6 +112,7 @@ async fn test_http_blobstore_integration() -> Result<()> {
                     .into_iter()
                     .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
@@ -255,6 +261,7 @@ async fn test_plugin_lifecycle() -> Result<()> {
 //             namespace: "stress-test".to_string(),
 //             name: "large-payload-workload".to_string(),
 //             annotations: HashMap::new(),
+//             secret_config_keys: Default::default(),
 //             service: None,
 //             components: vec![Component {
 //                 bytes: bytes::Bytes::from_static(HTTP_BLOBSTORE_WASM),
@@ -83,6 +83,7 @@ async fn test_http_counter_integration() -> Result<()> {
             namespace: "test".to_string(),
             name: "http-counter-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(HTTP_COUNTER_WASM),
@@ -98,9 +99,12 @@ async fn test_http_counter_integration() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 100,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -108,6 +112,7 @@ async fn test_http_counter_integration() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "foo".to_string());
@@ -125,6 +130,7 @@ async fn test_http_counter_integration() -> Result<()> {
                     .into_iter()
                     .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -134,6 +140,7 @@ async fn test_http_counter_integration() -> Result<()> {
                         .into_iter()
                         .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -141,6 +148,7 @@ async fn test_http_counter_integration() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -148,6 +156,7 @@ async fn test_http_counter_integration() -> Result<()> {
                     package: "config".to_string(),
                     interfaces: ["store".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-rc.1").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
@@ -422,6 +431,7 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
             namespace: "error-test".to_string(),
             name: "http-counter-error-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(HTTP_COUNTER_WASM),
@@ -432,9 +442,12 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 50,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -442,6 +455,7 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "error-test".to_string());
@@ -459,6 +473,7 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
                     .into_iter()
                     .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -468,6 +483,7 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
                         .into_iter()
                         .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -475,6 +491,7 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -482,6 +499,7 @@ async fn test_http_counter_error_scenarios() -> Result<()> {
                     package: "config".to_string(),
                     interfaces: ["store".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-rc.1").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
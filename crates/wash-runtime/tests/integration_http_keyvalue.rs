@@ -82,6 +82,7 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
             namespace: "test".to_string(),
             name: "keyvalue-counter-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(HTTP_KEYVALUE_COUNTER_WASM),
@@ -92,9 +93,12 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 100,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -102,6 +106,7 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "keyvalue-counter-test".to_string());
@@ -115,6 +120,7 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
                         .into_iter()
                         .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -122,6 +128,7 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
                     package: "blobstore".to_string(),
                     interfaces: ["blobstore".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -129,6 +136,7 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
                     package: "config".to_string(),
                     interfaces: ["store".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-rc.1").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -136,6 +144,7 @@ async fn test_http_keyvalue_counter_integration() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
@@ -351,6 +360,7 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
             namespace: "concurrent-test".to_string(),
             name: "concurrent-counter-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(HTTP_KEYVALUE_COUNTER_WASM),
@@ -361,9 +371,12 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 3, // Higher pool size for concurrent testing
                 max_invocations: 200,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -371,6 +384,7 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "concurrent-counter-test".to_string());
@@ -384,6 +398,7 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
                         .into_iter()
                         .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -391,6 +406,7 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
                     package: "blobstore".to_string(),
                     interfaces: ["blobstore".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -398,6 +414,7 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
                     package: "config".to_string(),
                     interfaces: ["store".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-rc.1").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -405,6 +422,7 @@ async fn test_keyvalue_counter_concurrent_access() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
@@ -531,6 +549,7 @@ async fn test_keyvalue_error_handling() -> Result<()> {
             namespace: "error-test".to_string(),
             name: "keyvalue-error-workload".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: bytes::Bytes::from_static(HTTP_KEYVALUE_COUNTER_WASM),
@@ -541,9 +560,12 @@ async fn test_keyvalue_error_handling() -> Result<()> {
                     environment: HashMap::new(),
                     volume_mounts: vec![],
                     allowed_hosts: vec![],
+                    arguments: vec![],
+                    inherited_environment: vec![],
                 },
                 pool_size: 1,
                 max_invocations: 50,
+                source: None,
             }],
             host_interfaces: vec![
                 WitInterface {
@@ -551,6 +573,7 @@ async fn test_keyvalue_error_handling() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.2").unwrap()),
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "keyvalue-error-test".to_string());
@@ -564,6 +587,7 @@ async fn test_keyvalue_error_handling() -> Result<()> {
                         .into_iter()
                         .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -571,6 +595,7 @@ async fn test_keyvalue_error_handling() -> Result<()> {
                     package: "blobstore".to_string(),
                     interfaces: ["blobstore".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -578,6 +603,7 @@ async fn test_keyvalue_error_handling() -> Result<()> {
                     package: "config".to_string(),
                     interfaces: ["store".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-rc.1").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -585,6 +611,7 @@ async fn test_keyvalue_error_handling() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],
@@ -251,7 +251,9 @@ impl CliCommand for DevCommand {
                                 key_path,
                                 self.tls_ca.as_deref(),
                             )
-                            .await?;
+                            .await?
+                            // Certificates regenerated during development are picked up
+                            .with_certificate_reload();
 
                             builder = builder.with_http_handler(Arc::new(http_server));
 
@@ -591,6 +593,7 @@ fn extract_component_interfaces(component_bytes: &[u8]) -> anyhow::Result<HashSe
             package: package.to_string(),
             interfaces: HashSet::from([interface]),
             version,
+            version_req: None,
             config: HashMap::new(),
         })
     };
@@ -686,11 +689,13 @@ fn create_workload(
                 name: "dev".to_string(),
                 mount_path: "/tmp".to_string(),
                 read_only: false,
+                quota_bytes: None,
             }],
             ..Default::default()
         },
         pool_size: -1,
         max_invocations: -1,
+        source: None,
     });
     components.extend(dev_register_components.into_iter().map(|bytes| Component {
         bytes,
@@ -711,6 +716,7 @@ fn create_workload(
         components,
         host_interfaces,
         annotations: HashMap::default(),
+        secret_config_keys: Default::default(),
         service: None,
         volumes: vec![Volume {
             name: "dev".to_string(),
@@ -1,8 +1,28 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
 use anyhow::Context as _;
 use clap::Args;
 use tracing::info;
+use wash_runtime::host::acme::{Acme, AcmeChallenge, LETS_ENCRYPT_DIRECTORY};
+use wash_runtime::host::api_keys::ApiKeyStore;
+use wash_runtime::host::assets::{
+    AssetSource, DEFAULT_INDEX, DirectoryAssets, ObjectStoreAssets, StaticMount,
+};
+use wash_runtime::host::baggage::BaggageConfig;
+use wash_runtime::host::blob_volumes::NatsVolumeBlobstore;
+use wash_runtime::host::connections::ConnectionLimits;
+use wash_runtime::host::dns::{Resolver, ResolverConfig};
+use wash_runtime::host::forwarded::{ForwardedConfig, ForwardedPolicy, IpRange};
+use wash_runtime::host::http::HostHandler as _;
+use wash_runtime::host::ip_filter::IpFilter;
+use wash_runtime::host::request_id::RequestIdConfig;
+use wash_runtime::host::upstream::Upstream;
+use wash_runtime::plugin::encryption::{EncryptionKey, Encryptor, LocalKeyring};
+use wash_runtime::plugin::wasi_blobstore_gcs::{GcsAuth, GcsBackend, WasiBlobstoreGcs};
 #[cfg(not(target_os = "windows"))]
 use wash_runtime::plugin::wasi_webgpu::WasiWebGpu;
 
@@ -26,10 +46,235 @@ pub struct HostCommand {
     #[clap(long = "host-name")]
     pub host_name: Option<String>,
 
+    /// A DNS server to resolve the host names of outbound connections with instead of the
+    /// system's, as `ip` or `ip:port`. May be repeated.
+    #[clap(long = "dns-server", value_parser = parse_dns_server)]
+    pub dns_servers: Vec<SocketAddr>,
+
+    /// A search domain for host names of outbound connections that aren't fully
+    /// qualified. May be repeated.
+    #[clap(long = "dns-search")]
+    pub dns_search: Vec<String>,
+
+    /// Resolve a host name of outbound connections to fixed addresses without DNS, as
+    /// `name=ip[,ip...]`. May be repeated.
+    #[clap(long = "dns-host", value_parser = ResolverConfig::parse_host)]
+    pub dns_hosts: Vec<(String, Vec<IpAddr>)>,
+
     /// The address on which the HTTP server will listen
     #[clap(long = "http-addr")]
     pub http_addr: Option<SocketAddr>,
 
+    /// Serve static files on the HTTP server, as `host/prefix=source`, e.g.
+    /// `app.localhost/assets=./dist`. The source is a directory, or `container:name` for a
+    /// `wasi:blobstore` container. Use `*` as the host to serve every virtual host.
+    #[clap(long = "http-static", value_parser = parse_static_mount, requires = "http_addr")]
+    pub http_static: Vec<(String, String, String)>,
+
+    /// Serve the index file of a static mount for paths it has no file for, as single-page
+    /// apps with client-side routing expect
+    #[clap(
+        long = "http-static-spa",
+        default_value_t = false,
+        requires = "http_addr"
+    )]
+    pub http_static_spa: bool,
+
+    /// Proxy requests on the HTTP server to an upstream server, as `host/prefix=url`, e.g.
+    /// `app.localhost/legacy=http://localhost:8080`. An upstream with a prefix takes its
+    /// requests ahead of workloads; without one it takes the requests no workload serves.
+    #[clap(long = "http-upstream", value_parser = parse_upstream, requires = "http_addr")]
+    pub http_upstream: Vec<(String, String, String)>,
+
+    /// The most connections the HTTP server keeps open at once. At the limit it stops
+    /// accepting until a connection closes.
+    #[clap(long = "http-max-connections", requires = "http_addr")]
+    pub http_max_connections: Option<usize>,
+
+    /// The most requests the HTTP server serves on one connection before closing it
+    #[clap(long = "http-max-requests-per-connection", requires = "http_addr")]
+    pub http_max_requests_per_connection: Option<usize>,
+
+    /// How many seconds the HTTP server keeps a connection open without a new request
+    #[clap(long = "http-idle-timeout-secs", requires = "http_addr")]
+    pub http_idle_timeout_secs: Option<u64>,
+
+    /// How many connections the kernel queues for the HTTP server while it isn't
+    /// accepting
+    #[clap(
+        long = "http-accept-backlog",
+        default_value_t = wash_runtime::host::connections::DEFAULT_ACCEPT_BACKLOG,
+        requires = "http_addr"
+    )]
+    pub http_accept_backlog: u32,
+
+    /// Require a PROXY protocol v2 header on every HTTP connection and pass the client
+    /// address it names to workloads, for hosts behind a load balancer such as HAProxy or
+    /// an AWS NLB that sends one
+    #[clap(
+        long = "http-proxy-protocol",
+        default_value_t = false,
+        requires = "http_addr"
+    )]
+    pub http_proxy_protocol: bool,
+
+    /// What workloads see in the `forwarded` and `x-forwarded-for` headers of requests:
+    /// `preserve` passes them through, `overwrite` replaces them with the client's
+    /// address and `append` adds the connection's address to those of trusted proxies
+    #[clap(
+        long = "http-forwarded-policy",
+        default_value = "preserve",
+        requires = "http_addr"
+    )]
+    pub http_forwarded_policy: ForwardedPolicy,
+
+    /// A proxy whose forwarding headers are believed, as an address or CIDR range, e.g.
+    /// `10.0.0.0/8`
+    #[clap(long = "http-trusted-proxy", requires = "http_addr")]
+    pub http_trusted_proxies: Vec<IpRange>,
+
+    /// An address or CIDR range the HTTP server accepts connections from, e.g.
+    /// `10.0.0.0/8`. When set, connections from other addresses are closed
+    #[clap(long = "http-allowed-ip", requires = "http_addr")]
+    pub http_allowed_ips: Vec<IpRange>,
+
+    /// An address or CIDR range the HTTP server closes connections from, even when
+    /// allowed with `--http-allowed-ip`
+    #[clap(long = "http-denied-ip", requires = "http_addr")]
+    pub http_denied_ips: Vec<IpRange>,
+
+    /// The header the HTTP server reads and writes request IDs in
+    #[clap(
+        long = "http-request-id-header",
+        default_value = wash_runtime::host::request_id::DEFAULT_HEADER,
+        requires = "http_addr"
+    )]
+    pub http_request_id_header: String,
+
+    /// Give every HTTP request a new ID, rather than keeping the one it arrives with
+    #[clap(
+        long = "http-regenerate-request-ids",
+        default_value_t = false,
+        requires = "http_addr"
+    )]
+    pub http_regenerate_request_ids: bool,
+
+    /// A W3C baggage key passed from HTTP requests to workloads and on to the requests and
+    /// messages they send, or `*` for every key. Other entries are removed.
+    #[clap(long = "http-baggage-key", requires = "http_addr")]
+    pub http_baggage_keys: Vec<String>,
+
+    /// A domain the HTTP server serves over HTTPS with a certificate ordered from an ACME
+    /// certificate authority. The server then only speaks HTTPS and answers TLS-ALPN-01
+    /// challenges, so it has to listen on port 443, unless `--http-acme-challenge-addr` is
+    /// set. The account and certificates are kept in the data directory
+    #[clap(long = "http-acme-domain", requires = "http_addr")]
+    pub http_acme_domains: Vec<String>,
+
+    /// A contact the ACME certificate authority may notify about expiring certificates,
+    /// e.g. `mailto:ops@example.com`
+    #[clap(long = "http-acme-contact", requires = "http_acme_domains")]
+    pub http_acme_contacts: Vec<String>,
+
+    /// The directory URL of the ACME certificate authority
+    #[clap(
+        long = "http-acme-directory",
+        default_value = LETS_ENCRYPT_DIRECTORY,
+        requires = "http_acme_domains"
+    )]
+    pub http_acme_directory: String,
+
+    /// Answer HTTP-01 challenges on a plain HTTP server at this address, e.g.
+    /// `0.0.0.0:80`, rather than TLS-ALPN-01 challenges
+    #[clap(long = "http-acme-challenge-addr", requires = "http_acme_domains")]
+    pub http_acme_challenge_addr: Option<SocketAddr>,
+
+    /// Keep the host's API keys in this JSON file so they survive restarts, rather than
+    /// in memory
+    #[clap(long = "api-key-file")]
+    pub api_key_file: Option<std::path::PathBuf>,
+
+    /// Enforce the CPU limits and the `cpuset` and `numa-nodes` pinning of workloads with
+    /// cgroups created under the host's own cgroup, which must be delegated to it. Linux
+    /// with cgroup v2 only.
+    #[clap(long = "cgroups", default_value_t = false)]
+    pub cgroups: bool,
+
+    /// A host directory workloads may mount as a host path volume, along with everything
+    /// beneath it, e.g. `/etc/ssl/certs`. May be repeated. Workloads mounting any other
+    /// directory are rejected, as are all host path volumes without this flag.
+    #[clap(long = "host-path-volume")]
+    pub host_path_volumes: Vec<std::path::PathBuf>,
+
+    /// Log a sample of the files workloads open, create and delete in the directories they
+    /// mount, e.g. `0.1` for one in ten operations or `1` for all of them
+    #[clap(long = "fs-audit-sample-rate")]
+    pub fs_audit_sample_rate: Option<f64>,
+
+    /// Share the components this host compiles with the cluster's other hosts through the
+    /// `wash-artifacts` NATS object store, and load those they compiled from it. Compiled
+    /// components run unverified, so anyone able to write to the object store can run code
+    /// on the hosts
+    #[clap(long = "share-compiled-components", default_value_t = false)]
+    pub share_compiled_components: bool,
+
+    /// Fetch the components workloads need from the cluster's other hosts when they have
+    /// them, checked against the registry's manifest, rather than pulling them from the
+    /// registry, and serve the components this host pulls to them
+    #[clap(long = "peer-components", default_value_t = false)]
+    pub peer_components: bool,
+
+    /// Serve the interfaces published by named workload services as JSON over HTTP on this
+    /// address, e.g. `POST /{namespace}/{service}/{package}/{interface}/{function}`
+    #[clap(long = "json-gateway-addr")]
+    pub json_gateway_addr: Option<SocketAddr>,
+
+    /// Serve a GraphQL endpoint for each named workload service on this address, at
+    /// `/{namespace}/{service}`
+    #[clap(long = "graphql-gateway-addr")]
+    pub graphql_gateway_addr: Option<SocketAddr>,
+
+    /// Serve CPU profiles of the host process on this address, at
+    /// `/debug/pprof/profile?seconds=N` and `/debug/pprof/flamegraph?seconds=N`, and heap
+    /// profiles at `/debug/pprof/heap` when wash is built with `heap-profiling`. Anyone who
+    /// can reach it can profile the host, so bind it to a loopback or otherwise private
+    /// address. Not available on Windows.
+    #[cfg(not(target_os = "windows"))]
+    #[clap(long = "profiler-addr")]
+    pub profiler_addr: Option<SocketAddr>,
+
+    /// Serve the gRPC services workloads declare with a `wasmcloud:grpc/ingress` host
+    /// interface on this address
+    #[clap(long = "grpc-ingress-addr")]
+    pub grpc_ingress_addr: Option<SocketAddr>,
+
+    /// Store `wasi:blobstore` containers as buckets in this Google Cloud project instead of
+    /// NATS object stores. Credentials are read from the key file named by
+    /// `GOOGLE_APPLICATION_CREDENTIALS`, or else from the workload identity of the machine.
+    #[clap(long = "blobstore-gcs-project")]
+    pub blobstore_gcs_project: Option<String>,
+
+    /// Encrypt `wasi:blobstore` objects with the AES-256 key in this file, base64-encoded,
+    /// before they are written to the backend
+    #[clap(long = "blobstore-encryption-key")]
+    pub blobstore_encryption_key: Option<std::path::PathBuf>,
+
+    /// Encrypt `wasi:keyvalue` values with the AES-256 key in this file, base64-encoded,
+    /// before they are written to NATS
+    #[clap(long = "keyvalue-encryption-key")]
+    pub keyvalue_encryption_key: Option<std::path::PathBuf>,
+
+    /// Read `wasi:blobstore` objects and `wasi:keyvalue` values that aren't encrypted as
+    /// they are, while a backend written before encryption was enabled is migrated. Anyone
+    /// who can write to the backend can then plant data that's read as authentic.
+    #[clap(long = "encryption-plaintext-migration", default_value_t = false)]
+    pub encryption_plaintext_migration: bool,
+
+    /// An additional named storage backend, as `name=nats-url`. Workloads select it by
+    /// setting `profile` in the config of their `wasi:keyvalue` or `wasi:blobstore` interface.
+    #[clap(long = "storage-profile", value_parser = parse_storage_profile)]
+    pub storage_profiles: Vec<(String, String)>,
+
     /// Enable WASI WebGPU support
     #[cfg(not(target_os = "windows"))]
     #[clap(long = "wasi-webgpu", default_value_t = false)]
@@ -37,7 +282,7 @@ pub struct HostCommand {
 }
 
 impl CliCommand for HostCommand {
-    async fn handle(&self, _ctx: &CliContext) -> anyhow::Result<CommandOutput> {
+    async fn handle(&self, ctx: &CliContext) -> anyhow::Result<CommandOutput> {
         let scheduler_nats_client =
             wash_runtime::washlet::connect_nats(self.scheduler_nats_url.clone(), None)
                 .await
@@ -49,6 +294,27 @@ impl CliCommand for HostCommand {
                 .context("failed to connect to NATS")?;
         let data_nats_client = Arc::new(data_nats_client);
 
+        let mut storage_profiles = Vec::with_capacity(self.storage_profiles.len());
+        for (name, url) in &self.storage_profiles {
+            let client = wash_runtime::washlet::connect_nats(url.clone(), None)
+                .await
+                .with_context(|| {
+                    format!("failed to connect to NATS for storage profile '{name}'")
+                })?;
+            storage_profiles.push((name.clone(), Arc::new(client)));
+        }
+
+        let mut keyvalue = wash_runtime::washlet::plugins::wasi_keyvalue::WasiKeyvalue::new(
+            data_nats_client.clone(),
+        );
+        for (name, client) in &storage_profiles {
+            keyvalue = keyvalue.with_profile(name.clone(), client.clone())?;
+        }
+        if let Some(path) = &self.keyvalue_encryption_key {
+            keyvalue = keyvalue
+                .with_encryption(load_encryptor(path, self.encryption_plaintext_migration)?);
+        }
+
         let mut cluster_host_builder = wash_runtime::washlet::ClusterHostBuilder::default()
             .with_nats_client(Arc::new(scheduler_nats_client))
             .with_host_group(self.host_group.clone())
@@ -58,32 +324,186 @@ impl CliCommand for HostCommand {
             .with_plugin(Arc::new(
                 wash_runtime::washlet::plugins::wasi_logging::TracingLogging::default(),
             ))?
-            .with_plugin(Arc::new(
-                wash_runtime::washlet::plugins::wasi_blobstore::WasiBlobstore::new(
-                    data_nats_client.clone(),
-                ),
-            ))?
             .with_plugin(Arc::new(
                 wash_runtime::washlet::plugins::wasmcloud_messaging::WasmcloudMessaging::new(
                     data_nats_client.clone(),
                 ),
             ))?
-            .with_plugin(Arc::new(
-                wash_runtime::washlet::plugins::wasi_keyvalue::WasiKeyvalue::new(
+            .with_plugin(Arc::new(keyvalue))?
+            .with_queue_source(
+                "nats",
+                Arc::new(wash_runtime::host::invoker::nats::NatsWorkQueues::new(
                     data_nats_client.clone(),
-                ),
-            ))?;
+                )),
+            )?
+            .with_wrpc_transport(Arc::new(wash_runtime::host::wrpc::WrpcTransport::new(
+                data_nats_client.clone(),
+            )));
+
+        let resolver = Arc::new(Resolver::new(ResolverConfig {
+            name_servers: self.dns_servers.clone(),
+            search_domains: self.dns_search.clone(),
+            hosts: self.dns_hosts.iter().cloned().collect(),
+        }));
+
+        let blobstore_encryptor = self
+            .blobstore_encryption_key
+            .as_deref()
+            .map(|path| load_encryptor(path, self.encryption_plaintext_migration))
+            .transpose()?;
+
+        cluster_host_builder = match &self.blobstore_gcs_project {
+            Some(project) => {
+                let auth = GcsAuth::from_env().context("failed to load GCS credentials")?;
+                info!(project, ?auth, "Using Google Cloud Storage for blobstore");
+                let backend = GcsBackend::new(project, auth)?.with_resolver(resolver.clone())?;
+                let mut blobstore = WasiBlobstoreGcs::new(backend);
+                if let Some(encryptor) = blobstore_encryptor {
+                    blobstore = blobstore.with_encryption(encryptor);
+                }
+                cluster_host_builder.with_plugin(Arc::new(blobstore))?
+            }
+            None => {
+                let mut blobstore =
+                    wash_runtime::washlet::plugins::wasi_blobstore::WasiBlobstore::new(
+                        data_nats_client.clone(),
+                    );
+                for (name, client) in &storage_profiles {
+                    blobstore = blobstore.with_profile(name.clone(), client.clone())?;
+                }
+                // Volumes would hold the ciphertext of encrypted containers
+                let cluster_host_builder = match blobstore_encryptor {
+                    Some(encryptor) => {
+                        blobstore = blobstore.with_encryption(encryptor);
+                        cluster_host_builder
+                    }
+                    None => cluster_host_builder.with_volume_blobstore(Arc::new(
+                        NatsVolumeBlobstore::new(data_nats_client.clone()),
+                    )),
+                };
+                cluster_host_builder.with_plugin(Arc::new(blobstore))?
+            }
+        };
+
+        let api_keys = Arc::new(match &self.api_key_file {
+            Some(path) => ApiKeyStore::open(path)?,
+            None => ApiKeyStore::in_memory(),
+        });
+        cluster_host_builder = cluster_host_builder.with_api_keys(api_keys.clone());
+
+        if self.cgroups {
+            let cgroups = wash_runtime::host::cgroups::Cgroups::for_current_process()
+                .context("failed to set up cgroups")?;
+            cluster_host_builder = cluster_host_builder.with_cgroups(Arc::new(cgroups));
+        }
+
+        cluster_host_builder =
+            cluster_host_builder.with_host_path_volumes(self.host_path_volumes.iter().cloned());
+
+        if let Some(sample_rate) = self.fs_audit_sample_rate {
+            let audit = wash_runtime::host::fs_audit::FsAudit::new(sample_rate)?;
+            cluster_host_builder = cluster_host_builder.with_fs_audit(Arc::new(audit));
+        }
+
+        cluster_host_builder = cluster_host_builder.with_peer_components(self.peer_components);
+
+        if self.share_compiled_components {
+            cluster_host_builder = cluster_host_builder.with_artifact_store(Arc::new(
+                wash_runtime::host::artifacts::NatsArtifactStore::new(data_nats_client.clone()),
+            ));
+        }
 
         if let Some(host_name) = &self.host_name {
             cluster_host_builder = cluster_host_builder.with_host_name(host_name);
         }
 
+        if let Some(addr) = self.json_gateway_addr {
+            info!(addr = ?addr, "Serving published workload exports as JSON over HTTP");
+            cluster_host_builder = cluster_host_builder.with_json_gateway_addr(addr);
+        }
+
+        if let Some(addr) = self.graphql_gateway_addr {
+            info!(addr = ?addr, "Serving published workload exports over GraphQL");
+            cluster_host_builder = cluster_host_builder.with_graphql_gateway_addr(addr);
+        }
+
+        #[cfg(not(target_os = "windows"))]
+        if let Some(addr) = self.profiler_addr {
+            info!(addr = ?addr, "Serving host CPU profiles");
+            cluster_host_builder = cluster_host_builder.with_profiler_addr(addr);
+        }
+
+        if let Some(addr) = self.grpc_ingress_addr {
+            info!(addr = ?addr, "Serving workload gRPC services");
+            cluster_host_builder = cluster_host_builder.with_grpc_ingress_addr(addr);
+        }
+
+        let acme = (!self.http_acme_domains.is_empty()).then(|| {
+            let acme = Acme::new(self.http_acme_domains.clone(), ctx.data_dir().join("acme"))
+                .with_directory_url(&self.http_acme_directory)
+                .with_contact(self.http_acme_contacts.clone());
+            match self.http_acme_challenge_addr {
+                Some(_) => Arc::new(acme.with_challenge(AcmeChallenge::Http01)),
+                None => Arc::new(acme),
+            }
+        });
+
         if let Some(addr) = self.http_addr {
             tracing::info!(addr = ?addr, "Starting HTTP server for components");
             let http_router = wash_runtime::host::http::DynamicRouter::default();
-            cluster_host_builder = cluster_host_builder.with_http_handler(Arc::new(
-                wash_runtime::host::http::HttpServer::new(http_router, addr),
-            ));
+            let mut http_server = wash_runtime::host::http::HttpServer::new(http_router, addr)
+                .with_api_keys(api_keys.clone())
+                .with_resolver(resolver.clone())
+                .with_connection_limits(ConnectionLimits {
+                    max_connections: self.http_max_connections,
+                    max_requests_per_connection: self.http_max_requests_per_connection,
+                    idle_timeout: self.http_idle_timeout_secs.map(Duration::from_secs),
+                    accept_backlog: self.http_accept_backlog,
+                })
+                .with_forwarded(ForwardedConfig {
+                    policy: self.http_forwarded_policy,
+                    trusted_proxies: self.http_trusted_proxies.clone(),
+                })
+                .with_ip_filter(IpFilter {
+                    allow: self.http_allowed_ips.clone(),
+                    deny: self.http_denied_ips.clone(),
+                })
+                .with_request_ids(
+                    RequestIdConfig {
+                        honor_incoming: !self.http_regenerate_request_ids,
+                        ..Default::default()
+                    }
+                    .with_header(&self.http_request_id_header)?,
+                )
+                .with_baggage(BaggageConfig {
+                    keys: self.http_baggage_keys.clone(),
+                });
+            for (host, prefix, source) in &self.http_static {
+                let assets: Arc<dyn AssetSource> = match source.strip_prefix("container:") {
+                    Some(container) => {
+                        Arc::new(ObjectStoreAssets::new(data_nats_client.clone(), container))
+                    }
+                    None => Arc::new(DirectoryAssets::new(source)),
+                };
+                let mut mount = StaticMount::new(host, prefix, assets);
+                if self.http_static_spa {
+                    mount = mount.with_fallback(DEFAULT_INDEX);
+                }
+                info!(host, prefix, source, "Serving static files");
+                http_server = http_server.with_static_mount(mount);
+            }
+            for (host, prefix, url) in &self.http_upstream {
+                info!(host, prefix, url, "Proxying to upstream server");
+                http_server = http_server.with_upstream(Upstream::new(host, prefix, url)?);
+            }
+            if self.http_proxy_protocol {
+                http_server = http_server.with_proxy_protocol();
+            }
+            if let Some(acme) = &acme {
+                info!(domains = ?acme.domains(), "Serving HTTPS with ACME certificates");
+                http_server = http_server.with_acme(acme.clone())?;
+            }
+            cluster_host_builder = cluster_host_builder.with_http_handler(Arc::new(http_server));
         }
 
         // Enable WASI WebGPU if requested
@@ -97,6 +517,23 @@ impl CliCommand for HostCommand {
         let cluster_host = cluster_host_builder
             .build()
             .context("failed to build cluster host")?;
+        // HTTP-01 challenges are answered by a plain HTTP server next to the host's
+        let acme_challenges = match (&acme, self.http_acme_challenge_addr) {
+            (Some(acme), Some(addr)) => {
+                info!(addr = ?addr, "Answering ACME HTTP-01 challenges");
+                let server = wash_runtime::host::http::HttpServer::new(
+                    wash_runtime::host::http::DynamicRouter::default(),
+                    addr,
+                )
+                .with_acme_challenges(acme.clone());
+                server
+                    .start()
+                    .await
+                    .context("failed to start ACME challenge server")?;
+                Some(server)
+            }
+            _ => None,
+        };
         let host_cleanup = wash_runtime::washlet::run_cluster_host(cluster_host)
             .await
             .context("failed to start cluster node")?;
@@ -107,6 +544,9 @@ impl CliCommand for HostCommand {
 
         info!("Stopping host...");
 
+        if let Some(server) = &acme_challenges {
+            server.stop().await?;
+        }
         host_cleanup.await?;
 
         Ok(CommandOutput::ok(
@@ -115,3 +555,61 @@ impl CliCommand for HostCommand {
         ))
     }
 }
+
+/// Creates an encryptor from a host-managed key file, opening data that isn't encrypted
+/// as is if `plaintext_migration` is set.
+fn load_encryptor(path: &std::path::Path, plaintext_migration: bool) -> anyhow::Result<Encryptor> {
+    let encryptor = Encryptor::new(LocalKeyring::new(0, EncryptionKey::from_file(path)?));
+    Ok(if plaintext_migration {
+        encryptor.with_plaintext_migration()
+    } else {
+        encryptor
+    })
+}
+
+/// Parses a `name=nats-url` storage profile.
+fn parse_storage_profile(value: &str) -> anyhow::Result<(String, String)> {
+    match value.split_once('=') {
+        Some((name, url)) if !name.is_empty() && !url.is_empty() => {
+            Ok((name.to_string(), url.to_string()))
+        }
+        _ => anyhow::bail!("expected a storage profile as name=nats-url, got '{value}'"),
+    }
+}
+
+/// Parses a DNS server as `ip` or `ip:port`, with port 53 by default.
+fn parse_dns_server(value: &str) -> anyhow::Result<SocketAddr> {
+    match value.parse() {
+        Ok(addr) => Ok(addr),
+        Err(_) => value
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .map(|ip| SocketAddr::new(ip, 53))
+            .with_context(|| format!("expected a DNS server as ip or ip:port, got '{value}'")),
+    }
+}
+
+/// Parses a `host/prefix=source` static mount.
+fn parse_static_mount(value: &str) -> anyhow::Result<(String, String, String)> {
+    parse_http_route(value)
+        .with_context(|| format!("expected a static mount as host/prefix=source, got '{value}'"))
+}
+
+/// Parses a `host/prefix=url` upstream, checking the URL.
+fn parse_upstream(value: &str) -> anyhow::Result<(String, String, String)> {
+    let (host, prefix, url) = parse_http_route(value)
+        .with_context(|| format!("expected an upstream as host/prefix=url, got '{value}'"))?;
+    Upstream::new(host.as_str(), &prefix, &url)?;
+    Ok((host, prefix, url))
+}
+
+/// Splits a `host/prefix=target` route on the HTTP server, where the prefix defaults to `/`.
+fn parse_http_route(value: &str) -> Option<(String, String, String)> {
+    let (location, target) = value.split_once('=')?;
+    let (host, prefix) = match location.split_once('/') {
+        Some((host, prefix)) => (host, format!("/{prefix}")),
+        None => (location, "/".to_string()),
+    };
+    (!host.is_empty() && !target.is_empty()).then(|| (host.to_string(), prefix, target.to_string()))
+}
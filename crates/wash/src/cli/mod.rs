@@ -491,6 +491,7 @@ impl CliContext {
             namespace: "default".to_string(),
             name: "temp-plugin".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![Component {
                 bytes: plugin_bytes.into(),
@@ -108,12 +108,14 @@ impl PluginManager {
                 namespace: "plugins".to_string(),
                 name: plugin_name.to_string(),
                 annotations: HashMap::new(),
+                secret_config_keys: Default::default(),
                 service: None,
                 components: vec![Component {
                     bytes: plugin.into(),
                     local_resources: LocalResources::default(),
                     pool_size: 1,
                     max_invocations: 1,
+                    source: None,
                 }],
                 host_interfaces: vec![
                     WitInterface::from("wasmcloud:wash/types@0.0.2"),
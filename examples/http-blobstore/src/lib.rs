@@ -4,6 +4,7 @@ use wasmcloud_component::{
         container::IncomingValue,
         types::{InputStream, OutgoingValue},
     },
+    wasi::io::streams::StreamError,
 };
 
 struct Component;
@@ -21,7 +22,22 @@ impl http::OutgoingBody for ResponseBody {
     ) -> std::io::Result<()> {
         match self {
             ResponseBody::String(s) => s.write(body, stream),
-            ResponseBody::Stream(data) => InputStream::write(data, body, stream),
+            ResponseBody::Stream(data) => {
+                // Splicing moves each chunk from the blobstore stream to the response body
+                // on the host, so the object is never copied through component memory
+                loop {
+                    match stream.blocking_splice(&data, u64::MAX) {
+                        Ok(_) => {}
+                        Err(StreamError::Closed) => break,
+                        Err(StreamError::LastOperationFailed(e)) => {
+                            return Err(std::io::Error::other(e.to_debug_string()));
+                        }
+                    }
+                }
+                drop(stream);
+                wasmcloud_component::wasi::http::types::OutgoingBody::finish(body, None)
+                    .map_err(|e| std::io::Error::other(format!("{e:?}")))
+            }
         }
     }
 }
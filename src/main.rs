@@ -3,13 +3,27 @@ use std::io::{BufWriter, IsTerminal};
 use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use clap_complete::generate;
 use tracing::{Level, error, info, instrument, trace, warn};
-use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt, util::SubscriberInitExt};
+use tracing_subscriber::{
+    EnvFilter, Layer, Registry, layer::SubscriberExt, util::SubscriberInitExt,
+};
+use wash_runtime::redact::RedactingWriter;
 
 use wash::cli::{
     CliCommand, CliCommandExt, CliContext, CommandOutput, OutputKind,
     plugin::ComponentPluginCommand,
 };
 
+#[cfg(feature = "heap-profiling")]
+#[global_allocator]
+static ALLOC: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Samples an allocation every 512KiB on average, so heap profiles can be taken at any
+/// time, see `wash_runtime::washlet::profiler`.
+#[cfg(feature = "heap-profiling")]
+#[allow(non_upper_case_globals)]
+#[unsafe(export_name = "malloc_conf")]
+pub static malloc_conf: &[u8] = b"prof:true,prof_active:true,lg_prof_sample:19\0";
+
 #[derive(Debug, Clone, Parser)]
 #[clap(
     name = "wash",
@@ -51,6 +65,13 @@ struct Cli {
     #[clap(long = "verbose", help = "Enable verbose output", global = true)]
     verbose: bool,
 
+    #[clap(
+        long = "tokio-console",
+        help = "Serve async task instrumentation to tokio-console on 127.0.0.1:6669, or TOKIO_CONSOLE_BIND (requires the tokio-console feature)",
+        global = true
+    )]
+    tokio_console: bool,
+
     #[clap(
         long = "non-interactive",
         help = "Run in non-interactive mode (skip terminal checks for host exec). Automatically enabled when stdin is not a TTY",
@@ -209,7 +230,8 @@ async fn main() {
             error!(error = ?e, "failed to infer global context");
             // In the rare case that this fails, we'll parse and initialize the CLI here to output properly.
             let cli = Cli::parse();
-            let (mut stdout, _stderr) = initialize_tracing(cli.log_level, cli.verbose);
+            let (mut stdout, _stderr) =
+                initialize_tracing(cli.log_level, cli.verbose, cli.tokio_console);
             exit_with_output(
                 &mut stdout,
                 CommandOutput::error(format!("{e:?}"), None).with_output_kind(cli.output),
@@ -230,7 +252,7 @@ async fn main() {
     };
 
     // Initialize tracing with the specified log level
-    let (stdout, _stderr) = initialize_tracing(cli.log_level, cli.verbose);
+    let (stdout, _stderr) = initialize_tracing(cli.log_level, cli.verbose, cli.tokio_console);
     // Use a buffered writer to prevent broken pipe errors
     let mut stdout_buf = BufWriter::new(stdout);
 
@@ -318,13 +340,36 @@ where
     command_output
 }
 
+/// A layer serving task instrumentation to tokio-console, if asked for and built in
+type ConsoleLayer = Option<Box<dyn Layer<Registry> + Send + Sync>>;
+
+#[cfg(feature = "tokio-console")]
+fn console_layer(enabled: bool) -> ConsoleLayer {
+    enabled.then(|| console_subscriber::spawn().boxed())
+}
+
+#[cfg(not(feature = "tokio-console"))]
+fn console_layer(enabled: bool) -> ConsoleLayer {
+    if enabled {
+        eprintln!(
+            "--tokio-console has no effect, wash wasn't built with the tokio-console feature"
+        );
+    }
+    None
+}
+
 /// Initialize tracing with a custom format
 ///
+/// The log level only filters the logs written out, so tokio-console still receives
+/// the trace-level task instrumentation it needs.
+///
 /// Returns a tuple of stdout and stderr writers for consistency with the previous API.
 fn initialize_tracing(
     log_level: Level,
     verbose: bool,
+    tokio_console: bool,
 ) -> (Box<dyn std::io::Write>, Box<dyn std::io::Write>) {
+    let console = console_layer(tokio_console);
     // Display logs in a compact, CLI-friendly format
     if verbose {
         // Enable dynamic filtering from `RUST_LOG`, fallback to "info"
@@ -332,7 +377,7 @@ fn initialize_tracing(
             .unwrap_or_else(|_| EnvFilter::new(log_level.as_str()));
 
         let fmt_layer = tracing_subscriber::fmt::layer()
-            .with_writer(std::io::stderr)
+            .with_writer(|| RedactingWriter::new(std::io::stderr()))
             .with_target(true)
             .with_thread_ids(true)
             .with_thread_names(true)
@@ -342,7 +387,10 @@ fn initialize_tracing(
             .with_ansi(true); // Color output for TTY
 
         // Register all layers with the subscriber
-        Registry::default().with(env_filter).with(fmt_layer).init();
+        Registry::default()
+            .with(console)
+            .with(fmt_layer.with_filter(env_filter))
+            .init();
 
         (Box::new(std::io::stdout()), Box::new(std::io::stderr()))
     } else {
@@ -362,7 +410,7 @@ fn initialize_tracing(
             );
 
         let fmt_layer = tracing_subscriber::fmt::layer()
-            .with_writer(std::io::stderr)
+            .with_writer(|| RedactingWriter::new(std::io::stderr()))
             .with_target(false)
             .with_thread_ids(false)
             .with_thread_names(false)
@@ -372,7 +420,10 @@ fn initialize_tracing(
             .with_ansi(true);
 
         // Register all layers with the subscriber
-        Registry::default().with(env_filter).with(fmt_layer).init();
+        Registry::default()
+            .with(console)
+            .with(fmt_layer.with_filter(env_filter))
+            .init();
 
         (Box::new(std::io::stdout()), Box::new(std::io::stderr()))
     }
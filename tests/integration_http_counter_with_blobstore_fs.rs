@@ -96,6 +96,7 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
             namespace: "test".to_string(),
             name: "http-counter-with-fs-blobstore".to_string(),
             annotations: HashMap::new(),
+            secret_config_keys: Default::default(),
             service: None,
             components: vec![
                 // Component 1: Blobstore filesystem plugin as a component
@@ -112,9 +113,12 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                                 name: "blobstore-data".to_string(),
                                 mount_path: "/data".to_string(),
                                 read_only: false,
+                                quota_bytes: None,
                             },
                         ],
                         allowed_hosts: vec![],
+                        arguments: vec![],
+                        inherited_environment: vec![],
                     },
                     pool_size: 1,
                     max_invocations: 100,
@@ -134,6 +138,8 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                         environment: HashMap::new(),
                         volume_mounts: vec![],
                         allowed_hosts: vec!["example.com".to_string()],
+                        arguments: vec![],
+                        inherited_environment: vec![],
                     },
                     pool_size: 2,
                     max_invocations: 100,
@@ -146,6 +152,7 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                     package: "http".to_string(),
                     interfaces: ["incoming-handler".to_string()].into_iter().collect(),
                     version: None,
+                    version_req: None,
                     config: {
                         let mut config = HashMap::new();
                         config.insert("host".to_string(), "test".to_string());
@@ -161,6 +168,7 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                         .into_iter()
                         .collect(),
                     version: Some(semver::Version::parse("0.2.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -168,6 +176,7 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                     package: "logging".to_string(),
                     interfaces: ["logging".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.1.0-draft").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -175,6 +184,7 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                     package: "config".to_string(),
                     interfaces: ["store".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.2.0-rc.1").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
                 WitInterface {
@@ -182,6 +192,7 @@ async fn test_http_counter_with_blobstore_fs_plugin() -> Result<()> {
                     package: "wash".to_string(),
                     interfaces: ["types".to_string()].into_iter().collect(),
                     version: Some(semver::Version::parse("0.0.2").unwrap()),
+                    version_req: None,
                     config: HashMap::new(),
                 },
             ],